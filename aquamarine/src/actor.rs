@@ -17,6 +17,7 @@
 use futures::future::BoxFuture;
 use futures::FutureExt;
 use std::sync::Arc;
+use std::time::Duration;
 use std::{
     collections::VecDeque,
     task::{Context, Poll, Waker},
@@ -25,22 +26,22 @@ use tracing::{instrument, Instrument, Span};
 
 use crate::deadline::Deadline;
 use crate::particle_effects::RawRoutingEffects;
-use crate::particle_executor::{FutResult, ParticleExecutor};
+use crate::particle_executor::{FutResult, ParticleExecutor, ReturnedVm};
 use crate::particle_functions::{Functions, SingleCallStat};
 use crate::spawner::{SpawnFunctions, Spawner};
 use crate::{AquaRuntime, InterpretationStats, ParticleDataStore, ParticleEffects};
 use fluence_keypair::KeyPair;
 use fluence_libp2p::PeerId;
 use particle_execution::{ParticleFunctionStatic, ServiceFunction};
-use particle_protocol::{ExtendedParticle, Particle};
-use types::DealId;
+use particle_protocol::{ExtendedParticle, Particle, ParticleTimings, Rejection};
+use types::{DealId, ParticleClass};
 
 struct Reusables<RT> {
     vm_id: usize,
-    vm: Option<RT>,
+    vm: ReturnedVm<RT>,
 }
 
-type AVMCallResult<RT> = FutResult<(usize, Option<RT>), RawRoutingEffects, InterpretationStats>;
+type AVMCallResult<RT> = FutResult<(usize, ReturnedVm<RT>), RawRoutingEffects, InterpretationStats>;
 type AVMTask<RT> = BoxFuture<
     'static,
     (
@@ -48,6 +49,7 @@ type AVMTask<RT> = BoxFuture<
         ParticleEffects,
         InterpretationStats,
         Arc<Span>,
+        ParticleTimings,
     ),
 >;
 pub struct Actor<RT, F> {
@@ -70,6 +72,10 @@ pub struct Actor<RT, F> {
     data_store: Arc<ParticleDataStore>,
     spawner: Spawner,
     deal_id: Option<DealId>,
+    /// Defines how soon the actor gets a VM when there are not enough of them
+    class: ParticleClass,
+    /// Execution stops once the particle data grows larger than that
+    max_data_size: Option<usize>,
 }
 
 impl<RT, F> Actor<RT, F>
@@ -88,6 +94,7 @@ where
         data_store: Arc<ParticleDataStore>,
         deal_id: Option<DealId>,
         spawner: Spawner,
+        class: ParticleClass,
     ) -> Self {
         Self {
             deadline: Deadline::from(particle),
@@ -106,9 +113,16 @@ where
             data_store,
             spawner,
             deal_id,
+            class,
+            max_data_size: None,
         }
     }
 
+    pub fn with_max_data_size(mut self, max_data_size: Option<usize>) -> Self {
+        self.max_data_size = max_data_size;
+        self
+    }
+
     pub fn is_expired(&self, now_ms: u64) -> bool {
         self.deadline.is_expired(now_ms)
     }
@@ -124,6 +138,10 @@ where
         (particle_id, self.current_peer_id, signature, token)
     }
 
+    pub fn class(&self) -> ParticleClass {
+        self.class
+    }
+
     pub fn mailbox_size(&self) -> usize {
         self.mailbox.len()
     }
@@ -139,10 +157,7 @@ where
     }
 
     /// Polls actor for result on previously ingested particle
-    pub fn poll_completed(
-        &mut self,
-        cx: &mut Context<'_>,
-    ) -> Poll<FutResult<(usize, Option<RT>), RawRoutingEffects, InterpretationStats>> {
+    pub fn poll_completed(&mut self, cx: &mut Context<'_>) -> Poll<AVMCallResult<RT>> {
         self.waker = Some(cx.waker().clone());
 
         self.functions.poll(cx);
@@ -157,7 +172,7 @@ where
 
     fn poll_avm_future(&mut self, cx: &mut Context<'_>) -> Option<Poll<AVMCallResult<RT>>> {
         if let Some(Poll::Ready(res)) = self.future.as_mut().map(|f| f.poll_unpin(cx)) {
-            let (reusables, effects, stats, parent_span) = res;
+            let (reusables, effects, stats, parent_span, mut timings) = res;
             let span = tracing::info_span!(
                 parent: parent_span.as_ref(),
                 "Actor::poll_avm_future::future_ready",
@@ -179,15 +194,26 @@ where
                 parent_span.clone(),
             );
 
-            let effects = RawRoutingEffects {
-                particle: ExtendedParticle::linked(
-                    Particle {
-                        data: effects.new_data,
-                        ..self.particle.clone()
-                    },
-                    parent_span,
-                ),
-                next_peers: effects.next_peers,
+            timings.interpretation = Some(stats.interpretation_time);
+            let effects = match stats.oversized_data(self.max_data_size) {
+                Some((size, limit)) => {
+                    let rejection = Rejection::DataSizeExceeded {
+                        size: size as u64,
+                        limit: limit as u64,
+                    };
+                    self.rejection_effects(rejection, parent_span, timings)
+                }
+                None => RawRoutingEffects {
+                    particle: ExtendedParticle::linked(
+                        Particle {
+                            data: effects.new_data,
+                            ..self.particle.clone()
+                        },
+                        parent_span,
+                    )
+                    .with_timings(timings),
+                    next_peers: effects.next_peers,
+                },
             };
             return Some(Poll::Ready(FutResult {
                 runtime: (reusables.vm_id, reusables.vm),
@@ -198,6 +224,40 @@ where
         None
     }
 
+    /// Sends an error particle to the origin of the particle, unless it's the current peer
+    fn rejection_effects(
+        &self,
+        rejection: Rejection,
+        span: Arc<Span>,
+        timings: ParticleTimings,
+    ) -> RawRoutingEffects {
+        let origin = self.particle.init_peer_id;
+        let error_particle = if origin == self.current_peer_id {
+            None
+        } else {
+            rejection
+                .error_particle(&self.particle.id, origin, &self.key_pair)
+                .map_err(|err| {
+                    tracing::warn!(
+                        particle_id = self.particle.id,
+                        "Can't create error particle: {err}"
+                    )
+                })
+                .ok()
+        };
+        match error_particle {
+            Some(particle) => RawRoutingEffects {
+                particle: ExtendedParticle::linked(particle, span).with_timings(timings),
+                next_peers: vec![origin],
+            },
+            None => RawRoutingEffects {
+                particle: ExtendedParticle::linked(self.particle.clone(), span)
+                    .with_timings(timings),
+                next_peers: vec![],
+            },
+        }
+    }
+
     /// Provide actor with new `vm` to execute particles, if there are any.
     ///
     /// If actor is in the middle of executing previous particle, vm is returned
@@ -224,22 +284,29 @@ where
             return ActorPoll::Vm(vm_id, vm);
         }
 
-        let particle = ext_particle
-            .as_ref()
-            .map(|p| p.particle.clone())
-            .unwrap_or_else(|| {
-                // If mailbox is empty, then take self.particle.
-                // Its data is empty, so `vm` will process `calls` on the old (saved on disk) data
-                self.particle.clone()
-            });
+        // Particle is moved out of the mailbox, so its data isn't copied on the way to AVM.
+        // Particle without call results waited in the queue since it was received,
+        // call results are interpreted right after they're gathered
+        let (particle, particle_span, mut timings) = match ext_particle {
+            Some(p) => (p.particle, Some(p.span), p.timings),
+            // If mailbox is empty, then take self.particle.
+            // Its data is empty, so `vm` will process `calls` on the old (saved on disk) data
+            None => (self.particle.clone(), None, <_>::default()),
+        };
+        timings.queue = Some(timings.received_at.elapsed());
+        let service_calls = stats.iter().filter_map(|s| s.call_time).sum::<Duration>();
+        if !stats.is_empty() {
+            timings.service_calls = Some(service_calls);
+        }
 
         let waker = cx.waker().clone();
         let data_store = self.data_store.clone();
         let key_pair = self.key_pair.clone();
         let peer_id = self.current_peer_id;
+        let max_data_size = self.max_data_size;
 
         let (async_span, linking_span) =
-            self.create_spans(call_spans, ext_particle, particle.id.as_str());
+            self.create_spans(call_spans, particle_span, particle.id.as_str());
 
         let spawner = self.spawner.clone();
         self.future = Some(
@@ -249,9 +316,10 @@ where
                         .execute(
                             spawner,
                             data_store,
-                            (particle.clone(), calls),
+                            (particle, calls),
                             peer_id,
                             key_pair,
+                            max_data_size,
                         )
                         .in_current_span()
                         .await;
@@ -263,7 +331,7 @@ where
                         vm: res.runtime,
                     };
 
-                    (reusables, res.effects, res.stats, linking_span)
+                    (reusables, res.effects, res.stats, linking_span, timings)
                 })
                 .instrument(async_span)
                 .boxed(),
@@ -276,7 +344,7 @@ where
     fn create_spans(
         &self,
         call_spans: Vec<Arc<Span>>,
-        ext_particle: Option<ExtendedParticle>,
+        particle_span: Option<Arc<Span>>,
         particle_id: &str,
     ) -> (Span, Arc<Span>) {
         let async_span = tracing::info_span!(
@@ -284,8 +352,8 @@ where
             particle_id = particle_id,
             deal_id = self.deal_id.as_ref().map(String::from)
         );
-        if let Some(ext_particle) = ext_particle.as_ref() {
-            async_span.follows_from(ext_particle.span.as_ref());
+        if let Some(particle_span) = particle_span {
+            async_span.follows_from(particle_span.as_ref());
         }
         for span in call_spans {
             async_span.follows_from(span.as_ref());
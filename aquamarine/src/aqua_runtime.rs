@@ -14,6 +14,7 @@
  * limitations under the License.
  */
 
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::{error::Error, task::Waker};
 
@@ -35,6 +36,9 @@ pub trait AquaRuntime: Sized + Send + 'static {
 
     fn create_runtime(config: Self::Config, waker: Waker) -> Result<Self, Self::Error>;
 
+    /// Config of runtimes running the interpreter from `path`
+    fn with_interpreter(config: Self::Config, path: PathBuf) -> Self::Config;
+
     // TODO: move into_effects inside call
     fn into_effects(
         outcome: Result<RawAVMOutcome, Self::Error>,
@@ -77,6 +81,13 @@ impl AquaRuntime for AVMRunner {
         Ok(vm)
     }
 
+    fn with_interpreter(config: Self::Config, path: PathBuf) -> Self::Config {
+        VmConfig {
+            air_interpreter: path,
+            ..config
+        }
+    }
+
     fn into_effects(
         outcome: Result<RawAVMOutcome, Self::Error>,
         particle_id: String,
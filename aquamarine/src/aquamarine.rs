@@ -15,12 +15,13 @@
  */
 use std::collections::HashMap;
 use std::future::Future;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::task::Poll;
 use std::time::Duration;
 
 use futures::StreamExt;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
 use tokio::task::JoinHandle;
 use tracing::{instrument, Instrument};
 
@@ -28,11 +29,13 @@ use health::HealthCheckRegistry;
 use particle_execution::{ParticleFunctionStatic, ServiceFunction};
 use particle_protocol::ExtendedParticle;
 use particle_services::PeerScope;
-use peer_metrics::{ParticleExecutorMetrics, VmPoolMetrics};
+use peer_metrics::{DataStoreMetrics, ParticleExecutorMetrics, VmPoolMetrics};
 use workers::{Event, KeyStorage, PeerScopes, Receiver, Workers};
 
 use crate::command::Command;
-use crate::command::Command::{AddService, Ingest, RemoveService};
+use crate::command::Command::{AddService, Ingest, RemoveService, UpdateInterpreter};
+use crate::config::DataRetention;
+use crate::data_compaction::start_compaction;
 use crate::error::AquamarineApiError;
 use crate::vm_pool::VmPool;
 use crate::{
@@ -47,6 +50,8 @@ pub struct AquamarineBackend<RT: AquaRuntime, F> {
     plumber: Plumber<RT, F>,
     out: EffectsChannel,
     data_store: Arc<ParticleDataStore>,
+    retention: Option<DataRetention>,
+    data_store_metrics: Option<DataStoreMetrics>,
 }
 
 impl<RT: AquaRuntime, F: ParticleFunctionStatic> AquamarineBackend<RT, F> {
@@ -59,6 +64,7 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> AquamarineBackend<RT, F> {
         out: EffectsChannel,
         plumber_metrics: Option<ParticleExecutorMetrics>,
         vm_pool_metrics: Option<VmPoolMetrics>,
+        data_store_metrics: Option<DataStoreMetrics>,
         health_registry: Option<&mut HealthCheckRegistry>,
         workers: Arc<Workers>,
         key_storage: Arc<KeyStorage>,
@@ -73,14 +79,19 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> AquamarineBackend<RT, F> {
             data_store_config.particles_dir,
             data_store_config.particles_vault_dir,
             data_store_config.particles_anomaly_dir,
-        );
+        )
+        .with_compression(data_store_config.compression);
         let data_store: Arc<ParticleDataStore> = Arc::new(data_store);
-        let vm_pool = VmPool::new(
+        let mut vm_pool = VmPool::new(
             config.pool_size,
             runtime_config.clone(),
             vm_pool_metrics,
             health_registry,
-        );
+        )
+        .with_scaling(config.scaling);
+        if config.preinit {
+            vm_pool.preinit();
+        }
         let plumber = Plumber::new(
             runtime_config,
             vm_pool,
@@ -90,13 +101,17 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> AquamarineBackend<RT, F> {
             workers,
             key_storage,
             scopes,
-        );
+        )
+        .with_priority(config.priority)
+        .with_max_data_size(config.max_data_size);
         let this = Self {
             inlet,
             worker_events,
             plumber,
             out,
             data_store,
+            retention: data_store_config.retention,
+            data_store_metrics,
         };
 
         Ok((this, sender))
@@ -126,6 +141,10 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> AquamarineBackend<RT, F> {
                     self.plumber.remove_service(service)
                 }
 
+                Poll::Ready(Some(UpdateInterpreter { path, out })) => {
+                    self.plumber.update_interpreter(path, out)
+                }
+
                 Poll::Pending | Poll::Ready(None) => break,
             }
         }
@@ -174,6 +193,13 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> AquamarineBackend<RT, F> {
 
     pub fn start(mut self) -> JoinHandle<()> {
         let data_store = self.data_store.clone();
+        if let Some(retention) = self.retention.take() {
+            start_compaction(
+                data_store.clone(),
+                retention,
+                self.data_store_metrics.take(),
+            );
+        }
         let mut stream = futures::stream::poll_fn(move |cx| self.poll(cx).map(|_| Some(()))).fuse();
         let result = tokio::task::Builder::new()
             .name("Aquamarine")
@@ -243,6 +269,23 @@ impl AquamarineApi {
         self.send_command(RemoveService { service }, None)
     }
 
+    /// Loads interpreter from `path` and replaces VMs with ones running it.
+    /// Resolves once the interpreter is checked, VMs are replaced gradually.
+    pub fn update_interpreter(
+        self,
+        path: PathBuf,
+    ) -> impl Future<Output = Result<(), AquamarineApiError>> {
+        let (out, inlet) = oneshot::channel();
+        let sent = self.send_command(UpdateInterpreter { path, out }, None);
+        async move {
+            sent.await?;
+            inlet
+                .await
+                .map_err(|_| AquamarineApiError::AquamarineDied { particle_id: None })?
+                .map_err(|reason| AquamarineApiError::InterpreterUpdateFailed { reason })
+        }
+    }
+
     fn send_command(
         self,
         command: Command,
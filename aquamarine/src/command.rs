@@ -15,9 +15,11 @@
  */
 
 use std::collections::HashMap;
+use std::path::PathBuf;
 
 use particle_execution::ServiceFunction;
 use particle_protocol::ExtendedParticle;
+use tokio::sync::oneshot;
 
 pub enum Command {
     Ingest {
@@ -32,4 +34,8 @@ pub enum Command {
     RemoveService {
         service: String,
     },
+    UpdateInterpreter {
+        path: PathBuf,
+        out: oneshot::Sender<Result<(), String>>,
+    },
 }
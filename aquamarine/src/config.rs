@@ -14,8 +14,10 @@
  * limitations under the License.
  */
 
+use crate::particle_priority::ParticlePriority;
 use fs_utils::to_abs_path;
 use libp2p::PeerId;
+use particle_protocol::Compression;
 use std::path::PathBuf;
 use std::time::Duration;
 
@@ -42,6 +44,29 @@ pub struct VmPoolConfig {
     pub pool_size: usize,
     /// Timeout of a particle execution
     pub execution_timeout: Duration,
+    /// If set, host pool starts with `min_size` VMs and grows or shrinks on load
+    pub scaling: Option<VmPoolScaling>,
+    /// If set, actors get free VMs in the order of their particle classes
+    pub priority: Option<ParticlePriority>,
+    /// Whether VMs are created as soon as the pool is, rather than when it's first polled
+    pub preinit: bool,
+    /// Execution of particles stops once their data grows larger than that
+    pub max_data_size: Option<usize>,
+}
+
+/// Bounds and triggers of the host AquaVM pool autoscaling
+#[derive(Debug, Clone)]
+pub struct VmPoolScaling {
+    pub min_size: usize,
+    pub max_size: usize,
+    /// Pool grows when at least that many actors wait for a free VM
+    pub scale_up_queue_depth: usize,
+    /// Pool grows when all VMs are busy and average interpretation time exceeds that
+    pub scale_up_latency: Duration,
+    /// Pool shrinks by one VM each time it has free VMs for that long
+    pub scale_down_after: Duration,
+    /// Pool grows ahead of load to keep that many VMs free, so particles don't wait for VM creation
+    pub warm_vms: usize,
 }
 
 impl VmConfig {
@@ -71,6 +96,37 @@ impl VmPoolConfig {
         Self {
             pool_size,
             execution_timeout,
+            scaling: None,
+            priority: None,
+            preinit: false,
+            max_data_size: None,
+        }
+    }
+
+    /// Pool size is set to `scaling.min_size`
+    pub fn with_scaling(self, scaling: VmPoolScaling) -> Self {
+        Self {
+            pool_size: scaling.min_size,
+            scaling: Some(scaling),
+            ..self
+        }
+    }
+
+    pub fn with_priority(self, priority: ParticlePriority) -> Self {
+        Self {
+            priority: Some(priority),
+            ..self
+        }
+    }
+
+    pub fn with_preinit(self, preinit: bool) -> Self {
+        Self { preinit, ..self }
+    }
+
+    pub fn with_max_data_size(self, max_data_size: Option<usize>) -> Self {
+        Self {
+            max_data_size,
+            ..self
         }
     }
 }
@@ -85,6 +141,31 @@ pub struct DataStoreConfig {
     pub particles_vault_dir: PathBuf,
     /// Dir to store particles data of AquaVM performance anomalies
     pub particles_anomaly_dir: PathBuf,
+    /// Compression of particle data stored in `particles_dir`
+    pub compression: Compression,
+    /// If set, particle data and anomaly dumps are compacted in the background
+    pub retention: Option<DataRetention>,
+}
+
+/// Limits of the particle data and anomaly dumps kept on disk
+#[derive(Debug, Clone)]
+pub struct DataRetention {
+    pub particles: RetentionPolicy,
+    pub anomalies: RetentionPolicy,
+    /// How often compaction runs
+    pub interval: Duration,
+}
+
+/// Entries above these limits are removed, oldest by modification time first
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPolicy {
+    /// Entries not modified for that long are removed
+    pub max_age: Option<Duration>,
+    /// Total size of the store in bytes
+    pub max_total_size: Option<u64>,
+    /// Size of a single particle's data in bytes. Larger data files are removed,
+    /// anomaly dumps of a particle are removed oldest first until they fit
+    pub max_particle_size: Option<u64>,
 }
 
 impl DataStoreConfig {
@@ -94,6 +175,22 @@ impl DataStoreConfig {
             particles_dir: config_utils::particles_dir(&base_dir),
             particles_vault_dir: config_utils::particles_vault_dir(&base_dir),
             particles_anomaly_dir: config_utils::particles_anomaly_dir(&base_dir),
+            compression: Compression::disabled(),
+            retention: None,
+        }
+    }
+
+    pub fn with_compression(self, compression: Compression) -> Self {
+        Self {
+            compression,
+            ..self
+        }
+    }
+
+    pub fn with_retention(self, retention: DataRetention) -> Self {
+        Self {
+            retention: Some(retention),
+            ..self
         }
     }
 }
@@ -0,0 +1,295 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use peer_metrics::{DataStoreKind, DataStoreMetrics};
+use tokio::task::JoinHandle;
+
+use crate::config::{DataRetention, RetentionPolicy};
+use crate::{DataStoreError, ParticleDataStore};
+
+type Result<T> = std::result::Result<T, DataStoreError>;
+
+/// Outcome of a single store compaction
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CompactionStats {
+    /// Number of removed data files or anomaly dumps
+    pub removed: u64,
+    /// Size of removed entries in bytes
+    pub reclaimed: u64,
+    /// Size of the store after compaction in bytes
+    pub size: u64,
+}
+
+#[derive(Debug)]
+struct Entry {
+    path: PathBuf,
+    size: u64,
+    modified: SystemTime,
+}
+
+impl ParticleDataStore {
+    /// Removes particle data files that exceed `policy`
+    pub async fn compact_particles(&self, policy: &RetentionPolicy) -> Result<CompactionStats> {
+        let mut stats = CompactionStats::default();
+
+        let mut entries = vec![];
+        for entry in list_dir(&self.particle_data_store).await? {
+            if policy.max_particle_size.is_some_and(|max| entry.size > max) {
+                remove(&entry, &mut stats).await?;
+            } else {
+                entries.push(entry);
+            }
+        }
+
+        let (kept, removed) = split_excess(entries, policy, SystemTime::now());
+        for entry in removed {
+            remove(&entry, &mut stats).await?;
+        }
+        stats.size = kept.iter().map(|e| e.size).sum();
+
+        Ok(stats)
+    }
+
+    /// Removes anomaly dumps that exceed `policy`. Dumps of a particle are kept in the same dir,
+    /// so age and total size are accounted per particle, and dirs are removed as a whole.
+    pub async fn compact_anomalies(&self, policy: &RetentionPolicy) -> Result<CompactionStats> {
+        let mut stats = CompactionStats::default();
+        let now = SystemTime::now();
+        let per_particle = RetentionPolicy {
+            max_total_size: policy.max_particle_size,
+            ..<_>::default()
+        };
+
+        let mut particles = vec![];
+        for particle in list_dir(&self.anomaly_data_store).await? {
+            let (dumps, removed) =
+                split_excess(list_dir(&particle.path).await?, &per_particle, now);
+            for dump in removed {
+                remove(&dump, &mut stats).await?;
+            }
+
+            let Some(modified) = dumps.iter().map(|d| d.modified).max() else {
+                remove(&particle, &mut stats).await?;
+                continue;
+            };
+            particles.push(Entry {
+                path: particle.path,
+                size: dumps.iter().map(|d| d.size).sum(),
+                modified,
+            });
+        }
+
+        let (kept, removed) = split_excess(particles, policy, now);
+        for particle in removed {
+            remove(&particle, &mut stats).await?;
+        }
+        stats.size = kept.iter().map(|e| e.size).sum();
+
+        Ok(stats)
+    }
+}
+
+/// Runs compaction of both stores every `retention.interval`
+pub fn start_compaction(
+    data_store: Arc<ParticleDataStore>,
+    retention: DataRetention,
+    metrics: Option<DataStoreMetrics>,
+) -> JoinHandle<()> {
+    tokio::task::Builder::new()
+        .name("DataStoreCompaction")
+        .spawn(async move {
+            let mut interval = tokio::time::interval(retention.interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+
+                let particles = data_store.compact_particles(&retention.particles).await;
+                let anomalies = data_store.compact_anomalies(&retention.anomalies).await;
+                for (kind, result) in [
+                    (DataStoreKind::Particles, particles),
+                    (DataStoreKind::Anomalies, anomalies),
+                ] {
+                    match result {
+                        Ok(stats) => {
+                            tracing::debug!(
+                                "Compacted {kind:?} store: removed {} entries, reclaimed {} bytes",
+                                stats.removed,
+                                stats.reclaimed
+                            );
+                            if let Some(m) = metrics.as_ref() {
+                                m.compacted(kind, stats.removed, stats.reclaimed, stats.size)
+                            }
+                        }
+                        Err(err) => tracing::warn!("Could not compact {kind:?} store: {err}"),
+                    }
+                }
+            }
+        })
+        .expect("Could not spawn task")
+}
+
+/// Splits entries into kept and removed ones, so that kept ones satisfy `max_age` and
+/// `max_total_size` of `policy`. The oldest entries are removed first.
+fn split_excess(
+    mut entries: Vec<Entry>,
+    policy: &RetentionPolicy,
+    now: SystemTime,
+) -> (Vec<Entry>, Vec<Entry>) {
+    // newest first
+    entries.sort_by(|a, b| b.modified.cmp(&a.modified));
+
+    let mut total = 0u64;
+    entries.into_iter().partition(|entry| {
+        let age = now.duration_since(entry.modified).unwrap_or(Duration::ZERO);
+        if policy.max_age.is_some_and(|max| age > max) {
+            return false;
+        }
+        total = total.saturating_add(entry.size);
+        policy.max_total_size.map_or(true, |max| total <= max)
+    })
+}
+
+/// Files and dirs of `dir` with their sizes, nothing if `dir` doesn't exist
+fn list_dir(dir: &Path) -> BoxFuture<'_, Result<Vec<Entry>>> {
+    async move {
+        let err = |e| DataStoreError::Compaction(e, dir.to_path_buf());
+
+        let mut read_dir = match tokio::fs::read_dir(dir).await {
+            Ok(read_dir) => read_dir,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(err(e)),
+        };
+
+        let mut entries = vec![];
+        while let Some(entry) = read_dir.next_entry().await.map_err(err)? {
+            let path = entry.path();
+            let metadata = match entry.metadata().await {
+                Ok(metadata) => metadata,
+                // removed concurrently, e.g. by particle cleanup
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(err(e)),
+            };
+            let modified = metadata.modified().map_err(err)?;
+            let size = if metadata.is_dir() {
+                list_dir(&path).await?.iter().map(|e| e.size).sum()
+            } else {
+                metadata.len()
+            };
+            entries.push(Entry {
+                path,
+                size,
+                modified,
+            });
+        }
+
+        Ok(entries)
+    }
+    .boxed()
+}
+
+async fn remove(entry: &Entry, stats: &mut CompactionStats) -> Result<()> {
+    let result = if entry.path.is_dir() {
+        tokio::fs::remove_dir_all(&entry.path).await
+    } else {
+        tokio::fs::remove_file(&entry.path).await
+    };
+    match result {
+        Ok(()) => {
+            stats.removed += 1;
+            stats.reclaimed += entry.size;
+            Ok(())
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(DataStoreError::Compaction(err, entry.path.clone())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, size: u64, age_secs: u64, now: SystemTime) -> Entry {
+        Entry {
+            path: name.into(),
+            size,
+            modified: now - Duration::from_secs(age_secs),
+        }
+    }
+
+    fn paths(entries: &[Entry]) -> Vec<&str> {
+        entries.iter().map(|e| e.path.to_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn split_excess_oldest_first() {
+        let now = SystemTime::now();
+        let entries = vec![
+            entry("old", 10, 300, now),
+            entry("new", 10, 10, now),
+            entry("mid", 10, 100, now),
+            entry("ancient", 1, 10_000, now),
+        ];
+        let policy = RetentionPolicy {
+            max_age: Some(Duration::from_secs(1000)),
+            max_total_size: Some(25),
+            max_particle_size: None,
+        };
+
+        let (kept, removed) = split_excess(entries, &policy, now);
+        assert_eq!(paths(&kept), vec!["new", "mid"]);
+        assert_eq!(paths(&removed), vec!["old", "ancient"]);
+    }
+
+    #[tokio::test]
+    async fn compact_anomalies() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let data_store = ParticleDataStore::new(
+            temp_dir.path().join("particles"),
+            temp_dir.path().join("vault"),
+            temp_dir.path().join("anomalies"),
+        );
+
+        for (particle, dumps) in [("first", 3), ("second", 1)] {
+            for dump in 0..dumps {
+                let dir = data_store
+                    .anomaly_data_store
+                    .join(particle)
+                    .join(dump.to_string());
+                tokio::fs::create_dir_all(&dir).await.unwrap();
+                tokio::fs::write(dir.join("data"), [0u8; 100])
+                    .await
+                    .unwrap();
+            }
+        }
+
+        let policy = RetentionPolicy {
+            max_particle_size: Some(250),
+            ..<_>::default()
+        };
+        let stats = data_store.compact_anomalies(&policy).await.unwrap();
+        assert_eq!(stats.removed, 1);
+        assert_eq!(stats.reclaimed, 100);
+        assert_eq!(stats.size, 300);
+        assert!(data_store.anomaly_data_store.join("second").exists());
+    }
+}
@@ -14,6 +14,9 @@
  * limitations under the License.
  */
 
+use std::time::Duration;
+
+use particle_execution::ParticleParams;
 use particle_protocol::Particle;
 
 #[derive(Debug, Clone)]
@@ -25,11 +28,16 @@ pub struct Deadline {
 }
 
 impl Deadline {
+    pub fn new(timestamp: u64, ttl: u32) -> Self {
+        Self { timestamp, ttl }
+    }
+
     pub fn from(particle: &Particle) -> Self {
-        Self {
-            timestamp: particle.timestamp,
-            ttl: particle.ttl,
-        }
+        Self::new(particle.timestamp, particle.ttl)
+    }
+
+    pub fn from_params(params: &ParticleParams) -> Self {
+        Self::new(params.timestamp, params.ttl)
     }
 
     pub fn is_expired(&self, now_ms: u64) -> bool {
@@ -43,4 +51,23 @@ impl Deadline {
                 true
             })
     }
+
+    /// Time left until the particle is expired, zero if it's already expired
+    pub fn remaining(&self, now_ms: u64) -> Duration {
+        let deadline = self.timestamp.saturating_add(self.ttl as u64);
+        Duration::from_millis(deadline.saturating_sub(now_ms))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remaining() {
+        let deadline = Deadline::new(1000, 500);
+        assert_eq!(deadline.remaining(1200), Duration::from_millis(300));
+        assert_eq!(deadline.remaining(1500), Duration::ZERO);
+        assert_eq!(deadline.remaining(2000), Duration::ZERO);
+    }
 }
@@ -57,6 +57,8 @@ pub enum AquamarineApiError {
         worker_id: String,
         particle_id: String,
     },
+    #[error("AquamarineApiError::InterpreterUpdateFailed: {reason}")]
+    InterpreterUpdateFailed { reason: String },
 }
 
 impl AquamarineApiError {
@@ -66,6 +68,7 @@ impl AquamarineApiError {
             AquamarineApiError::OneshotCancelled { particle_id } => Some(particle_id),
             AquamarineApiError::ExecutionTimedOut { particle_id, .. } => Some(particle_id),
             AquamarineApiError::WorkerIsNotActive { particle_id, .. } => Some(particle_id),
+            AquamarineApiError::InterpreterUpdateFailed { .. } => None,
             // Should it be `None`  considering usage of signature as particle id?
             // It can compromise valid particles into thinking they are invalid.
             // But still there can be a case when signature was generated wrong
@@ -19,12 +19,17 @@ impl VMPoolHealth {
     pub fn increment_count(&self) {
         self.current_count.fetch_add(1, Ordering::Release);
     }
+
+    pub fn decrement_count(&self) {
+        self.current_count.fetch_sub(1, Ordering::Release);
+    }
 }
 
 impl HealthCheck for VMPoolHealth {
     fn status(&self) -> eyre::Result<()> {
         let current = self.current_count.load(Ordering::Acquire);
-        if self.expected_count != current {
+        // pool grows beyond the expected size with autoscaling
+        if current < self.expected_count {
             return Err(eyre::eyre!(
                 "VM pool isn't full. Current: {}, Expected: {}",
                 current,
@@ -71,6 +76,19 @@ mod tests {
         assert!(status.is_ok());
     }
 
+    #[test]
+    fn test_vm_pool_health_scaled() {
+        let pool_health = VMPoolHealth::new(2);
+        pool_health.increment_count();
+        pool_health.increment_count();
+        pool_health.increment_count();
+        assert!(pool_health.status().is_ok());
+
+        pool_health.decrement_count();
+        pool_health.decrement_count();
+        assert!(pool_health.status().is_err());
+    }
+
     #[test]
     fn test_vm_pool_health_concurrent_access() {
         let pool_health = VMPoolHealth::new(100);
@@ -30,12 +30,14 @@ mod actor;
 mod aquamarine;
 mod command;
 mod config;
+mod data_compaction;
 mod deadline;
 mod error;
 mod log;
 mod particle_data_store;
 mod particle_executor;
 mod particle_functions;
+mod particle_priority;
 mod plumber;
 mod spawner;
 
@@ -45,11 +47,15 @@ mod particle_effects;
 
 mod health;
 mod vm_pool;
+mod vm_pool_scaler;
 
 pub use crate::aqua_runtime::AquaRuntime;
 pub use crate::aquamarine::{AquamarineApi, AquamarineBackend};
-pub use crate::config::{DataStoreConfig, VmConfig, VmPoolConfig};
+pub use crate::config::{
+    DataRetention, DataStoreConfig, RetentionPolicy, VmConfig, VmPoolConfig, VmPoolScaling,
+};
 pub use crate::particle_effects::{InterpretationStats, ParticleEffects, RemoteRoutingEffects};
+pub use crate::particle_priority::ParticlePriority;
 pub use avm_server::avm_runner::AVMRunner;
 pub use error::AquamarineApiError;
 pub use particle_data_store::{DataStoreError, ParticleDataStore};
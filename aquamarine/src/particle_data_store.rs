@@ -29,6 +29,7 @@ use tracing::instrument;
 
 use now_millis::now_ms;
 use particle_execution::{ParticleVault, VaultError};
+use particle_protocol::{decompress_stored, Compression};
 
 type Result<T> = std::result::Result<T, DataStoreError>;
 
@@ -37,6 +38,8 @@ pub struct ParticleDataStore {
     pub particle_data_store: PathBuf,
     pub vault: ParticleVault,
     pub anomaly_data_store: PathBuf,
+    /// Data is compressed on write if enabled, and decompressed on read if it was compressed
+    pub compression: Compression,
 }
 
 impl ParticleDataStore {
@@ -49,6 +52,14 @@ impl ParticleDataStore {
             particle_data_store,
             vault: ParticleVault::new(vault_dir),
             anomaly_data_store,
+            compression: Compression::disabled(),
+        }
+    }
+
+    pub fn with_compression(self, compression: Compression) -> Self {
+        Self {
+            compression,
+            ..self
         }
     }
 
@@ -99,6 +110,10 @@ impl ParticleDataStore {
     ) -> Result<()> {
         tracing::trace!(target: "particle_reap", particle_id = particle_id, "Storing data for particle");
         let data_path = self.data_file(particle_id, current_peer_id, signature);
+        let data = self
+            .compression
+            .compress_stored(data)
+            .map_err(|err| DataStoreError::StoreData(err, data_path.clone()))?;
         tokio::fs::write(&data_path, data)
             .await
             .map_err(|err| DataStoreError::StoreData(err, data_path))?;
@@ -115,6 +130,9 @@ impl ParticleDataStore {
     ) -> Result<Vec<u8>> {
         let data_path = self.data_file(particle_id, current_peer_id, signature);
         let data = tokio::fs::read(&data_path).await.unwrap_or_default();
+        let data = decompress_stored(&data)
+            .map_err(|err| DataStoreError::ReadData(err, data_path.clone()))?
+            .into_owned();
         Ok(data)
     }
 
@@ -277,6 +295,8 @@ pub enum DataStoreError {
     SerializeAnomaly(#[source] serde_json::error::Error),
     #[error("error reading data from {1:?}")]
     ReadData(#[source] std::io::Error, PathBuf),
+    #[error("error compacting {1:?}")]
+    Compaction(#[source] std::io::Error, PathBuf),
 }
 
 fn store_key_from_components(particle_id: &str, current_peer_id: &str, signature: &[u8]) -> String {
@@ -296,6 +316,7 @@ mod tests {
     use avm_server::avm_runner::RawAVMOutcome;
     use avm_server::{CallRequests, SoftLimitsTriggering};
     use fluence_libp2p::PeerId;
+    use particle_protocol::Compression;
     use std::path::PathBuf;
     use std::time::Duration;
 
@@ -347,6 +368,47 @@ mod tests {
         assert_eq!(read_result.unwrap(), data);
     }
 
+    #[tokio::test]
+    async fn test_store_and_read_compressed_data() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let particle_data_store = temp_dir.path().join("particle_data_store");
+        let vault_dir = temp_dir.path().join("vault");
+        let anomaly_data_store = temp_dir.path().join("anomaly_data_store");
+
+        let compression = Compression {
+            threshold: bytesize::ByteSize::b(16),
+            ..<_>::default()
+        };
+        let particle_data_store =
+            ParticleDataStore::new(particle_data_store, vault_dir, anomaly_data_store)
+                .with_compression(compression);
+        particle_data_store
+            .initialize()
+            .await
+            .expect("Failed to initialize");
+
+        let particle_id = "test_particle";
+        let current_peer_id = "test_peer";
+        let signature: &[u8] = &[0];
+        let data = "canon result ".repeat(1000).into_bytes();
+
+        particle_data_store
+            .store_data(&data, particle_id, current_peer_id, signature)
+            .await
+            .expect("Failed to store data");
+        let stored =
+            tokio::fs::read(particle_data_store.data_file(particle_id, current_peer_id, signature))
+                .await
+                .expect("Failed to read data file");
+        assert!(stored.len() < data.len());
+
+        let read_result = particle_data_store
+            .read_data(particle_id, current_peer_id, signature)
+            .await
+            .expect("Failed to read data");
+        assert_eq!(read_result, data);
+    }
+
     #[tokio::test]
     async fn test_detect_anomaly() {
         let particle_data_store = ParticleDataStore::new(
@@ -61,6 +61,14 @@ impl InterpretationStats {
             success: false,
         }
     }
+
+    /// New data size and the limit if the data exceeded it, the execution is stopped then
+    pub fn oversized_data(&self, max_data_size: Option<usize>) -> Option<(usize, usize)> {
+        let size = self.new_data_len?;
+        max_data_size
+            .filter(|limit| size > *limit)
+            .map(|limit| (size, limit))
+    }
 }
 
 /// Routing part of the [[ParticleEffects].
@@ -75,6 +83,8 @@ pub struct RawRoutingEffects {
 pub struct RemoteRoutingEffects {
     pub particle: ExtendedParticle,
     pub next_peers: Vec<PeerId>,
+    /// Scope the particle was executed in
+    pub peer_scope: PeerScope,
 }
 
 #[derive(Clone, Debug)]
@@ -17,22 +17,36 @@
 use async_trait::async_trait;
 use std::borrow::Cow;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use avm_server::avm_runner::RawAVMOutcome;
 use avm_server::{CallResults, ParticleParameters};
 use fluence_keypair::KeyPair;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use now_millis::now_ms;
 use tokio::task::JoinError;
 use tracing::instrument;
 
 use fluence_libp2p::PeerId;
 use particle_protocol::Particle;
 
+use crate::deadline::Deadline;
 use crate::spawner::SpawnFunctions;
 use crate::spawner::Spawner;
 use crate::{AquaRuntime, InterpretationStats, ParticleDataStore, ParticleEffects};
 
-pub(super) type AVMRes<RT> = FutResult<Option<RT>, ParticleEffects, InterpretationStats>;
+pub(super) type AVMRes<RT> = FutResult<ReturnedVm<RT>, ParticleEffects, InterpretationStats>;
+
+/// VM given back after a particle execution
+pub(super) enum ReturnedVm<RT> {
+    Free(RT),
+    /// Particle expired during interpretation, but the call can't be interrupted and keeps the VM.
+    /// Resolves to the VM once the call returns, or to `None` if the call panicked or never started.
+    Busy(BoxFuture<'static, Option<RT>>),
+    /// VM was lost due to a panic or cancellation
+    Lost,
+}
 
 #[async_trait]
 pub trait ParticleExecutor {
@@ -45,6 +59,7 @@ pub trait ParticleExecutor {
         p: Self::Particle,
         current_peer_id: PeerId,
         key_pair: KeyPair,
+        max_data_size: Option<usize>,
     ) -> Self::Output;
 }
 
@@ -58,6 +73,12 @@ pub struct FutResult<RT, Eff, Stats> {
     pub stats: Stats,
 }
 
+enum AVMCallError<RT> {
+    Join(JoinError),
+    /// Particle expired before AVM finished the execution, the VM is returned once it's done
+    DeadlineExceeded(BoxFuture<'static, Option<RT>>),
+}
+
 struct AVMCallResult<'a, RT: AquaRuntime> {
     particle: Particle,
     call_results: CallResults,
@@ -80,11 +101,22 @@ impl<RT: AquaRuntime> ParticleExecutor for RT {
         p: Self::Particle,
         current_peer_id: PeerId,
         key_pair: KeyPair,
+        max_data_size: Option<usize>,
     ) -> Self::Output {
         let (particle, call_results) = p;
         let particle_id = particle.id.clone();
         tracing::trace!(target: "execution", particle_id = particle_id, "Executing particle");
 
+        let timeout = Deadline::from(&particle).remaining(now_ms() as u64);
+        if timeout.is_zero() {
+            tracing::info!(target: "expired", particle_id = particle_id, "Particle is expired, execution is skipped");
+            return FutResult {
+                runtime: ReturnedVm::Free(self),
+                effects: ParticleEffects::empty(),
+                stats: InterpretationStats::failed(),
+            };
+        }
+
         let prev_data = data_store
             .clone()
             .read_data(
@@ -104,11 +136,13 @@ impl<RT: AquaRuntime> ParticleExecutor for RT {
                 particle,
                 call_results,
                 prev_data,
+                timeout,
+                max_data_size,
             )
             .await
         } else {
             FutResult {
-                runtime: Some(self),
+                runtime: ReturnedVm::Free(self),
                 effects: ParticleEffects::empty(),
                 stats: InterpretationStats::failed(),
             }
@@ -126,6 +160,8 @@ async fn execute_with_prev_data<RT: AquaRuntime>(
     particle: Particle,
     call_results: CallResults,
     prev_data: Vec<u8>,
+    timeout: Duration,
+    max_data_size: Option<usize>,
 ) -> AVMRes<RT> {
     let particle_id = particle.id.clone();
     let prev_data_len = prev_data.len();
@@ -138,14 +174,36 @@ async fn execute_with_prev_data<RT: AquaRuntime>(
         particle,
         call_results,
         prev_data,
+        timeout,
     )
     .await;
 
     match avm_result {
         Ok(avm_result) => {
-            process_avm_result(data_store, current_peer_id, prev_data_len, avm_result).await
+            process_avm_result(
+                data_store,
+                current_peer_id,
+                prev_data_len,
+                max_data_size,
+                avm_result,
+            )
+            .await
         }
-        Err(err) => {
+        Err(AVMCallError::DeadlineExceeded(busy)) => {
+            tracing::warn!(
+                target: "expired",
+                particle_id,
+                "Particle expired during interpretation, AVM is returned to the pool once done"
+            );
+            FutResult {
+                // AVM is still busy with the particle, VmPool doesn't reuse or recreate it
+                // until the call returns, so the calls never take more threads than the pool size
+                runtime: ReturnedVm::Busy(busy),
+                effects: ParticleEffects::empty(),
+                stats: InterpretationStats::failed(),
+            }
+        }
+        Err(AVMCallError::Join(err)) => {
             if err.is_cancelled() {
                 tracing::warn!(particle_id, "Particle task was cancelled");
             } else {
@@ -156,7 +214,7 @@ async fn execute_with_prev_data<RT: AquaRuntime>(
             FutResult {
                 // We loose an AVM instance here
                 // But it will be recreated via VmPool
-                runtime: None,
+                runtime: ReturnedVm::Lost,
                 effects,
                 stats,
             }
@@ -169,6 +227,7 @@ async fn process_avm_result<RT>(
     data_store: Arc<ParticleDataStore>,
     current_peer_id: PeerId,
     prev_data_len: usize,
+    max_data_size: Option<usize>,
     avm_result: AVMCallResult<'_, RT>,
 ) -> AVMRes<RT>
 where
@@ -185,6 +244,22 @@ where
                 humantime::format_duration(stats.interpretation_time), prev_data_len, len
             );
 
+            if let Some(limit) = max_data_size.filter(|limit| len > *limit) {
+                tracing::warn!(
+                    particle_id = particle_id,
+                    "Particle data grew to {len} bytes during execution, the limit is {limit} bytes; execution is stopped"
+                );
+                // Neither the data is stored nor calls are made, so the particle can't grow further
+                return FutResult {
+                    runtime: ReturnedVm::Free(avm_result.vm),
+                    effects: ParticleEffects::empty(),
+                    stats: InterpretationStats {
+                        success: false,
+                        ..stats
+                    },
+                };
+            }
+
             if data_store.detect_anomaly(stats.interpretation_time, stats.memory_delta, outcome) {
                 let anomaly_result = data_store
                     .save_anomaly_data(
@@ -222,7 +297,7 @@ where
                     err
                 );
                 return FutResult {
-                    runtime: Some(avm_result.vm),
+                    runtime: ReturnedVm::Free(avm_result.vm),
                     effects: ParticleEffects::empty(),
                     stats: InterpretationStats::failed(),
                 };
@@ -239,7 +314,7 @@ where
     let effects = RT::into_effects(avm_result.avm_outcome, particle_id);
 
     FutResult {
-        runtime: Some(avm_result.vm),
+        runtime: ReturnedVm::Free(avm_result.vm),
         effects,
         stats,
     }
@@ -254,47 +329,57 @@ async fn avm_call<'a, RT: AquaRuntime>(
     particle: Particle,
     call_results: CallResults,
     prev_data: Vec<u8>,
-) -> Result<AVMCallResult<'a, RT>, JoinError> {
-    spawner
-        .spawn_avm_call(move || {
-            let particle_id = particle.id.clone();
-            let now = Instant::now();
-            let memory_size_before = vm.memory_stats().memory_size;
-            let particle_params = ParticleParameters {
-                current_peer_id: Cow::Owned(current_peer_id.to_string()),
-                init_peer_id: Cow::Owned(particle.init_peer_id.to_string()),
-                particle_id: Cow::Owned(particle_id),
-                timestamp: particle.timestamp,
-                ttl: particle.ttl,
-            };
-            let current_data = &particle.data[..];
-            let avm_outcome = vm.call(
-                &particle.script,
-                prev_data,
-                current_data,
-                particle_params.clone(),
-                call_results.clone(),
-                &key_pair,
-            );
-            let memory_size_after = vm.memory_stats().memory_size;
+    timeout: Duration,
+) -> Result<AVMCallResult<'a, RT>, AVMCallError<RT>> {
+    let mut handle = spawner.spawn_avm_call(move || {
+        let particle_id = particle.id.clone();
+        let now = Instant::now();
+        let memory_size_before = vm.memory_stats().memory_size;
+        let particle_params = ParticleParameters {
+            current_peer_id: Cow::Owned(current_peer_id.to_string()),
+            init_peer_id: Cow::Owned(particle.init_peer_id.to_string()),
+            particle_id: Cow::Owned(particle_id),
+            timestamp: particle.timestamp,
+            ttl: particle.ttl,
+        };
+        let current_data = &particle.data[..];
+        let avm_outcome = vm.call(
+            &particle.script,
+            prev_data,
+            current_data,
+            particle_params.clone(),
+            call_results.clone(),
+            &key_pair,
+        );
+        let memory_size_after = vm.memory_stats().memory_size;
 
-            let interpretation_time = now.elapsed();
-            let new_data_len = avm_outcome.as_ref().map(|e| e.data.len()).ok();
-            let memory_delta = memory_size_after - memory_size_before;
-            let stats = InterpretationStats {
-                memory_delta,
-                interpretation_time,
-                new_data_len,
-                success: avm_outcome.is_ok(),
-            };
-            AVMCallResult {
-                avm_outcome,
-                stats,
-                particle,
-                call_results,
-                particle_params,
-                vm,
-            }
-        })
-        .await
+        let interpretation_time = now.elapsed();
+        let new_data_len = avm_outcome.as_ref().map(|e| e.data.len()).ok();
+        let memory_delta = memory_size_after - memory_size_before;
+        let stats = InterpretationStats {
+            memory_delta,
+            interpretation_time,
+            new_data_len,
+            success: avm_outcome.is_ok(),
+        };
+        AVMCallResult {
+            avm_outcome,
+            stats,
+            particle,
+            call_results,
+            particle_params,
+            vm,
+        }
+    });
+
+    match tokio::time::timeout(timeout, &mut handle).await {
+        Ok(result) => result.map_err(AVMCallError::Join),
+        Err(_) => {
+            // Prevents the call from starting if it's still waiting for a thread,
+            // a call in progress can't be interrupted and runs to completion
+            handle.abort();
+            let busy = handle.map(|result| result.ok().map(|result| result.vm));
+            Err(AVMCallError::DeadlineExceeded(busy.boxed()))
+        }
+    }
 }
@@ -24,6 +24,7 @@ use futures::future::BoxFuture;
 use futures::stream::FuturesUnordered;
 use futures::{FutureExt, StreamExt};
 use humantime::format_duration as pretty;
+use now_millis::now_ms;
 use serde_json::json;
 use serde_json::Value as JValue;
 use tracing::{instrument, Instrument, Span};
@@ -34,6 +35,7 @@ use particle_execution::{
 };
 use peer_metrics::FunctionKind;
 
+use crate::deadline::Deadline;
 use crate::log::builtin_log_fn;
 use crate::spawner::{SpawnFunctions, Spawner};
 
@@ -196,28 +198,50 @@ impl<F: ParticleFunctionStatic> Functions<F> {
         let schedule_wait_start = Instant::now();
 
         let function_identity = format!("{}:{}", &args.service_id, &args.function_name);
+        let deadline = Deadline::from_params(&params);
 
         let fut = async move {
             // How much time it took to start execution on blocking pool
             let schedule_wait_time = schedule_wait_start.elapsed();
-            let outcome = builtins.call(args, params).await;
-            // record whether call was handled by builtin or not. needed for stats.
-            let mut call_kind = FunctionKind::Service;
-            let outcome = match outcome {
-                // If particle_function isn't set, just return what we have
-                outcome if particle_function.is_none() => outcome,
-                // If builtins weren't defined over these args, try particle_function
-                FunctionOutcome::NotDefined { args, params } => {
-                    let func = particle_function.unwrap();
-                    // TODO: Actors would allow to get rid of Mutex
-                    //       i.e., wrap each callback with a queue & channel
-                    let func = func.lock().await;
-                    let outcome = func.call(args, params).await;
-                    call_kind = FunctionKind::ParticleFunction;
-                    outcome
+            // Counted once the call starts, since it may have waited for a thread
+            let timeout = deadline.remaining(now_ms() as u64);
+            if timeout.is_zero() {
+                let outcome = FunctionOutcome::Err(JError::new("particle deadline exceeded"));
+                return (
+                    outcome,
+                    FunctionKind::NotHappened,
+                    Duration::ZERO,
+                    schedule_wait_time,
+                );
+            }
+            let call = async move {
+                let outcome = builtins.call(args, params).await;
+                match outcome {
+                    // If particle_function isn't set, just return what we have
+                    outcome if particle_function.is_none() => (outcome, FunctionKind::Service),
+                    // If builtins weren't defined over these args, try particle_function
+                    FunctionOutcome::NotDefined { args, params } => {
+                        let func = particle_function.unwrap();
+                        // TODO: Actors would allow to get rid of Mutex
+                        //       i.e., wrap each callback with a queue & channel
+                        let func = func.lock().await;
+                        let outcome = func.call(args, params).await;
+                        (outcome, FunctionKind::ParticleFunction)
+                    }
+                    // Builtins were called, return their outcome
+                    outcome => (outcome, FunctionKind::Service),
                 }
-                // Builtins were called, return their outcome
-                outcome => outcome,
+            };
+            // The timeout fires only when the call yields, e.g. host calls run via `block_on`
+            // on a blocking thread and a synchronous call occupies it until done. Service calls
+            // can't be interrupted at all, they check the deadline right before running instead,
+            // see `ParticleAppServices::call_service`
+            let (outcome, call_kind) = match tokio::time::timeout(timeout, call).await {
+                Ok(result) => result,
+                Err(_) => (
+                    FunctionOutcome::Err(JError::new("particle deadline exceeded")),
+                    FunctionKind::Service,
+                ),
             };
             // How much time it took to execute the call
             // TODO: Time for ParticleFunction includes lock time, which is not good. Low priority cuz ParticleFunctions are barely used.
@@ -0,0 +1,94 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use particle_execution::ParticleParams;
+use particle_services::PeerScope;
+use types::ParticleClass;
+
+/// Order in which actors of different particle classes get free VMs
+#[derive(Debug, Clone)]
+pub struct ParticlePriority {
+    /// Classes from the highest priority to the lowest
+    order: Vec<ParticleClass>,
+}
+
+impl Default for ParticlePriority {
+    fn default() -> Self {
+        Self::new(ParticleClass::DEFAULT_ORDER.to_vec())
+    }
+}
+
+impl ParticlePriority {
+    /// Classes missing in `order` get the lowest priority
+    pub fn new(order: Vec<ParticleClass>) -> Self {
+        Self { order }
+    }
+
+    /// Lower rank is executed first
+    pub fn rank(&self, class: ParticleClass) -> usize {
+        self.order
+            .iter()
+            .position(|c| *c == class)
+            .unwrap_or(self.order.len())
+    }
+}
+
+/// Spells on the host are system spells, spells on workers are installed by deals
+pub fn classify(particle_id: &str, peer_scope: PeerScope) -> ParticleClass {
+    if !ParticleParams::is_spell_particle(particle_id) {
+        return ParticleClass::Client;
+    }
+    match peer_scope {
+        PeerScope::Host => ParticleClass::SystemSpell,
+        PeerScope::WorkerId(_) => ParticleClass::DealSpell,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fluence_libp2p::RandomPeerId;
+
+    #[test]
+    fn classify_particles() {
+        let worker = PeerScope::WorkerId(RandomPeerId::random().into());
+
+        assert_eq!(
+            classify("spell_decider_1", PeerScope::Host),
+            ParticleClass::SystemSpell
+        );
+        assert_eq!(classify("spell_abc_1", worker), ParticleClass::DealSpell);
+        assert_eq!(
+            classify("some-uuid", PeerScope::Host),
+            ParticleClass::Client
+        );
+        assert_eq!(classify("some-uuid", worker), ParticleClass::Client);
+    }
+
+    #[test]
+    fn rank_by_order() {
+        let priority = ParticlePriority::default();
+        assert!(
+            priority.rank(ParticleClass::SystemSpell) < priority.rank(ParticleClass::DealSpell)
+        );
+        assert!(priority.rank(ParticleClass::DealSpell) < priority.rank(ParticleClass::Client));
+
+        let priority = ParticlePriority::new(vec![ParticleClass::DealSpell]);
+        assert_eq!(priority.rank(ParticleClass::DealSpell), 0);
+        assert_eq!(priority.rank(ParticleClass::SystemSpell), 1);
+        assert_eq!(priority.rank(ParticleClass::Client), 1);
+    }
+}
@@ -19,6 +19,7 @@ use fluence_keypair::KeyPair;
 use futures::future::BoxFuture;
 use futures::FutureExt;
 use std::collections::hash_map::Entry;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::task::Poll::Ready;
 use std::{
@@ -26,8 +27,9 @@ use std::{
     task::{Context, Poll},
 };
 
-use futures::task::Waker;
+use futures::task::{noop_waker, Waker};
 use tokio::runtime::Handle;
+use tokio::sync::oneshot;
 use tokio::task;
 use tracing::instrument;
 
@@ -49,7 +51,9 @@ use crate::actor::{Actor, ActorPoll};
 use crate::deadline::Deadline;
 use crate::error::AquamarineApiError;
 use crate::particle_effects::LocalRoutingEffects;
+use crate::particle_executor::ReturnedVm;
 use crate::particle_functions::{Functions, SingleCallStat};
+use crate::particle_priority::{classify, ParticlePriority};
 use crate::spawner::{RootSpawner, Spawner, WorkerSpawner};
 use crate::vm_pool::VmPool;
 use crate::{AquaRuntime, ParticleDataStore, RemoteRoutingEffects};
@@ -62,6 +66,13 @@ struct ActorKey {
 
 const MAX_CLEANUP_KEYS_SIZE: usize = 1024;
 
+/// Interpreter update waiting for a VM to be created from the new config
+struct InterpreterUpdate<RT: AquaRuntime> {
+    config: RT::Config,
+    probe: BoxFuture<'static, Result<(), String>>,
+    out: oneshot::Sender<Result<(), String>>,
+}
+
 pub struct Plumber<RT: AquaRuntime, F> {
     config: RT::Config,
     events: VecDeque<Result<RemoteRoutingEffects, AquamarineApiError>>,
@@ -78,6 +89,11 @@ pub struct Plumber<RT: AquaRuntime, F> {
     scopes: PeerScopes,
     cleanup_future: Option<BoxFuture<'static, ()>>,
     root_runtime_handle: Handle,
+    /// If set, actors with higher priority particles get free VMs first
+    priority: Option<ParticlePriority>,
+    /// Execution of particles stops once their data grows larger than that
+    max_data_size: Option<usize>,
+    interpreter_update: Option<InterpreterUpdate<RT>>,
 }
 
 impl<RT: AquaRuntime, F: ParticleFunctionStatic> Plumber<RT, F> {
@@ -107,9 +123,31 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> Plumber<RT, F> {
             scopes: scope,
             cleanup_future: None,
             root_runtime_handle: Handle::current(),
+            priority: None,
+            max_data_size: None,
+            interpreter_update: None,
         }
     }
 
+    pub fn with_priority(mut self, priority: Option<ParticlePriority>) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_max_data_size(mut self, max_data_size: Option<usize>) -> Self {
+        self.max_data_size = max_data_size;
+        self
+    }
+
+    /// Ingests particle routed to a local peer by another local peer
+    fn ingest_local(&mut self, particle: ExtendedParticle, peer_scope: PeerScope) {
+        let span =
+            tracing::info_span!(parent: particle.span.as_ref(), "Plumber: routing effect ingest");
+        let _guard = span.enter();
+        // queue time of the local hop starts now
+        self.ingest(particle.with_timings(<_>::default()), None, peer_scope);
+    }
+
     /// Receives and ingests incoming particle: creates a new actor or forwards to the existing mailbox
     #[instrument(level = tracing::Level::INFO, skip_all)]
     pub fn ingest(
@@ -194,6 +232,7 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> Plumber<RT, F> {
             builtins: &self.builtins,
             key_storage: self.key_storage.as_ref(),
             data_store: self.data_store.clone(),
+            max_data_size: self.max_data_size,
         };
         match peer_scope {
             PeerScope::Host => {
@@ -278,7 +317,9 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> Plumber<RT, F> {
                     data_store,
                     actor_params.deal_id,
                     actor_params.spawner,
-                );
+                    classify(&actor_params.particle.particle.id, actor_params.peer_scope),
+                )
+                .with_max_data_size(plumber_params.max_data_size);
                 entry.insert(actor)
             }
         };
@@ -312,12 +353,58 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> Plumber<RT, F> {
             .expect("Could not spawn remove service task");
     }
 
+    /// Switches all VM pools to the interpreter from `path` once a VM is created from it
+    /// successfully. Executions in progress finish on the previous interpreter.
+    pub fn update_interpreter(&mut self, path: PathBuf, out: oneshot::Sender<Result<(), String>>) {
+        if self.interpreter_update.is_some() {
+            out.send(Err("interpreter update is already in progress".to_string()))
+                .ok();
+            return;
+        }
+
+        tracing::info!("Updating AquaVM interpreter to {}", path.display());
+        let config = RT::with_interpreter(self.config.clone(), path);
+        let probe_config = config.clone();
+        let probe = task::spawn_blocking(move || {
+            RT::create_runtime(probe_config, noop_waker())
+                .map(drop)
+                .map_err(|err| err.to_string())
+        });
+        let probe = async move { probe.await.map_err(|err| err.to_string())? }.boxed();
+        self.interpreter_update = Some(InterpreterUpdate { config, probe, out });
+        self.wake();
+    }
+
+    fn poll_interpreter_update(&mut self, cx: &mut Context<'_>) {
+        let Some(update) = self.interpreter_update.as_mut() else {
+            return;
+        };
+        let Poll::Ready(result) = update.probe.poll_unpin(cx) else {
+            return;
+        };
+        let update = self.interpreter_update.take().expect("checked above");
+
+        match &result {
+            Ok(()) => {
+                tracing::info!("AquaVM interpreter is updated, replacing VMs");
+                self.config = update.config.clone();
+                self.host_vm_pool.update_config(update.config.clone(), cx);
+                for vm_pool in self.worker_vm_pools.values_mut() {
+                    vm_pool.update_config(update.config.clone(), cx);
+                }
+            }
+            Err(err) => tracing::warn!("AquaVM interpreter isn't updated: {err}"),
+        }
+        update.out.send(result).ok();
+    }
+
     pub fn poll(
         &mut self,
         cx: &mut Context<'_>,
     ) -> Poll<Result<RemoteRoutingEffects, AquamarineApiError>> {
         self.waker = Some(cx.waker().clone());
 
+        self.poll_interpreter_update(cx);
         self.poll_pools(cx);
 
         if let Some(event) = self.events.pop_front() {
@@ -335,6 +422,7 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> Plumber<RT, F> {
         // Execute next messages
         let host_call_stats = self.poll_next_host_messages(cx);
         let workers_call_stats = self.poll_next_worker_messages(cx);
+        self.scale_host_pool(cx);
 
         // TODO: separate workers and root metrics
         self.meter(|m| {
@@ -347,11 +435,15 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> Plumber<RT, F> {
         });
 
         for effect in local_effects {
-            for local_peer in effect.next_peers {
-                let span = tracing::info_span!(parent: effect.particle.span.as_ref(), "Plumber: routing effect ingest");
-                let _guard = span.enter();
-                self.ingest(effect.particle.clone(), None, local_peer);
+            let mut next_peers = effect.next_peers;
+            // the last peer takes the particle itself, the rest get copies
+            let Some(last_peer) = next_peers.pop() else {
+                continue;
+            };
+            for local_peer in next_peers {
+                self.ingest_local(effect.particle.clone(), local_peer);
             }
+            self.ingest_local(effect.particle, last_peer);
         }
 
         // Turn effects into events, and buffer them
@@ -380,7 +472,9 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> Plumber<RT, F> {
             &mut self.host_vm_pool,
             &self.scopes,
             self.metrics.as_ref(),
+            self.max_data_size,
             cx,
+            PeerScope::Host,
             host_label,
             remote_effects,
             local_effects,
@@ -402,7 +496,9 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> Plumber<RT, F> {
                     pool,
                     &self.scopes,
                     self.metrics.as_ref(),
+                    self.max_data_size,
                     cx,
+                    PeerScope::WorkerId(*worker_id),
                     host_label,
                     remote_effects,
                     local_effects,
@@ -417,16 +513,23 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> Plumber<RT, F> {
         vm_pool: &mut VmPool<RT>,
         scopes: &PeerScopes,
         metrics: Option<&ParticleExecutorMetrics>,
+        max_data_size: Option<usize>,
         cx: &mut Context<'_>,
+        peer_scope: PeerScope,
         label: WorkerLabel,
         remote_effects: &mut Vec<RemoteRoutingEffects>,
         local_effects: &mut Vec<LocalRoutingEffects>,
     ) {
         let mut mailbox_size = 0;
         let mut interpretation_stats = vec![];
+        let mut data_size_exceeded = 0;
 
         for actor in actors.values_mut() {
             if let Poll::Ready(result) = actor.poll_completed(cx) {
+                // actor sends an error particle to the origin instead of the particle
+                if result.stats.oversized_data(max_data_size).is_some() {
+                    data_size_exceeded += 1;
+                }
                 interpretation_stats.push(result.stats);
 
                 let mut remote_peers = vec![];
@@ -443,33 +546,48 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> Plumber<RT, F> {
                     }
                 }
 
-                if !remote_peers.is_empty() {
-                    remote_effects.push(RemoteRoutingEffects {
-                        particle: result.effects.particle.clone(),
+                // particle data is copied only if it's routed both to local and remote peers
+                let particle = result.effects.particle;
+                match (remote_peers.is_empty(), local_peers.is_empty()) {
+                    (true, true) => {}
+                    (false, true) => remote_effects.push(RemoteRoutingEffects {
+                        particle,
                         next_peers: remote_peers,
-                    });
-                }
-
-                if !local_peers.is_empty() {
-                    local_effects.push(LocalRoutingEffects {
-                        particle: result.effects.particle.clone(),
+                        peer_scope,
+                    }),
+                    (true, false) => local_effects.push(LocalRoutingEffects {
+                        particle,
                         next_peers: local_peers,
-                    });
+                    }),
+                    (false, false) => {
+                        remote_effects.push(RemoteRoutingEffects {
+                            particle: particle.clone(),
+                            next_peers: remote_peers,
+                            peer_scope,
+                        });
+                        local_effects.push(LocalRoutingEffects {
+                            particle,
+                            next_peers: local_peers,
+                        });
+                    }
                 }
 
-                let (vm_id, vm) = result.runtime;
-                if let Some(vm) = vm {
-                    vm_pool.put_vm(vm_id, vm);
-                } else {
-                    // if `result.vm` is None, then an AVM instance was lost due to
-                    // panic or cancellation, and we must ask VmPool to recreate that AVM
+                match result.runtime {
+                    (vm_id, ReturnedVm::Free(vm)) => vm_pool.put_vm(vm_id, vm, cx),
+                    (vm_id, ReturnedVm::Busy(busy)) => vm_pool.put_busy_vm(vm_id, busy, cx),
+                    // an AVM instance was lost due to panic or cancellation,
+                    // and we must ask VmPool to recreate that AVM
                     // TODO: add a Count metric to count how often we call `recreate_avm`
-                    vm_pool.recreate_avm(vm_id, cx);
+                    (vm_id, ReturnedVm::Lost) => vm_pool.recreate_avm(vm_id, cx),
                 }
             }
             mailbox_size += actor.mailbox_size();
         }
 
+        for stat in &interpretation_stats {
+            vm_pool.observe_latency(stat.interpretation_time);
+        }
+
         if let Some(m) = metrics {
             for stat in &interpretation_stats {
                 // count particle interpretations
@@ -490,6 +608,11 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> Plumber<RT, F> {
             m.alive_actors
                 .get_or_create(&label)
                 .set(actors.len() as i64);
+            if data_size_exceeded > 0 {
+                m.data_size_exceeded
+                    .get_or_create(&label)
+                    .inc_by(data_size_exceeded);
+            }
         }
     }
 
@@ -558,39 +681,61 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> Plumber<RT, F> {
 
     fn poll_next_host_messages(&mut self, cx: &mut Context<'_>) -> Vec<SingleCallStat> {
         let mut stats = vec![];
-        for actor in self.host_actors.values_mut() {
-            if let Some((vm_id, vm)) = self.host_vm_pool.get_vm() {
-                match actor.poll_next(vm_id, vm, cx) {
-                    ActorPoll::Vm(vm_id, vm) => self.host_vm_pool.put_vm(vm_id, vm),
-                    ActorPoll::Executing(mut s) => stats.append(&mut s),
-                }
-            } else {
-                break;
-            }
-        }
+        Self::poll_next_messages(
+            &mut self.host_actors,
+            &mut self.host_vm_pool,
+            self.priority.as_ref(),
+            cx,
+            &mut stats,
+        );
         stats
     }
 
+    /// Scales host pool by the number of actors that have particles to execute, but no VM
+    fn scale_host_pool(&mut self, cx: &Context<'_>) {
+        let waiting = self
+            .host_actors
+            .values()
+            .filter(|actor| !actor.is_executing() && actor.mailbox_size() > 0)
+            .count();
+        self.host_vm_pool.scale(waiting, cx);
+    }
+
     fn poll_next_worker_messages(&mut self, cx: &mut Context<'_>) -> Vec<SingleCallStat> {
         let mut stats = vec![];
 
         for (worker_id, actors) in self.worker_actors.iter_mut() {
             if let Some(pool) = self.worker_vm_pools.get_mut(worker_id) {
-                for actor in actors.values_mut() {
-                    if let Some((vm_id, vm)) = pool.get_vm() {
-                        match actor.poll_next(vm_id, vm, cx) {
-                            ActorPoll::Vm(vm_id, vm) => pool.put_vm(vm_id, vm),
-                            ActorPoll::Executing(mut s) => stats.append(&mut s),
-                        }
-                    } else {
-                        break;
-                    }
-                }
+                Self::poll_next_messages(actors, pool, self.priority.as_ref(), cx, &mut stats);
             }
         }
         stats
     }
 
+    /// Hands out free VMs to actors, ordered by the rank of their particle class if priority is set
+    fn poll_next_messages(
+        actors: &mut HashMap<ActorKey, Actor<RT, F>>,
+        vm_pool: &mut VmPool<RT>,
+        priority: Option<&ParticlePriority>,
+        cx: &mut Context<'_>,
+        stats: &mut Vec<SingleCallStat>,
+    ) {
+        let mut actors: Vec<_> = actors.values_mut().collect();
+        if let Some(priority) = priority {
+            actors.sort_by_key(|actor| priority.rank(actor.class()));
+        }
+        for actor in actors {
+            if let Some((vm_id, vm)) = vm_pool.get_vm() {
+                match actor.poll_next(vm_id, vm, cx) {
+                    ActorPoll::Vm(vm_id, vm) => vm_pool.put_vm(vm_id, vm, cx),
+                    ActorPoll::Executing(mut s) => stats.append(&mut s),
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
     fn wake(&self) {
         if let Some(waker) = &self.waker {
             waker.wake_by_ref();
@@ -612,11 +757,11 @@ fn get_particle_token(key_pair: &KeyPair, signature: &Vec<u8>) -> eyre::Result<S
     Ok(bs58::encode(particle_token.to_vec()).into_string())
 }
 
-/// Implements `now` by taking number of non-leap seconds from `Utc::now()`
+/// Implements `now` with `now_millis`, so particle deadlines follow the paused time in tests
 mod real_time {
     #[allow(dead_code)]
     pub fn now_ms() -> u64 {
-        (chrono::Utc::now().timestamp() * 1000) as u64
+        now_millis::now_ms() as u64
     }
 }
 
@@ -636,6 +781,7 @@ where
     builtins: &'p F,
     key_storage: &'p KeyStorage,
     data_store: Arc<ParticleDataStore>,
+    max_data_size: Option<usize>,
 }
 
 #[cfg(test)]
@@ -650,6 +796,7 @@ mod tests {
     use fluence_keypair::KeyPair;
     use fluence_libp2p::RandomPeerId;
     use futures::task::noop_waker_ref;
+    use futures::FutureExt;
     use workers::{DummyCoreManager, KeyStorage, PeerScopes, Workers};
 
     use particle_args::Args;
@@ -699,6 +846,8 @@ mod tests {
             Ok(VMMock)
         }
 
+        fn with_interpreter(_config: Self::Config, _path: PathBuf) -> Self::Config {}
+
         fn into_effects(
             _outcome: Result<RawAVMOutcome, Self::Error>,
             _particle_id: String,
@@ -878,6 +1027,56 @@ mod tests {
         }
         assert_eq!(plumber.host_actors.len(), 0);
     }
+
+    /// Checks that VM busy with an expired particle is neither reused nor recreated until its call returns
+    #[tokio::test]
+    async fn busy_vm() {
+        let mut plumber = plumber().await;
+        let mut cx = context();
+        let (id, vm) = loop {
+            plumber.host_vm_pool.poll(&mut cx);
+            if let Some(vm) = plumber.host_vm_pool.get_vm() {
+                break vm;
+            }
+            tokio::task::yield_now().await;
+        };
+
+        let (done, busy) = tokio::sync::oneshot::channel();
+        plumber
+            .host_vm_pool
+            .put_busy_vm(id, busy.map(Result::ok).boxed(), &cx);
+        plumber.host_vm_pool.poll(&mut cx);
+        assert!(plumber.host_vm_pool.get_vm().is_none());
+
+        assert!(done.send(vm).is_ok());
+        plumber.host_vm_pool.poll(&mut cx);
+        let (returned_id, _) = plumber.host_vm_pool.get_vm().expect("VM is returned");
+        assert_eq!(returned_id, id);
+    }
+
+    /// Checks that second interpreter update is refused while the first one is in progress
+    #[tokio::test]
+    async fn update_interpreter() {
+        let mut plumber = plumber().await;
+
+        let (out, mut first) = tokio::sync::oneshot::channel();
+        plumber.update_interpreter("interpreter.wasm".into(), out);
+        let (out, second) = tokio::sync::oneshot::channel();
+        plumber.update_interpreter("interpreter.wasm".into(), out);
+        assert!(second.await.unwrap().is_err());
+
+        let mut cx = context();
+        loop {
+            if let Ok(result) = first.try_recv() {
+                assert!(result.is_ok());
+                break;
+            }
+            // 'is_pending' is used to suppress "must use" warning
+            plumber.poll(&mut cx).is_pending();
+            tokio::task::yield_now().await;
+        }
+        assert!(plumber.interpreter_update.is_none());
+    }
 }
 
 /// Code taken from https://blog.iany.me/2019/03/how-to-mock-time-in-rust-tests-and-cargo-gotchas-we-met/
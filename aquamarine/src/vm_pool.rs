@@ -14,11 +14,14 @@
  * limitations under the License.
  */
 
+use std::collections::HashSet;
 use std::error::Error;
 use std::fmt::Debug;
-use std::task::{Context, Poll};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
 
 use futures::future::BoxFuture;
+use futures::task::noop_waker;
 use futures::FutureExt;
 use tokio::task::JoinError;
 
@@ -26,9 +29,12 @@ use health::HealthCheckRegistry;
 use peer_metrics::VmPoolMetrics;
 
 use crate::health::VMPoolHealth;
-use crate::AquaRuntime;
+use crate::vm_pool_scaler::{Scaling, VmPoolScaler};
+use crate::{AquaRuntime, VmPoolScaling};
 
 type RuntimeF<RT> = BoxFuture<'static, Result<RT, CreateAVMError>>;
+/// VM busy with a call that can't be interrupted, `None` if the call panicked or never started
+type BusyF<RT> = BoxFuture<'static, Option<RT>>;
 
 #[derive(Debug)]
 enum CreateAVMError {
@@ -41,17 +47,33 @@ enum CreateAVMError {
 /// Futures representing background VM creation are stored in `VmPool::creating_runtimes`
 /// Created vms are moved to `VmPool::runtimes`
 ///
+/// VM creation starts on the first `VmPool::poll`, or earlier with `VmPool::preinit`.
+///
 /// Main API consists of `VmPool::get_vm` and `VmPool::put_vm`.
 /// API allows taking VM for execution (via `get_vm`), and then it is expected that VM is
 /// returned back via `put_vm`.
 /// It is also expected that `VmPool::poll` is called periodically.
+///
+/// With [VmPoolScaling], pool grows and shrinks according to `VmPool::scale` calls.
+/// VMs are added to and removed from the end of `VmPool::runtimes`, so ids of other VMs don't change.
+///
+/// `VmPool::update_config` replaces VMs with ones created from the new config: free VMs right away,
+/// busy and being created ones as soon as they are returned or created.
+///
+/// VMs abandoned by expired particles are returned via `VmPool::put_busy_vm` and stay busy
+/// until their calls return, so the pool never runs more than `pool_size` interpreter calls.
 pub struct VmPool<RT: AquaRuntime> {
     runtimes: Vec<Option<RT>>,
     creating_runtimes: Option<Vec<(usize, RuntimeF<RT>)>>,
+    /// VMs still busy with calls of expired particles
+    busy_runtimes: Vec<(usize, BusyF<RT>)>,
     runtime_config: RT::Config,
+    /// Ids of VMs created from the previous config
+    outdated: HashSet<usize>,
     pool_size: usize,
     metrics: Option<VmPoolMetrics>,
     health: Option<VMPoolHealth>,
+    scaler: Option<VmPoolScaler>,
 }
 
 impl<RT: AquaRuntime> VmPool<RT> {
@@ -71,10 +93,13 @@ impl<RT: AquaRuntime> VmPool<RT> {
         let mut this = Self {
             runtimes: (0..pool_size).map(|_| None).collect(),
             creating_runtimes: None,
+            busy_runtimes: vec![],
             runtime_config,
+            outdated: <_>::default(),
             pool_size,
             metrics,
             health,
+            scaler: None,
         };
 
         this.meter(|m| m.set_pool_size(pool_size));
@@ -82,6 +107,30 @@ impl<RT: AquaRuntime> VmPool<RT> {
         this
     }
 
+    /// Makes pool grow and shrink on load, see [VmPoolScaling]
+    pub fn with_scaling(mut self, scaling: Option<VmPoolScaling>) -> Self {
+        self.scaler = scaling.map(VmPoolScaler::new);
+        self
+    }
+
+    /// Starts creating VMs right away, so the interpreter is compiled and instantiated
+    /// while the node is still starting, rather than when the first particle arrives.
+    /// Must be called within Tokio runtime.
+    pub fn preinit(&mut self) {
+        if self.creating_runtimes.is_none() {
+            self.start_creation(noop_waker());
+        }
+    }
+
+    fn start_creation(&mut self, waker: Waker) {
+        tracing::debug!("Starting creation {} AVMs", self.pool_size);
+        self.creating_runtimes = Some(
+            (0..self.pool_size)
+                .map(|id| (id, self.create_avm(waker.clone())))
+                .collect(),
+        );
+    }
+
     fn meter<U, FF: Fn(&mut VmPoolMetrics) -> U>(&mut self, f: FF) {
         self.metrics.as_mut().map(f);
     }
@@ -98,7 +147,8 @@ impl<RT: AquaRuntime> VmPool<RT> {
             .enumerate()
             .find_map(|(idx, vm)| vm.take().map(|vm| (idx, vm)));
 
-        let free_vms_count = self.runtimes.iter().filter(|vm| vm.is_some()).count();
+        let free_vms_count = self.count_free();
+        let busy_vms_count = self.count_busy();
         self.meter(|m| {
             m.get_vm.inc();
 
@@ -106,30 +156,165 @@ impl<RT: AquaRuntime> VmPool<RT> {
                 m.no_free_vm.inc();
             }
             m.free_vms.set(free_vms_count as i64);
+            m.busy_vms.set(busy_vms_count as i64);
         });
 
         vm
     }
 
     /// Puts VM back to the pool
-    pub fn put_vm(&mut self, id: usize, vm: RT) {
+    pub fn put_vm(&mut self, id: usize, vm: RT, cx: &Context<'_>) {
         debug_assert!(
             self.runtimes[id].is_none(),
             "put_vm must never happen before get_vm"
         );
+        if self.outdated.remove(&id) {
+            tracing::debug!("Replacing AquaVM {id} created from the previous config");
+            drop(vm);
+            if let Some(h) = self.health.as_ref() {
+                h.decrement_count()
+            }
+            self.recreate_avm(id, cx);
+            return;
+        }
         let memory_stats = vm.memory_stats();
         self.runtimes[id] = Some(vm);
 
-        let free_vms_count = self.runtimes.iter().filter(|vm| vm.is_some()).count();
+        let free_vms_count = self.count_free();
+        let busy_vms_count = self.count_busy();
         self.meter(|m| {
             m.put_vm.inc();
             m.free_vms.set(free_vms_count as i64);
+            m.busy_vms.set(busy_vms_count as i64);
             m.measure_memory(id, memory_stats.memory_size as u64);
             // TODO: measure max memory
         });
     }
 
+    /// Takes back VM that is still busy with a call of an expired particle. VM is put back to the pool
+    /// once the call returns, or recreated if the call panicked, but never while the call runs.
+    pub fn put_busy_vm(&mut self, id: usize, busy: BusyF<RT>, cx: &Context<'_>) {
+        debug_assert!(
+            self.runtimes[id].is_none(),
+            "put_busy_vm must never happen before get_vm"
+        );
+        tracing::debug!("AquaVM {id} is busy with an expired particle, waiting for it to return");
+        self.busy_runtimes.push((id, busy));
+        cx.waker().wake_by_ref();
+    }
+
+    fn count_free(&self) -> usize {
+        self.runtimes.iter().filter(|vm| vm.is_some()).count()
+    }
+
+    fn count_creating(&self) -> usize {
+        self.creating_runtimes.as_ref().map_or(0, Vec::len)
+    }
+
+    /// VMs taken for execution
+    fn count_busy(&self) -> usize {
+        self.runtimes.len() - self.count_free() - self.count_creating()
+    }
+
+    /// Interpretation time of a particle executed on a VM of this pool
+    pub fn observe_latency(&mut self, interpretation_time: Duration) {
+        if let Some(scaler) = self.scaler.as_mut() {
+            scaler.observe_latency(interpretation_time);
+        }
+    }
+
+    /// Grows or shrinks the pool by one VM if needed. `waiting` is the number of actors
+    /// waiting for a free VM. VM creation is never cancelled: only a free VM is removed,
+    /// so a VM being created at the end of the pool postpones shrinking until it's created.
+    pub fn scale(&mut self, waiting: usize, cx: &Context<'_>) {
+        self.meter(|m| m.waiting_actors.set(waiting as i64));
+        // pool isn't scaled until creation of the initial VMs has started
+        if self.creating_runtimes.is_none() {
+            return;
+        }
+        let (size, free, creating) = (self.pool_size, self.count_free(), self.count_creating());
+        let decision = match self.scaler.as_mut() {
+            Some(scaler) => scaler.decide(size, free, creating, waiting, Instant::now()),
+            None => return,
+        };
+
+        match decision {
+            Scaling::Grow => {
+                let id = self.runtimes.len();
+                tracing::info!(
+                    "Growing AquaVM pool to {} VMs, {} actors are waiting",
+                    size + 1,
+                    waiting
+                );
+                self.runtimes.push(None);
+                let avm_f = self.create_avm(cx.waker().clone());
+                if let Some(creating_vms) = self.creating_runtimes.as_mut() {
+                    creating_vms.push((id, avm_f))
+                }
+                self.pool_size += 1;
+                let pool_size = self.pool_size;
+                self.meter(|m| {
+                    m.scale_up.inc();
+                    m.set_pool_size(pool_size);
+                });
+            }
+            Scaling::Shrink => {
+                // only the last VM can be removed, and only if it's free
+                if !matches!(self.runtimes.last(), Some(Some(_))) {
+                    return;
+                }
+                tracing::info!("Shrinking AquaVM pool to {} VMs", size - 1);
+                self.runtimes.pop();
+                self.pool_size -= 1;
+                if let Some(h) = self.health.as_ref() {
+                    h.decrement_count()
+                }
+                let (pool_size, free) = (self.pool_size, self.count_free());
+                self.meter(|m| {
+                    m.scale_down.inc();
+                    m.set_pool_size(pool_size);
+                    m.free_vms.set(free as i64);
+                });
+            }
+            Scaling::Keep => {}
+        }
+    }
+
+    /// New VMs are created from `config`, existing ones are replaced without interrupting
+    /// executions in progress
+    pub fn update_config(&mut self, config: RT::Config, cx: &Context<'_>) {
+        self.runtime_config = config;
+        if self.creating_runtimes.is_none() {
+            // no VMs are created yet, so all of them will be created from the new config
+            return;
+        }
+
+        let mut replaced = 0;
+        for id in 0..self.runtimes.len() {
+            if self.runtimes[id].take().is_some() {
+                if let Some(h) = self.health.as_ref() {
+                    h.decrement_count()
+                }
+                self.recreate_avm(id, cx);
+                replaced += 1;
+            } else {
+                // VM is either busy or being created from the previous config
+                self.outdated.insert(id);
+            }
+        }
+        tracing::info!(
+            "Replacing AquaVMs: {} free ones are recreated, {} busy or being created ones are replaced later",
+            replaced,
+            self.outdated.len()
+        );
+
+        let free = self.count_free();
+        self.meter(|m| m.free_vms.set(free as i64));
+    }
+
     pub fn recreate_avm(&mut self, id: usize, cx: &Context<'_>) {
+        // VM is recreated from the current config
+        self.outdated.remove(&id);
         if self.creating_runtimes.is_none() {
             tracing::error!(
                 "Attempt to recreate an AVM before initialization (self.creating_runtimes is None), ignoring"
@@ -137,19 +322,19 @@ impl<RT: AquaRuntime> VmPool<RT> {
             return;
         }
 
-        let avm_f = self.create_avm(cx);
+        let avm_f = self.create_avm(cx.waker().clone());
         if let Some(creating_vms) = self.creating_runtimes.as_mut() {
             creating_vms.push((id, avm_f))
         }
     }
 
-    fn create_avm(&self, cx: &Context<'_>) -> RuntimeF<RT> {
+    /// Spawns VM creation immediately, the returned future only awaits it
+    fn create_avm(&self, waker: Waker) -> RuntimeF<RT> {
         let config = self.runtime_config.clone();
-        let waker = cx.waker().clone();
+        let task = tokio::task::spawn_blocking(|| RT::create_runtime(config, waker)); //TODO: move waker outside create runtime
 
         async {
-            let task_result =
-                tokio::task::spawn_blocking(|| RT::create_runtime(config, waker)).await; //TODO: move waker outside create runtime
+            let task_result = task.await;
             match task_result {
                 Ok(joined_res) => joined_res.map_err(|e| CreateAVMError::AVMError(Box::new(e))),
                 Err(e) => Err(CreateAVMError::JoinError(e)),
@@ -162,18 +347,14 @@ impl<RT: AquaRuntime> VmPool<RT> {
     pub fn poll(&mut self, cx: &mut Context<'_>) {
         let creating_vms = match &mut self.creating_runtimes {
             None => {
-                tracing::debug!("Starting creation {} AVMs", self.pool_size);
-                self.creating_runtimes = Some(
-                    (0..self.pool_size)
-                        .map(|id| (id, self.create_avm(cx)))
-                        .collect(),
-                );
+                self.start_creation(cx.waker().clone());
                 self.creating_runtimes.as_mut().unwrap()
             }
             Some(ref mut vms) => vms,
         };
 
         let mut wake = false;
+        let mut recreate = vec![];
 
         let mut fut_index = 0;
         while fut_index < creating_vms.len() {
@@ -189,7 +370,12 @@ impl<RT: AquaRuntime> VmPool<RT> {
                 }
 
                 // Put created vm to self.vms
+                let outdated = self.outdated.remove(&id);
                 match vm {
+                    Ok(_) if outdated => {
+                        tracing::debug!("Replacing AquaVM {id} created from the previous config");
+                        recreate.push(id);
+                    }
                     Ok(vm) => {
                         vms[id] = Some(vm);
                         if let Some(h) = self.health.as_ref() {
@@ -206,6 +392,30 @@ impl<RT: AquaRuntime> VmPool<RT> {
             fut_index += 1;
         }
 
+        for id in recreate {
+            self.recreate_avm(id, cx);
+        }
+
+        let mut returned = vec![];
+        self.busy_runtimes
+            .retain_mut(|(id, busy)| match busy.poll_unpin(cx) {
+                Poll::Ready(vm) => {
+                    returned.push((*id, vm));
+                    false
+                }
+                Poll::Pending => true,
+            });
+        for (id, vm) in returned {
+            match vm {
+                Some(vm) => self.put_vm(id, vm, cx),
+                None => {
+                    tracing::warn!("AquaVM {id} was lost after its particle expired, recreating");
+                    self.recreate_avm(id, cx);
+                }
+            }
+            wake = true;
+        }
+
         if wake {
             cx.waker().wake_by_ref()
         }
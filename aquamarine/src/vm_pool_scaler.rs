@@ -0,0 +1,171 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::time::{Duration, Instant};
+
+use crate::VmPoolScaling;
+
+/// Weight of the latest interpretation time in the average
+const LATENCY_WEIGHT: f64 = 0.2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Scaling {
+    Grow,
+    Shrink,
+    Keep,
+}
+
+/// Decides when the pool should grow or shrink, see [VmPoolScaling]
+pub(crate) struct VmPoolScaler {
+    config: VmPoolScaling,
+    /// Exponential moving average of interpretation time
+    avg_latency: Duration,
+    /// Since when the pool has free VMs and no waiting actors
+    idle_since: Option<Instant>,
+}
+
+impl VmPoolScaler {
+    pub fn new(config: VmPoolScaling) -> Self {
+        Self {
+            config,
+            avg_latency: Duration::ZERO,
+            idle_since: None,
+        }
+    }
+
+    pub fn observe_latency(&mut self, interpretation_time: Duration) {
+        self.avg_latency = self.avg_latency.mul_f64(1.0 - LATENCY_WEIGHT)
+            + interpretation_time.mul_f64(LATENCY_WEIGHT);
+    }
+
+    /// Pool grows by one VM at a time, so only when no VMs are being created
+    pub fn decide(
+        &mut self,
+        size: usize,
+        free: usize,
+        creating: usize,
+        waiting: usize,
+        now: Instant,
+    ) -> Scaling {
+        let warm = self.config.warm_vms;
+        let below_warm = free + creating < warm;
+        if free > 0 && waiting == 0 && !below_warm {
+            let idle_since = *self.idle_since.get_or_insert(now);
+            if size > self.config.min_size
+                && free > warm
+                && now >= idle_since + self.config.scale_down_after
+            {
+                // next VM is removed after another idle period
+                self.idle_since = Some(now);
+                return Scaling::Shrink;
+            }
+            return Scaling::Keep;
+        }
+        self.idle_since = None;
+
+        let overloaded = (waiting > 0 && waiting >= self.config.scale_up_queue_depth)
+            || (free == 0 && self.avg_latency >= self.config.scale_up_latency)
+            || below_warm;
+        if overloaded && creating == 0 && size < self.config.max_size {
+            Scaling::Grow
+        } else {
+            Scaling::Keep
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scaler() -> VmPoolScaler {
+        VmPoolScaler::new(VmPoolScaling {
+            min_size: 1,
+            max_size: 3,
+            scale_up_queue_depth: 2,
+            scale_up_latency: Duration::from_secs(1),
+            scale_down_after: Duration::from_secs(60),
+            warm_vms: 0,
+        })
+    }
+
+    #[test]
+    fn grow_on_queue_depth() {
+        let mut scaler = scaler();
+        let now = Instant::now();
+
+        assert_eq!(scaler.decide(1, 0, 0, 1, now), Scaling::Keep);
+        assert_eq!(scaler.decide(1, 0, 0, 2, now), Scaling::Grow);
+        // VM is being created
+        assert_eq!(scaler.decide(2, 0, 1, 2, now), Scaling::Keep);
+        // max size reached
+        assert_eq!(scaler.decide(3, 0, 0, 5, now), Scaling::Keep);
+    }
+
+    #[test]
+    fn grow_on_latency() {
+        let mut scaler = scaler();
+        let now = Instant::now();
+
+        for _ in 0..20 {
+            scaler.observe_latency(Duration::from_secs(2));
+        }
+        assert_eq!(scaler.decide(1, 0, 0, 0, now), Scaling::Grow);
+        // there are free VMs, so slow particles don't wait
+        assert_eq!(scaler.decide(2, 1, 0, 0, now), Scaling::Keep);
+    }
+
+    #[test]
+    fn shrink_when_idle() {
+        let mut scaler = scaler();
+        let now = Instant::now();
+        let minute = Duration::from_secs(60);
+
+        assert_eq!(scaler.decide(3, 2, 0, 0, now), Scaling::Keep);
+        assert_eq!(scaler.decide(3, 2, 0, 0, now + minute), Scaling::Shrink);
+        assert_eq!(scaler.decide(2, 1, 0, 0, now + minute), Scaling::Keep);
+        // load resets the idle period
+        assert_eq!(
+            scaler.decide(2, 0, 0, 0, now + minute * 3 / 2),
+            Scaling::Keep
+        );
+        assert_eq!(scaler.decide(2, 1, 0, 0, now + minute * 2), Scaling::Keep);
+        assert_eq!(scaler.decide(2, 1, 0, 0, now + minute * 3), Scaling::Shrink);
+        // min size reached
+        assert_eq!(scaler.decide(1, 1, 0, 0, now + minute * 5), Scaling::Keep);
+    }
+
+    #[test]
+    fn keep_warm_vms() {
+        let mut scaler = VmPoolScaler::new(VmPoolScaling {
+            warm_vms: 2,
+            ..scaler().config
+        });
+        let now = Instant::now();
+        let minute = Duration::from_secs(60);
+
+        // one of two VMs is busy, grow without waiting actors
+        assert_eq!(scaler.decide(2, 1, 0, 0, now), Scaling::Grow);
+        // VM being created counts as warm
+        assert_eq!(scaler.decide(3, 1, 1, 0, now), Scaling::Keep);
+        // max size reached
+        assert_eq!(scaler.decide(3, 1, 0, 0, now), Scaling::Keep);
+        // warm VMs aren't removed when idle
+        assert_eq!(scaler.decide(3, 2, 0, 0, now), Scaling::Keep);
+        assert_eq!(scaler.decide(3, 2, 0, 0, now + minute), Scaling::Keep);
+        assert_eq!(scaler.decide(3, 3, 0, 0, now + minute * 2), Scaling::Shrink);
+    }
+}
@@ -23,8 +23,12 @@ use tokio_stream::wrappers::UnboundedReceiverStream;
 
 use particle_protocol::ExtendedParticle;
 use particle_protocol::{Contact, SendStatus};
+use peer_reputation::PeerScore;
 
-use crate::connection_pool::LifecycleEvent;
+use crate::connection_pool::{ConnectedPeer, LifecycleEvent};
+use crate::maintenance::MaintenanceStatus;
+use crate::peer_filter::{PeerFilterConfig, PeerFilterError, PeerFilterUpdate, TrustCheck};
+use crate::rate_limit::RateLimitConfig;
 use crate::ConnectionPoolT;
 
 // marked `pub` to be available in benchmarks
@@ -62,6 +66,39 @@ pub enum Command {
     LifecycleEvents {
         out: mpsc::UnboundedSender<LifecycleEvent>,
     },
+    PeerScores {
+        out: oneshot::Sender<Vec<PeerScore>>,
+    },
+    ConnectedPeers {
+        out: oneshot::Sender<Vec<ConnectedPeer>>,
+    },
+    PeerFilter {
+        out: oneshot::Sender<PeerFilterConfig>,
+    },
+    UpdatePeerFilter {
+        update: PeerFilterUpdate,
+        out: oneshot::Sender<Result<bool, PeerFilterError>>,
+    },
+    Drain {
+        relays: usize,
+        out: oneshot::Sender<()>,
+    },
+    SetRateLimit {
+        config: RateLimitConfig,
+        out: oneshot::Sender<()>,
+    },
+    SetTrustCheck {
+        trust_check: Option<TrustCheck>,
+        out: oneshot::Sender<()>,
+    },
+    SetMaintenance {
+        enabled: bool,
+        relays: usize,
+        out: oneshot::Sender<MaintenanceStatus>,
+    },
+    MaintenanceStatus {
+        out: oneshot::Sender<MaintenanceStatus>,
+    },
 }
 
 #[derive(Clone, Debug)]
@@ -144,4 +181,69 @@ impl ConnectionPoolT for ConnectionPoolApi {
 
         UnboundedReceiverStream::new(inlet).boxed()
     }
+
+    fn peer_scores(&self) -> BoxFuture<'static, Vec<PeerScore>> {
+        // timeout isn't needed because result is returned immediately
+        self.execute(|out| Command::PeerScores { out })
+    }
+
+    fn connected_peers(&self) -> BoxFuture<'static, Vec<ConnectedPeer>> {
+        // timeout isn't needed because result is returned immediately
+        self.execute(|out| Command::ConnectedPeers { out })
+    }
+
+    fn peer_filter(&self) -> BoxFuture<'static, PeerFilterConfig> {
+        // timeout isn't needed because result is returned immediately
+        self.execute(|out| Command::PeerFilter { out })
+    }
+
+    fn update_peer_filter(
+        &self,
+        update: PeerFilterUpdate,
+    ) -> BoxFuture<'static, Result<bool, PeerFilterError>> {
+        let (out, inlet) = oneshot::channel();
+        if self
+            .outlet
+            .send(Command::UpdatePeerFilter { update, out })
+            .is_err()
+        {
+            return futures::future::ready(Err(PeerFilterError::Stopped)).boxed();
+        }
+        inlet
+            .map(|r| r.unwrap_or(Err(PeerFilterError::Stopped)))
+            .boxed()
+    }
+
+    fn drain(&self, relays: usize) -> BoxFuture<'static, ()> {
+        // no timeout here, it's up to the caller how long to wait for shutdown
+        self.execute(|out| Command::Drain { relays, out })
+    }
+
+    fn set_rate_limit(&self, config: RateLimitConfig) -> BoxFuture<'static, ()> {
+        // timeout isn't needed because result is returned immediately
+        self.execute(|out| Command::SetRateLimit { config, out })
+    }
+
+    fn set_trust_check(&self, trust_check: Option<TrustCheck>) -> BoxFuture<'static, ()> {
+        // timeout isn't needed because result is returned immediately
+        self.execute(|out| Command::SetTrustCheck { trust_check, out })
+    }
+
+    fn set_maintenance(
+        &self,
+        enabled: bool,
+        relays: usize,
+    ) -> BoxFuture<'static, MaintenanceStatus> {
+        // timeout isn't needed because result is returned immediately
+        self.execute(|out| Command::SetMaintenance {
+            enabled,
+            relays,
+            out,
+        })
+    }
+
+    fn maintenance_status(&self) -> BoxFuture<'static, MaintenanceStatus> {
+        // timeout isn't needed because result is returned immediately
+        self.execute(|out| Command::MaintenanceStatus { out })
+    }
 }
@@ -0,0 +1,249 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, PeerId};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Number of tracked failures after which stale ones are dropped
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// Retry policy for failed dials: n-th consecutive failure delays the next dial
+/// by `initial_delay * multiplier^(n-1)`, but no more than `max_delay`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DialBackoffConfig {
+    #[serde(with = "humantime_serde")]
+    pub initial_delay: Duration,
+    pub multiplier: f64,
+    #[serde(with = "humantime_serde")]
+    pub max_delay: Duration,
+    /// Bootstrap nodes aren't redialed after that many consecutive failures, unlimited if not set
+    pub max_attempts: Option<u32>,
+    /// Random fraction of the delay added to the bootstrap redial delays, so nodes don't redial in sync
+    pub jitter: f64,
+    /// Whether failures are tracked per address or per peer
+    pub scope: BackoffScope,
+}
+
+impl Default for DialBackoffConfig {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_secs(1),
+            multiplier: 2.0,
+            max_delay: Duration::from_secs(60),
+            max_attempts: None,
+            jitter: 0.1,
+            scope: BackoffScope::default(),
+        }
+    }
+}
+
+impl DialBackoffConfig {
+    /// Config without any delays. Useful for tests.
+    pub fn zero() -> Self {
+        Self {
+            initial_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+            jitter: 0.0,
+            ..Self::default()
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.multiplier < 1.0 {
+            return Err(format!(
+                "dial_backoff.multiplier must be at least 1, got {}",
+                self.multiplier
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.jitter) {
+            return Err(format!(
+                "dial_backoff.jitter must be between 0 and 1, got {}",
+                self.jitter
+            ));
+        }
+        if self.max_attempts == Some(0) {
+            return Err("dial_backoff.max_attempts must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    /// Delay after `failures` consecutive failed dials
+    pub fn delay(&self, failures: u32) -> Duration {
+        if failures == 0 {
+            return Duration::ZERO;
+        }
+        let exponent = failures.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let delay = self.initial_delay.as_secs_f64() * self.multiplier.powi(exponent);
+        Duration::try_from_secs_f64(delay)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay)
+    }
+
+    /// Same as [DialBackoffConfig::delay], but with a random jitter added
+    pub fn delay_with_jitter(&self, failures: u32) -> Duration {
+        let delay = self.delay(failures);
+        if self.jitter <= 0.0 {
+            return delay;
+        }
+        delay.mul_f64(1.0 + rand::thread_rng().gen_range(0.0..=self.jitter))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackoffScope {
+    /// Failure of one address doesn't prevent dialing other addresses of the same peer
+    #[default]
+    PerAddress,
+    /// Failure of any address backs off all addresses of the peer.
+    /// Addresses without known peer id are still tracked separately.
+    PerPeer,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum BackoffKey {
+    Peer(PeerId),
+    Address(Multiaddr),
+}
+
+#[derive(Debug)]
+struct Failures {
+    count: u32,
+    retry_at: Instant,
+}
+
+/// Tracks failed dials, so connection pool doesn't redial peers faster than the policy allows
+#[derive(Debug)]
+pub struct DialBackoff {
+    config: DialBackoffConfig,
+    failures: HashMap<BackoffKey, Failures>,
+}
+
+impl DialBackoff {
+    pub fn new(config: DialBackoffConfig) -> Self {
+        Self {
+            config,
+            failures: <_>::default(),
+        }
+    }
+
+    fn key(&self, peer_id: Option<PeerId>, addr: &Multiaddr) -> BackoffKey {
+        let peer_id = peer_id.or_else(|| {
+            addr.iter().find_map(|p| match p {
+                Protocol::P2p(peer_id) => Some(peer_id),
+                _ => None,
+            })
+        });
+        match (self.config.scope, peer_id) {
+            (BackoffScope::PerPeer, Some(peer_id)) => BackoffKey::Peer(peer_id),
+            _ => BackoffKey::Address(addr.clone()),
+        }
+    }
+
+    /// Whether dialing `addr` has to wait until the backoff delay passes
+    pub fn is_backed_off(&self, peer_id: Option<PeerId>, addr: &Multiaddr) -> bool {
+        self.failures
+            .get(&self.key(peer_id, addr))
+            .is_some_and(|f| Instant::now() < f.retry_at)
+    }
+
+    pub fn on_failure(&mut self, peer_id: Option<PeerId>, addr: &Multiaddr) {
+        let now = Instant::now();
+        if self.failures.len() > PRUNE_THRESHOLD {
+            // failures older than max delay are forgotten, so the count starts over
+            let max_delay = self.config.max_delay;
+            self.failures.retain(|_, f| f.retry_at + max_delay > now);
+        }
+
+        let key = self.key(peer_id, addr);
+        let failures = self.failures.entry(key).or_insert(Failures {
+            count: 0,
+            retry_at: now,
+        });
+        failures.count = failures.count.saturating_add(1);
+        failures.retry_at = now + self.config.delay(failures.count);
+    }
+
+    pub fn on_success(&mut self, peer_id: PeerId, addr: Option<&Multiaddr>) {
+        self.failures.remove(&BackoffKey::Peer(peer_id));
+        if let Some(addr) = addr {
+            self.failures.remove(&BackoffKey::Address(addr.clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_grows_exponentially() {
+        let config = DialBackoffConfig {
+            initial_delay: Duration::from_secs(1),
+            multiplier: 2.0,
+            max_delay: Duration::from_secs(10),
+            ..<_>::default()
+        };
+
+        assert_eq!(config.delay(0), Duration::ZERO);
+        assert_eq!(config.delay(1), Duration::from_secs(1));
+        assert_eq!(config.delay(3), Duration::from_secs(4));
+        assert_eq!(config.delay(5), Duration::from_secs(10));
+        assert_eq!(config.delay(u32::MAX), Duration::from_secs(10));
+
+        let jittered = config.delay_with_jitter(2);
+        assert!(jittered >= Duration::from_secs(2));
+        assert!(jittered <= Duration::from_millis(2200));
+    }
+
+    #[test]
+    fn backoff_scope() {
+        let peer_id = PeerId::random();
+        let addr: Multiaddr = "/ip4/1.2.3.4/tcp/7777".parse().unwrap();
+        let other_addr: Multiaddr = "/ip4/1.2.3.4/tcp/9999".parse().unwrap();
+
+        let mut per_address = DialBackoff::new(DialBackoffConfig::default());
+        per_address.on_failure(Some(peer_id), &addr);
+        assert!(per_address.is_backed_off(Some(peer_id), &addr));
+        assert!(!per_address.is_backed_off(Some(peer_id), &other_addr));
+
+        let mut per_peer = DialBackoff::new(DialBackoffConfig {
+            scope: BackoffScope::PerPeer,
+            ..<_>::default()
+        });
+        per_peer.on_failure(Some(peer_id), &addr);
+        assert!(per_peer.is_backed_off(Some(peer_id), &other_addr));
+        // address without peer id isn't affected
+        assert!(!per_peer.is_backed_off(None, &other_addr));
+
+        per_peer.on_success(peer_id, None);
+        assert!(!per_peer.is_backed_off(Some(peer_id), &addr));
+    }
+
+    #[test]
+    fn zero_config_never_backs_off() {
+        let mut backoff = DialBackoff::new(DialBackoffConfig::zero());
+        let addr: Multiaddr = "/ip4/1.2.3.4/tcp/7777".parse().unwrap();
+        backoff.on_failure(None, &addr);
+        assert!(!backoff.is_backed_off(None, &addr));
+    }
+}
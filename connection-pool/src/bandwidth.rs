@@ -0,0 +1,274 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bytesize::ByteSize;
+use libp2p::PeerId;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+/// Number of per-peer buckets after which idle ones are dropped
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// Limits of particle bytes per second, applied to received and sent particles separately.
+/// Unset limits are not enforced.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BandwidthConfig {
+    /// Limit for all connections together
+    pub global: Option<ByteSize>,
+    /// Limit for each connected peer
+    pub per_connection: Option<ByteSize>,
+    /// Limit for particles sent by each worker. Workers don't receive particles from
+    /// the network directly, so only the sent ones are accounted
+    pub per_worker: Option<ByteSize>,
+    /// Received particles delayed by the limits are kept in memory. Particles from a peer
+    /// beyond that number are dropped and the peer is told to retry later
+    pub max_throttled_per_connection: usize,
+    /// Same as `max_throttled_per_connection`, but for all peers together
+    pub max_throttled: usize,
+}
+
+impl Default for BandwidthConfig {
+    fn default() -> Self {
+        Self {
+            global: None,
+            per_connection: None,
+            per_worker: None,
+            max_throttled_per_connection: 64,
+            max_throttled: 4096,
+        }
+    }
+}
+
+impl BandwidthConfig {
+    fn is_unlimited(&self) -> bool {
+        self.global.is_none() && self.per_connection.is_none() && self.per_worker.is_none()
+    }
+}
+
+/// Token bucket that allows bursts of up to one second worth of traffic.
+/// A reservation may exceed the available tokens, then the bucket goes into debt and
+/// the caller has to wait until it's paid off. That way particles larger than the bucket
+/// are delayed rather than refused.
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: ByteSize, now: Instant) -> Self {
+        let rate = rate.as_u64().max(1) as f64;
+        Self {
+            rate,
+            tokens: rate,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.updated = now;
+    }
+
+    /// Takes `bytes` from the bucket, returns how long to wait before they may be transferred
+    fn reserve(&mut self, bytes: usize, now: Instant) -> Duration {
+        self.refill(now);
+        self.tokens -= bytes as f64;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+
+    fn is_full(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= self.rate
+    }
+}
+
+#[derive(Debug, Default)]
+struct Buckets {
+    global: Option<TokenBucket>,
+    per_peer: HashMap<PeerId, TokenBucket>,
+    per_worker: HashMap<PeerId, TokenBucket>,
+}
+
+impl Buckets {
+    fn reserve(
+        &mut self,
+        config: &BandwidthConfig,
+        peer_id: PeerId,
+        worker_id: Option<PeerId>,
+        bytes: usize,
+        now: Instant,
+    ) -> Duration {
+        let mut delay = Duration::ZERO;
+
+        if let Some(rate) = config.global {
+            let bucket = self
+                .global
+                .get_or_insert_with(|| TokenBucket::new(rate, now));
+            delay = delay.max(bucket.reserve(bytes, now));
+        }
+        if let Some(rate) = config.per_connection {
+            prune(&mut self.per_peer, now);
+            let bucket = self
+                .per_peer
+                .entry(peer_id)
+                .or_insert_with(|| TokenBucket::new(rate, now));
+            delay = delay.max(bucket.reserve(bytes, now));
+        }
+        if let (Some(rate), Some(worker_id)) = (config.per_worker, worker_id) {
+            prune(&mut self.per_worker, now);
+            let bucket = self
+                .per_worker
+                .entry(worker_id)
+                .or_insert_with(|| TokenBucket::new(rate, now));
+            delay = delay.max(bucket.reserve(bytes, now));
+        }
+
+        delay
+    }
+}
+
+/// Full buckets are indistinguishable from new ones, so they can be dropped
+fn prune(buckets: &mut HashMap<PeerId, TokenBucket>, now: Instant) {
+    if buckets.len() >= PRUNE_THRESHOLD {
+        buckets.retain(|_, bucket| !bucket.is_full(now));
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    inbound: Buckets,
+    outbound: Buckets,
+}
+
+/// Throttles particle traffic according to [`BandwidthConfig`].
+/// Cloning is cheap, all clones share the same buckets.
+#[derive(Debug, Clone, Default)]
+pub struct BandwidthLimiter {
+    config: Arc<BandwidthConfig>,
+    state: Arc<Mutex<State>>,
+}
+
+impl BandwidthLimiter {
+    pub fn new(config: BandwidthConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            state: <_>::default(),
+        }
+    }
+
+    /// Whether a particle delayed by [`Self::inbound`] may be kept until its delay passes,
+    /// given the number of particles already delayed for its sender and in total
+    pub fn may_delay(&self, from_peer: usize, total: usize) -> bool {
+        from_peer < self.config.max_throttled_per_connection && total < self.config.max_throttled
+    }
+
+    /// Accounts a particle received from `from`, returns how long its processing should be delayed
+    pub fn inbound(&self, from: PeerId, bytes: usize) -> Duration {
+        if self.config.is_unlimited() {
+            return Duration::ZERO;
+        }
+
+        self.state
+            .lock()
+            .inbound
+            .reserve(&self.config, from, None, bytes, Instant::now())
+    }
+
+    /// Accounts a particle sent to `to` on behalf of `worker_id` (`None` for the host),
+    /// returns how long sending should be delayed
+    pub fn outbound(&self, to: PeerId, worker_id: Option<PeerId>, bytes: usize) -> Duration {
+        if self.config.is_unlimited() {
+            return Duration::ZERO;
+        }
+
+        self.state
+            .lock()
+            .outbound
+            .reserve(&self.config, to, worker_id, bytes, Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_allows_burst_then_delays() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(ByteSize::kb(1), now);
+
+        assert_eq!(bucket.reserve(1000, now), Duration::ZERO);
+        assert_eq!(bucket.reserve(500, now), Duration::from_millis(500));
+
+        // debt is paid off with time
+        let later = now + Duration::from_millis(1500);
+        assert_eq!(bucket.reserve(1000, later), Duration::ZERO);
+    }
+
+    #[test]
+    fn limits_are_combined() {
+        let config = BandwidthConfig {
+            global: Some(ByteSize::kb(10)),
+            per_connection: Some(ByteSize::kb(1)),
+            per_worker: Some(ByteSize::kb(2)),
+            ..<_>::default()
+        };
+        let mut buckets = Buckets::default();
+        let now = Instant::now();
+        let peer = PeerId::random();
+        let worker = PeerId::random();
+
+        // the most restrictive limit wins
+        let delay = buckets.reserve(&config, peer, Some(worker), 2000, now);
+        assert_eq!(delay, Duration::from_secs(1));
+
+        // other peers are limited only by the worker and global limits
+        let delay = buckets.reserve(&config, PeerId::random(), Some(worker), 1000, now);
+        assert_eq!(delay, Duration::from_millis(500));
+
+        // the host isn't limited by the worker limit
+        let delay = buckets.reserve(&config, PeerId::random(), None, 1000, now);
+        assert_eq!(delay, Duration::ZERO);
+    }
+
+    #[test]
+    fn throttled_particles_are_limited() {
+        let limiter = BandwidthLimiter::new(BandwidthConfig {
+            per_connection: Some(ByteSize::kb(1)),
+            max_throttled_per_connection: 2,
+            max_throttled: 3,
+            ..<_>::default()
+        });
+
+        assert!(limiter.may_delay(1, 2));
+        // the sender has too many delayed particles
+        assert!(!limiter.may_delay(2, 2));
+        // all senders together have too many delayed particles
+        assert!(!limiter.may_delay(0, 3));
+    }
+}
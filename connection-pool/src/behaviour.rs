@@ -14,41 +14,64 @@
  * limitations under the License.
  */
 
-use futures::{Sink, StreamExt};
+use futures::future::BoxFuture;
+use futures::stream::FuturesUnordered;
+use futures::{FutureExt, Sink, StreamExt};
 use libp2p::core::Endpoint;
 use libp2p::swarm::dial_opts::DialOpts;
-use libp2p::swarm::CloseConnection::All;
+use libp2p::swarm::CloseConnection::{self, All};
 use libp2p::swarm::{
-    dial_opts, ConnectionDenied, ConnectionId, DialError, FromSwarm, ListenFailure, THandler,
-    THandlerOutEvent, ToSwarm,
+    dial_opts, ConnectionDenied, ConnectionId, DialError, FromSwarm, ListenFailure,
+    StreamUpgradeError, THandler, THandlerOutEvent, ToSwarm,
 };
 use libp2p::{
     core::{ConnectedPoint, Multiaddr},
     swarm::{NetworkBehaviour, NotifyHandler, OneShotHandler},
     PeerId,
 };
+use std::io;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use std::{
     collections::{hash_map::Entry, HashMap, HashSet, VecDeque},
     task::{Context, Poll, Waker},
 };
 use tokio::sync::{mpsc, oneshot};
+use tokio::time::Interval;
 use tokio_stream::wrappers::UnboundedReceiverStream;
 use tokio_util::sync::PollSender;
-
-use crate::connection_pool::LifecycleEvent;
+use tokio_util::time::DelayQueue;
+
+use crate::backoff::DialBackoff;
+use crate::bandwidth::BandwidthLimiter;
+use crate::connection_pool::{ConnectedPeer, LifecycleEvent};
+use crate::endpoint_peers::{AllowedPeers, EndpointPeers};
+use crate::handover::{HandoverAnnouncement, HostHandover, PeerAliases};
+use crate::keep_alive::{KeepAlive, KeepAliveHandler, PeerClass, IDLE_CHECK_INTERVAL};
+use crate::maintenance::{MaintenanceAnnouncement, MaintenanceStatus, MAINTENANCE_PROTOCOL};
+use crate::peer_filter::{PeerFilter, PeerFilterError, PeerFilterUpdate};
+use crate::rate_limit::RateLimiter;
+use crate::signatures::{verify_signature, ParticleSignatureConfig};
 use crate::{Command, ConnectionPoolApi};
-use fluence_libp2p::remote_multiaddr;
+use fluence_keypair::KeyPair;
+use fluence_libp2p::{happy_eyeballs_order, is_dialable, remote_multiaddr};
 use particle_protocol::{
-    CompletionChannel, Contact, ExtendedParticle, HandlerMessage, ProtocolConfig, SendStatus,
+    CompletionChannel, Contact, ExtendedParticle, HandlerMessage, Particle, ProtocolConfig,
+    Rejection, SendStatus,
 };
-use peer_metrics::ConnectionPoolMetrics;
+use peer_metrics::{ConnectionDirection, ConnectionPoolMetrics, DialResult, SendFailure};
+use peer_reputation::{Offence, PeerReputation};
 
 // type SwarmEventType = generate_swarm_event_type!(ConnectionPoolBehaviour);
 
 // TODO: replace with generate_swarm_event_type
 type SwarmEventType = ToSwarm<(), HandlerMessage>;
 
+/// Peer a message was sent to, and why sending failed if it did
+type SendCompletion = (PeerId, Option<SendFailure>);
+
 #[derive(Debug, Default)]
 /// [Peer] is the representation of [Contact] extended with precise connectivity information
 struct Peer {
@@ -58,6 +81,8 @@ struct Peer {
     discovered: HashSet<Multiaddr>,
     /// Dialed but not yet connected addresses
     dialing: HashSet<Multiaddr>,
+    /// Protocols advertised via Identify
+    protocols: Vec<String>,
     /// Channels to notify when any dial succeeds or peer is already connected
     dial_promises: Vec<oneshot::Sender<bool>>,
     // TODO: this layout of `dialing` and `dial_promises` doesn't allow to check specific addresses for reachability
@@ -65,6 +90,25 @@ struct Peer {
     //       reachability_promises: HashMap<Multiaddr, Vec<oneshot::Sender<bool>>
 }
 
+/// Stage of the graceful shutdown, see [ConnectionPoolBehaviour::drain]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DrainState {
+    /// Waiting for outbound particles and goodbyes to be sent
+    Flushing,
+    /// Waiting for connections to be closed
+    Closing,
+}
+
+/// See [ConnectionPoolBehaviour::set_maintenance]
+#[derive(Debug)]
+struct Maintenance {
+    started: Instant,
+    /// Clients connected when maintenance started, they are kept until they leave
+    clients: HashSet<PeerId>,
+    /// Number of clients disconnected since maintenance started
+    rejected: usize,
+}
+
 impl Peer {
     pub fn addresses(&self) -> impl Iterator<Item = &Multiaddr> {
         self.connected
@@ -80,6 +124,7 @@ impl Peer {
             connected: addresses.into_iter().collect(),
             discovered: Default::default(),
             dialing: Default::default(),
+            protocols: vec![],
             dial_promises: vec![],
         }
     }
@@ -92,6 +137,7 @@ impl Peer {
             connected: Default::default(),
             discovered: Default::default(),
             dialing: addresses.into_iter().collect(),
+            protocols: vec![],
             dial_promises: vec![outlet],
         }
     }
@@ -99,6 +145,8 @@ impl Peer {
 
 pub struct ConnectionPoolBehaviour {
     peer_id: PeerId,
+    /// Signs error particles sent to origins of refused particles
+    key_pair: KeyPair,
 
     commands: UnboundedReceiverStream<Command>,
 
@@ -106,6 +154,12 @@ pub struct ConnectionPoolBehaviour {
     subscribers: Vec<mpsc::UnboundedSender<LifecycleEvent>>,
 
     queue: VecDeque<ExtendedParticle>,
+    /// Particles from peers with low reputation, processed only when `queue` is empty
+    low_priority_queue: VecDeque<ExtendedParticle>,
+    /// Particles delayed because their senders exceeded bandwidth limits
+    throttled: DelayQueue<(PeerId, ExtendedParticle)>,
+    /// Number of particles in `throttled` by sender
+    throttled_by_peer: HashMap<PeerId, usize>,
     contacts: HashMap<PeerId, Peer>,
     dialing: HashMap<Multiaddr, Vec<oneshot::Sender<Option<Contact>>>>,
 
@@ -114,6 +168,36 @@ pub struct ConnectionPoolBehaviour {
     pub(super) protocol_config: ProtocolConfig,
 
     metrics: Option<ConnectionPoolMetrics>,
+    reputation: PeerReputation,
+    bandwidth: BandwidthLimiter,
+    rate_limiter: RateLimiter,
+    /// Particles larger than that are refused
+    max_particle_size: Option<usize>,
+    signatures: ParticleSignatureConfig,
+    peer_filter: PeerFilter,
+    dial_backoff: DialBackoff,
+    /// Dial IPv6 addresses first, see [happy_eyeballs_order]
+    prefer_ipv6: bool,
+    keep_alive: KeepAlive,
+    endpoint_peers: EndpointPeers,
+    /// Inbound connections accepted on endpoints that don't allow all peers
+    restricted_connections: HashMap<ConnectionId, (PeerId, AllowedPeers)>,
+    /// Created on the first poll, so the pool can be created outside of Tokio runtime
+    idle_check: Option<Interval>,
+    /// Completions of messages sent to remote peers
+    sending: FuturesUnordered<BoxFuture<'static, SendCompletion>>,
+    /// Number of messages in `sending` per remote peer
+    outbound: HashMap<PeerId, usize>,
+    /// When dials of pending outbound connections have started
+    dials: HashMap<ConnectionId, Instant>,
+    drain_state: Option<DrainState>,
+    /// Channels to notify when drain is finished
+    drain_promises: Vec<oneshot::Sender<()>>,
+    maintenance: Option<Maintenance>,
+    /// Shared with [MaintenanceAnnouncement]
+    maintenance_announced: Arc<AtomicBool>,
+    /// Previous peer ids of peers in a host key handover, shared with [HostHandover]
+    aliases: PeerAliases,
 }
 
 impl ConnectionPoolBehaviour {
@@ -127,6 +211,38 @@ impl ConnectionPoolBehaviour {
             Command::Send { to, particle, out } => self.send(to, particle, out),
             Command::CountConnections { out } => self.count_connections(out),
             Command::LifecycleEvents { out } => self.add_subscriber(out),
+            Command::PeerScores { out } => {
+                out.send(self.reputation.scores()).ok();
+            }
+            Command::ConnectedPeers { out } => {
+                out.send(self.connected_peers()).ok();
+            }
+            Command::PeerFilter { out } => {
+                out.send(self.peer_filter.entries()).ok();
+            }
+            Command::UpdatePeerFilter { update, out } => {
+                out.send(self.update_peer_filter(update)).ok();
+            }
+            Command::Drain { relays, out } => self.drain(relays, out),
+            Command::SetRateLimit { config, out } => {
+                self.rate_limiter.set_config(config);
+                out.send(()).ok();
+            }
+            Command::SetTrustCheck { trust_check, out } => {
+                self.peer_filter.set_trust_check(trust_check);
+                out.send(()).ok();
+            }
+            Command::SetMaintenance {
+                enabled,
+                relays,
+                out,
+            } => {
+                self.set_maintenance(enabled, relays);
+                out.send(self.maintenance_status()).ok();
+            }
+            Command::MaintenanceStatus { out } => {
+                out.send(self.maintenance_status()).ok();
+            }
         }
     }
 
@@ -134,6 +250,11 @@ impl ConnectionPoolBehaviour {
     /// `None` means something prevented us from connecting - dial reach failure or something else
     pub fn dial(&mut self, address: Multiaddr, out: oneshot::Sender<Option<Contact>>) {
         // TODO: return Contact immediately if that address is already connected
+        if self.dial_backoff.is_backed_off(None, &address) {
+            log::debug!(target: "network", "{}: won't dial {}: backing off after failed dials", self.peer_id, address);
+            out.send(None).ok();
+            return;
+        }
         self.dialing.entry(address.clone()).or_default().push(out);
 
         self.push_event(ToSwarm::Dial {
@@ -144,7 +265,26 @@ impl ConnectionPoolBehaviour {
     /// Connect to the contact by all of its known addresses and return whether connection succeeded
     /// If contact is already being dialed and there are no new addresses in Contact, don't dial
     /// If contact is already connected, return `true` immediately
-    pub fn connect(&mut self, new_contact: Contact, outlet: oneshot::Sender<bool>) {
+    /// If contact isn't connected and all of its addresses are backed off, return `false` immediately
+    pub fn connect(&mut self, mut new_contact: Contact, outlet: oneshot::Sender<bool>) {
+        new_contact.peer_id = self.resolve_alias(new_contact.peer_id);
+        let peer_id = new_contact.peer_id;
+        new_contact.addresses.retain(is_dialable);
+        let connected = self
+            .contacts
+            .get(&peer_id)
+            .is_some_and(|c| !c.connected.is_empty());
+        if !connected && !new_contact.addresses.is_empty() {
+            new_contact
+                .addresses
+                .retain(|addr| !self.dial_backoff.is_backed_off(Some(peer_id), addr));
+            if new_contact.addresses.is_empty() {
+                log::debug!(target: "network", "{}: won't dial {}: backing off after failed dials", self.peer_id, peer_id);
+                outlet.send(false).ok();
+                return;
+            }
+        }
+
         let addresses = match self.contacts.entry(new_contact.peer_id) {
             Entry::Occupied(mut entry) => {
                 let known_contact = entry.get_mut();
@@ -182,7 +322,7 @@ impl ConnectionPoolBehaviour {
         if !addresses.is_empty() {
             self.push_event(ToSwarm::Dial {
                 opts: DialOpts::peer_id(new_contact.peer_id)
-                    .addresses(addresses)
+                    .addresses(happy_eyeballs_order(addresses, self.prefer_ipv6))
                     .build(),
             });
         }
@@ -199,6 +339,7 @@ impl ConnectionPoolBehaviour {
 
     /// Returns whether given peer is connected or not
     pub fn is_connected(&self, peer_id: PeerId, outlet: oneshot::Sender<bool>) {
+        let peer_id = self.resolve_alias(peer_id);
         outlet.send(self.contacts.contains_key(&peer_id)).ok();
     }
 
@@ -212,10 +353,11 @@ impl ConnectionPoolBehaviour {
     /// Result is sent to channel inside `upgrade_outbound` in ProtocolHandler
     pub fn send(
         &mut self,
-        to: Contact,
+        mut to: Contact,
         particle: ExtendedParticle,
         outlet: oneshot::Sender<SendStatus>,
     ) {
+        to.peer_id = self.resolve_alias(to.peer_id);
         let span =
             tracing::info_span!(parent: particle.span.as_ref(), "ConnectionPool::Behaviour::send");
         let _guard = span.enter();
@@ -233,12 +375,15 @@ impl ConnectionPoolBehaviour {
                 to.peer_id
             );
             // Send particle to remote peer
+            self.keep_alive.on_activity(&to.peer_id);
+            let channel = self.track_sending(to.peer_id, Some(outlet));
             self.push_event(ToSwarm::NotifyHandler {
                 peer_id: to.peer_id,
                 handler: NotifyHandler::Any,
                 event: HandlerMessage::OutParticle(
                     particle.particle,
-                    CompletionChannel::Oneshot(outlet),
+                    channel,
+                    self.protocol_config.compression.clone(),
                 ),
             });
         } else {
@@ -247,10 +392,144 @@ impl ConnectionPoolBehaviour {
                 "Won't send particle to contact {}: not connected",
                 to.peer_id
             );
+            self.meter(|m| m.send_failed(SendFailure::NotConnected));
             outlet.send(SendStatus::NotConnected).ok();
         }
     }
 
+    /// Refuses `particle` received from `from` because it violates the node limits
+    fn reject(&mut self, from: PeerId, particle: &Particle, rejection: Rejection) {
+        self.meter(|m| m.particle_rejected(&particle.id));
+        self.send_rejection(from, particle, rejection);
+    }
+
+    /// Sends `from` an error particle telling the origin of `particle` why it was refused.
+    /// If `from` isn't the origin, it routes the error particle further like any other particle.
+    fn send_rejection(&mut self, from: PeerId, particle: &Particle, rejection: Rejection) {
+        let error_particle =
+            match rejection.error_particle(&particle.id, particle.init_peer_id, &self.key_pair) {
+                Ok(error_particle) => error_particle,
+                Err(err) => {
+                    tracing::warn!(
+                        particle_id = particle.id,
+                        "Can't create error particle: {err}"
+                    );
+                    return;
+                }
+            };
+        let channel = self.track_sending(from, None);
+        self.push_event(ToSwarm::NotifyHandler {
+            peer_id: from,
+            handler: NotifyHandler::Any,
+            event: HandlerMessage::OutParticle(
+                error_particle,
+                channel,
+                self.protocol_config.compression.clone(),
+            ),
+        });
+    }
+
+    /// Stops accepting inbound particles and connections and sends goodbye to connected clients,
+    /// suggesting up to `relays` connected nodes to reconnect to. Once outbound particles are sent,
+    /// closes all connections and notifies `outlet`
+    pub fn drain(&mut self, relays: usize, outlet: oneshot::Sender<()>) {
+        if self.drain_state.is_none() {
+            self.drain_state = Some(DrainState::Flushing);
+
+            let relays = self.alternative_relays(relays);
+            let clients: Vec<_> = self
+                .contacts
+                .keys()
+                .filter(|peer_id| self.keep_alive.class(peer_id) == Some(PeerClass::Client))
+                .copied()
+                .collect();
+            log::info!(target: "network", "{}: draining, sending goodbye to {} clients with {} relays", self.peer_id, clients.len(), relays.len());
+            for peer_id in clients {
+                let channel = self.track_sending(peer_id, None);
+                self.push_event(ToSwarm::NotifyHandler {
+                    peer_id,
+                    handler: NotifyHandler::Any,
+                    event: HandlerMessage::Goodbye(relays.clone(), channel),
+                });
+            }
+        }
+        self.drain_promises.push(outlet);
+        self.wake();
+    }
+
+    /// Enters or leaves maintenance. On entering sends goodbye to connected clients, suggesting up to
+    /// `relays` connected nodes to reconnect to. Unlike [Self::drain], clients are left to disconnect
+    /// on their own, while clients connected afterwards are disconnected once identified.
+    pub fn set_maintenance(&mut self, enabled: bool, relays: usize) {
+        match (enabled, self.maintenance.is_some()) {
+            (true, false) => {
+                let relays = self.alternative_relays(relays);
+                let clients: HashSet<_> = self
+                    .contacts
+                    .iter()
+                    .filter(|(peer_id, peer)| {
+                        !peer.connected.is_empty()
+                            && self.keep_alive.class(peer_id) == Some(PeerClass::Client)
+                    })
+                    .map(|(peer_id, _)| *peer_id)
+                    .collect();
+                log::info!(target: "network", "{}: entering maintenance, sending goodbye to {} clients with {} relays", self.peer_id, clients.len(), relays.len());
+                for peer_id in clients.iter().copied() {
+                    let channel = self.track_sending(peer_id, None);
+                    self.push_event(ToSwarm::NotifyHandler {
+                        peer_id,
+                        handler: NotifyHandler::Any,
+                        event: HandlerMessage::Goodbye(relays.clone(), channel),
+                    });
+                }
+                self.maintenance = Some(Maintenance {
+                    started: Instant::now(),
+                    clients,
+                    rejected: 0,
+                });
+            }
+            (false, true) => {
+                log::info!(target: "network", "{}: leaving maintenance", self.peer_id);
+                self.maintenance = None;
+            }
+            _ => {}
+        }
+        self.maintenance_announced.store(enabled, Ordering::Relaxed);
+        self.wake();
+    }
+
+    fn maintenance_status(&self) -> MaintenanceStatus {
+        match &self.maintenance {
+            Some(maintenance) => MaintenanceStatus {
+                enabled: true,
+                remaining_clients: maintenance.clients.len(),
+                rejected_clients: maintenance.rejected,
+                elapsed_secs: maintenance.started.elapsed().as_secs(),
+            },
+            None => MaintenanceStatus::default(),
+        }
+    }
+
+    /// Behaviour that announces maintenance of this pool via Identify
+    pub fn maintenance_announcement(&self) -> MaintenanceAnnouncement {
+        MaintenanceAnnouncement::new(self.maintenance_announced.clone())
+    }
+
+    /// Behaviour that announces `announcement` to connected peers and learns their handovers,
+    /// so particles sent to previous peer ids reach the peers that took them over
+    pub fn host_handover(&self, announcement: Option<HandoverAnnouncement>) -> HostHandover {
+        HostHandover::new(announcement, self.aliases.clone())
+    }
+
+    /// Peer that took over `peer_id` in a host key handover, if `peer_id` itself isn't connected
+    fn resolve_alias(&self, peer_id: PeerId) -> PeerId {
+        if self.contacts.contains_key(&peer_id) {
+            peer_id
+        } else {
+            self.aliases.resolve(peer_id)
+        }
+    }
+
     /// Returns number of connected contacts
     pub fn count_connections(&mut self, outlet: oneshot::Sender<usize>) {
         outlet.send(self.contacts.len()).ok();
@@ -269,17 +548,244 @@ impl ConnectionPoolBehaviour {
             .extend(addresses);
     }
 
+    /// Remembers protocols the connected peer advertised via Identify
+    pub fn set_protocols(&mut self, peer_id: PeerId, protocols: Vec<String>) {
+        if let Some(peer) = self.contacts.get_mut(&peer_id) {
+            peer.protocols = protocols;
+        }
+    }
+
+    fn connected_peers(&self) -> Vec<ConnectedPeer> {
+        self.contacts
+            .iter()
+            .filter(|(_, peer)| !peer.connected.is_empty())
+            .map(|(peer_id, peer)| ConnectedPeer {
+                peer_id: *peer_id,
+                addresses: peer.connected.iter().cloned().collect(),
+                class: self.keep_alive.class(peer_id),
+                protocols: peer.protocols.clone(),
+            })
+            .collect()
+    }
+
+    pub fn reputation(&self) -> &PeerReputation {
+        &self.reputation
+    }
+
+    /// Sets class of the connected peer once it's identified, see [KeepAliveConfig](crate::KeepAliveConfig).
+    /// Closes connections accepted on endpoints that don't allow that class, see [EndpointPeers],
+    /// and connections of clients that connected during maintenance.
+    pub fn set_peer_class(&mut self, peer_id: PeerId, class: PeerClass) {
+        self.keep_alive.set_class(&peer_id, class);
+
+        if let Some(maintenance) = self.maintenance.as_mut() {
+            if class == PeerClass::Client && !maintenance.clients.contains(&peer_id) {
+                log::debug!(target: "network", "{}: disconnecting client {}: node is in maintenance", self.peer_id, peer_id);
+                maintenance.rejected += 1;
+                self.push_event(ToSwarm::CloseConnection {
+                    peer_id,
+                    connection: All,
+                });
+                return;
+            }
+        }
+
+        let denied: Vec<_> = self
+            .restricted_connections
+            .iter()
+            .filter(|(_, (peer, allowed))| *peer == peer_id && !allowed.allows(class))
+            .map(|(connection_id, _)| *connection_id)
+            .collect();
+        for connection_id in denied {
+            log::debug!(
+                target: "network",
+                "{}: closing connection with {:?} {}, its endpoint doesn't allow that peer class",
+                self.peer_id,
+                class,
+                peer_id
+            );
+            self.restricted_connections.remove(&connection_id);
+            self.push_event(ToSwarm::CloseConnection {
+                peer_id,
+                connection: CloseConnection::One(connection_id),
+            });
+        }
+    }
+
+    /// Connected nodes with addresses they listen on, as reported by Identify.
+    /// Nodes that announce maintenance are skipped.
+    fn alternative_relays(&self, limit: usize) -> Vec<Contact> {
+        self.contacts
+            .iter()
+            .filter(|(peer_id, peer)| {
+                !peer.connected.is_empty()
+                    && !peer.discovered.is_empty()
+                    && !peer.protocols.iter().any(|p| p == MAINTENANCE_PROTOCOL)
+                    && matches!(
+                        self.keep_alive.class(peer_id),
+                        Some(PeerClass::Node | PeerClass::Bootstrap)
+                    )
+            })
+            .take(limit)
+            .map(|(peer_id, peer)| {
+                Contact::new(*peer_id, peer.discovered.iter().cloned().collect())
+            })
+            .collect()
+    }
+
+    /// Returns channel to pass to the handler along with the message. Status is forwarded
+    /// to `outlet`, while the message is kept in `sending` until it's sent or dropped.
+    fn track_sending(
+        &mut self,
+        to: PeerId,
+        outlet: Option<oneshot::Sender<SendStatus>>,
+    ) -> CompletionChannel {
+        let (status_outlet, status_inlet) = oneshot::channel();
+        self.sending.push(
+            async move {
+                // handler drops the channel if the connection is closed before sending
+                let failure = match status_inlet.await {
+                    Ok(status) => {
+                        let failure = send_failure(&status);
+                        if let Some(outlet) = outlet {
+                            outlet.send(status).ok();
+                        }
+                        failure
+                    }
+                    Err(_) => Some(SendFailure::ConnectionClosed),
+                };
+                (to, failure)
+            }
+            .boxed(),
+        );
+
+        let outbound = self.outbound.entry(to).or_default();
+        *outbound += 1;
+        let size = *outbound;
+        self.meter(|m| m.outbound_queue(&to, size));
+
+        CompletionChannel::Oneshot(status_outlet)
+    }
+
+    fn on_sent(&mut self, (peer_id, failure): SendCompletion) {
+        if let Entry::Occupied(mut entry) = self.outbound.entry(peer_id) {
+            *entry.get_mut() -= 1;
+            let size = *entry.get();
+            if size == 0 {
+                entry.remove();
+            }
+            self.meter(|m| m.outbound_queue(&peer_id, size));
+        }
+        if let Some(failure) = failure {
+            self.meter(|m| m.send_failed(failure));
+        }
+    }
+
+    fn on_dial_finished(&mut self, connection_id: ConnectionId, result: DialResult) {
+        if let Some(started) = self.dials.remove(&connection_id) {
+            self.meter(|m| m.dial_finished(result, started.elapsed()));
+        }
+    }
+
+    fn poll_drain(&mut self, cx: &mut Context<'_>) {
+        while let Poll::Ready(Some(completion)) = self.sending.poll_next_unpin(cx) {
+            self.on_sent(completion);
+        }
+
+        match self.drain_state {
+            Some(DrainState::Flushing) if self.sending.is_empty() => {
+                log::info!(target: "network", "{}: outbound particles are sent, closing connections", self.peer_id);
+                self.drain_state = Some(DrainState::Closing);
+                let connected: Vec<_> = self
+                    .contacts
+                    .iter()
+                    .filter(|(_, peer)| !peer.connected.is_empty())
+                    .map(|(peer_id, _)| *peer_id)
+                    .collect();
+                for peer_id in connected {
+                    self.push_event(ToSwarm::CloseConnection {
+                        peer_id,
+                        connection: All,
+                    });
+                }
+                self.poll_drain(cx);
+            }
+            Some(DrainState::Closing)
+                if self.contacts.values().all(|peer| peer.connected.is_empty()) =>
+            {
+                for out in self.drain_promises.drain(..) {
+                    out.send(()).ok();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn close_idle_connections(&mut self) {
+        for (peer_id, class) in self.keep_alive.idle_peers() {
+            log::debug!(target: "network", "{}: closing idle connection with {} ({:?})", self.peer_id, peer_id, class);
+            // forget the peer, so it isn't closed again while connections are closing
+            self.keep_alive.on_disconnected(&peer_id);
+            self.push_event(ToSwarm::CloseConnection {
+                peer_id,
+                connection: All,
+            });
+        }
+    }
+
+    fn disconnect_if_banned(&mut self, peer_id: PeerId) {
+        if self.reputation.is_banned(&peer_id) && self.contacts.contains_key(&peer_id) {
+            log::info!(target: "network", "{}: disconnecting {} due to low reputation", self.peer_id, peer_id);
+            self.push_event(ToSwarm::CloseConnection {
+                peer_id,
+                connection: All,
+            });
+        }
+    }
+
+    fn update_peer_filter(&mut self, update: PeerFilterUpdate) -> Result<bool, PeerFilterError> {
+        let changed = self.peer_filter.update(update)?;
+        if changed {
+            log::info!(target: "network", "{}: peer filter updated: {:?}", self.peer_id, update);
+            let denied: Vec<_> = self
+                .contacts
+                .keys()
+                .filter(|peer_id| !self.peer_filter.is_allowed(peer_id))
+                .copied()
+                .collect();
+            for peer_id in denied {
+                log::info!(target: "network", "{}: disconnecting {} denied by peer filter", self.peer_id, peer_id);
+                self.push_event(ToSwarm::CloseConnection {
+                    peer_id,
+                    connection: All,
+                });
+            }
+        }
+        Ok(changed)
+    }
+
     fn meter<U, F: Fn(&ConnectionPoolMetrics) -> U>(&self, f: F) {
         self.metrics.as_ref().map(f);
     }
 }
 
 impl ConnectionPoolBehaviour {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         buffer: usize,
         protocol_config: ProtocolConfig,
-        peer_id: PeerId,
+        key_pair: KeyPair,
         metrics: Option<ConnectionPoolMetrics>,
+        reputation: PeerReputation,
+        bandwidth: BandwidthLimiter,
+        rate_limiter: RateLimiter,
+        max_particle_size: Option<usize>,
+        signatures: ParticleSignatureConfig,
+        peer_filter: PeerFilter,
+        dial_backoff: DialBackoff,
+        prefer_ipv6: bool,
+        keep_alive: KeepAlive,
+        endpoint_peers: EndpointPeers,
     ) -> (Self, mpsc::Receiver<ExtendedParticle>, ConnectionPoolApi) {
         let (outlet, inlet) = mpsc::channel(buffer);
         let outlet = PollSender::new(outlet);
@@ -290,22 +796,81 @@ impl ConnectionPoolBehaviour {
         };
 
         let this = Self {
-            peer_id,
+            peer_id: key_pair.get_peer_id(),
+            key_pair,
             outlet,
             commands: UnboundedReceiverStream::new(command_inlet),
             subscribers: <_>::default(),
             queue: <_>::default(),
+            low_priority_queue: <_>::default(),
+            throttled: <_>::default(),
+            throttled_by_peer: <_>::default(),
             contacts: <_>::default(),
             dialing: <_>::default(),
             events: <_>::default(),
             waker: None,
             protocol_config,
             metrics,
+            reputation,
+            bandwidth,
+            rate_limiter,
+            max_particle_size,
+            signatures,
+            peer_filter,
+            dial_backoff,
+            prefer_ipv6,
+            keep_alive,
+            endpoint_peers,
+            restricted_connections: <_>::default(),
+            idle_check: None,
+            sending: <_>::default(),
+            outbound: <_>::default(),
+            dials: <_>::default(),
+            drain_state: None,
+            drain_promises: vec![],
+            maintenance: None,
+            maintenance_announced: <_>::default(),
+            aliases: <_>::default(),
         };
 
         (this, inlet, api)
     }
 
+    fn enqueue(&mut self, from: PeerId, particle: ExtendedParticle) {
+        if self.reputation.is_low_priority(&from) {
+            self.low_priority_queue.push_back(particle);
+        } else {
+            self.queue.push_back(particle);
+        }
+    }
+
+    /// Delays the particle, or drops it if too many particles are delayed already
+    fn throttle(&mut self, from: PeerId, particle: ExtendedParticle, delay: Duration) {
+        let particle_id = particle.particle.id.clone();
+        let from_peer = self.throttled_by_peer.get(&from).copied().unwrap_or(0);
+        if !self.bandwidth.may_delay(from_peer, self.throttled.len()) {
+            tracing::debug!(target: "network", particle_id, "Particle from {from} is dropped: too many throttled particles, retry after {delay:?}");
+            self.meter(|m| m.throttled_particle_dropped(&particle_id));
+            let rejection = Rejection::Throttled {
+                retry_after_ms: delay.as_millis() as u64,
+            };
+            self.send_rejection(from, &particle.particle, rejection);
+            return;
+        }
+
+        tracing::debug!(target: "network", particle_id, "Particle from {from} is throttled for {delay:?}");
+        self.throttled.insert((from, particle), delay);
+        *self.throttled_by_peer.entry(from).or_default() += 1;
+    }
+
+    fn handler(&self, peer_id: PeerId) -> THandler<Self> {
+        KeepAliveHandler::new(
+            self.protocol_config.clone().into(),
+            peer_id,
+            self.keep_alive.clone(),
+        )
+    }
+
     fn wake(&self) {
         if let Some(waker) = &self.waker {
             waker.wake_by_ref();
@@ -356,6 +921,7 @@ impl ConnectionPoolBehaviour {
     }
 
     fn remove_contact(&mut self, peer_id: &PeerId, reason: &str) {
+        self.keep_alive.on_disconnected(peer_id);
         if let Some(contact) = self.contacts.remove(peer_id) {
             log::debug!("Contact {} was removed: {}", peer_id, reason);
             self.lifecycle_event(LifecycleEvent::Disconnected(Contact::new(
@@ -372,6 +938,7 @@ impl ConnectionPoolBehaviour {
     }
 
     fn get_contact_impl(&self, peer_id: PeerId) -> Option<Contact> {
+        let peer_id = self.resolve_alias(peer_id);
         self.contacts.get(&peer_id).map(|c| Contact {
             peer_id,
             addresses: c.addresses().cloned().collect(),
@@ -387,6 +954,10 @@ impl ConnectionPoolBehaviour {
         let multiaddr = remote_multiaddr(cp);
         if remaining_established == 0 {
             self.remove_contact(peer_id, "disconnected");
+            // clients that left during maintenance can't come back until it's over
+            if let Some(maintenance) = self.maintenance.as_mut() {
+                maintenance.clients.remove(peer_id);
+            }
             log::debug!(
                 target: "network",
                 "{}: connection lost with {} @ {}",
@@ -436,10 +1007,12 @@ impl ConnectionPoolBehaviour {
                     ConnectedPoint::Dialer { address, .. } => address,
                     ConnectedPoint::Listener { send_back_addr, .. } => send_back_addr,
                 };
+                self.dial_backoff.on_failure(peer_id, addr);
                 self.cleanup_address(peer_id.as_ref(), addr);
             }
             DialError::Transport(addrs) => {
                 for (addr, _) in addrs {
+                    self.dial_backoff.on_failure(peer_id, addr);
                     self.cleanup_address(peer_id.as_ref(), addr);
                 }
             }
@@ -494,25 +1067,63 @@ impl ConnectionPoolBehaviour {
 }
 
 impl NetworkBehaviour for ConnectionPoolBehaviour {
-    type ConnectionHandler = OneShotHandler<ProtocolConfig, HandlerMessage, HandlerMessage>;
+    type ConnectionHandler =
+        KeepAliveHandler<OneShotHandler<ProtocolConfig, HandlerMessage, HandlerMessage>>;
     type ToSwarm = ();
 
     fn handle_pending_inbound_connection(
         &mut self,
         _connection_id: ConnectionId,
         _local_addr: &Multiaddr,
-        _remote_addr: &Multiaddr,
+        remote_addr: &Multiaddr,
     ) -> Result<(), ConnectionDenied> {
+        if self.drain_state.is_some() {
+            log::debug!(target: "network", "{}: inbound connection from {} denied: node is shutting down", self.peer_id, remote_addr);
+            return Err(ConnectionDenied::new("node is shutting down"));
+        }
         Ok(())
     }
 
     fn handle_established_inbound_connection(
         &mut self,
-        _connection_id: ConnectionId,
+        connection_id: ConnectionId,
         peer_id: PeerId,
-        _local_addr: &Multiaddr,
+        local_addr: &Multiaddr,
         remote_addr: &Multiaddr,
     ) -> Result<THandler<Self>, ConnectionDenied> {
+        if !self.peer_filter.is_allowed(&peer_id) {
+            log::debug!(
+                target: "network",
+                "{}: inbound connection from {} @ {} denied by peer filter",
+                self.peer_id,
+                peer_id,
+                remote_addr
+            );
+            return Err(ConnectionDenied::new(format!(
+                "peer {peer_id} is denied by peer filter"
+            )));
+        }
+
+        if self.reputation.is_banned(&peer_id) {
+            log::debug!(
+                target: "network",
+                "{}: inbound connection from {} @ {} denied due to low reputation",
+                self.peer_id,
+                peer_id,
+                remote_addr
+            );
+            return Err(ConnectionDenied::new(format!(
+                "peer {peer_id} is banned due to low reputation"
+            )));
+        }
+
+        // peer class is checked once the connection is identified, see [Self::set_peer_class]
+        let allowed = self.endpoint_peers.allowed(local_addr);
+        if allowed != AllowedPeers::All {
+            self.restricted_connections
+                .insert(connection_id, (peer_id, allowed));
+        }
+
         log::debug!(
             target: "network",
             "{}: inbound connection established with {} @ {}",
@@ -521,42 +1132,72 @@ impl NetworkBehaviour for ConnectionPoolBehaviour {
             remote_addr
         );
 
+        // send back address is usually ephemeral, so only per-peer backoff is reset
+        self.dial_backoff.on_success(peer_id, None);
         self.add_connected_address(peer_id, remote_addr.clone());
+        self.keep_alive.on_connected(peer_id, remote_addr);
 
         self.lifecycle_event(LifecycleEvent::Connected(Contact::new(
             peer_id,
             vec![remote_addr.clone()],
         )));
+        self.meter(|m| m.connection_established(ConnectionDirection::Inbound));
 
-        Ok(self.protocol_config.clone().into())
+        Ok(self.handler(peer_id))
     }
 
     fn handle_pending_outbound_connection(
         &mut self,
-        _connection_id: ConnectionId,
+        connection_id: ConnectionId,
         maybe_peer: Option<PeerId>,
         _addresses: &[Multiaddr],
         _effective_role: Endpoint,
     ) -> Result<Vec<Multiaddr>, ConnectionDenied> {
+        // swarm reports either established connection or dial failure, so the entry is removed either way
+        self.dials.insert(connection_id, Instant::now());
+        if self.drain_state == Some(DrainState::Closing) {
+            return Err(ConnectionDenied::new("node is shutting down"));
+        }
         let peer_id = match maybe_peer {
             None => return Ok(vec![]),
             Some(peer_id) => peer_id,
         };
-        Ok(self
+        if !self.peer_filter.is_allowed(&peer_id) {
+            return Err(ConnectionDenied::new(format!(
+                "peer {peer_id} is denied by peer filter"
+            )));
+        }
+        let addresses = self
             .contacts
             .get(&peer_id)
             .into_iter()
             .flat_map(|p| p.addresses().cloned())
-            .collect())
+            .filter(is_dialable);
+        Ok(happy_eyeballs_order(addresses, self.prefer_ipv6))
     }
 
     fn handle_established_outbound_connection(
         &mut self,
-        _connection_id: ConnectionId,
+        connection_id: ConnectionId,
         peer_id: PeerId,
         addr: &Multiaddr,
         _role_override: Endpoint,
     ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.on_dial_finished(connection_id, DialResult::Success);
+        // peer id of the dialed address may be unknown before the connection is established
+        if !self.peer_filter.is_allowed(&peer_id) {
+            log::debug!(
+                target: "network",
+                "{}: outbound connection to {} @ {} denied by peer filter",
+                self.peer_id,
+                peer_id,
+                addr
+            );
+            return Err(ConnectionDenied::new(format!(
+                "peer {peer_id} is denied by peer filter"
+            )));
+        }
+
         log::debug!(
             target: "network",
             "{}: outbound connection established with {} @ {}",
@@ -565,13 +1206,16 @@ impl NetworkBehaviour for ConnectionPoolBehaviour {
             addr
         );
 
+        self.dial_backoff.on_success(peer_id, Some(addr));
         self.add_connected_address(peer_id, addr.clone());
+        self.keep_alive.on_connected(peer_id, addr);
 
         self.lifecycle_event(LifecycleEvent::Connected(Contact::new(
             peer_id,
             vec![addr.clone()],
         )));
-        Ok(self.protocol_config.clone().into())
+        self.meter(|m| m.connection_established(ConnectionDirection::Outbound));
+        Ok(self.handler(peer_id))
     }
 
     fn on_swarm_event(&mut self, event: FromSwarm<'_>) {
@@ -583,6 +1227,13 @@ impl NetworkBehaviour for ConnectionPoolBehaviour {
                 }
             }
             FromSwarm::ConnectionClosed(event) => {
+                self.restricted_connections.remove(&event.connection_id);
+                let direction = if event.endpoint.is_dialer() {
+                    ConnectionDirection::Outbound
+                } else {
+                    ConnectionDirection::Inbound
+                };
+                self.meter(|m| m.connection_closed(direction));
                 self.on_connection_closed(
                     &event.peer_id,
                     event.endpoint,
@@ -591,6 +1242,7 @@ impl NetworkBehaviour for ConnectionPoolBehaviour {
             }
             FromSwarm::AddressChange(_) => {}
             FromSwarm::DialFailure(event) => {
+                self.on_dial_finished(event.connection_id, DialResult::Failure);
                 self.on_dial_failure(event.peer_id, event.error);
             }
             FromSwarm::ListenFailure(event) => {
@@ -624,6 +1276,55 @@ impl NetworkBehaviour for ConnectionPoolBehaviour {
                 tracing::info!(target: "network", particle_id = particle.id,"{}: received particle from {}; queue {}", self.peer_id, from, self.queue.len());
                 let root_span = tracing::info_span!("Particle", particle_id = particle.id);
 
+                if self.drain_state.is_some() {
+                    tracing::debug!(target: "network", particle_id = particle.id, "Particle from {from} is dropped: node is shutting down");
+                    return;
+                }
+
+                if !self.peer_filter.is_allowed(&from)
+                    || !self.peer_filter.is_allowed(&particle.init_peer_id)
+                {
+                    tracing::debug!(target: "network", particle_id = particle.id, "Particle from {from} initiated by {} is denied by peer filter", particle.init_peer_id);
+                    return;
+                }
+
+                let size = particle.data.len() + particle.script.len();
+                if let Some(limit) = self.max_particle_size.filter(|limit| size > *limit) {
+                    tracing::debug!(target: "network", particle_id = particle.id, "Particle from {from} is refused: size {size} exceeds the limit of {limit} bytes");
+                    let rejection = Rejection::ParticleTooLarge {
+                        size: size as u64,
+                        limit: limit as u64,
+                    };
+                    self.reject(from, &particle, rejection);
+                    return;
+                }
+
+                if self.signatures.is_enforced(&from, &particle) {
+                    if let Err((reason, rejection)) = verify_signature(&particle) {
+                        tracing::debug!(target: "network", particle_id = particle.id, "Particle from {from} is refused: {rejection}");
+                        self.meter(|m| m.particle_signature_rejected(reason));
+                        self.reject(from, &particle, rejection);
+                        return;
+                    }
+                }
+
+                if let Err(retry_after) = self.rate_limiter.check(from, particle.data.len()) {
+                    tracing::debug!(target: "network", particle_id = particle.id, "Particle from {from} is refused: rate limit exceeded, retry after {retry_after:?}");
+                    self.meter(|m| m.particle_throttled(&particle.id));
+                    let rejection = Rejection::Throttled {
+                        retry_after_ms: retry_after.as_millis() as u64,
+                    };
+                    self.send_rejection(from, &particle, rejection);
+                    return;
+                }
+
+                self.reputation.record_particle(from);
+                self.keep_alive.on_activity(&from);
+                if particle.is_expired() {
+                    self.reputation.report(from, Offence::ExpiredParticle);
+                }
+                self.disconnect_if_banned(from);
+
                 self.meter(|m| {
                     m.incoming_particle(
                         &particle.id,
@@ -631,26 +1332,65 @@ impl NetworkBehaviour for ConnectionPoolBehaviour {
                         particle.data.len() as f64,
                     )
                 });
-                self.queue
-                    .push_back(ExtendedParticle::new(particle, root_span));
+                let delay = self.bandwidth.inbound(from, particle.data.len());
+                let particle = ExtendedParticle::new(particle, root_span);
+                if delay.is_zero() {
+                    self.enqueue(from, particle);
+                } else {
+                    self.throttle(from, particle, delay);
+                }
                 self.wake();
             }
+            Ok(HandlerMessage::Goodbye(relays, _)) => {
+                log::debug!(target: "network", "{}: {} is shutting down, suggested relays: {:?}", self.peer_id, from, relays);
+            }
             Ok(HandlerMessage::Upgrade) => {}
             Ok(HandlerMessage::OutParticle(..)) => unreachable!("can't receive OutParticle"),
-            Err(err) => log::warn!("Handler error: {:?}", err),
+            Err(err) => {
+                log::warn!("Handler error: {:?}", err);
+                // decoding errors are reported as InvalidInput by the codec
+                if let StreamUpgradeError::Apply(err) = &err {
+                    if err.kind() == io::ErrorKind::InvalidInput {
+                        self.reputation.report(from, Offence::MalformedParticle);
+                        self.disconnect_if_banned(from);
+                    }
+                }
+            }
         }
     }
 
     fn poll(&mut self, cx: &mut Context<'_>) -> Poll<SwarmEventType> {
         self.waker = Some(cx.waker().clone());
 
+        let idle_check = self
+            .idle_check
+            .get_or_insert_with(|| tokio::time::interval(IDLE_CHECK_INTERVAL));
+        if idle_check.poll_tick(cx).is_ready() {
+            self.close_idle_connections();
+        }
+
+        while let Poll::Ready(Some(expired)) = self.throttled.poll_expired(cx) {
+            let (from, particle) = expired.into_inner();
+            if let Entry::Occupied(mut count) = self.throttled_by_peer.entry(from) {
+                *count.get_mut() -= 1;
+                if *count.get() == 0 {
+                    count.remove();
+                }
+            }
+            self.enqueue(from, particle);
+        }
+
         loop {
             // Check backpressure on the outlet
             let mut outlet = Pin::new(&mut self.outlet);
             match outlet.as_mut().poll_ready(cx) {
                 Poll::Ready(Ok(_)) => {
                     // channel is ready to consume more particles, so send them
-                    if let Some(particle) = self.queue.pop_front() {
+                    let particle = self
+                        .queue
+                        .pop_front()
+                        .or_else(|| self.low_priority_queue.pop_front());
+                    if let Some(particle) = particle {
                         let particle_id = particle.particle.id.clone();
 
                         if let Err(err) = outlet.start_send(particle) {
@@ -672,7 +1412,7 @@ impl NetworkBehaviour for ConnectionPoolBehaviour {
                 }
                 Poll::Pending => {
                     // if channel is full, then keep particles in the queue
-                    let len = self.queue.len();
+                    let len = self.queue.len() + self.low_priority_queue.len();
                     if len > 30 {
                         log::warn!("Particle queue seems to have stalled; queue {}", len);
                     } else {
@@ -690,11 +1430,16 @@ impl NetworkBehaviour for ConnectionPoolBehaviour {
             }
         }
 
-        self.meter(|m| m.particle_queue_size.set(self.queue.len() as i64));
+        self.meter(|m| {
+            m.particle_queue_size
+                .set((self.queue.len() + self.low_priority_queue.len()) as i64)
+        });
         while let Poll::Ready(Some(cmd)) = self.commands.poll_next_unpin(cx) {
             self.execute(cmd)
         }
 
+        self.poll_drain(cx);
+
         if let Some(event) = self.events.pop_front() {
             return Poll::Ready(event);
         }
@@ -702,3 +1447,13 @@ impl NetworkBehaviour for ConnectionPoolBehaviour {
         Poll::Pending
     }
 }
+
+fn send_failure(status: &SendStatus) -> Option<SendFailure> {
+    match status {
+        SendStatus::Ok => None,
+        SendStatus::TimedOut { .. } => Some(SendFailure::TimedOut),
+        SendStatus::ProtocolError(_) => Some(SendFailure::ProtocolError),
+        SendStatus::NotConnected => Some(SendFailure::NotConnected),
+        SendStatus::ConnectionPoolDied => Some(SendFailure::ConnectionClosed),
+    }
+}
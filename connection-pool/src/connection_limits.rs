@@ -0,0 +1,380 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::net::IpAddr;
+use std::task::{Context, Poll};
+
+use libp2p::core::{ConnectedPoint, Endpoint, Multiaddr};
+use libp2p::multiaddr::Protocol;
+use libp2p::swarm::behaviour::{ConnectionEstablished, DialFailure, ListenFailure};
+use libp2p::swarm::{
+    dummy, ConnectionClosed, ConnectionDenied, ConnectionId, FromSwarm, NetworkBehaviour, THandler,
+    THandlerInEvent, THandlerOutEvent, ToSwarm,
+};
+use libp2p::PeerId;
+
+use peer_metrics::{ConnectionLimit, ConnectionPoolMetrics};
+
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionLimits {
+    max_pending_incoming: Option<u32>,
+    max_pending_outgoing: Option<u32>,
+    max_established_incoming: Option<u32>,
+    max_established_outgoing: Option<u32>,
+    max_established_per_peer: Option<u32>,
+    max_established_per_ip: Option<u32>,
+    max_established_total: Option<u32>,
+}
+
+impl ConnectionLimits {
+    pub fn with_max_pending_incoming(mut self, limit: Option<u32>) -> Self {
+        self.max_pending_incoming = limit;
+        self
+    }
+
+    pub fn with_max_pending_outgoing(mut self, limit: Option<u32>) -> Self {
+        self.max_pending_outgoing = limit;
+        self
+    }
+
+    pub fn with_max_established_incoming(mut self, limit: Option<u32>) -> Self {
+        self.max_established_incoming = limit;
+        self
+    }
+
+    pub fn with_max_established_outgoing(mut self, limit: Option<u32>) -> Self {
+        self.max_established_outgoing = limit;
+        self
+    }
+
+    pub fn with_max_established_per_peer(mut self, limit: Option<u32>) -> Self {
+        self.max_established_per_peer = limit;
+        self
+    }
+
+    /// Limits inbound connections coming from a single IP address
+    pub fn with_max_established_per_ip(mut self, limit: Option<u32>) -> Self {
+        self.max_established_per_ip = limit;
+        self
+    }
+
+    pub fn with_max_established(mut self, limit: Option<u32>) -> Self {
+        self.max_established_total = limit;
+        self
+    }
+}
+
+/// Reason of a connection being denied by [`ConnectionLimitsBehaviour`].
+/// Can be obtained by downcasting [`ConnectionDenied`].
+#[derive(Debug, Clone, Copy)]
+pub struct Exceeded {
+    limit: ConnectionLimit,
+    max: u32,
+}
+
+impl Exceeded {
+    pub fn limit(&self) -> ConnectionLimit {
+        self.limit
+    }
+
+    pub fn max(&self) -> u32 {
+        self.max
+    }
+}
+
+impl fmt::Display for Exceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let what = match self.limit {
+            ConnectionLimit::PendingIncoming => "pending incoming connections",
+            ConnectionLimit::PendingOutgoing => "pending outgoing connections",
+            ConnectionLimit::EstablishedIncoming => "established incoming connections",
+            ConnectionLimit::EstablishedOutgoing => "established outgoing connections",
+            ConnectionLimit::EstablishedPerPeer => "established connections per peer",
+            ConnectionLimit::EstablishedPerIp => "established incoming connections per IP",
+            ConnectionLimit::EstablishedTotal => "established connections",
+        };
+        write!(
+            f,
+            "connection limit exceeded: at most {} {what} are allowed",
+            self.max
+        )
+    }
+}
+
+impl std::error::Error for Exceeded {}
+
+/// Enforces [`ConnectionLimits`], counting denied connections in metrics
+pub struct ConnectionLimitsBehaviour {
+    limits: ConnectionLimits,
+    metrics: Option<ConnectionPoolMetrics>,
+
+    pending_inbound_connections: HashSet<ConnectionId>,
+    pending_outbound_connections: HashSet<ConnectionId>,
+    established_inbound_connections: HashSet<ConnectionId>,
+    established_outbound_connections: HashSet<ConnectionId>,
+    established_per_peer: HashMap<PeerId, HashSet<ConnectionId>>,
+    established_per_ip: HashMap<IpAddr, HashSet<ConnectionId>>,
+}
+
+impl ConnectionLimitsBehaviour {
+    pub fn new(limits: ConnectionLimits, metrics: Option<ConnectionPoolMetrics>) -> Self {
+        Self {
+            limits,
+            metrics,
+            pending_inbound_connections: <_>::default(),
+            pending_outbound_connections: <_>::default(),
+            established_inbound_connections: <_>::default(),
+            established_outbound_connections: <_>::default(),
+            established_per_peer: <_>::default(),
+            established_per_ip: <_>::default(),
+        }
+    }
+
+    fn check(
+        &self,
+        max: Option<u32>,
+        current: usize,
+        limit: ConnectionLimit,
+    ) -> Result<(), ConnectionDenied> {
+        match max {
+            Some(max) if current >= max as usize => {
+                let exceeded = Exceeded { limit, max };
+                log::debug!(target: "network", "Connection denied: {exceeded}");
+                if let Some(m) = self.metrics.as_ref() {
+                    m.connection_denied(limit)
+                }
+                Err(ConnectionDenied::new(exceeded))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check_established(&self, peer: &PeerId) -> Result<(), ConnectionDenied> {
+        self.check(
+            self.limits.max_established_per_peer,
+            self.established_per_peer.get(peer).map_or(0, HashSet::len),
+            ConnectionLimit::EstablishedPerPeer,
+        )?;
+        self.check(
+            self.limits.max_established_total,
+            self.established_inbound_connections.len()
+                + self.established_outbound_connections.len(),
+            ConnectionLimit::EstablishedTotal,
+        )
+    }
+
+    fn check_ip(&self, remote_addr: &Multiaddr) -> Result<(), ConnectionDenied> {
+        match remote_ip(remote_addr) {
+            Some(ip) => self.check(
+                self.limits.max_established_per_ip,
+                self.established_per_ip.get(&ip).map_or(0, HashSet::len),
+                ConnectionLimit::EstablishedPerIp,
+            ),
+            None => Ok(()),
+        }
+    }
+}
+
+/// IP of a directly connected remote. Relayed connections aren't attributed to the relay's IP.
+fn remote_ip(addr: &Multiaddr) -> Option<IpAddr> {
+    if addr.iter().any(|p| p == Protocol::P2pCircuit) {
+        return None;
+    }
+
+    addr.iter().find_map(|p| match p {
+        Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+        Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+        _ => None,
+    })
+}
+
+impl NetworkBehaviour for ConnectionLimitsBehaviour {
+    type ConnectionHandler = dummy::ConnectionHandler;
+    type ToSwarm = void::Void;
+
+    fn handle_pending_inbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        _local_addr: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<(), ConnectionDenied> {
+        self.check(
+            self.limits.max_pending_incoming,
+            self.pending_inbound_connections.len(),
+            ConnectionLimit::PendingIncoming,
+        )?;
+        // reject early, before spending resources on the handshake
+        self.check_ip(remote_addr)?;
+
+        self.pending_inbound_connections.insert(connection_id);
+
+        Ok(())
+    }
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        peer: PeerId,
+        _local_addr: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.pending_inbound_connections.remove(&connection_id);
+
+        self.check(
+            self.limits.max_established_incoming,
+            self.established_inbound_connections.len(),
+            ConnectionLimit::EstablishedIncoming,
+        )?;
+        self.check_ip(remote_addr)?;
+        self.check_established(&peer)?;
+
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn handle_pending_outbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        _maybe_peer: Option<PeerId>,
+        _addresses: &[Multiaddr],
+        _effective_role: Endpoint,
+    ) -> Result<Vec<Multiaddr>, ConnectionDenied> {
+        self.check(
+            self.limits.max_pending_outgoing,
+            self.pending_outbound_connections.len(),
+            ConnectionLimit::PendingOutgoing,
+        )?;
+
+        self.pending_outbound_connections.insert(connection_id);
+
+        Ok(vec![])
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        peer: PeerId,
+        _addr: &Multiaddr,
+        _role_override: Endpoint,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.pending_outbound_connections.remove(&connection_id);
+
+        self.check(
+            self.limits.max_established_outgoing,
+            self.established_outbound_connections.len(),
+            ConnectionLimit::EstablishedOutgoing,
+        )?;
+        self.check_established(&peer)?;
+
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm<'_>) {
+        match event {
+            FromSwarm::ConnectionEstablished(ConnectionEstablished {
+                peer_id,
+                connection_id,
+                endpoint,
+                ..
+            }) => {
+                match endpoint {
+                    ConnectedPoint::Listener { send_back_addr, .. } => {
+                        self.established_inbound_connections.insert(connection_id);
+                        if let Some(ip) = remote_ip(send_back_addr) {
+                            self.established_per_ip
+                                .entry(ip)
+                                .or_default()
+                                .insert(connection_id);
+                        }
+                    }
+                    ConnectedPoint::Dialer { .. } => {
+                        self.established_outbound_connections.insert(connection_id);
+                    }
+                }
+                self.established_per_peer
+                    .entry(peer_id)
+                    .or_default()
+                    .insert(connection_id);
+            }
+            FromSwarm::ConnectionClosed(ConnectionClosed {
+                peer_id,
+                connection_id,
+                endpoint,
+                ..
+            }) => {
+                self.established_inbound_connections.remove(&connection_id);
+                self.established_outbound_connections.remove(&connection_id);
+                remove_connection(&mut self.established_per_peer, peer_id, &connection_id);
+                if let ConnectedPoint::Listener { send_back_addr, .. } = endpoint {
+                    if let Some(ip) = remote_ip(send_back_addr) {
+                        remove_connection(&mut self.established_per_ip, ip, &connection_id);
+                    }
+                }
+            }
+            FromSwarm::DialFailure(DialFailure { connection_id, .. }) => {
+                self.pending_outbound_connections.remove(&connection_id);
+            }
+            FromSwarm::ListenFailure(ListenFailure { connection_id, .. }) => {
+                self.pending_inbound_connections.remove(&connection_id);
+            }
+            _ => {}
+        }
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        _peer_id: PeerId,
+        _connection_id: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        void::unreachable(event)
+    }
+
+    fn poll(&mut self, _: &mut Context<'_>) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        Poll::Pending
+    }
+}
+
+fn remove_connection<K: std::hash::Hash + Eq>(
+    connections: &mut HashMap<K, HashSet<ConnectionId>>,
+    key: K,
+    connection_id: &ConnectionId,
+) {
+    if let Some(set) = connections.get_mut(&key) {
+        set.remove(connection_id);
+        if set.is_empty() {
+            connections.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remote_ip_ignores_relayed_connections() {
+        let direct: Multiaddr = "/ip4/1.2.3.4/tcp/7777".parse().unwrap();
+        assert_eq!(remote_ip(&direct), Some("1.2.3.4".parse().unwrap()));
+
+        let ws: Multiaddr = "/ip6/::1/tcp/9999/ws".parse().unwrap();
+        assert_eq!(remote_ip(&ws), Some("::1".parse().unwrap()));
+
+        let relayed: Multiaddr = "/ip4/1.2.3.4/tcp/7777/p2p-circuit".parse().unwrap();
+        assert_eq!(remote_ip(&relayed), None);
+    }
+}
@@ -18,8 +18,15 @@ use std::fmt::{Display, Formatter};
 
 use futures::{future::BoxFuture, stream::BoxStream};
 use libp2p::{core::Multiaddr, PeerId};
+use serde::Serialize;
 
 use particle_protocol::{Contact, ExtendedParticle, SendStatus};
+use peer_reputation::PeerScore;
+
+use crate::keep_alive::PeerClass;
+use crate::maintenance::MaintenanceStatus;
+use crate::peer_filter::{PeerFilterConfig, PeerFilterError, PeerFilterUpdate, TrustCheck};
+use crate::rate_limit::RateLimitConfig;
 
 #[derive(Debug, Clone)]
 pub enum LifecycleEvent {
@@ -36,6 +43,23 @@ impl Display for LifecycleEvent {
     }
 }
 
+/// Peer with established connections, see [ConnectionPoolT::connected_peers]
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectedPeer {
+    #[serde(serialize_with = "serialize_peer_id")]
+    pub peer_id: PeerId,
+    /// Addresses of established connections
+    pub addresses: Vec<Multiaddr>,
+    /// `None` until the peer is identified
+    pub class: Option<PeerClass>,
+    /// Protocols advertised by the peer via Identify
+    pub protocols: Vec<String>,
+}
+
+fn serialize_peer_id<S: serde::Serializer>(peer_id: &PeerId, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&peer_id.to_base58())
+}
+
 pub trait ConnectionPoolT {
     fn dial(&self, addr: Multiaddr) -> BoxFuture<'static, Option<Contact>>;
     fn connect(&self, contact: Contact) -> BoxFuture<'static, bool>;
@@ -45,4 +69,29 @@ pub trait ConnectionPoolT {
     fn send(&self, to: Contact, particle: ExtendedParticle) -> BoxFuture<'static, SendStatus>;
     fn count_connections(&self) -> BoxFuture<'static, usize>;
     fn lifecycle_events(&self) -> BoxStream<'static, LifecycleEvent>;
+    fn peer_scores(&self) -> BoxFuture<'static, Vec<PeerScore>>;
+    fn connected_peers(&self) -> BoxFuture<'static, Vec<ConnectedPeer>>;
+    /// Entries of the allowlist and denylist, both from config and added at runtime
+    fn peer_filter(&self) -> BoxFuture<'static, PeerFilterConfig>;
+    /// Returns whether the lists have changed
+    fn update_peer_filter(
+        &self,
+        update: PeerFilterUpdate,
+    ) -> BoxFuture<'static, Result<bool, PeerFilterError>>;
+    /// Stops accepting inbound particles and connections, sends goodbye with up to `relays`
+    /// alternative relays to clients, waits for outbound particles to be sent and closes connections
+    fn drain(&self, relays: usize) -> BoxFuture<'static, ()>;
+    /// Replaces per-peer ingress rate limits
+    fn set_rate_limit(&self, config: RateLimitConfig) -> BoxFuture<'static, ()>;
+    /// Allows peers outside of the allowlist that pass `trust_check`, `None` to stop consulting it
+    fn set_trust_check(&self, trust_check: Option<TrustCheck>) -> BoxFuture<'static, ()>;
+    /// In maintenance the node sends goodbye with up to `relays` alternative relays to connected
+    /// clients, disconnects clients that connect afterwards and announces maintenance via Identify.
+    /// Connections with other nodes are kept.
+    fn set_maintenance(
+        &self,
+        enabled: bool,
+        relays: usize,
+    ) -> BoxFuture<'static, MaintenanceStatus>;
+    fn maintenance_status(&self) -> BoxFuture<'static, MaintenanceStatus>;
 }
@@ -0,0 +1,39 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// How the node closes its connections on shutdown
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DrainConfig {
+    /// Max time to wait for outbound particles to be sent before connections are closed
+    #[serde(with = "humantime_serde")]
+    pub timeout: Duration,
+    /// Max number of connected nodes suggested to clients as alternative relays
+    pub relays: usize,
+}
+
+impl Default for DrainConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+            relays: 3,
+        }
+    }
+}
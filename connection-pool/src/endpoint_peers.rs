@@ -0,0 +1,118 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use libp2p::multiaddr::Protocol;
+use libp2p::Multiaddr;
+use serde::{Deserialize, Serialize};
+
+use crate::keep_alive::PeerClass;
+
+/// Classes of peers that can connect to a listen endpoint
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AllowedPeers {
+    #[default]
+    All,
+    /// Peers that don't take part in the DHT, i.e. SDK clients
+    Clients,
+    /// Other nodes, including bootstrap ones
+    Nodes,
+}
+
+impl AllowedPeers {
+    pub fn allows(&self, class: PeerClass) -> bool {
+        match self {
+            AllowedPeers::All => true,
+            AllowedPeers::Clients => class == PeerClass::Client,
+            AllowedPeers::Nodes => matches!(class, PeerClass::Node | PeerClass::Bootstrap),
+        }
+    }
+}
+
+/// Peer classes allowed on listen endpoints. Class of a peer is known only once it's identified,
+/// so inbound connections of other classes are closed after identification.
+#[derive(Clone, Debug, Default)]
+pub struct EndpointPeers {
+    /// Listen addresses of endpoints that don't allow all peers
+    endpoints: Vec<(Multiaddr, AllowedPeers)>,
+}
+
+impl EndpointPeers {
+    pub fn new(endpoints: impl IntoIterator<Item = (Multiaddr, AllowedPeers)>) -> Self {
+        Self {
+            endpoints: endpoints
+                .into_iter()
+                .filter(|(_, allowed)| *allowed != AllowedPeers::All)
+                .collect(),
+        }
+    }
+
+    /// Peers allowed on the connection accepted on `local_addr`
+    pub fn allowed(&self, local_addr: &Multiaddr) -> AllowedPeers {
+        self.endpoints
+            .iter()
+            .find(|(listen_addr, _)| is_accepted_on(local_addr, listen_addr))
+            .map_or(AllowedPeers::All, |(_, allowed)| *allowed)
+    }
+}
+
+/// Whether connection with `local_addr` is accepted by listener on `listen_addr`,
+/// unspecified ip of the listener matches any ip of the same family
+fn is_accepted_on(local_addr: &Multiaddr, listen_addr: &Multiaddr) -> bool {
+    let mut local = local_addr.iter();
+    let mut listen = listen_addr.iter();
+    loop {
+        match (local.next(), listen.next()) {
+            (None, None) => return true,
+            (Some(Protocol::Ip4(_)), Some(Protocol::Ip4(ip))) if ip.is_unspecified() => {}
+            (Some(Protocol::Ip6(_)), Some(Protocol::Ip6(ip))) if ip.is_unspecified() => {}
+            (Some(local), Some(listen)) if local == listen => {}
+            _ => return false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn match_local_address() {
+        let endpoints = EndpointPeers::new([
+            (
+                "/ip4/0.0.0.0/tcp/7777".parse().unwrap(),
+                AllowedPeers::Nodes,
+            ),
+            (
+                "/ip4/10.0.0.1/tcp/9999/ws".parse().unwrap(),
+                AllowedPeers::Clients,
+            ),
+            ("/ip4/0.0.0.0/tcp/8888".parse().unwrap(), AllowedPeers::All),
+        ]);
+
+        let tcp = "/ip4/10.0.0.1/tcp/7777".parse().unwrap();
+        assert_eq!(endpoints.allowed(&tcp), AllowedPeers::Nodes);
+        let ws = "/ip4/10.0.0.1/tcp/9999/ws".parse().unwrap();
+        assert_eq!(endpoints.allowed(&ws), AllowedPeers::Clients);
+        let other_ip = "/ip4/10.0.0.2/tcp/9999/ws".parse().unwrap();
+        assert_eq!(endpoints.allowed(&other_ip), AllowedPeers::All);
+        let ipv6 = "/ip6/::1/tcp/7777".parse().unwrap();
+        assert_eq!(endpoints.allowed(&ipv6), AllowedPeers::All);
+
+        assert!(AllowedPeers::Nodes.allows(PeerClass::Bootstrap));
+        assert!(!AllowedPeers::Clients.allows(PeerClass::Node));
+    }
+}
@@ -0,0 +1,380 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{io, iter};
+
+use fluence_keypair::{PublicKey, Signature};
+use futures::{future::BoxFuture, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, FutureExt};
+use libp2p::core::{Endpoint, InboundUpgrade, Multiaddr, OutboundUpgrade, UpgradeInfo};
+use libp2p::swarm::behaviour::ConnectionEstablished;
+use libp2p::swarm::handler::SubstreamProtocol;
+use libp2p::swarm::{
+    ConnectionDenied, ConnectionId, FromSwarm, NetworkBehaviour, NotifyHandler, OneShotHandler,
+    OneShotHandlerConfig, THandler, THandlerInEvent, THandlerOutEvent, ToSwarm,
+};
+use libp2p::PeerId;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+
+/// Sent by a node that rotated its host key to every peer it connects to
+pub const HANDOVER_PROTOCOL: &str = "/fluence/host-handover/1.0.0";
+
+/// Announcements larger than that are rejected
+const MAX_MESSAGE_SIZE: u64 = 4 * 1024;
+const UPGRADE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Proof that the peer sending it took over `previous_peer_id`
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct HandoverAnnouncement {
+    pub previous_peer_id: String,
+    /// Peer id of the sender signed by the previous key, in base58
+    pub signature: String,
+    /// UNIX timestamp in seconds when the previous peer id is retired
+    pub retires_at: u64,
+}
+
+impl HandoverAnnouncement {
+    /// Previous peer id of `peer_id` if the announcement is signed by the previous key
+    pub fn verify(&self, peer_id: &PeerId) -> Option<PeerId> {
+        let previous = PeerId::from_str(&self.previous_peer_id).ok()?;
+        let public_key: PublicKey = previous.try_into().ok()?;
+        let signature = bs58::decode(&self.signature).into_vec().ok()?;
+        let signature = Signature::from_bytes(public_key.get_key_format(), signature);
+        public_key.verify(&peer_id.to_bytes(), &signature).ok()?;
+        Some(previous)
+    }
+}
+
+/// Previous peer ids of the peers that announced a host key handover.
+/// Shared between [HostHandover], which learns them, and the connection pool, which routes
+/// particles sent to a previous peer id to the peer that took it over.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PeerAliases {
+    /// Previous peer id to the current one and the UNIX timestamp in seconds it's retired at
+    aliases: Arc<RwLock<HashMap<PeerId, (PeerId, u64)>>>,
+}
+
+impl PeerAliases {
+    /// A peer has a single previous peer id, an announcement replaces the earlier one
+    fn insert(&self, previous: PeerId, peer_id: PeerId, retires_at: u64) {
+        let now = now_secs();
+        let mut aliases = self.aliases.write();
+        aliases.retain(|_, (current, retires_at)| *current != peer_id && *retires_at > now);
+        if retires_at > now {
+            aliases.insert(previous, (peer_id, retires_at));
+        }
+    }
+
+    /// Peer that took over `peer_id`, or `peer_id` itself if it wasn't taken over
+    pub(crate) fn resolve(&self, peer_id: PeerId) -> PeerId {
+        match self.aliases.read().get(&peer_id) {
+            Some((current, retires_at)) if *retires_at > now_secs() => *current,
+            _ => peer_id,
+        }
+    }
+}
+
+/// Announces the host key handover of this node to every connected peer, bootstrap nodes
+/// included, and learns the handovers announced by others.
+pub struct HostHandover {
+    /// `None` if this node isn't in a handover
+    announcement: Option<HandoverAnnouncement>,
+    aliases: PeerAliases,
+    events: VecDeque<ToSwarm<(), HandoverAnnouncement>>,
+    waker: Option<Waker>,
+}
+
+impl HostHandover {
+    pub(crate) fn new(announcement: Option<HandoverAnnouncement>, aliases: PeerAliases) -> Self {
+        Self {
+            announcement,
+            aliases,
+            events: <_>::default(),
+            waker: None,
+        }
+    }
+
+    fn on_announcement(&mut self, peer_id: PeerId, announcement: HandoverAnnouncement) {
+        match announcement.verify(&peer_id) {
+            Some(previous) => {
+                log::info!(
+                    target: "network",
+                    "Peer {} took over {}, particles to it are routed to {} until {}",
+                    peer_id, previous, peer_id, announcement.retires_at
+                );
+                self.aliases
+                    .insert(previous, peer_id, announcement.retires_at);
+            }
+            None => {
+                log::debug!(
+                    target: "network",
+                    "Ignoring host handover from {} of {}: invalid signature",
+                    peer_id, announcement.previous_peer_id
+                );
+            }
+        }
+    }
+
+    fn handler(&self) -> THandler<Self> {
+        OneShotHandler::new(
+            SubstreamProtocol::new(HandoverProtocol, ()).with_timeout(UPGRADE_TIMEOUT),
+            OneShotHandlerConfig::default(),
+        )
+    }
+}
+
+impl NetworkBehaviour for HostHandover {
+    type ConnectionHandler = OneShotHandler<HandoverProtocol, HandoverAnnouncement, HandlerEvent>;
+    type ToSwarm = ();
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        _peer: PeerId,
+        _local_addr: &Multiaddr,
+        _remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(self.handler())
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        _peer: PeerId,
+        _addr: &Multiaddr,
+        _role_override: Endpoint,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(self.handler())
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm<'_>) {
+        if let FromSwarm::ConnectionEstablished(ConnectionEstablished {
+            peer_id,
+            connection_id,
+            other_established: 0,
+            ..
+        }) = event
+        {
+            if let Some(announcement) = self.announcement.clone() {
+                self.events.push_back(ToSwarm::NotifyHandler {
+                    peer_id,
+                    handler: NotifyHandler::One(connection_id),
+                    event: announcement,
+                });
+                if let Some(waker) = self.waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        peer_id: PeerId,
+        _connection_id: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        match event {
+            Ok(HandlerEvent::Received(announcement)) => self.on_announcement(peer_id, announcement),
+            Ok(HandlerEvent::Sent) => {}
+            // peers that don't support the protocol refuse it
+            Err(err) => {
+                log::debug!(target: "network", "Host handover with {peer_id} failed: {err}");
+            }
+        }
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        match self.events.pop_front() {
+            Some(event) => Poll::Ready(event),
+            None => {
+                self.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Inbound side of the protocol, reads a single [HandoverAnnouncement]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HandoverProtocol;
+
+#[derive(Debug)]
+pub enum HandlerEvent {
+    Received(HandoverAnnouncement),
+    Sent,
+}
+
+impl From<HandoverAnnouncement> for HandlerEvent {
+    fn from(announcement: HandoverAnnouncement) -> Self {
+        HandlerEvent::Received(announcement)
+    }
+}
+
+impl From<()> for HandlerEvent {
+    fn from(_: ()) -> Self {
+        HandlerEvent::Sent
+    }
+}
+
+impl UpgradeInfo for HandoverProtocol {
+    type Info = &'static str;
+    type InfoIter = iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(HANDOVER_PROTOCOL)
+    }
+}
+
+impl UpgradeInfo for HandoverAnnouncement {
+    type Info = &'static str;
+    type InfoIter = iter::Once<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        iter::once(HANDOVER_PROTOCOL)
+    }
+}
+
+impl<Socket> InboundUpgrade<Socket> for HandoverProtocol
+where
+    Socket: AsyncRead + Send + Unpin + 'static,
+{
+    type Output = HandoverAnnouncement;
+    type Error = io::Error;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_inbound(self, socket: Socket, _: Self::Info) -> Self::Future {
+        async move {
+            let mut bytes = vec![];
+            socket
+                .take(MAX_MESSAGE_SIZE + 1)
+                .read_to_end(&mut bytes)
+                .await?;
+            if bytes.len() as u64 > MAX_MESSAGE_SIZE {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("host handover exceeds {MAX_MESSAGE_SIZE} bytes"),
+                ));
+            }
+            serde_json::from_slice(&bytes)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+        }
+        .boxed()
+    }
+}
+
+impl<Socket> OutboundUpgrade<Socket> for HandoverAnnouncement
+where
+    Socket: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    type Output = ();
+    type Error = io::Error;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_outbound(self, mut socket: Socket, _: Self::Info) -> Self::Future {
+        async move {
+            let bytes = serde_json::to_vec(&self)?;
+            socket.write_all(&bytes).await?;
+            // announcement ends with the stream, so it must be closed
+            socket.close().await
+        }
+        .boxed()
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_keypair::KeyPair;
+    use futures::io::Cursor;
+
+    use super::*;
+
+    fn announcement(previous: &KeyPair, peer_id: PeerId, retires_at: u64) -> HandoverAnnouncement {
+        let signature = previous.sign(&peer_id.to_bytes()).unwrap();
+        HandoverAnnouncement {
+            previous_peer_id: previous.get_peer_id().to_string(),
+            signature: bs58::encode(signature.to_vec()).into_string(),
+            retires_at,
+        }
+    }
+
+    #[test]
+    fn verify() {
+        let previous = KeyPair::generate_ed25519();
+        let current = KeyPair::generate_ed25519().get_peer_id();
+        let other = KeyPair::generate_ed25519().get_peer_id();
+
+        let announcement = announcement(&previous, current, 0);
+        assert_eq!(announcement.verify(&current), Some(previous.get_peer_id()));
+        // replayed by another peer
+        assert_eq!(announcement.verify(&other), None);
+    }
+
+    #[test]
+    fn learn_aliases() {
+        let previous = KeyPair::generate_ed25519();
+        let current = KeyPair::generate_ed25519().get_peer_id();
+        let aliases = PeerAliases::default();
+        let mut handover = HostHandover::new(None, aliases.clone());
+
+        let retired = announcement(&previous, current, now_secs() - 1);
+        handover.on_announcement(current, retired);
+        assert_eq!(
+            aliases.resolve(previous.get_peer_id()),
+            previous.get_peer_id()
+        );
+
+        let forged = announcement(&previous, current, now_secs() + 60);
+        let other = KeyPair::generate_ed25519().get_peer_id();
+        handover.on_announcement(other, forged.clone());
+        assert_eq!(
+            aliases.resolve(previous.get_peer_id()),
+            previous.get_peer_id()
+        );
+
+        handover.on_announcement(current, forged);
+        assert_eq!(aliases.resolve(previous.get_peer_id()), current);
+        assert_eq!(aliases.resolve(current), current);
+    }
+
+    #[tokio::test]
+    async fn roundtrip() {
+        let previous = KeyPair::generate_ed25519();
+        let announcement = announcement(&previous, previous.get_peer_id(), 1);
+
+        let socket = Cursor::new(serde_json::to_vec(&announcement).unwrap());
+        let received = HandoverProtocol
+            .upgrade_inbound(socket, HANDOVER_PROTOCOL)
+            .await
+            .unwrap();
+        assert_eq!(received, announcement);
+    }
+}
@@ -0,0 +1,301 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use libp2p::multiaddr::Protocol;
+use libp2p::swarm::handler::ConnectionEvent;
+use libp2p::swarm::{ConnectionHandler, ConnectionHandlerEvent, SubstreamProtocol};
+use libp2p::{Multiaddr, PeerId};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+
+/// How often idle connections are looked for
+pub(crate) const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Ping and idle connection settings, per class of the remote peer
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct KeepAliveConfig {
+    /// How often connected peers are pinged
+    #[serde(with = "humantime_serde")]
+    pub ping_interval: Duration,
+    /// Ping failure timeout, connection is closed after a failed ping
+    #[serde(with = "humantime_serde")]
+    pub ping_timeout: Duration,
+    pub bootstrap: PeerClassPolicy,
+    /// Other nodes, i.e. peers supporting Kademlia
+    pub nodes: PeerClassPolicy,
+    /// Peers that didn't identify themselves as nodes
+    pub clients: PeerClassPolicy,
+}
+
+impl Default for KeepAliveConfig {
+    fn default() -> Self {
+        Self {
+            ping_interval: Duration::from_secs(15),
+            ping_timeout: Duration::from_secs(20),
+            bootstrap: PeerClassPolicy {
+                keep_alive: true,
+                idle_timeout: Duration::from_secs(180),
+            },
+            nodes: PeerClassPolicy {
+                keep_alive: false,
+                idle_timeout: Duration::from_secs(180),
+            },
+            clients: PeerClassPolicy {
+                keep_alive: false,
+                idle_timeout: Duration::from_secs(60),
+            },
+        }
+    }
+}
+
+impl KeepAliveConfig {
+    fn policy(&self, class: PeerClass) -> &PeerClassPolicy {
+        match class {
+            PeerClass::Bootstrap => &self.bootstrap,
+            PeerClass::Node => &self.nodes,
+            PeerClass::Client => &self.clients,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PeerClassPolicy {
+    /// Keep connections open even when there are no particles
+    pub keep_alive: bool,
+    /// Otherwise close connections after that long without particles
+    #[serde(with = "humantime_serde")]
+    pub idle_timeout: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PeerClass {
+    Bootstrap,
+    Node,
+    Client,
+}
+
+#[derive(Debug)]
+struct PeerActivity {
+    class: PeerClass,
+    last_activity: Instant,
+}
+
+/// Tracks particle activity of connected peers. Shared between the connection pool,
+/// which closes idle connections, and connection handlers, which keep connections alive.
+#[derive(Clone)]
+pub struct KeepAlive {
+    config: Arc<KeepAliveConfig>,
+    bootstrap_peers: Arc<HashSet<PeerId>>,
+    bootstrap_addresses: Arc<HashSet<Multiaddr>>,
+    peers: Arc<RwLock<HashMap<PeerId, PeerActivity>>>,
+}
+
+impl KeepAlive {
+    pub fn new(config: KeepAliveConfig, bootstrap_nodes: &[Multiaddr]) -> Self {
+        let bootstrap_peers = bootstrap_nodes
+            .iter()
+            .filter_map(|addr| {
+                addr.iter().find_map(|p| match p {
+                    Protocol::P2p(peer_id) => Some(peer_id),
+                    _ => None,
+                })
+            })
+            .collect();
+
+        Self {
+            config: Arc::new(config),
+            bootstrap_peers: Arc::new(bootstrap_peers),
+            bootstrap_addresses: Arc::new(bootstrap_nodes.iter().cloned().collect()),
+            peers: <_>::default(),
+        }
+    }
+
+    pub fn on_connected(&self, peer_id: PeerId, addr: &Multiaddr) {
+        let bootstrap =
+            self.bootstrap_peers.contains(&peer_id) || self.bootstrap_addresses.contains(addr);
+        let mut peers = self.peers.write();
+        let activity = peers.entry(peer_id).or_insert(PeerActivity {
+            class: PeerClass::Client,
+            last_activity: Instant::now(),
+        });
+        activity.last_activity = Instant::now();
+        if bootstrap {
+            activity.class = PeerClass::Bootstrap;
+        }
+    }
+
+    pub fn on_disconnected(&self, peer_id: &PeerId) {
+        self.peers.write().remove(peer_id);
+    }
+
+    pub fn on_activity(&self, peer_id: &PeerId) {
+        if let Some(activity) = self.peers.write().get_mut(peer_id) {
+            activity.last_activity = Instant::now();
+        }
+    }
+
+    /// Bootstrap class is assigned on connection and never changes
+    pub fn set_class(&self, peer_id: &PeerId, class: PeerClass) {
+        if let Some(activity) = self.peers.write().get_mut(peer_id) {
+            if activity.class != PeerClass::Bootstrap {
+                activity.class = class;
+            }
+        }
+    }
+
+    pub fn class(&self, peer_id: &PeerId) -> Option<PeerClass> {
+        self.peers
+            .read()
+            .get(peer_id)
+            .map(|activity| activity.class)
+    }
+
+    fn is_idle(&self, activity: &PeerActivity, now: Instant) -> bool {
+        let policy = self.config.policy(activity.class);
+        !policy.keep_alive && now >= activity.last_activity + policy.idle_timeout
+    }
+
+    pub fn keep_alive(&self, peer_id: &PeerId) -> bool {
+        self.peers
+            .read()
+            .get(peer_id)
+            .is_some_and(|activity| !self.is_idle(activity, Instant::now()))
+    }
+
+    pub fn idle_peers(&self) -> Vec<(PeerId, PeerClass)> {
+        let now = Instant::now();
+        self.peers
+            .read()
+            .iter()
+            .filter(|(_, activity)| self.is_idle(activity, now))
+            .map(|(peer_id, activity)| (*peer_id, activity.class))
+            .collect()
+    }
+}
+
+/// Keeps connection alive while the peer isn't idle according to [KeepAliveConfig],
+/// otherwise while `inner` wants it
+pub struct KeepAliveHandler<H> {
+    inner: H,
+    peer_id: PeerId,
+    keep_alive: KeepAlive,
+}
+
+impl<H> KeepAliveHandler<H> {
+    pub fn new(inner: H, peer_id: PeerId, keep_alive: KeepAlive) -> Self {
+        Self {
+            inner,
+            peer_id,
+            keep_alive,
+        }
+    }
+}
+
+impl<H: ConnectionHandler> ConnectionHandler for KeepAliveHandler<H> {
+    type FromBehaviour = H::FromBehaviour;
+    type ToBehaviour = H::ToBehaviour;
+    type InboundProtocol = H::InboundProtocol;
+    type OutboundProtocol = H::OutboundProtocol;
+    type InboundOpenInfo = H::InboundOpenInfo;
+    type OutboundOpenInfo = H::OutboundOpenInfo;
+
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, Self::InboundOpenInfo> {
+        self.inner.listen_protocol()
+    }
+
+    fn connection_keep_alive(&self) -> bool {
+        self.inner.connection_keep_alive() || self.keep_alive.keep_alive(&self.peer_id)
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<
+        ConnectionHandlerEvent<Self::OutboundProtocol, Self::OutboundOpenInfo, Self::ToBehaviour>,
+    > {
+        self.inner.poll(cx)
+    }
+
+    fn poll_close(&mut self, cx: &mut Context<'_>) -> Poll<Option<Self::ToBehaviour>> {
+        self.inner.poll_close(cx)
+    }
+
+    fn on_behaviour_event(&mut self, event: Self::FromBehaviour) {
+        self.inner.on_behaviour_event(event)
+    }
+
+    fn on_connection_event(
+        &mut self,
+        event: ConnectionEvent<
+            '_,
+            Self::InboundProtocol,
+            Self::OutboundProtocol,
+            Self::InboundOpenInfo,
+            Self::OutboundOpenInfo,
+        >,
+    ) {
+        self.inner.on_connection_event(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idle_peers_by_class() {
+        let bootstrap = PeerId::random();
+        let node = PeerId::random();
+        let client = PeerId::random();
+        let bootstrap_addr: Multiaddr = format!("/ip4/1.2.3.4/tcp/7777/p2p/{bootstrap}")
+            .parse()
+            .unwrap();
+        let addr: Multiaddr = "/ip4/1.2.3.5/tcp/7777".parse().unwrap();
+
+        let config = KeepAliveConfig {
+            nodes: PeerClassPolicy {
+                keep_alive: false,
+                idle_timeout: Duration::from_secs(60),
+            },
+            clients: PeerClassPolicy {
+                keep_alive: false,
+                idle_timeout: Duration::ZERO,
+            },
+            ..<_>::default()
+        };
+        let keep_alive = KeepAlive::new(config, &[bootstrap_addr]);
+        keep_alive.on_connected(bootstrap, &addr);
+        keep_alive.on_connected(node, &addr);
+        keep_alive.on_connected(client, &addr);
+        keep_alive.set_class(&node, PeerClass::Node);
+        keep_alive.set_class(&bootstrap, PeerClass::Node);
+
+        assert_eq!(keep_alive.idle_peers(), vec![(client, PeerClass::Client)]);
+        assert!(keep_alive.keep_alive(&bootstrap));
+        assert!(keep_alive.keep_alive(&node));
+        assert!(!keep_alive.keep_alive(&client));
+
+        keep_alive.on_disconnected(&client);
+        assert!(keep_alive.idle_peers().is_empty());
+    }
+}
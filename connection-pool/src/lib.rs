@@ -14,11 +14,36 @@
 pub use api::ConnectionPoolApi;
 // to be available in benchmarks
 pub use api::Command;
+pub use backoff::{BackoffScope, DialBackoff, DialBackoffConfig};
+pub use bandwidth::{BandwidthConfig, BandwidthLimiter};
 pub use behaviour::ConnectionPoolBehaviour;
+pub use connection_limits::{ConnectionLimits, ConnectionLimitsBehaviour, Exceeded};
+pub use drain::DrainConfig;
+pub use endpoint_peers::{AllowedPeers, EndpointPeers};
+pub use handover::{HandoverAnnouncement, HostHandover, HANDOVER_PROTOCOL};
+pub use keep_alive::{KeepAlive, KeepAliveConfig, PeerClass, PeerClassPolicy};
+pub use maintenance::{MaintenanceAnnouncement, MaintenanceStatus, MAINTENANCE_PROTOCOL};
+pub use peer_filter::{
+    PeerFilter, PeerFilterConfig, PeerFilterError, PeerFilterUpdate, PeerList, TrustCheck,
+};
+pub use rate_limit::{RateLimitConfig, RateLimiter};
+pub use signatures::{ParticleSignatureConfig, SignatureEnforcement};
 
+pub use crate::connection_pool::ConnectedPeer;
 pub use crate::connection_pool::ConnectionPoolT;
 pub use crate::connection_pool::LifecycleEvent;
 
 mod api;
+mod backoff;
+mod bandwidth;
 mod behaviour;
+mod connection_limits;
 mod connection_pool;
+mod drain;
+mod endpoint_peers;
+mod handover;
+mod keep_alive;
+mod maintenance;
+mod peer_filter;
+mod rate_limit;
+mod signatures;
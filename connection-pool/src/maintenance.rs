@@ -0,0 +1,201 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures::future::{ready, Ready};
+use libp2p::core::upgrade::DeniedUpgrade;
+use libp2p::core::{Endpoint, InboundUpgrade, Multiaddr, UpgradeInfo};
+use libp2p::swarm::handler::ConnectionEvent;
+use libp2p::swarm::{
+    ConnectionDenied, ConnectionHandler, ConnectionHandlerEvent, ConnectionId, FromSwarm,
+    NetworkBehaviour, Stream, SubstreamProtocol, THandler, THandlerInEvent, THandlerOutEvent,
+    ToSwarm,
+};
+use libp2p::PeerId;
+use serde::Serialize;
+
+/// Advertised via Identify while the node is in maintenance, so other nodes don't suggest it
+/// as a relay. Streams of this protocol carry nothing and are closed right away.
+pub const MAINTENANCE_PROTOCOL: &str = "/fluence/maintenance/1.0.0";
+
+/// Progress of the maintenance mode, see [ConnectionPoolT::set_maintenance](crate::ConnectionPoolT::set_maintenance)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MaintenanceStatus {
+    pub enabled: bool,
+    /// Clients that were connected when maintenance started and haven't disconnected yet
+    pub remaining_clients: usize,
+    /// Clients disconnected because they connected during maintenance
+    pub rejected_clients: usize,
+    /// Seconds since maintenance started
+    pub elapsed_secs: u64,
+}
+
+/// Announces the maintenance mode via Identify by adding [MAINTENANCE_PROTOCOL]
+/// to the protocols the node supports. Identify pushes the change to connected peers.
+pub struct MaintenanceAnnouncement {
+    enabled: Arc<AtomicBool>,
+}
+
+impl MaintenanceAnnouncement {
+    pub(crate) fn new(enabled: Arc<AtomicBool>) -> Self {
+        Self { enabled }
+    }
+
+    fn handler(&self) -> AnnouncementHandler {
+        AnnouncementHandler {
+            enabled: self.enabled.clone(),
+        }
+    }
+}
+
+impl NetworkBehaviour for MaintenanceAnnouncement {
+    type ConnectionHandler = AnnouncementHandler;
+    type ToSwarm = Infallible;
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        _peer: PeerId,
+        _local_addr: &Multiaddr,
+        _remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(self.handler())
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        _peer: PeerId,
+        _addr: &Multiaddr,
+        _role_override: Endpoint,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(self.handler())
+    }
+
+    fn on_swarm_event(&mut self, _event: FromSwarm<'_>) {}
+
+    fn on_connection_handler_event(
+        &mut self,
+        _peer_id: PeerId,
+        _connection_id: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        match event {}
+    }
+
+    fn poll(
+        &mut self,
+        _cx: &mut Context<'_>,
+    ) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        Poll::Pending
+    }
+}
+
+/// Listens on [MAINTENANCE_PROTOCOL] only while the node is in maintenance.
+/// The swarm checks listen protocols of handlers on every poll and notifies Identify of changes.
+pub struct AnnouncementHandler {
+    enabled: Arc<AtomicBool>,
+}
+
+impl ConnectionHandler for AnnouncementHandler {
+    type FromBehaviour = Infallible;
+    type ToBehaviour = Infallible;
+    type InboundProtocol = AnnouncementUpgrade;
+    type OutboundProtocol = DeniedUpgrade;
+    type InboundOpenInfo = ();
+    type OutboundOpenInfo = Infallible;
+
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, Self::InboundOpenInfo> {
+        let enabled = self.enabled.load(Ordering::Relaxed);
+        SubstreamProtocol::new(AnnouncementUpgrade { enabled }, ())
+    }
+
+    fn poll(
+        &mut self,
+        _cx: &mut Context<'_>,
+    ) -> Poll<
+        ConnectionHandlerEvent<Self::OutboundProtocol, Self::OutboundOpenInfo, Self::ToBehaviour>,
+    > {
+        Poll::Pending
+    }
+
+    fn on_behaviour_event(&mut self, event: Self::FromBehaviour) {
+        match event {}
+    }
+
+    fn on_connection_event(
+        &mut self,
+        _event: ConnectionEvent<
+            '_,
+            Self::InboundProtocol,
+            Self::OutboundProtocol,
+            Self::InboundOpenInfo,
+            Self::OutboundOpenInfo,
+        >,
+    ) {
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AnnouncementUpgrade {
+    enabled: bool,
+}
+
+impl UpgradeInfo for AnnouncementUpgrade {
+    type Info = &'static str;
+    type InfoIter = Option<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        self.enabled.then_some(MAINTENANCE_PROTOCOL)
+    }
+}
+
+impl InboundUpgrade<Stream> for AnnouncementUpgrade {
+    type Output = ();
+    type Error = Infallible;
+    type Future = Ready<Result<(), Infallible>>;
+
+    fn upgrade_inbound(self, _stream: Stream, _info: Self::Info) -> Self::Future {
+        ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protocols_follow_flag() {
+        let enabled = Arc::new(AtomicBool::new(false));
+        let handler = MaintenanceAnnouncement::new(enabled.clone()).handler();
+        let protocols = || {
+            handler
+                .listen_protocol()
+                .upgrade()
+                .protocol_info()
+                .into_iter()
+                .collect::<Vec<_>>()
+        };
+
+        assert!(protocols().is_empty());
+        enabled.store(true, Ordering::Relaxed);
+        assert_eq!(protocols(), vec![MAINTENANCE_PROTOCOL]);
+    }
+}
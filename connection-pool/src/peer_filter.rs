@@ -0,0 +1,338 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashSet;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+
+/// Peers allowed or denied to connect and send particles.
+/// Entries added at runtime are persisted in the same format.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct PeerFilterConfig {
+    /// If not empty, only these peers can connect and send particles
+    #[serde(with = "peer_ids")]
+    pub allowlist: HashSet<PeerId>,
+    /// These peers can't connect or send particles
+    #[serde(with = "peer_ids")]
+    pub denylist: HashSet<PeerId>,
+}
+
+impl PeerFilterConfig {
+    fn list(&self, list: PeerList) -> &HashSet<PeerId> {
+        match list {
+            PeerList::Allowlist => &self.allowlist,
+            PeerList::Denylist => &self.denylist,
+        }
+    }
+
+    fn list_mut(&mut self, list: PeerList) -> &mut HashSet<PeerId> {
+        match list {
+            PeerList::Allowlist => &mut self.allowlist,
+            PeerList::Denylist => &mut self.denylist,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PeerList {
+    Allowlist,
+    Denylist,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum PeerFilterUpdate {
+    Add { list: PeerList, peer_id: PeerId },
+    Remove { list: PeerList, peer_id: PeerId },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PeerFilterError {
+    #[error("{peer_id} is in the {list:?} of the node config, it can't be removed at runtime")]
+    Configured { list: PeerList, peer_id: PeerId },
+    #[error("failed to persist peer filter: {0}")]
+    Persist(#[from] io::Error),
+    #[error("connection pool is stopped")]
+    Stopped,
+}
+
+/// Decides whether a peer outside of the allowlist is trusted enough to be allowed,
+/// e.g. by its trust graph weight. Called for every connection and particle, so it should be cheap.
+#[derive(Clone)]
+pub struct TrustCheck(Arc<dyn Fn(&PeerId) -> bool + Send + Sync>);
+
+impl TrustCheck {
+    pub fn new(check: impl Fn(&PeerId) -> bool + Send + Sync + 'static) -> Self {
+        Self(Arc::new(check))
+    }
+
+    fn is_trusted(&self, peer_id: &PeerId) -> bool {
+        (self.0)(peer_id)
+    }
+}
+
+impl fmt::Debug for TrustCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TrustCheck")
+    }
+}
+
+pub struct PeerFilter {
+    /// Entries from the node config, immutable at runtime
+    config: PeerFilterConfig,
+    /// Entries managed at runtime
+    runtime: PeerFilterConfig,
+    /// Where runtime entries are persisted, `None` to keep them in memory only
+    path: Option<PathBuf>,
+    /// Peers that are never filtered out, i.e. the management peer
+    always_allowed: HashSet<PeerId>,
+    /// Consulted for peers outside of the allowlist when the allowlist is enabled
+    trust_check: Option<TrustCheck>,
+}
+
+impl PeerFilter {
+    pub fn new(config: PeerFilterConfig, always_allowed: impl IntoIterator<Item = PeerId>) -> Self {
+        Self {
+            config,
+            runtime: <_>::default(),
+            path: None,
+            always_allowed: always_allowed.into_iter().collect(),
+            trust_check: None,
+        }
+    }
+
+    /// Creates filter with runtime entries persisted in `path`, loading them from there if it exists
+    pub fn load(
+        config: PeerFilterConfig,
+        path: PathBuf,
+        always_allowed: impl IntoIterator<Item = PeerId>,
+    ) -> io::Result<Self> {
+        let runtime = match std::fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => PeerFilterConfig::default(),
+            Err(err) => return Err(err),
+        };
+
+        let mut this = Self::new(config, always_allowed);
+        this.runtime = runtime;
+        this.path = Some(path);
+        Ok(this)
+    }
+
+    pub fn is_allowed(&self, peer_id: &PeerId) -> bool {
+        if self.always_allowed.contains(peer_id) {
+            return true;
+        }
+
+        let in_list = |list| {
+            self.config.list(list).contains(peer_id) || self.runtime.list(list).contains(peer_id)
+        };
+        if in_list(PeerList::Denylist) {
+            return false;
+        }
+
+        let allowlist_enabled =
+            !self.config.allowlist.is_empty() || !self.runtime.allowlist.is_empty();
+        !allowlist_enabled
+            || in_list(PeerList::Allowlist)
+            || self
+                .trust_check
+                .as_ref()
+                .is_some_and(|check| check.is_trusted(peer_id))
+    }
+
+    pub fn set_trust_check(&mut self, trust_check: Option<TrustCheck>) {
+        self.trust_check = trust_check;
+    }
+
+    /// Both config and runtime entries
+    pub fn entries(&self) -> PeerFilterConfig {
+        let merge = |list| {
+            self.config
+                .list(list)
+                .union(self.runtime.list(list))
+                .copied()
+                .collect()
+        };
+        PeerFilterConfig {
+            allowlist: merge(PeerList::Allowlist),
+            denylist: merge(PeerList::Denylist),
+        }
+    }
+
+    /// Applies `update` and persists runtime entries. Returns whether anything has changed.
+    pub fn update(&mut self, update: PeerFilterUpdate) -> Result<bool, PeerFilterError> {
+        let changed = match update {
+            PeerFilterUpdate::Add { list, peer_id } => self.runtime.list_mut(list).insert(peer_id),
+            PeerFilterUpdate::Remove { list, peer_id } => {
+                if self.config.list(list).contains(&peer_id) {
+                    return Err(PeerFilterError::Configured { list, peer_id });
+                }
+                self.runtime.list_mut(list).remove(&peer_id)
+            }
+        };
+
+        if changed {
+            self.persist()?;
+        }
+        Ok(changed)
+    }
+
+    fn persist(&self) -> io::Result<()> {
+        if let Some(path) = &self.path {
+            let contents = toml::to_string(&self.runtime)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            std::fs::write(path, contents)?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) mod peer_ids {
+    use std::collections::HashSet;
+    use std::str::FromStr;
+
+    use libp2p::PeerId;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S>(value: &HashSet<PeerId>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut peer_ids: Vec<_> = value.iter().map(|p| p.to_base58()).collect();
+        peer_ids.sort();
+        peer_ids.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<HashSet<PeerId>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let peer_ids: Vec<String> = Vec::deserialize(deserializer)?;
+        peer_ids
+            .iter()
+            .map(|p| {
+                PeerId::from_str(p).map_err(|e| {
+                    serde::de::Error::custom(format!("peer id deserialization failed for {e:?}"))
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_libp2p::RandomPeerId;
+
+    use super::*;
+
+    fn config(allowlist: &[PeerId], denylist: &[PeerId]) -> PeerFilterConfig {
+        PeerFilterConfig {
+            allowlist: allowlist.iter().copied().collect(),
+            denylist: denylist.iter().copied().collect(),
+        }
+    }
+
+    #[test]
+    fn denylist() {
+        let denied = RandomPeerId::random();
+        let filter = PeerFilter::new(config(&[], &[denied]), []);
+
+        assert!(!filter.is_allowed(&denied));
+        assert!(filter.is_allowed(&RandomPeerId::random()));
+    }
+
+    #[test]
+    fn allowlist() {
+        let allowed = RandomPeerId::random();
+        let manager = RandomPeerId::random();
+        let filter = PeerFilter::new(config(&[allowed], &[]), [manager]);
+
+        assert!(filter.is_allowed(&allowed));
+        assert!(filter.is_allowed(&manager));
+        assert!(!filter.is_allowed(&RandomPeerId::random()));
+    }
+
+    #[test]
+    fn trust_check() {
+        let allowed = RandomPeerId::random();
+        let trusted = RandomPeerId::random();
+        let mut filter = PeerFilter::new(config(&[allowed], &[]), []);
+        filter.set_trust_check(Some(TrustCheck::new(move |peer_id| *peer_id == trusted)));
+
+        assert!(filter.is_allowed(&allowed));
+        assert!(filter.is_allowed(&trusted));
+        assert!(!filter.is_allowed(&RandomPeerId::random()));
+
+        // denylist takes precedence over trust
+        let mut filter = PeerFilter::new(config(&[allowed], &[trusted]), []);
+        filter.set_trust_check(Some(TrustCheck::new(|_| true)));
+        assert!(!filter.is_allowed(&trusted));
+    }
+
+    #[test]
+    fn configured_entries_are_not_removable() {
+        let denied = RandomPeerId::random();
+        let mut filter = PeerFilter::new(config(&[], &[denied]), []);
+
+        let result = filter.update(PeerFilterUpdate::Remove {
+            list: PeerList::Denylist,
+            peer_id: denied,
+        });
+        assert!(matches!(result, Err(PeerFilterError::Configured { .. })));
+        assert!(!filter.is_allowed(&denied));
+    }
+
+    #[test]
+    fn persist_runtime_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("peer_filter.toml");
+        let configured = RandomPeerId::random();
+        let denied = RandomPeerId::random();
+
+        let mut filter = PeerFilter::load(config(&[], &[configured]), path.clone(), []).unwrap();
+        let changed = filter
+            .update(PeerFilterUpdate::Add {
+                list: PeerList::Denylist,
+                peer_id: denied,
+            })
+            .unwrap();
+        assert!(changed);
+
+        let filter = PeerFilter::load(<_>::default(), path.clone(), []).unwrap();
+        assert!(!filter.is_allowed(&denied));
+        // config entries aren't persisted
+        assert!(filter.is_allowed(&configured));
+
+        let mut filter = PeerFilter::load(<_>::default(), path.clone(), []).unwrap();
+        filter
+            .update(PeerFilterUpdate::Remove {
+                list: PeerList::Denylist,
+                peer_id: denied,
+            })
+            .unwrap();
+        let filter = PeerFilter::load(<_>::default(), path, []).unwrap();
+        assert!(filter.is_allowed(&denied));
+        assert_eq!(filter.entries(), PeerFilterConfig::default());
+    }
+}
@@ -0,0 +1,225 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use bytesize::ByteSize;
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+
+/// Number of per-peer limits after which idle ones are dropped
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// Ingress limits for each peer particles are received from. Particles above the limits
+/// are refused with an error particle to their origin, unlike [`crate::BandwidthConfig`] that delays them.
+/// Unset limits are not enforced.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub particles_per_sec: Option<u32>,
+    pub bytes_per_sec: Option<ByteSize>,
+}
+
+impl RateLimitConfig {
+    fn is_unlimited(&self) -> bool {
+        self.particles_per_sec.is_none() && self.bytes_per_sec.is_none()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.particles_per_sec == Some(0) {
+            return Err("rate_limit.particles_per_sec must be positive".to_string());
+        }
+        if self.bytes_per_sec.is_some_and(|b| b.as_u64() == 0) {
+            return Err("rate_limit.bytes_per_sec must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Token bucket that allows bursts of up to one second worth of traffic.
+/// Amount larger than the burst is accepted when the bucket is full, then the bucket
+/// goes into debt and nothing is accepted until it's paid off.
+#[derive(Debug)]
+struct Bucket {
+    rate: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(rate: u64, now: Instant) -> Self {
+        let rate = rate.max(1) as f64;
+        Self {
+            rate,
+            tokens: rate,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.updated = now;
+    }
+
+    /// How long to wait until `amount` is accepted, zero if it's accepted right away
+    fn wait_time(&mut self, amount: f64, now: Instant) -> Duration {
+        self.refill(now);
+        let required = amount.min(self.rate);
+        if self.tokens >= required {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((required - self.tokens) / self.rate)
+        }
+    }
+
+    fn is_full(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= self.rate
+    }
+}
+
+#[derive(Debug)]
+struct PeerBuckets {
+    particles: Option<Bucket>,
+    bytes: Option<Bucket>,
+}
+
+impl PeerBuckets {
+    fn is_full(&mut self, now: Instant) -> bool {
+        self.particles.as_mut().map_or(true, |b| b.is_full(now))
+            && self.bytes.as_mut().map_or(true, |b| b.is_full(now))
+    }
+}
+
+/// Enforces [`RateLimitConfig`] on particles received from each peer
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    peers: HashMap<PeerId, PeerBuckets>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            peers: <_>::default(),
+        }
+    }
+
+    /// Replaces the limits, peers start over with full buckets
+    pub fn set_config(&mut self, config: RateLimitConfig) {
+        self.config = config;
+        self.peers.clear();
+    }
+
+    /// Accounts a particle of `bytes` received from `from`. If the particle exceeds the limits,
+    /// it's not accounted and the time after which it would be accepted is returned as an error.
+    pub fn check(&mut self, from: PeerId, bytes: usize) -> Result<(), Duration> {
+        self.check_at(from, bytes, Instant::now())
+    }
+
+    fn check_at(&mut self, from: PeerId, bytes: usize, now: Instant) -> Result<(), Duration> {
+        if self.config.is_unlimited() {
+            return Ok(());
+        }
+
+        if self.peers.len() >= PRUNE_THRESHOLD {
+            // full buckets are indistinguishable from new ones, so they can be dropped
+            self.peers.retain(|_, buckets| !buckets.is_full(now));
+        }
+
+        let config = &self.config;
+        let buckets = self.peers.entry(from).or_insert_with(|| PeerBuckets {
+            particles: config
+                .particles_per_sec
+                .map(|rate| Bucket::new(rate as u64, now)),
+            bytes: config
+                .bytes_per_sec
+                .map(|rate| Bucket::new(rate.as_u64(), now)),
+        });
+
+        let amounts = [
+            (buckets.particles.as_mut(), 1.0),
+            (buckets.bytes.as_mut(), bytes as f64),
+        ];
+        let mut accepted = vec![];
+        let mut wait = Duration::ZERO;
+        for (bucket, amount) in amounts {
+            if let Some(bucket) = bucket {
+                wait = wait.max(bucket.wait_time(amount, now));
+                accepted.push((bucket, amount));
+            }
+        }
+        if !wait.is_zero() {
+            return Err(wait);
+        }
+
+        for (bucket, amount) in accepted {
+            bucket.tokens -= amount;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuse_above_particle_rate() {
+        let mut limiter = RateLimiter::new(RateLimitConfig {
+            particles_per_sec: Some(2),
+            bytes_per_sec: None,
+        });
+        let now = Instant::now();
+        let peer = PeerId::random();
+
+        assert_eq!(limiter.check_at(peer, 100, now), Ok(()));
+        assert_eq!(limiter.check_at(peer, 100, now), Ok(()));
+        assert_eq!(
+            limiter.check_at(peer, 100, now),
+            Err(Duration::from_millis(500))
+        );
+        // other peers have their own limits
+        assert_eq!(limiter.check_at(PeerId::random(), 100, now), Ok(()));
+
+        let later = now + Duration::from_millis(500);
+        assert_eq!(limiter.check_at(peer, 100, later), Ok(()));
+    }
+
+    #[test]
+    fn large_particle_goes_into_debt() {
+        let mut limiter = RateLimiter::new(RateLimitConfig {
+            particles_per_sec: Some(100),
+            bytes_per_sec: Some(ByteSize::b(1000)),
+        });
+        let now = Instant::now();
+        let peer = PeerId::random();
+
+        // larger than the burst, but the bucket is full
+        assert_eq!(limiter.check_at(peer, 3000, now), Ok(()));
+        // debt of 2000 bytes has to be paid off first
+        assert_eq!(
+            limiter.check_at(peer, 1, now),
+            Err(Duration::from_secs_f64(2.001))
+        );
+        // refused particles aren't accounted
+        let later = now + Duration::from_secs(3);
+        assert_eq!(limiter.check_at(peer, 1000, later), Ok(()));
+    }
+}
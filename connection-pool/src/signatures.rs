@@ -0,0 +1,135 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashSet;
+
+use libp2p::PeerId;
+use particle_protocol::{Particle, Rejection};
+use peer_metrics::SignatureRejection;
+use serde::{Deserialize, Serialize};
+
+use crate::peer_filter::peer_ids;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureEnforcement {
+    /// Particles are forwarded unverified, signatures are checked only before execution
+    #[default]
+    Permissive,
+    /// Particles with missing or invalid signatures are refused when received from the network
+    Strict,
+}
+
+/// How particle signatures are checked at ingress
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct ParticleSignatureConfig {
+    pub enforcement: SignatureEnforcement,
+    /// Particles sent or initiated by these peers aren't verified at ingress, i.e. legacy clients
+    #[serde(with = "peer_ids")]
+    pub exempt_peers: HashSet<PeerId>,
+}
+
+impl ParticleSignatureConfig {
+    /// Whether signature of `particle` received from `from` has to be verified at ingress
+    pub fn is_enforced(&self, from: &PeerId, particle: &Particle) -> bool {
+        self.enforcement == SignatureEnforcement::Strict
+            && !self.exempt_peers.contains(from)
+            && !self.exempt_peers.contains(&particle.init_peer_id)
+    }
+}
+
+/// Verifies signature of `particle`, telling missing signatures from invalid ones
+pub(crate) fn verify_signature(particle: &Particle) -> Result<(), (SignatureRejection, Rejection)> {
+    if particle.signature.is_empty() {
+        return Err((SignatureRejection::Missing, Rejection::MissingSignature));
+    }
+
+    particle.verify().map_err(|err| {
+        let rejection = Rejection::InvalidSignature {
+            error: err.to_string(),
+        };
+        (SignatureRejection::Invalid, rejection)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_keypair::KeyPair;
+    use fluence_libp2p::RandomPeerId;
+
+    use super::*;
+
+    fn signed_particle(keypair: &KeyPair) -> Particle {
+        let mut particle = Particle {
+            id: "particle".to_string(),
+            init_peer_id: keypair.get_peer_id(),
+            timestamp: 1,
+            ttl: 1000,
+            script: "(null)".to_string(),
+            ..<_>::default()
+        };
+        particle.sign(keypair).unwrap();
+        particle
+    }
+
+    #[test]
+    fn verify() {
+        let keypair = KeyPair::generate_ed25519();
+        let particle = signed_particle(&keypair);
+        assert!(verify_signature(&particle).is_ok());
+
+        let unsigned = Particle {
+            signature: vec![],
+            ..particle.clone()
+        };
+        assert!(matches!(
+            verify_signature(&unsigned),
+            Err((SignatureRejection::Missing, Rejection::MissingSignature))
+        ));
+
+        let tampered = Particle {
+            script: "(seq (null) (null))".to_string(),
+            ..particle
+        };
+        assert!(matches!(
+            verify_signature(&tampered),
+            Err((
+                SignatureRejection::Invalid,
+                Rejection::InvalidSignature { .. }
+            ))
+        ));
+    }
+
+    #[test]
+    fn exempt_peers() {
+        let keypair = KeyPair::generate_ed25519();
+        let particle = signed_particle(&keypair);
+        let legacy = RandomPeerId::random();
+        let sender = RandomPeerId::random();
+
+        let permissive = ParticleSignatureConfig::default();
+        assert!(!permissive.is_enforced(&sender, &particle));
+
+        let strict = ParticleSignatureConfig {
+            enforcement: SignatureEnforcement::Strict,
+            exempt_peers: [legacy, keypair.get_peer_id()].into(),
+        };
+        assert!(!strict.is_enforced(&sender, &particle));
+        assert!(!strict.is_enforced(&legacy, &Particle::default()));
+        assert!(strict.is_enforced(&sender, &Particle::default()));
+    }
+}
@@ -0,0 +1,574 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Append-only log of significant node events: services and spells created or removed,
+//! workers changing their state, peers banned, config reloaded, node keys used.
+//!
+//! Each event is appended as a JSON line to the log file and emitted as a structured
+//! tracing event with the `audit` target, so it also ends up in the exported logs.
+//!
+//! Recorded events are also broadcast to live subscribers along with frequent events
+//! that aren't worth persisting, e.g. peer connections and spell executions.
+//!
+//! Recorded events are chained: each one carries a hash of itself and of the previous event,
+//! so editing or removing an event in the middle of the log is detected by [AuditLog::verify].
+
+#![warn(rust_2018_idioms)]
+#![deny(
+    dead_code,
+    nonstandard_style,
+    unused_imports,
+    unused_mut,
+    unused_variables,
+    unused_unsafe,
+    unreachable_patterns
+)]
+
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+/// Number of events kept by the in-memory log
+const MEMORY_CAPACITY: usize = 1024;
+/// Number of events a slow subscriber may lag behind before it starts missing them
+const SUBSCRIPTION_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEventKind {
+    ServiceCreated,
+    ServiceRemoved,
+    SpellInstalled,
+    SpellRemoved,
+    WorkerCreated,
+    WorkerRemoved,
+    WorkerActivated,
+    WorkerDeactivated,
+    PeerBanned,
+    PeerUnbanned,
+    ConfigReloaded,
+    KeyUsed,
+    ApiTokenIssued,
+    ApiTokenRevoked,
+    MaintenanceStarted,
+    MaintenanceFinished,
+    HostKeyRotationStarted,
+    HostKeyRetired,
+    SystemServiceEnabled,
+    SystemServiceDisabled,
+    /// Update of a system service by `system.update`, the details say whether it's rolled back
+    SystemServiceUpdated,
+    /// Redeployment of a failed system service by the supervisor
+    SystemServiceRestarted,
+    BuiltinCallDenied,
+    BuiltinPolicyChanged,
+    ServiceAclChanged,
+    /// Call authorized by the management key or an admin token
+    ManagementOperation,
+    ManagementKeySet,
+    ManagementKeyRevoked,
+    /// Published only, not persisted
+    PeerConnected,
+    /// Published only, not persisted
+    PeerDisconnected,
+    /// Published only, not persisted
+    SpellExecuted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AuditEvent {
+    /// UNIX timestamp in milliseconds
+    pub timestamp: u64,
+    pub kind: AuditEventKind,
+    /// Peer that caused the event, usually the init peer id of the particle
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actor: Option<String>,
+    /// What the event is about: service id, worker id, peer id, etc
+    pub subject: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<String>,
+    /// Hash of the event chained with the previous recorded one, set when the event is recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+impl AuditEvent {
+    pub fn new(kind: AuditEventKind, subject: impl ToString) -> Self {
+        Self {
+            timestamp: now_millis::now_ms() as u64,
+            kind,
+            actor: None,
+            subject: subject.to_string(),
+            details: None,
+            hash: None,
+        }
+    }
+
+    pub fn with_actor(self, actor: impl ToString) -> Self {
+        Self {
+            actor: Some(actor.to_string()),
+            ..self
+        }
+    }
+
+    pub fn with_details(self, details: impl ToString) -> Self {
+        Self {
+            details: Some(details.to_string()),
+            ..self
+        }
+    }
+
+    /// Hash of the event without its own hash, chained with `previous` hash
+    fn chained_hash(&self, previous: &str) -> String {
+        let event = Self {
+            hash: None,
+            ..self.clone()
+        };
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(previous.as_bytes());
+        // serialization of a struct without maps is deterministic
+        hasher.update(&serde_json::to_vec(&event).unwrap_or_default());
+        hasher.finalize().to_hex().to_string()
+    }
+}
+
+/// Hash of call arguments, so they can be matched without being stored in the log.
+/// Object keys are sorted, so the hash doesn't depend on the order of map entries.
+pub fn arguments_hash(args: &impl Serialize) -> String {
+    let value = sort_keys(serde_json::to_value(args).unwrap_or_default());
+    let bytes = serde_json::to_vec(&value).unwrap_or_default();
+    blake3::hash(&bytes).to_hex().to_string()
+}
+
+fn sort_keys(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut entries: Vec<_> = map.into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            entries
+                .into_iter()
+                .map(|(key, value)| (key, sort_keys(value)))
+                .collect()
+        }
+        serde_json::Value::Array(values) => values.into_iter().map(sort_keys).collect(),
+        value => value,
+    }
+}
+
+/// Result of [AuditLog::verify]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChainStatus {
+    /// Number of verified events
+    pub events: usize,
+    /// Index of the first event which hash doesn't match, `None` if the chain is intact
+    pub broken_at: Option<usize>,
+}
+
+/// Which events to return from [AuditLog::query]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AuditQuery {
+    /// Only events recorded at or after this UNIX timestamp in milliseconds
+    pub since: Option<u64>,
+    /// Only events of these kinds, all kinds if empty
+    pub kinds: Vec<AuditEventKind>,
+    /// Only events about this subject
+    pub subject: Option<String>,
+    /// Only events caused by this actor
+    pub actor: Option<String>,
+    /// Max number of the most recent events to return
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    pub fn matches(&self, event: &AuditEvent) -> bool {
+        self.since.map_or(true, |since| event.timestamp >= since)
+            && (self.kinds.is_empty() || self.kinds.contains(&event.kind))
+            && self.subject.as_ref().map_or(true, |s| *s == event.subject)
+            && self
+                .actor
+                .as_ref()
+                .map_or(true, |a| Some(a) == event.actor.as_ref())
+    }
+}
+
+/// Hash preceding the first event of a chain
+const GENESIS_HASH: &str = "";
+
+enum Storage {
+    /// Keeps last [MEMORY_CAPACITY] events, used when there's no log file
+    Memory(VecDeque<AuditEvent>),
+    File {
+        path: PathBuf,
+        file: File,
+    },
+}
+
+struct Chain {
+    storage: Storage,
+    /// Hash of the last recorded event
+    last_hash: String,
+}
+
+/// Append-only audit log, cheap to clone.
+/// Recording never fails: write errors are logged and the event is still emitted to tracing.
+#[derive(Clone)]
+pub struct AuditLog {
+    chain: Arc<Mutex<Chain>>,
+    subscribers: broadcast::Sender<AuditEvent>,
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        Self::with_storage(Storage::Memory(VecDeque::new()))
+    }
+}
+
+impl AuditLog {
+    fn with_storage(storage: Storage) -> Self {
+        Self::with_chain(storage, GENESIS_HASH.to_string())
+    }
+
+    fn with_chain(storage: Storage, last_hash: String) -> Self {
+        let (subscribers, _) = broadcast::channel(SUBSCRIPTION_CAPACITY);
+        Self {
+            chain: Arc::new(Mutex::new(Chain { storage, last_hash })),
+            subscribers,
+        }
+    }
+
+    /// Opens the log file for appending, creating it if it doesn't exist.
+    /// New events continue the chain of the events already in the file.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let last_hash = read_events(&path)?
+            .last()
+            .and_then(|e| e.hash.clone())
+            .unwrap_or_else(|| GENESIS_HASH.to_string());
+        Ok(Self::with_chain(Storage::File { path, file }, last_hash))
+    }
+
+    /// Live stream of recorded and published events
+    pub fn subscribe(&self) -> broadcast::Receiver<AuditEvent> {
+        self.subscribers.subscribe()
+    }
+
+    /// Sends the event to subscribers only, without persisting or logging it
+    pub fn publish(&self, event: AuditEvent) {
+        // fails only when there are no subscribers
+        self.subscribers.send(event).ok();
+    }
+
+    pub fn record(&self, event: AuditEvent) {
+        let mut chain = self.chain.lock();
+        let hash = event.chained_hash(&chain.last_hash);
+        let event = AuditEvent {
+            hash: Some(hash.clone()),
+            ..event
+        };
+        chain.last_hash = hash;
+
+        tracing::info!(
+            target: "audit",
+            kind = ?event.kind,
+            actor = event.actor.as_deref().unwrap_or_default(),
+            subject = %event.subject,
+            details = event.details.as_deref().unwrap_or_default(),
+            "Audit event {:?} {}",
+            event.kind,
+            event.subject
+        );
+        self.publish(event.clone());
+
+        match &mut chain.storage {
+            Storage::Memory(events) => {
+                if events.len() >= MEMORY_CAPACITY {
+                    events.pop_front();
+                }
+                events.push_back(event);
+            }
+            Storage::File { path, file } => {
+                if let Err(err) = try_append(file, &event) {
+                    tracing::warn!(
+                        target: "audit",
+                        "Failed to append audit event to {}: {err}",
+                        path.display()
+                    );
+                }
+            }
+        }
+    }
+
+    /// Events matching `query`, oldest first
+    pub fn query(&self, query: &AuditQuery) -> io::Result<Vec<AuditEvent>> {
+        let mut events = self.events()?;
+        events.retain(|e| query.matches(e));
+
+        if let Some(limit) = query.limit {
+            let skip = events.len().saturating_sub(limit);
+            events.drain(..skip);
+        }
+        Ok(events)
+    }
+
+    /// Checks hashes of the recorded events. Events recorded before chaining was introduced
+    /// don't have hashes, the chain starts after them. An event without a hash after the chain
+    /// has started breaks it, otherwise an inserted event could pass as an old one.
+    pub fn verify(&self) -> io::Result<ChainStatus> {
+        let events = self.events()?;
+        // the memory log drops the oldest events, so its first event isn't checked
+        let mut previous = match self.chain.lock().storage {
+            Storage::Memory(_) => None,
+            Storage::File { .. } => Some(GENESIS_HASH.to_string()),
+        };
+        let mut verified = 0;
+        for (index, event) in events.iter().enumerate() {
+            let Some(hash) = &event.hash else {
+                if verified > 0 {
+                    return Ok(ChainStatus {
+                        events: verified,
+                        broken_at: Some(index),
+                    });
+                }
+                previous = Some(GENESIS_HASH.to_string());
+                continue;
+            };
+            if previous.is_some_and(|previous| event.chained_hash(&previous) != *hash) {
+                return Ok(ChainStatus {
+                    events: verified,
+                    broken_at: Some(index),
+                });
+            }
+            verified += 1;
+            previous = Some(hash.clone());
+        }
+        Ok(ChainStatus {
+            events: verified,
+            broken_at: None,
+        })
+    }
+
+    /// All recorded events, oldest first
+    fn events(&self) -> io::Result<Vec<AuditEvent>> {
+        let chain = self.chain.lock();
+        match &chain.storage {
+            Storage::Memory(events) => Ok(events.iter().cloned().collect()),
+            Storage::File { path, .. } => read_events(path),
+        }
+    }
+}
+
+fn read_events(path: &Path) -> io::Result<Vec<AuditEvent>> {
+    let mut events = vec![];
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        // a partially written line is skipped rather than failing the whole query
+        match serde_json::from_str::<AuditEvent>(&line) {
+            Ok(event) => events.push(event),
+            Err(err) => {
+                tracing::warn!(target: "audit", "Skipping malformed audit event: {err}")
+            }
+        }
+    }
+    Ok(events)
+}
+
+fn try_append(file: &mut File, event: &AuditEvent) -> io::Result<()> {
+    let mut line = serde_json::to_vec(event)?;
+    line.push(b'\n');
+    // single write, so lines of concurrent writers don't interleave
+    file.write_all(&line)?;
+    file.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subjects(events: &[AuditEvent]) -> Vec<(AuditEventKind, &str)> {
+        events
+            .iter()
+            .map(|e| (e.kind, e.subject.as_str()))
+            .collect()
+    }
+
+    fn events() -> Vec<AuditEvent> {
+        vec![
+            AuditEvent::new(AuditEventKind::ServiceCreated, "service").with_actor("peer"),
+            AuditEvent::new(AuditEventKind::PeerBanned, "banned"),
+            AuditEvent::new(AuditEventKind::ConfigReloaded, "config").with_details("log_filter"),
+        ]
+    }
+
+    #[test]
+    fn query_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+
+        let log = AuditLog::open(&path).unwrap();
+        for event in events() {
+            log.record(event);
+        }
+        // reopened log appends to the existing file
+        let log = AuditLog::open(&path).unwrap();
+        log.record(AuditEvent::new(AuditEventKind::KeyUsed, "host"));
+
+        let all = log.query(&AuditQuery::default()).unwrap();
+        assert_eq!(all.len(), 4);
+        assert_eq!(subjects(&all[..3]), subjects(&events()));
+        assert_eq!(all[0].actor.as_deref(), Some("peer"));
+        assert_eq!(all[2].details.as_deref(), Some("log_filter"));
+
+        let banned = log
+            .query(&AuditQuery {
+                kinds: vec![AuditEventKind::PeerBanned],
+                ..<_>::default()
+            })
+            .unwrap();
+        assert_eq!(banned.len(), 1);
+        assert_eq!(banned[0].subject, "banned");
+
+        let last = log
+            .query(&AuditQuery {
+                limit: Some(2),
+                ..<_>::default()
+            })
+            .unwrap();
+        assert_eq!(last.len(), 2);
+        assert_eq!(last[1].kind, AuditEventKind::KeyUsed);
+
+        let future = log
+            .query(&AuditQuery {
+                since: Some(u64::MAX),
+                ..<_>::default()
+            })
+            .unwrap();
+        assert!(future.is_empty());
+    }
+
+    #[test]
+    fn tamper_evident_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+        // event recorded before chaining was introduced
+        std::fs::write(
+            &path,
+            r#"{"timestamp":1,"kind":"peer_banned","subject":"legacy"}"#.to_string() + "\n",
+        )
+        .unwrap();
+
+        let log = AuditLog::open(&path).unwrap();
+        for event in events() {
+            log.record(event);
+        }
+        let log = AuditLog::open(&path).unwrap();
+        log.record(
+            AuditEvent::new(AuditEventKind::ManagementOperation, "srv.create")
+                .with_details(arguments_hash(&["blueprint"])),
+        );
+        assert_eq!(
+            log.verify().unwrap(),
+            ChainStatus {
+                events: 4,
+                broken_at: None
+            }
+        );
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        // event without a hash inserted after the chain has started
+        std::fs::write(
+            &path,
+            contents.clone()
+                + r#"{"timestamp":2,"kind":"peer_banned","subject":"inserted"}"#
+                + "\n",
+        )
+        .unwrap();
+        assert_eq!(
+            log.verify().unwrap(),
+            ChainStatus {
+                events: 4,
+                broken_at: Some(5)
+            }
+        );
+
+        std::fs::write(
+            &path,
+            contents.replace(r#""subject":"banned""#, r#""subject":"forgiven""#),
+        )
+        .unwrap();
+        assert_eq!(
+            log.verify().unwrap(),
+            ChainStatus {
+                events: 1,
+                broken_at: Some(2)
+            }
+        );
+    }
+
+    #[test]
+    fn query_memory() {
+        let log = AuditLog::default();
+        for _ in 0..MEMORY_CAPACITY {
+            log.record(AuditEvent::new(AuditEventKind::WorkerCreated, "worker"));
+        }
+        for event in events() {
+            log.record(event);
+        }
+
+        let all = log.query(&AuditQuery::default()).unwrap();
+        assert_eq!(all.len(), MEMORY_CAPACITY);
+        assert_eq!(subjects(&all[MEMORY_CAPACITY - 3..]), subjects(&events()));
+    }
+
+    #[test]
+    fn subscribe() {
+        let log = AuditLog::default();
+        let mut subscription = log.subscribe();
+
+        log.record(AuditEvent::new(AuditEventKind::ServiceCreated, "service"));
+        log.publish(AuditEvent::new(AuditEventKind::PeerConnected, "peer"));
+
+        let received = [
+            subscription.try_recv().unwrap(),
+            subscription.try_recv().unwrap(),
+        ];
+        assert_eq!(
+            subjects(&received),
+            vec![
+                (AuditEventKind::ServiceCreated, "service"),
+                (AuditEventKind::PeerConnected, "peer")
+            ]
+        );
+        assert!(subscription.try_recv().is_err());
+
+        // published events aren't persisted
+        let all = log.query(&AuditQuery::default()).unwrap();
+        assert_eq!(
+            subjects(&all),
+            vec![(AuditEventKind::ServiceCreated, "service")]
+        );
+    }
+}
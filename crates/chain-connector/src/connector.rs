@@ -3,7 +3,6 @@ use std::sync::Arc;
 
 use ccp_shared::proof::CCProof;
 use ccp_shared::types::{Difficulty, GlobalNonce, CUID};
-use clarity::Transaction;
 use ethabi::ethereum_types::U256;
 use ethabi::Token;
 use eyre::eyre;
@@ -30,6 +29,7 @@ use types::DealId;
 
 use crate::error::{process_response, ConnectorError};
 use crate::function::{GetCommitmentFunction, GetCommitmentStatusFunction, SubmitProofFunction};
+use crate::signer::{make_signer, TxSigner, UnsignedTx};
 use crate::ConnectorError::InvalidBaseFeePerGas;
 use crate::{
     CurrentEpochFunction, DifficultyFunction, EpochDurationFunction, GetComputePeerFunction,
@@ -44,6 +44,10 @@ pub struct ChainConnector {
     config: ChainConfig,
     tx_nonce_mutex: Arc<Mutex<()>>,
     host_id: PeerId,
+    /// Peer id the host is registered with on chain.
+    /// It's the previous host peer id until the grace period of a host key handover ends.
+    compute_peer_id: PeerId,
+    signer: Box<dyn TxSigner>,
 }
 
 pub struct CCInitParams {
@@ -58,6 +62,18 @@ impl ChainConnector {
     pub fn new(
         config: ChainConfig,
         host_id: PeerId,
+        compute_peer_id: PeerId,
+    ) -> eyre::Result<(Arc<Self>, HashMap<String, CustomService>)> {
+        let signer = make_signer(&config)?;
+        Self::with_signer(config, host_id, compute_peer_id, signer)
+    }
+
+    /// Transactions are signed by `signer` instead of the one configured in `config`
+    pub fn with_signer(
+        config: ChainConfig,
+        host_id: PeerId,
+        compute_peer_id: PeerId,
+        signer: Box<dyn TxSigner>,
     ) -> eyre::Result<(Arc<Self>, HashMap<String, CustomService>)> {
         tracing::info!(target: "chain-connector","Connecting to chain via {}", config.http_endpoint);
 
@@ -66,6 +82,8 @@ impl ChainConnector {
             config,
             tx_nonce_mutex: Arc::new(Default::default()),
             host_id,
+            compute_peer_id,
+            signer,
         });
 
         let builtins = Self::make_connector_builtins(connector.clone());
@@ -127,7 +145,7 @@ impl ChainConnector {
     }
 
     async fn get_tx_nonce(&self) -> Result<U256, ConnectorError> {
-        let address = self.config.wallet_key.to_address().to_string();
+        let address = self.signer.address();
         let resp: String = process_response(
             self.client
                 .request("eth_getTransactionCount", rpc_params![address, "pending"])
@@ -156,7 +174,7 @@ impl ChainConnector {
                 .request(
                     "eth_estimateGas",
                     rpc_params![json!({
-                        "from": self.config.wallet_key.to_address().to_string(),
+                        "from": self.signer.address(),
                         "to": to,
                         "data": format!("0x{}", hex::encode(data)),
                     })],
@@ -170,7 +188,7 @@ impl ChainConnector {
 
     pub async fn send_tx(&self, data: Vec<u8>, to: &str) -> Result<String, ConnectorError> {
         let base_fee_per_gas = self.get_base_fee_per_gas().await?;
-        tracing::info!(target: "chain-connector", "Estimating gas for tx from {} to {} data {}", self.config.wallet_key.to_address(), to, hex::encode(&data));
+        tracing::info!(target: "chain-connector", "Estimating gas for tx from {} to {} data {}", self.signer.address(), to, hex::encode(&data));
         let gas_limit = self.estimate_gas_limit(&data, to).await?;
         let max_priority_fee_per_gas = self.max_priority_fee_per_gas().await?;
 
@@ -186,27 +204,22 @@ impl ChainConnector {
         let nonce = self.get_tx_nonce().await?;
 
         // Create a new transaction
-        let tx = Transaction::Eip1559 {
-            chain_id: self.config.network_id.into(),
-            nonce: nonce.as_u128().into(),
-            max_priority_fee_per_gas: max_priority_fee_per_gas.as_u128().into(),
-            gas_limit: gas_limit.as_u128().into(),
-            to: to.parse()?,
-            value: 0u32.into(),
+        let tx = UnsignedTx {
+            chain_id: self.config.network_id,
+            nonce,
+            max_priority_fee_per_gas,
+            max_fee_per_gas,
+            gas_limit,
+            to: to.to_string(),
             data,
-            signature: None, // Not signed. Yet.
-            max_fee_per_gas: max_fee_per_gas.as_u128().into(),
-            access_list: vec![],
         };
 
-        let tx = tx
-            .sign(&self.config.wallet_key, Some(self.config.network_id))
-            .to_bytes();
+        let tx = self.signer.sign(tx).await?;
         let tx = hex::encode(tx);
 
         tracing::info!(target: "chain-connector",
             "Sending tx to {to} from {} signed {tx}",
-            self.config.wallet_key.to_address()
+            self.signer.address()
         );
 
         let resp: String = process_response(
@@ -218,7 +231,7 @@ impl ChainConnector {
     }
 
     pub async fn get_current_commitment_id(&self) -> Result<Option<CommitmentId>, ConnectorError> {
-        let peer_id = Token::FixedBytes(peer_id_to_bytes(self.host_id));
+        let peer_id = Token::FixedBytes(peer_id_to_bytes(self.compute_peer_id));
         let data = GetComputePeerFunction::data(&[peer_id])?;
         let resp: String = process_response(
             self.client
@@ -315,8 +328,9 @@ impl ChainConnector {
     }
 
     pub async fn get_compute_units(&self) -> Result<Vec<ComputeUnit>, ConnectorError> {
-        let data =
-            GetComputeUnitsFunction::data(&[Token::FixedBytes(peer_id_to_bytes(self.host_id))])?;
+        let data = GetComputeUnitsFunction::data(&[Token::FixedBytes(peer_id_to_bytes(
+            self.compute_peer_id,
+        ))])?;
         let resp: String = process_response(
             self.client
                 .request(
@@ -495,6 +509,9 @@ mod tests {
     use crate::{ChainConnector, ConnectorError};
 
     fn get_connector(url: &str) -> Arc<ChainConnector> {
+        let host_id =
+            peer_id_from_hex("0x6497db93b32e4cdd979ada46a23249f444da1efb186cd74b9666bd03f710028b")
+                .unwrap();
         let (connector, _) = ChainConnector::new(
             server_config::ChainConfig {
                 http_endpoint: url.to_string(),
@@ -502,13 +519,16 @@ mod tests {
                 core_contract_address: "0x0B306BF915C4d645ff596e518fAf3F9669b97016".to_string(),
                 market_contract_address: "0x68B1D87F95878fE05B998F19b66F4baba5De1aed".to_string(),
                 network_id: 3525067388221321,
-                wallet_key: PrivateKey::from_str(
-                    "0x97a2456e78c4894c62eef6031972d1ca296ed40bf311ab54c231f13db59fc428",
-                )
-                .unwrap(),
+                wallet_key: Some(
+                    PrivateKey::from_str(
+                        "0x97a2456e78c4894c62eef6031972d1ca296ed40bf311ab54c231f13db59fc428",
+                    )
+                    .unwrap(),
+                ),
+                remote_signer: None,
             },
-            peer_id_from_hex("0x6497db93b32e4cdd979ada46a23249f444da1efb186cd74b9666bd03f710028b")
-                .unwrap(),
+            host_id,
+            host_id,
         )
         .unwrap();
 
@@ -3,8 +3,10 @@
 mod connector;
 mod error;
 mod function;
+mod signer;
 
 pub use connector::CCInitParams;
 pub use connector::ChainConnector;
 pub use error::ConnectorError;
 pub use function::*;
+pub use signer::{LocalSigner, RemoteSigner, TxSigner, UnsignedTx};
@@ -0,0 +1,218 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use clarity::{Address, PrivateKey, Transaction};
+use ethabi::ethereum_types::U256;
+use jsonrpsee::core::client::ClientT;
+use jsonrpsee::http_client::{HeaderMap, HeaderValue, HttpClient, HttpClientBuilder};
+use jsonrpsee::rpc_params;
+use serde_json::json;
+
+use server_config::{ChainConfig, RemoteSignerConfig};
+
+use crate::error::{process_response, ConnectorError};
+
+/// EIP-1559 transaction before signing
+#[derive(Debug, Clone)]
+pub struct UnsignedTx {
+    pub chain_id: u64,
+    pub nonce: U256,
+    pub max_priority_fee_per_gas: U256,
+    pub max_fee_per_gas: U256,
+    pub gas_limit: U256,
+    pub to: String,
+    pub data: Vec<u8>,
+}
+
+/// Signs transactions sent by the node, so the wallet key doesn't have to be kept in memory
+#[async_trait]
+pub trait TxSigner: Send + Sync {
+    /// Address of the wallet that sends transactions
+    fn address(&self) -> String;
+
+    /// Returns the signed transaction encoded for `eth_sendRawTransaction`
+    async fn sign(&self, tx: UnsignedTx) -> Result<Vec<u8>, ConnectorError>;
+}
+
+/// Signer configured by `chain_config`: either `remote_signer` or `wallet_key`
+pub fn make_signer(config: &ChainConfig) -> eyre::Result<Box<dyn TxSigner>> {
+    match (&config.remote_signer, &config.wallet_key) {
+        (Some(remote), _) => Ok(Box::new(RemoteSigner::new(remote)?)),
+        (None, Some(key)) => Ok(Box::new(LocalSigner::new(key.clone()))),
+        (None, None) => Err(eyre::eyre!(
+            "chain_config: either wallet_key or remote_signer must be set"
+        )),
+    }
+}
+
+/// Signs with the wallet key in memory
+pub struct LocalSigner {
+    key: PrivateKey,
+}
+
+impl LocalSigner {
+    pub fn new(key: PrivateKey) -> Self {
+        Self { key }
+    }
+}
+
+#[async_trait]
+impl TxSigner for LocalSigner {
+    fn address(&self) -> String {
+        self.key.to_address().to_string()
+    }
+
+    async fn sign(&self, tx: UnsignedTx) -> Result<Vec<u8>, ConnectorError> {
+        let chain_id = tx.chain_id;
+        let tx = Transaction::Eip1559 {
+            chain_id: tx.chain_id.into(),
+            nonce: tx.nonce.as_u128().into(),
+            max_priority_fee_per_gas: tx.max_priority_fee_per_gas.as_u128().into(),
+            gas_limit: tx.gas_limit.as_u128().into(),
+            to: tx.to.parse()?,
+            value: 0u32.into(),
+            data: tx.data,
+            signature: None, // Not signed. Yet.
+            max_fee_per_gas: tx.max_fee_per_gas.as_u128().into(),
+            access_list: vec![],
+        };
+        Ok(tx.sign(&self.key, Some(chain_id)).to_bytes())
+    }
+}
+
+/// Signs with `eth_signTransaction` of an external signer, i.e. Web3Signer or Clef,
+/// which keeps the key in an HSM or a KMS
+pub struct RemoteSigner {
+    client: HttpClient,
+    address: String,
+}
+
+impl RemoteSigner {
+    pub fn new(config: &RemoteSignerConfig) -> eyre::Result<Self> {
+        Address::from_str(&config.address)
+            .map_err(|err| eyre::eyre!("remote_signer.address is invalid: {err}"))?;
+        let mut headers = HeaderMap::new();
+        if let Some(token) = &config.token {
+            headers.insert(
+                "Authorization",
+                HeaderValue::from_str(&format!("Bearer {token}"))?,
+            );
+        }
+        let client = HttpClientBuilder::default()
+            .request_timeout(config.timeout)
+            .set_headers(headers)
+            .build(&config.endpoint)?;
+        Ok(Self {
+            client,
+            address: config.address.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl TxSigner for RemoteSigner {
+    fn address(&self) -> String {
+        self.address.clone()
+    }
+
+    async fn sign(&self, tx: UnsignedTx) -> Result<Vec<u8>, ConnectorError> {
+        let request = json!({
+            "from": self.address,
+            "to": tx.to,
+            "chainId": format!("{:#x}", tx.chain_id),
+            "nonce": format!("{:#x}", tx.nonce),
+            "gas": format!("{:#x}", tx.gas_limit),
+            "maxFeePerGas": format!("{:#x}", tx.max_fee_per_gas),
+            "maxPriorityFeePerGas": format!("{:#x}", tx.max_priority_fee_per_gas),
+            "value": "0x0",
+            "data": format!("0x{}", hex::encode(&tx.data)),
+        });
+        let signed: String = process_response(
+            self.client
+                .request("eth_signTransaction", rpc_params![request])
+                .await,
+        )?;
+        Ok(hex::decode(signed.trim_start_matches("0x"))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use mockito::Matcher;
+    use serde_json::json;
+
+    use super::*;
+
+    fn unsigned_tx() -> UnsignedTx {
+        UnsignedTx {
+            chain_id: 3525067388221321,
+            nonce: 1.into(),
+            max_priority_fee_per_gas: 2.into(),
+            max_fee_per_gas: 3.into(),
+            gas_limit: 21000.into(),
+            to: "0x68B1D87F95878fE05B998F19b66F4baba5De1aed".to_string(),
+            data: vec![1, 2, 3],
+        }
+    }
+
+    #[tokio::test]
+    async fn remote_signer() {
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/")
+            .match_header("authorization", "Bearer token")
+            .match_body(Matcher::PartialJson(json!({
+                "method": "eth_signTransaction",
+                "params": [{
+                    "from": "0x6c3ff92d92d0d0aa3bd8b1b71cd3ba4a3cf3e8e6",
+                    "nonce": "0x1",
+                    "gas": "0x5208",
+                    "data": "0x010203",
+                }]
+            })))
+            .with_status(200)
+            .with_body(r#"{"jsonrpc":"2.0","result":"0x02f8ab","id":0}"#)
+            .create();
+
+        let signer = RemoteSigner::new(&RemoteSignerConfig {
+            endpoint: server.url(),
+            address: "0x6c3ff92d92d0d0aa3bd8b1b71cd3ba4a3cf3e8e6".to_string(),
+            token: Some("token".to_string()),
+            timeout: Duration::from_secs(1),
+        })
+        .unwrap();
+        let signed = signer.sign(unsigned_tx()).await.unwrap();
+
+        assert_eq!(signed, vec![0x02, 0xf8, 0xab]);
+        mock.assert();
+    }
+
+    #[test]
+    fn invalid_address() {
+        let config = RemoteSignerConfig {
+            endpoint: "http://127.0.0.1:9000".to_string(),
+            address: "not an address".to_string(),
+            token: None,
+            timeout: Duration::from_secs(1),
+        };
+        assert!(RemoteSigner::new(&config).is_err());
+    }
+}
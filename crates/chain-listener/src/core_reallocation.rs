@@ -0,0 +1,112 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use core_manager::CUID;
+
+/// Hysteresis for moving cores between deals and capacity commitment proving.
+///
+/// A unit matched with a deal leaves proving at once, since the deal worker needs its core.
+/// A unit whose deal has ended keeps its core for `hold` before proving on it again, so
+/// the next deal matched in the meantime doesn't make CCP reallocate cores twice.
+#[derive(Debug)]
+pub(crate) struct CoreReallocation {
+    hold: Duration,
+    /// Units returning to proving, with the time they are allowed to
+    returning: BTreeMap<CUID, Instant>,
+}
+
+impl CoreReallocation {
+    pub fn new(hold: Duration) -> Self {
+        Self {
+            hold,
+            returning: BTreeMap::new(),
+        }
+    }
+
+    /// The unit's core goes to a deal, it isn't returning to proving anymore
+    pub fn to_deal(&mut self, unit_id: &CUID) {
+        if self.returning.remove(unit_id).is_some() {
+            tracing::info!(target: "chain-listener", "Unit {unit_id} stays in deal, its core is not returned to capacity commitment");
+        }
+    }
+
+    pub fn deal_ended(&mut self, unit_id: CUID, now: Instant) {
+        if !self.hold.is_zero() {
+            self.returning.insert(unit_id, now + self.hold);
+        }
+    }
+
+    /// Whether the unit's core is still held for deals
+    pub fn is_held(&self, unit_id: &CUID, now: Instant) -> bool {
+        self.returning
+            .get(unit_id)
+            .is_some_and(|returns_at| *returns_at > now)
+    }
+
+    /// Forgets units whose hold has expired and returns them
+    pub fn expired(&mut self, now: Instant) -> Vec<CUID> {
+        self.returning
+            .extract_if(|_, returns_at| *returns_at <= now)
+            .map(|(unit_id, _)| unit_id)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hex::FromHex;
+
+    use super::*;
+
+    fn unit(id: u8) -> CUID {
+        <CUID>::from_hex(format!("{id:064x}")).unwrap()
+    }
+
+    #[test]
+    fn hold_after_deal() {
+        let now = Instant::now();
+        let mut reallocation = CoreReallocation::new(Duration::from_secs(60));
+        reallocation.deal_ended(unit(1), now);
+        reallocation.deal_ended(unit(2), now);
+        assert!(reallocation.is_held(&unit(1), now + Duration::from_secs(30)));
+        assert!(!reallocation.is_held(&unit(3), now));
+
+        // the next deal is matched while the core is held
+        reallocation.to_deal(&unit(2));
+        assert!(!reallocation.is_held(&unit(2), now));
+
+        assert!(reallocation
+            .expired(now + Duration::from_secs(30))
+            .is_empty());
+        assert_eq!(
+            reallocation.expired(now + Duration::from_secs(60)),
+            vec![unit(1)]
+        );
+        assert!(!reallocation.is_held(&unit(1), now + Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn no_hold() {
+        let now = Instant::now();
+        let mut reallocation = CoreReallocation::new(Duration::ZERO);
+        reallocation.deal_ended(unit(1), now);
+        assert!(!reallocation.is_held(&unit(1), now));
+        assert!(reallocation.expired(now).is_empty());
+    }
+}
@@ -30,7 +30,7 @@ pub struct DealMatchedData {
     pub deal_id: DealId,
     pub(crate) unit_id: CUID,
     deal_creation_block: U256,
-    app_cid: String,
+    pub(crate) app_cid: String,
 }
 
 #[allow(dead_code)]
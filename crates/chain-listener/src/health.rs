@@ -0,0 +1,83 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use health::HealthCheck;
+use parking_lot::Mutex;
+
+/// Chain listener is live while it's subscribed to chain events and receives new blocks
+#[derive(Clone)]
+pub struct ChainListenerHealth {
+    subscribed: Arc<AtomicBool>,
+    last_block: Arc<Mutex<Instant>>,
+    block_timeout: Duration,
+}
+
+impl ChainListenerHealth {
+    pub fn new(block_timeout: Duration) -> Self {
+        Self {
+            subscribed: Arc::new(AtomicBool::new(false)),
+            last_block: Arc::new(Mutex::new(Instant::now())),
+            block_timeout,
+        }
+    }
+
+    pub fn on_subscribed(&self) {
+        *self.last_block.lock() = Instant::now();
+        self.subscribed.store(true, Ordering::Release)
+    }
+
+    pub fn on_new_block(&self) {
+        *self.last_block.lock() = Instant::now();
+    }
+}
+
+impl HealthCheck for ChainListenerHealth {
+    fn status(&self) -> eyre::Result<()> {
+        if !self.subscribed.load(Ordering::Acquire) {
+            return Err(eyre::eyre!("Not subscribed to chain events"));
+        }
+
+        let elapsed = self.last_block.lock().elapsed();
+        if elapsed > self.block_timeout {
+            return Err(eyre::eyre!("No new blocks for {}s", elapsed.as_secs()));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chain_listener_health() {
+        let health = ChainListenerHealth::new(Duration::from_secs(60));
+        assert!(health.status().is_err());
+
+        health.on_subscribed();
+        assert!(health.status().is_ok());
+
+        let stale = ChainListenerHealth::new(Duration::ZERO);
+        stale.on_subscribed();
+        std::thread::sleep(Duration::from_millis(1));
+        assert!(stale.status().is_err());
+    }
+}
@@ -3,9 +3,12 @@
 #![feature(extract_if)]
 #![feature(btree_extract_if)]
 
+pub use health::ChainListenerHealth;
 pub use listener::ChainListener;
 
+mod core_reallocation;
 mod event;
+mod health;
 mod listener;
 
 mod persistence;
@@ -3,7 +3,7 @@ use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::path::PathBuf;
 use std::process::exit;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use backoff::future::retry;
 use backoff::ExponentialBackoff;
@@ -19,10 +19,12 @@ use jsonrpsee::rpc_params;
 use libp2p_identity::PeerId;
 use serde::de::DeserializeOwned;
 use serde_json::{json, Value};
+use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
 use tokio::time::interval;
 use tokio_stream::wrappers::IntervalStream;
 use tokio_stream::StreamExt;
+use tracing::instrument;
 
 use chain_connector::{ChainConnector, ConnectorError};
 use chain_data::{parse_log, peer_id_to_hex, ChainData, Log};
@@ -36,12 +38,14 @@ use core_manager::CUID;
 use server_config::{ChainConfig, ChainListenerConfig};
 use types::DealId;
 
+use crate::core_reallocation::CoreReallocation;
 use crate::event::cc_activated::CommitmentActivated;
 use crate::event::{
     CommitmentActivatedData, DealMatched, DealMatchedData, UnitActivated, UnitActivatedData,
     UnitDeactivated, UnitDeactivatedData,
 };
-use crate::persistence;
+use crate::health::ChainListenerHealth;
+use crate::persistence::ProofIdStorage;
 
 const PROOF_POLL_LIMIT: usize = 10;
 
@@ -69,16 +73,22 @@ pub struct ChainListener {
     pending_compute_units: BTreeSet<PendingUnit>,
 
     active_deals: BTreeMap<DealId, CUID>,
+    /// Delays returning cores of ended deals to capacity commitment
+    core_reallocation: CoreReallocation,
 
     /// Resets every epoch
     last_submitted_proof_id: ProofIdx,
-    persisted_proof_id_dir: PathBuf,
+    proof_ids: ProofIdStorage,
 
     unit_activated: Option<Subscription<Log>>,
     unit_deactivated: Option<Subscription<Log>>,
     heads: Option<Subscription<JsonValue>>,
     commitment_activated: Option<Subscription<Log>>,
     unit_matched: Option<Subscription<Log>>,
+
+    health: Option<ChainListenerHealth>,
+    /// Receives ids and app CIDs of deals matched with compute units of this peer
+    deal_matched: Option<mpsc::UnboundedSender<(DealId, String)>>,
 }
 
 async fn poll_subscription<T>(s: &mut Option<Subscription<T>>) -> Option<Result<T, client::Error>>
@@ -123,13 +133,28 @@ impl ChainListener {
             timer_resolution: listener_config.proof_poll_period,
             ccp_client,
             last_submitted_proof_id: ProofIdx::zero(),
-            persisted_proof_id_dir,
+            proof_ids: ProofIdStorage::new(persisted_proof_id_dir),
             unit_activated: None,
             unit_deactivated: None,
             heads: None,
             commitment_activated: None,
             unit_matched: None,
             active_deals: BTreeMap::new(),
+            core_reallocation: CoreReallocation::new(listener_config.deal_core_hold),
+            health: None,
+            deal_matched: None,
+        }
+    }
+
+    pub fn with_health(self, health: Option<ChainListenerHealth>) -> Self {
+        Self { health, ..self }
+    }
+
+    /// Sends ids and app CIDs of matched deals to `deal_matched`, e.g. to prefetch their content
+    pub fn with_deal_matched(self, deal_matched: mpsc::UnboundedSender<(DealId, String)>) -> Self {
+        Self {
+            deal_matched: Some(deal_matched),
+            ..self
         }
     }
 
@@ -231,48 +256,28 @@ impl ChainListener {
         Ok(())
     }
 
-    pub async fn reset_proof_id(&mut self) -> eyre::Result<()> {
-        self.set_proof_id(ProofIdx::zero()).await
+    pub fn reset_proof_id(&mut self) {
+        self.set_proof_id(ProofIdx::zero())
     }
 
-    pub async fn set_proof_id(&mut self, proof_id: ProofIdx) -> eyre::Result<()> {
-        let mut backoff = ExponentialBackoff::default();
-        backoff.max_elapsed_time = Some(Duration::from_secs(3));
-
-        let write = retry(backoff, || async {
-            persistence::persist_proof_id(
-                &self.persisted_proof_id_dir,
-                self.last_submitted_proof_id,
-                self.current_epoch,
-            ).await.map_err(|err|{
-                tracing::error!(target: "chain-listener", "Failed to persist proof id: {err}; Retrying...");
-                eyre!(err)
-            })?;
-            Ok(())
-        }).await;
-
-        if let Err(err) = write {
-            tracing::error!(target: "chain-listener", "Failed to persist proof id: {err}; Ignoring..");
-        }
-
+    /// Proof id is persisted in the background, see [ProofIdStorage]
+    pub fn set_proof_id(&mut self, proof_id: ProofIdx) {
+        self.proof_ids.persist(proof_id, self.current_epoch);
         self.last_submitted_proof_id = proof_id;
-        tracing::info!(target: "chain-listener", "Persisted proof id {proof_id} on epoch {}", self.current_epoch);
-        Ok(())
     }
 
     pub async fn load_proof_id(&mut self) -> eyre::Result<()> {
-        let persisted_proof_id =
-            persistence::load_persisted_proof_id(&self.persisted_proof_id_dir).await?;
+        let persisted_proof_id = self.proof_ids.load().await?;
 
         if let Some(persisted_proof_id) = persisted_proof_id {
             tracing::info!(target: "chain-listener", "Loaded persisted proof id {} saved on epoch {}", persisted_proof_id.proof_id, persisted_proof_id.epoch);
             if persisted_proof_id.epoch != self.current_epoch {
                 tracing::info!(target: "chain-listener","Persisted proof id epoch is different from current epoch {}, resetting proof id", self.current_epoch);
-                self.reset_proof_id().await?;
+                self.reset_proof_id();
             }
         } else {
             tracing::info!(target: "chain-listener","No persisted proof id found, starting from zero");
-            self.reset_proof_id().await?;
+            self.reset_proof_id();
         }
 
         Ok(())
@@ -309,6 +314,8 @@ impl ChainListener {
         let result = tokio::task::Builder::new()
             .name("ChainListener")
             .spawn(async move {
+                self.proof_ids.start_writer();
+
                 if let Err(err) = self.set_utility_core().await {
                     tracing::error!(target: "chain-listener", "Failed to set utility core: {err}; Stopping...");
                     exit(1);
@@ -331,6 +338,9 @@ impl ChainListener {
                 }
 
                 tracing::info!(target: "chain-listener", "Subscribed successfully");
+                if let Some(health) = &self.health {
+                    health.on_subscribed();
+                }
 
                 let setup: eyre::Result<()> = try {
                     self.refresh_state().await?;
@@ -345,7 +355,11 @@ impl ChainListener {
                 loop {
                     tokio::select! {
                         event = poll_subscription(&mut self.heads) => {
-                            if let Err(err) = self.process_new_header(event).await {
+                            let result = self.process_new_header(event).await;
+                            if let (Ok(_), Some(health)) = (&result, &self.health) {
+                                health.on_new_block();
+                            }
+                            if let Err(err) = result {
                                tracing::error!(target: "chain-listener", "newHeads event processing error: {err}");
 
                                 let result: eyre::Result<()> = try {
@@ -433,6 +447,10 @@ impl ChainListener {
                             if let Err(err) = self.poll_deal_statuses().await {
                                 tracing::error!(target: "chain-listener", "Failed to poll deal statuses: {err}");
                             }
+
+                            if let Err(err) = self.return_held_cores().await {
+                                tracing::error!(target: "chain-listener", "Failed to return cores to capacity commitment: {err}");
+                            }
                         }
                     }
                 }
@@ -619,6 +637,7 @@ impl ChainListener {
         Ok(())
     }
 
+    #[instrument(level = tracing::Level::INFO, skip_all)]
     async fn process_new_header(
         &mut self,
         event: Option<Result<Value, Error>>,
@@ -638,7 +657,7 @@ impl ChainListener {
             tracing::info!(target: "chain-listener", "Epoch changed, new epoch number: {epoch_number}");
 
             tracing::info!(target: "chain-listener", "Resetting proof id counter");
-            self.reset_proof_id().await?;
+            self.reset_proof_id();
 
             // nonce changes every epoch
             self.global_nonce = self.chain_connector.get_global_nonce().await?;
@@ -670,6 +689,7 @@ impl ChainListener {
         Ok(())
     }
 
+    #[instrument(level = tracing::Level::INFO, skip_all)]
     async fn process_commitment_activated(
         &mut self,
         event: Option<Result<Log, Error>>,
@@ -710,6 +730,7 @@ impl ChainListener {
         Ok(())
     }
 
+    #[instrument(level = tracing::Level::INFO, skip_all)]
     async fn process_unit_activated(
         &mut self,
         event: Option<Result<Log, Error>>,
@@ -733,6 +754,7 @@ impl ChainListener {
     }
 
     /// Unit goes to Deal
+    #[instrument(level = tracing::Level::INFO, skip_all)]
     async fn process_unit_deactivated(
         &mut self,
         event: Option<Result<Log, client::Error>>,
@@ -747,11 +769,13 @@ impl ChainListener {
         self.active_compute_units.remove(&unit_event.info.unit_id);
         self.pending_compute_units
             .retain(|cu| cu.id != unit_event.info.unit_id);
+        self.core_reallocation.to_deal(&unit_event.info.unit_id);
         self.refresh_commitment().await?;
         self.acquire_deal_core(unit_event.info.unit_id)?;
         Ok(())
     }
 
+    #[instrument(level = tracing::Level::INFO, skip_all)]
     pub fn process_deal_matched(
         &mut self,
         event: Option<Result<Log, client::Error>>,
@@ -763,6 +787,16 @@ impl ChainListener {
             deal_event.info.deal_id
         );
 
+        if let Some(deal_matched) = &self.deal_matched {
+            // fails only if the receiver is dropped, there's nothing to notify then
+            deal_matched
+                .send((
+                    deal_event.info.deal_id.clone(),
+                    deal_event.info.app_cid.clone(),
+                ))
+                .ok();
+        }
+        self.core_reallocation.to_deal(&deal_event.info.unit_id);
         self.active_deals
             .insert(deal_event.info.deal_id, deal_event.info.unit_id);
         Ok(())
@@ -770,14 +804,15 @@ impl ChainListener {
 
     /// Send GlobalNonce, Difficulty and Core<>CUID mapping (full commitment info) to CCP
     async fn refresh_commitment(&self) -> eyre::Result<()> {
-        if self.active_compute_units.is_empty() {
+        let proving_units = self.proving_units();
+        if proving_units.is_empty() {
             self.stop_commitment().await?;
             return Ok(());
         }
 
         tracing::info!(target: "chain-listener",
             "Refreshing commitment, active compute units: {}",
-            self.active_compute_units
+            proving_units
                 .iter()
                 .map(CUID::to_string)
                 .collect::<Vec<_>>()
@@ -786,7 +821,7 @@ impl ChainListener {
         tracing::info!(target: "chain-listener", "Global nonce: {}", self.global_nonce);
         tracing::info!(target: "chain-listener", "Difficulty: {}", self.difficulty);
         if let Some(ref ccp_client) = self.ccp_client {
-            let cores = self.acquire_active_units()?;
+            let cores = self.acquire_active_units(proving_units)?;
             ccp_client
                 .on_active_commitment(
                     OrHex::from(self.global_nonce),
@@ -802,11 +837,40 @@ impl ChainListener {
         Ok(())
     }
 
-    fn acquire_active_units(&self) -> eyre::Result<HashMap<PhysicalCoreId, OrHex<CUID>>> {
+    /// Active compute units except those whose cores are held after their deals
+    fn proving_units(&self) -> Vec<CUID> {
+        let now = Instant::now();
+        self.active_compute_units
+            .iter()
+            .filter(|unit_id| !self.core_reallocation.is_held(unit_id, now))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns cores to capacity commitment once they have been held long enough after their deals
+    async fn return_held_cores(&mut self) -> eyre::Result<()> {
+        let returned = self.core_reallocation.expired(Instant::now());
+        if returned
+            .iter()
+            .any(|unit_id| self.active_compute_units.contains(unit_id))
+        {
+            tracing::info!(target: "chain-listener",
+                "Returning cores to capacity commitment for units: {}",
+                returned.iter().map(CUID::to_string).collect::<Vec<_>>().join(", ")
+            );
+            self.refresh_commitment().await?;
+        }
+        Ok(())
+    }
+
+    fn acquire_active_units(
+        &self,
+        units: Vec<CUID>,
+    ) -> eyre::Result<HashMap<PhysicalCoreId, OrHex<CUID>>> {
         let cores = self
             .core_manager
             .acquire_worker_core(AcquireRequest::new(
-                self.active_compute_units.clone().into_iter().collect(),
+                units.clone(),
                 WorkType::CapacityCommitment,
             ))
             .map_err(|err| {
@@ -817,12 +881,7 @@ impl ChainListener {
         Ok(cores
             .physical_core_ids
             .into_iter()
-            .zip(
-                self.active_compute_units
-                    .clone()
-                    .into_iter()
-                    .map(OrHex::Data),
-            )
+            .zip(units.into_iter().map(OrHex::Data))
             .collect())
     }
 
@@ -883,7 +942,7 @@ impl ChainListener {
 
         // proof_id is used only by CCP and is not sent to chain
         let proof_id = CCProofId::new(self.global_nonce, self.difficulty, ProofIdx::zero());
-        for unit in self.active_compute_units.clone().into_iter() {
+        for unit in self.proving_units() {
             let local_nonce = LocalNonce::random();
             self.submit_proof(CCProof::new(proof_id, local_nonce, unit, result_hash))
                 .await?;
@@ -917,12 +976,13 @@ impl ChainListener {
                 let id = proof.id.idx.clone();
                 tracing::info!(target: "chain-listener", "Submitting proof: {id}");
                 self.submit_proof(proof).await?;
-                self.set_proof_id(proof.id.idx).await?;
+                self.set_proof_id(proof.id.idx);
             }
         }
         Ok(())
     }
 
+    #[instrument(level = tracing::Level::INFO, skip_all)]
     async fn submit_proof(&mut self, proof: CCProof) -> eyre::Result<()> {
         if !self.active_compute_units.contains(&proof.cu_id) {
             return Ok(());
@@ -1015,6 +1075,7 @@ impl ChainListener {
             U256::from_str_radix(&block_number, 16)?,
         ))
     }
+    #[instrument(level = tracing::Level::INFO, skip_all)]
     async fn poll_deal_statuses(&mut self) -> eyre::Result<()> {
         if self.active_deals.is_empty() {
             return Ok(());
@@ -1067,6 +1128,7 @@ impl ChainListener {
         .await?;
 
         self.active_deals.remove(deal_id);
+        self.core_reallocation.deal_ended(cu_id, Instant::now());
         Ok(())
     }
 }
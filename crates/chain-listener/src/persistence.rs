@@ -13,14 +13,25 @@
  * See the License for the specific language governing permissions and
  * limitations under the License.
 */
-use eyre::Context;
-use std::path::Path;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
+use backoff::future::retry;
+use backoff::ExponentialBackoff;
 use ccp_shared::proof::ProofIdx;
 use ethabi::ethereum_types::U256;
+use eyre::Context;
 use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
 
-#[derive(Serialize, Deserialize)]
+/// Proof ids set during this delay are written at once, only the latest one is written
+const WRITE_BATCH_DELAY: Duration = Duration::from_millis(100);
+const WRITE_RETRY_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct PersistedProofId {
     pub proof_id: ProofIdx,
     pub epoch: U256,
@@ -30,36 +41,149 @@ pub(crate) fn proof_id_filename() -> String {
     "proof_id.toml".to_string()
 }
 
-pub(crate) async fn persist_proof_id(
-    proof_id_dir: &Path,
-    proof_id: ProofIdx,
-    current_epoch: U256,
-) -> eyre::Result<()> {
+/// Persists proof ids in the background, so the listener doesn't wait for disk IO
+/// while submitting proofs
+pub(crate) struct ProofIdStorage {
+    proof_id_dir: PathBuf,
+    latest: watch::Sender<Option<PersistedProofId>>,
+}
+
+impl ProofIdStorage {
+    pub fn new(proof_id_dir: PathBuf) -> Self {
+        let (latest, _) = watch::channel(None);
+        Self {
+            proof_id_dir,
+            latest,
+        }
+    }
+
+    /// Starts writing persisted proof ids. The writer stops after the storage is dropped
+    /// and the latest proof id is written.
+    pub fn start_writer(&self) -> JoinHandle<()> {
+        let proof_id_dir = self.proof_id_dir.clone();
+        let latest = self.latest.subscribe();
+        tokio::spawn(write_proof_ids(proof_id_dir, latest))
+    }
+
+    /// Schedules proof id to be written, doesn't wait for the write
+    pub fn persist(&self, proof_id: ProofIdx, epoch: U256) {
+        self.latest
+            .send_replace(Some(PersistedProofId { proof_id, epoch }));
+    }
+
+    /// The latest persisted proof id, including the one that isn't written yet
+    pub async fn load(&self) -> eyre::Result<Option<PersistedProofId>> {
+        if let Some(pending) = self.latest.borrow().clone() {
+            return Ok(Some(pending));
+        }
+        load_persisted_proof_id(&self.proof_id_dir).await
+    }
+}
+
+async fn write_proof_ids(
+    proof_id_dir: PathBuf,
+    mut latest: watch::Receiver<Option<PersistedProofId>>,
+) {
+    while latest.changed().await.is_ok() {
+        tokio::time::sleep(WRITE_BATCH_DELAY).await;
+        let Some(proof_id) = latest.borrow_and_update().clone() else {
+            continue;
+        };
+
+        let backoff = ExponentialBackoff {
+            max_elapsed_time: Some(WRITE_RETRY_TIMEOUT),
+            ..ExponentialBackoff::default()
+        };
+        let write = retry(backoff, || async {
+            write_proof_id(&proof_id_dir, &proof_id).await.map_err(|err| {
+                tracing::error!(target: "chain-listener", "Failed to persist proof id: {err}; Retrying...");
+                backoff::Error::transient(err)
+            })
+        })
+        .await;
+
+        match write {
+            Ok(()) => {
+                tracing::info!(target: "chain-listener", "Persisted proof id {} on epoch {}", proof_id.proof_id, proof_id.epoch)
+            }
+            Err(err) => {
+                tracing::error!(target: "chain-listener", "Failed to persist proof id: {err}; Ignoring..")
+            }
+        }
+    }
+}
+
+/// Writes proof id to a temporary file and renames it, so the persisted proof id
+/// is never partially written
+async fn write_proof_id(proof_id_dir: &Path, proof_id: &PersistedProofId) -> eyre::Result<()> {
     let path = proof_id_dir.join(proof_id_filename());
-    let bytes = toml::ser::to_vec(&PersistedProofId {
-        proof_id,
-        epoch: current_epoch,
-    })
-    .map_err(|err| eyre::eyre!("Proof id serialization failed {err}"))?;
-    Ok(tokio::fs::write(&path, bytes)
+    let tmp_path = path.with_extension("toml.tmp");
+    let bytes = toml::ser::to_vec(proof_id)
+        .map_err(|err| eyre::eyre!("Proof id serialization failed {err}"))?;
+
+    let mut file = tokio::fs::File::create(&tmp_path)
+        .await
+        .context(format!("error creating {}", tmp_path.display()))?;
+    file.write_all(&bytes)
         .await
-        .context(format!("error writing proof id to {}", path.display()))?)
+        .context(format!("error writing proof id to {}", tmp_path.display()))?;
+    file.sync_all()
+        .await
+        .context(format!("error syncing {}", tmp_path.display()))?;
+
+    tokio::fs::rename(&tmp_path, &path).await.context(format!(
+        "error renaming {} to {}",
+        tmp_path.display(),
+        path.display()
+    ))
 }
 
 pub(crate) async fn load_persisted_proof_id(
     proof_id_dir: &Path,
 ) -> eyre::Result<Option<PersistedProofId>> {
     let path = proof_id_dir.join(proof_id_filename());
-    if path.exists() {
-        let bytes = tokio::fs::read(&path)
-            .await
-            .context(format!("error reading proof id from {}", path.display()))?;
-        let persisted_proof = toml::from_slice(&bytes).context(format!(
-            "error deserializing proof id from {}",
-            path.display()
-        ))?;
-        Ok(Some(persisted_proof))
-    } else {
-        Ok(None)
+    let bytes = match tokio::fs::read(&path).await {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(err).context(format!("error reading proof id from {}", path.display()))
+        }
+    };
+    let persisted_proof = toml::from_slice(&bytes).context(format!(
+        "error deserializing proof id from {}",
+        path.display()
+    ))?;
+    Ok(Some(persisted_proof))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn latest_proof_id_is_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = ProofIdStorage::new(dir.path().to_path_buf());
+        let writer = storage.start_writer();
+
+        for epoch in 0..10 {
+            storage.persist(ProofIdx::zero(), U256::from(epoch));
+        }
+        let pending = storage.load().await.unwrap().unwrap();
+        assert_eq!(pending.epoch, U256::from(9));
+
+        drop(storage);
+        writer.await.unwrap();
+
+        let persisted = load_persisted_proof_id(dir.path()).await.unwrap().unwrap();
+        assert_eq!(persisted.epoch, U256::from(9));
+        assert!(!dir.path().join("proof_id.toml.tmp").exists());
+    }
+
+    #[tokio::test]
+    async fn no_persisted_proof_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = ProofIdStorage::new(dir.path().to_path_buf());
+        assert!(storage.load().await.unwrap().is_none());
     }
 }
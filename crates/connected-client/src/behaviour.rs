@@ -14,12 +14,13 @@
  * limitations under the License.
  */
 
-use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
 use std::task::{Context, Poll, Waker};
 use std::time::Duration;
 
 use futures::future::BoxFuture;
-use futures::FutureExt;
+use futures::stream::FuturesUnordered;
+use futures::{FutureExt, StreamExt};
 use libp2p::core::Endpoint;
 use libp2p::identity::PublicKey;
 use libp2p::swarm::ToSwarm::GenerateEvent;
@@ -34,9 +35,13 @@ use libp2p::{
     swarm::{NetworkBehaviour, NotifyHandler, OneShotHandler, ToSwarm},
     PeerId,
 };
-use particle_protocol::{HandlerMessage, Particle, ProtocolConfig, PROTOCOL_NAME};
+use particle_protocol::{
+    CompletionChannel, HandlerMessage, Particle, ProtocolConfig, RejectionNotice, SendStatus,
+    PROTOCOL_NAME,
+};
+use tokio::sync::oneshot;
 
-use crate::ClientEvent;
+use crate::{ClientEvent, ReconnectConfig};
 
 pub type SwarmEventType = ToSwarm<ClientEvent, THandlerInEvent<ClientBehaviour>>;
 
@@ -48,8 +53,12 @@ pub struct FluenceClientBehaviour {
 }
 
 impl FluenceClientBehaviour {
-    pub fn new(protocol_config: ProtocolConfig, public_key: PublicKey) -> Self {
-        let client = ClientBehaviour::new(protocol_config);
+    pub fn new(
+        protocol_config: ProtocolConfig,
+        public_key: PublicKey,
+        reconnect: Option<ReconnectConfig>,
+    ) -> Self {
+        let client = ClientBehaviour::new(protocol_config, reconnect);
         let identify = Identify::new(IdentifyConfig::new(PROTOCOL_NAME.into(), public_key));
         let ping = Ping::new(
             PingConfig::new()
@@ -64,29 +73,39 @@ impl FluenceClientBehaviour {
     }
 
     pub fn call(&mut self, peer_id: PeerId, call: Particle) {
-        self.client.events.push_back(ToSwarm::NotifyHandler {
-            event: HandlerMessage::OutParticle(call, <_>::default()),
-            handler: NotifyHandler::Any,
-            peer_id,
-        });
-
-        self.client.wake();
+        self.client.send(peer_id, call);
     }
 }
 
+/// Result of sending a particle, along with the particle to resend it on failure
+type SendResult = (PeerId, Particle, SendStatus);
+
 pub struct ClientBehaviour {
     protocol_config: ProtocolConfig,
     events: VecDeque<SwarmEventType>,
-    reconnect: Option<BoxFuture<'static, Vec<Multiaddr>>>,
+    /// How to recover from connection loss, the relay isn't redialed if not set
+    reconnect_config: Option<ReconnectConfig>,
+    redial: Option<BoxFuture<'static, Vec<Multiaddr>>>,
+    connected: HashSet<PeerId>,
+    /// Particles waiting for the connection to be (re)established or for a retry
+    pending: VecDeque<(PeerId, Particle)>,
+    /// Particles being sent, tracked to resend them if the connection is lost meanwhile
+    in_flight: FuturesUnordered<BoxFuture<'static, SendResult>>,
+    retry: Option<BoxFuture<'static, ()>>,
     waker: Option<Waker>,
 }
 
 impl ClientBehaviour {
-    pub fn new(protocol_config: ProtocolConfig) -> Self {
+    pub fn new(protocol_config: ProtocolConfig, reconnect_config: Option<ReconnectConfig>) -> Self {
         Self {
             protocol_config,
             events: VecDeque::default(),
-            reconnect: None,
+            reconnect_config,
+            redial: None,
+            connected: <_>::default(),
+            pending: <_>::default(),
+            in_flight: <_>::default(),
+            retry: None,
             waker: None,
         }
     }
@@ -97,6 +116,97 @@ impl ClientBehaviour {
         }
     }
 
+    fn send(&mut self, peer_id: PeerId, particle: Particle) {
+        let compression = self.protocol_config.compression.clone();
+        let Some(config) = &self.reconnect_config else {
+            self.events.push_back(ToSwarm::NotifyHandler {
+                event: HandlerMessage::OutParticle(particle, <_>::default(), compression),
+                handler: NotifyHandler::Any,
+                peer_id,
+            });
+            self.wake();
+            return;
+        };
+
+        if !self.connected.contains(&peer_id) {
+            if self.pending.len() >= config.max_pending {
+                if let Some((_, dropped)) = self.pending.pop_front() {
+                    log::warn!(
+                        "Too many particles wait for reconnection, dropping particle {}",
+                        dropped.id
+                    );
+                }
+            }
+            tracing::debug!(
+                particle_id = particle.id,
+                "Not connected to {peer_id}, particle will be sent after reconnection"
+            );
+            self.pending.push_back((peer_id, particle));
+            return;
+        }
+
+        let (outlet, inlet) = oneshot::channel();
+        let resend = particle.clone();
+        self.in_flight.push(
+            async move {
+                // the channel is dropped if the connection is closed before the particle is sent
+                let status = inlet.await.unwrap_or(SendStatus::NotConnected);
+                (peer_id, resend, status)
+            }
+            .boxed(),
+        );
+        self.events.push_back(ToSwarm::NotifyHandler {
+            event: HandlerMessage::OutParticle(
+                particle,
+                CompletionChannel::Oneshot(outlet),
+                compression,
+            ),
+            handler: NotifyHandler::Any,
+            peer_id,
+        });
+        self.wake();
+    }
+
+    fn on_send_result(&mut self, (peer_id, particle, status): SendResult) {
+        let Some(config) = &self.reconnect_config else {
+            return;
+        };
+        if matches!(status, SendStatus::Ok) {
+            return;
+        }
+        if particle.is_expired() {
+            log::warn!(
+                "Failed to send particle {} to {peer_id}: {status:?}, it has expired",
+                particle.id
+            );
+            return;
+        }
+
+        log::warn!(
+            "Failed to send particle {} to {peer_id}: {status:?}, will resend it",
+            particle.id
+        );
+        self.pending.push_back((peer_id, particle));
+        if self.retry.is_none() {
+            self.retry = tokio::time::sleep(config.delay).boxed().into();
+        }
+    }
+
+    /// Sends pending particles to connected peers, particles for other peers keep waiting
+    fn send_pending(&mut self) {
+        let pending = std::mem::take(&mut self.pending);
+        for (peer_id, particle) in pending {
+            if particle.is_expired() {
+                log::warn!(
+                    "Particle {} expired while waiting for reconnection to {peer_id}",
+                    particle.id
+                );
+                continue;
+            }
+            self.send(peer_id, particle);
+        }
+    }
+
     fn on_connection_established(&mut self, peer_id: &PeerId, cp: &ConnectedPoint) {
         let multiaddr = match cp {
             ConnectedPoint::Dialer { address, .. } => address,
@@ -118,10 +228,17 @@ impl ClientBehaviour {
             .push_back(ToSwarm::GenerateEvent(ClientEvent::NewConnection {
                 peer_id: *peer_id,
                 multiaddr: multiaddr.clone(),
-            }))
+            }));
+
+        self.connected.insert(*peer_id);
+        self.send_pending();
     }
 
     fn on_dial_failure(&mut self, peer_id: Option<PeerId>, error: &DialError) {
+        let Some(config) = &self.reconnect_config else {
+            log::warn!("Failed to connect to {:?}: {:?}", peer_id, error);
+            return;
+        };
         log::warn!(
             "Failed to connect to {:?}: {:?}, reconnecting",
             peer_id,
@@ -130,9 +247,9 @@ impl ClientBehaviour {
 
         if let DialError::Transport(addresses) = error {
             let addresses = addresses.iter().map(|(a, _)| a.clone()).collect();
-            self.reconnect = async move {
-                // TODO: move timeout to config
-                tokio::time::sleep(Duration::from_secs(1)).await;
+            let delay = config.delay;
+            self.redial = async move {
+                tokio::time::sleep(delay).await;
                 addresses
             }
             .boxed()
@@ -150,8 +267,12 @@ impl ClientBehaviour {
             // not disconnected, we don't care
             return;
         }
+        self.connected.remove(peer_id);
 
         match cp {
+            ConnectedPoint::Dialer { address, .. } if self.reconnect_config.is_none() => {
+                log::warn!("Disconnected from {} @ {:?}", peer_id, address);
+            }
             ConnectedPoint::Dialer { address, .. } => {
                 let address = address.clone();
                 log::warn!(
@@ -239,26 +360,57 @@ impl NetworkBehaviour for ClientBehaviour {
         _cid: ConnectionId,
         event: THandlerOutEvent<Self>,
     ) {
-        use ClientEvent::Particle;
-
-        if let Ok(HandlerMessage::InParticle(particle)) = event {
-            self.events.push_back(GenerateEvent(Particle {
-                particle,
-                sender: peer_id,
-            }))
+        match event {
+            Ok(HandlerMessage::InParticle(particle)) => {
+                let event = match RejectionNotice::from_particle(&particle) {
+                    Some(notice) => {
+                        log::warn!(
+                            "{} rejected particle {}: {}",
+                            peer_id,
+                            notice.particle_id,
+                            notice.message
+                        );
+                        ClientEvent::Rejected { peer_id, notice }
+                    }
+                    None => ClientEvent::Particle {
+                        particle,
+                        sender: peer_id,
+                    },
+                };
+                self.events.push_back(GenerateEvent(event))
+            }
+            Ok(HandlerMessage::Goodbye(relays, _)) => {
+                log::info!(
+                    "{} is shutting down, suggested relays: {:?}",
+                    peer_id,
+                    relays
+                );
+                self.events
+                    .push_back(GenerateEvent(ClientEvent::Goodbye { peer_id, relays }))
+            }
+            _ => {}
         }
     }
 
     fn poll(&mut self, cx: &mut Context<'_>) -> Poll<SwarmEventType> {
         self.waker = Some(cx.waker().clone());
 
-        if let Some(Poll::Ready(addresses)) = self.reconnect.as_mut().map(|r| r.poll_unpin(cx)) {
-            self.reconnect = None;
+        if let Some(Poll::Ready(addresses)) = self.redial.as_mut().map(|r| r.poll_unpin(cx)) {
+            self.redial = None;
             for addr in addresses {
                 self.events.push_front(ToSwarm::Dial { opts: addr.into() });
             }
         }
 
+        while let Poll::Ready(Some(result)) = self.in_flight.poll_next_unpin(cx) {
+            self.on_send_result(result);
+        }
+
+        if let Some(Poll::Ready(())) = self.retry.as_mut().map(|r| r.poll_unpin(cx)) {
+            self.retry = None;
+            self.send_pending();
+        }
+
         if let Some(event) = self.events.pop_front() {
             return Poll::Ready(event);
         }
@@ -26,13 +26,33 @@ use tokio::sync::mpsc::error::SendError;
 use tokio::sync::{mpsc, oneshot};
 use tokio::{select, task, task::JoinHandle};
 
-use fluence_libp2p::{build_transport, Transport};
+use fluence_libp2p::{
+    build_transport, with_fault_injection, FaultConfig, FaultInjector, Transport,
+};
 use particle_protocol::{Particle, ProtocolConfig};
 
 use crate::api::ParticleApi;
 use crate::behaviour::FluenceClientBehaviourEvent;
 use crate::{behaviour::FluenceClientBehaviour, ClientEvent};
 
+/// How the client recovers when the connection with the relay is lost
+#[derive(Debug, Clone)]
+pub struct ReconnectConfig {
+    /// Delay before redialing the relay after a failed dial, and before resending failed particles
+    pub delay: Duration,
+    /// Max number of particles kept while the relay is unreachable, the oldest ones are dropped
+    pub max_pending: usize,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            delay: Duration::from_secs(1),
+            max_pending: 1024,
+        }
+    }
+}
+
 #[derive(Debug)]
 struct Command {
     node: PeerId,
@@ -51,6 +71,9 @@ pub struct Client {
     client_inlet: mpsc::Receiver<ClientEvent>,
     stop_outlet: oneshot::Sender<()>,
     pub(crate) fetched: Vec<Particle>,
+    /// Network faults injected into the connection with the relay
+    #[derivative(Debug = "ignore")]
+    pub faults: FaultInjector,
 }
 
 impl Client {
@@ -70,6 +93,7 @@ impl Client {
             client_inlet,
             stop_outlet,
             fetched: vec![],
+            faults: <_>::default(),
         }
     }
 
@@ -95,6 +119,15 @@ impl Client {
         self.key_pair.sign(bytes).expect("signing error")
     }
 
+    /// Simulates a bad network: streams opened afterwards suffer the faults of `config`
+    pub fn inject_faults(&self, config: FaultConfig) {
+        self.faults.set_default(Some(config));
+    }
+
+    pub fn clear_faults(&self) {
+        self.faults.clear();
+    }
+
     fn dial(
         &self,
         node: Multiaddr,
@@ -102,13 +135,16 @@ impl Client {
         transport_timeout: Duration,
         idle_connection_timeout: Duration,
         protocol_config: ProtocolConfig,
+        reconnect: Option<ReconnectConfig>,
     ) -> Result<Swarm<FluenceClientBehaviour>, Box<dyn Error>> {
         let mut swarm = {
             let public_key = self.key_pair.public();
-            let behaviour = FluenceClientBehaviour::new(protocol_config, public_key.into());
+            let behaviour =
+                FluenceClientBehaviour::new(protocol_config, public_key.into(), reconnect);
 
             let kp = self.key_pair.clone().into();
-            let transport = build_transport(transport, &kp, transport_timeout);
+            let transport = build_transport(transport, &kp, transport_timeout, None);
+            let transport = with_fault_injection(transport, self.faults.clone());
             SwarmBuilder::with_existing_identity(kp)
                 .with_tokio()
                 .with_other_transport(|_| transport)?
@@ -139,15 +175,19 @@ impl Client {
             None,
             transport_timeout,
             idle_connection_timeout,
+            Some(ReconnectConfig::default()),
         )
     }
 
+    /// Connects to `relay`. If `reconnect` is set, the relay is redialed after the connection
+    /// is lost and particles sent meanwhile are delivered once it's reestablished.
     pub fn connect_with(
         relay: Multiaddr,
         transport: Transport,
         key_pair: Option<KeyPair>,
         transport_timeout: Duration,
         idle_connection_timeout: Duration,
+        reconnect: Option<ReconnectConfig>,
     ) -> Result<(Client, JoinHandle<()>), Box<dyn Error>> {
         let (client_outlet, client_inlet) = mpsc::channel(128);
         let (relay_outlet, mut relay_inlet) = mpsc::channel(128);
@@ -162,6 +202,7 @@ impl Client {
             transport_timeout,
             idle_connection_timeout,
             protocol_config,
+            reconnect,
         )?;
         let mut stop_inlet = Some(stop_inlet);
 
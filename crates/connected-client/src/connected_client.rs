@@ -31,7 +31,7 @@ use test_constants::{
     IDLE_CONNECTION_TIMEOUT, PARTICLE_TTL, SHORT_TIMEOUT, TIMEOUT, TRANSPORT_TIMEOUT,
 };
 
-use crate::client::Client;
+use crate::client::{Client, ReconnectConfig};
 use crate::event::ClientEvent;
 
 #[allow(clippy::upper_case_acronyms)]
@@ -99,6 +99,23 @@ impl ConnectedClient {
             timeout,
             idle_connection_timeout,
             particle_ttl,
+            Some(ReconnectConfig::default()),
+        )
+        .await
+    }
+
+    /// Connects to the node, `reconnect` tells whether and how to recover from connection loss
+    pub async fn connect_with_reconnect(
+        node_address: Multiaddr,
+        reconnect: Option<ReconnectConfig>,
+    ) -> Result<Self> {
+        Self::connect_with_timeout(
+            node_address,
+            None,
+            TRANSPORT_TIMEOUT,
+            IDLE_CONNECTION_TIMEOUT,
+            None,
+            reconnect,
         )
         .await
     }
@@ -113,6 +130,7 @@ impl ConnectedClient {
             TRANSPORT_TIMEOUT,
             IDLE_CONNECTION_TIMEOUT,
             None,
+            Some(ReconnectConfig::default()),
         )
         .await
     }
@@ -123,6 +141,7 @@ impl ConnectedClient {
         timeout: Duration,
         idle_connection_timeout: Duration,
         particle_ttl: Option<Duration>,
+        reconnect: Option<ReconnectConfig>,
     ) -> Result<Self> {
         use core::result::Result;
         use std::io::{Error, ErrorKind};
@@ -135,6 +154,7 @@ impl ConnectedClient {
                 key_pair.map(Into::into),
                 timeout,
                 idle_connection_timeout,
+                reconnect,
             )
             .expect("sender connected");
             let result: Result<_, Error> = if let Some(ClientEvent::NewConnection {
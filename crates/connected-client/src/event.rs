@@ -16,7 +16,7 @@
 
 use libp2p::core::Multiaddr;
 use libp2p::PeerId;
-use particle_protocol::Particle;
+use particle_protocol::{Contact, Particle, RejectionNotice};
 
 #[derive(Debug)]
 pub enum ClientEvent {
@@ -28,4 +28,14 @@ pub enum ClientEvent {
         peer_id: PeerId,
         multiaddr: Multiaddr,
     },
+    /// Node is shutting down and suggests `relays` to reconnect to
+    Goodbye {
+        peer_id: PeerId,
+        relays: Vec<Contact>,
+    },
+    /// Error particle that tells why a node refused a particle of the client
+    Rejected {
+        peer_id: PeerId,
+        notice: RejectionNotice,
+    },
 }
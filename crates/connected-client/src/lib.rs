@@ -33,6 +33,7 @@ mod command;
 mod connected_client;
 mod event;
 
+pub use crate::client::ReconnectConfig;
 pub use crate::connected_client::ConnectedClient;
 pub use command::ClientCommand;
 pub use event::ClientEvent;
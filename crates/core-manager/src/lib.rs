@@ -4,6 +4,7 @@ extern crate core;
 mod core_range;
 pub mod errors;
 pub mod manager;
+mod numa;
 pub mod types;
 
 pub use ccp_shared::types::CUID;
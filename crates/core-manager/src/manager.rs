@@ -1,4 +1,5 @@
-use std::collections::{BTreeSet, HashMap};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs::File;
 use std::hash::BuildHasherDefault;
 use std::io::Write;
@@ -21,7 +22,8 @@ use tokio_stream::wrappers::ReceiverStream;
 
 use crate::core_range::CoreRange;
 use crate::errors::{AcquireError, CreateError, LoadingError, PersistError};
-use crate::types::{AcquireRequest, Assignment, WorkType};
+use crate::numa::numa_nodes;
+use crate::types::{AcquireRequest, Assignment, CoreInfo, NumaNodeId, WorkType};
 
 type Map<K, V> = HashMap<K, V, BuildHasherDefault<FxHasher>>;
 type MultiMap<K, V> = multimap::MultiMap<K, V, BuildHasherDefault<FxHasher>>;
@@ -44,6 +46,9 @@ type BiMap<K, V> =
 /// - `get_system_cpu_assignment() -> Assignment`:
 ///   Retrieves the system's CPU assignment, including physical and logical core IDs.
 ///
+/// - `get_core_map() -> Vec<CoreInfo>`:
+///   Retrieves all managed physical cores with their NUMA nodes and assigned units.
+///
 /// - `persist() -> Result<(), PersistError>`:
 ///   Persists the current state of the core manager to an external storage location.
 ///
@@ -88,6 +93,8 @@ pub trait CoreManagerFunctions {
 
     fn get_system_cpu_assignment(&self) -> Assignment;
 
+    fn get_core_map(&self) -> Vec<CoreInfo>;
+
     fn persist(&self) -> Result<(), PersistError>;
 }
 
@@ -127,7 +134,8 @@ impl PersistentCoreManager {
             if config_range == loaded_range
                 && persistent_state.system_cores.len() == system_cpu_count
             {
-                let state: CoreManagerState = persistent_state.into();
+                let mut state: CoreManagerState = persistent_state.into();
+                state.numa_nodes = physical_numa_nodes(&state.cores_mapping);
                 Ok(Self::make_instance_with_task(file_path, state))
             } else {
                 tracing::warn!(target: "core-manager", "The initial config has been changed. Ignoring the previous state");
@@ -215,7 +223,9 @@ impl PersistentCoreManager {
         let type_mapping =
             Map::with_capacity_and_hasher(available_core_count, FxBuildHasher::default());
 
+        let numa_nodes = physical_numa_nodes(&cores_mapping);
         let inner_state = CoreManagerState {
+            numa_nodes,
             cores_mapping,
             system_cores,
             available_cores,
@@ -292,6 +302,8 @@ impl PersistenceTask {
 struct CoreManagerState {
     // mapping between physical and logical cores
     cores_mapping: MultiMap<PhysicalCoreId, LogicalCoreId>,
+    // NUMA nodes of physical cores, read from the OS on start
+    numa_nodes: Map<PhysicalCoreId, NumaNodeId>,
     // allocated system cores
     system_cores: BTreeSet<PhysicalCoreId>,
     // free physical cores
@@ -314,7 +326,12 @@ struct PersistentCoreManagerState {
 impl From<&CoreManagerState> for PersistentCoreManagerState {
     fn from(value: &CoreManagerState) -> Self {
         Self {
-            cores_mapping: value.cores_mapping.iter().map(|(k, v)| (*k, *v)).collect(),
+            // all hyperthread siblings of a physical core
+            cores_mapping: value
+                .cores_mapping
+                .flat_iter()
+                .map(|(k, v)| (*k, *v))
+                .collect(),
             system_cores: value.system_cores.iter().cloned().collect(),
             available_cores: value.available_cores.iter().cloned().collect(),
             unit_id_mapping: value
@@ -335,6 +352,7 @@ impl From<PersistentCoreManagerState> for CoreManagerState {
     fn from(value: PersistentCoreManagerState) -> Self {
         Self {
             cores_mapping: value.cores_mapping.into_iter().collect(),
+            numa_nodes: Map::default(),
             system_cores: value.system_cores.into_iter().collect(),
             available_cores: value.available_cores.into_iter().collect(),
             unit_id_mapping: value.unit_id_mapping.into_iter().collect(),
@@ -343,6 +361,89 @@ impl From<PersistentCoreManagerState> for CoreManagerState {
     }
 }
 
+impl CoreManagerState {
+    fn numa_node(&self, physical_core_id: &PhysicalCoreId) -> NumaNodeId {
+        // all cores are on the same node if NUMA topology is unknown
+        self.numa_nodes
+            .get(physical_core_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Picks `count` available cores for new units of a worker that already has `assigned` units.
+    /// NUMA nodes are preferred in this order:
+    /// - nodes with cores of the worker, so the worker's cores share memory
+    /// - nodes that fit all the remaining cores
+    /// - nodes with fewer units of another work type, so capacity commitment proving
+    ///   doesn't compete with deal workers for caches and memory bandwidth
+    /// - nodes with more available cores
+    ///
+    /// Hyperthread siblings always go along with their physical core, so units never share one.
+    fn pick_cores(
+        &self,
+        assigned: &[CUID],
+        count: usize,
+        work_type: &WorkType,
+    ) -> Option<Vec<PhysicalCoreId>> {
+        if count > self.available_cores.len() {
+            return None;
+        }
+
+        let mut available: BTreeMap<NumaNodeId, Vec<PhysicalCoreId>> = BTreeMap::new();
+        for core_id in &self.available_cores {
+            available
+                .entry(self.numa_node(core_id))
+                .or_default()
+                .push(*core_id);
+        }
+        let mut other_type_units: BTreeMap<NumaNodeId, usize> = BTreeMap::new();
+        for (core_id, unit_id) in self.unit_id_mapping.iter() {
+            if self.work_type_mapping.get(unit_id) != Some(work_type) {
+                *other_type_units.entry(self.numa_node(core_id)).or_default() += 1;
+            }
+        }
+        let mut worker_nodes: BTreeSet<NumaNodeId> = assigned
+            .iter()
+            .filter_map(|unit_id| self.unit_id_mapping.get_by_right(unit_id))
+            .map(|core_id| self.numa_node(core_id))
+            .collect();
+
+        let mut picked = Vec::with_capacity(count);
+        while picked.len() < count {
+            let remaining = count - picked.len();
+            let (node, cores) = available
+                .iter_mut()
+                .filter(|(_, cores)| !cores.is_empty())
+                .max_by_key(|(node, cores)| {
+                    (
+                        worker_nodes.contains(*node),
+                        cores.len() >= remaining,
+                        Reverse(other_type_units.get(*node).cloned().unwrap_or_default()),
+                        cores.len(),
+                        Reverse(**node),
+                    )
+                })?;
+            // the highest cores first, as the system cores are the lowest ones
+            picked.push(cores.pop()?);
+            worker_nodes.insert(*node);
+        }
+        Some(picked)
+    }
+}
+
+fn physical_numa_nodes(
+    cores_mapping: &MultiMap<PhysicalCoreId, LogicalCoreId>,
+) -> Map<PhysicalCoreId, NumaNodeId> {
+    let logical_nodes = numa_nodes();
+    cores_mapping
+        .flat_iter()
+        .filter_map(|(physical_core_id, logical_core_id)| {
+            let node = logical_nodes.get(logical_core_id)?;
+            Some((*physical_core_id, *node))
+        })
+        .collect()
+}
+
 impl CoreManagerFunctions for PersistentCoreManager {
     fn acquire_worker_core(
         &self,
@@ -352,36 +453,45 @@ impl CoreManagerFunctions for PersistentCoreManager {
         let mut result_physical_core_ids = BTreeSet::new();
         let mut result_logical_core_ids = BTreeSet::new();
         let worker_unit_type = assign_request.worker_type;
+
+        let mut assigned_unit_ids = vec![];
+        let mut new_unit_ids = vec![];
+        for unit_id in &assign_request.unit_ids {
+            if lock.unit_id_mapping.contains_right(unit_id) {
+                assigned_unit_ids.push(*unit_id);
+            } else if !new_unit_ids.contains(unit_id) {
+                new_unit_ids.push(*unit_id);
+            }
+        }
+        let core_ids = lock
+            .pick_cores(&assigned_unit_ids, new_unit_ids.len(), &worker_unit_type)
+            .ok_or_else(|| {
+                let current_assignment: Vec<(PhysicalCoreId, CUID)> =
+                    lock.unit_id_mapping.iter().map(|(k, v)| (*k, *v)).collect();
+                AcquireError::NotFoundAvailableCores { current_assignment }
+            })?;
+        for (unit_id, core_id) in new_unit_ids.into_iter().zip(core_ids) {
+            lock.available_cores.remove(&core_id);
+            lock.unit_id_mapping.insert(core_id, unit_id);
+        }
+
         for unit_id in assign_request.unit_ids {
-            let physical_core_id = lock.unit_id_mapping.get_by_right(&unit_id).cloned();
-            let physical_core_id = match physical_core_id {
-                None => {
-                    let core_id = lock.available_cores.pop_last().ok_or({
-                        let current_assignment: Vec<(PhysicalCoreId, CUID)> =
-                            lock.unit_id_mapping.iter().map(|(k, v)| (*k, *v)).collect();
-                        AcquireError::NotFoundAvailableCores { current_assignment }
-                    })?;
-                    lock.unit_id_mapping.insert(core_id, unit_id);
-                    lock.work_type_mapping
-                        .insert(unit_id, worker_unit_type.clone());
-                    core_id
-                }
-                Some(core_id) => {
-                    lock.work_type_mapping
-                        .insert(unit_id, worker_unit_type.clone());
-                    core_id
-                }
-            };
+            let physical_core_id = *lock
+                .unit_id_mapping
+                .get_by_right(&unit_id)
+                .expect("Unexpected state. Should not be empty never");
+            lock.work_type_mapping
+                .insert(unit_id, worker_unit_type.clone());
             result_physical_core_ids.insert(physical_core_id);
 
-            let physical_core_ids = lock
+            let logical_core_ids = lock
                 .cores_mapping
                 .get_vec(&physical_core_id)
                 .cloned()
                 .expect("Unexpected state. Should not be empty never");
 
-            for physical_core_id in physical_core_ids {
-                result_logical_core_ids.insert(physical_core_id);
+            for logical_core_id in logical_core_ids {
+                result_logical_core_ids.insert(logical_core_id);
             }
         }
 
@@ -424,6 +534,37 @@ impl CoreManagerFunctions for PersistentCoreManager {
         }
     }
 
+    fn get_core_map(&self) -> Vec<CoreInfo> {
+        let lock = self.state.read();
+        let mut physical_core_ids: BTreeSet<PhysicalCoreId> =
+            lock.cores_mapping.keys().cloned().collect();
+        physical_core_ids.extend(lock.system_cores.iter().cloned());
+        physical_core_ids
+            .into_iter()
+            .map(|physical_core_id| {
+                let unit_id = lock.unit_id_mapping.get_by_left(&physical_core_id).cloned();
+                let work_type = unit_id
+                    .as_ref()
+                    .and_then(|unit_id| lock.work_type_mapping.get(unit_id))
+                    .cloned();
+                CoreInfo {
+                    physical_core_id,
+                    logical_core_ids: lock
+                        .cores_mapping
+                        .get_vec(&physical_core_id)
+                        .into_iter()
+                        .flatten()
+                        .cloned()
+                        .collect(),
+                    numa_node: lock.numa_node(&physical_core_id),
+                    system: lock.system_cores.contains(&physical_core_id),
+                    unit_id,
+                    work_type,
+                }
+            })
+            .collect()
+    }
+
     fn persist(&self) -> Result<(), PersistError> {
         let lock = self.state.read();
         let inner_state = lock.deref();
@@ -483,6 +624,10 @@ impl CoreManagerFunctions for DummyCoreManager {
     fn get_system_cpu_assignment(&self) -> Assignment {
         self.all_cores()
     }
+
+    fn get_core_map(&self) -> Vec<CoreInfo> {
+        vec![]
+    }
     fn persist(&self) -> Result<(), PersistError> {
         Ok(())
     }
@@ -490,15 +635,138 @@ impl CoreManagerFunctions for DummyCoreManager {
 
 #[cfg(test)]
 mod tests {
-    use crate::manager::{AcquireRequest, CoreManagerFunctions, PersistentCoreManager, WorkType};
+    use crate::manager::{
+        AcquireRequest, BiMap, CoreManagerFunctions, CoreManagerState, Map, MultiMap,
+        PersistentCoreManager, WorkType,
+    };
+    use crate::types::NumaNodeId;
     use crate::CoreRange;
     use ccp_shared::types::CUID;
+    use cpu_utils::{LogicalCoreId, PhysicalCoreId};
     use hex::FromHex;
 
     fn cores_exists() -> bool {
         num_cpus::get_physical() >= 4
     }
 
+    fn unit(id: u8) -> CUID {
+        <CUID>::from_hex(format!("{id:064x}")).unwrap()
+    }
+
+    /// Physical cores on the given NUMA nodes, each with two hyperthreads
+    fn numa_state(nodes: &[NumaNodeId]) -> CoreManagerState {
+        let mut state = CoreManagerState {
+            cores_mapping: MultiMap::default(),
+            numa_nodes: Map::default(),
+            system_cores: Default::default(),
+            available_cores: Default::default(),
+            unit_id_mapping: BiMap::default(),
+            work_type_mapping: Map::default(),
+        };
+        for (id, node) in nodes.iter().enumerate() {
+            let core_id = PhysicalCoreId::from(id as u32);
+            state
+                .cores_mapping
+                .insert(core_id, LogicalCoreId::from(2 * id as u32));
+            state
+                .cores_mapping
+                .insert(core_id, LogicalCoreId::from(2 * id as u32 + 1));
+            state.numa_nodes.insert(core_id, *node);
+            state.available_cores.insert(core_id);
+        }
+        state
+    }
+
+    /// Assigns cores to new units like `acquire_worker_core`, returns NUMA nodes of the cores
+    fn assign(
+        state: &mut CoreManagerState,
+        assigned: &[CUID],
+        units: &[CUID],
+        work_type: WorkType,
+    ) -> Vec<NumaNodeId> {
+        let core_ids = state
+            .pick_cores(assigned, units.len(), &work_type)
+            .expect("Not enough cores");
+        let mut nodes = vec![];
+        for (unit_id, core_id) in units.iter().zip(core_ids) {
+            state.available_cores.remove(&core_id);
+            state.unit_id_mapping.insert(core_id, *unit_id);
+            state.work_type_mapping.insert(*unit_id, work_type.clone());
+            nodes.push(state.numa_node(&core_id));
+        }
+        nodes
+    }
+
+    #[test]
+    fn test_pick_numa_cores() {
+        let mut state = numa_state(&[0, 0, 0, 0, 1, 1, 1, 1]);
+
+        // a worker fits one node
+        let cc = [unit(1), unit(2), unit(3)];
+        assert_eq!(
+            assign(&mut state, &[], &cc, WorkType::CapacityCommitment),
+            vec![0, 0, 0]
+        );
+        // the rest of the capacity commitment node doesn't fit the deal
+        let deal = [unit(4), unit(5)];
+        assert_eq!(assign(&mut state, &[], &deal, WorkType::Deal), vec![1, 1]);
+        // new units of a worker are colocated with its other units
+        assert_eq!(
+            assign(&mut state, &cc, &[unit(6)], WorkType::CapacityCommitment),
+            vec![0]
+        );
+        assert_eq!(
+            assign(&mut state, &deal, &[unit(7)], WorkType::Deal),
+            vec![1]
+        );
+        assert_eq!(
+            assign(&mut state, &[], &[unit(8)], WorkType::CapacityCommitment),
+            vec![1]
+        );
+        assert!(state
+            .pick_cores(&[], 1, &WorkType::CapacityCommitment)
+            .is_none());
+    }
+
+    #[test]
+    fn test_pick_cores_away_from_other_work_type() {
+        let mut state = numa_state(&[0, 0, 0, 1, 1, 1]);
+
+        assert_eq!(
+            assign(&mut state, &[], &[unit(1)], WorkType::CapacityCommitment),
+            vec![0]
+        );
+        // the other node is free from capacity commitment proving
+        assert_eq!(assign(&mut state, &[], &[unit(2)], WorkType::Deal), vec![1]);
+        assert_eq!(
+            assign(&mut state, &[], &[unit(3)], WorkType::CapacityCommitment),
+            vec![0]
+        );
+        // a worker spans nodes only if no node fits it
+        let nodes = assign(
+            &mut state,
+            &[],
+            &[unit(4), unit(5), unit(6)],
+            WorkType::Deal,
+        );
+        assert_eq!(nodes, vec![1, 1, 0]);
+    }
+
+    #[test]
+    fn test_pick_cores_without_numa() {
+        let mut state = numa_state(&[0, 0, 0, 0]);
+        state.numa_nodes.clear();
+
+        // the highest cores first as before NUMA awareness
+        let core_ids = state
+            .pick_cores(&[], 2, &WorkType::CapacityCommitment)
+            .unwrap();
+        assert_eq!(
+            core_ids,
+            vec![PhysicalCoreId::from(3), PhysicalCoreId::from(2)]
+        );
+    }
+
     #[test]
     fn test_acquire_and_switch() {
         if cores_exists() {
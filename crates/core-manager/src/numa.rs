@@ -0,0 +1,75 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::str::FromStr;
+
+use cpu_utils::LogicalCoreId;
+
+use crate::core_range::CoreRange;
+use crate::types::NumaNodeId;
+
+const SYSFS_NODES_DIR: &str = "/sys/devices/system/node";
+
+/// Reads NUMA nodes of logical cores from sysfs.
+/// Returns an empty map if the topology isn't available, e.g. not on Linux
+pub(crate) fn numa_nodes() -> BTreeMap<LogicalCoreId, NumaNodeId> {
+    read_numa_nodes(Path::new(SYSFS_NODES_DIR))
+}
+
+fn read_numa_nodes(dir: &Path) -> BTreeMap<LogicalCoreId, NumaNodeId> {
+    let mut result = BTreeMap::new();
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            tracing::debug!(target: "core-manager", "NUMA topology is not available: {err}");
+            return result;
+        }
+    };
+    for entry in entries.flatten() {
+        let file_name = entry.file_name();
+        let node_id = file_name
+            .to_str()
+            .and_then(|name| name.strip_prefix("node"))
+            .and_then(|id| id.parse::<NumaNodeId>().ok());
+        let Some(node_id) = node_id else {
+            continue;
+        };
+        // the same format as the core range, e.g. "0-7,16-23", and empty for memory-only nodes
+        let cpus = std::fs::read_to_string(entry.path().join("cpulist"))
+            .ok()
+            .and_then(|cpus| CoreRange::from_str(&cpus).ok());
+        for cpu in cpus.iter().flat_map(|cpus| cpus.0.iter()) {
+            result.insert(LogicalCoreId::from(cpu as u32), node_id);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_sysfs_nodes() {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let node = |name: &str, cpus: &str| {
+            let node_dir = dir.path().join(name);
+            std::fs::create_dir(&node_dir).unwrap();
+            std::fs::write(node_dir.join("cpulist"), cpus).unwrap();
+        };
+        node("node0", "0-1,4\n");
+        node("node1", "2-3,5\n");
+        node("node2", "\n");
+        node("possible", "0-2\n");
+
+        let nodes = read_numa_nodes(dir.path());
+        let node_of = |cpu: u32| nodes.get(&LogicalCoreId::from(cpu)).copied();
+        assert_eq!(nodes.len(), 6);
+        assert_eq!(node_of(0), Some(0));
+        assert_eq!(node_of(4), Some(0));
+        assert_eq!(node_of(2), Some(1));
+        assert_eq!(node_of(5), Some(1));
+        assert_eq!(node_of(6), None);
+
+        assert!(read_numa_nodes(&dir.path().join("missing")).is_empty());
+    }
+}
@@ -4,6 +4,8 @@ use cpu_utils::{LogicalCoreId, PhysicalCoreId};
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeSet;
 
+pub type NumaNodeId = u32;
+
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
 pub enum WorkType {
     CapacityCommitment,
@@ -35,3 +37,15 @@ impl Assignment {
         pin_current_thread_to_cpuset(self.logical_core_ids.iter().cloned());
     }
 }
+
+/// A physical core with its hyperthread siblings and the unit it is assigned to
+#[derive(Debug, Clone, Serialize)]
+pub struct CoreInfo {
+    pub physical_core_id: PhysicalCoreId,
+    pub logical_core_ids: BTreeSet<LogicalCoreId>,
+    pub numa_node: NumaNodeId,
+    /// Whether the core is reserved for the node itself
+    pub system: bool,
+    pub unit_id: Option<CUID>,
+    pub work_type: Option<WorkType>,
+}
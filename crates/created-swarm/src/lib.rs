@@ -27,10 +27,14 @@
     unreachable_patterns
 )]
 
+mod restart;
 mod services;
+mod snapshot;
 mod swarm;
 
+pub use crate::restart::*;
 pub use crate::services::*;
+pub use crate::snapshot::*;
 pub use crate::swarm::*;
 
 pub use server_config::system_services_config;
@@ -0,0 +1,81 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::convert::identity;
+
+use futures::FutureExt;
+use libp2p::{Multiaddr, PeerId};
+
+use crate::{create_swarm, make_swarms_with, CreatedSwarm, SwarmConfig};
+
+/// How [CreatedSwarm::stop] stops the node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopMode {
+    /// Connections are drained and node state is persisted, as on SIGTERM
+    Graceful,
+    /// Node stops as if it crashed, nothing is drained or persisted
+    Abrupt,
+}
+
+/// Node stopped by [CreatedSwarm::stop]. Its persistent dir is kept, so it can be started again.
+pub struct StoppedSwarm {
+    pub peer_id: PeerId,
+    pub multiaddr: Multiaddr,
+    config: SwarmConfig,
+}
+
+impl CreatedSwarm {
+    /// Stops the node and waits until it's stopped
+    pub async fn stop(self, mode: StopMode) -> StoppedSwarm {
+        let outlet = match mode {
+            StopMode::Graceful => self.exit_outlet,
+            StopMode::Abrupt => self.kill_outlet,
+        };
+        outlet.send(()).ok();
+        self.stopped.await.expect("node task failed");
+
+        StoppedSwarm {
+            peer_id: self.peer_id,
+            multiaddr: self.multiaddr,
+            config: self.swarm_config,
+        }
+    }
+
+    /// Stops the node and starts it again, see [StoppedSwarm::start]
+    pub async fn restart(self, mode: StopMode) -> CreatedSwarm {
+        self.stop(mode).await.start().await
+    }
+}
+
+impl StoppedSwarm {
+    /// Starts the node with the same keys, address and persistent dir and waits until it
+    /// rejoins the network, i.e. is healthy and connected to its bootstrap nodes.
+    /// The node is started with AquaVM even if it was created with a mocked one.
+    pub async fn start(self) -> CreatedSwarm {
+        let mut config = Some(self.config);
+        let multiaddr = self.multiaddr;
+        let mut swarms = make_swarms_with(
+            1,
+            move |_, _| create_swarm(config.take().expect("node is started once")).boxed(),
+            move || multiaddr.clone(),
+            identity,
+            true,
+        )
+        .await;
+
+        swarms.pop().expect("node is started")
+    }
+}
@@ -0,0 +1,158 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use fluence_keypair::KeyPair;
+use server_config::persistent_dir;
+use tempfile::TempDir;
+use tokio::sync::Mutex;
+
+use crate::{make_swarms_with_cfg, CreatedSwarm, SwarmConfig};
+
+/// Snapshots created by [cached_snapshot], kept until the test process exits
+static SNAPSHOTS: Mutex<BTreeMap<(String, usize), SwarmSnapshot>> =
+    Mutex::const_new(BTreeMap::new());
+
+struct NodeSnapshot {
+    /// Copy of the persistent dir of the initialized node
+    dir: TempDir,
+    keypair: KeyPair,
+    builtins_keypair: KeyPair,
+}
+
+/// Persistent dirs of fully initialized nodes, i.e. with system services deployed
+/// and their modules stored. Nodes started from a snapshot load that state instead of
+/// deploying everything from scratch, so they start much faster.
+///
+/// Nodes created from the same snapshot share peer ids, so they must not be connected.
+#[derive(Clone)]
+pub struct SwarmSnapshot {
+    nodes: Arc<Vec<NodeSnapshot>>,
+}
+
+impl SwarmSnapshot {
+    /// Starts `n` nodes configured by `update_cfg`, waits until they are initialized
+    /// and stops them, keeping their persistent dirs
+    pub async fn create<F>(n: usize, update_cfg: F) -> eyre::Result<Self>
+    where
+        F: (FnMut(SwarmConfig) -> SwarmConfig) + 'static + Send,
+    {
+        let swarms = make_swarms_with_cfg(n, update_cfg).await;
+        let nodes = swarms
+            .into_iter()
+            .map(NodeSnapshot::take)
+            .collect::<eyre::Result<_>>()?;
+
+        Ok(Self {
+            nodes: Arc::new(nodes),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Makes `config` start from the state of the `index`-th node of the snapshot
+    pub fn apply(&self, index: usize, mut config: SwarmConfig) -> eyre::Result<SwarmConfig> {
+        let node = self
+            .nodes
+            .get(index)
+            .ok_or_else(|| eyre::eyre!("snapshot has only {} nodes", self.nodes.len()))?;
+
+        copy_files(node.dir.path(), &persistent_dir(config.tmp_dir.path()))?;
+        config.keypair = node.keypair.clone();
+        config.builtins_keypair = node.builtins_keypair.clone();
+        Ok(config)
+    }
+}
+
+impl NodeSnapshot {
+    fn take(swarm: CreatedSwarm) -> eyre::Result<Self> {
+        // the node is initialized once it's started, so nothing is written to the dir after that
+        swarm.exit_outlet.send(()).ok();
+
+        let dir = tempfile::tempdir()?;
+        copy_files(&persistent_dir(swarm.tmp_dir.path()), dir.path())?;
+        Ok(Self {
+            dir,
+            keypair: swarm.config.node_config.root_key_pair.clone(),
+            builtins_keypair: swarm.config.node_config.builtins_key_pair.clone(),
+        })
+    }
+}
+
+/// Same as [fs_utils::copy_dir_all], but skips sockets and other special files
+fn copy_files(src: &Path, dst: &Path) -> eyre::Result<()> {
+    fs::create_dir_all(dst)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            copy_files(&entry.path(), &dst.join(entry.file_name()))?;
+        } else if file_type.is_file() {
+            fs::copy(entry.path(), dst.join(entry.file_name()))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Snapshot of `n` nodes created with `update_cfg` on the first call with `key`.
+/// Next calls with the same `key` and `n` return the same snapshot.
+pub async fn cached_snapshot<F>(key: &str, n: usize, update_cfg: F) -> SwarmSnapshot
+where
+    F: (FnMut(SwarmConfig) -> SwarmConfig) + 'static + Send,
+{
+    let mut snapshots = SNAPSHOTS.lock().await;
+    let key = (key.to_string(), n);
+    if let Some(snapshot) = snapshots.get(&key) {
+        return snapshot.clone();
+    }
+
+    let snapshot = SwarmSnapshot::create(n, update_cfg)
+        .await
+        .expect("create swarm snapshot");
+    snapshots.insert(key, snapshot.clone());
+    snapshot
+}
+
+/// Same as [make_swarms_with_cfg], but nodes start from `snapshot`
+pub async fn make_swarms_from_snapshot<F>(
+    snapshot: &SwarmSnapshot,
+    mut update_cfg: F,
+) -> Vec<CreatedSwarm>
+where
+    F: (FnMut(SwarmConfig) -> SwarmConfig) + 'static + Send,
+{
+    let snapshot = snapshot.clone();
+    let mut index = 0;
+    make_swarms_with_cfg(snapshot.len(), move |cfg| {
+        let cfg = snapshot
+            .apply(index, update_cfg(cfg))
+            .expect("apply swarm snapshot");
+        index += 1;
+        cfg
+    })
+    .await
+}
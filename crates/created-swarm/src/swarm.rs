@@ -33,21 +33,24 @@ use aquamarine::{AVMRunner, AquamarineApi, VmConfig};
 use aquamarine::{AquaRuntime, DataStoreConfig};
 use base64::{engine::general_purpose::STANDARD as base64, Engine};
 use cid_utils::Hash;
+use connection_pool::{DialBackoffConfig, ParticleSignatureConfig};
 use core_manager::manager::DummyCoreManager;
 use fluence_libp2p::random_multiaddr::{create_memory_maddr, create_tcp_maddr};
-use fluence_libp2p::Transport;
+use fluence_libp2p::{FaultInjector, Transport};
 use fs_utils::to_abs_path;
 use futures::future::BoxFuture;
 use futures::stream::iter;
+use mock_chain::MockChain;
 use nox::{Connectivity, Node};
 use particle_protocol::ProtocolConfig;
 use server_config::{
-    persistent_dir, system_services_config, BootstrapConfig, ChainConfig, ResolvedConfig,
-    UnresolvedConfig,
+    persistent_dir, system_services_config, BootstrapConfig, ChainConfig, ChainListenerConfig,
+    ParticleLimitsConfig, ResolvedConfig, UnresolvedConfig,
 };
 use tempfile::TempDir;
 use test_constants::{EXECUTION_TIMEOUT, IDLE_CONNECTION_TIMEOUT, TRANSPORT_TIMEOUT};
 use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
 use toy_vms::EasyVM;
 use tracing::{Instrument, Span};
 
@@ -77,7 +80,14 @@ pub struct CreatedSwarm {
     pub connectivity: Connectivity,
     #[derivative(Debug = "ignore")]
     pub aquamarine_api: AquamarineApi,
+    /// Network faults injected into connections of the node, by remote peer id
+    #[derivative(Debug = "ignore")]
+    pub faults: FaultInjector,
     http_listen_addr: SocketAddr,
+    pub(crate) kill_outlet: oneshot::Sender<()>,
+    pub(crate) stopped: JoinHandle<()>,
+    /// Config the node was created with, used to restart it
+    pub(crate) swarm_config: SwarmConfig,
 }
 
 pub async fn make_swarms(n: usize) -> Vec<CreatedSwarm> {
@@ -98,6 +108,79 @@ where
     .await
 }
 
+type ConfigUpdate = Box<dyn FnOnce(SwarmConfig) -> SwarmConfig + Send>;
+
+/// Creates swarms whose nodes can be configured differently, e.g. to test nodes
+/// with different subsystems, limits or versions working together
+pub struct SwarmsBuilder {
+    n: usize,
+    common: Box<dyn FnMut(SwarmConfig) -> SwarmConfig + Send>,
+    nodes: HashMap<usize, Vec<ConfigUpdate>>,
+}
+
+impl SwarmsBuilder {
+    pub fn new(n: usize) -> Self {
+        Self {
+            n,
+            common: Box::new(identity),
+            nodes: <_>::default(),
+        }
+    }
+
+    /// Config update applied to all nodes, before the updates of individual nodes
+    pub fn with_config<F>(mut self, update_cfg: F) -> Self
+    where
+        F: (FnMut(SwarmConfig) -> SwarmConfig) + 'static + Send,
+    {
+        self.common = Box::new(update_cfg);
+        self
+    }
+
+    /// Config update applied only to the `index`-th node, updates of the same node are chained
+    pub fn with_node_config<F>(mut self, index: usize, update_cfg: F) -> Self
+    where
+        F: (FnOnce(SwarmConfig) -> SwarmConfig) + 'static + Send,
+    {
+        assert!(
+            index < self.n,
+            "node index {index} is out of range, swarm has {} nodes",
+            self.n
+        );
+        self.nodes
+            .entry(index)
+            .or_default()
+            .push(Box::new(update_cfg));
+        self
+    }
+
+    /// Creates the nodes, the returned swarms are ordered by node index
+    pub async fn build(self) -> Vec<CreatedSwarm> {
+        let Self {
+            n,
+            mut common,
+            mut nodes,
+        } = self;
+        let mut index = 0;
+        make_swarms_with(
+            n,
+            move |bs, maddr| {
+                let cfg = common(SwarmConfig::new(bs, maddr));
+                let cfg = nodes
+                    .remove(&index)
+                    .into_iter()
+                    .flatten()
+                    .fold(cfg, |cfg, update| update(cfg));
+                index += 1;
+                create_swarm(cfg).boxed()
+            },
+            create_memory_maddr,
+            identity,
+            true,
+        )
+        .await
+    }
+}
+
 pub async fn make_swarms_with_transport_and_mocked_vm(
     n: usize,
     transport: Transport,
@@ -205,18 +288,23 @@ where
                 CreatedSwarm {
                     config: resolved_config,
                     peer_id,
-                    multiaddr: input_config.listen_on,
+                    multiaddr: input_config.listen_on.clone(),
                     tmp_dir: input_config.tmp_dir.clone(),
                     management_keypair,
                     exit_outlet: started_node.exit_outlet,
                     connectivity,
                     aquamarine_api,
+                    faults: input_config.faults.clone(),
                     http_listen_addr,
+                    kill_outlet: started_node.kill_outlet,
+                    stopped: started_node.stopped,
+                    swarm_config: input_config,
                 }
             }
             .boxed()
         })
-        .buffer_unordered(parallelism)
+        // keep the order of nodes, so configs applied by index match the returned swarms
+        .buffered(parallelism)
         .collect()
         .await;
 
@@ -276,7 +364,14 @@ pub struct SwarmConfig {
     pub http_port: u16,
     pub connector_api_endpoint: Option<String>,
     pub chain_config: Option<ChainConfig>,
+    pub chain_listener_config: Option<ChainListenerConfig>,
     pub cc_events_dir: Option<PathBuf>,
+    pub particle_limits: ParticleLimitsConfig,
+    pub particle_signatures: ParticleSignatureConfig,
+    /// Version the node reports, i.e. in identify
+    pub node_version: &'static str,
+    #[derivative(Debug = "ignore")]
+    pub faults: FaultInjector,
 }
 
 impl SwarmConfig {
@@ -305,9 +400,21 @@ impl SwarmConfig {
             http_port: 0,
             connector_api_endpoint: None,
             chain_config: None,
+            chain_listener_config: None,
             cc_events_dir: None,
+            particle_limits: <_>::default(),
+            particle_signatures: <_>::default(),
+            node_version: "some version",
+            faults: <_>::default(),
         }
     }
+
+    /// Connects chain connector and chain listener of the node to `chain`
+    pub fn with_mock_chain(mut self, chain: &MockChain) -> Self {
+        self.chain_config = Some(chain.chain_config());
+        self.chain_listener_config = Some(chain.listener_config());
+        self
+    }
 }
 
 pub struct BaseVmConfig {
@@ -389,13 +496,17 @@ pub async fn create_swarm_with_runtime<RT: AquaRuntime>(
         let mut resolved = node_config.resolve().expect("failed to resolve config");
 
         resolved.node_config.transport_config.transport = Transport::Memory;
+        resolved.node_config.fault_injector = Some(config.faults.clone());
         resolved.node_config.transport_config.socket_timeout = TRANSPORT_TIMEOUT;
         resolved.node_config.protocol_config =
             ProtocolConfig::new(TRANSPORT_TIMEOUT, TRANSPORT_TIMEOUT);
 
         resolved.node_config.bootstrap_nodes = config.bootstraps.clone();
         resolved.node_config.bootstrap_config = BootstrapConfig::zero();
+        resolved.node_config.dial_backoff = DialBackoffConfig::zero();
         resolved.node_config.bootstrap_frequency = 1;
+        resolved.node_config.particle_limits = config.particle_limits.clone();
+        resolved.node_config.particle_signatures = config.particle_signatures.clone();
 
         resolved.metrics_config.metrics_enabled = false;
 
@@ -434,6 +545,7 @@ pub async fn create_swarm_with_runtime<RT: AquaRuntime>(
             .to_peer_id();
         resolved.node_config.management_peer_id = management_peer_id;
         resolved.chain_config = config.chain_config.clone();
+        resolved.chain_listener_config = config.chain_listener_config.clone();
 
         let vm_config = vm_config(BaseVmConfig {
             peer_id,
@@ -455,7 +567,7 @@ pub async fn create_swarm_with_runtime<RT: AquaRuntime>(
             core_manager,
             vm_config,
             data_store_config,
-            "some version",
+            config.node_version,
             "some version",
             system_service_distros,
         );
@@ -0,0 +1,213 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tokio::sync::{mpsc, oneshot};
+
+enum Change {
+    Write(Vec<u8>),
+    Remove,
+}
+
+struct Op {
+    path: PathBuf,
+    change: Change,
+    committed: oneshot::Sender<io::Result<()>>,
+}
+
+/// Groups file writes into batches committed together.
+///
+/// Changes of the same file within a batch are coalesced, the last one wins.
+/// Every written file is synced before it atomically replaces the old one,
+/// and directories are synced once per batch, so a burst of writes doesn't turn into
+/// a storm of fsyncs. [BatchWriter::write] and [BatchWriter::remove] resolve once
+/// the batch with the change is committed, that's the durability point for the caller.
+#[derive(Clone, Debug)]
+pub struct BatchWriter {
+    ops: mpsc::UnboundedSender<Op>,
+}
+
+impl BatchWriter {
+    /// Spawns the task committing batches, a change waits at most `delay` for others to join its batch.
+    /// The task stops when all clones of the writer are dropped and pending changes are committed.
+    pub fn spawn(delay: Duration) -> Self {
+        let (ops, inlet) = mpsc::unbounded_channel();
+        tokio::spawn(run(inlet, delay));
+        Self { ops }
+    }
+
+    /// Replaces contents of the file at `path` with `contents`
+    pub async fn write(&self, path: PathBuf, contents: Vec<u8>) -> io::Result<()> {
+        self.submit(path, Change::Write(contents)).await
+    }
+
+    /// Removes the file at `path`, missing file isn't an error
+    pub async fn remove(&self, path: PathBuf) -> io::Result<()> {
+        self.submit(path, Change::Remove).await
+    }
+
+    async fn submit(&self, path: PathBuf, change: Change) -> io::Result<()> {
+        let (committed, result) = oneshot::channel();
+        let op = Op {
+            path,
+            change,
+            committed,
+        };
+        let stopped = || io::Error::new(io::ErrorKind::BrokenPipe, "batch writer is stopped");
+        self.ops.send(op).map_err(|_| stopped())?;
+        result.await.map_err(|_| stopped())?
+    }
+}
+
+async fn run(mut inlet: mpsc::UnboundedReceiver<Op>, delay: Duration) {
+    while let Some(op) = inlet.recv().await {
+        tokio::time::sleep(delay).await;
+        let mut batch = vec![op];
+        while let Ok(op) = inlet.try_recv() {
+            batch.push(op);
+        }
+
+        // the last change of every file wins, all its writers wait for it
+        let mut changes: HashMap<PathBuf, (Change, Vec<oneshot::Sender<io::Result<()>>>)> =
+            HashMap::with_capacity(batch.len());
+        for op in batch {
+            let entry = changes
+                .entry(op.path)
+                .or_insert_with(|| (Change::Remove, vec![]));
+            entry.0 = op.change;
+            entry.1.push(op.committed);
+        }
+
+        let (changes, waiters): (Vec<_>, Vec<_>) = changes
+            .into_iter()
+            .map(|(path, (change, waiters))| ((path, change), waiters))
+            .unzip();
+        let results = match tokio::task::spawn_blocking(move || commit(changes)).await {
+            Ok(results) => results,
+            Err(err) => {
+                tracing::error!("Batch commit has failed: {err}");
+                continue;
+            }
+        };
+
+        for (result, waiters) in results.into_iter().zip(waiters) {
+            for waiter in waiters {
+                let result = match &result {
+                    Ok(()) => Ok(()),
+                    Err(err) => Err(io::Error::new(err.kind(), err.to_string())),
+                };
+                waiter.send(result).ok();
+            }
+        }
+    }
+}
+
+/// Applies `changes`, returns results in the same order
+fn commit(changes: Vec<(PathBuf, Change)>) -> Vec<io::Result<()>> {
+    let mut results: Vec<_> = changes
+        .iter()
+        .map(|(path, change)| match change {
+            Change::Write(contents) => write_synced(path, contents),
+            Change::Remove => crate::remove_file(path),
+        })
+        .collect();
+
+    // renames and removals are durable only once their directories are synced
+    let dirs: HashSet<&Path> = changes
+        .iter()
+        .zip(&results)
+        .filter(|(_, result)| result.is_ok())
+        .filter_map(|((path, _), _)| path.parent())
+        .collect();
+    let failed_dirs: HashMap<PathBuf, io::Error> = dirs
+        .into_iter()
+        .filter_map(|dir| {
+            let synced = File::open(dir).and_then(|dir| dir.sync_all());
+            synced.err().map(|err| (dir.to_path_buf(), err))
+        })
+        .collect();
+
+    for ((path, _), result) in changes.iter().zip(results.iter_mut()) {
+        let failed = path.parent().and_then(|dir| failed_dirs.get(dir));
+        if let (Ok(()), Some(err)) = (&result, failed) {
+            *result = Err(io::Error::new(
+                err.kind(),
+                format!("error syncing directory of {path:?}: {err}"),
+            ));
+        }
+    }
+    results
+}
+
+fn write_synced(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+
+    let written = File::create(&tmp).and_then(|mut file| {
+        file.write_all(contents)?;
+        file.sync_all()
+    });
+    written
+        .and_then(|_| std::fs::rename(&tmp, path))
+        .map_err(|err| io::Error::new(err.kind(), format!("error writing file {path:?}: {err}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn batched_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let writer = BatchWriter::spawn(Duration::from_millis(10));
+        let first = dir.path().join("first");
+        let second = dir.path().join("second");
+
+        let (a, b, c) = tokio::join!(
+            writer.write(first.clone(), b"a".to_vec()),
+            writer.write(first.clone(), b"b".to_vec()),
+            writer.write(second.clone(), b"c".to_vec()),
+        );
+        a.unwrap();
+        b.unwrap();
+        c.unwrap();
+        assert_eq!(std::fs::read(&first).unwrap(), b"b");
+        assert_eq!(std::fs::read(&second).unwrap(), b"c");
+
+        writer.remove(first.clone()).await.unwrap();
+        assert!(!first.exists());
+        // removing a missing file isn't an error
+        writer.remove(first).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_writes_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let writer = BatchWriter::spawn(Duration::ZERO);
+
+        let result = writer
+            .write(dir.path().join("missing").join("file"), vec![])
+            .await;
+        assert!(result.is_err());
+    }
+}
@@ -26,6 +26,10 @@
     unreachable_patterns
 )]
 
+mod batch;
+
+pub use batch::BatchWriter;
+
 use eyre::{eyre, Context};
 use futures_util::StreamExt;
 use std::fmt::Debug;
@@ -33,7 +37,8 @@ use std::fs;
 use std::fs::{DirBuilder, Permissions};
 use std::future::Future;
 use std::io::ErrorKind;
-use std::os::unix::fs::DirBuilderExt;
+use std::io::Write;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
 use std::path::{Path, PathBuf};
 use std::thread::available_parallelism;
 use thiserror::Error;
@@ -130,6 +135,30 @@ pub fn remove_file(file: &Path) -> Result<(), std::io::Error> {
     }
 }
 
+/// Writes `contents` to a temporary file next to `path` and renames it to `path`,
+/// so `path` is never left partially written. The file is accessible only by the node's user,
+/// since it's used for keys and other secrets.
+pub fn write_atomically(path: &Path, contents: &[u8]) -> Result<(), std::io::Error> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    // a temporary file left by a failed write may have other permissions
+    remove_file(&tmp)
+        .and_then(|_| {
+            let mut file = fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(&tmp)?;
+            file.write_all(contents)?;
+            file.sync_all()
+        })
+        .and_then(|_| std::fs::rename(&tmp, path))
+        .map_err(|err| {
+            std::io::Error::new(err.kind(), format!("error writing file {path:?}: {err:?}"))
+        })
+}
+
 pub fn file_stem(path: impl AsRef<Path>) -> eyre::Result<String> {
     let path = path.as_ref();
     Ok(path
@@ -6,8 +6,67 @@ pub trait HealthCheck: Send + Sync + 'static {
     fn status(&self) -> eyre::Result<()>;
 }
 
+/// Which probe a failed check fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Probe {
+    /// The node can't serve requests for now, but may recover by itself
+    Readiness,
+    /// The node is stuck and has to be restarted. Failed liveness check fails readiness as well.
+    Liveness,
+}
+
 pub struct HealthCheckRegistry {
-    checks: Vec<(&'static str, Box<dyn HealthCheck>)>,
+    checks: Vec<(&'static str, Probe, Box<dyn HealthCheck>)>,
+}
+
+/// Result of a single check, `reason` is set if the check has failed
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckReport {
+    pub name: &'static str,
+    pub probe: Probe,
+    pub reason: Option<String>,
+}
+
+impl CheckReport {
+    pub fn is_ok(&self) -> bool {
+        self.reason.is_none()
+    }
+}
+
+/// Results of all registered checks, in the order of registration
+#[derive(Debug, Clone, PartialEq)]
+pub struct HealthReport {
+    pub checks: Vec<CheckReport>,
+}
+
+impl HealthReport {
+    pub fn status(&self) -> HealthStatus {
+        let (oks, fails): (Vec<_>, Vec<_>) = self.checks.iter().partition(|c| c.is_ok());
+        let oks: Vec<_> = oks.into_iter().map(|c| c.name).collect();
+        let fails: Vec<_> = fails.into_iter().map(|c| c.name).collect();
+
+        if fails.is_empty() {
+            HealthStatus::Ok(oks)
+        } else if oks.is_empty() {
+            HealthStatus::Fail(fails)
+        } else {
+            HealthStatus::Warning(oks, fails)
+        }
+    }
+
+    /// All checks have passed
+    pub fn is_ready(&self) -> bool {
+        self.checks.iter().all(CheckReport::is_ok)
+    }
+
+    /// All liveness checks have passed
+    pub fn is_live(&self) -> bool {
+        self.liveness().all(CheckReport::is_ok)
+    }
+
+    pub fn liveness(&self) -> impl Iterator<Item = &CheckReport> {
+        self.checks.iter().filter(|c| c.probe == Probe::Liveness)
+    }
 }
 
 ///  The result of the health check, which can be one of the following:
@@ -27,30 +86,30 @@ impl HealthCheckRegistry {
         HealthCheckRegistry { checks: Vec::new() }
     }
 
+    /// Registers readiness check
     pub fn register(&mut self, name: &'static str, check: impl HealthCheck) {
-        self.checks.push((name, Box::new(check)));
+        self.checks.push((name, Probe::Readiness, Box::new(check)));
     }
 
-    pub fn status(&self) -> HealthStatus {
-        let mut fails = Vec::new();
-        let mut oks = Vec::new();
-
-        for (name, check) in &self.checks {
-            match check.status() {
-                Ok(_) => oks.push(*name),
-                Err(_) => {
-                    fails.push(*name);
-                }
-            }
-        }
+    pub fn register_liveness(&mut self, name: &'static str, check: impl HealthCheck) {
+        self.checks.push((name, Probe::Liveness, Box::new(check)));
+    }
 
-        if fails.is_empty() {
-            HealthStatus::Ok(oks)
-        } else if oks.is_empty() {
-            HealthStatus::Fail(fails)
-        } else {
-            HealthStatus::Warning(oks, fails)
-        }
+    pub fn report(&self) -> HealthReport {
+        let checks = self
+            .checks
+            .iter()
+            .map(|(name, probe, check)| CheckReport {
+                name: *name,
+                probe: *probe,
+                reason: check.status().err().map(|err| err.to_string()),
+            })
+            .collect();
+        HealthReport { checks }
+    }
+
+    pub fn status(&self) -> HealthStatus {
+        self.report().status()
     }
 }
 
@@ -122,4 +181,24 @@ mod tests {
             HealthStatus::Warning(vec!["MockCheck1", "MockCheck3"], vec!["MockCheck2"])
         );
     }
+
+    #[test]
+    fn test_health_check_registry_probes() {
+        let mut registry = HealthCheckRegistry::new();
+        registry.register("Readiness", MockHealthCheck { should_pass: false });
+        registry.register_liveness("Liveness", MockHealthCheck { should_pass: true });
+
+        let report = registry.report();
+        assert!(!report.is_ready());
+        assert!(report.is_live());
+        assert_eq!(
+            report.checks[0].reason.as_deref(),
+            Some("Health check failed")
+        );
+
+        registry.register_liveness("Liveness2", MockHealthCheck { should_pass: false });
+        let report = registry.report();
+        assert!(!report.is_live());
+        assert_eq!(report.liveness().count(), 2);
+    }
 }
@@ -20,6 +20,7 @@ use futures::{future::BoxFuture, FutureExt};
 use libp2p::{core::Multiaddr, PeerId};
 use multihash::Multihash;
 use particle_protocol::Contact;
+use serde::Serialize;
 use tokio::sync::{mpsc, oneshot};
 
 use crate::error::{KademliaError, Result};
@@ -32,6 +33,33 @@ pub trait KademliaApiT {
     fn local_lookup(&self, peer: PeerId) -> Future<Result<Vec<Multiaddr>>>;
     fn discover_peer(&self, peer: PeerId) -> Future<Result<Vec<Multiaddr>>>;
     fn neighborhood(&self, key: Multihash<64>, count: usize) -> Future<Result<Vec<PeerId>>>;
+    /// Republishes records published by this node, returns how many were republished
+    fn republish(&self) -> Future<Result<usize>>;
+    /// Non-empty buckets of the routing table
+    fn routing_table(&self) -> Future<Result<Vec<KBucket>>>;
+}
+
+/// Bucket of the Kademlia routing table
+#[derive(Debug, Clone, Serialize)]
+pub struct KBucket {
+    /// Index of the bucket, i.e. log2 of the distance from the local peer id
+    pub index: u32,
+    pub peers: Vec<KBucketPeer>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct KBucketPeer {
+    #[serde(serialize_with = "serialize_peer_id")]
+    pub peer_id: PeerId,
+    pub addresses: Vec<Multiaddr>,
+    pub connected: bool,
+}
+
+fn serialize_peer_id<S: serde::Serializer>(
+    peer_id: &PeerId,
+    s: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    s.serialize_str(&peer_id.to_base58())
 }
 
 // marked `pub` to be available in benchmarks
@@ -56,6 +84,12 @@ pub enum Command {
         count: usize,
         out: oneshot::Sender<Result<Vec<PeerId>>>,
     },
+    Republish {
+        out: oneshot::Sender<Result<usize>>,
+    },
+    RoutingTable {
+        out: oneshot::Sender<Result<Vec<KBucket>>>,
+    },
 }
 
 #[derive(Clone, Debug)]
@@ -110,4 +144,12 @@ impl KademliaApiT for KademliaApi {
     fn neighborhood(&self, key: Multihash<64>, count: usize) -> Future<Result<Vec<PeerId>>> {
         self.execute(|out| Command::Neighborhood { key, count, out })
     }
+
+    fn republish(&self) -> Future<Result<usize>> {
+        self.execute(|out| Command::Republish { out })
+    }
+
+    fn routing_table(&self) -> Future<Result<Vec<KBucket>>> {
+        self.execute(|out| Command::RoutingTable { out })
+    }
 }
@@ -37,14 +37,15 @@ use libp2p::swarm::{ConnectionDenied, ConnectionId, THandler};
 use libp2p::{
     core::Multiaddr,
     kad::{
-        self, store::MemoryStore, BootstrapError, BootstrapOk, BootstrapResult,
-        Event as KademliaEvent, GetClosestPeersError, GetClosestPeersOk, GetClosestPeersResult,
-        QueryId, QueryResult,
+        self,
+        store::{MemoryStore, RecordStore},
+        BootstrapError, BootstrapOk, BootstrapResult, Event as KademliaEvent, GetClosestPeersError,
+        GetClosestPeersOk, GetClosestPeersResult, QueryId, QueryResult, Quorum,
     },
     swarm::NetworkBehaviour,
     PeerId,
 };
-use libp2p_kad::{KBucketKey, Mode};
+use libp2p_kad::{KBucketKey, Mode, NodeStatus};
 use libp2p_metrics::{Metrics, Recorder};
 use multihash::Multihash;
 use tokio::sync::{mpsc, oneshot};
@@ -55,7 +56,7 @@ use control_macro::get_return;
 use particle_protocol::Contact;
 
 use crate::error::{KademliaError, Result};
-use crate::{Command, KademliaApi};
+use crate::{Command, KBucket, KBucketPeer, KademliaApi};
 
 pub struct KademliaConfig {
     pub peer_id: PeerId,
@@ -168,6 +169,8 @@ impl Kademlia {
             Command::LocalLookup { peer, out } => self.local_lookup(&peer, out),
             Command::DiscoverPeer { peer, out } => self.discover_peer(peer, out),
             Command::Neighborhood { key, count, out } => self.neighborhood(key, count, out),
+            Command::Republish { out } => self.republish(out),
+            Command::RoutingTable { out } => self.routing_table(out),
         }
     }
 
@@ -177,6 +180,12 @@ impl Kademlia {
         }
         self.wake();
     }
+
+    /// Removes peer from the routing table, e.g. if it was added on connection, but shouldn't be there
+    pub fn remove_kad_node(&mut self, peer: &PeerId) {
+        self.kademlia.remove_peer(peer);
+        self.wake();
+    }
 }
 
 impl Kademlia {
@@ -259,6 +268,50 @@ impl Kademlia {
         self.wake();
     }
 
+    /// Puts records published by this node to the network again, without waiting
+    /// for `publication_interval`
+    pub fn republish(&mut self, outlet: oneshot::Sender<Result<usize>>) {
+        let local_peer_id = self.config.peer_id;
+        let records: Vec<_> = self
+            .kademlia
+            .store_mut()
+            .records()
+            .filter(|r| r.publisher == Some(local_peer_id))
+            .map(|r| r.into_owned())
+            .collect();
+
+        let mut republished = 0;
+        for record in records {
+            let key = record.key.clone();
+            match self.kademlia.put_record(record, Quorum::One) {
+                Ok(_) => republished += 1,
+                Err(err) => log::warn!("Failed to republish record {key:?}: {err:?}"),
+            }
+        }
+        outlet.send(Ok(republished)).ok();
+        self.wake();
+    }
+
+    pub fn routing_table(&mut self, outlet: oneshot::Sender<Result<Vec<KBucket>>>) {
+        let buckets = self
+            .kademlia
+            .kbuckets()
+            .filter(|bucket| !bucket.is_empty())
+            .map(|bucket| KBucket {
+                index: bucket.range().0.ilog2().unwrap_or_default(),
+                peers: bucket
+                    .iter()
+                    .map(|entry| KBucketPeer {
+                        peer_id: *entry.node.key.preimage(),
+                        addresses: entry.node.value.iter().cloned().collect(),
+                        connected: matches!(entry.status, NodeStatus::Connected),
+                    })
+                    .collect(),
+            })
+            .collect();
+        outlet.send(Ok(buckets)).ok();
+    }
+
     pub fn remote_neighborhood(
         &mut self,
         key: Multihash<64>,
@@ -693,6 +746,24 @@ mod tests {
         (swarm, maddr)
     }
 
+    #[tokio::test]
+    async fn republish_own_records() {
+        use libp2p::kad::{store::RecordStore, Record, RecordKey};
+
+        let (mut node, _) = make_node("a".to_string());
+        let local_peer_id = *node.local_peer_id();
+        let store = node.behaviour_mut().kademlia.store_mut();
+        for (key, publisher) in [("own", local_peer_id), ("other", RandomPeerId::random())] {
+            let mut record = Record::new(RecordKey::new(&key), vec![]);
+            record.publisher = Some(publisher);
+            store.put(record).unwrap();
+        }
+
+        let (out, mut inlet) = oneshot::channel();
+        node.behaviour_mut().republish(out);
+        assert_eq!(inlet.try_recv().unwrap().unwrap(), 1);
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn discovery_heavy() {
         enable_logs();
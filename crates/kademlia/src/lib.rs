@@ -22,6 +22,7 @@ mod error;
 
 pub use api::KademliaApi;
 pub use api::KademliaApiT;
+pub use api::{KBucket, KBucketPeer};
 pub use behaviour::Kademlia;
 pub use behaviour::KademliaConfig;
 pub use error::KademliaError;
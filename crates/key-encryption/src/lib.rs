@@ -0,0 +1,197 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Encryption of key pairs stored on disk. A key is encrypted with ChaCha20-Poly1305
+//! under a key derived from a passphrase with Argon2id, or from a 32-byte key, i.e. provided by a KMS.
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+use base64::{engine::general_purpose::STANDARD as base64, Engine};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use parking_lot::Mutex;
+use rand::RngCore;
+
+/// Prefix of encrypted keys, the rest is base64 of salt, nonce and ciphertext
+pub const ENCRYPTED_PREFIX: &str = "nox-encrypted:v1:";
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+
+type Salt = [u8; SALT_LEN];
+
+#[derive(Debug, thiserror::Error)]
+pub enum KeyEncryptionError {
+    #[error("key must be 32 bytes encoded in base64")]
+    InvalidKey,
+    #[error("failed to derive key from passphrase: {0}")]
+    Derivation(String),
+    #[error("encrypted key is malformed")]
+    Malformed,
+    #[error("failed to decrypt key: wrong passphrase or corrupted data")]
+    Decryption,
+}
+
+enum Secret {
+    Passphrase(String),
+    Key([u8; KEY_LEN]),
+}
+
+/// Encrypts and decrypts keys at rest. Keys encrypted by the same instance share the salt,
+/// so the expensive passphrase derivation runs once per salt.
+#[derive(Clone)]
+pub struct KeyEncryption {
+    secret: Arc<Secret>,
+    salt: Salt,
+    derived: Arc<Mutex<HashMap<Salt, [u8; KEY_LEN]>>>,
+}
+
+impl Debug for KeyEncryption {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("KeyEncryption(<redacted>)")
+    }
+}
+
+impl KeyEncryption {
+    pub fn from_passphrase(passphrase: impl Into<String>) -> Self {
+        Self::new(Secret::Passphrase(passphrase.into()))
+    }
+
+    /// `key` is 32 bytes encoded in base64
+    pub fn from_key(key: &str) -> Result<Self, KeyEncryptionError> {
+        let key = base64
+            .decode(key.trim())
+            .map_err(|_| KeyEncryptionError::InvalidKey)?
+            .try_into()
+            .map_err(|_| KeyEncryptionError::InvalidKey)?;
+        Ok(Self::new(Secret::Key(key)))
+    }
+
+    /// Same as [KeyEncryption::from_key], but takes raw bytes, i.e. derived from another key
+    pub fn from_bytes(key: [u8; KEY_LEN]) -> Self {
+        Self::new(Secret::Key(key))
+    }
+
+    fn new(secret: Secret) -> Self {
+        let mut salt = Salt::default();
+        rand::thread_rng().fill_bytes(&mut salt);
+        Self {
+            secret: Arc::new(secret),
+            salt,
+            derived: <_>::default(),
+        }
+    }
+
+    fn cipher(&self, salt: &Salt) -> Result<ChaCha20Poly1305, KeyEncryptionError> {
+        let mut derived = self.derived.lock();
+        let key = match derived.get(salt) {
+            Some(key) => *key,
+            None => {
+                let mut key = [0u8; KEY_LEN];
+                match self.secret.as_ref() {
+                    Secret::Passphrase(passphrase) => argon2::Argon2::default()
+                        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+                        .map_err(|err| KeyEncryptionError::Derivation(err.to_string()))?,
+                    Secret::Key(secret) => {
+                        key = *blake3::keyed_hash(secret, salt).as_bytes();
+                    }
+                }
+                derived.insert(*salt, key);
+                key
+            }
+        };
+        Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+    }
+
+    /// Returns [ENCRYPTED_PREFIX] followed by the encrypted `plaintext`
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<String, KeyEncryptionError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = self
+            .cipher(&self.salt)?
+            .encrypt(Nonce::from_slice(&nonce), plaintext)
+            .map_err(|_| KeyEncryptionError::Malformed)?;
+
+        let mut bytes = Vec::with_capacity(SALT_LEN + NONCE_LEN + ciphertext.len());
+        bytes.extend_from_slice(&self.salt);
+        bytes.extend_from_slice(&nonce);
+        bytes.extend_from_slice(&ciphertext);
+        Ok(format!("{ENCRYPTED_PREFIX}{}", base64.encode(bytes)))
+    }
+
+    pub fn decrypt(&self, encrypted: &str) -> Result<Vec<u8>, KeyEncryptionError> {
+        let encoded = encrypted
+            .trim()
+            .strip_prefix(ENCRYPTED_PREFIX)
+            .ok_or(KeyEncryptionError::Malformed)?;
+        let bytes = base64
+            .decode(encoded)
+            .map_err(|_| KeyEncryptionError::Malformed)?;
+        if bytes.len() < SALT_LEN + NONCE_LEN {
+            return Err(KeyEncryptionError::Malformed);
+        }
+        let (salt, rest) = bytes.split_at(SALT_LEN);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        let salt: Salt = salt.try_into().expect("salt length is checked");
+        self.cipher(&salt)?
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| KeyEncryptionError::Decryption)
+    }
+}
+
+/// Whether `contents` were produced by [KeyEncryption::encrypt]
+pub fn is_encrypted(contents: &str) -> bool {
+    contents.trim_start().starts_with(ENCRYPTED_PREFIX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passphrase_roundtrip() {
+        let encryption = KeyEncryption::from_passphrase("correct horse");
+        let encrypted = encryption.encrypt(b"secret key").unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert!(!encrypted.contains("secret key"));
+
+        // another instance derives the key from the salt stored with the ciphertext
+        let decrypted = KeyEncryption::from_passphrase("correct horse")
+            .decrypt(&encrypted)
+            .unwrap();
+        assert_eq!(decrypted, b"secret key");
+
+        let wrong = KeyEncryption::from_passphrase("battery staple").decrypt(&encrypted);
+        assert!(matches!(wrong, Err(KeyEncryptionError::Decryption)));
+    }
+
+    #[test]
+    fn raw_key() {
+        let key = base64.encode([7u8; KEY_LEN]);
+        let encryption = KeyEncryption::from_key(&key).unwrap();
+        let encrypted = encryption.encrypt(b"secret key").unwrap();
+        assert_eq!(encryption.decrypt(&encrypted).unwrap(), b"secret key");
+
+        assert!(KeyEncryption::from_key("c2hvcnQ=").is_err());
+        assert!(matches!(
+            encryption.decrypt("nox-encrypted:v1:AAAA"),
+            Err(KeyEncryptionError::Malformed)
+        ));
+    }
+}
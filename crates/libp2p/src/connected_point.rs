@@ -15,6 +15,7 @@
  */
 
 use libp2p::core::{connection::ConnectedPoint, Multiaddr};
+use libp2p::multiaddr::Protocol;
 
 /// Retrieves multiaddr of the remote peer
 pub fn remote_multiaddr(cp: &ConnectedPoint) -> &Multiaddr {
@@ -23,3 +24,28 @@ pub fn remote_multiaddr(cp: &ConnectedPoint) -> &Multiaddr {
         ConnectedPoint::Listener { send_back_addr, .. } => send_back_addr,
     }
 }
+
+/// Whether `addr` can be dialed. Browser peers connect over `/webrtc-direct` from addresses
+/// without `/certhash`, these can't be dialed back.
+pub fn is_dialable(addr: &Multiaddr) -> bool {
+    let webrtc = addr.iter().any(|p| p == Protocol::WebRTCDirect);
+    !webrtc || addr.iter().any(|p| matches!(p, Protocol::Certhash(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn browser_addresses_are_not_dialable() {
+        let tcp: Multiaddr = "/ip4/1.2.3.4/tcp/7777".parse().unwrap();
+        let browser: Multiaddr = "/ip4/1.2.3.4/udp/50000/webrtc-direct".parse().unwrap();
+        let node: Multiaddr = "/ip4/1.2.3.4/udp/9998/webrtc-direct/certhash/uEiDDq4_xNyDorZBH3TlGazyJdOWSwvo4PUo5YHFMrvDE8g"
+            .parse()
+            .unwrap();
+
+        assert!(is_dialable(&tcp));
+        assert!(!is_dialable(&browser));
+        assert!(is_dialable(&node));
+    }
+}
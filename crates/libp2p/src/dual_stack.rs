@@ -0,0 +1,111 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use libp2p::core::multiaddr::Protocol;
+use libp2p::Multiaddr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressFamily {
+    Ipv4,
+    Ipv6,
+}
+
+/// Address family of the first protocol of `maddr`, `None` for `/dns`, `/dnsaddr`, `/memory` etc
+pub fn address_family(maddr: &Multiaddr) -> Option<AddressFamily> {
+    match maddr.iter().next()? {
+        Protocol::Ip4(_) | Protocol::Dns4(_) => Some(AddressFamily::Ipv4),
+        Protocol::Ip6(_) | Protocol::Dns6(_) => Some(AddressFamily::Ipv6),
+        _ => None,
+    }
+}
+
+/// Orders addresses for dialing the way Happy Eyeballs (RFC 8305) does:
+/// families alternate starting with the preferred one, so a broken family
+/// doesn't delay the dial while the swarm dials addresses concurrently in that order.
+/// Addresses of unknown family go last, relative order within a family is kept.
+pub fn happy_eyeballs_order(
+    addresses: impl IntoIterator<Item = Multiaddr>,
+    prefer_ipv6: bool,
+) -> Vec<Multiaddr> {
+    let mut preferred = vec![];
+    let mut fallback = vec![];
+    let mut unknown = vec![];
+    let preferred_family = if prefer_ipv6 {
+        AddressFamily::Ipv6
+    } else {
+        AddressFamily::Ipv4
+    };
+
+    for addr in addresses {
+        match address_family(&addr) {
+            Some(family) if family == preferred_family => preferred.push(addr),
+            Some(_) => fallback.push(addr),
+            None => unknown.push(addr),
+        }
+    }
+
+    let mut result = Vec::with_capacity(preferred.len() + fallback.len() + unknown.len());
+    let mut preferred = preferred.into_iter();
+    let mut fallback = fallback.into_iter();
+    loop {
+        match (preferred.next(), fallback.next()) {
+            (None, None) => break,
+            (p, f) => result.extend(p.into_iter().chain(f)),
+        }
+    }
+    result.extend(unknown);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alternate_families() {
+        let addrs: Vec<Multiaddr> = vec![
+            "/ip4/1.1.1.1/tcp/7777".parse().unwrap(),
+            "/dns/fluence.dev/tcp/7777".parse().unwrap(),
+            "/ip4/2.2.2.2/tcp/7777".parse().unwrap(),
+            "/ip4/3.3.3.3/tcp/7777".parse().unwrap(),
+            "/ip6/2001:db8::1/tcp/7777".parse().unwrap(),
+        ];
+
+        let ordered = happy_eyeballs_order(addrs.clone(), true);
+        assert_eq!(
+            ordered,
+            vec![
+                addrs[4].clone(),
+                addrs[0].clone(),
+                addrs[2].clone(),
+                addrs[3].clone(),
+                addrs[1].clone(),
+            ]
+        );
+
+        let ordered = happy_eyeballs_order(addrs.clone(), false);
+        assert_eq!(
+            ordered,
+            vec![
+                addrs[0].clone(),
+                addrs[4].clone(),
+                addrs[2].clone(),
+                addrs[3].clone(),
+                addrs[1].clone(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,323 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::{ready, AsyncRead, AsyncWrite};
+use libp2p::core::muxing::{
+    StreamMuxer, StreamMuxerBox, StreamMuxerEvent, StreamMuxerExt, SubstreamBox,
+};
+use libp2p::core::transport::Boxed;
+use libp2p::{PeerId, Transport};
+use parking_lot::RwLock;
+use rand::Rng;
+use tokio::time::Sleep;
+
+/// Network conditions simulated on a connection. Default config doesn't inject any faults.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FaultConfig {
+    /// Delay before each stream can be written to
+    pub latency: Duration,
+    /// Random delay of up to that much added to `latency`
+    pub jitter: Duration,
+    /// Probability of a stream to fail as if its data was lost, from 0 to 1
+    pub drop_probability: f64,
+    /// Max bytes per second written to each stream, unlimited if not set
+    pub bandwidth: Option<u64>,
+}
+
+impl FaultConfig {
+    fn delay(&self) -> Duration {
+        if self.jitter.is_zero() {
+            return self.latency;
+        }
+        self.latency + self.jitter.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+    }
+
+    fn should_drop(&self) -> bool {
+        self.drop_probability > 0.0 && rand::thread_rng().gen_bool(self.drop_probability.min(1.0))
+    }
+
+    /// Time it takes to transfer `bytes` with the bandwidth cap
+    fn transfer_time(&self, bytes: usize) -> Duration {
+        match self.bandwidth {
+            Some(bandwidth) if bandwidth > 0 => {
+                Duration::from_secs_f64(bytes as f64 / bandwidth as f64)
+            }
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Faults {
+    default: Option<FaultConfig>,
+    peers: HashMap<PeerId, FaultConfig>,
+}
+
+/// Faults injected into connections, by remote peer id.
+/// Changes apply to streams opened afterwards, including ones of the established connections.
+#[derive(Clone, Debug, Default)]
+pub struct FaultInjector {
+    faults: Arc<RwLock<Faults>>,
+}
+
+impl FaultInjector {
+    /// Injects faults of `config` into connections with peers that don't have their own config
+    pub fn set_default(&self, config: Option<FaultConfig>) {
+        self.faults.write().default = config;
+    }
+
+    pub fn set_peer(&self, peer_id: PeerId, config: FaultConfig) {
+        self.faults.write().peers.insert(peer_id, config);
+    }
+
+    pub fn clear_peer(&self, peer_id: &PeerId) {
+        self.faults.write().peers.remove(peer_id);
+    }
+
+    /// Removes all injected faults
+    pub fn clear(&self) {
+        *self.faults.write() = Faults::default();
+    }
+
+    pub fn config(&self, peer_id: &PeerId) -> Option<FaultConfig> {
+        let faults = self.faults.read();
+        faults
+            .peers
+            .get(peer_id)
+            .or(faults.default.as_ref())
+            .cloned()
+    }
+}
+
+/// Wraps muxer of every connection of `transport`, so streams suffer the faults
+/// configured in `injector` for the remote peer. Meant for tests only.
+pub fn with_fault_injection(
+    transport: Boxed<(PeerId, StreamMuxerBox)>,
+    injector: FaultInjector,
+) -> Boxed<(PeerId, StreamMuxerBox)> {
+    transport
+        .map(move |(peer_id, muxer), _| {
+            let muxer = FaultyMuxer {
+                inner: muxer,
+                peer_id,
+                injector: injector.clone(),
+            };
+            (peer_id, StreamMuxerBox::new(muxer))
+        })
+        .boxed()
+}
+
+struct FaultyMuxer {
+    inner: StreamMuxerBox,
+    peer_id: PeerId,
+    injector: FaultInjector,
+}
+
+impl FaultyMuxer {
+    fn substream(&self, stream: SubstreamBox) -> FaultySubstream {
+        let config = self.injector.config(&self.peer_id).unwrap_or_default();
+        FaultySubstream::new(stream, config)
+    }
+}
+
+impl StreamMuxer for FaultyMuxer {
+    type Substream = FaultySubstream;
+    type Error = io::Error;
+
+    fn poll_inbound(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::Substream, Self::Error>> {
+        let this = self.get_mut();
+        let stream = ready!(this.inner.poll_inbound_unpin(cx))?;
+        Poll::Ready(Ok(this.substream(stream)))
+    }
+
+    fn poll_outbound(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::Substream, Self::Error>> {
+        let this = self.get_mut();
+        let stream = ready!(this.inner.poll_outbound_unpin(cx))?;
+        Poll::Ready(Ok(this.substream(stream)))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().inner.poll_close_unpin(cx)
+    }
+
+    fn poll(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<StreamMuxerEvent, Self::Error>> {
+        self.get_mut().inner.poll_unpin(cx)
+    }
+}
+
+struct FaultySubstream {
+    inner: SubstreamBox,
+    config: FaultConfig,
+    /// Whether the stream is lost, so all reads and writes fail
+    dropped: bool,
+    /// Delay before the next write: latency of the stream or transfer time of the previous write
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl FaultySubstream {
+    fn new(inner: SubstreamBox, config: FaultConfig) -> Self {
+        let dropped = config.should_drop();
+        let delay = config.delay();
+        Self {
+            inner,
+            dropped,
+            delay: (!delay.is_zero()).then(|| Box::pin(tokio::time::sleep(delay))),
+            config,
+        }
+    }
+
+    fn check_dropped(&self) -> io::Result<()> {
+        if self.dropped {
+            return Err(io::Error::new(
+                io::ErrorKind::ConnectionReset,
+                "stream is dropped by fault injection",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl AsyncRead for FaultySubstream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        this.check_dropped()?;
+        Pin::new(&mut this.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for FaultySubstream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        this.check_dropped()?;
+        if let Some(delay) = this.delay.as_mut() {
+            ready!(delay.as_mut().poll(cx));
+            this.delay = None;
+        }
+
+        let written = ready!(Pin::new(&mut this.inner).poll_write(cx, buf))?;
+        let transfer_time = this.config.transfer_time(written);
+        if !transfer_time.is_zero() {
+            this.delay = Some(Box::pin(tokio::time::sleep(transfer_time)));
+        }
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.check_dropped()?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use futures::io::Cursor;
+    use futures::AsyncWriteExt;
+
+    use super::*;
+
+    fn stream(config: FaultConfig) -> FaultySubstream {
+        FaultySubstream::new(SubstreamBox::new(Cursor::new(vec![])), config)
+    }
+
+    #[test]
+    fn config_by_peer() {
+        let injector = FaultInjector::default();
+        let peer_id = PeerId::random();
+        let slow = FaultConfig {
+            latency: Duration::from_millis(100),
+            ..<_>::default()
+        };
+        assert_eq!(injector.config(&peer_id), None);
+
+        injector.set_default(Some(FaultConfig::default()));
+        injector.set_peer(peer_id, slow.clone());
+        assert_eq!(injector.config(&peer_id), Some(slow));
+        assert_eq!(
+            injector.config(&PeerId::random()),
+            Some(FaultConfig::default())
+        );
+
+        injector.clear();
+        assert_eq!(injector.config(&peer_id), None);
+    }
+
+    #[tokio::test]
+    async fn dropped_stream() {
+        let mut dropped = stream(FaultConfig {
+            drop_probability: 1.0,
+            ..<_>::default()
+        });
+        let err = dropped.write_all(b"particle").await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+
+        let mut healthy = stream(FaultConfig::default());
+        healthy.write_all(b"particle").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn latency_and_bandwidth() {
+        let latency = Duration::from_millis(50);
+        let start = Instant::now();
+        let mut slow = stream(FaultConfig {
+            latency,
+            ..<_>::default()
+        });
+        slow.write_all(b"particle").await.unwrap();
+        assert!(start.elapsed() >= latency);
+
+        // the second write waits until 100 bytes are transferred at 1000 bytes per second
+        let start = Instant::now();
+        let mut throttled = stream(FaultConfig {
+            bandwidth: Some(1000),
+            ..<_>::default()
+        });
+        throttled.write_all(&[0; 100]).await.unwrap();
+        throttled.write_all(&[0; 100]).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+}
@@ -27,18 +27,44 @@
 )]
 
 mod connected_point;
+mod dual_stack;
+#[cfg(feature = "tokio")]
+mod fault_injection;
 mod macros;
+mod pnet;
 pub mod random_multiaddr;
 mod random_peer_id;
 mod serde;
+mod stream_metrics;
+#[cfg(feature = "tls")]
+mod tls;
 #[cfg(feature = "tokio")]
 mod transport;
+#[cfg(feature = "webrtc")]
+mod webrtc;
 
 pub use self::serde::*;
 pub use connected_point::*;
+pub use dual_stack::{address_family, happy_eyeballs_order, AddressFamily};
+#[cfg(feature = "tokio")]
+pub use fault_injection::{with_fault_injection, FaultConfig, FaultInjector};
+pub use pnet::{PnetOutput, PreSharedKey, PrivateNetwork};
 pub use random_peer_id::RandomPeerId;
+pub use stream_metrics::{with_stream_metrics, StreamObserver, UNKNOWN_PROTOCOL};
+#[cfg(feature = "tls")]
+pub use tls::{
+    build_network_transport_with_tls, static_tls_server_config, tls_server_config,
+    TlsCertificateResolver, TlsTransportError, ACME_TLS_ALPN_NAME,
+};
 #[cfg(feature = "tokio")]
-pub use transport::{build_memory_transport, build_transport, Transport};
+pub use transport::{
+    build_memory_transport, build_transport, with_quic_transport, with_relay_client_transport,
+    Transport,
+};
+#[cfg(feature = "webrtc")]
+pub use webrtc::{
+    load_or_create_webrtc_certificate, webrtc_certhash, with_webrtc_transport, WebRtcCertificate,
+};
 
 // libp2p reexports
 pub use libp2p::PeerId;
@@ -0,0 +1,389 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Private network protection, wire-compatible with libp2p pnet (`/key/swarm/psk/1.0.0/`).
+//!
+//! Each side sends a random 24-byte nonce, after that all bytes are encrypted with
+//! XSalsa20 keyed by the pre-shared key. Peers without the key can't complete
+//! the multistream-select negotiation that follows, so they can't connect.
+//!
+//! To rotate the key without downtime, nodes are reconfigured one by one with the new `key`
+//! and the old one as `previous_key`. Until `previous_key` expires, inbound connections encrypted
+//! with either key are accepted, so not yet reconfigured nodes can still reach the updated ones.
+
+use std::fmt::{Debug, Formatter};
+use std::io;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::task::{Context, Poll};
+use std::time::SystemTime;
+
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use libp2p::core::ConnectedPoint;
+use rand::RngCore;
+use salsa20::cipher::{KeyIvInit, StreamCipher};
+use salsa20::XSalsa20;
+
+const KEY_SIZE: usize = 32;
+const NONCE_SIZE: usize = 24;
+const SWARM_KEY_HEADER: &str = "/key/swarm/psk/1.0.0/";
+const BASE16_ENCODING: &str = "/base16/";
+/// The first message a dialer sends after the pnet handshake, used to detect which key it uses
+const MULTISTREAM_HEADER: &[u8] = b"\x13/multistream/1.0.0\n";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct PreSharedKey([u8; KEY_SIZE]);
+
+impl PreSharedKey {
+    pub fn new(key: [u8; KEY_SIZE]) -> Self {
+        Self(key)
+    }
+
+    /// Short hex prefix of the key, safe to show in logs
+    pub fn fingerprint(&self) -> String {
+        hex::encode(&self.0[..4])
+    }
+
+    fn cipher(&self, nonce: &[u8; NONCE_SIZE]) -> XSalsa20 {
+        XSalsa20::new(&self.0.into(), nonce.into())
+    }
+}
+
+impl Debug for PreSharedKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PreSharedKey({})", self.fingerprint())
+    }
+}
+
+/// Parses either a 64-character hex string or a go-libp2p `swarm.key` file contents
+impl FromStr for PreSharedKey {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.trim().lines().map(str::trim);
+        let key = match lines.next() {
+            Some(SWARM_KEY_HEADER) => {
+                if lines.next() != Some(BASE16_ENCODING) {
+                    return Err(invalid_data("swarm key must be base16-encoded"));
+                }
+                lines.next().unwrap_or_default()
+            }
+            Some(key) => key,
+            None => return Err(invalid_data("pre-shared key is empty")),
+        };
+
+        let mut bytes = [0u8; KEY_SIZE];
+        hex::decode_to_slice(key, &mut bytes).map_err(|err| {
+            invalid_data(format!(
+                "pre-shared key must be {KEY_SIZE} hex-encoded bytes: {err}"
+            ))
+        })?;
+
+        Ok(Self(bytes))
+    }
+}
+
+/// Keys accepted by the node in a private network
+#[derive(Clone, Debug)]
+pub struct PrivateNetwork {
+    /// Used for outbound connections and always accepted for inbound ones
+    key: PreSharedKey,
+    /// Key that is being rotated out, accepted for inbound connections until it expires
+    previous_key: Option<(PreSharedKey, SystemTime)>,
+}
+
+impl PrivateNetwork {
+    pub fn new(key: PreSharedKey) -> Self {
+        Self {
+            key,
+            previous_key: None,
+        }
+    }
+
+    pub fn with_previous_key(mut self, key: PreSharedKey, valid_until: SystemTime) -> Self {
+        self.previous_key = Some((key, valid_until));
+        self
+    }
+
+    pub fn key(&self) -> &PreSharedKey {
+        &self.key
+    }
+
+    fn valid_previous_key(&self) -> Option<&PreSharedKey> {
+        self.previous_key
+            .as_ref()
+            .filter(|(_, valid_until)| SystemTime::now() < *valid_until)
+            .map(|(key, _)| key)
+    }
+
+    /// Exchanges nonces and wraps `socket` into encryption with the pre-shared key
+    pub async fn handshake<S>(
+        self,
+        mut socket: S,
+        endpoint: ConnectedPoint,
+    ) -> io::Result<PnetOutput<S>>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut local_nonce = [0u8; NONCE_SIZE];
+        rand::thread_rng().fill_bytes(&mut local_nonce);
+        socket.write_all(&local_nonce).await?;
+        socket.flush().await?;
+
+        let mut remote_nonce = [0u8; NONCE_SIZE];
+        socket.read_exact(&mut remote_nonce).await?;
+
+        let previous_key = self
+            .valid_previous_key()
+            .filter(|_| endpoint.is_listener())
+            .copied();
+        let (key, read_cipher, read_buffer) = match previous_key {
+            // Only the dialer's key is ambiguous, so detect it by the multistream header it sends first
+            Some(previous_key) => {
+                let mut header = [0u8; MULTISTREAM_HEADER.len()];
+                socket.read_exact(&mut header).await?;
+                [self.key, previous_key]
+                    .into_iter()
+                    .find_map(|key| {
+                        let mut cipher = key.cipher(&remote_nonce);
+                        let mut plain = header;
+                        cipher.apply_keystream(&mut plain);
+                        (plain == MULTISTREAM_HEADER).then(|| (key, cipher, plain.to_vec()))
+                    })
+                    .ok_or_else(|| invalid_data("remote peer uses unknown pre-shared key"))?
+            }
+            None => (self.key, self.key.cipher(&remote_nonce), vec![]),
+        };
+
+        if key != self.key {
+            log::debug!(
+                "Inbound connection uses previous pre-shared key {}",
+                key.fingerprint()
+            );
+        }
+
+        Ok(PnetOutput {
+            inner: socket,
+            read_cipher,
+            write_cipher: key.cipher(&local_nonce),
+            read_buffer,
+            write_buffer: vec![],
+        })
+    }
+}
+
+/// Socket encrypted with the pre-shared key
+pub struct PnetOutput<S> {
+    inner: S,
+    read_cipher: XSalsa20,
+    write_cipher: XSalsa20,
+    /// Already decrypted bytes that were read during the handshake
+    read_buffer: Vec<u8>,
+    /// Encrypted bytes that weren't written to `inner` yet
+    write_buffer: Vec<u8>,
+}
+
+impl<S: AsyncWrite + Unpin> PnetOutput<S> {
+    fn poll_write_buffer(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.write_buffer.is_empty() {
+            match Pin::new(&mut self.inner).poll_write(cx, &self.write_buffer) {
+                Poll::Ready(Ok(0)) => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                Poll::Ready(Ok(written)) => {
+                    self.write_buffer.drain(..written);
+                }
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for PnetOutput<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if !this.read_buffer.is_empty() {
+            let len = buf.len().min(this.read_buffer.len());
+            buf[..len].copy_from_slice(&this.read_buffer[..len]);
+            this.read_buffer.drain(..len);
+            return Poll::Ready(Ok(len));
+        }
+
+        let read = futures::ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+        this.read_cipher.apply_keystream(&mut buf[..read]);
+        Poll::Ready(Ok(read))
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for PnetOutput<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        futures::ready!(this.poll_write_buffer(cx))?;
+
+        this.write_buffer.extend_from_slice(buf);
+        this.write_cipher.apply_keystream(&mut this.write_buffer);
+        // The data is accepted and encrypted already, the rest is written on the next poll
+        if let Poll::Ready(Err(err)) = this.poll_write_buffer(cx) {
+            return Poll::Ready(Err(err));
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        futures::ready!(this.poll_write_buffer(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        futures::ready!(this.poll_write_buffer(cx))?;
+        Pin::new(&mut this.inner).poll_close(cx)
+    }
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::future::join;
+    use futures::{AsyncReadExt, AsyncWriteExt, StreamExt};
+    use libp2p::core::transport::{MemoryTransport, TransportEvent};
+    use libp2p::core::{ConnectedPoint, Endpoint};
+    use libp2p::{Multiaddr, Transport};
+
+    use super::*;
+
+    fn key(byte: u8) -> PreSharedKey {
+        PreSharedKey::new([byte; KEY_SIZE])
+    }
+
+    /// Connects two memory sockets with pnet handshakes and sends the multistream header over them
+    async fn connect(dialer: PrivateNetwork, listener: PrivateNetwork) -> io::Result<Vec<u8>> {
+        let port = rand::random::<u64>().saturating_add(1);
+        let addr: Multiaddr = format!("/memory/{port}").parse().unwrap();
+        let mut transport = MemoryTransport::default().boxed();
+        transport
+            .listen_on(libp2p::core::transport::ListenerId::next(), addr.clone())
+            .unwrap();
+        let dial = transport.dial(addr.clone()).unwrap();
+
+        let inbound = async {
+            loop {
+                if let Some(TransportEvent::Incoming { upgrade, .. }) = transport.next().await {
+                    break upgrade.await.unwrap();
+                }
+            }
+        };
+        let (inbound, outbound) = join(inbound, dial).await;
+        let outbound = outbound.unwrap();
+
+        let dialer_endpoint = ConnectedPoint::Dialer {
+            address: addr.clone(),
+            role_override: Endpoint::Dialer,
+        };
+        let listener_endpoint = ConnectedPoint::Listener {
+            local_addr: addr.clone(),
+            send_back_addr: addr,
+        };
+        let outbound = async move {
+            let mut socket = dialer.handshake(outbound, dialer_endpoint).await?;
+            socket.write_all(MULTISTREAM_HEADER).await?;
+            socket.flush().await?;
+            Ok::<_, io::Error>(socket)
+        };
+        let inbound = async move {
+            let mut socket = listener.handshake(inbound, listener_endpoint).await?;
+            let mut header = vec![0u8; MULTISTREAM_HEADER.len()];
+            socket.read_exact(&mut header).await?;
+            Ok::<_, io::Error>(header)
+        };
+        let (outbound, inbound) = join(outbound, inbound).await;
+        outbound?;
+        inbound
+    }
+
+    #[test]
+    fn parse_key() {
+        let hex = "ab".repeat(KEY_SIZE);
+        let swarm_key = format!("{SWARM_KEY_HEADER}\n{BASE16_ENCODING}\n{hex}\n");
+
+        assert_eq!(hex.parse::<PreSharedKey>().unwrap(), key(0xab));
+        assert_eq!(swarm_key.parse::<PreSharedKey>().unwrap(), key(0xab));
+        assert!("abab".parse::<PreSharedKey>().is_err());
+        assert!(format!("{SWARM_KEY_HEADER}\n/base64/\n{hex}")
+            .parse::<PreSharedKey>()
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn same_key() {
+        let header = connect(PrivateNetwork::new(key(1)), PrivateNetwork::new(key(1)))
+            .await
+            .unwrap();
+        assert_eq!(header, MULTISTREAM_HEADER);
+    }
+
+    #[tokio::test]
+    async fn different_key() {
+        let result = connect(PrivateNetwork::new(key(1)), PrivateNetwork::new(key(2))).await;
+        assert_ne!(result.ok().as_deref(), Some(MULTISTREAM_HEADER));
+    }
+
+    #[tokio::test]
+    async fn previous_key_accepted() {
+        let valid_until = SystemTime::now() + Duration::from_secs(60);
+        let listener = PrivateNetwork::new(key(2)).with_previous_key(key(1), valid_until);
+
+        let header = connect(PrivateNetwork::new(key(1)), listener.clone())
+            .await
+            .unwrap();
+        assert_eq!(header, MULTISTREAM_HEADER);
+
+        let header = connect(PrivateNetwork::new(key(2)), listener.clone())
+            .await
+            .unwrap();
+        assert_eq!(header, MULTISTREAM_HEADER);
+
+        let result = connect(PrivateNetwork::new(key(3)), listener).await;
+        assert_eq!(
+            result.unwrap_err().kind(),
+            io::ErrorKind::InvalidData,
+            "unknown key must be rejected"
+        );
+    }
+
+    #[tokio::test]
+    async fn previous_key_expired() {
+        let valid_until = SystemTime::now() - Duration::from_secs(1);
+        let listener = PrivateNetwork::new(key(2)).with_previous_key(key(1), valid_until);
+
+        let result = connect(PrivateNetwork::new(key(1)), listener).await;
+        assert_ne!(result.ok().as_deref(), Some(MULTISTREAM_HEADER));
+    }
+}
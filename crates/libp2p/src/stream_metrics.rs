@@ -0,0 +1,366 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures::{ready, AsyncRead, AsyncWrite};
+use libp2p::core::muxing::{
+    StreamMuxer, StreamMuxerBox, StreamMuxerEvent, StreamMuxerExt, SubstreamBox,
+};
+use libp2p::core::transport::Boxed;
+use libp2p::{PeerId, Transport};
+
+/// Protocol label of streams that were closed before the protocol was negotiated
+/// or negotiated something that doesn't look like a protocol
+pub const UNKNOWN_PROTOCOL: &str = "unknown";
+
+const MULTISTREAM_HEADER: &[u8] = b"/multistream/1.0.0\n";
+/// Negotiation is considered unrecognized if no protocol is found in that many written bytes
+const MAX_NEGOTIATION_BYTES: usize = 1024;
+
+/// Receives traffic statistics of muxed streams labeled by the stream protocol
+pub trait StreamObserver: Send + Sync + 'static {
+    fn stream_opened(&self, protocol: &str);
+    fn stream_closed(&self, protocol: &str);
+    fn bytes_sent(&self, protocol: &str, bytes: u64);
+    fn bytes_received(&self, protocol: &str, bytes: u64);
+}
+
+/// Wraps muxer of every connection of `transport`, so `observer` sees traffic of each stream.
+///
+/// Stream protocol is sniffed from the multistream-select messages written by this side:
+/// it's the protocol we accepted on inbound streams and the first one we proposed on outbound
+/// streams. Bytes are counted above the muxer, i.e. without encryption and muxing overhead.
+pub fn with_stream_metrics(
+    transport: Boxed<(PeerId, StreamMuxerBox)>,
+    observer: Arc<dyn StreamObserver>,
+) -> Boxed<(PeerId, StreamMuxerBox)> {
+    transport
+        .map(move |(peer_id, muxer), _| {
+            let muxer = MeteredMuxer {
+                inner: muxer,
+                observer: observer.clone(),
+            };
+            (peer_id, StreamMuxerBox::new(muxer))
+        })
+        .boxed()
+}
+
+struct MeteredMuxer {
+    inner: StreamMuxerBox,
+    observer: Arc<dyn StreamObserver>,
+}
+
+impl StreamMuxer for MeteredMuxer {
+    type Substream = MeteredSubstream;
+    type Error = io::Error;
+
+    fn poll_inbound(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::Substream, Self::Error>> {
+        let this = self.get_mut();
+        let stream = ready!(this.inner.poll_inbound_unpin(cx))?;
+        Poll::Ready(Ok(MeteredSubstream::new(stream, this.observer.clone())))
+    }
+
+    fn poll_outbound(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::Substream, Self::Error>> {
+        let this = self.get_mut();
+        let stream = ready!(this.inner.poll_outbound_unpin(cx))?;
+        Poll::Ready(Ok(MeteredSubstream::new(stream, this.observer.clone())))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().inner.poll_close_unpin(cx)
+    }
+
+    fn poll(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<StreamMuxerEvent, Self::Error>> {
+        self.get_mut().inner.poll_unpin(cx)
+    }
+}
+
+enum Negotiation {
+    /// Bytes written so far
+    InProgress(Vec<u8>),
+    Done(String),
+}
+
+struct MeteredSubstream {
+    inner: SubstreamBox,
+    observer: Arc<dyn StreamObserver>,
+    negotiation: Negotiation,
+    /// Bytes transferred before the protocol is known
+    sent: u64,
+    received: u64,
+}
+
+impl MeteredSubstream {
+    fn new(inner: SubstreamBox, observer: Arc<dyn StreamObserver>) -> Self {
+        Self {
+            inner,
+            observer,
+            negotiation: Negotiation::InProgress(vec![]),
+            sent: 0,
+            received: 0,
+        }
+    }
+
+    fn on_sent(&mut self, data: &[u8]) {
+        if let Negotiation::InProgress(written) = &mut self.negotiation {
+            written.extend_from_slice(data);
+            let protocol = match parse_protocol(written) {
+                Parsed::Protocol(protocol) => Some(protocol),
+                Parsed::Unrecognized => Some(UNKNOWN_PROTOCOL.to_string()),
+                Parsed::Incomplete if written.len() > MAX_NEGOTIATION_BYTES => {
+                    Some(UNKNOWN_PROTOCOL.to_string())
+                }
+                Parsed::Incomplete => None,
+            };
+            if let Some(protocol) = protocol {
+                self.negotiated(protocol);
+            }
+        }
+
+        match &self.negotiation {
+            Negotiation::Done(protocol) => self.observer.bytes_sent(protocol, data.len() as u64),
+            Negotiation::InProgress(_) => self.sent += data.len() as u64,
+        }
+    }
+
+    fn on_received(&mut self, bytes: usize) {
+        match &self.negotiation {
+            Negotiation::Done(protocol) => self.observer.bytes_received(protocol, bytes as u64),
+            Negotiation::InProgress(_) => self.received += bytes as u64,
+        }
+    }
+
+    fn negotiated(&mut self, protocol: String) {
+        self.observer.stream_opened(&protocol);
+        self.flush_pending(&protocol);
+        self.negotiation = Negotiation::Done(protocol);
+    }
+
+    fn flush_pending(&mut self, protocol: &str) {
+        if self.sent > 0 {
+            self.observer
+                .bytes_sent(protocol, std::mem::take(&mut self.sent));
+        }
+        if self.received > 0 {
+            self.observer
+                .bytes_received(protocol, std::mem::take(&mut self.received));
+        }
+    }
+}
+
+impl Drop for MeteredSubstream {
+    fn drop(&mut self) {
+        match &self.negotiation {
+            Negotiation::Done(protocol) => self.observer.stream_closed(protocol),
+            Negotiation::InProgress(_) => self.flush_pending(UNKNOWN_PROTOCOL),
+        }
+    }
+}
+
+impl AsyncRead for MeteredSubstream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let read = ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+        this.on_received(read);
+        Poll::Ready(Ok(read))
+    }
+}
+
+impl AsyncWrite for MeteredSubstream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let written = ready!(Pin::new(&mut this.inner).poll_write(cx, buf))?;
+        this.on_sent(&buf[..written]);
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_close(cx)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Parsed {
+    Incomplete,
+    Protocol(String),
+    Unrecognized,
+}
+
+/// Finds the protocol in length-prefixed multistream-select messages,
+/// skipping the header and `na`/`ls` messages
+fn parse_protocol(mut data: &[u8]) -> Parsed {
+    loop {
+        let Some((len, rest)) = decode_uvarint(data) else {
+            return Parsed::Incomplete;
+        };
+        if rest.len() < len {
+            return Parsed::Incomplete;
+        }
+        let (message, rest) = rest.split_at(len);
+        data = rest;
+
+        match message {
+            MULTISTREAM_HEADER | b"na\n" | b"ls\n" => continue,
+            [b'/', protocol @ .., b'\n'] => {
+                let protocol = String::from_utf8_lossy(protocol);
+                return Parsed::Protocol(format!("/{protocol}"));
+            }
+            _ => return Parsed::Unrecognized,
+        }
+    }
+}
+
+/// Decodes unsigned varint of at most 4 bytes, returns `None` if it isn't complete
+fn decode_uvarint(data: &[u8]) -> Option<(usize, &[u8])> {
+    let mut value = 0;
+    for (i, byte) in data.iter().enumerate().take(4) {
+        value |= ((byte & 0x7f) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, &data[i + 1..]));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use futures::io::Cursor;
+    use futures::AsyncWriteExt;
+
+    use super::*;
+
+    fn message(data: &[u8]) -> Vec<u8> {
+        let mut message = vec![data.len() as u8];
+        message.extend_from_slice(data);
+        message
+    }
+
+    #[derive(Default)]
+    struct TestObserver {
+        active: Mutex<HashMap<String, i64>>,
+        sent: Mutex<HashMap<String, u64>>,
+    }
+
+    impl StreamObserver for TestObserver {
+        fn stream_opened(&self, protocol: &str) {
+            *self
+                .active
+                .lock()
+                .unwrap()
+                .entry(protocol.into())
+                .or_default() += 1;
+        }
+
+        fn stream_closed(&self, protocol: &str) {
+            *self
+                .active
+                .lock()
+                .unwrap()
+                .entry(protocol.into())
+                .or_default() -= 1;
+        }
+
+        fn bytes_sent(&self, protocol: &str, bytes: u64) {
+            *self
+                .sent
+                .lock()
+                .unwrap()
+                .entry(protocol.into())
+                .or_default() += bytes;
+        }
+
+        fn bytes_received(&self, _protocol: &str, _bytes: u64) {}
+    }
+
+    #[test]
+    fn parse_negotiation() {
+        let mut data = message(MULTISTREAM_HEADER);
+        assert_eq!(parse_protocol(&data), Parsed::Incomplete);
+
+        data.extend(message(b"na\n"));
+        data.extend(message(b"/meshsub/1.1.0\n")[..4].iter());
+        assert_eq!(parse_protocol(&data), Parsed::Incomplete);
+
+        let mut data = message(MULTISTREAM_HEADER);
+        data.extend(message(b"/meshsub/1.1.0\n"));
+        data.extend(b"payload");
+        assert_eq!(
+            parse_protocol(&data),
+            Parsed::Protocol("/meshsub/1.1.0".to_string())
+        );
+
+        assert_eq!(parse_protocol(&message(b"hello\n")), Parsed::Unrecognized);
+    }
+
+    #[test]
+    fn count_stream_bytes() {
+        let observer = Arc::new(TestObserver::default());
+        let mut stream =
+            MeteredSubstream::new(SubstreamBox::new(Cursor::new(vec![])), observer.clone());
+
+        let header = message(MULTISTREAM_HEADER);
+        let protocol = message(b"/fluence/particle/2.0.0\n");
+        futures::executor::block_on(async {
+            stream.write_all(&header).await.unwrap();
+            stream.write_all(&protocol).await.unwrap();
+            stream.write_all(b"particle").await.unwrap();
+        });
+
+        let expected = (header.len() + protocol.len() + b"particle".len()) as u64;
+        assert_eq!(
+            observer.sent.lock().unwrap()["/fluence/particle/2.0.0"],
+            expected
+        );
+        assert_eq!(
+            observer.active.lock().unwrap()["/fluence/particle/2.0.0"],
+            1
+        );
+
+        drop(stream);
+        assert_eq!(
+            observer.active.lock().unwrap()["/fluence/particle/2.0.0"],
+            0
+        );
+    }
+}
@@ -0,0 +1,401 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::future::{BoxFuture, Either};
+use futures::{AsyncRead, AsyncWrite, FutureExt, TryFutureExt};
+use futures_rustls::rustls;
+use futures_rustls::server::TlsStream;
+use futures_rustls::TlsAcceptor;
+use libp2p::core::muxing::StreamMuxerBox;
+use libp2p::core::transport::{Boxed, ListenerId, TransportError, TransportEvent};
+use libp2p::core::Multiaddr;
+use libp2p::identity::Keypair;
+use libp2p::multiaddr::Protocol;
+use libp2p::{PeerId, Transport};
+use parking_lot::RwLock;
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+
+use crate::pnet::PrivateNetwork;
+use crate::transport::{configure_transport, dns_tcp_transport};
+
+/// ALPN protocol used by ACME TLS-ALPN-01 challenge, see RFC 8737
+pub const ACME_TLS_ALPN_NAME: &[u8] = b"acme-tls/1";
+
+/// Certificate storage that can be updated while the listener is running,
+/// so renewed certificates are served without restarting the node.
+#[derive(Default)]
+pub struct TlsCertificateResolver {
+    certificate: RwLock<Option<Arc<CertifiedKey>>>,
+    /// TLS-ALPN-01 challenge certificates by domain
+    challenges: RwLock<HashMap<String, Arc<CertifiedKey>>>,
+}
+
+impl TlsCertificateResolver {
+    /// Sets certificate served to clients. Both arguments are PEM-encoded.
+    pub fn set_certificate(&self, certificate_chain: &[u8], private_key: &[u8]) -> io::Result<()> {
+        let certificate = certified_key(certificate_chain, private_key)?;
+        *self.certificate.write() = Some(Arc::new(certificate));
+        Ok(())
+    }
+
+    pub fn has_certificate(&self) -> bool {
+        self.certificate.read().is_some()
+    }
+
+    /// Sets certificate served for `domain` to ACME validation requests. Both arguments are PEM-encoded.
+    pub fn set_challenge_certificate(
+        &self,
+        domain: String,
+        certificate: &[u8],
+        private_key: &[u8],
+    ) -> io::Result<()> {
+        let certificate = certified_key(certificate, private_key)?;
+        self.challenges
+            .write()
+            .insert(domain, Arc::new(certificate));
+        Ok(())
+    }
+
+    pub fn remove_challenge_certificate(&self, domain: &str) {
+        self.challenges.write().remove(domain);
+    }
+}
+
+impl ResolvesServerCert for TlsCertificateResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let is_acme_challenge = client_hello
+            .alpn()
+            .map_or(false, |mut alpn| alpn.any(|p| p == ACME_TLS_ALPN_NAME));
+
+        if is_acme_challenge {
+            let domain = client_hello.server_name()?;
+            self.challenges.read().get(domain).cloned()
+        } else {
+            self.certificate.read().clone()
+        }
+    }
+}
+
+fn certified_key(certificate_chain: &[u8], private_key: &[u8]) -> io::Result<CertifiedKey> {
+    let certificates = rustls_pemfile::certs(&mut &*certificate_chain)?
+        .into_iter()
+        .map(rustls::Certificate)
+        .collect::<Vec<_>>();
+    if certificates.is_empty() {
+        return Err(invalid_data("no certificates found in PEM"));
+    }
+
+    let key = rustls_pemfile::read_all(&mut &*private_key)?
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::ECKey(key) => Some(rustls::PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| invalid_data("no private key found in PEM"))?;
+    let key = rustls::sign::any_supported_type(&key).map_err(invalid_data)?;
+
+    Ok(CertifiedKey::new(certificates, key))
+}
+
+fn invalid_data(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// Creates TLS server config which takes certificates from `resolver`
+pub fn tls_server_config(resolver: Arc<TlsCertificateResolver>) -> rustls::ServerConfig {
+    let mut config = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_cert_resolver(resolver);
+    config.alpn_protocols = vec![b"http/1.1".to_vec(), ACME_TLS_ALPN_NAME.to_vec()];
+    config
+}
+
+/// TLS server config with a fixed certificate for http listeners.
+/// With `client_ca`, clients must present a certificate signed by one of its CA certificates,
+/// unless `client_auth_optional` is set, then clients without a certificate are accepted too.
+/// All arguments are PEM-encoded.
+pub fn static_tls_server_config(
+    certificate_chain: &[u8],
+    private_key: &[u8],
+    client_ca: Option<&[u8]>,
+    client_auth_optional: bool,
+) -> io::Result<rustls::ServerConfig> {
+    let resolver = TlsCertificateResolver::default();
+    resolver.set_certificate(certificate_chain, private_key)?;
+    let builder = rustls::ServerConfig::builder().with_safe_defaults();
+    let builder = match client_ca {
+        Some(client_ca) => {
+            let mut roots = rustls::RootCertStore::empty();
+            for certificate in rustls_pemfile::certs(&mut &*client_ca)? {
+                roots
+                    .add(&rustls::Certificate(certificate))
+                    .map_err(invalid_data)?;
+            }
+            if roots.is_empty() {
+                return Err(invalid_data("no CA certificates found in PEM"));
+            }
+            if client_auth_optional {
+                let verifier = rustls::server::AllowAnyAnonymousOrAuthenticatedClient::new(roots);
+                builder.with_client_cert_verifier(verifier.boxed())
+            } else {
+                let verifier = rustls::server::AllowAnyAuthenticatedClient::new(roots);
+                builder.with_client_cert_verifier(verifier.boxed())
+            }
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let mut config = builder.with_cert_resolver(Arc::new(resolver));
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+/// Same as [crate::build_transport] for network transport, but websocket listeners
+/// with `/tls/ws` suffix are served over TLS with certificates from `tls_config`
+pub fn build_network_transport_with_tls(
+    key_pair: &Keypair,
+    socket_timeout: Duration,
+    tls_config: rustls::ServerConfig,
+    pnet: Option<PrivateNetwork>,
+) -> Boxed<(PeerId, StreamMuxerBox)> {
+    let transport = {
+        let tls = TlsTransport::new(dns_tcp_transport(), tls_config);
+        let mut websocket = libp2p::websocket::WsConfig::new(tls);
+        websocket.set_tls_config(libp2p::websocket::tls::Config::client());
+        websocket.or_transport(dns_tcp_transport())
+    };
+
+    configure_transport(transport, key_pair, socket_timeout, pnet)
+}
+
+#[derive(Debug)]
+pub enum TlsTransportError<E> {
+    Transport(E),
+    Tls(io::Error),
+}
+
+impl<E: Display> Display for TlsTransportError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TlsTransportError::Transport(err) => write!(f, "{err}"),
+            TlsTransportError::Tls(err) => write!(f, "TLS handshake failed: {err}"),
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for TlsTransportError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TlsTransportError::Transport(err) => Some(err),
+            TlsTransportError::Tls(err) => Some(err),
+        }
+    }
+}
+
+/// Terminates TLS on listeners with `/tls` suffix, e.g. `/ip4/0.0.0.0/tcp/443/tls`.
+///
+/// Meant to be wrapped into websocket transport, so `/ip4/0.0.0.0/tcp/443/tls/ws` is served
+/// with certificates from [TlsCertificateResolver] instead of the static libp2p websocket TLS config.
+/// Dialing and other listeners are passed to the inner transport as is.
+pub struct TlsTransport<T> {
+    inner: T,
+    acceptor: TlsAcceptor,
+    tls_listeners: HashSet<ListenerId>,
+}
+
+impl<T> TlsTransport<T> {
+    pub fn new(inner: T, config: rustls::ServerConfig) -> Self {
+        Self {
+            inner,
+            acceptor: TlsAcceptor::from(Arc::new(config)),
+            tls_listeners: HashSet::new(),
+        }
+    }
+}
+
+impl<T, C> Transport for TlsTransport<T>
+where
+    T: Transport<Output = C> + Unpin,
+    T::ListenerUpgrade: Send + 'static,
+    T::Dial: Send + 'static,
+    T::Error: Send + 'static,
+    C: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Output = Either<C, TlsStream<C>>;
+    type Error = TlsTransportError<T::Error>;
+    type ListenerUpgrade = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+    type Dial = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn listen_on(
+        &mut self,
+        id: ListenerId,
+        mut addr: Multiaddr,
+    ) -> Result<(), TransportError<Self::Error>> {
+        let tls = matches!(addr.iter().last(), Some(Protocol::Tls));
+        if tls {
+            addr.pop();
+        }
+
+        self.inner
+            .listen_on(id, addr)
+            .map_err(|e| e.map(TlsTransportError::Transport))?;
+        if tls {
+            self.tls_listeners.insert(id);
+        }
+
+        Ok(())
+    }
+
+    fn remove_listener(&mut self, id: ListenerId) -> bool {
+        self.tls_listeners.remove(&id);
+        self.inner.remove_listener(id)
+    }
+
+    fn dial(&mut self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        if addr.iter().any(|p| matches!(p, Protocol::Tls)) {
+            return Err(TransportError::MultiaddrNotSupported(addr));
+        }
+
+        let dial = self
+            .inner
+            .dial(addr)
+            .map_err(|e| e.map(TlsTransportError::Transport))?;
+        Ok(dial
+            .map_ok(Either::Left)
+            .map_err(TlsTransportError::Transport)
+            .boxed())
+    }
+
+    fn dial_as_listener(
+        &mut self,
+        addr: Multiaddr,
+    ) -> Result<Self::Dial, TransportError<Self::Error>> {
+        if addr.iter().any(|p| matches!(p, Protocol::Tls)) {
+            return Err(TransportError::MultiaddrNotSupported(addr));
+        }
+
+        let dial = self
+            .inner
+            .dial_as_listener(addr)
+            .map_err(|e| e.map(TlsTransportError::Transport))?;
+        Ok(dial
+            .map_ok(Either::Left)
+            .map_err(TlsTransportError::Transport)
+            .boxed())
+    }
+
+    fn poll(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<TransportEvent<Self::ListenerUpgrade, Self::Error>> {
+        let event = match Pin::new(&mut self.inner).poll(cx) {
+            Poll::Ready(event) => event,
+            Poll::Pending => return Poll::Pending,
+        };
+
+        let event = match event {
+            TransportEvent::Incoming {
+                listener_id,
+                upgrade,
+                mut local_addr,
+                mut send_back_addr,
+            } => {
+                let upgrade = if self.tls_listeners.contains(&listener_id) {
+                    local_addr.push(Protocol::Tls);
+                    send_back_addr.push(Protocol::Tls);
+
+                    let acceptor = self.acceptor.clone();
+                    upgrade
+                        .map_err(TlsTransportError::Transport)
+                        .and_then(move |conn| {
+                            acceptor
+                                .accept(conn)
+                                .map_ok(Either::Right)
+                                .map_err(TlsTransportError::Tls)
+                        })
+                        .boxed()
+                } else {
+                    upgrade
+                        .map_ok(Either::Left)
+                        .map_err(TlsTransportError::Transport)
+                        .boxed()
+                };
+
+                TransportEvent::Incoming {
+                    listener_id,
+                    upgrade,
+                    local_addr,
+                    send_back_addr,
+                }
+            }
+            TransportEvent::NewAddress {
+                listener_id,
+                mut listen_addr,
+            } => {
+                if self.tls_listeners.contains(&listener_id) {
+                    listen_addr.push(Protocol::Tls);
+                }
+                TransportEvent::NewAddress {
+                    listener_id,
+                    listen_addr,
+                }
+            }
+            TransportEvent::AddressExpired {
+                listener_id,
+                mut listen_addr,
+            } => {
+                if self.tls_listeners.contains(&listener_id) {
+                    listen_addr.push(Protocol::Tls);
+                }
+                TransportEvent::AddressExpired {
+                    listener_id,
+                    listen_addr,
+                }
+            }
+            TransportEvent::ListenerClosed {
+                listener_id,
+                reason,
+            } => {
+                self.tls_listeners.remove(&listener_id);
+                TransportEvent::ListenerClosed {
+                    listener_id,
+                    reason: reason.map_err(TlsTransportError::Transport),
+                }
+            }
+            TransportEvent::ListenerError { listener_id, error } => TransportEvent::ListenerError {
+                listener_id,
+                error: TlsTransportError::Transport(error),
+            },
+        };
+
+        Poll::Ready(event)
+    }
+
+    fn address_translation(&self, listen: &Multiaddr, observed: &Multiaddr) -> Option<Multiaddr> {
+        self.inner.address_translation(listen, observed)
+    }
+}
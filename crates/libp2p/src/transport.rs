@@ -16,6 +16,7 @@
 
 use std::time::Duration;
 
+use futures::future::Either;
 use futures::{AsyncRead, AsyncWrite};
 use libp2p::core::muxing::StreamMuxerBox;
 use libp2p::core::transport::{Boxed, MemoryTransport};
@@ -26,14 +27,21 @@ use libp2p::tcp::{tokio::Tcp as TokioTcp, Config as GenTcpConfig};
 use libp2p::{core, identity::Keypair, PeerId, Transport as NetworkTransport};
 use serde::{Deserialize, Serialize};
 
+use crate::pnet::PrivateNetwork;
+
+/// Builds transport of the given kind. If `pnet` is set, only peers with the same
+/// pre-shared key are able to connect.
 pub fn build_transport(
     transport: Transport,
     key_pair: &Keypair,
     timeout: Duration,
+    pnet: Option<PrivateNetwork>,
 ) -> Boxed<(PeerId, StreamMuxerBox)> {
     match transport {
-        Transport::Network => build_network_transport(key_pair, timeout),
-        Transport::Memory => build_memory_transport(key_pair, timeout),
+        Transport::Network => build_network_transport(key_pair, timeout, pnet),
+        Transport::Memory => {
+            configure_transport(MemoryTransport::default(), key_pair, timeout, pnet)
+        }
     }
 }
 
@@ -44,29 +52,31 @@ pub fn build_transport(
 pub fn build_network_transport(
     key_pair: &Keypair,
     socket_timeout: Duration,
+    pnet: Option<PrivateNetwork>,
 ) -> Boxed<(PeerId, StreamMuxerBox)> {
-    let tcp = || {
-        let tcp = TcpTransport::<TokioTcp>::new(GenTcpConfig::default().nodelay(true));
-
-        TokioDnsConfig::system(tcp).expect("Can't build DNS")
-    };
-
     let transport = {
-        let mut websocket = libp2p::websocket::WsConfig::new(tcp());
+        let mut websocket = libp2p::websocket::WsConfig::new(dns_tcp_transport());
         websocket.set_tls_config(libp2p::websocket::tls::Config::client());
-        websocket.or_transport(tcp())
+        websocket.or_transport(dns_tcp_transport())
     };
 
-    configure_transport(transport, key_pair, socket_timeout)
+    configure_transport(transport, key_pair, socket_timeout, pnet)
+}
+
+pub(crate) fn dns_tcp_transport() -> TokioDnsConfig<TcpTransport<TokioTcp>> {
+    let tcp = TcpTransport::<TokioTcp>::new(GenTcpConfig::default().nodelay(true));
+
+    TokioDnsConfig::system(tcp).expect("Can't build DNS")
 }
 
 pub fn configure_transport<T, C>(
     transport: T,
     key_pair: &Keypair,
     transport_timeout: Duration,
+    pnet: Option<PrivateNetwork>,
 ) -> Boxed<(PeerId, StreamMuxerBox)>
 where
-    T: NetworkTransport<Output = C> + Send + Sync + Unpin + 'static,
+    T: NetworkTransport<Output = C> + Send + Unpin + 'static,
     C: AsyncRead + AsyncWrite + Unpin + Send + Unpin + 'static,
     T::Dial: Send + Unpin + 'static,
     T::ListenerUpgrade: Send + Unpin + 'static,
@@ -84,6 +94,14 @@ where
 
     let auth_config = libp2p::noise::Config::new(key_pair).expect("create noise keypair");
 
+    // pnet encryption goes below everything else, so peers without the key can't even negotiate protocols
+    let transport = transport.and_then(move |socket, endpoint| async move {
+        match pnet {
+            Some(pnet) => pnet.handshake(socket, endpoint).await.map(Either::Left),
+            None => Ok(Either::Right(socket)),
+        }
+    });
+
     transport
         .upgrade(core::upgrade::Version::V1)
         .authenticate(auth_config)
@@ -92,13 +110,48 @@ where
         .boxed()
 }
 
+/// Extends `transport` with relay v2 client, so the node can dial and listen on `/p2p-circuit` addresses
+pub fn with_relay_client_transport(
+    transport: Boxed<(PeerId, StreamMuxerBox)>,
+    relay_transport: libp2p::relay::client::Transport,
+    key_pair: &Keypair,
+    transport_timeout: Duration,
+    pnet: Option<PrivateNetwork>,
+) -> Boxed<(PeerId, StreamMuxerBox)> {
+    configure_transport(relay_transport, key_pair, transport_timeout, pnet)
+        .or_transport(transport)
+        .map(|either, _| match either {
+            Either::Left(output) => output,
+            Either::Right(output) => output,
+        })
+        .boxed()
+}
+
+/// Extends `transport` with QUIC, which is encrypted and multiplexed by the protocol itself,
+/// so pre-shared key of a private network can't be applied to it
+pub fn with_quic_transport(
+    transport: Boxed<(PeerId, StreamMuxerBox)>,
+    key_pair: &Keypair,
+) -> Boxed<(PeerId, StreamMuxerBox)> {
+    let quic = libp2p::quic::tokio::Transport::new(libp2p::quic::Config::new(key_pair))
+        .map(|(peer_id, conn), _| (peer_id, StreamMuxerBox::new(conn)));
+
+    transport
+        .or_transport(quic)
+        .map(|either, _| match either {
+            Either::Left(output) => output,
+            Either::Right(output) => output,
+        })
+        .boxed()
+}
+
 pub fn build_memory_transport(
     key_pair: &Keypair,
     transport_timeout: Duration,
 ) -> Boxed<(PeerId, StreamMuxerBox)> {
     let transport = MemoryTransport::default();
 
-    configure_transport(transport, key_pair, transport_timeout)
+    configure_transport(transport, key_pair, transport_timeout, None)
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, Copy)]
@@ -0,0 +1,98 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::path::Path;
+
+use futures::future::Either;
+use libp2p::core::muxing::StreamMuxerBox;
+use libp2p::core::transport::Boxed;
+use libp2p::multiaddr::Protocol;
+use libp2p::{identity::Keypair, PeerId, Transport as NetworkTransport};
+
+/// Extends `transport` with WebRTC (browser-to-server) support.
+///
+/// WebRTC connections are encrypted and multiplexed by the protocol itself,
+/// so they bypass the noise/yamux upgrade applied to TCP and websocket connections.
+pub fn with_webrtc_transport(
+    transport: Boxed<(PeerId, StreamMuxerBox)>,
+    key_pair: &Keypair,
+    certificate: WebRtcCertificate,
+) -> Boxed<(PeerId, StreamMuxerBox)> {
+    let webrtc = libp2p_webrtc::tokio::Transport::new(key_pair.clone(), certificate)
+        .map(|(peer_id, conn), _| (peer_id, StreamMuxerBox::new(conn)));
+
+    transport
+        .or_transport(webrtc)
+        .map(|either, _| match either {
+            Either::Left(output) => output,
+            Either::Right(output) => output,
+        })
+        .boxed()
+}
+
+pub type WebRtcCertificate = libp2p_webrtc::tokio::Certificate;
+
+/// Loads WebRTC certificate from `path`, or generates a new one and stores it there.
+///
+/// Certificate must be persistent: its hash is a part of the advertised `/webrtc-direct` address,
+/// so browsers won't be able to reconnect if it changes between restarts.
+pub fn load_or_create_webrtc_certificate(path: &Path) -> std::io::Result<WebRtcCertificate> {
+    use std::io::{Error, ErrorKind};
+
+    if path.exists() {
+        let pem = std::fs::read_to_string(path)?;
+        return WebRtcCertificate::from_pem(&pem).map_err(|err| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("invalid WebRTC certificate at {}: {err}", path.display()),
+            )
+        });
+    }
+
+    let certificate = WebRtcCertificate::generate(&mut rand::thread_rng())
+        .map_err(|err| Error::new(ErrorKind::Other, err))?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    fs_utils::write_atomically(path, certificate.serialize_pem().as_bytes())?;
+
+    Ok(certificate)
+}
+
+/// `/certhash` protocol to append to `/webrtc-direct` addresses so browsers can verify the node
+pub fn webrtc_certhash(certificate: &WebRtcCertificate) -> Protocol<'static> {
+    Protocol::Certhash(certificate.fingerprint().to_multihash())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    #[test]
+    fn webrtc_certificate_is_persisted() {
+        let dir = tempfile::tempdir().expect("create tempdir");
+        let path = dir.path().join("webrtc").join("certificate.pem");
+
+        let created = load_or_create_webrtc_certificate(&path).expect("create certificate");
+        let permissions = std::fs::metadata(&path).expect("stat").permissions();
+        assert_eq!(permissions.mode() & 0o777, 0o600);
+
+        let loaded = load_or_create_webrtc_certificate(&path).expect("load certificate");
+        assert_eq!(webrtc_certhash(&created), webrtc_certhash(&loaded));
+    }
+}
@@ -0,0 +1,495 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+
+use serde_json::{json, Value as JValue};
+use tokio::sync::mpsc;
+
+/// Log included into the next mined block
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MockLog {
+    pub address: String,
+    pub topics: Vec<String>,
+    pub data: String,
+}
+
+impl MockLog {
+    pub fn new(address: impl Into<String>, topics: Vec<String>, data: impl Into<String>) -> Self {
+        Self {
+            address: address.into(),
+            topics,
+            data: data.into(),
+        }
+    }
+}
+
+/// JSON-RPC error returned instead of the result of a method
+#[derive(Clone, Debug)]
+pub struct Failure {
+    pub code: i32,
+    pub message: String,
+    /// How many of the next calls fail, all of them if not set
+    pub times: Option<usize>,
+}
+
+impl Failure {
+    pub fn always(code: i32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            times: None,
+        }
+    }
+
+    pub fn times(times: usize, code: i32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            times: Some(times),
+        }
+    }
+}
+
+/// Result of `eth_call`: either returned data or revert data
+pub(crate) type CallResult = Result<String, String>;
+
+#[derive(Clone, Debug)]
+struct Block {
+    number: u64,
+    hash: String,
+    parent_hash: String,
+    timestamp: u64,
+    logs: Vec<MockLog>,
+    transactions: Vec<String>,
+}
+
+/// Filter of `eth_getLogs` and `logs` subscriptions
+#[derive(Clone, Debug, Default)]
+pub(crate) struct LogFilter {
+    addresses: Vec<String>,
+    /// Topics by position, `None` matches any topic
+    topics: Vec<Option<Vec<String>>>,
+}
+
+impl LogFilter {
+    pub(crate) fn parse(filter: Option<&JValue>) -> Self {
+        let Some(filter) = filter else {
+            return Self::default();
+        };
+        let addresses = strings(filter.get("address"));
+        let topics = filter
+            .get("topics")
+            .and_then(JValue::as_array)
+            .map(|topics| {
+                topics
+                    .iter()
+                    .map(|topic| (!topic.is_null()).then(|| strings(Some(topic))))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self { addresses, topics }
+    }
+
+    fn matches(&self, log: &MockLog) -> bool {
+        let address_matches = self.addresses.is_empty()
+            || self
+                .addresses
+                .iter()
+                .any(|address| address.eq_ignore_ascii_case(&log.address));
+        let topics_match = self.topics.iter().enumerate().all(|(i, expected)| {
+            let Some(expected) = expected else {
+                return true;
+            };
+            log.topics
+                .get(i)
+                .is_some_and(|topic| expected.iter().any(|e| e.eq_ignore_ascii_case(topic)))
+        });
+        address_matches && topics_match
+    }
+}
+
+/// Either a single string or an array of strings
+fn strings(value: Option<&JValue>) -> Vec<String> {
+    match value {
+        Some(JValue::String(s)) => vec![s.clone()],
+        Some(JValue::Array(values)) => values
+            .iter()
+            .filter_map(|v| v.as_str().map(str::to_string))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+pub(crate) enum SubscriptionKind {
+    NewHeads,
+    Logs(LogFilter),
+}
+
+pub(crate) struct Subscriber {
+    pub(crate) kind: SubscriptionKind,
+    pub(crate) sender: mpsc::UnboundedSender<JValue>,
+}
+
+pub(crate) struct ChainState {
+    pub(crate) chain_id: u64,
+    blocks: Vec<Block>,
+    pending_logs: Vec<MockLog>,
+    pending_transactions: Vec<String>,
+    /// Receipts of mined transactions
+    receipts: HashMap<String, JValue>,
+    sent_transactions: Vec<String>,
+    /// Status of receipts of transactions mined afterwards
+    pub(crate) receipt_status: bool,
+    /// Results of `eth_call` by contract address and function selector
+    calls: HashMap<(String, String), CallResult>,
+    failures: HashMap<String, Failure>,
+    requests: HashMap<String, usize>,
+    subscribers: Vec<Subscriber>,
+    /// Incremented on every reorg, so replaced blocks get new hashes
+    fork: u64,
+}
+
+impl ChainState {
+    pub(crate) fn new(chain_id: u64) -> Self {
+        let genesis = Block {
+            number: 0,
+            hash: block_hash(0, 0),
+            parent_hash: format!("0x{}", "0".repeat(64)),
+            timestamp: now_millis::now_sec(),
+            logs: vec![],
+            transactions: vec![],
+        };
+        Self {
+            chain_id,
+            blocks: vec![genesis],
+            pending_logs: vec![],
+            pending_transactions: vec![],
+            receipts: <_>::default(),
+            sent_transactions: vec![],
+            receipt_status: true,
+            calls: <_>::default(),
+            failures: <_>::default(),
+            requests: <_>::default(),
+            subscribers: vec![],
+            fork: 0,
+        }
+    }
+
+    /// Counts the request and returns the failure injected into `method`, if any
+    pub(crate) fn on_request(&mut self, method: &str) -> Result<(), Failure> {
+        *self.requests.entry(method.to_string()).or_default() += 1;
+
+        let Some(failure) = self.failures.get_mut(method) else {
+            return Ok(());
+        };
+        let result = Err(failure.clone());
+        match &mut failure.times {
+            Some(1) => {
+                self.failures.remove(method);
+            }
+            Some(times) => *times -= 1,
+            None => {}
+        }
+        result
+    }
+
+    pub(crate) fn request_count(&self, method: &str) -> usize {
+        self.requests.get(method).copied().unwrap_or_default()
+    }
+
+    pub(crate) fn set_failure(&mut self, method: &str, failure: Option<Failure>) {
+        match failure {
+            Some(failure) => self.failures.insert(method.to_string(), failure),
+            None => self.failures.remove(method),
+        };
+    }
+
+    pub(crate) fn set_call(&mut self, to: &str, selector: &str, result: CallResult) {
+        self.calls
+            .insert((to.to_lowercase(), selector.to_lowercase()), result);
+    }
+
+    pub(crate) fn call(&self, to: &str, data: &str) -> Option<CallResult> {
+        let selector = data.get(..10).unwrap_or(data);
+        self.calls
+            .get(&(to.to_lowercase(), selector.to_lowercase()))
+            .cloned()
+    }
+
+    pub(crate) fn add_log(&mut self, log: MockLog) {
+        self.pending_logs.push(log);
+    }
+
+    pub(crate) fn send_transaction(&mut self, raw: String) -> String {
+        self.sent_transactions.push(raw);
+        let hash = format!("0x{:064x}", self.sent_transactions.len());
+        self.pending_transactions.push(hash.clone());
+        hash
+    }
+
+    pub(crate) fn sent_transactions(&self) -> Vec<String> {
+        self.sent_transactions.clone()
+    }
+
+    pub(crate) fn transaction_count(&self) -> u64 {
+        self.sent_transactions.len() as u64
+    }
+
+    pub(crate) fn receipt(&self, hash: &str) -> Option<JValue> {
+        self.receipts.get(hash).cloned()
+    }
+
+    pub(crate) fn block_number(&self) -> u64 {
+        self.latest().number
+    }
+
+    fn latest(&self) -> &Block {
+        self.blocks.last().expect("genesis block is never removed")
+    }
+
+    /// Block by number or tag: `latest`, `pending`, `earliest` or hex number
+    pub(crate) fn block(&self, tag: &str) -> Option<JValue> {
+        let block = match tag {
+            "latest" | "pending" | "safe" | "finalized" => Some(self.latest()),
+            "earliest" => self.blocks.first(),
+            number => {
+                let number = parse_hex(number)?;
+                self.blocks.get(number as usize)
+            }
+        };
+        block.map(block_json)
+    }
+
+    pub(crate) fn block_tag_number(&self, tag: Option<&JValue>) -> u64 {
+        tag.and_then(JValue::as_str)
+            .and_then(|tag| match tag {
+                "earliest" => Some(0),
+                "latest" | "pending" | "safe" | "finalized" => None,
+                number => parse_hex(number),
+            })
+            .unwrap_or(self.block_number())
+    }
+
+    pub(crate) fn logs(&self, filter: &LogFilter, from: u64, to: u64) -> Vec<JValue> {
+        self.blocks
+            .iter()
+            .filter(|block| block.number >= from && block.number <= to)
+            .flat_map(|block| {
+                block
+                    .logs
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, log)| filter.matches(log))
+                    .map(|(index, log)| log_json(block, log, index, false))
+            })
+            .collect()
+    }
+
+    pub(crate) fn subscribe(&mut self, subscriber: Subscriber) {
+        self.subscribers.push(subscriber);
+    }
+
+    pub(crate) fn subscription_count(&self) -> usize {
+        self.subscribers
+            .iter()
+            .filter(|s| !s.sender.is_closed())
+            .count()
+    }
+
+    /// Closes all subscriptions as if the websocket connection was lost
+    pub(crate) fn drop_subscriptions(&mut self) {
+        self.subscribers.clear();
+    }
+
+    /// Mines a block with pending logs and transactions, returns its number
+    pub(crate) fn mine(&mut self) -> u64 {
+        let parent = self.latest();
+        let number = parent.number + 1;
+        let parent_hash = parent.hash.clone();
+        let timestamp = now_millis::now_sec().max(parent.timestamp + 1);
+        let block = Block {
+            number,
+            hash: block_hash(number, self.fork),
+            parent_hash,
+            timestamp,
+            logs: std::mem::take(&mut self.pending_logs),
+            transactions: std::mem::take(&mut self.pending_transactions),
+        };
+
+        for (index, hash) in block.transactions.iter().enumerate() {
+            let receipt = receipt_json(&block, hash, index, self.receipt_status);
+            self.receipts.insert(hash.clone(), receipt);
+        }
+        self.notify_heads(&block);
+        for (index, log) in block.logs.iter().enumerate() {
+            self.notify_log(&block, log, index, false);
+        }
+
+        self.blocks.push(block);
+        number
+    }
+
+    /// Replaces the last `depth` blocks with new ones. Logs of the replaced blocks are
+    /// notified as removed and dropped, their transactions are mined again in the first new block.
+    pub(crate) fn reorg(&mut self, depth: usize) {
+        let depth = depth.min(self.blocks.len() - 1);
+        let removed = self.blocks.split_off(self.blocks.len() - depth);
+        self.fork += 1;
+
+        let mut transactions = vec![];
+        for block in removed.iter().rev() {
+            for (index, log) in block.logs.iter().enumerate().rev() {
+                self.notify_log(block, log, index, true);
+            }
+            for hash in &block.transactions {
+                self.receipts.remove(hash);
+            }
+            transactions.extend(block.transactions.iter().cloned());
+        }
+        transactions.append(&mut self.pending_transactions);
+        self.pending_transactions = transactions;
+
+        for _ in 0..depth {
+            self.mine();
+        }
+    }
+
+    fn notify_heads(&mut self, block: &Block) {
+        let header = block_json(block);
+        self.subscribers.retain(|s| match &s.kind {
+            SubscriptionKind::NewHeads => s.sender.send(header.clone()).is_ok(),
+            SubscriptionKind::Logs(_) => !s.sender.is_closed(),
+        });
+    }
+
+    fn notify_log(&mut self, block: &Block, log: &MockLog, index: usize, removed: bool) {
+        let log_json = log_json(block, log, index, removed);
+        self.subscribers.retain(|s| match &s.kind {
+            SubscriptionKind::Logs(filter) if filter.matches(log) => {
+                s.sender.send(log_json.clone()).is_ok()
+            }
+            _ => !s.sender.is_closed(),
+        });
+    }
+}
+
+fn block_hash(number: u64, fork: u64) -> String {
+    format!("0x{fork:032x}{number:032x}")
+}
+
+fn parse_hex(value: &str) -> Option<u64> {
+    u64::from_str_radix(value.trim_start_matches("0x"), 16).ok()
+}
+
+fn hex(value: u64) -> String {
+    format!("0x{value:x}")
+}
+
+fn block_json(block: &Block) -> JValue {
+    json!({
+        "number": hex(block.number),
+        "hash": block.hash,
+        "parentHash": block.parent_hash,
+        "timestamp": hex(block.timestamp),
+        "baseFeePerGas": "0x1",
+        "gasLimit": "0x1c9c380",
+        "gasUsed": "0x0",
+        "difficulty": "0x0",
+        "miner": format!("0x{}", "0".repeat(40)),
+        "logsBloom": format!("0x{}", "0".repeat(512)),
+        "transactions": block.transactions,
+    })
+}
+
+fn log_json(block: &Block, log: &MockLog, index: usize, removed: bool) -> JValue {
+    json!({
+        "address": log.address,
+        "topics": log.topics,
+        "data": log.data,
+        "blockNumber": hex(block.number),
+        "blockHash": block.hash,
+        "transactionHash": block.transactions.first(),
+        "transactionIndex": "0x0",
+        "logIndex": hex(index as u64),
+        "removed": removed,
+    })
+}
+
+fn receipt_json(block: &Block, hash: &str, index: usize, success: bool) -> JValue {
+    json!({
+        "transactionHash": hash,
+        "transactionIndex": hex(index as u64),
+        "blockNumber": hex(block.number),
+        "blockHash": block.hash,
+        "status": if success { "0x1" } else { "0x0" },
+        "gasUsed": "0x5208",
+        "cumulativeGasUsed": "0x5208",
+        "effectiveGasPrice": "0x1",
+        "logs": [],
+        "logsBloom": format!("0x{}", "0".repeat(512)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_filter() {
+        let log = MockLog::new("0xAB", vec!["0x01".into(), "0x02".into()], "0x");
+        let filter = |value: JValue| LogFilter::parse(Some(&value)).matches(&log);
+
+        assert!(filter(json!({})));
+        assert!(filter(json!({"address": "0xab"})));
+        assert!(filter(
+            json!({"address": ["0xcd", "0xab"], "topics": [null, "0x02"]})
+        ));
+        assert!(filter(json!({"topics": [["0x03", "0x01"]]})));
+        assert!(!filter(json!({"address": "0xcd"})));
+        assert!(!filter(json!({"topics": ["0x01", "0x02", "0x03"]})));
+    }
+
+    #[test]
+    fn reorg_replaces_blocks() {
+        let mut state = ChainState::new(1);
+        state.add_log(MockLog::new("0xab", vec![], "0x"));
+        let hash = state.send_transaction("0xf8".into());
+        state.mine();
+        state.mine();
+        let replaced = state.block("0x1").unwrap()["hash"].clone();
+        assert!(state.receipt(&hash).is_some());
+
+        state.reorg(2);
+        assert_eq!(state.block_number(), 2);
+        assert_ne!(state.block("0x1").unwrap()["hash"], replaced);
+        // the log is dropped, the transaction is mined again
+        assert!(state.logs(&LogFilter::default(), 0, 2).is_empty());
+        assert_eq!(state.receipt(&hash).unwrap()["blockNumber"], "0x1");
+    }
+
+    #[test]
+    fn failures_run_out() {
+        let mut state = ChainState::new(1);
+        state.set_failure("eth_call", Some(Failure::times(2, -32000, "unavailable")));
+
+        assert!(state.on_request("eth_call").is_err());
+        assert!(state.on_request("eth_blockNumber").is_ok());
+        assert!(state.on_request("eth_call").is_err());
+        assert!(state.on_request("eth_call").is_ok());
+        assert_eq!(state.request_count("eth_call"), 3);
+    }
+}
@@ -0,0 +1,446 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clarity::PrivateKey;
+use jsonrpsee::server::{Server, ServerHandle};
+use jsonrpsee::types::{ErrorObject, ErrorObjectOwned, Params};
+use jsonrpsee::{PendingSubscriptionSink, RpcModule, SubscriptionMessage};
+use parking_lot::Mutex;
+use serde_json::{json, Value as JValue};
+use server_config::{ChainConfig, ChainListenerConfig};
+use tokio::sync::mpsc;
+
+use crate::chain::{ChainState, Failure, LogFilter, MockLog, Subscriber, SubscriptionKind};
+
+/// Addresses of the contracts in [MockChain::chain_config]
+pub const CORE_CONTRACT: &str = "0x0000000000000000000000000000000000000c0e";
+pub const CC_CONTRACT: &str = "0x00000000000000000000000000000000000000cc";
+pub const MARKET_CONTRACT: &str = "0x0000000000000000000000000000000000000ac7";
+/// Wallet key of [MockChain::chain_config], any transaction is accepted
+pub const WALLET_KEY: &str = "0xfdc4ba94809c7930fe4676b7d845cbf8fa5c1beae8744d959530e5073004cf3f";
+
+pub const CHAIN_ID: u64 = 31337;
+
+const INVALID_PARAMS: i32 = -32602;
+/// Code of the `execution reverted` error
+const EXECUTION_REVERTED: i32 = 3;
+
+type Chain = Arc<Mutex<ChainState>>;
+
+/// Ethereum JSON-RPC server that serves a scripted chain over http and websocket.
+/// Blocks are mined only on demand, so tests fully control what the chain listener sees.
+#[derive(Clone)]
+pub struct MockChain {
+    addr: SocketAddr,
+    state: Chain,
+    handle: ServerHandle,
+}
+
+impl MockChain {
+    /// Starts the server on a random local port
+    pub async fn start() -> eyre::Result<Self> {
+        let state = Arc::new(Mutex::new(ChainState::new(CHAIN_ID)));
+        let server = Server::builder().build("127.0.0.1:0").await?;
+        let addr = server.local_addr()?;
+        let handle = server.start(rpc_module(state.clone())?);
+        log::info!("Mock chain is listening on {addr}");
+
+        Ok(Self {
+            addr,
+            state,
+            handle,
+        })
+    }
+
+    pub fn http_endpoint(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn ws_endpoint(&self) -> String {
+        format!("ws://{}", self.addr)
+    }
+
+    /// Chain config pointing to this server, with the contracts at constant addresses
+    pub fn chain_config(&self) -> ChainConfig {
+        ChainConfig {
+            http_endpoint: self.http_endpoint(),
+            core_contract_address: CORE_CONTRACT.to_string(),
+            cc_contract_address: CC_CONTRACT.to_string(),
+            market_contract_address: MARKET_CONTRACT.to_string(),
+            network_id: CHAIN_ID,
+            wallet_key: Some(PrivateKey::from_str(WALLET_KEY).expect("valid wallet key")),
+            remote_signer: None,
+        }
+    }
+
+    pub fn listener_config(&self) -> ChainListenerConfig {
+        ChainListenerConfig {
+            ws_endpoint: self.ws_endpoint(),
+            ccp_endpoint: None,
+            proof_poll_period: Duration::from_secs(1),
+            deal_core_hold: Duration::ZERO,
+        }
+    }
+
+    /// Mines a block with the logs and transactions added since the previous one
+    pub fn mine_block(&self) -> u64 {
+        self.state.lock().mine()
+    }
+
+    pub fn mine_blocks(&self, count: usize) -> u64 {
+        let mut state = self.state.lock();
+        let mut number = state.block_number();
+        for _ in 0..count {
+            number = state.mine();
+        }
+        number
+    }
+
+    pub fn block_number(&self) -> u64 {
+        self.state.lock().block_number()
+    }
+
+    /// Adds a log to the next mined block
+    pub fn add_log(&self, log: MockLog) {
+        self.state.lock().add_log(log);
+    }
+
+    /// Replaces the last `depth` blocks with new ones, notifying their logs as removed
+    pub fn reorg(&self, depth: usize) {
+        self.state.lock().reorg(depth);
+    }
+
+    /// Sets the data returned by `eth_call` of the function `selector` of the contract `to`.
+    /// Calls that aren't set revert.
+    pub fn set_call(&self, to: &str, selector: &str, result: impl Into<String>) {
+        self.state.lock().set_call(to, selector, Ok(result.into()));
+    }
+
+    /// Makes `eth_call` of the function `selector` of the contract `to` revert with `data`
+    pub fn set_call_revert(&self, to: &str, selector: &str, data: impl Into<String>) {
+        self.state.lock().set_call(to, selector, Err(data.into()));
+    }
+
+    /// Sets whether transactions mined afterwards succeed
+    pub fn set_receipt_status(&self, success: bool) {
+        self.state.lock().receipt_status = success;
+    }
+
+    /// Raw transactions sent with `eth_sendRawTransaction`
+    pub fn sent_transactions(&self) -> Vec<String> {
+        self.state.lock().sent_transactions()
+    }
+
+    /// Makes `method` return an error instead of the result
+    pub fn fail(&self, method: &str, failure: Failure) {
+        self.state.lock().set_failure(method, Some(failure));
+    }
+
+    pub fn clear_failure(&self, method: &str) {
+        self.state.lock().set_failure(method, None);
+    }
+
+    /// Number of requests of `method`, including failed ones
+    pub fn request_count(&self, method: &str) -> usize {
+        self.state.lock().request_count(method)
+    }
+
+    /// Number of active `eth_subscribe` subscriptions
+    pub fn subscription_count(&self) -> usize {
+        self.state.lock().subscription_count()
+    }
+
+    /// Terminates all subscriptions, as a node does when it drops websocket clients
+    pub fn drop_subscriptions(&self) {
+        self.state.lock().drop_subscriptions();
+    }
+
+    pub fn stop(self) {
+        if let Err(err) = self.handle.stop() {
+            log::warn!("Mock chain is already stopped: {err}");
+        }
+    }
+}
+
+fn rpc_error(failure: Failure) -> ErrorObjectOwned {
+    ErrorObject::owned(failure.code, failure.message, None::<()>)
+}
+
+fn invalid_params(message: impl Into<String>) -> ErrorObjectOwned {
+    ErrorObject::owned(INVALID_PARAMS, message.into(), None::<()>)
+}
+
+fn param<'a>(params: &'a [JValue], index: usize) -> Option<&'a JValue> {
+    params.get(index).filter(|p| !p.is_null())
+}
+
+fn parse_params(params: Params) -> Result<Vec<JValue>, ErrorObjectOwned> {
+    if params.is_object() {
+        return Err(invalid_params("positional params expected"));
+    }
+    Ok(params.parse::<Option<Vec<JValue>>>()?.unwrap_or_default())
+}
+
+/// Registers a method that counts its requests and returns injected failures
+fn register<F>(module: &mut RpcModule<Chain>, method: &'static str, f: F) -> eyre::Result<()>
+where
+    F: Fn(&[JValue], &mut ChainState) -> Result<JValue, ErrorObjectOwned> + Send + Sync + 'static,
+{
+    module.register_method(method, move |params, chain| {
+        let params = parse_params(params)?;
+        let mut state = chain.lock();
+        state.on_request(method).map_err(rpc_error)?;
+        f(&params, &mut *state)
+    })?;
+    Ok(())
+}
+
+fn rpc_module(state: Chain) -> eyre::Result<RpcModule<Chain>> {
+    let mut module = RpcModule::new(state);
+
+    register(&mut module, "eth_chainId", |_, state| {
+        Ok(json!(format!("0x{:x}", state.chain_id)))
+    })?;
+    register(&mut module, "net_version", |_, state| {
+        Ok(json!(state.chain_id.to_string()))
+    })?;
+    register(&mut module, "eth_blockNumber", |_, state| {
+        Ok(json!(format!("0x{:x}", state.block_number())))
+    })?;
+    register(&mut module, "eth_getBlockByNumber", |params, state| {
+        let tag = param(params, 0)
+            .and_then(JValue::as_str)
+            .ok_or_else(|| invalid_params("block number expected"))?;
+        Ok(state.block(tag).unwrap_or(JValue::Null))
+    })?;
+    register(&mut module, "eth_getLogs", |params, state| {
+        let filter = param(params, 0);
+        let from = state.block_tag_number(filter.and_then(|f| f.get("fromBlock")));
+        let to = state.block_tag_number(filter.and_then(|f| f.get("toBlock")));
+        Ok(json!(state.logs(&LogFilter::parse(filter), from, to)))
+    })?;
+    register(&mut module, "eth_call", |params, state| {
+        let call = param(params, 0).ok_or_else(|| invalid_params("call expected"))?;
+        let to = call.get("to").and_then(JValue::as_str).unwrap_or_default();
+        let data = call
+            .get("data")
+            .or_else(|| call.get("input"))
+            .and_then(JValue::as_str)
+            .unwrap_or_default();
+        match state.call(to, data) {
+            Some(Ok(result)) => Ok(json!(result)),
+            Some(Err(revert)) => Err(ErrorObject::owned(
+                EXECUTION_REVERTED,
+                "execution reverted",
+                Some(revert),
+            )),
+            None => Err(ErrorObject::owned(
+                EXECUTION_REVERTED,
+                "execution reverted",
+                None::<()>,
+            )),
+        }
+    })?;
+    register(&mut module, "eth_estimateGas", |_, _| Ok(json!("0x5208")))?;
+    register(&mut module, "eth_gasPrice", |_, _| Ok(json!("0x1")))?;
+    register(&mut module, "eth_maxPriorityFeePerGas", |_, _| {
+        Ok(json!("0x1"))
+    })?;
+    register(&mut module, "eth_getTransactionCount", |_, state| {
+        Ok(json!(format!("0x{:x}", state.transaction_count())))
+    })?;
+    register(&mut module, "eth_sendRawTransaction", |params, state| {
+        let raw = param(params, 0)
+            .and_then(JValue::as_str)
+            .ok_or_else(|| invalid_params("raw transaction expected"))?;
+        Ok(json!(state.send_transaction(raw.to_string())))
+    })?;
+    register(&mut module, "eth_getTransactionReceipt", |params, state| {
+        let hash = param(params, 0)
+            .and_then(JValue::as_str)
+            .ok_or_else(|| invalid_params("transaction hash expected"))?;
+        Ok(state.receipt(hash).unwrap_or(JValue::Null))
+    })?;
+
+    module.register_subscription(
+        "eth_subscribe",
+        "eth_subscription",
+        "eth_unsubscribe",
+        |params, pending, chain| async move { subscribe(params, pending, chain).await },
+    )?;
+
+    Ok(module)
+}
+
+async fn subscribe(
+    params: Params<'static>,
+    pending: PendingSubscriptionSink,
+    chain: Arc<Chain>,
+) -> jsonrpsee::core::SubscriptionResult {
+    let kind = parse_params(params).and_then(|params| {
+        let kind = match param(&params, 0).and_then(JValue::as_str) {
+            Some("newHeads") => SubscriptionKind::NewHeads,
+            Some("logs") => SubscriptionKind::Logs(LogFilter::parse(param(&params, 1))),
+            other => {
+                return Err(invalid_params(format!(
+                    "unsupported subscription {other:?}"
+                )))
+            }
+        };
+        chain
+            .lock()
+            .on_request("eth_subscribe")
+            .map_err(rpc_error)?;
+        Ok(kind)
+    });
+    let kind = match kind {
+        Ok(kind) => kind,
+        Err(err) => {
+            pending.reject(err).await;
+            return Ok(());
+        }
+    };
+
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let sink = pending.accept().await?;
+    chain.lock().subscribe(Subscriber { kind, sender });
+
+    loop {
+        tokio::select! {
+            message = receiver.recv() => match message {
+                Some(message) => sink.send(SubscriptionMessage::from_json(&message)?).await?,
+                // subscriptions were dropped
+                None => return Err("subscription dropped by the mock chain".into()),
+            },
+            _ = sink.closed() => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonrpsee::core::client::{ClientT, Subscription, SubscriptionClientT};
+    use jsonrpsee::http_client::HttpClientBuilder;
+    use jsonrpsee::rpc_params;
+    use jsonrpsee::ws_client::WsClientBuilder;
+
+    use super::*;
+
+    const TOPIC: &str = "0x0000000000000000000000000000000000000000000000000000000000000001";
+
+    #[tokio::test]
+    async fn get_logs() {
+        let chain = MockChain::start().await.unwrap();
+        let client = HttpClientBuilder::default()
+            .build(chain.http_endpoint())
+            .unwrap();
+
+        chain.add_log(MockLog::new(CC_CONTRACT, vec![TOPIC.into()], "0x"));
+        chain.add_log(MockLog::new(MARKET_CONTRACT, vec![TOPIC.into()], "0x"));
+        chain.mine_blocks(2);
+
+        let number: String = client
+            .request("eth_blockNumber", rpc_params![])
+            .await
+            .unwrap();
+        assert_eq!(number, "0x2");
+
+        let filter = json!({"fromBlock": "0x0", "address": CC_CONTRACT, "topics": [TOPIC]});
+        let logs: Vec<JValue> = client
+            .request("eth_getLogs", rpc_params![filter])
+            .await
+            .unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0]["blockNumber"], "0x1");
+        assert_eq!(chain.request_count("eth_getLogs"), 1);
+
+        chain.stop();
+    }
+
+    #[tokio::test]
+    async fn calls_and_failures() {
+        let chain = MockChain::start().await.unwrap();
+        let client = HttpClientBuilder::default()
+            .build(chain.http_endpoint())
+            .unwrap();
+        chain.set_call(CORE_CONTRACT, "0x12345678", "0x01");
+        chain.fail("eth_call", Failure::times(1, -32000, "header not found"));
+
+        let call = json!({"to": CORE_CONTRACT, "data": "0x12345678"});
+        let result: Result<String, _> = client.request("eth_call", rpc_params![&call]).await;
+        assert!(result.is_err());
+
+        let result: String = client
+            .request("eth_call", rpc_params![&call, "latest"])
+            .await
+            .unwrap();
+        assert_eq!(result, "0x01");
+
+        let unknown = json!({"to": CORE_CONTRACT, "data": "0x87654321"});
+        let result: Result<String, _> = client.request("eth_call", rpc_params![unknown]).await;
+        assert!(result.is_err());
+        assert_eq!(chain.request_count("eth_call"), 3);
+
+        chain.stop();
+    }
+
+    #[tokio::test]
+    async fn subscriptions_see_reorgs() {
+        let chain = MockChain::start().await.unwrap();
+        let client = WsClientBuilder::default()
+            .build(chain.ws_endpoint())
+            .await
+            .unwrap();
+
+        let mut heads: Subscription<JValue> = client
+            .subscribe("eth_subscribe", rpc_params!["newHeads"], "eth_unsubscribe")
+            .await
+            .unwrap();
+        let filter = json!({"address": CC_CONTRACT});
+        let mut logs: Subscription<JValue> = client
+            .subscribe(
+                "eth_subscribe",
+                rpc_params!["logs", filter],
+                "eth_unsubscribe",
+            )
+            .await
+            .unwrap();
+        assert_eq!(chain.subscription_count(), 2);
+
+        chain.add_log(MockLog::new(CC_CONTRACT, vec![TOPIC.into()], "0x"));
+        chain.mine_block();
+        let head = heads.next().await.unwrap().unwrap();
+        assert_eq!(head["number"], "0x1");
+        let log = logs.next().await.unwrap().unwrap();
+        assert_eq!(log["removed"], false);
+
+        chain.reorg(1);
+        let log = logs.next().await.unwrap().unwrap();
+        assert_eq!(log["removed"], true);
+        let head = heads.next().await.unwrap().unwrap();
+        assert_ne!(head["hash"], log["blockHash"]);
+
+        chain.drop_subscriptions();
+        assert!(!matches!(heads.next().await, Some(Ok(_))));
+
+        chain.stop();
+    }
+}
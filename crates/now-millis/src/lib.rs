@@ -1,10 +1,22 @@
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-/// Returns UNIX timestamp as Duration
+/// Returns UNIX timestamp as Duration.
+///
+/// Follows tokio time when it's paused, e.g. by `tokio::time::pause` in tests: while the paused
+/// clock is ahead of the system one, timestamps are ahead by the same amount. That way TTLs and
+/// timers can be tested by advancing time instead of sleeping.
 pub fn now() -> Duration {
-    SystemTime::now()
+    let system = SystemTime::now()
         .duration_since(UNIX_EPOCH)
-        .expect("system time before Unix epoch")
+        .expect("system time before Unix epoch");
+    system + virtual_offset()
+}
+
+/// How far paused tokio time is ahead of the real time, zero if it isn't paused
+fn virtual_offset() -> Duration {
+    let virtual_now = tokio::time::Instant::now().into_std();
+    let real_now = std::time::Instant::now();
+    virtual_now.saturating_duration_since(real_now)
 }
 
 /// Returns UNIX timestamp in milliseconds
@@ -16,3 +28,18 @@ pub fn now_ms() -> u128 {
 pub fn now_sec() -> u64 {
     now().as_secs()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn follows_paused_time() {
+        let before = now();
+        tokio::time::advance(Duration::from_secs(3600)).await;
+        let after = now();
+
+        assert!(after >= before + Duration::from_secs(3590));
+        assert!(after < before + Duration::from_secs(3610));
+    }
+}
@@ -18,8 +18,8 @@
 extern crate fstrings;
 use connected_client::ConnectedClient;
 use created_swarm::{
-    make_swarms, make_swarms_with_cfg, make_swarms_with_keypair,
-    make_swarms_with_transport_and_mocked_vm,
+    cached_snapshot, make_swarms, make_swarms_from_snapshot, make_swarms_with_cfg,
+    make_swarms_with_keypair, make_swarms_with_transport_and_mocked_vm, CreatedSwarm,
 };
 use eyre::{Report, WrapErr};
 use fluence_keypair::KeyPair;
@@ -51,6 +51,20 @@ struct NodeInfo {
     pub external_addresses: Vec<Multiaddr>,
 }
 
+/// Swarm with registry deployed, started from a snapshot shared by the tests
+async fn registry_swarms(n: usize) -> Vec<CreatedSwarm> {
+    let snapshot = cached_snapshot("registry", n, |mut cfg| {
+        cfg.enabled_system_services = vec!["registry".to_string()];
+        cfg
+    })
+    .await;
+    make_swarms_from_snapshot(&snapshot, |mut cfg| {
+        cfg.enabled_system_services = vec!["registry".to_string()];
+        cfg
+    })
+    .await
+}
+
 #[tokio::test]
 async fn identify() {
     let swarms = make_swarms(1).await;
@@ -642,6 +656,201 @@ async fn timestamp_sec() {
     let _: u64 = serde_json::from_value(result).unwrap();
 }
 
+#[tokio::test]
+async fn peer_scores() {
+    let swarms = make_swarms(1).await;
+
+    let mut client = ConnectedClient::connect_with_keypair(
+        swarms[0].multiaddr.clone(),
+        Some(swarms[0].management_keypair.clone()),
+    )
+    .await
+    .wrap_err("connect client")
+    .unwrap();
+
+    client
+        .send_particle(
+            r#"
+        (seq
+            (call relay ("peer" "scores") [] result)
+            (call client ("op" "return") [result])
+        )
+        "#,
+            hashmap! {
+                "relay" => json!(client.node.to_string()),
+                "client" => json!(client.peer_id.to_string()),
+            },
+        )
+        .await;
+
+    let result = client
+        .receive_args()
+        .await
+        .wrap_err("receive args")
+        .unwrap();
+    let result = result.into_iter().next().unwrap();
+    // nobody misbehaved
+    assert_eq!(result, json!([]));
+}
+
+#[tokio::test]
+async fn peer_scores_requires_management() {
+    let swarms = make_swarms(1).await;
+
+    let mut client = ConnectedClient::connect_to(swarms[0].multiaddr.clone())
+        .await
+        .wrap_err("connect client")
+        .unwrap();
+
+    client
+        .send_particle(
+            r#"
+        (xor
+            (call relay ("peer" "scores") [])
+            (call client ("op" "return") [%last_error%.$.message])
+        )
+        "#,
+            hashmap! {
+                "relay" => json!(client.node.to_string()),
+                "client" => json!(client.peer_id.to_string()),
+            },
+        )
+        .await;
+
+    let result = client
+        .receive_args()
+        .await
+        .wrap_err("receive args")
+        .unwrap();
+    let message = result[0].as_str().unwrap();
+    assert!(
+        message.contains("can be called only by management peer id"),
+        "{message}"
+    );
+}
+
+#[tokio::test]
+async fn peer_filter() {
+    let swarms = make_swarms(1).await;
+
+    let mut client = ConnectedClient::connect_with_keypair(
+        swarms[0].multiaddr.clone(),
+        Some(swarms[0].management_keypair.clone()),
+    )
+    .await
+    .wrap_err("connect client")
+    .unwrap();
+
+    let denied = RandomPeerId::random().to_string();
+    client
+        .send_particle(
+            r#"
+        (seq
+            (seq
+                (call relay ("peer_filter" "add") ["denylist" denied] added)
+                (call relay ("peer_filter" "list") [] list_before)
+            )
+            (seq
+                (call relay ("peer_filter" "remove") ["denylist" denied] removed)
+                (seq
+                    (call relay ("peer_filter" "list") [] list_after)
+                    (call client ("op" "return") [added list_before removed list_after])
+                )
+            )
+        )
+        "#,
+            hashmap! {
+                "relay" => json!(client.node.to_string()),
+                "client" => json!(client.peer_id.to_string()),
+                "denied" => json!(denied),
+            },
+        )
+        .await;
+
+    let result = client
+        .receive_args()
+        .await
+        .wrap_err("receive args")
+        .unwrap();
+    assert_eq!(result[0], json!(true));
+    assert_eq!(result[1], json!({"allowlist": [], "denylist": [denied]}));
+    assert_eq!(result[2], json!(true));
+    assert_eq!(result[3], json!({"allowlist": [], "denylist": []}));
+}
+
+#[tokio::test]
+async fn peer_filter_requires_management() {
+    let swarms = make_swarms(1).await;
+
+    let mut client = ConnectedClient::connect_to(swarms[0].multiaddr.clone())
+        .await
+        .wrap_err("connect client")
+        .unwrap();
+
+    client
+        .send_particle(
+            r#"
+        (xor
+            (call relay ("peer_filter" "add") ["denylist" denied])
+            (call client ("op" "return") [%last_error%.$.message])
+        )
+        "#,
+            hashmap! {
+                "relay" => json!(client.node.to_string()),
+                "client" => json!(client.peer_id.to_string()),
+                "denied" => json!(RandomPeerId::random().to_string()),
+            },
+        )
+        .await;
+
+    let result = client
+        .receive_args()
+        .await
+        .wrap_err("receive args")
+        .unwrap();
+    let message = result[0].as_str().unwrap();
+    assert!(
+        message.contains("can be called only by management peer id"),
+        "{message}"
+    );
+}
+
+#[tokio::test]
+async fn kad_republish() {
+    let swarms = make_swarms(1).await;
+
+    let mut client = ConnectedClient::connect_with_keypair(
+        swarms[0].multiaddr.clone(),
+        Some(swarms[0].management_keypair.clone()),
+    )
+    .await
+    .wrap_err("connect client")
+    .unwrap();
+
+    client
+        .send_particle(
+            r#"
+        (seq
+            (call relay ("kad" "republish") [] republished)
+            (call client ("op" "return") [republished])
+        )
+        "#,
+            hashmap! {
+                "relay" => json!(client.node.to_string()),
+                "client" => json!(client.peer_id.to_string()),
+            },
+        )
+        .await;
+
+    let result = client
+        .receive_args()
+        .await
+        .wrap_err("receive args")
+        .unwrap();
+    // nox doesn't publish records by itself
+    assert_eq!(result[0], json!(0));
+}
+
 #[tokio::test]
 async fn base58_string_builtins() {
     let script = r#"
@@ -1807,11 +2016,7 @@ async fn sign_verify() {
 #[tokio::test]
 async fn sign_invalid_tetraplets() {
     enable_logs();
-    let swarms = make_swarms_with_cfg(2, |mut cfg| {
-        cfg.enabled_system_services = vec!["registry".to_string()];
-        cfg
-    })
-    .await;
+    let swarms = registry_swarms(2).await;
 
     let mut client = ConnectedClient::connect_to(swarms[0].multiaddr.clone())
         .await
@@ -1875,11 +2080,7 @@ async fn sign_invalid_tetraplets() {
 
 #[tokio::test]
 async fn sig_verify_invalid_signature() {
-    let swarms = make_swarms_with_cfg(1, |mut cfg| {
-        cfg.enabled_system_services = vec!["registry".to_string()];
-        cfg
-    })
-    .await;
+    let swarms = registry_swarms(1).await;
 
     let mut client = ConnectedClient::connect_to(swarms[0].multiaddr.clone())
         .await
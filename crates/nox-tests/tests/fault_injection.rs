@@ -0,0 +1,130 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::time::Duration;
+
+use connected_client::ConnectedClient;
+use created_swarm::make_swarms;
+use eyre::WrapErr;
+use fluence_libp2p::FaultConfig;
+use maplit::hashmap;
+use serde_json::json;
+
+const SCRIPT: &str = r#"
+    (seq
+        (call relay ("op" "noop") [])
+        (call client ("return" "") [name])
+    )"#;
+
+#[tokio::test]
+async fn expired_by_latency() {
+    let swarms = make_swarms(1).await;
+    let mut client = ConnectedClient::connect_to(swarms[0].multiaddr.clone())
+        .await
+        .wrap_err("connect client")
+        .unwrap();
+    let particle_ttl = client.particle_ttl();
+    client.set_particle_ttl(Duration::from_millis(500));
+
+    let data = hashmap! {
+        "name" => json!("folex"),
+        "client" => json!(client.peer_id.to_string()),
+        "relay" => json!(client.node.to_string()),
+    };
+
+    // particle reaches the node after it has expired
+    client.inject_faults(FaultConfig {
+        latency: Duration::from_secs(1),
+        ..<_>::default()
+    });
+    client.send_particle(SCRIPT, data.clone()).await;
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    assert!(client.maybe_receive().await.is_none());
+
+    client.clear_faults();
+    client.set_particle_ttl(particle_ttl);
+    let response = client.execute_particle(SCRIPT, data.clone()).await.unwrap();
+    assert_eq!(data["name"], response[0]);
+}
+
+#[tokio::test]
+async fn retry_after_loss() {
+    let swarms = make_swarms(1).await;
+    let mut client = ConnectedClient::connect_to(swarms[0].multiaddr.clone())
+        .await
+        .wrap_err("connect client")
+        .unwrap();
+
+    let data = hashmap! {
+        "name" => json!("folex"),
+        "client" => json!(client.peer_id.to_string()),
+        "relay" => json!(client.node.to_string()),
+    };
+
+    client.inject_faults(FaultConfig {
+        drop_probability: 1.0,
+        ..<_>::default()
+    });
+    client.send_particle(SCRIPT, data.clone()).await;
+    assert!(client.maybe_receive().await.is_none());
+
+    // the connection survives lost streams, so the retry goes through
+    client.clear_faults();
+    let response = client.execute_particle(SCRIPT, data.clone()).await.unwrap();
+    assert_eq!(data["name"], response[0]);
+}
+
+#[tokio::test]
+async fn latency_between_nodes() {
+    let swarms = make_swarms(2).await;
+    let latency = Duration::from_millis(500);
+    swarms[0].faults.set_peer(
+        swarms[1].peer_id,
+        FaultConfig {
+            latency,
+            jitter: Duration::from_millis(100),
+            ..<_>::default()
+        },
+    );
+    let mut client = ConnectedClient::connect_to(swarms[0].multiaddr.clone())
+        .await
+        .wrap_err("connect client")
+        .unwrap();
+
+    let data = hashmap! {
+        "client" => json!(client.peer_id.to_string()),
+        "relay" => json!(client.node.to_string()),
+        "other" => json!(swarms[1].peer_id.to_string()),
+        "name" => json!("folex"),
+    };
+    let start = std::time::Instant::now();
+    let response = client
+        .execute_particle(
+            r#"
+            (seq
+                (call other ("op" "noop") [])
+                (seq
+                    (call relay ("op" "noop") [])
+                    (call client ("return" "") [name])
+                )
+            )"#,
+            data.clone(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(data["name"], response[0]);
+    assert!(start.elapsed() >= latency);
+}
@@ -410,7 +410,9 @@ async fn explore_services_fixed_heavy() {
                     .expect("no error");
                     received.push(args);
                 }
-                ClientEvent::NewConnection { .. } => {}
+                ClientEvent::NewConnection { .. }
+                | ClientEvent::Goodbye { .. }
+                | ClientEvent::Rejected { .. } => {}
             }
         }
 
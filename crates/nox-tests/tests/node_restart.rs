@@ -0,0 +1,107 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::time::Duration;
+
+use connected_client::ConnectedClient;
+use created_swarm::{make_swarms, StopMode};
+use eyre::WrapErr;
+use maplit::hashmap;
+use serde_json::json;
+use test_utils::{clock_config, create_spell, spell_counter, wait_spell_executed};
+
+const SPELL_TIMEOUT: Duration = Duration::from_secs(30);
+
+async fn spell_resubscribes_after_restart(mode: StopMode) {
+    let mut swarms = make_swarms(1).await;
+    let mut client = ConnectedClient::connect_to(swarms[0].multiaddr.clone())
+        .await
+        .wrap_err("connect client")
+        .unwrap();
+
+    let script = r#"(call %init_peer_id% ("op" "noop") [])"#;
+    let config = clock_config(1, 1, 0);
+    let (spell_id, worker_id) = create_spell(&mut client, script, config, json!({}), None).await;
+    wait_spell_executed(&mut client, &worker_id, &spell_id, 1, SPELL_TIMEOUT)
+        .await
+        .unwrap();
+    drop(client);
+
+    let swarm = swarms.remove(0).restart(mode).await;
+    let mut client = ConnectedClient::connect_to(swarm.multiaddr.clone())
+        .await
+        .wrap_err("connect client")
+        .unwrap();
+
+    // the counter is persisted, it keeps growing only if the spell is scheduled again
+    let counter = spell_counter(&mut client, &worker_id, &spell_id)
+        .await
+        .unwrap()
+        .expect("spell counter is persisted");
+    wait_spell_executed(
+        &mut client,
+        &worker_id,
+        &spell_id,
+        counter + 2,
+        SPELL_TIMEOUT,
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn spell_resubscribes_after_graceful_restart() {
+    spell_resubscribes_after_restart(StopMode::Graceful).await;
+}
+
+#[tokio::test]
+async fn spell_resubscribes_after_abrupt_restart() {
+    spell_resubscribes_after_restart(StopMode::Abrupt).await;
+}
+
+#[tokio::test]
+async fn killed_node_rejoins() {
+    let mut swarms = make_swarms(2).await;
+    let stopped = swarms.remove(1).stop(StopMode::Abrupt).await;
+    let restarted = stopped.start().await;
+
+    let mut client = ConnectedClient::connect_to(swarms[0].multiaddr.clone())
+        .await
+        .wrap_err("connect client")
+        .unwrap();
+
+    let result = client
+        .execute_particle(
+            r#"
+            (seq
+                (seq
+                    (call other ("op" "identity") [other] identity)
+                    (call relay ("op" "noop") [])
+                )
+                (call client ("return" "") [identity])
+            )
+            "#,
+            hashmap! {
+                "relay" => json!(client.node.to_string()),
+                "client" => json!(client.peer_id.to_string()),
+                "other" => json!(restarted.peer_id.to_string()),
+            },
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(result, vec![json!(restarted.peer_id.to_string())]);
+}
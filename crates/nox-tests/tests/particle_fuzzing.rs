@@ -0,0 +1,275 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::cell::RefCell;
+use std::fmt;
+
+use bytesize::ByteSize;
+use connected_client::{ClientEvent, ConnectedClient};
+use connection_pool::SignatureEnforcement;
+use created_swarm::make_swarms_with_cfg;
+use eyre::{ensure, eyre, WrapErr};
+use fluence_keypair::KeyPair;
+use maplit::hashmap;
+use now_millis::now_ms;
+use particle_protocol::{Particle, RejectionNotice};
+use proptest::collection::vec;
+use proptest::prelude::*;
+use proptest::test_runner::{TestCaseError, TestRunner};
+use serde_json::json;
+
+const MAX_PARTICLE_SIZE: usize = 64 * 1024;
+const CASES: u32 = 64;
+
+/// Executed after each generated particle to check the node is still alive
+const PROBE: &str = r#"
+    (seq
+        (call relay ("op" "noop") [])
+        (call client ("return" "") [name])
+    )"#;
+
+#[derive(Debug, Clone)]
+enum Signature {
+    Valid,
+    Missing,
+    Garbage(Vec<u8>),
+    /// Signed by a key other than the key of the init peer
+    Foreign,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Expected {
+    Accepted,
+    TooLarge,
+    MissingSignature,
+    InvalidSignature,
+}
+
+impl Expected {
+    fn matches(&self, notice: &RejectionNotice) -> bool {
+        matches!(
+            (self, notice.reason.as_str()),
+            (Expected::TooLarge, "particle_too_large")
+                | (Expected::MissingSignature, "missing_signature")
+                | (Expected::InvalidSignature, "invalid_signature")
+        )
+    }
+}
+
+#[derive(Clone)]
+struct WeirdParticle {
+    id: String,
+    /// Current time if not set
+    timestamp: Option<u64>,
+    ttl: u32,
+    script: String,
+    data: Vec<u8>,
+    signature: Signature,
+}
+
+impl WeirdParticle {
+    fn build(&self, keypair: &KeyPair) -> Particle {
+        let mut particle = Particle {
+            id: self.id.clone(),
+            init_peer_id: keypair.get_peer_id(),
+            timestamp: self.timestamp.unwrap_or(now_ms() as u64),
+            ttl: self.ttl,
+            script: self.script.clone(),
+            data: self.data.clone(),
+            ..<_>::default()
+        };
+        match &self.signature {
+            Signature::Valid => particle.sign(keypair).expect("sign particle"),
+            Signature::Missing => {}
+            Signature::Garbage(signature) => particle.signature = signature.clone(),
+            Signature::Foreign => particle
+                .sign(&KeyPair::generate_ed25519())
+                .expect("sign particle"),
+        }
+        particle
+    }
+
+    /// How the node has to respond, the size is checked before the signature
+    fn expected(&self) -> Expected {
+        if self.data.len() + self.script.len() > MAX_PARTICLE_SIZE {
+            return Expected::TooLarge;
+        }
+        match self.signature {
+            Signature::Valid => Expected::Accepted,
+            Signature::Missing => Expected::MissingSignature,
+            Signature::Garbage(_) | Signature::Foreign => Expected::InvalidSignature,
+        }
+    }
+}
+
+// data and script can be huge, so only their sizes are printed on failures
+impl fmt::Debug for WeirdParticle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WeirdParticle")
+            .field("id", &self.id)
+            .field("timestamp", &self.timestamp)
+            .field("ttl", &self.ttl)
+            .field("script_len", &self.script.len())
+            .field("script", &self.script.chars().take(64).collect::<String>())
+            .field("data_len", &self.data.len())
+            .field("signature", &self.signature)
+            .finish()
+    }
+}
+
+fn deep_json(depth: usize) -> Vec<u8> {
+    format!("{}{}", r#"{"a":"#.repeat(depth), "}".repeat(depth)).into_bytes()
+}
+
+fn particle_id() -> impl Strategy<Value = String> {
+    prop_oneof![
+        Just(String::new()),
+        // ids shared by different particles
+        "dup-[0-2]",
+        "\\PC{1,128}",
+        Just("x".repeat(10_000)),
+    ]
+}
+
+fn script() -> impl Strategy<Value = String> {
+    prop_oneof![
+        Just(String::new()),
+        Just("(null)".to_string()),
+        Just(r#"(call %init_peer_id% ("op" "noop") [])"#.to_string()),
+        "\\PC{0,256}",
+        // unbalanced
+        (1..1024usize).prop_map(|depth| "(seq ".repeat(depth)),
+    ]
+}
+
+fn data() -> impl Strategy<Value = Vec<u8>> {
+    prop_oneof![
+        Just(vec![]),
+        vec(any::<u8>(), 0..1024),
+        (1..4096usize).prop_map(deep_json),
+        (MAX_PARTICLE_SIZE + 1..2 * MAX_PARTICLE_SIZE).prop_map(|size| vec![b'x'; size]),
+    ]
+}
+
+fn signature() -> impl Strategy<Value = Signature> {
+    prop_oneof![
+        3 => Just(Signature::Valid),
+        1 => Just(Signature::Missing),
+        1 => vec(any::<u8>(), 1..128).prop_map(Signature::Garbage),
+        1 => Just(Signature::Foreign),
+    ]
+}
+
+prop_compose! {
+    fn weird_particle()(
+        id in particle_id(),
+        timestamp in prop_oneof![Just(None), Just(Some(0)), Just(Some(u64::MAX)), any::<u64>().prop_map(Some)],
+        ttl in prop_oneof![Just(0), Just(u32::MAX), 1..120_000u32],
+        script in script(),
+        data in data(),
+        signature in signature(),
+    ) -> WeirdParticle {
+        WeirdParticle { id, timestamp, ttl, script, data, signature }
+    }
+}
+
+async fn wait_rejection(
+    client: &mut ConnectedClient,
+    particle_id: &str,
+) -> eyre::Result<RejectionNotice> {
+    let timeout = client.timeout();
+    let receive = async {
+        loop {
+            match client.client.receive_one().await {
+                Some(ClientEvent::Rejected { notice, .. }) if notice.refers_to(particle_id) => {
+                    break Ok(notice)
+                }
+                Some(_) => {}
+                None => break Err(eyre!("client is stopped")),
+            }
+        }
+    };
+    tokio::time::timeout(timeout, receive)
+        .await
+        .map_err(|_| eyre!("no rejection received"))?
+}
+
+async fn check_particle(client: &mut ConnectedClient, weird: WeirdParticle) -> eyre::Result<()> {
+    let particle = weird.build(&client.key_pair);
+    let particle_id = particle.id.clone();
+    client.send(particle).await;
+
+    let expected = weird.expected();
+    if expected != Expected::Accepted {
+        let notice = wait_rejection(client, &particle_id).await?;
+        ensure!(
+            expected.matches(&notice),
+            "expected {expected:?}, got {notice:?}"
+        );
+    }
+
+    let data = hashmap! {
+        "name" => json!("alive"),
+        "client" => json!(client.peer_id.to_string()),
+        "relay" => json!(client.node.to_string()),
+    };
+    let response = client
+        .execute_particle(PROBE, data)
+        .await
+        .wrap_err("node doesn't respond after the particle")?;
+    ensure!(
+        response == vec![json!("alive")],
+        "unexpected probe response {response:?}"
+    );
+    Ok(())
+}
+
+/// The node must survive any particle and refuse invalid ones with a structured rejection
+#[test]
+fn weird_particles() {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let (_swarms, client) = runtime.block_on(async {
+        let swarms = make_swarms_with_cfg(1, |mut cfg| {
+            cfg.particle_limits.max_particle_size = Some(ByteSize::b(MAX_PARTICLE_SIZE as u64));
+            cfg.particle_signatures.enforcement = SignatureEnforcement::Strict;
+            cfg
+        })
+        .await;
+        let client = ConnectedClient::connect_to(swarms[0].multiaddr.clone())
+            .await
+            .wrap_err("connect client")
+            .unwrap();
+        (swarms, client)
+    });
+    let client = RefCell::new(client);
+
+    let mut runner = TestRunner::new(ProptestConfig {
+        cases: CASES,
+        ..<_>::default()
+    });
+    let result = runner.run(&weird_particle(), |particle| {
+        runtime
+            .block_on(check_particle(&mut client.borrow_mut(), particle))
+            .map_err(|err| TestCaseError::fail(format!("{err:?}")))
+    });
+    if let Err(err) = result {
+        panic!("{err}");
+    }
+}
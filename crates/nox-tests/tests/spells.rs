@@ -20,8 +20,7 @@ use std::collections::HashMap;
 use std::time::Duration;
 
 use eyre::Context;
-use fluence_spell_dtos::trigger_config::{ClockConfig, TriggerConfig};
-use hex::FromHex;
+use fluence_spell_dtos::trigger_config::TriggerConfig;
 use maplit::hashmap;
 use serde_json::{json, Value as JValue};
 
@@ -31,136 +30,10 @@ use created_swarm::{make_swarms, make_swarms_with_cfg};
 use log_utils::enable_logs;
 use service_modules::load_module;
 use spell_event_bus::api::{TriggerInfo, TriggerInfoAqua, MAX_PERIOD_SEC};
-use test_utils::{create_service, create_service_worker};
-use workers::CUID;
-
-type SpellId = String;
-type WorkerPeerId = String;
-
-async fn create_worker(client: &mut ConnectedClient, deal_id: Option<String>) -> WorkerPeerId {
-    let init_id_1 =
-        <CUID>::from_hex("54ae1b506c260367a054f80800a545f23e32c6bc4a8908c9a794cb8dad23e5ea")
-            .unwrap();
-    let unit_ids = vec![init_id_1];
-    let data = hashmap! {
-        "deal_id" => deal_id.map(JValue::String).unwrap_or(JValue::String("default_deal".to_string())),
-        "relay" => json!(client.node.to_string()),
-        "client" => json!(client.peer_id.to_string()),
-        "cu_ids" => json!(unit_ids)
-    };
-
-    let response = client
-        .execute_particle(
-            r#"
-            (seq
-                (xor
-                    (call relay ("worker" "create") [deal_id cu_ids] worker_peer_id)
-                    (seq
-                        (call relay ("worker" "get_worker_id") [deal_id] get_worker_peer_id)
-                        (ap get_worker_peer_id.$.[0] worker_peer_id)
-                    )
-                )
-                (call client ("return" "") [worker_peer_id])
-            )"#,
-            data.clone(),
-        )
-        .await
-        .unwrap();
-
-    let worker_id = response[0].as_str().unwrap().to_string();
-    assert_ne!(worker_id.len(), 0);
-
-    worker_id
-}
-
-async fn create_spell_with_alias(
-    client: &mut ConnectedClient,
-    script: &str,
-    config: TriggerConfig,
-    init_data: JValue,
-    deal_id: Option<String>,
-    alias: String,
-) -> (SpellId, WorkerPeerId) {
-    let worker_id = create_worker(client, deal_id).await;
-    let data = hashmap! {
-        "script" => json!(script.to_string()),
-        "config" => json!(config),
-        "client" => json!(client.peer_id.to_string()),
-        "relay" => json!(client.node.to_string()),
-        "worker_id" => json!(worker_id.clone()),
-        "alias" => json!(alias),
-        "data" => init_data,
-    };
-
-    let response = client
-        .execute_particle(
-            r#"
-        (seq
-            (call relay ("op" "noop") [])            
-            (seq
-                (call worker_id ("spell" "install") [script data config alias] spell_id)
-                (call client ("return" "") [spell_id])
-            )
-        )"#,
-            data.clone(),
-        )
-        .await
-        .unwrap();
-
-    let spell_id = response[0].as_str().unwrap().to_string();
-    assert_ne!(spell_id.len(), 0);
-
-    (spell_id, worker_id)
-}
-
-async fn create_spell(
-    client: &mut ConnectedClient,
-    script: &str,
-    config: TriggerConfig,
-    init_data: JValue,
-    deal_id: Option<String>,
-) -> (SpellId, WorkerPeerId) {
-    let worker_id = create_worker(client, deal_id).await;
-    let data = hashmap! {
-        "script" => json!(script.to_string()),
-        "config" => json!(config),
-        "client" => json!(client.peer_id.to_string()),
-        "relay" => json!(client.node.to_string()),
-        "worker_id" => json!(worker_id.clone()),
-        "data" => init_data,
-    };
-
-    let response = client
-        .execute_particle(
-            r#"
-        (seq
-            (call relay ("op" "noop") [])            
-            (seq
-                (call worker_id ("spell" "install") [script data config] spell_id)
-                (call client ("return" "") [spell_id])
-            )
-        )"#,
-            data.clone(),
-        )
-        .await
-        .unwrap();
-
-    let spell_id = response[0].as_str().unwrap().to_string();
-    assert_ne!(spell_id.len(), 0);
-
-    (spell_id, worker_id)
-}
-
-fn make_clock_config(period_sec: u32, start_sec: u32, end_sec: u32) -> TriggerConfig {
-    TriggerConfig {
-        clock: ClockConfig {
-            start_sec,
-            end_sec,
-            period_sec,
-        },
-        ..Default::default()
-    }
-}
+use test_utils::{
+    clock_config, create_service, create_service_worker, create_spell, create_spell_with_alias,
+    create_worker, default_cu_ids, spell_counter, wait_spell_executed,
+};
 
 #[tokio::test]
 async fn spell_simple_test() {
@@ -186,7 +59,7 @@ async fn spell_simple_test() {
         client.peer_id
     );
 
-    let config = make_clock_config(0, 1, 0);
+    let config = clock_config(0, 1, 0);
     create_spell(&mut client, &script, config, json!({}), None).await;
 
     let response = client.receive_args().await.wrap_err("receive").unwrap();
@@ -213,7 +86,7 @@ async fn spell_error_handling_test() {
             (call %init_peer_id% ("errorHandlingSrv" "error") [%last_error% 1])        
         )"#;
 
-    let config = make_clock_config(2, 3, 0);
+    let config = clock_config(2, 3, 0);
     let (spell_id, worker_id) =
         create_spell(&mut client, failing_script, config, json!({}), None).await;
 
@@ -278,7 +151,7 @@ async fn spell_args_test() {
         client.peer_id
     );
 
-    let config = make_clock_config(1, 1, 0);
+    let config = clock_config(1, 1, 0);
 
     let expected_value = json!({"a": "b", "c": 1});
     create_spell(
@@ -329,7 +202,7 @@ async fn spell_return_test() {
         client.peer_id, client.peer_id,
     );
 
-    let config = make_clock_config(1, 1, 0);
+    let config = clock_config(1, 1, 0);
     create_spell(&mut client, &script, config, json!({}), None).await;
 
     let response = client.receive_args().await.wrap_err("receive").unwrap();
@@ -359,8 +232,8 @@ async fn spell_install_alias() {
         alias, client.peer_id
     );
 
-    let config = make_clock_config(1, 1, 0);
-    create_spell_with_alias(&mut client, &script, config, json!({}), None, alias).await;
+    let config = clock_config(1, 1, 0);
+    create_spell_with_alias(&mut client, &script, config, json!({}), None, &alias).await;
 
     let response = client.receive_args().await.wrap_err("receive").unwrap();
     let actual_spell_id = response[0].as_str().unwrap().to_string();
@@ -386,34 +259,19 @@ async fn spell_run_oneshot() {
         )"#;
 
     // Note that when period is 0, the spell is executed only once
-    let config = make_clock_config(0, 1, 0);
+    let config = clock_config(0, 1, 0);
     let (spell_id, worker_id) = create_spell(&mut client, script, config, json!({}), None).await;
 
-    let data = hashmap! {
-        "spell_id" => json!(spell_id),
-        "client" => json!(client.peer_id.to_string()),
-        "worker" => json!(worker_id),
-        "relay" => json!(client.node.to_string()),
-    };
-    let response = client
-        .execute_particle(
-            r#"
-        (seq
-            (seq
-                (call relay ("op" "noop") [])
-                (call worker (spell_id "get_u32") ["hw_counter"] counter)
-            )
-            (call client ("return" "") [counter])
-        )"#,
-            data.clone(),
-        )
-        .await
-        .unwrap();
-
-    if response[0]["success"].as_bool().unwrap() {
-        let counter = response[0]["value"].as_u64().unwrap();
-        assert_eq!(counter, 1);
-    }
+    let counter = wait_spell_executed(
+        &mut client,
+        &worker_id,
+        &spell_id,
+        1,
+        Duration::from_secs(10),
+    )
+    .await
+    .unwrap();
+    assert_eq!(counter, 1);
 }
 
 // The config considered empty if start_sec is 0. In this case we don't schedule a spell.
@@ -437,34 +295,10 @@ async fn spell_install_ok_empty_config() {
     // it's counter is zero on different occasions:
 
     // 1. Check that the spell wasn't executed immediately after installation (the case of `start_sec` <= now)
-    let response = client
-        .execute_particle(
-            r#"
-        (seq
-            (seq
-                (call relay ("op" "noop") [])
-                (call worker (spell_id "get_u32") ["hw_counter"] counter)
-            )
-            (call %init_peer_id% ("return" "") [counter])
-        )"#,
-            hashmap! {
-                "worker" => json!(worker_id),
-                "spell_id" => json!(spell_id),
-                "relay" => json!(client.node.to_string()),
-            },
-        )
+    let counter = spell_counter(&mut client, &worker_id, &spell_id)
         .await
         .unwrap();
-
-    if response[0]["success"]
-        .as_bool()
-        .unwrap_or_else(|| panic!("{:?}", response))
-    {
-        let counter = response[0]["value"]
-            .as_u64()
-            .unwrap_or_else(|| panic!("{:?}", response));
-        assert_eq!(counter, 0);
-    }
+    assert_matches!(counter, None | Some(0));
     // 2. Connect and disconnect a client to the same node. The spell should not be executed
     let connected = ConnectedClient::connect_to(swarms[0].multiaddr.clone())
         .await
@@ -472,28 +306,10 @@ async fn spell_install_ok_empty_config() {
         .unwrap();
     drop(connected);
 
-    let response = client
-        .execute_particle(
-            r#"
-        (seq
-            (seq
-                (call relay ("op" "noop") [])
-                (call worker (spell_id "get_u32") ["hw_counter"] counter)
-            )
-            (call %init_peer_id% ("return" "") [counter])
-        )"#,
-            hashmap! {
-                "relay" => json!(client.node.to_string()),
-                "worker" => json!(worker_id),
-                "spell_id" => json!(spell_id),
-            },
-        )
+    let counter = spell_counter(&mut client, &worker_id, &spell_id)
         .await
         .unwrap();
-    if response[0]["success"].as_bool().unwrap() {
-        let counter = response[0]["value"].as_u64().unwrap();
-        assert_eq!(counter, 0);
-    }
+    assert_matches!(counter, None | Some(0));
 
     // 3. We cannot check that it's not scheduled to run in the future, but it's ok for now.
 }
@@ -511,7 +327,7 @@ async fn spell_install_fail_large_period() {
     let worker_id = create_worker(&mut client, None).await;
 
     // Note that when period is 0, the spell is executed only once
-    let config = make_clock_config(MAX_PERIOD_SEC + 1, 1, 0);
+    let config = clock_config(MAX_PERIOD_SEC + 1, 1, 0);
 
     let data = hashmap! {
         "worker_id" => json!(worker_id),
@@ -556,7 +372,7 @@ async fn spell_install_fail_end_sec_past() {
     let empty: HashMap<String, String> = HashMap::new();
 
     // Note that when period is 0, the spell is executed only once
-    let config = make_clock_config(0, 10, 1);
+    let config = clock_config(0, 10, 1);
     let worker_id = create_worker(&mut client, None).await;
 
     let data = hashmap! {
@@ -610,7 +426,7 @@ async fn spell_install_fail_end_sec_before_start() {
         .as_secs();
 
     // Note that when period is 0, the spell is executed only once
-    let config = make_clock_config(0, now as u32 + 100, now as u32 + 90);
+    let config = clock_config(0, now as u32 + 100, now as u32 + 90);
     let worker_id = create_worker(&mut client, None).await;
 
     let data = hashmap! {
@@ -654,7 +470,7 @@ async fn spell_store_trigger_config() {
         .unwrap();
 
     let script = r#"(call %init_peer_id% ("peer" "identify") [] x)"#;
-    let config = make_clock_config(13, 10, 0);
+    let config = clock_config(13, 10, 0);
 
     let (spell_id, worker_id) =
         create_spell(&mut client, script, config.clone(), json!({}), None).await;
@@ -697,7 +513,7 @@ async fn spell_remove() {
         .unwrap();
 
     let script = r#"(call %init_peer_id% ("peer" "identify") [] x)"#;
-    let config = make_clock_config(2, 1, 0);
+    let config = clock_config(2, 1, 0);
     let (spell_id, worker_id) = create_spell(&mut client, script, config, json!({}), None).await;
 
     let data = hashmap! {
@@ -777,7 +593,7 @@ async fn spell_remove_by_alias() {
         client.peer_id
     );
 
-    let config = make_clock_config(2, 1, 0);
+    let config = clock_config(2, 1, 0);
     let (spell_id, _) = create_spell(&mut client, &script, config, json!({}), None).await;
 
     if let [JValue::Array(before), JValue::Array(after)] = client
@@ -804,7 +620,7 @@ async fn spell_remove_spell_as_service() {
 
     let script = r#"(call %init_peer_id% ("peer" "identify") [] x)"#;
 
-    let config = make_clock_config(2, 1, 0);
+    let config = clock_config(2, 1, 0);
     let (spell_id, worker_id) = create_spell(&mut client, script, config, json!({}), None).await;
 
     let data = hashmap! {
@@ -912,7 +728,7 @@ async fn spell_call_by_alias() {
         client.peer_id
     );
 
-    let config = make_clock_config(100, 1, 0);
+    let config = clock_config(100, 1, 0);
     create_spell(&mut client, &script, config, json!({}), None).await;
 
     if let [JValue::Number(counter)] = client
@@ -1033,7 +849,7 @@ async fn spell_timer_trigger_mailbox_test() {
         client.peer_id
     );
 
-    let config = make_clock_config(0, 1, 0);
+    let config = clock_config(0, 1, 0);
     create_spell(&mut client, &script, config, json!({}), None).await;
 
     let value = client.receive_args().await.wrap_err("receive").unwrap()[0]
@@ -1178,6 +994,72 @@ async fn spell_set_u32() {
     assert_eq!(two["value"], json!(2));
 }
 
+#[tokio::test]
+async fn spell_secrets() {
+    enable_logs();
+    let swarms = make_swarms(1).await;
+    let mut client = ConnectedClient::connect_with_keypair(
+        swarms[0].multiaddr.clone(),
+        Some(swarms[0].management_keypair.clone()),
+    )
+    .await
+    .wrap_err("connect client")
+    .unwrap();
+
+    let script = format!(
+        r#"( seq
+        (seq
+            (call %init_peer_id% ("getDataSrv" "spell_id") [] spell_id)
+            (seq
+                (call %init_peer_id% ("spell" "set_secret") ["token" "s3cr3t"])
+                (seq
+                    (call %init_peer_id% ("spell" "get_secret") ["token"] secret)
+                    (call %init_peer_id% (spell_id "get_string") ["secret:token"] raw)
+                )
+            )
+        )
+        (call "{}" ("return" "") [secret raw])
+    )"#,
+        client.peer_id
+    );
+    // oneshot spell
+    let mut config = TriggerConfig::default();
+    config.clock.start_sec = 1;
+    config.clock.period_sec = 0;
+
+    let (_spell_id, worker_id) =
+        create_spell(&mut client, &script, config.clone(), json!({}), None).await;
+    let mut result = client.receive_args().await.wrap_err("receive").unwrap();
+
+    assert_eq!(result.len(), 2);
+    let (secret, raw) = (result.remove(0), result.remove(0));
+    assert_eq!(secret, json!("s3cr3t"));
+    assert_eq!(raw["absent"], json!(false));
+    assert!(!raw["value"].as_str().unwrap().contains("s3cr3t"));
+
+    // secrets aren't readable outside of the spell executions
+    let data = hashmap! {
+        "client" => json!(client.peer_id.to_string()),
+        "relay" => json!(client.node.to_string()),
+        "worker_id" => json!(worker_id),
+    };
+    let result = client
+        .execute_particle(
+            r#"
+            (xor
+                (seq
+                    (call relay ("op" "noop") [])
+                    (call worker_id ("spell" "get_secret") ["token"] secret)
+                )
+                (call client ("return" "") [%last_error%.$.message])
+            )"#,
+            data,
+        )
+        .await
+        .unwrap();
+    assert!(result[0].as_str().unwrap().contains("Invalid particle id"));
+}
+
 #[tokio::test]
 async fn spell_peer_id_test() {
     let swarms = make_swarms(1).await;
@@ -1193,7 +1075,7 @@ async fn spell_peer_id_test() {
         client.peer_id
     );
 
-    let config = make_clock_config(0, 1, 0);
+    let config = clock_config(0, 1, 0);
     let (_, worker_peer_id) = create_spell(&mut client, &script, config, json!({}), None).await;
 
     let response = client.receive_args().await.wrap_err("receive").unwrap();
@@ -1316,7 +1198,7 @@ async fn spell_update_config_stopped_spell() {
     let (spell_id, worker_id) = create_spell(&mut client, &script, config, json!({}), None).await;
 
     // Update trigger config to do something.
-    let config = make_clock_config(0, 1, 0);
+    let config = clock_config(0, 1, 0);
     let data = hashmap! {
         "spell_id" => json!(spell_id),
         "relay" => json!(client.node.to_string()),
@@ -1398,7 +1280,7 @@ async fn resolve_alias_wrong_worker() {
         client.node, client.peer_id
     );
 
-    let config = make_clock_config(2, 1, 0);
+    let config = clock_config(2, 1, 0);
     create_spell(&mut client, &script, config, json!({}), None).await;
 
     if let [JValue::String(error)] = client
@@ -1445,7 +1327,7 @@ async fn worker_sig_test() {
         client.node, client.peer_id
     );
 
-    let config = make_clock_config(2, 1, 0);
+    let config = clock_config(2, 1, 0);
     let (_, worker_id) = create_spell(&mut client, &script, config, json!({}), None).await;
 
     use serde_json::Value::Bool;
@@ -1483,7 +1365,7 @@ async fn spell_relay_id_test() {
         client.peer_id
     );
 
-    let config = make_clock_config(1, 1, 0);
+    let config = clock_config(1, 1, 0);
     create_spell(&mut client, &script, config, json!({}), None).await;
 
     if let [JValue::String(relay_id)] = client
@@ -1506,10 +1388,7 @@ async fn spell_create_worker_twice() {
         .await
         .wrap_err("connect client")
         .unwrap();
-    let init_id_1 =
-        <CUID>::from_hex("54ae1b506c260367a054f80800a545f23e32c6bc4a8908c9a794cb8dad23e5ea")
-            .unwrap();
-    let unit_ids = vec![init_id_1];
+    let unit_ids = default_cu_ids();
     let data = hashmap! {
         "client" => json!(client.peer_id.to_string()),
         "relay" => json!(client.node.to_string()),
@@ -1558,7 +1437,7 @@ async fn spell_install_root_scope() {
 
     let script = r#"(call %init_peer_id% ("getDataSrv" "spell_id") [] spell_id)"#;
 
-    let config = make_clock_config(0, 1, 0);
+    let config = clock_config(0, 1, 0);
 
     let data = hashmap! {
         "script" => json!(script.to_string()),
@@ -1599,10 +1478,7 @@ async fn spell_create_worker_same_deal_id_different_peer() {
         .await
         .wrap_err("connect client")
         .unwrap();
-    let init_id_1 =
-        <CUID>::from_hex("54ae1b506c260367a054f80800a545f23e32c6bc4a8908c9a794cb8dad23e5ea")
-            .unwrap();
-    let unit_ids = vec![init_id_1];
+    let unit_ids = default_cu_ids();
     let data = hashmap! {
         "client" => json!(client1.peer_id.to_string()),
         "relay" => json!(client1.node.to_string()),
@@ -1657,7 +1533,7 @@ async fn create_remove_worker() {
         .unwrap();
 
     let script = r#"(call %init_peer_id% ("getDataSrv" "spell_id") [] spell_id)"#;
-    let config = make_clock_config(0, 1, 0);
+    let config = clock_config(0, 1, 0);
 
     let (spell_id, worker_id) = create_spell(&mut client, script, config, json!({}), None).await;
     let service = create_service_worker(
@@ -1869,7 +1745,7 @@ async fn test_spell_list() {
 
     let script = r#"(call %init_peer_id% ("peer" "identify") [] x)"#;
 
-    let config = make_clock_config(0, 1, 0);
+    let config = clock_config(0, 1, 0);
     let (spell_id1, _worker_id1) = create_spell(
         &mut client,
         script,
@@ -1978,7 +1854,7 @@ async fn spell_call_by_default_alias() {
         client.peer_id
     );
 
-    let config = make_clock_config(2, 1, 0);
+    let config = clock_config(2, 1, 0);
     let (expected_spell_id, _) = create_spell(&mut client, &script, config, json!({}), None).await;
 
     if let [JValue::Number(counter1), JValue::String(spell_id1), JValue::String(error1), JValue::Number(counter2), JValue::String(spell_id2), JValue::String(error2)] =
@@ -2010,10 +1886,7 @@ async fn get_worker_peer_id_opt() {
         .wrap_err("connect client")
         .unwrap();
 
-    let init_id_1 =
-        <CUID>::from_hex("54ae1b506c260367a054f80800a545f23e32c6bc4a8908c9a794cb8dad23e5ea")
-            .unwrap();
-    let unit_ids = vec![init_id_1];
+    let unit_ids = default_cu_ids();
     let data = hashmap! {
         "relay" => json!(client.node.to_string()),
         "client" => json!(client.peer_id.to_string()),
@@ -2225,6 +2098,7 @@ async fn test_activate_deactivate() {
     let swarms = make_swarms_with_cfg(1, move |mut cfg| {
         cfg.override_system_services_config = Some(SystemServicesConfig {
             enable: vec![],
+            lazy: vec![],
             aqua_ipfs: Default::default(),
             decider: DeciderConfig {
                 worker_period_sec,
@@ -2232,6 +2106,10 @@ async fn test_activate_deactivate() {
             },
             registry: Default::default(),
             connector: Default::default(),
+            trust_graph: Default::default(),
+            pinned_versions: Default::default(),
+            spell_overrides_dir: None,
+            supervisor: Default::default(),
         });
         cfg
     })
@@ -2246,14 +2124,14 @@ async fn test_activate_deactivate() {
 
     let deal_id = "deal-id-1".to_string();
 
-    let config = make_clock_config(worker_period_sec, 1, 0);
+    let config = clock_config(worker_period_sec, 1, 0);
     let (_, worker_id) = create_spell_with_alias(
         &mut client,
         r#"(call %init_peer_id% ("op" "noop") [])"#,
         config.clone(),
         json!({}),
         Some(deal_id.clone()),
-        "worker-spell".to_string(),
+        "worker-spell",
     )
     .await;
 
@@ -2263,7 +2141,7 @@ async fn test_activate_deactivate() {
         config.clone(),
         json!({}),
         Some(deal_id.clone()),
-        "other-spell".to_string(),
+        "other-spell",
     )
     .await;
 
@@ -2336,3 +2214,176 @@ async fn test_activate_deactivate() {
         panic!("expected result")
     }
 }
+
+#[tokio::test]
+async fn test_deal_settings() {
+    let worker_period_sec = 120u32;
+    let swarms = make_swarms_with_cfg(1, move |mut cfg| {
+        cfg.override_system_services_config = Some(SystemServicesConfig {
+            enable: vec![],
+            lazy: vec![],
+            aqua_ipfs: Default::default(),
+            decider: DeciderConfig {
+                worker_period_sec,
+                ..Default::default()
+            },
+            registry: Default::default(),
+            connector: Default::default(),
+            trust_graph: Default::default(),
+            pinned_versions: Default::default(),
+            spell_overrides_dir: None,
+            supervisor: Default::default(),
+        });
+        cfg
+    })
+    .await;
+    let mut client = ConnectedClient::connect_with_keypair(
+        swarms[0].multiaddr.clone(),
+        Some(swarms[0].management_keypair.clone()),
+    )
+    .await
+    .wrap_err("connect client")
+    .unwrap();
+
+    let deal_id = "deal-id-1".to_string();
+    let config = clock_config(worker_period_sec, 1, 0);
+    let (_, worker_id) = create_spell_with_alias(
+        &mut client,
+        r#"(call %init_peer_id% ("op" "noop") [])"#,
+        config,
+        json!({}),
+        Some(deal_id.clone()),
+        "worker-spell",
+    )
+    .await;
+
+    client
+        .send_particle(
+            r#"(seq
+                (seq
+                    (seq
+                        (call relay ("worker" "set_deal_settings") [deal_id period none])
+                        (seq
+                            (call relay ("worker" "get_deal_settings") [deal_id] settings)
+                            (call worker ("worker-spell" "get_trigger_config") [] trigger_config)
+                        )
+                    )
+                    (seq
+                        (call relay ("worker" "set_deal_settings") [deal_id none stop])
+                        (seq
+                            (call relay ("worker" "get_deal_settings") [deal_id] settings_stopped)
+                            (call relay ("worker" "is_active") [deal_id] is_active)
+                        )
+                    )
+                )
+                (call client ("return" "") [settings trigger_config settings_stopped is_active])
+            )"#,
+            hashmap! {
+                "relay" => json!(client.node.to_string()),
+                "client" => json!(client.peer_id.to_string()),
+                "worker" => json!(worker_id),
+                "deal_id" => json!(deal_id),
+                "period" => json!([60]),
+                "none" => json!([]),
+                "stop" => json!([true]),
+            },
+        )
+        .await;
+
+    if let [settings, JValue::Object(trigger_config), settings_stopped, JValue::Bool(is_active)] =
+        client.receive_args().await.unwrap().as_slice()
+    {
+        assert_eq!(
+            *settings,
+            json!({"worker_period_sec": [60], "stopped": false})
+        );
+        let trigger_config: TriggerConfig =
+            serde_json::from_value(trigger_config["config"].clone()).unwrap();
+        assert_eq!(trigger_config.clock.period_sec, 60);
+        assert_eq!(
+            *settings_stopped,
+            json!({"worker_period_sec": [], "stopped": true})
+        );
+        assert!(!*is_active);
+    } else {
+        panic!("expected result")
+    }
+}
+
+#[tokio::test]
+async fn spell_pubsub_trigger() {
+    let swarms = make_swarms(2).await;
+    let mut client = ConnectedClient::connect_to(swarms[0].multiaddr.clone())
+        .await
+        .wrap_err("connect client")
+        .unwrap();
+    // publishing requires management, node signs messages with its own key
+    let mut publisher = ConnectedClient::connect_with_keypair(
+        swarms[1].multiaddr.clone(),
+        Some(swarms[1].management_keypair.clone()),
+    )
+    .await
+    .wrap_err("connect publisher")
+    .unwrap();
+
+    let script = format!(
+        r#"(seq
+            (seq
+                (call %init_peer_id% ("getDataSrv" "hw_trigger") [] trigger)
+                (call %init_peer_id% ("pubsub" "subscribe") ["topic"])
+            )
+            (xor
+                (seq
+                    (ap trigger.$.topic.[0] event)
+                    (call "{}" ("return" "") [event])
+                )
+                (null)
+            )
+        )"#,
+        client.peer_id
+    );
+    let config = clock_config(0, 1, 0);
+    create_spell(&mut client, &script, config, json!({}), None).await;
+
+    let data = hashmap! {
+        "relay" => json!(publisher.node.to_string()),
+        "topic" => json!("topic"),
+        "data" => json!("hello"),
+    };
+    let script = r#"
+        (xor
+            (seq
+                (call relay ("pubsub" "publish") [topic data])
+                (call %init_peer_id% ("return" "") [true])
+            )
+            (call %init_peer_id% ("return" "") [false])
+        )"#;
+    // publishing fails until the nodes learn about each other's subscriptions
+    let mut published = false;
+    for _ in 0..20 {
+        let result = publisher
+            .execute_particle(script, data.clone())
+            .await
+            .unwrap();
+        if result == [JValue::Bool(true)] {
+            published = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+    assert!(published, "message must be published");
+
+    if let [event] = client
+        .receive_args()
+        .await
+        .wrap_err("receive")
+        .unwrap()
+        .as_slice()
+    {
+        assert_eq!(event["topic"], json!("topic"));
+        assert_eq!(event["data"], json!("hello"));
+        assert_eq!(event["peer_id"], json!(swarms[1].peer_id.to_string()));
+    } else {
+        panic!("wrong result from spell, expected topic event");
+    }
+}
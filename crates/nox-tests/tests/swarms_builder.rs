@@ -0,0 +1,63 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use connected_client::ConnectedClient;
+use created_swarm::SwarmsBuilder;
+use eyre::WrapErr;
+use maplit::hashmap;
+use serde_json::json;
+
+#[tokio::test]
+async fn nodes_with_different_services() {
+    let swarms = SwarmsBuilder::new(2)
+        .with_node_config(0, |mut cfg| {
+            cfg.enabled_system_services = vec!["registry".to_string()];
+            cfg
+        })
+        .build()
+        .await;
+
+    let mut client = ConnectedClient::connect_to(swarms[0].multiaddr.clone())
+        .await
+        .wrap_err("connect client")
+        .unwrap();
+
+    let result = client
+        .execute_particle(
+            r#"
+            (seq
+                (seq
+                    (call relay ("srv" "resolve_alias_opt") ["registry"] with_registry)
+                    (seq
+                        (call other ("srv" "resolve_alias_opt") ["registry"] without_registry)
+                        (call relay ("op" "noop") [])
+                    )
+                )
+                (call client ("return" "") [with_registry without_registry])
+            )
+            "#,
+            hashmap! {
+                "relay" => json!(client.node.to_string()),
+                "client" => json!(client.peer_id.to_string()),
+                "other" => json!(swarms[1].peer_id.to_string()),
+            },
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(result[0].as_array().unwrap().len(), 1);
+    assert_eq!(result[1], json!([]));
+}
@@ -0,0 +1,298 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::task::{Context, Poll, Waker};
+
+use libp2p::core::Endpoint;
+use libp2p::swarm::behaviour::{ConnectionClosed, ConnectionEstablished};
+use libp2p::swarm::dial_opts::{DialOpts, PeerCondition};
+use libp2p::swarm::handler::SubstreamProtocol;
+use libp2p::swarm::{
+    ConnectionDenied, ConnectionId, FromSwarm, NetworkBehaviour, NotifyHandler, OneShotHandler,
+    OneShotHandlerConfig, THandler, THandlerInEvent, THandlerOutEvent, ToSwarm,
+};
+use libp2p::{Multiaddr, PeerId};
+use rand::seq::IteratorRandom;
+
+use particle_protocol::Contact;
+
+use crate::config::PeerExchangeConfig;
+use crate::protocol::{HandlerEvent, PeerExchangeMessage, PeerExchangeProtocol, UPGRADE_TIMEOUT};
+
+/// Max number of addresses accepted for a single shared peer
+const MAX_ADDRESSES: usize = 8;
+
+#[derive(Debug)]
+pub enum PeerExchangeEvent {
+    /// `from` shared peers it's connected to. Peers we're already connected to are filtered out.
+    Discovered { from: PeerId, peers: Vec<Contact> },
+}
+
+/// Shares a random sample of identified nodes with each newly identified node
+/// and reports the samples received from them
+pub struct PeerExchange {
+    config: PeerExchangeConfig,
+    local_peer_id: PeerId,
+    connected: HashSet<PeerId>,
+    /// Connected nodes with their listen addresses, they are the ones shared with others
+    nodes: HashMap<PeerId, Vec<Multiaddr>>,
+    /// Nodes the sample was sent to during the current connection
+    sent: HashSet<PeerId>,
+    events: VecDeque<ToSwarm<PeerExchangeEvent, PeerExchangeMessage>>,
+    waker: Option<Waker>,
+}
+
+impl PeerExchange {
+    pub fn new(config: PeerExchangeConfig, local_peer_id: PeerId) -> Self {
+        Self {
+            config,
+            local_peer_id,
+            connected: <_>::default(),
+            nodes: <_>::default(),
+            sent: <_>::default(),
+            events: <_>::default(),
+            waker: None,
+        }
+    }
+
+    pub fn max_dials(&self) -> usize {
+        self.config.max_dials
+    }
+
+    pub fn is_connected(&self, peer_id: &PeerId) -> bool {
+        self.connected.contains(peer_id)
+    }
+
+    /// Registers an identified node, so it's shared with others, and sends it a sample
+    /// of other nodes if it supports the protocol and hasn't received one yet
+    pub fn add_node(
+        &mut self,
+        peer_id: PeerId,
+        addresses: Vec<Multiaddr>,
+        supports_exchange: bool,
+    ) {
+        if !self.connected.contains(&peer_id) || addresses.is_empty() {
+            return;
+        }
+        self.nodes.insert(peer_id, addresses);
+
+        if !supports_exchange || self.sent.contains(&peer_id) {
+            return;
+        }
+        let peers: Vec<_> = self
+            .nodes
+            .iter()
+            .filter(|(node, _)| **node != peer_id)
+            .choose_multiple(&mut rand::thread_rng(), self.config.sample_size)
+            .into_iter()
+            .map(|(node, addresses)| Contact::new(*node, addresses.clone()))
+            .collect();
+        // empty sample isn't sent, so the node gets one on the next identification
+        if peers.is_empty() {
+            return;
+        }
+
+        log::debug!(target: "network", "Sharing {} peers with {}", peers.len(), peer_id);
+        self.sent.insert(peer_id);
+        self.push_event(ToSwarm::NotifyHandler {
+            peer_id,
+            handler: NotifyHandler::Any,
+            event: PeerExchangeMessage { peers },
+        });
+    }
+
+    pub fn dial(&mut self, peer_id: PeerId, addresses: Vec<Multiaddr>) {
+        let opts = DialOpts::peer_id(peer_id)
+            .addresses(addresses)
+            .condition(PeerCondition::DisconnectedAndNotDialing)
+            .build();
+        self.push_event(ToSwarm::Dial { opts });
+    }
+
+    fn on_message(&mut self, from: PeerId, message: PeerExchangeMessage) {
+        let peers: Vec<_> = message
+            .peers
+            .into_iter()
+            .filter(|c| c.peer_id != self.local_peer_id && c.peer_id != from)
+            .filter(|c| !self.connected.contains(&c.peer_id) && !c.addresses.is_empty())
+            .take(self.config.sample_size)
+            .map(|mut c| {
+                c.addresses.truncate(MAX_ADDRESSES);
+                c
+            })
+            .collect();
+
+        log::debug!(target: "network", "Received {} new peers from {}", peers.len(), from);
+        if !peers.is_empty() {
+            self.push_event(ToSwarm::GenerateEvent(PeerExchangeEvent::Discovered {
+                from,
+                peers,
+            }));
+        }
+    }
+
+    fn push_event(&mut self, event: ToSwarm<PeerExchangeEvent, PeerExchangeMessage>) {
+        self.events.push_back(event);
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn handler(&self) -> THandler<Self> {
+        OneShotHandler::new(
+            SubstreamProtocol::new(PeerExchangeProtocol, ()).with_timeout(UPGRADE_TIMEOUT),
+            OneShotHandlerConfig::default(),
+        )
+    }
+}
+
+impl NetworkBehaviour for PeerExchange {
+    type ConnectionHandler =
+        OneShotHandler<PeerExchangeProtocol, PeerExchangeMessage, HandlerEvent>;
+    type ToSwarm = PeerExchangeEvent;
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        _peer_id: PeerId,
+        _local_addr: &Multiaddr,
+        _remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(self.handler())
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        _peer_id: PeerId,
+        _addr: &Multiaddr,
+        _role_override: Endpoint,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(self.handler())
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm<'_>) {
+        match event {
+            FromSwarm::ConnectionEstablished(ConnectionEstablished { peer_id, .. }) => {
+                self.connected.insert(peer_id);
+            }
+            FromSwarm::ConnectionClosed(ConnectionClosed {
+                peer_id,
+                remaining_established: 0,
+                ..
+            }) => {
+                self.connected.remove(&peer_id);
+                self.nodes.remove(&peer_id);
+                self.sent.remove(&peer_id);
+            }
+            _ => {}
+        }
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        peer_id: PeerId,
+        _connection_id: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        match event {
+            Ok(HandlerEvent::Received(message)) => self.on_message(peer_id, message),
+            Ok(HandlerEvent::Sent) => {}
+            Err(err) => {
+                log::debug!(target: "network", "Peer exchange with {peer_id} failed: {err}");
+            }
+        }
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        match self.events.pop_front() {
+            Some(event) => Poll::Ready(event),
+            None => {
+                self.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_libp2p::RandomPeerId;
+
+    use super::*;
+
+    fn addr(port: u16) -> Vec<Multiaddr> {
+        vec![format!("/ip4/1.2.3.4/tcp/{port}").parse().unwrap()]
+    }
+
+    fn connect(exchange: &mut PeerExchange, peer_id: PeerId) {
+        exchange.connected.insert(peer_id);
+    }
+
+    #[test]
+    fn share_sample_once() {
+        let mut exchange = PeerExchange::new(PeerExchangeConfig::default(), RandomPeerId::random());
+        let known = RandomPeerId::random();
+        let new = RandomPeerId::random();
+        connect(&mut exchange, known);
+        connect(&mut exchange, new);
+
+        exchange.add_node(known, addr(1), true);
+        // nothing to share with the first node
+        assert!(exchange.events.is_empty());
+
+        exchange.add_node(new, addr(2), true);
+        match exchange.events.pop_front() {
+            Some(ToSwarm::NotifyHandler { peer_id, event, .. }) => {
+                assert_eq!(peer_id, new);
+                assert_eq!(event.peers, vec![Contact::new(known, addr(1))]);
+            }
+            _ => panic!("expected sample to be sent"),
+        }
+
+        // re-identification doesn't resend the sample
+        exchange.add_node(new, addr(2), true);
+        assert!(exchange.events.is_empty());
+    }
+
+    #[test]
+    fn filter_received_peers() {
+        let local = RandomPeerId::random();
+        let mut exchange = PeerExchange::new(PeerExchangeConfig::default(), local);
+        let from = RandomPeerId::random();
+        let connected = RandomPeerId::random();
+        let discovered = RandomPeerId::random();
+        connect(&mut exchange, from);
+        connect(&mut exchange, connected);
+
+        let peers = [local, from, connected, discovered]
+            .into_iter()
+            .map(|peer_id| Contact::new(peer_id, addr(1)))
+            .collect();
+        exchange.on_message(from, PeerExchangeMessage { peers });
+
+        match exchange.events.pop_front() {
+            Some(ToSwarm::GenerateEvent(PeerExchangeEvent::Discovered { peers, .. })) => {
+                assert_eq!(peers, vec![Contact::new(discovered, addr(1))]);
+            }
+            _ => panic!("expected discovered peers"),
+        }
+    }
+}
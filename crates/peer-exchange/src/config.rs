@@ -0,0 +1,40 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use serde::{Deserialize, Serialize};
+
+/// Sharing of connected peers with newly connected nodes, so the node finds its way
+/// into the network even when bootstrap nodes are unavailable
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PeerExchangeConfig {
+    pub enabled: bool,
+    /// Max number of peers shared with a newly connected node.
+    /// Received samples are truncated to the same size.
+    pub sample_size: usize,
+    /// Max number of received peers dialed right away, the rest are only added to Kademlia
+    pub max_dials: usize,
+}
+
+impl Default for PeerExchangeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            sample_size: 16,
+            max_dials: 4,
+        }
+    }
+}
@@ -0,0 +1,154 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{io, iter, time::Duration};
+
+use futures::{future::BoxFuture, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, FutureExt};
+use libp2p::core::{InboundUpgrade, OutboundUpgrade, UpgradeInfo};
+use serde::{Deserialize, Serialize};
+
+use particle_protocol::Contact;
+
+pub const PROTOCOL_NAME: &str = "/fluence/peer-exchange/1.0.0";
+
+/// Messages larger than that are rejected
+const MAX_MESSAGE_SIZE: u64 = 64 * 1024;
+pub(crate) const UPGRADE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Sample of peers the sender is connected to
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct PeerExchangeMessage {
+    pub peers: Vec<Contact>,
+}
+
+/// Inbound side of the protocol, reads a single [PeerExchangeMessage]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PeerExchangeProtocol;
+
+#[derive(Debug)]
+pub enum HandlerEvent {
+    Received(PeerExchangeMessage),
+    Sent,
+}
+
+impl From<PeerExchangeMessage> for HandlerEvent {
+    fn from(message: PeerExchangeMessage) -> Self {
+        HandlerEvent::Received(message)
+    }
+}
+
+impl From<()> for HandlerEvent {
+    fn from(_: ()) -> Self {
+        HandlerEvent::Sent
+    }
+}
+
+macro_rules! impl_upgrade_info {
+    ($tname:ident) => {
+        impl UpgradeInfo for $tname {
+            type Info = &'static str;
+            type InfoIter = iter::Once<Self::Info>;
+
+            fn protocol_info(&self) -> Self::InfoIter {
+                iter::once(PROTOCOL_NAME)
+            }
+        }
+    };
+}
+
+impl_upgrade_info!(PeerExchangeProtocol);
+impl_upgrade_info!(PeerExchangeMessage);
+
+impl<Socket> InboundUpgrade<Socket> for PeerExchangeProtocol
+where
+    Socket: AsyncRead + Send + Unpin + 'static,
+{
+    type Output = PeerExchangeMessage;
+    type Error = io::Error;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_inbound(self, socket: Socket, _: Self::Info) -> Self::Future {
+        async move {
+            let mut bytes = vec![];
+            socket
+                .take(MAX_MESSAGE_SIZE + 1)
+                .read_to_end(&mut bytes)
+                .await?;
+            if bytes.len() as u64 > MAX_MESSAGE_SIZE {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("peer exchange message exceeds {MAX_MESSAGE_SIZE} bytes"),
+                ));
+            }
+            serde_json::from_slice(&bytes)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+        }
+        .boxed()
+    }
+}
+
+impl<Socket> OutboundUpgrade<Socket> for PeerExchangeMessage
+where
+    Socket: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    type Output = ();
+    type Error = io::Error;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_outbound(self, mut socket: Socket, _: Self::Info) -> Self::Future {
+        async move {
+            let bytes = serde_json::to_vec(&self)?;
+            socket.write_all(&bytes).await?;
+            // message ends with the stream, so it must be closed
+            socket.close().await
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::io::Cursor;
+    use libp2p::Multiaddr;
+
+    use fluence_libp2p::RandomPeerId;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn roundtrip() {
+        let addr: Multiaddr = "/ip4/1.2.3.4/tcp/7777".parse().unwrap();
+        let message = PeerExchangeMessage {
+            peers: vec![Contact::new(RandomPeerId::random(), vec![addr])],
+        };
+
+        let socket = Cursor::new(serde_json::to_vec(&message).unwrap());
+        let received = PeerExchangeProtocol
+            .upgrade_inbound(socket, PROTOCOL_NAME)
+            .await
+            .unwrap();
+        assert_eq!(received, message);
+    }
+
+    #[tokio::test]
+    async fn oversized_message() {
+        let socket = Cursor::new(vec![b' '; MAX_MESSAGE_SIZE as usize + 1]);
+        let result = PeerExchangeProtocol
+            .upgrade_inbound(socket, PROTOCOL_NAME)
+            .await;
+        assert!(result.is_err());
+    }
+}
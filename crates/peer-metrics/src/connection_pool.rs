@@ -1,16 +1,98 @@
+use std::time::Duration;
+
 use crate::{ParticleLabel, ParticleType};
+use fluence_libp2p::PeerId;
+use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue};
 use prometheus_client::metrics::counter::Counter;
 use prometheus_client::metrics::family::Family;
 use prometheus_client::metrics::gauge::Gauge;
 use prometheus_client::metrics::histogram::{exponential_buckets, Histogram};
 use prometheus_client::registry::Registry;
 
+#[derive(EncodeLabelValue, Hash, Clone, Copy, Eq, PartialEq, Debug)]
+pub enum ConnectionLimit {
+    PendingIncoming,
+    PendingOutgoing,
+    EstablishedIncoming,
+    EstablishedOutgoing,
+    EstablishedPerPeer,
+    EstablishedPerIp,
+    EstablishedTotal,
+}
+
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+pub struct ConnectionLimitLabel {
+    limit: ConnectionLimit,
+}
+
+#[derive(EncodeLabelValue, Hash, Clone, Copy, Eq, PartialEq, Debug)]
+pub enum SendFailure {
+    NotConnected,
+    TimedOut,
+    ProtocolError,
+    /// Connection was closed before the particle was sent
+    ConnectionClosed,
+}
+
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+pub struct SendFailureLabel {
+    reason: SendFailure,
+}
+
+#[derive(EncodeLabelValue, Hash, Clone, Copy, Eq, PartialEq, Debug)]
+pub enum SignatureRejection {
+    Missing,
+    Invalid,
+}
+
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+pub struct SignatureRejectionLabel {
+    reason: SignatureRejection,
+}
+
+#[derive(EncodeLabelValue, Hash, Clone, Copy, Eq, PartialEq, Debug)]
+pub enum DialResult {
+    Success,
+    Failure,
+}
+
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+pub struct DialResultLabel {
+    result: DialResult,
+}
+
+#[derive(EncodeLabelValue, Hash, Clone, Copy, Eq, PartialEq, Debug)]
+pub enum ConnectionDirection {
+    Inbound,
+    Outbound,
+}
+
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+pub struct ConnectionDirectionLabel {
+    direction: ConnectionDirection,
+}
+
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+pub struct PeerLabel {
+    peer_id: String,
+}
+
 #[derive(Clone)]
 pub struct ConnectionPoolMetrics {
     pub received_particles: Family<ParticleLabel, Counter>,
     pub particle_sizes: Family<ParticleLabel, Histogram>,
     pub connected_peers: Gauge,
     pub particle_queue_size: Gauge,
+    denied_connections: Family<ConnectionLimitLabel, Counter>,
+    throttled_particles: Family<ParticleLabel, Counter>,
+    throttled_particles_dropped: Family<ParticleLabel, Counter>,
+    rejected_particles: Family<ParticleLabel, Counter>,
+    signature_rejected_particles: Family<SignatureRejectionLabel, Counter>,
+    outbound_queue_size: Family<PeerLabel, Gauge>,
+    send_failures: Family<SendFailureLabel, Counter>,
+    dial_duration_sec: Family<DialResultLabel, Histogram>,
+    established_connections: Family<ConnectionDirectionLabel, Counter>,
+    closed_connections: Family<ConnectionDirectionLabel, Counter>,
 }
 
 impl ConnectionPoolMetrics {
@@ -47,11 +129,93 @@ impl ConnectionPoolMetrics {
             particle_queue_size.clone(),
         );
 
+        let denied_connections = Family::default();
+        sub_registry.register(
+            "denied_connections",
+            "Number of connections denied because of exceeded connection limits",
+            denied_connections.clone(),
+        );
+
+        let throttled_particles = Family::default();
+        sub_registry.register(
+            "throttled_particles",
+            "Number of particles refused because their senders exceeded rate limits",
+            throttled_particles.clone(),
+        );
+
+        let throttled_particles_dropped = Family::default();
+        sub_registry.register(
+            "throttled_particles_dropped",
+            "Number of particles dropped because too many particles were delayed by bandwidth limits",
+            throttled_particles_dropped.clone(),
+        );
+
+        let rejected_particles = Family::default();
+        sub_registry.register(
+            "rejected_particles",
+            "Number of particles refused because they exceeded size limits or weren't properly signed",
+            rejected_particles.clone(),
+        );
+
+        let signature_rejected_particles = Family::default();
+        sub_registry.register(
+            "signature_rejected_particles",
+            "Number of particles refused because of missing or invalid signatures",
+            signature_rejected_particles.clone(),
+        );
+
+        let outbound_queue_size = Family::default();
+        sub_registry.register(
+            "outbound_queue_size",
+            "Number of particles being sent to a peer, peers without particles in flight aren't reported",
+            outbound_queue_size.clone(),
+        );
+
+        let send_failures = Family::default();
+        sub_registry.register(
+            "send_failures",
+            "Number of particles that failed to be sent to remote peers",
+            send_failures.clone(),
+        );
+
+        // from 10 ms to ~40 sec
+        let dial_duration_sec: Family<_, _> =
+            Family::new_with_constructor(|| Histogram::new(exponential_buckets(0.01, 2.0, 13)));
+        sub_registry.register(
+            "dial_duration_sec",
+            "Time from starting a dial until the connection is established or the dial fails",
+            dial_duration_sec.clone(),
+        );
+
+        let established_connections = Family::default();
+        sub_registry.register(
+            "established_connections",
+            "Number of established connections",
+            established_connections.clone(),
+        );
+
+        let closed_connections = Family::default();
+        sub_registry.register(
+            "closed_connections",
+            "Number of closed connections",
+            closed_connections.clone(),
+        );
+
         Self {
             received_particles,
             particle_sizes,
             connected_peers,
             particle_queue_size,
+            denied_connections,
+            throttled_particles,
+            throttled_particles_dropped,
+            rejected_particles,
+            signature_rejected_particles,
+            outbound_queue_size,
+            send_failures,
+            dial_duration_sec,
+            established_connections,
+            closed_connections,
         }
     }
 
@@ -65,4 +229,75 @@ impl ConnectionPoolMetrics {
             .get_or_create(&label)
             .observe(particle_len);
     }
+
+    pub fn connection_denied(&self, limit: ConnectionLimit) {
+        self.denied_connections
+            .get_or_create(&ConnectionLimitLabel { limit })
+            .inc();
+    }
+
+    pub fn particle_throttled(&self, particle_id: &str) {
+        let label = ParticleLabel {
+            particle_type: ParticleType::from_particle(particle_id),
+        };
+        self.throttled_particles.get_or_create(&label).inc();
+    }
+
+    pub fn throttled_particle_dropped(&self, particle_id: &str) {
+        let label = ParticleLabel {
+            particle_type: ParticleType::from_particle(particle_id),
+        };
+        self.throttled_particles_dropped.get_or_create(&label).inc();
+    }
+
+    pub fn particle_rejected(&self, particle_id: &str) {
+        let label = ParticleLabel {
+            particle_type: ParticleType::from_particle(particle_id),
+        };
+        self.rejected_particles.get_or_create(&label).inc();
+    }
+
+    pub fn particle_signature_rejected(&self, reason: SignatureRejection) {
+        self.signature_rejected_particles
+            .get_or_create(&SignatureRejectionLabel { reason })
+            .inc();
+    }
+
+    /// Number of particles in flight to `peer_id`, the series is removed when it drops to zero
+    pub fn outbound_queue(&self, peer_id: &PeerId, size: usize) {
+        let label = PeerLabel {
+            peer_id: peer_id.to_string(),
+        };
+        if size == 0 {
+            self.outbound_queue_size.remove(&label);
+        } else {
+            self.outbound_queue_size
+                .get_or_create(&label)
+                .set(size as i64);
+        }
+    }
+
+    pub fn send_failed(&self, reason: SendFailure) {
+        self.send_failures
+            .get_or_create(&SendFailureLabel { reason })
+            .inc();
+    }
+
+    pub fn dial_finished(&self, result: DialResult, duration: Duration) {
+        self.dial_duration_sec
+            .get_or_create(&DialResultLabel { result })
+            .observe(duration.as_secs_f64());
+    }
+
+    pub fn connection_established(&self, direction: ConnectionDirection) {
+        self.established_connections
+            .get_or_create(&ConnectionDirectionLabel { direction })
+            .inc();
+    }
+
+    pub fn connection_closed(&self, direction: ConnectionDirection) {
+        self.closed_connections
+            .get_or_create(&ConnectionDirectionLabel { direction })
+            .inc();
+    }
 }
@@ -1,7 +1,10 @@
-use crate::{ParticleLabel, ParticleType};
+use std::time::Duration;
+
+use crate::{execution_time_buckets, ParticleLabel, ParticleType};
 use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue};
 use prometheus_client::metrics::counter::Counter;
 use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::histogram::Histogram;
 use prometheus_client::registry::Registry;
 
 #[derive(EncodeLabelValue, Hash, Clone, Eq, PartialEq, Debug)]
@@ -17,6 +20,24 @@ pub struct ResolutionLabel {
     action: Resolution,
 }
 
+/// Stages of particle processing on the current peer
+#[derive(EncodeLabelValue, Hash, Clone, Copy, Eq, PartialEq, Debug)]
+pub enum ParticleStage {
+    /// From receiving the particle until AquaVM picks it up
+    Queue,
+    Interpretation,
+    ServiceCalls,
+    Send,
+    /// From receiving the particle until it's sent to all next peers
+    Total,
+}
+
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+pub struct ParticleStageLabel {
+    particle_type: ParticleType,
+    stage: ParticleStage,
+}
+
 #[derive(Clone)]
 pub struct ConnectivityMetrics {
     contact_resolve: Family<ResolutionLabel, Counter>,
@@ -24,6 +45,7 @@ pub struct ConnectivityMetrics {
     pub particle_send_failure: Family<ParticleLabel, Counter>,
     pub bootstrap_disconnected: Counter,
     pub bootstrap_connected: Counter,
+    particle_stage_time_sec: Family<ParticleStageLabel, Histogram>,
 }
 
 impl ConnectivityMetrics {
@@ -65,12 +87,21 @@ impl ConnectivityMetrics {
             bootstrap_connected.clone(),
         );
 
+        let particle_stage_time_sec: Family<_, _> =
+            Family::new_with_constructor(|| Histogram::new(execution_time_buckets()));
+        sub_registry.register(
+            "particle_stage_time_sec",
+            "Distribution of time particles spend on each processing stage",
+            particle_stage_time_sec.clone(),
+        );
+
         Self {
             contact_resolve,
             particle_send_success,
             particle_send_failure,
             bootstrap_disconnected,
             bootstrap_connected,
+            particle_stage_time_sec,
         }
     }
 
@@ -95,4 +126,13 @@ impl ConnectivityMetrics {
             })
             .inc();
     }
+
+    pub fn particle_stage_time(&self, particle: &str, stage: ParticleStage, time: Duration) {
+        self.particle_stage_time_sec
+            .get_or_create(&ParticleStageLabel {
+                particle_type: ParticleType::from_particle(particle),
+                stage,
+            })
+            .observe(time.as_secs_f64());
+    }
 }
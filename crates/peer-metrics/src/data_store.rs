@@ -0,0 +1,63 @@
+use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue};
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::registry::Registry;
+
+#[derive(EncodeLabelValue, Hash, Clone, Copy, Eq, PartialEq, Debug)]
+pub enum DataStoreKind {
+    Particles,
+    Anomalies,
+}
+
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+pub struct DataStoreLabel {
+    store: DataStoreKind,
+}
+
+#[derive(Clone)]
+pub struct DataStoreMetrics {
+    pub removed_entries: Family<DataStoreLabel, Counter>,
+    pub reclaimed_bytes: Family<DataStoreLabel, Counter>,
+    pub store_size: Family<DataStoreLabel, Gauge>,
+}
+
+impl DataStoreMetrics {
+    pub fn new(registry: &mut Registry) -> Self {
+        let sub_registry = registry.sub_registry_with_prefix("data_store");
+
+        let removed_entries = Family::default();
+        sub_registry.register(
+            "removed_entries",
+            "Number of particle data files and anomaly dumps removed by compaction",
+            removed_entries.clone(),
+        );
+
+        let reclaimed_bytes = Family::default();
+        sub_registry.register(
+            "reclaimed_bytes",
+            "Disk space reclaimed by compaction in bytes",
+            reclaimed_bytes.clone(),
+        );
+
+        let store_size = Family::default();
+        sub_registry.register(
+            "size_bytes",
+            "Size of the store after the last compaction in bytes",
+            store_size.clone(),
+        );
+
+        Self {
+            removed_entries,
+            reclaimed_bytes,
+            store_size,
+        }
+    }
+
+    pub fn compacted(&self, store: DataStoreKind, removed: u64, reclaimed: u64, size: u64) {
+        let label = DataStoreLabel { store };
+        self.removed_entries.get_or_create(&label).inc_by(removed);
+        self.reclaimed_bytes.get_or_create(&label).inc_by(reclaimed);
+        self.store_size.get_or_create(&label).set(size as i64);
+    }
+}
@@ -0,0 +1,88 @@
+use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue};
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::registry::Registry;
+
+#[derive(EncodeLabelValue, Hash, Clone, Copy, Eq, PartialEq, Debug)]
+pub enum PrefetchResult {
+    Fetched,
+    Failed,
+}
+
+/// State of the deal content prefetch when the deal's worker is created
+#[derive(EncodeLabelValue, Hash, Clone, Copy, Eq, PartialEq, Debug)]
+pub enum PrefetchUsage {
+    /// Content has been fetched
+    Hit,
+    /// Content is still being fetched
+    Pending,
+    /// Content hasn't been prefetched or the prefetch has failed
+    Miss,
+}
+
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+pub struct PrefetchResultLabel {
+    result: PrefetchResult,
+}
+
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+pub struct PrefetchUsageLabel {
+    usage: PrefetchUsage,
+}
+
+#[derive(Clone)]
+pub struct DealPrefetchMetrics {
+    pub prefetches: Family<PrefetchResultLabel, Counter>,
+    pub activations: Family<PrefetchUsageLabel, Counter>,
+    pub cached_deals: Gauge,
+}
+
+impl DealPrefetchMetrics {
+    pub fn new(registry: &mut Registry) -> Self {
+        let sub_registry = registry.sub_registry_with_prefix("deal_prefetch");
+
+        let prefetches = Family::default();
+        sub_registry.register(
+            "prefetches",
+            "Number of finished prefetches of matched deals' content from IPFS",
+            prefetches.clone(),
+        );
+
+        let activations = Family::default();
+        sub_registry.register(
+            "activations",
+            "Number of created deal workers by whether their content was prefetched",
+            activations.clone(),
+        );
+
+        let cached_deals = Gauge::default();
+        sub_registry.register(
+            "cached_deals",
+            "Number of deals tracked by the prefetch cache",
+            cached_deals.clone(),
+        );
+
+        Self {
+            prefetches,
+            activations,
+            cached_deals,
+        }
+    }
+
+    pub fn prefetched(&self, result: PrefetchResult) {
+        self.prefetches
+            .get_or_create(&PrefetchResultLabel { result })
+            .inc();
+    }
+
+    pub fn activated(&self, usage: PrefetchUsage) {
+        self.activations
+            .get_or_create(&PrefetchUsageLabel { usage })
+            .inc();
+    }
+
+    pub fn cached(&self, deals: usize) {
+        self.cached_deals.set(deals as i64);
+    }
+}
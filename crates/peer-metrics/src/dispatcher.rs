@@ -1,11 +1,15 @@
 use crate::{ParticleLabel, ParticleType};
 use prometheus_client::metrics::counter::Counter;
 use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
 use prometheus_client::registry::Registry;
 
 #[derive(Clone)]
 pub struct DispatcherMetrics {
     pub expired_particles: Family<ParticleLabel, Counter>,
+    pub duplicate_particles: Family<ParticleLabel, Counter>,
+    pub seen_particles: Gauge,
+    pub seen_particles_evicted: Counter,
 }
 
 impl DispatcherMetrics {
@@ -32,7 +36,33 @@ impl DispatcherMetrics {
             expired_particles.clone(),
         );
 
-        DispatcherMetrics { expired_particles }
+        let duplicate_particles = Family::default();
+        sub_registry.register(
+            "particles_duplicate",
+            "Number of particles rejected as already received",
+            duplicate_particles.clone(),
+        );
+
+        let seen_particles = Gauge::default();
+        sub_registry.register(
+            "seen_particles",
+            "Number of particles remembered to reject duplicates",
+            seen_particles.clone(),
+        );
+
+        let seen_particles_evicted = Counter::default();
+        sub_registry.register(
+            "seen_particles_evicted",
+            "Number of remembered particles evicted before their deadline, their duplicates aren't rejected",
+            seen_particles_evicted.clone(),
+        );
+
+        DispatcherMetrics {
+            expired_particles,
+            duplicate_particles,
+            seen_particles,
+            seen_particles_evicted,
+        }
     }
 
     pub fn particle_expired(&self, particle_id: &str) {
@@ -42,4 +72,18 @@ impl DispatcherMetrics {
             })
             .inc();
     }
+
+    pub fn particle_duplicate(&self, particle_id: &str) {
+        self.duplicate_particles
+            .get_or_create(&ParticleLabel {
+                particle_type: ParticleType::from_particle(particle_id),
+            })
+            .inc();
+    }
+
+    /// Number of remembered particles, and how many were evicted since the previous report
+    pub fn seen_particles(&self, entries: usize, evicted: u64) {
+        self.seen_particles.set(entries as i64);
+        self.seen_particles_evicted.inc_by(evicted);
+    }
 }
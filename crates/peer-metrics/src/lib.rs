@@ -3,24 +3,34 @@ use std::fmt::Debug;
 use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue, EncodeMetric};
 use prometheus_client::registry::Registry;
 
-pub use connection_pool::ConnectionPoolMetrics;
+pub use connection_pool::{
+    ConnectionDirection, ConnectionLimit, ConnectionPoolMetrics, DialResult, SendFailure,
+    SignatureRejection,
+};
 pub use connectivity::ConnectivityMetrics;
-pub use connectivity::Resolution;
+pub use connectivity::{ParticleStage, Resolution};
+pub use data_store::{DataStoreKind, DataStoreMetrics};
+pub use deal_prefetch::{DealPrefetchMetrics, PrefetchResult, PrefetchUsage};
 pub use dispatcher::DispatcherMetrics;
 pub use info::add_info_metrics;
+pub use lifecycle::{LifecycleMetrics, LifecycleStage};
+pub use network_protocol::{DialOutcome, NetworkProtocolMetrics};
 use particle_execution::ParticleParams;
 pub use particle_executor::{FunctionKind, ParticleExecutorMetrics, WorkerLabel, WorkerType};
 pub use services_metrics::{
     ServiceCallStats, ServiceMemoryStat, ServiceType, ServicesMetrics, ServicesMetricsBackend,
     ServicesMetricsBuiltin, ServicesMetricsExternal,
 };
-pub use spell_metrics::SpellMetrics;
+pub use spell_metrics::{SpellMetrics, SpellTriggerType};
 pub use vm_pool::VmPoolMetrics;
 
 mod connection_pool;
 mod connectivity;
+mod data_store;
+mod deal_prefetch;
 mod dispatcher;
 mod info;
+mod lifecycle;
 mod network_protocol;
 mod particle_executor;
 mod services_metrics;
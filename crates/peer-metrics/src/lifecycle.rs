@@ -0,0 +1,72 @@
+use std::sync::atomic::AtomicU64;
+use std::time::Duration;
+
+use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue};
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::registry::Registry;
+
+#[derive(EncodeLabelValue, Hash, Clone, Copy, Eq, PartialEq, Debug)]
+pub enum LifecycleStage {
+    Startup,
+    Shutdown,
+}
+
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+pub struct StageLabel {
+    stage: LifecycleStage,
+}
+
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+pub struct PhaseLabel {
+    stage: LifecycleStage,
+    phase: String,
+}
+
+#[derive(Clone)]
+pub struct LifecycleMetrics {
+    pub duration: Family<StageLabel, Gauge<f64, AtomicU64>>,
+    pub phase_duration: Family<PhaseLabel, Gauge<f64, AtomicU64>>,
+}
+
+impl LifecycleMetrics {
+    pub fn new(registry: &mut Registry) -> Self {
+        let sub_registry = registry.sub_registry_with_prefix("lifecycle");
+
+        let duration = Family::default();
+        sub_registry.register(
+            "duration_seconds",
+            "Duration of the last node startup",
+            duration.clone(),
+        );
+
+        let phase_duration = Family::default();
+        sub_registry.register(
+            "phase_duration_seconds",
+            "Duration of startup phases, including subsystems initialized after the node has started",
+            phase_duration.clone(),
+        );
+
+        Self {
+            duration,
+            phase_duration,
+        }
+    }
+
+    pub fn finished(&self, stage: LifecycleStage, elapsed: Duration) {
+        let label = StageLabel { stage };
+        self.duration
+            .get_or_create(&label)
+            .set(elapsed.as_secs_f64());
+    }
+
+    pub fn phase_finished(&self, stage: LifecycleStage, phase: &str, elapsed: Duration) {
+        let label = PhaseLabel {
+            stage,
+            phase: phase.to_string(),
+        };
+        self.phase_duration
+            .get_or_create(&label)
+            .set(elapsed.as_secs_f64());
+    }
+}
@@ -1,2 +1,123 @@
-// TODO:
-// - number of bytes transmitted
+use std::time::Duration;
+
+use fluence_libp2p::StreamObserver;
+use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue};
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::metrics::histogram::{exponential_buckets, Histogram};
+use prometheus_client::registry::Registry;
+
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+pub struct ProtocolLabel {
+    protocol: String,
+}
+
+impl ProtocolLabel {
+    fn new(protocol: &str) -> Self {
+        Self {
+            protocol: protocol.to_string(),
+        }
+    }
+}
+
+#[derive(EncodeLabelValue, Hash, Clone, Copy, Eq, PartialEq, Debug)]
+pub enum DialOutcome {
+    Success,
+    Failure,
+}
+
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+pub struct DialLabel {
+    transport: String,
+    outcome: DialOutcome,
+}
+
+/// Traffic of libp2p streams by negotiated protocol and latency of outgoing dials.
+/// Traffic by transport is reported by the libp2p bandwidth metrics.
+#[derive(Clone)]
+pub struct NetworkProtocolMetrics {
+    bytes_sent: Family<ProtocolLabel, Counter>,
+    bytes_received: Family<ProtocolLabel, Counter>,
+    active_streams: Family<ProtocolLabel, Gauge>,
+    dial_duration: Family<DialLabel, Histogram>,
+}
+
+impl NetworkProtocolMetrics {
+    pub fn new(registry: &mut Registry) -> Self {
+        let sub_registry = registry.sub_registry_with_prefix("network_protocol");
+
+        let bytes_sent = Family::default();
+        sub_registry.register(
+            "bytes_sent",
+            "Number of bytes sent over streams of a protocol",
+            bytes_sent.clone(),
+        );
+
+        let bytes_received = Family::default();
+        sub_registry.register(
+            "bytes_received",
+            "Number of bytes received over streams of a protocol",
+            bytes_received.clone(),
+        );
+
+        let active_streams = Family::default();
+        sub_registry.register(
+            "active_streams",
+            "Number of open streams of a protocol at a given moment",
+            active_streams.clone(),
+        );
+
+        // from 10 ms to 40 seconds
+        let dial_duration: Family<_, _> =
+            Family::new_with_constructor(|| Histogram::new(exponential_buckets(0.01, 2.0, 13)));
+        sub_registry.register(
+            "dial_duration_seconds",
+            "Time from the start of a dial to an established connection or a failure",
+            dial_duration.clone(),
+        );
+
+        Self {
+            bytes_sent,
+            bytes_received,
+            active_streams,
+            dial_duration,
+        }
+    }
+
+    pub fn dial_finished(&self, transport: &str, outcome: DialOutcome, duration: Duration) {
+        let label = DialLabel {
+            transport: transport.to_string(),
+            outcome,
+        };
+        self.dial_duration
+            .get_or_create(&label)
+            .observe(duration.as_secs_f64());
+    }
+}
+
+impl StreamObserver for NetworkProtocolMetrics {
+    fn stream_opened(&self, protocol: &str) {
+        self.active_streams
+            .get_or_create(&ProtocolLabel::new(protocol))
+            .inc();
+    }
+
+    fn stream_closed(&self, protocol: &str) {
+        self.active_streams
+            .get_or_create(&ProtocolLabel::new(protocol))
+            .dec();
+    }
+
+    fn bytes_sent(&self, protocol: &str, bytes: u64) {
+        self.bytes_sent
+            .get_or_create(&ProtocolLabel::new(protocol))
+            .inc_by(bytes);
+    }
+
+    fn bytes_received(&self, protocol: &str, bytes: u64) {
+        self.bytes_received
+            .get_or_create(&ProtocolLabel::new(protocol))
+            .inc_by(bytes);
+    }
+}
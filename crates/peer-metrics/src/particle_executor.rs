@@ -29,6 +29,7 @@ pub struct ParticleExecutorMetrics {
     pub interpretation_failures: Family<WorkerLabel, Counter>,
     pub total_actors_mailbox: Family<WorkerLabel, Gauge>,
     pub alive_actors: Family<WorkerLabel, Gauge>,
+    pub data_size_exceeded: Family<WorkerLabel, Counter>,
     service_call_time_sec: Family<FunctionKindLabel, Histogram>,
     service_call_success: Family<FunctionKindLabel, Counter>,
     service_call_failure: Family<FunctionKindLabel, Counter>,
@@ -102,6 +103,13 @@ impl ParticleExecutorMetrics {
             alive_actors.clone(),
         );
 
+        let data_size_exceeded = Family::default();
+        sub_registry.register(
+            "data_size_exceeded",
+            "Number of particles dropped because their data grew beyond the limit during execution",
+            data_size_exceeded.clone(),
+        );
+
         let service_call_time_sec: Family<_, _> =
             Family::new_with_constructor(|| Histogram::new(execution_time_buckets()));
         sub_registry.register(
@@ -128,6 +136,7 @@ impl ParticleExecutorMetrics {
             interpretation_failures,
             total_actors_mailbox,
             alive_actors,
+            data_size_exceeded,
             service_call_time_sec,
             service_call_success,
             service_call_failure,
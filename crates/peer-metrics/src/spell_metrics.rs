@@ -1,9 +1,31 @@
+use std::time::Duration;
+
 use crate::register;
+use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue};
 use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
 use prometheus_client::metrics::gauge::Gauge;
-use prometheus_client::metrics::histogram::Histogram;
+use prometheus_client::metrics::histogram::{exponential_buckets, Histogram};
 use prometheus_client::registry::Registry;
 
+#[derive(EncodeLabelValue, Hash, Clone, Copy, Eq, PartialEq, Debug)]
+pub enum SpellTriggerType {
+    Timer,
+    PeerEvent,
+    Topic,
+}
+
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+pub struct SpellTriggerLabel {
+    trigger_type: SpellTriggerType,
+}
+
+impl SpellTriggerLabel {
+    fn new(trigger_type: SpellTriggerType) -> Self {
+        Self { trigger_type }
+    }
+}
+
 #[derive(Clone)]
 pub struct SpellMetrics {
     // How much spell _particles_ were created by the node
@@ -12,6 +34,18 @@ pub struct SpellMetrics {
     spell_scheduled_now: Gauge,
     // Distribution of spell's scheduled periods
     spell_periods: Histogram,
+    // Subscriptions in the event bus
+    bus_subscriptions: Family<SpellTriggerLabel, Gauge>,
+    // Events that triggered at least a subscription check in the event bus
+    bus_events_produced: Family<SpellTriggerLabel, Counter>,
+    // Triggers sent to the subscribed spells
+    bus_events_delivered: Family<SpellTriggerLabel, Counter>,
+    // Triggers that couldn't be sent to the spells
+    bus_events_dropped: Family<SpellTriggerLabel, Counter>,
+    // How late timer triggers fire comparing to the scheduled time
+    bus_scheduling_lag_sec: Histogram,
+    // Time from a trigger until the spell particle is created
+    trigger_latency_sec: Family<SpellTriggerLabel, Histogram>,
 }
 
 impl SpellMetrics {
@@ -39,13 +73,67 @@ impl SpellMetrics {
             "Spell particle periods",
         );
 
+        let bus_subscriptions = register(
+            sub_registry,
+            Family::default(),
+            "bus_subscriptions",
+            "Number of spell subscriptions in the event bus by trigger type",
+        );
+
+        let bus_events_produced = register(
+            sub_registry,
+            Family::default(),
+            "bus_events_produced",
+            "Number of events received by the event bus by trigger type",
+        );
+
+        let bus_events_delivered = register(
+            sub_registry,
+            Family::default(),
+            "bus_events_delivered",
+            "Number of triggers sent to spells by trigger type",
+        );
+
+        let bus_events_dropped = register(
+            sub_registry,
+            Family::default(),
+            "bus_events_dropped",
+            "Number of triggers that couldn't be sent to spells by trigger type",
+        );
+
+        let bus_scheduling_lag_sec = register(
+            sub_registry,
+            Histogram::new(Self::latency_buckets()),
+            "bus_scheduling_lag_sec",
+            "Delay between the scheduled and the actual time of timer triggers",
+        );
+
+        let trigger_latency_sec: Family<_, _> =
+            Family::new_with_constructor(|| Histogram::new(Self::latency_buckets()));
+        sub_registry.register(
+            "trigger_latency_sec",
+            "Time from a trigger until the spell particle is created by trigger type",
+            trigger_latency_sec.clone(),
+        );
+
         Self {
             spell_particles_created,
             spell_scheduled_now,
             spell_periods,
+            bus_subscriptions,
+            bus_events_produced,
+            bus_events_delivered,
+            bus_events_dropped,
+            bus_scheduling_lag_sec,
+            trigger_latency_sec,
         }
     }
 
+    fn latency_buckets() -> impl Iterator<Item = f64> {
+        // 1 ms to ~65 sec
+        exponential_buckets(0.001, 2.0, 17)
+    }
+
     fn periods_buckets() -> std::vec::IntoIter<f64> {
         // 0.0 sec, 1 sec, 30 sec, 1 min, 5 min, 10 min, 1 hour, 12 hours, 1 day, 1 week, 1 month
         // 0 means that the spell is oneshot or reacts only on events
@@ -77,4 +165,38 @@ impl SpellMetrics {
     pub fn observe_spell_cast(&self) {
         self.spell_particles_created.inc();
     }
+
+    pub fn bus_subscriptions(&self, trigger_type: SpellTriggerType, count: usize) {
+        self.bus_subscriptions
+            .get_or_create(&SpellTriggerLabel::new(trigger_type))
+            .set(count as i64);
+    }
+
+    pub fn bus_event_produced(&self, trigger_type: SpellTriggerType) {
+        self.bus_events_produced
+            .get_or_create(&SpellTriggerLabel::new(trigger_type))
+            .inc();
+    }
+
+    pub fn bus_event_delivered(&self, trigger_type: SpellTriggerType) {
+        self.bus_events_delivered
+            .get_or_create(&SpellTriggerLabel::new(trigger_type))
+            .inc();
+    }
+
+    pub fn bus_event_dropped(&self, trigger_type: SpellTriggerType) {
+        self.bus_events_dropped
+            .get_or_create(&SpellTriggerLabel::new(trigger_type))
+            .inc();
+    }
+
+    pub fn bus_scheduling_lag(&self, lag: Duration) {
+        self.bus_scheduling_lag_sec.observe(lag.as_secs_f64());
+    }
+
+    pub fn trigger_latency(&self, trigger_type: SpellTriggerType, latency: Duration) {
+        self.trigger_latency_sec
+            .get_or_create(&SpellTriggerLabel::new(trigger_type))
+            .observe(latency.as_secs_f64());
+    }
 }
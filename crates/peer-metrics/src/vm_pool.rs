@@ -11,6 +11,10 @@ use crate::mem_buckets;
 pub struct VmPoolMetrics {
     pool_size: Gauge,
     pub free_vms: Gauge,
+    pub busy_vms: Gauge,
+    pub waiting_actors: Gauge,
+    pub scale_up: Counter,
+    pub scale_down: Counter,
     pub get_vm: Counter,
     pub put_vm: Counter,
     pub no_free_vm: Counter,
@@ -44,6 +48,34 @@ impl VmPoolMetrics {
             free_vms.clone(),
         );
 
+        let busy_vms = Gauge::default();
+        sub_registry.register(
+            "busy_vms",
+            "Number of AquaVMs currently executing particles",
+            busy_vms.clone(),
+        );
+
+        let waiting_actors = Gauge::default();
+        sub_registry.register(
+            "waiting_actors",
+            "Number of actors with pending particles waiting for a free AquaVM",
+            waiting_actors.clone(),
+        );
+
+        let scale_up = Counter::default();
+        sub_registry.register(
+            "scale_up",
+            "Number of times the AquaVM pool has grown",
+            scale_up.clone(),
+        );
+
+        let scale_down = Counter::default();
+        sub_registry.register(
+            "scale_down",
+            "Number of times the AquaVM pool has shrunk",
+            scale_down.clone(),
+        );
+
         let get_vm = Counter::default();
         sub_registry.register(
             "get_vm",
@@ -100,6 +132,10 @@ impl VmPoolMetrics {
         Self {
             pool_size,
             free_vms,
+            busy_vms,
+            waiting_actors,
+            scale_up,
+            scale_down,
             get_vm,
             put_vm,
             no_free_vm,
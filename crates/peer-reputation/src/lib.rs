@@ -0,0 +1,364 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Per-peer reputation.
+//!
+//! Misbehaviour of remote peers is reported as [`Offence`]s, each lowering the peer's score.
+//! Failures on our side, e.g. failed dials to offline peers, aren't offences.
+//! Scores decay back to zero over time, so peers are eventually forgiven.
+//! See [`PeerReputation`] for details.
+
+#![warn(rust_2018_idioms)]
+#![deny(
+    dead_code,
+    nonstandard_style,
+    unused_imports,
+    unused_mut,
+    unused_variables,
+    unused_unsafe,
+    unreachable_patterns
+)]
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use libp2p::PeerId;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+/// Scores closer to zero than that are considered neutral and are forgotten
+const NEGLIGIBLE_SCORE: f64 = 0.01;
+/// Number of tracked peers after which neutral peers are forgotten
+const PRUNE_THRESHOLD: usize = 1024;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ReputationConfig {
+    /// Penalty for a particle that failed to be decoded
+    pub malformed_particle_penalty: f64,
+    /// Penalty for a particle that is already expired when received
+    pub expired_particle_penalty: f64,
+    /// Penalty for exceeding `max_particles_per_window`
+    pub excessive_traffic_penalty: f64,
+    /// Max number of particles a peer may send during `traffic_window`
+    pub max_particles_per_window: u32,
+    #[serde(with = "humantime_serde")]
+    pub traffic_window: Duration,
+    /// Time it takes for a score to decay by half
+    #[serde(with = "humantime_serde")]
+    pub decay_half_life: Duration,
+    /// Particles from peers with score below that are processed after everyone else's
+    pub low_priority_threshold: f64,
+    /// Peers with score below that are refused connections and excluded from Kademlia
+    pub ban_threshold: f64,
+}
+
+impl Default for ReputationConfig {
+    fn default() -> Self {
+        Self {
+            malformed_particle_penalty: 20.0,
+            expired_particle_penalty: 1.0,
+            excessive_traffic_penalty: 10.0,
+            max_particles_per_window: 1000,
+            traffic_window: Duration::from_secs(10),
+            decay_half_life: Duration::from_secs(10 * 60),
+            low_priority_threshold: -20.0,
+            ban_threshold: -100.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Offence {
+    MalformedParticle,
+    ExpiredParticle,
+    ExcessiveTraffic,
+}
+
+/// Snapshot of a peer's reputation
+#[derive(Debug, Clone, Serialize)]
+pub struct PeerScore {
+    #[serde(serialize_with = "serialize_peer_id")]
+    pub peer_id: PeerId,
+    pub score: f64,
+    pub malformed_particles: u32,
+    pub expired_particles: u32,
+    pub excessive_traffic: u32,
+}
+
+fn serialize_peer_id<S: serde::Serializer>(peer_id: &PeerId, s: S) -> Result<S::Ok, S::Error> {
+    s.collect_str(peer_id)
+}
+
+#[derive(Debug)]
+struct Entry {
+    score: f64,
+    updated: Instant,
+    window_start: Instant,
+    window_particles: u32,
+    malformed_particles: u32,
+    expired_particles: u32,
+    excessive_traffic: u32,
+}
+
+impl Entry {
+    fn new(now: Instant) -> Self {
+        Self {
+            score: 0.0,
+            updated: now,
+            window_start: now,
+            window_particles: 0,
+            malformed_particles: 0,
+            expired_particles: 0,
+            excessive_traffic: 0,
+        }
+    }
+
+    fn decayed_score(&self, half_life: Duration, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        let half_life = half_life.as_secs_f64().max(f64::EPSILON);
+        self.score * 0.5f64.powf(elapsed / half_life)
+    }
+
+    fn decay(&mut self, half_life: Duration, now: Instant) {
+        self.score = self.decayed_score(half_life, now);
+        self.updated = now;
+    }
+}
+
+/// Shared table of peer scores. Cloning is cheap, all clones refer to the same table.
+#[derive(Debug, Clone)]
+pub struct PeerReputation {
+    config: Arc<ReputationConfig>,
+    peers: Arc<Mutex<HashMap<PeerId, Entry>>>,
+}
+
+impl PeerReputation {
+    pub fn new(config: ReputationConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            peers: <_>::default(),
+        }
+    }
+
+    pub fn report(&self, peer_id: PeerId, offence: Offence) {
+        self.report_at(peer_id, offence, Instant::now())
+    }
+
+    /// Counts a particle received from `peer_id`, penalizing the peer if it sends too many
+    pub fn record_particle(&self, peer_id: PeerId) {
+        self.record_particle_at(peer_id, Instant::now())
+    }
+
+    pub fn score(&self, peer_id: &PeerId) -> f64 {
+        self.score_at(peer_id, Instant::now())
+    }
+
+    pub fn is_banned(&self, peer_id: &PeerId) -> bool {
+        self.score(peer_id) < self.config.ban_threshold
+    }
+
+    pub fn is_low_priority(&self, peer_id: &PeerId) -> bool {
+        self.score(peer_id) < self.config.low_priority_threshold
+    }
+
+    /// Scores of all peers that misbehaved recently, worst first
+    pub fn scores(&self) -> Vec<PeerScore> {
+        let now = Instant::now();
+        let peers = self.peers.lock();
+        let mut scores: Vec<_> = peers
+            .iter()
+            .map(|(peer_id, entry)| PeerScore {
+                peer_id: *peer_id,
+                score: entry.decayed_score(self.config.decay_half_life, now),
+                malformed_particles: entry.malformed_particles,
+                expired_particles: entry.expired_particles,
+                excessive_traffic: entry.excessive_traffic,
+            })
+            .filter(|score| score.score.abs() > NEGLIGIBLE_SCORE)
+            .collect();
+        scores.sort_by(|a, b| a.score.total_cmp(&b.score));
+        scores
+    }
+
+    fn penalty(&self, offence: Offence) -> f64 {
+        match offence {
+            Offence::MalformedParticle => self.config.malformed_particle_penalty,
+            Offence::ExpiredParticle => self.config.expired_particle_penalty,
+            Offence::ExcessiveTraffic => self.config.excessive_traffic_penalty,
+        }
+    }
+
+    fn report_at(&self, peer_id: PeerId, offence: Offence, now: Instant) {
+        let mut peers = self.peers.lock();
+        self.prune(&mut peers, now);
+
+        let entry = peers.entry(peer_id).or_insert_with(|| Entry::new(now));
+        self.punish(entry, offence, now);
+        log::debug!(
+            target: "reputation",
+            "Peer {peer_id} reported for {offence:?}, score {:.2}",
+            entry.score
+        );
+    }
+
+    fn record_particle_at(&self, peer_id: PeerId, now: Instant) {
+        let mut peers = self.peers.lock();
+        self.prune(&mut peers, now);
+
+        let entry = peers.entry(peer_id).or_insert_with(|| Entry::new(now));
+        if now.saturating_duration_since(entry.window_start) >= self.config.traffic_window {
+            entry.window_start = now;
+            entry.window_particles = 0;
+        }
+        entry.window_particles += 1;
+
+        // penalize once per window
+        if entry.window_particles == self.config.max_particles_per_window + 1 {
+            self.punish(entry, Offence::ExcessiveTraffic, now);
+            log::debug!(
+                target: "reputation",
+                "Peer {peer_id} sent more than {} particles in {:?}, score {:.2}",
+                self.config.max_particles_per_window,
+                self.config.traffic_window,
+                entry.score
+            );
+        }
+    }
+
+    fn punish(&self, entry: &mut Entry, offence: Offence, now: Instant) {
+        entry.decay(self.config.decay_half_life, now);
+        entry.score -= self.penalty(offence);
+        match offence {
+            Offence::MalformedParticle => entry.malformed_particles += 1,
+            Offence::ExpiredParticle => entry.expired_particles += 1,
+            Offence::ExcessiveTraffic => entry.excessive_traffic += 1,
+        }
+    }
+
+    fn score_at(&self, peer_id: &PeerId, now: Instant) -> f64 {
+        self.peers.lock().get(peer_id).map_or(0.0, |entry| {
+            entry.decayed_score(self.config.decay_half_life, now)
+        })
+    }
+
+    /// Forgets peers whose score has decayed to neutral and who are not being rate-tracked
+    fn prune(&self, peers: &mut HashMap<PeerId, Entry>, now: Instant) {
+        if peers.len() < PRUNE_THRESHOLD {
+            return;
+        }
+
+        let config = &self.config;
+        peers.retain(|_, entry| {
+            let in_window =
+                now.saturating_duration_since(entry.window_start) < config.traffic_window;
+            in_window || entry.decayed_score(config.decay_half_life, now).abs() > NEGLIGIBLE_SCORE
+        });
+    }
+}
+
+impl Default for PeerReputation {
+    fn default() -> Self {
+        Self::new(ReputationConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_libp2p::RandomPeerId;
+
+    use super::*;
+
+    #[test]
+    fn offences_lower_score() {
+        let reputation = PeerReputation::default();
+        let peer = RandomPeerId::random();
+        let now = Instant::now();
+
+        reputation.report_at(peer, Offence::ExpiredParticle, now);
+        reputation.report_at(peer, Offence::MalformedParticle, now);
+        assert_eq!(reputation.score_at(&peer, now), -21.0);
+        assert_eq!(reputation.score_at(&RandomPeerId::random(), now), 0.0);
+
+        let scores = reputation.scores();
+        assert_eq!(scores.len(), 1);
+        assert_eq!(scores[0].expired_particles, 1);
+        assert_eq!(scores[0].malformed_particles, 1);
+    }
+
+    #[test]
+    fn score_decays() {
+        let config = ReputationConfig::default();
+        let half_life = config.decay_half_life;
+        let reputation = PeerReputation::new(config);
+        let peer = RandomPeerId::random();
+        let now = Instant::now();
+
+        reputation.report_at(peer, Offence::MalformedParticle, now);
+        let score = reputation.score_at(&peer, now + half_life);
+        assert!((score + 10.0).abs() < 1e-9, "score is {score}");
+
+        // penalty is applied on top of the decayed score
+        reputation.report_at(peer, Offence::MalformedParticle, now + half_life);
+        let score = reputation.score_at(&peer, now + half_life);
+        assert!((score + 30.0).abs() < 1e-9, "score is {score}");
+    }
+
+    #[test]
+    fn excessive_traffic() {
+        let config = ReputationConfig {
+            max_particles_per_window: 2,
+            ..<_>::default()
+        };
+        let window = config.traffic_window;
+        let half_life = config.decay_half_life;
+        let reputation = PeerReputation::new(config);
+        let peer = RandomPeerId::random();
+        let now = Instant::now();
+
+        for _ in 0..5 {
+            reputation.record_particle_at(peer, now);
+        }
+        // penalized once per window
+        assert_eq!(reputation.score_at(&peer, now), -10.0);
+
+        // new window starts from scratch
+        for _ in 0..3 {
+            reputation.record_particle_at(peer, now + window);
+        }
+        let score = reputation.score_at(&peer, now + window);
+        let decayed = -10.0 * 0.5f64.powf(window.as_secs_f64() / half_life.as_secs_f64());
+        assert!((score - decayed + 10.0).abs() < 1e-9, "score is {score}");
+    }
+
+    #[test]
+    fn ban() {
+        let reputation = PeerReputation::new(ReputationConfig {
+            ban_threshold: -30.0,
+            ..<_>::default()
+        });
+        let peer = RandomPeerId::random();
+
+        reputation.report(peer, Offence::MalformedParticle);
+        reputation.report(peer, Offence::ExpiredParticle);
+        assert!(reputation.is_low_priority(&peer));
+        assert!(!reputation.is_banned(&peer));
+
+        reputation.report(peer, Offence::MalformedParticle);
+        assert!(reputation.is_banned(&peer));
+    }
+}
@@ -0,0 +1,111 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::convert::identity;
+
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::{FutureExt, StreamExt};
+use libp2p::PeerId;
+use tokio::sync::{mpsc, oneshot};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+
+use crate::error::{PubSubError, Result};
+
+#[derive(Debug)]
+pub enum Command {
+    Subscribe {
+        topic: String,
+    },
+    Unsubscribe {
+        topic: String,
+    },
+    Publish {
+        topic: String,
+        data: Vec<u8>,
+        out: oneshot::Sender<Result<()>>,
+    },
+    Messages {
+        out: mpsc::UnboundedSender<TopicMessage>,
+    },
+}
+
+/// Message received on one of the subscribed topics
+#[derive(Clone, Debug)]
+pub struct TopicMessage {
+    pub topic: String,
+    /// Author of the message
+    pub source: PeerId,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Debug)]
+pub struct PubSubApi {
+    outlet: Option<mpsc::UnboundedSender<Command>>,
+}
+
+impl PubSubApi {
+    pub fn new(outlet: mpsc::UnboundedSender<Command>) -> Self {
+        Self {
+            outlet: Some(outlet),
+        }
+    }
+
+    /// Api of a node with pubsub disabled, all calls fail with [`PubSubError::Disabled`]
+    pub fn disabled() -> Self {
+        Self { outlet: None }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.outlet.is_some()
+    }
+
+    fn send(&self, cmd: Command) -> Result<()> {
+        let outlet = self.outlet.as_ref().ok_or(PubSubError::Disabled)?;
+        outlet.send(cmd).map_err(|_| PubSubError::Cancelled)
+    }
+
+    /// Start receiving messages of the `topic` and relaying them to other peers.
+    /// Subscribing to the same topic twice has no effect.
+    pub fn subscribe(&self, topic: String) -> Result<()> {
+        self.send(Command::Subscribe { topic })
+    }
+
+    pub fn unsubscribe(&self, topic: String) -> Result<()> {
+        self.send(Command::Unsubscribe { topic })
+    }
+
+    /// Publish `data` to all peers subscribed to the `topic`
+    pub fn publish(&self, topic: String, data: Vec<u8>) -> BoxFuture<'static, Result<()>> {
+        let (out, inlet) = oneshot::channel();
+        if let Err(err) = self.send(Command::Publish { topic, data, out }) {
+            return futures::future::err(err).boxed();
+        }
+        inlet
+            .map(|r| r.map_err(|_| PubSubError::Cancelled).and_then(identity))
+            .boxed()
+    }
+
+    /// Messages of all subscribed topics
+    pub fn messages(&self) -> BoxStream<'static, TopicMessage> {
+        let (out, inlet) = mpsc::unbounded_channel();
+        if self.send(Command::Messages { out }).is_err() {
+            return futures::stream::empty().boxed();
+        }
+
+        UnboundedReceiverStream::new(inlet).boxed()
+    }
+}
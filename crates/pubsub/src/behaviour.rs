@@ -0,0 +1,282 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::task::{Context, Poll};
+
+use libp2p::core::Endpoint;
+use libp2p::gossipsub::{self, IdentTopic, MessageAuthenticity};
+use libp2p::identity::Keypair;
+use libp2p::swarm::{
+    ConnectionDenied, ConnectionId, FromSwarm, NetworkBehaviour, THandler, THandlerInEvent,
+    THandlerOutEvent, ToSwarm,
+};
+use libp2p::{Multiaddr, PeerId};
+use tokio::sync::mpsc;
+
+use crate::api::{Command, PubSubApi, TopicMessage};
+use crate::error::PubSubError;
+
+/// Gossipsub driven by [`PubSubApi`]. Messages are signed by the node key
+pub struct PubSub {
+    gossipsub: gossipsub::Behaviour,
+    commands: mpsc::UnboundedReceiver<Command>,
+    subscribers: Vec<mpsc::UnboundedSender<TopicMessage>>,
+}
+
+impl PubSub {
+    pub fn new(key_pair: Keypair, config: gossipsub::Config) -> (Self, PubSubApi) {
+        let gossipsub = gossipsub::Behaviour::new(MessageAuthenticity::Signed(key_pair), config)
+            .expect("signed messages are allowed by the validated config");
+
+        let (outlet, commands) = mpsc::unbounded_channel();
+        let api = PubSubApi::new(outlet);
+
+        let this = Self {
+            gossipsub,
+            commands,
+            subscribers: <_>::default(),
+        };
+
+        (this, api)
+    }
+
+    fn execute(&mut self, cmd: Command) {
+        match cmd {
+            Command::Subscribe { topic } => {
+                if let Err(err) = self.gossipsub.subscribe(&IdentTopic::new(&topic)) {
+                    log::warn!("Failed to subscribe to topic {topic}: {err}");
+                }
+            }
+            Command::Unsubscribe { topic } => {
+                if let Err(err) = self.gossipsub.unsubscribe(&IdentTopic::new(&topic)) {
+                    log::warn!("Failed to unsubscribe from topic {topic}: {err}");
+                }
+            }
+            Command::Publish { topic, data, out } => {
+                let result = self
+                    .gossipsub
+                    .publish(IdentTopic::new(topic), data)
+                    .map(|_| ())
+                    .map_err(PubSubError::from);
+                out.send(result).ok();
+            }
+            Command::Messages { out } => self.subscribers.push(out),
+        }
+    }
+
+    fn inject_gossipsub_event(&mut self, event: gossipsub::Event) {
+        if let gossipsub::Event::Message {
+            propagation_source,
+            message,
+            ..
+        } = event
+        {
+            let message = TopicMessage {
+                topic: message.topic.into_string(),
+                source: message.source.unwrap_or(propagation_source),
+                data: message.data,
+            };
+            self.subscribers
+                .retain(|out| out.send(message.clone()).is_ok());
+        }
+    }
+}
+
+impl NetworkBehaviour for PubSub {
+    type ConnectionHandler = <gossipsub::Behaviour as NetworkBehaviour>::ConnectionHandler;
+    type ToSwarm = ();
+
+    fn handle_pending_inbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        local_addr: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<(), ConnectionDenied> {
+        self.gossipsub
+            .handle_pending_inbound_connection(connection_id, local_addr, remote_addr)
+    }
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        peer_id: PeerId,
+        local_addr: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.gossipsub.handle_established_inbound_connection(
+            connection_id,
+            peer_id,
+            local_addr,
+            remote_addr,
+        )
+    }
+
+    fn handle_pending_outbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        maybe_peer: Option<PeerId>,
+        addresses: &[Multiaddr],
+        effective_role: Endpoint,
+    ) -> Result<Vec<Multiaddr>, ConnectionDenied> {
+        self.gossipsub.handle_pending_outbound_connection(
+            connection_id,
+            maybe_peer,
+            addresses,
+            effective_role,
+        )
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        peer_id: PeerId,
+        addr: &Multiaddr,
+        role_override: Endpoint,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.gossipsub.handle_established_outbound_connection(
+            connection_id,
+            peer_id,
+            addr,
+            role_override,
+        )
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm<'_>) {
+        self.gossipsub.on_swarm_event(event)
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        peer_id: PeerId,
+        connection_id: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        self.gossipsub
+            .on_connection_handler_event(peer_id, connection_id, event)
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>) -> Poll<ToSwarm<(), THandlerInEvent<Self>>> {
+        while let Poll::Ready(Some(cmd)) = self.commands.poll_recv(cx) {
+            self.execute(cmd);
+        }
+
+        loop {
+            match self.gossipsub.poll(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(ToSwarm::GenerateEvent(event)) => self.inject_gossipsub_event(event),
+                Poll::Ready(event) => return Poll::Ready(event.map_out(|_| ())),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::Poll;
+    use std::time::Duration;
+
+    use futures::StreamExt;
+    use libp2p::core::Multiaddr;
+    use libp2p::gossipsub::ConfigBuilder;
+    use libp2p::identity::Keypair;
+    use libp2p::multiaddr::Protocol;
+    use libp2p::{Swarm, SwarmBuilder};
+
+    use fluence_keypair::KeyPair;
+    use fluence_libp2p::build_memory_transport;
+    use fluence_libp2p::random_multiaddr::create_memory_maddr;
+
+    use crate::{PubSub, PubSubApi};
+
+    fn make_node() -> (Swarm<PubSub>, PubSubApi, Multiaddr) {
+        let kp: Keypair = KeyPair::generate_ed25519().into();
+        let peer_id = kp.public().to_peer_id();
+        let config = ConfigBuilder::default()
+            .heartbeat_interval(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        let (pubsub, api) = PubSub::new(kp.clone(), config);
+        let timeout = Duration::from_secs(20);
+
+        let mut swarm = SwarmBuilder::with_existing_identity(kp.clone())
+            .with_tokio()
+            .with_other_transport(|_| build_memory_transport(&kp, timeout))
+            .unwrap()
+            .with_behaviour(|_| pubsub)
+            .unwrap()
+            .with_swarm_config(|cfg| cfg.with_idle_connection_timeout(Duration::from_secs(10)))
+            .build();
+
+        let mut maddr = create_memory_maddr();
+        maddr.push(Protocol::P2p(peer_id));
+        Swarm::listen_on(&mut swarm, maddr.clone()).expect("Could not make swarm");
+
+        (swarm, api, maddr)
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn publish_subscribe() {
+        log_utils::enable_logs();
+
+        let (mut a, a_api, _) = make_node();
+        let (b, b_api, b_addr) = make_node();
+        let a_peer_id = *a.local_peer_id();
+        Swarm::dial(&mut a, b_addr).unwrap();
+
+        let mut messages = b_api.messages();
+        a_api.subscribe("topic".to_string()).unwrap();
+        b_api.subscribe("topic".to_string()).unwrap();
+
+        let mut swarms = vec![a, b];
+        let task = tokio::task::Builder::new()
+            .name("PubSub")
+            .spawn(futures::future::poll_fn(move |ctx| {
+                for swarm in swarms.iter_mut() {
+                    while swarm.poll_next_unpin(ctx).is_ready() {}
+                }
+                Poll::Pending as Poll<()>
+            }))
+            .expect("Could not spawn task");
+
+        let message = tokio::time::timeout(Duration::from_secs(10), async {
+            // publishing fails until the nodes learn about each other's subscriptions
+            while a_api
+                .publish("topic".to_string(), b"hello".to_vec())
+                .await
+                .is_err()
+            {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            messages.next().await
+        })
+        .await;
+        task.abort();
+
+        let message = message.expect("message wasn't received").unwrap();
+        assert_eq!(message.topic, "topic");
+        assert_eq!(message.source, a_peer_id);
+        assert_eq!(message.data, b"hello");
+    }
+
+    #[tokio::test]
+    async fn disabled() {
+        let api = PubSubApi::disabled();
+        assert!(!api.is_enabled());
+        assert!(api.subscribe("topic".to_string()).is_err());
+        assert!(api.publish("topic".to_string(), vec![]).await.is_err());
+        assert!(api.messages().next().await.is_none());
+    }
+}
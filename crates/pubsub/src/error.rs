@@ -0,0 +1,30 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use libp2p::gossipsub::PublishError;
+use thiserror::Error;
+
+pub(crate) type Result<T> = std::result::Result<T, PubSubError>;
+
+#[derive(Debug, Error)]
+pub enum PubSubError {
+    #[error("PubSubError::Disabled: pubsub is disabled in the node config")]
+    Disabled,
+    #[error("PubSubError::Cancelled")]
+    Cancelled,
+    #[error("PubSubError::Publish: {0}")]
+    Publish(#[from] PublishError),
+}
@@ -0,0 +1,36 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+/// Serve the http and gRPC listeners over TLS. Clients presenting a certificate signed by one of
+/// the `client_ca_path` certificates have the admin role, as an alternative to bearer tokens.
+/// Certificates aren't mapped to narrower roles, so the CA must only sign certificates of admins.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AdminTlsConfig {
+    /// PEM-encoded certificate chain of the listeners
+    pub certificate_path: PathBuf,
+    /// PEM-encoded private key of the certificate
+    pub private_key_path: PathBuf,
+    /// PEM-encoded CA certificates of the admin clients
+    pub client_ca_path: PathBuf,
+    /// Whether connections without a client certificate are rejected, bearer tokens can't be used then.
+    /// Probes can use `http_config.probe_port` instead
+    #[serde(default)]
+    pub require_client_certificate: bool,
+}
@@ -14,9 +14,10 @@
  * limitations under the License.
  */
 
+use crate::resolved_config::OtlpProtocol;
 use crate::system_services_config::ServiceKey;
 use clap::error::ErrorKind;
-use clap::{Args, Parser};
+use clap::{Args, Parser, Subcommand};
 use config::{ConfigError, Map, Source, Value};
 use serde::ser::SerializeStruct;
 use serde::{Serialize, Serializer};
@@ -129,6 +130,39 @@ pub struct TracingArgs {
         display_order = 26
     )]
     pub endpoint: Option<String>,
+
+    #[arg(
+        long("tracing-otlp-protocol"),
+        id = "TRACING_OTLP_PROTOCOL",
+        help = "otlp exporter protocol",
+        help_heading = "Node configuration",
+        display_order = 27,
+        value_enum
+    )]
+    pub protocol: Option<OtlpProtocol>,
+
+    #[arg(
+        long("tracing-sample-ratio"),
+        id = "TRACING_SAMPLE_RATIO",
+        value_name = "RATIO",
+        help = "Fraction of root traces exported, from 0 to 1",
+        help_heading = "Node configuration",
+        display_order = 28
+    )]
+    pub sample_ratio: Option<f64>,
+}
+
+#[derive(Args, Debug, Clone, Serialize)]
+pub(crate) struct ResourcesArgs {
+    #[arg(
+        long("aqua-pool-size"),
+        id = "AQUA_VM_POOL_SIZE",
+        help_heading = "AIR configuration",
+        help = "Number of AquaVM instances (particle script execution parallelism)",
+        value_name = "NUM",
+        display_order = 21
+    )]
+    aquavm_pool_size: Option<usize>,
 }
 
 #[derive(clap::ValueEnum, Debug, Clone, Serialize)]
@@ -266,6 +300,14 @@ pub(crate) struct DerivedArgs {
         display_order = 2
     )]
     websocket_port: Option<u16>,
+    #[arg(
+        long("webrtc-port"),
+        id = "WEBRTC_PORT",
+        help = "webrtc port (udp), enables WebRTC for browser peers",
+        help_heading = "Networking",
+        display_order = 2
+    )]
+    webrtc_port: Option<u16>,
     #[arg(
         short('s'),
         long,
@@ -402,15 +444,6 @@ pub(crate) struct DerivedArgs {
         display_order = 20
     )]
     services_workdir: Option<PathBuf>,
-    #[arg(
-        long("aqua-pool-size"),
-        id = "AQUA_VM_POOL_SIZE",
-        help_heading = "AIR configuration",
-        help = "Number of AquaVM instances (particle script execution parallelism)",
-        value_name = "NUM",
-        display_order = 21
-    )]
-    aquavm_pool_size: Option<usize>,
     #[arg(
         long,
         value_parser = clap::value_parser ! (bool),
@@ -431,6 +464,16 @@ pub(crate) struct DerivedArgs {
         action = clap::ArgAction::SetTrue
     )]
     pub(crate) no_banner: Option<bool>,
+    #[arg(
+        long,
+        id = "MIGRATE_CONFIG",
+        help = "Upgrade config files to the current config version, keeping backups of the originals",
+        help_heading = "Node configuration",
+        display_order = 24,
+        action = clap::ArgAction::SetTrue
+    )]
+    #[serde(skip)]
+    pub(crate) migrate_config: bool,
 
     #[command(flatten)]
     system_services: Option<SystemServicesArgs>,
@@ -438,8 +481,36 @@ pub(crate) struct DerivedArgs {
     #[command(flatten)]
     tracing: Option<TracingArgs>,
 
+    #[command(flatten)]
+    resources: Option<ResourcesArgs>,
+
     #[command(flatten)]
     dev_mode: Option<DevModeArgs>,
+
+    #[command(subcommand)]
+    #[serde(skip)]
+    command: Option<Subcommands>,
+}
+
+impl DerivedArgs {
+    pub(crate) fn command(&self) -> Option<ConfigCommand> {
+        self.command.map(|Subcommands::Config(command)| command)
+    }
+}
+
+#[derive(Subcommand, Debug, Clone, Copy)]
+enum Subcommands {
+    /// Inspect the config without starting the node
+    #[command(subcommand)]
+    Config(ConfigCommand),
+}
+
+#[derive(Subcommand, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigCommand {
+    /// Parse and validate the config, including cross-field checks
+    Validate,
+    /// Print the effective config with the source of every value: default, file, env or args
+    Explain,
 }
 
 impl Source for DerivedArgs {
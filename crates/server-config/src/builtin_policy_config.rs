@@ -0,0 +1,206 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::fmt;
+use std::str::FromStr;
+
+use fluence_libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use serde_with::{DeserializeFromStr, SerializeDisplay};
+
+/// Who can call privileged builtins, e.g. `dist.*`, `worker.*` or `spell.install` on the host.
+/// A call matched by any rule is allowed only to the principals of the matching rules,
+/// calls matched by no rule are left to the checks of the builtins themselves.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct BuiltinPolicyConfig {
+    pub rules: Vec<PolicyRule>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct PolicyRule {
+    /// `service.function`, `service.*` or `*`
+    pub builtins: Vec<String>,
+    /// Rule applies only to calls on the host or only to calls on workers, to both if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<PolicyScope>,
+    pub allow: Vec<Principal>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicyScope {
+    Host,
+    Worker,
+}
+
+/// Init peer id of a particle allowed by a rule
+#[derive(Clone, Copy, Debug, PartialEq, Eq, SerializeDisplay, DeserializeFromStr)]
+pub enum Principal {
+    /// Management and builtins management peer ids
+    Management,
+    /// The node itself, i.e. its spells
+    Host,
+    /// Workers of the node, i.e. their spells
+    Workers,
+    Peer(PeerId),
+}
+
+impl FromStr for Principal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "management" => Ok(Principal::Management),
+            "host" => Ok(Principal::Host),
+            "workers" => Ok(Principal::Workers),
+            peer_id => PeerId::from_str(peer_id).map(Principal::Peer).map_err(|_| {
+                format!("expected management, host, workers or a peer id, got {peer_id:?}")
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Principal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Principal::Management => write!(f, "management"),
+            Principal::Host => write!(f, "host"),
+            Principal::Workers => write!(f, "workers"),
+            Principal::Peer(peer_id) => write!(f, "{peer_id}"),
+        }
+    }
+}
+
+impl PolicyRule {
+    pub fn validate(&self) -> eyre::Result<()> {
+        for builtin in &self.builtins {
+            let valid = builtin == "*"
+                || matches!(builtin.split_once('.'), Some((service, function))
+                    if !service.is_empty() && !function.is_empty() && !service.contains('*')
+                        && (function == "*" || !function.contains('*')));
+            if !valid {
+                eyre::bail!(
+                    "builtin_policy: {builtin:?} must be `service.function`, `service.*` or `*`"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    pub fn matches(&self, service: &str, function: &str, on_host: bool) -> bool {
+        let scope_matches = match self.scope {
+            None => true,
+            Some(PolicyScope::Host) => on_host,
+            Some(PolicyScope::Worker) => !on_host,
+        };
+        scope_matches
+            && self
+                .builtins
+                .iter()
+                .any(|pattern| match pattern.split_once('.') {
+                    _ if pattern == "*" => true,
+                    Some((s, "*")) => s == service,
+                    Some((s, f)) => s == service && f == function,
+                    None => false,
+                })
+    }
+}
+
+impl BuiltinPolicyConfig {
+    pub fn validate(&self) -> eyre::Result<()> {
+        self.rules.iter().try_for_each(PolicyRule::validate)
+    }
+
+    /// Whether the call is allowed, `None` if no rule matches it.
+    /// `is_caller` tells whether the init peer id of the call is the principal.
+    pub fn allows(
+        &self,
+        service: &str,
+        function: &str,
+        on_host: bool,
+        is_caller: impl Fn(&Principal) -> bool,
+    ) -> Option<bool> {
+        let mut matched = self
+            .rules
+            .iter()
+            .filter(|rule| rule.matches(service, function, on_host))
+            .peekable();
+        matched.peek()?;
+        Some(matched.any(|rule| rule.allow.iter().any(&is_caller)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules() {
+        let peer_id = PeerId::random();
+        let config: BuiltinPolicyConfig = toml::from_str(&format!(
+            r#"
+            [[rules]]
+            builtins = ["dist.*", "worker.create"]
+            allow = ["management", "{peer_id}"]
+
+            [[rules]]
+            builtins = ["spell.install"]
+            scope = "host"
+            allow = ["management"]
+            "#
+        ))
+        .unwrap();
+        config.validate().unwrap();
+        let is = |principal: Principal| move |p: &Principal| *p == principal;
+
+        assert_eq!(
+            config.allows("dist", "add_module", false, is(Principal::Peer(peer_id))),
+            Some(true)
+        );
+        assert_eq!(
+            config.allows("worker", "create", true, is(Principal::Workers)),
+            Some(false)
+        );
+        assert_eq!(
+            config.allows("spell", "install", true, is(Principal::Host)),
+            Some(false)
+        );
+        assert_eq!(
+            config.allows("spell", "install", true, is(Principal::Management)),
+            Some(true)
+        );
+        // the rule is limited to the host, workers install spells as usual
+        assert_eq!(
+            config.allows("spell", "install", false, is(Principal::Host)),
+            None
+        );
+        assert_eq!(config.allows("op", "noop", true, is(Principal::Host)), None);
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        for pattern in ["dist", "*.add_module", "dist.add_*", ".*"] {
+            let rule = PolicyRule {
+                builtins: vec![pattern.to_string()],
+                scope: None,
+                allow: vec![Principal::Management],
+            };
+            assert!(rule.validate().is_err(), "{pattern}");
+        }
+        assert!("nobody".parse::<Principal>().is_err());
+    }
+}
@@ -0,0 +1,42 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::time::Duration;
+
+use derivative::Derivative;
+use serde::{Deserialize, Serialize};
+
+/// Signer that keeps the chain wallet key outside of the node, e.g. Web3Signer or Clef
+/// backed by an HSM over PKCS#11 or by a cloud KMS.
+/// Transactions are signed with the `eth_signTransaction` JSON-RPC call.
+#[derive(Clone, Deserialize, Serialize, Derivative)]
+#[derivative(Debug)]
+pub struct RemoteSignerConfig {
+    pub endpoint: String,
+    /// Address of the wallet managed by the signer
+    pub address: String,
+    /// Sent as a bearer token if set
+    #[derivative(Debug = "ignore")]
+    #[serde(default)]
+    pub token: Option<String>,
+    #[serde(default = "default_remote_signer_timeout")]
+    #[serde(with = "humantime_serde")]
+    pub timeout: Duration,
+}
+
+fn default_remote_signer_timeout() -> Duration {
+    Duration::from_secs(10)
+}
@@ -0,0 +1,618 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::fmt;
+
+use serde::Serialize;
+use serde_json::Value;
+use url::Url;
+
+use crate::config_reload::is_within;
+use crate::resolved_config::config_sources;
+use crate::secret_refs::resolve_secret_refs;
+use crate::system_services_config::ServiceKey;
+use crate::{ConfigData, ResolvedConfig, UnresolvedConfig};
+
+/// Fields never printed by `nox config explain`
+const SECRET_FIELDS: [&str; 14] = [
+    "root_key_pair.value",
+    "root_key_pair.secret_key",
+    "builtins_key_pair.value",
+    "builtins_key_pair.secret_key",
+    "http_client_key_pair.value",
+    "http_client_key_pair.secret_key",
+    "chain_config.wallet_key",
+    "chain_config.remote_signer.token",
+    "system_services.decider.wallet_key",
+    "private_network.key",
+    "private_network.previous_key",
+    "keystore.passphrase",
+    "keystore.key",
+    "http_config.admin_token",
+];
+
+/// Endpoints that may have API keys in their paths, queries or credentials, only their hosts are printed
+const ENDPOINT_FIELDS: [&str; 6] = [
+    "chain_config.http_endpoint",
+    "chain_config.remote_signer.endpoint",
+    "chain_listener_config.ws_endpoint",
+    "chain_listener_config.ccp_endpoint",
+    "system_services.decider.network_api_endpoint",
+    "tracing.endpoint",
+];
+
+const REDACTED: &str = "<redacted>";
+
+/// Where the value of a config field comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigProvenance {
+    Default,
+    File,
+    Env,
+    Args,
+}
+
+impl ConfigProvenance {
+    /// Sources overriding defaults, from the lowest priority to the highest
+    pub(crate) const OVERRIDES: [ConfigProvenance; 3] = [Self::File, Self::Env, Self::Args];
+    /// Sources that can't set `${exec:...}` secret references
+    pub(crate) const EXEC_DENIED: [ConfigProvenance; 2] = [Self::Env, Self::Args];
+}
+
+impl fmt::Display for ConfigProvenance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Default => write!(f, "default"),
+            Self::File => write!(f, "file"),
+            Self::Env => write!(f, "env"),
+            Self::Args => write!(f, "args"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExplainedField {
+    /// Dotted path, e.g. `listen_config.tcp_port`
+    pub path: String,
+    pub value: Value,
+    pub provenance: ConfigProvenance,
+}
+
+impl fmt::Display for ExplainedField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} = {} # {}", self.path, self.value, self.provenance)
+    }
+}
+
+/// Effective config field by field, with secrets redacted and secret references kept as is
+pub fn explain_config(data: Option<ConfigData>) -> eyre::Result<Vec<ExplainedField>> {
+    let raw_args = std::env::args_os().collect::<Vec<_>>();
+    explain_config_with_args(raw_args, data)
+}
+
+pub fn explain_config_with_args(
+    raw_args: Vec<OsString>,
+    data: Option<ConfigData>,
+) -> eyre::Result<Vec<ExplainedField>> {
+    let sources = config_sources(raw_args, data)?;
+    let config = sources.builder(&ConfigProvenance::OVERRIDES).build()?;
+    let exec_denied = sources.builder(&ConfigProvenance::EXEC_DENIED).build()?;
+    // commands aren't run, the explained value of their fields is the reference
+    let (config, secret_refs) = resolve_secret_refs(config, &exec_denied, false)?;
+    let config: UnresolvedConfig = config.try_deserialize()?;
+
+    let mut overridden = HashMap::new();
+    for provenance in ConfigProvenance::OVERRIDES {
+        let layer: Value = sources.builder(&[provenance]).build()?.try_deserialize()?;
+        let mut paths = vec![];
+        collect_leaves(&layer, None, &mut paths);
+        for (path, _) in paths {
+            // later sources take precedence
+            overridden.insert(path, provenance);
+        }
+    }
+
+    let mut fields = vec![];
+    collect_leaves(&serde_json::to_value(config)?, None, &mut fields);
+    let explained = fields
+        .into_iter()
+        .map(|(path, value)| {
+            let provenance = overridden
+                .iter()
+                .filter(|(set, _)| is_within(&path, set))
+                .map(|(_, provenance)| *provenance)
+                .max_by_key(|p| *p as u8)
+                .unwrap_or(ConfigProvenance::Default);
+            let value = if let Some(reference) = secret_refs.get(&path) {
+                Value::from(reference.as_str())
+            } else if is_secret_ref(&path, &secret_refs) {
+                Value::from(REDACTED)
+            } else {
+                redacted(&path, value)
+            };
+            ExplainedField {
+                path,
+                value,
+                provenance,
+            }
+        })
+        .collect();
+    Ok(explained)
+}
+
+/// Masks secrets in the serialized [UnresolvedConfig], including fields set by `secret_refs`,
+/// see [UnresolvedConfig::secret_refs]
+pub fn redact_config(config: &mut Value, secret_refs: &HashMap<String, String>) {
+    redact_leaves(config, None, secret_refs)
+}
+
+fn redact_leaves(value: &mut Value, path: Option<&str>, secret_refs: &HashMap<String, String>) {
+    match (value, path) {
+        (Value::Object(object), _) => {
+            for (key, value) in object.iter_mut() {
+                let path = match path {
+                    Some(path) => format!("{path}.{key}"),
+                    None => key.clone(),
+                };
+                redact_leaves(value, Some(&path), secret_refs);
+            }
+        }
+        (value, Some(path)) if is_secret_ref(path, secret_refs) => *value = Value::from(REDACTED),
+        (value, Some(path)) => *value = redacted(path, value.take()),
+        (_, None) => {}
+    }
+}
+
+/// Whether the field or any of its list elements is set by a secret reference
+fn is_secret_ref(path: &str, secret_refs: &HashMap<String, String>) -> bool {
+    secret_refs.contains_key(path)
+        || secret_refs
+            .keys()
+            .any(|r| r.starts_with(&format!("{path}[")))
+}
+
+fn redacted(path: &str, value: Value) -> Value {
+    if value.is_null() {
+        return value;
+    }
+    if SECRET_FIELDS.iter().any(|s| is_within(path, s)) {
+        return Value::from(REDACTED);
+    }
+    if ENDPOINT_FIELDS.contains(&path) {
+        return match value.as_str().map(Url::parse) {
+            Some(Ok(url)) => Value::from(redact_url(&url)),
+            _ => Value::from(REDACTED),
+        };
+    }
+    value
+}
+
+/// Keeps only scheme, host and port of the url
+fn redact_url(url: &Url) -> String {
+    let host = url.host_str().unwrap_or_default();
+    let port = url.port().map(|p| format!(":{p}")).unwrap_or_default();
+    let has_secrets = !url.username().is_empty()
+        || url.password().is_some()
+        || !matches!(url.path(), "" | "/")
+        || url.query().is_some();
+    if has_secrets {
+        format!("{}://{host}{port}/{REDACTED}", url.scheme())
+    } else {
+        format!("{}://{host}{port}", url.scheme())
+    }
+}
+
+/// Leaf values with their dotted paths, lists are leaves
+fn collect_leaves(value: &Value, path: Option<&str>, leaves: &mut Vec<(String, Value)>) {
+    match (value, path) {
+        (Value::Object(object), _) => {
+            for (key, value) in object {
+                let path = match path {
+                    Some(path) => format!("{path}.{key}"),
+                    None => key.clone(),
+                };
+                collect_leaves(value, Some(&path), leaves);
+            }
+        }
+        (value, Some(path)) => leaves.push((path.to_string(), value.clone())),
+        (_, None) => {}
+    }
+}
+
+/// Cross-field checks that sections can't do on their own. Returns found problems.
+pub fn validate_config(config: &ResolvedConfig) -> Vec<String> {
+    let mut problems = vec![];
+
+    let listen = &config.listen_config;
+    // (field, port, is udp)
+    let mut ports: Vec<(String, u16, bool)> = if listen.listen_endpoints.is_empty() {
+        vec![
+            ("listen_config.tcp_port".into(), listen.tcp_port, false),
+            (
+                "listen_config.websocket_port".into(),
+                listen.websocket_port,
+                false,
+            ),
+        ]
+    } else {
+        listen
+            .listen_endpoints
+            .iter()
+            .enumerate()
+            .map(|(i, e)| (format!("listen_endpoints[{i}]"), e.port, e.is_udp()))
+            .collect()
+    };
+    ports.extend(
+        config
+            .http_config
+            .as_ref()
+            .map(|c| ("http_config.http_port".into(), c.http_port, false)),
+    );
+    ports.extend(
+        config
+            .http_config
+            .as_ref()
+            .and_then(|c| c.grpc_port)
+            .map(|port| ("http_config.grpc_port".into(), port, false)),
+    );
+    if config
+        .http_config
+        .as_ref()
+        .is_some_and(|c| c.grpc_port.is_some() && c.admin_token.is_none() && c.admin_tls.is_none())
+    {
+        problems.push(
+            "http_config.grpc_port requires http_config.admin_token or http_config.admin_tls"
+                .to_string(),
+        );
+    }
+    ports.extend(
+        config
+            .websocket_tls
+            .as_ref()
+            .map(|c| ("websocket_tls.port".into(), c.port, false)),
+    );
+    ports.extend(
+        config
+            .metrics_endpoint
+            .as_ref()
+            .map(|c| ("metrics_endpoint.port".into(), c.port, false)),
+    );
+    ports.extend(
+        listen
+            .webrtc_port
+            .map(|port| ("listen_config.webrtc_port".into(), port, true)),
+    );
+    let mut used = HashMap::new();
+    // zero port is picked by OS
+    for (field, port, udp) in ports.into_iter().filter(|(_, port, _)| *port != 0) {
+        if let Some(other) = used.insert((port, udp), field.clone()) {
+            problems.push(format!("{other} and {field} use the same port {port}"));
+        }
+    }
+
+    let services = &config.system_services;
+    let enabled: HashSet<_> = services.enable.iter().collect();
+    let mut spell_periods = vec![];
+    if enabled.contains(&ServiceKey::Decider) {
+        spell_periods.extend([
+            (
+                "system_services.decider.decider_period_sec",
+                services.decider.decider_period_sec,
+            ),
+            (
+                "system_services.decider.worker_period_sec",
+                services.decider.worker_period_sec,
+            ),
+        ]);
+    }
+    if enabled.contains(&ServiceKey::Registry) {
+        let registry = &services.registry;
+        spell_periods.extend([
+            (
+                "system_services.registry.registry_period_sec",
+                registry.registry_period_sec,
+            ),
+            (
+                "system_services.registry.expired_period_sec",
+                registry.expired_period_sec,
+            ),
+            (
+                "system_services.registry.renew_period_sec",
+                registry.renew_period_sec,
+            ),
+            (
+                "system_services.registry.replicate_period_sec",
+                registry.replicate_period_sec,
+            ),
+        ]);
+    }
+    let ttl = config.max_spell_particle_ttl;
+    for (field, period) in spell_periods {
+        if ttl.as_secs() > period as u64 {
+            problems.push(format!(
+                "max_spell_particle_ttl ({ttl:?}) exceeds {field} ({period}s), spell runs would overlap"
+            ));
+        }
+    }
+
+    if enabled.contains(&ServiceKey::Registry) {
+        let registry = &services.registry;
+        if let Some(ttl) = registry.record_ttl_sec {
+            if ttl <= registry.renew_period_sec as u64 {
+                problems.push(format!(
+                    "system_services.registry.record_ttl_sec ({ttl}s) doesn't exceed renew_period_sec ({}s), records would expire before renewal",
+                    registry.renew_period_sec
+                ));
+            }
+        }
+    }
+    if services.trust_graph.allowlist_min_weight.is_some()
+        && !enabled.contains(&ServiceKey::TrustGraph)
+    {
+        problems.push(
+            "system_services.trust_graph.allowlist_min_weight requires trust-graph to be enabled"
+                .to_string(),
+        );
+    }
+    if enabled.contains(&ServiceKey::Decider) {
+        check_address(
+            &mut problems,
+            "system_services.decider.matcher_address",
+            &services.decider.matcher_address,
+        );
+    }
+    if let Some(chain) = &config.chain_config {
+        check_url(
+            &mut problems,
+            "chain_config.http_endpoint",
+            &chain.http_endpoint,
+        );
+        check_address(
+            &mut problems,
+            "chain_config.core_contract_address",
+            &chain.core_contract_address,
+        );
+        check_address(
+            &mut problems,
+            "chain_config.cc_contract_address",
+            &chain.cc_contract_address,
+        );
+        check_address(
+            &mut problems,
+            "chain_config.market_contract_address",
+            &chain.market_contract_address,
+        );
+    }
+    if let Some(listener) = &config.chain_listener_config {
+        check_url(
+            &mut problems,
+            "chain_listener_config.ws_endpoint",
+            &listener.ws_endpoint,
+        );
+        if let Some(ccp_endpoint) = &listener.ccp_endpoint {
+            check_url(
+                &mut problems,
+                "chain_listener_config.ccp_endpoint",
+                ccp_endpoint,
+            );
+        }
+    }
+
+    problems
+}
+
+/// Checks that `address` is a 0x-prefixed 20-byte hex address
+fn check_address(problems: &mut Vec<String>, field: &str, address: &str) {
+    let valid = address
+        .strip_prefix("0x")
+        .is_some_and(|hex| hex.len() == 40 && hex.chars().all(|c| c.is_ascii_hexdigit()));
+    if !valid {
+        problems.push(format!(
+            "{field} must be a 0x-prefixed 20-byte hex address, got {address:?}"
+        ));
+    }
+}
+
+fn check_url(problems: &mut Vec<String>, field: &str, url: &str) {
+    if let Err(err) = Url::parse(url) {
+        problems.push(format!("{field} must be a URL, got {url:?}: {err}"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::time::Duration;
+
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::{load_config_with_args, EndpointProtocol, ListenEndpoint};
+
+    #[test]
+    fn explain_provenance() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [listen_config]
+            tcp_port = 7778
+            websocket_port = 9998
+            "#
+        )
+        .expect("Could not write in file");
+        let path = file.path().display().to_string();
+
+        temp_env::with_vars(
+            [
+                ("FLUENCE_CONFIG", Some(path)),
+                (
+                    "NOX_LISTEN_CONFIG__WEBSOCKET_PORT",
+                    Some("9997".to_string()),
+                ),
+            ],
+            || {
+                let fields = explain_config_with_args(vec![], None).expect("Could not explain");
+                let field = |path: &str| {
+                    fields
+                        .iter()
+                        .find(|f| f.path == path)
+                        .cloned()
+                        .unwrap_or_else(|| panic!("{path} not found"))
+                };
+
+                let tcp_port = field("listen_config.tcp_port");
+                assert_eq!(tcp_port.value, Value::from(7778));
+                assert_eq!(tcp_port.provenance, ConfigProvenance::File);
+                let websocket_port = field("listen_config.websocket_port");
+                assert_eq!(websocket_port.value, Value::from(9997));
+                assert_eq!(websocket_port.provenance, ConfigProvenance::Env);
+                let ttl = field("max_spell_particle_ttl");
+                assert_eq!(ttl.provenance, ConfigProvenance::Default);
+            },
+        );
+    }
+
+    #[test]
+    fn redact_secrets() {
+        let mut config = serde_json::json!({
+            "root_key_pair": { "format": "ed25519", "value": "secret", "path": null },
+            "chain_config": {
+                "http_endpoint": "https://rpc.example.com/v3/api_key",
+                "network_id": 1,
+            },
+            "chain_listener_config": { "ws_endpoint": "wss://rpc.example.com:8546" },
+        });
+        redact_config(&mut config, &<_>::default());
+        assert_eq!(
+            config,
+            serde_json::json!({
+                "root_key_pair": { "format": "ed25519", "value": "<redacted>", "path": null },
+                "chain_config": {
+                    "http_endpoint": "https://rpc.example.com/<redacted>",
+                    "network_id": 1,
+                },
+                "chain_listener_config": { "ws_endpoint": "wss://rpc.example.com:8546" },
+            })
+        );
+    }
+
+    #[test]
+    fn redact_admin_token() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [http_config]
+            admin_token = "secret"
+            "#
+        )
+        .expect("Could not write in file");
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let fields = explain_config_with_args(vec![], None).expect("Could not explain");
+            let admin_token = fields
+                .iter()
+                .find(|f| f.path == "http_config.admin_token")
+                .expect("admin_token is explained");
+            assert_eq!(admin_token.value, Value::from(REDACTED));
+
+            let loaded = load_config_with_args(vec![], None).expect("Could not load config");
+            let mut config = serde_json::to_value(&loaded).expect("Could not serialize config");
+            redact_config(&mut config, &loaded.secret_refs);
+            assert_eq!(config["http_config"]["admin_token"], Value::from(REDACTED));
+        });
+    }
+
+    #[test]
+    fn redact_secret_refs() {
+        let mut config = serde_json::json!({
+            "log_filter": "info",
+            "bootstrap_nodes": ["/dns4/a/tcp/7777", "/dns4/b/tcp/7777"],
+            "listen_config": { "tcp_port": 7777 },
+        });
+        let secret_refs = HashMap::from([
+            ("log_filter".to_string(), "${env:LOG_FILTER}".to_string()),
+            (
+                "bootstrap_nodes[1]".to_string(),
+                "${file:/run/secrets/bootstrap}".to_string(),
+            ),
+        ]);
+        redact_config(&mut config, &secret_refs);
+        assert_eq!(
+            config,
+            serde_json::json!({
+                "log_filter": "<redacted>",
+                "bootstrap_nodes": "<redacted>",
+                "listen_config": { "tcp_port": 7777 },
+            })
+        );
+    }
+
+    #[test]
+    fn validate_cross_fields() {
+        let config = load_config_with_args(vec![], None)
+            .expect("Could not load config")
+            .resolve()
+            .expect("Could not resolve config");
+        assert!(validate_config(&config).is_empty());
+
+        let mut config = config;
+        config.listen_config.websocket_port = config.listen_config.tcp_port;
+        config.max_spell_particle_ttl = Duration::from_secs(24 * 60 * 60);
+        config.system_services.decider.matcher_address = "0x123".to_string();
+        config.system_services.registry.record_ttl_sec = Some(60);
+        let problems = validate_config(&config);
+        assert!(problems[0].contains("use the same port"));
+        assert!(problems
+            .iter()
+            .any(|p| p.contains("spell runs would overlap")));
+        assert!(problems.iter().any(|p| p.contains("matcher_address")));
+        assert!(problems.iter().any(|p| p.contains("expire before renewal")));
+
+        let mut config = load_config_with_args(vec![], None)
+            .expect("Could not load config")
+            .resolve()
+            .expect("Could not resolve config");
+        let http_config = config.http_config.as_mut().expect("http config is set");
+        http_config.grpc_port = Some(http_config.http_port);
+        let problems = validate_config(&config);
+        assert!(problems
+            .iter()
+            .any(|p| p.contains("http_config.http_port and http_config.grpc_port")));
+        assert!(problems.iter().any(|p| p.contains("requires")));
+
+        let mut config = load_config_with_args(vec![], None)
+            .expect("Could not load config")
+            .resolve()
+            .expect("Could not resolve config");
+        let quic = ListenEndpoint {
+            protocol: EndpointProtocol::Quic,
+            ..ListenEndpoint::tcp(7777)
+        };
+        config.listen_config.listen_endpoints = vec![ListenEndpoint::tcp(7777), quic];
+        assert!(validate_config(&config).is_empty());
+        config
+            .listen_config
+            .listen_endpoints
+            .push(ListenEndpoint::ws(7777));
+        assert!(validate_config(&config)[0].contains("listen_endpoints[0] and listen_endpoints[2]"));
+    }
+}
@@ -0,0 +1,344 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::path::{Path, PathBuf};
+
+use config::{Config, File, FileFormat, FileSourceString};
+use eyre::{eyre, WrapErr};
+use serde_json::{Map, Value};
+
+/// Version of the config layout this node understands, stored in the `config_version` field.
+/// Files without the field are of version 0.
+pub const CONFIG_VERSION: u64 = 2;
+
+const VERSION_FIELD: &str = "config_version";
+
+/// Upgrade of the config layout to version `to` from the previous one
+struct Migration {
+    to: u64,
+    /// Fields moved to another path, as `(old, new)` dotted paths
+    moved: &'static [(&'static str, &'static str)],
+    /// Fields the node doesn't use anymore, with the reason shown to the user
+    removed: &'static [(&'static str, &'static str)],
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        to: 1,
+        // system services used to be configured by top-level [[aqua_ipfs]], [[decider]] etc
+        moved: &[
+            ("aqua_ipfs", "system_services.aqua_ipfs"),
+            ("decider", "system_services.decider"),
+            ("connector", "system_services.connector"),
+            ("registry", "system_services.registry"),
+        ],
+        removed: &[],
+    },
+    Migration {
+        to: 2,
+        // resource limits are gathered in [resources]
+        moved: &[
+            ("aquavm_pool_size", "resources.aquavm_pool_size"),
+            (
+                "avm_config.aquavm_heap_size_limit",
+                "resources.aquavm_heap_size",
+            ),
+            ("default_service_memory_limit", "resources.service_memory"),
+            (
+                "particle_limits.max_particle_size",
+                "resources.max_particle_size",
+            ),
+            (
+                "particle_limits.max_data_size",
+                "resources.max_particle_data_size",
+            ),
+        ],
+        removed: &[],
+    },
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationReport {
+    pub from: u64,
+    pub to: u64,
+    pub warnings: Vec<String>,
+}
+
+impl MigrationReport {
+    pub fn is_migrated(&self) -> bool {
+        self.from != self.to
+    }
+}
+
+/// Upgrades config file contents to [CONFIG_VERSION], fails on configs of newer nodes
+pub fn migrate(config: &mut Value) -> eyre::Result<MigrationReport> {
+    let Some(table) = config.as_object_mut() else {
+        return Err(eyre!("config must be a table"));
+    };
+    let from = match table.get(VERSION_FIELD) {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .ok_or_else(|| eyre!("{VERSION_FIELD} must be a non-negative integer"))?,
+    };
+    if from > CONFIG_VERSION {
+        return Err(eyre!(
+            "config version {from} is newer than {CONFIG_VERSION} supported by this node, \
+             update the node or downgrade the config"
+        ));
+    }
+
+    let mut warnings = vec![];
+    for migration in MIGRATIONS.iter().filter(|m| m.to > from) {
+        for (old, new) in migration.moved {
+            let Some(value) = remove_path(table, old) else {
+                continue;
+            };
+            if get_path(table, new).is_some() {
+                warnings.push(format!(
+                    "{old} is ignored: it was moved to {new}, which is set too"
+                ));
+                continue;
+            }
+            insert_path(table, new, unwrap_single(value));
+            warnings.push(format!("{old} was moved to {new}"));
+        }
+        for (field, reason) in migration.removed {
+            if remove_path(table, field).is_some() {
+                warnings.push(format!("{field} was removed: {reason}"));
+            }
+        }
+    }
+    table.insert(VERSION_FIELD.to_string(), CONFIG_VERSION.into());
+
+    Ok(MigrationReport {
+        from,
+        to: CONFIG_VERSION,
+        warnings,
+    })
+}
+
+/// Reads config file and migrates it in memory, `None` if the file is optional and doesn't exist
+pub(crate) fn migrated_file(
+    path: &Path,
+    required: bool,
+) -> eyre::Result<Option<File<FileSourceString, FileFormat>>> {
+    if !required && !path.exists() {
+        return Ok(None);
+    }
+
+    let (mut config, _) = read_file(path)?;
+    let report = migrate(&mut config).wrap_err_with(|| format!("failed to migrate {path:?}"))?;
+    for warning in &report.warnings {
+        log::warn!("{}: {warning}", path.display());
+    }
+    if report.is_migrated() {
+        log::warn!(
+            "{} is of config version {}, it was migrated to {} in memory. Run with --migrate-config to upgrade the file",
+            path.display(),
+            report.from,
+            report.to
+        );
+    }
+
+    Ok(Some(File::from_str(
+        &serde_json::to_string(&config)?,
+        FileFormat::Json,
+    )))
+}
+
+/// Upgrades config file on disk, keeping the original at `<path>.v<version>.bak`.
+/// Returns the migration report, the file isn't touched if it's already of the current version.
+pub(crate) fn migrate_file(path: &Path) -> eyre::Result<MigrationReport> {
+    let (mut config, format) = read_file(path)?;
+    let report = migrate(&mut config).wrap_err_with(|| format!("failed to migrate {path:?}"))?;
+    if !report.is_migrated() {
+        return Ok(report);
+    }
+
+    let contents = match format {
+        FileFormat::Toml => toml::to_string_pretty(&config)?,
+        // JSON is valid YAML
+        _ => serde_json::to_string_pretty(&config)?,
+    };
+    let backup = backup_path(path, report.from);
+    std::fs::copy(path, &backup)
+        .wrap_err_with(|| format!("failed to back up {path:?} to {backup:?}"))?;
+    std::fs::write(path, contents).wrap_err_with(|| format!("failed to write {path:?}"))?;
+    log::info!(
+        "Migrated {} from config version {} to {}, the original is saved to {}",
+        path.display(),
+        report.from,
+        report.to,
+        backup.display()
+    );
+
+    Ok(report)
+}
+
+/// Config file format, selected by extension. Files without known extension are TOML.
+pub(crate) fn file_format(path: &Path) -> FileFormat {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("yaml" | "yml") => FileFormat::Yaml,
+        Some("json") => FileFormat::Json,
+        _ => FileFormat::Toml,
+    }
+}
+
+fn read_file(path: &Path) -> eyre::Result<(Value, FileFormat)> {
+    let format = file_format(path);
+    let config = Config::builder()
+        .add_source(File::from(path).format(format))
+        .build()
+        .and_then(|c| c.try_deserialize())
+        .wrap_err_with(|| format!("failed to read config file {path:?}"))?;
+    Ok((config, format))
+}
+
+fn backup_path(path: &Path, version: u64) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".v{version}.bak"));
+    PathBuf::from(backup)
+}
+
+/// `[[decider]]` in TOML is an array of tables, while the config expects a table
+fn unwrap_single(value: Value) -> Value {
+    match value {
+        Value::Array(mut array) if array.len() == 1 => array.remove(0),
+        value => value,
+    }
+}
+
+fn get_path<'a>(table: &'a Map<String, Value>, path: &str) -> Option<&'a Value> {
+    let (first, rest) = path.split_once('.').unwrap_or((path, ""));
+    let value = table.get(first)?;
+    if rest.is_empty() {
+        Some(value)
+    } else {
+        get_path(value.as_object()?, rest)
+    }
+}
+
+fn remove_path(table: &mut Map<String, Value>, path: &str) -> Option<Value> {
+    match path.split_once('.') {
+        None => table.remove(path),
+        Some((first, rest)) => remove_path(table.get_mut(first)?.as_object_mut()?, rest),
+    }
+}
+
+fn insert_path(table: &mut Map<String, Value>, path: &str, value: Value) {
+    match path.split_once('.') {
+        None => {
+            table.insert(path.to_string(), value);
+        }
+        Some((first, rest)) => {
+            let inner = table
+                .entry(first)
+                .or_insert_with(|| Value::Object(<_>::default()));
+            if !inner.is_object() {
+                *inner = Value::Object(<_>::default());
+            }
+            if let Value::Object(inner) = inner {
+                insert_path(inner, rest, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn migrate_system_services() {
+        let mut config = json!({
+            "aqua_ipfs": [{ "ipfs_binary_path": "/usr/bin/ipfs" }],
+            "decider": [{ "worker_period_sec": 900 }],
+            "registry": { "registry_period_sec": 3600 },
+            "system_services": {
+                "enable": ["decider"],
+                "registry": { "registry_period_sec": 60 }
+            }
+        });
+
+        let report = migrate(&mut config).unwrap();
+        assert_eq!(report.from, 0);
+        assert_eq!(report.to, CONFIG_VERSION);
+        assert_eq!(report.warnings.len(), 3);
+        assert_eq!(
+            config,
+            json!({
+                "config_version": CONFIG_VERSION,
+                "system_services": {
+                    "enable": ["decider"],
+                    "aqua_ipfs": { "ipfs_binary_path": "/usr/bin/ipfs" },
+                    "decider": { "worker_period_sec": 900 },
+                    "registry": { "registry_period_sec": 60 }
+                }
+            })
+        );
+
+        // already migrated config is left as is
+        let report = migrate(&mut config).unwrap();
+        assert!(!report.is_migrated());
+        assert!(report.warnings.is_empty());
+
+        let mut legacy = json!({
+            "config_version": 1,
+            "aquavm_pool_size": 4,
+            "particle_limits": { "max_data_size": "1 MiB" }
+        });
+        let report = migrate(&mut legacy).unwrap();
+        assert_eq!(report.from, 1);
+        assert_eq!(
+            legacy,
+            json!({
+                "config_version": CONFIG_VERSION,
+                "particle_limits": {},
+                "resources": { "aquavm_pool_size": 4, "max_particle_data_size": "1 MiB" }
+            })
+        );
+
+        let mut newer = json!({ "config_version": CONFIG_VERSION + 1 });
+        assert!(migrate(&mut newer).is_err());
+    }
+
+    #[test]
+    fn migrate_file_with_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Config.toml");
+        let original = "[[decider]]\nworker_period_sec = 900\n";
+        std::fs::write(&path, original).unwrap();
+
+        let report = migrate_file(&path).unwrap();
+        assert!(report.is_migrated());
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("Config.toml.v0.bak")).unwrap(),
+            original
+        );
+
+        let (config, _) = read_file(&path).unwrap();
+        assert_eq!(
+            config,
+            json!({
+                "config_version": CONFIG_VERSION,
+                "system_services": { "decider": { "worker_period_sec": 900 } }
+            })
+        );
+    }
+}
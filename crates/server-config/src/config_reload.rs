@@ -0,0 +1,210 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// Config fields that are applied without restarting the node.
+/// A field covers its nested fields, e.g. `rate_limit` covers `rate_limit.bytes_per_sec`.
+pub const RELOADABLE_FIELDS: &[&str] = &[
+    "log_filter",
+    "rate_limit",
+    "builtin_policy",
+    "effectors",
+    "system_services",
+    "metrics_config.metrics_enabled",
+];
+
+/// Fields that differ between the config the node runs with and the reloaded one.
+/// Fields are dotted paths up to the second level, e.g. `listen_config.tcp_port`.
+#[derive(Debug, Clone, Default, Serialize, PartialEq, Eq)]
+pub struct ReloadReport {
+    /// Fields applied at runtime
+    pub applied: Vec<String>,
+    /// Fields that take effect only after restart
+    pub requires_restart: Vec<String>,
+}
+
+impl ReloadReport {
+    /// Compares serialized configs, usually [crate::UnresolvedConfig]
+    pub fn new(current: &Value, new: &Value) -> Self {
+        let mut report = Self::default();
+        for field in changed_fields(current, new) {
+            if is_reloadable(&field) {
+                report.applied.push(field);
+            } else {
+                report.requires_restart.push(field);
+            }
+        }
+        report
+    }
+
+    /// Whether `field` or any of its nested fields is applied
+    pub fn is_applied(&self, field: &str) -> bool {
+        self.applied.iter().any(|f| is_within(f, field))
+    }
+
+    /// Moves `field` and its nested fields to [ReloadReport::requires_restart],
+    /// e.g. when the node can't apply them in its current setup
+    pub fn require_restart(&mut self, field: &str) {
+        let (restart, applied) = std::mem::take(&mut self.applied)
+            .into_iter()
+            .partition(|f| is_within(f, field));
+        self.applied = applied;
+        self.requires_restart.extend::<Vec<_>>(restart);
+        self.requires_restart.sort();
+    }
+
+    /// Copies applied fields from `new` to `current`, so the next report
+    /// still lists fields that require restart, but not the applied ones
+    pub fn update(&self, current: &mut Value, new: &Value) {
+        self.update_field(current, new, "");
+    }
+
+    /// Copies applied fields within `field` from `new` to `current`, empty `field` covers all of them
+    pub fn update_field(&self, current: &mut Value, new: &Value, field: &str) {
+        let applied = self
+            .applied
+            .iter()
+            .filter(|f| field.is_empty() || is_within(f, field));
+        for field in applied {
+            let pointer = format!("/{}", field.replace('.', "/"));
+            let value = new.pointer(&pointer).cloned().unwrap_or(Value::Null);
+            match current.pointer_mut(&pointer) {
+                Some(current) => *current = value,
+                None => set_missing(current, field, value),
+            }
+        }
+    }
+}
+
+fn is_reloadable(field: &str) -> bool {
+    RELOADABLE_FIELDS.iter().any(|r| is_within(field, r))
+}
+
+/// Whether `path` is `field` or one of its nested fields
+pub(crate) fn is_within(path: &str, field: &str) -> bool {
+    path.strip_prefix(field)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+}
+
+/// Top-level fields that differ, objects are compared field by field one level deeper
+fn changed_fields(current: &Value, new: &Value) -> Vec<String> {
+    let mut changed = vec![];
+    for key in keys(current, new) {
+        let (a, b) = (current.get(&key), new.get(&key));
+        match (a, b) {
+            (Some(Value::Object(_)), Some(Value::Object(_))) => {
+                let (a, b) = (a.unwrap(), b.unwrap());
+                for nested in keys(a, b) {
+                    if a.get(&nested) != b.get(&nested) {
+                        changed.push(format!("{key}.{nested}"));
+                    }
+                }
+            }
+            (a, b) if a != b => changed.push(key),
+            _ => {}
+        }
+    }
+    changed
+}
+
+/// Sorted union of the object keys of `a` and `b`
+fn keys(a: &Value, b: &Value) -> Vec<String> {
+    let mut keys: Vec<String> = [a, b]
+        .into_iter()
+        .filter_map(Value::as_object)
+        .flat_map(|o| o.keys().cloned())
+        .collect();
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+fn set_missing(current: &mut Value, field: &str, value: Value) {
+    let mut target = current;
+    let mut parts = field.split('.').peekable();
+    while let Some(part) = parts.next() {
+        if !target.is_object() {
+            *target = Value::Object(<_>::default());
+        }
+        let object = target.as_object_mut().expect("set above");
+        if parts.peek().is_none() {
+            object.insert(part.to_string(), value);
+            return;
+        }
+        target = object.entry(part).or_insert(Value::Null);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn classify_changed_fields() {
+        let mut current = json!({
+            "log_filter": null,
+            "rate_limit": { "particles_per_sec": 100 },
+            "listen_config": { "tcp_port": 7777, "websocket_port": 9999 },
+            "metrics_config": { "metrics_enabled": true, "tokio_metrics_enabled": false },
+        });
+        let new = json!({
+            "log_filter": "particle_reap=debug",
+            "rate_limit": { "particles_per_sec": 10 },
+            "listen_config": { "tcp_port": 7778, "websocket_port": 9999 },
+            "metrics_config": { "metrics_enabled": false, "tokio_metrics_enabled": true },
+        });
+
+        let report = ReloadReport::new(&current, &new);
+        assert_eq!(
+            report.applied,
+            vec![
+                "log_filter",
+                "metrics_config.metrics_enabled",
+                "rate_limit.particles_per_sec"
+            ]
+        );
+        assert_eq!(
+            report.requires_restart,
+            vec![
+                "listen_config.tcp_port",
+                "metrics_config.tokio_metrics_enabled"
+            ]
+        );
+        assert!(report.is_applied("rate_limit"));
+        assert!(!report.is_applied("effectors"));
+
+        let mut moved = report.clone();
+        moved.require_restart("metrics_config");
+        assert!(!moved.is_applied("metrics_config"));
+        assert_eq!(moved.requires_restart.len(), 3);
+
+        let mut partial = current.clone();
+        report.update_field(&mut partial, &new, "rate_limit");
+        assert_eq!(
+            ReloadReport::new(&partial, &new).applied,
+            vec!["log_filter", "metrics_config.metrics_enabled"]
+        );
+
+        report.update(&mut current, &new);
+        let report = ReloadReport::new(&current, &new);
+        assert!(report.applied.is_empty());
+        assert_eq!(report.requires_restart.len(), 2);
+    }
+}
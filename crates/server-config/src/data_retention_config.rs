@@ -0,0 +1,91 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::time::Duration;
+
+use bytesize::ByteSize;
+use serde::{Deserialize, Serialize};
+
+/// Background compaction of particle data and anomaly dumps.
+/// Compaction is disabled unless some limit is set.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DataRetentionConfig {
+    /// How often compaction runs
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+    /// Particle data merged between executions, removing it breaks particles still in flight,
+    /// so `max_age` should be longer than particle TTLs
+    pub particles: RetentionPolicyConfig,
+    /// Dumps of AquaVM performance anomalies
+    pub anomalies: RetentionPolicyConfig,
+}
+
+impl Default for DataRetentionConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(600),
+            particles: <_>::default(),
+            anomalies: <_>::default(),
+        }
+    }
+}
+
+/// Entries above these limits are removed, oldest first
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RetentionPolicyConfig {
+    /// Entries not modified for that long are removed
+    #[serde(with = "humantime_serde")]
+    pub max_age: Option<Duration>,
+    pub max_total_size: Option<ByteSize>,
+    /// Larger particle data files are removed, anomaly dumps of a particle are
+    /// removed oldest first until they fit
+    pub max_particle_size: Option<ByteSize>,
+}
+
+impl RetentionPolicyConfig {
+    fn is_set(&self) -> bool {
+        self.max_age.is_some() || self.max_total_size.is_some() || self.max_particle_size.is_some()
+    }
+
+    fn validate(&self, name: &str) -> eyre::Result<()> {
+        if self.max_age.is_some_and(|age| age.is_zero()) {
+            eyre::bail!("data_retention.{name}.max_age must be positive");
+        }
+        if self.max_total_size.is_some_and(|s| s.as_u64() == 0) {
+            eyre::bail!("data_retention.{name}.max_total_size must be positive");
+        }
+        if self.max_particle_size.is_some_and(|s| s.as_u64() == 0) {
+            eyre::bail!("data_retention.{name}.max_particle_size must be positive");
+        }
+        Ok(())
+    }
+}
+
+impl DataRetentionConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.particles.is_set() || self.anomalies.is_set()
+    }
+
+    pub fn validate(&self) -> eyre::Result<()> {
+        if self.interval.is_zero() {
+            eyre::bail!("data_retention.interval must be positive");
+        }
+        self.particles.validate("particles")?;
+        self.anomalies.validate("anomalies")
+    }
+}
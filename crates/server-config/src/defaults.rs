@@ -54,6 +54,10 @@ pub fn default_connection_idle_timeout() -> Duration {
     Duration::from_secs(180)
 }
 
+pub fn default_prefer_ipv6() -> bool {
+    true
+}
+
 pub fn default_max_established_per_peer_limit() -> Option<u32> {
     Some(5)
 }
@@ -93,6 +97,28 @@ pub fn default_http_port() -> u16 {
     18080
 }
 
+pub fn default_metrics_port() -> u16 {
+    18081
+}
+
+pub fn default_websocket_tls_port() -> u16 {
+    9443
+}
+
+pub fn default_acme_directory_url() -> String {
+    "https://acme-v02.api.letsencrypt.org/directory".to_string()
+}
+
+pub fn default_acme_renew_before() -> Duration {
+    // 30 days
+    Duration::from_secs(30 * 24 * 60 * 60)
+}
+
+pub fn default_acme_check_interval() -> Duration {
+    // 12 hours
+    Duration::from_secs(12 * 60 * 60)
+}
+
 pub fn default_metrics_enabled() -> bool {
     true
 }
@@ -109,6 +135,18 @@ pub fn default_health_check_enabled() -> bool {
     true
 }
 
+pub fn default_control_socket_enabled() -> bool {
+    true
+}
+
+pub fn default_min_free_disk_space() -> bytesize::ByteSize {
+    bytesize::ByteSize::gib(1)
+}
+
+pub fn default_chain_block_timeout() -> Duration {
+    Duration::from_secs(300)
+}
+
 pub fn default_services_metrics_timer_resolution() -> Duration {
     Duration::from_secs(60)
 }
@@ -145,10 +183,20 @@ pub fn default_builtins_keypair_path(persistent_base_dir: &Path) -> PathOrValue
     }
 }
 
+pub fn default_http_client_keypair_path(persistent_base_dir: &Path) -> PathOrValue {
+    PathOrValue::Path {
+        path: persistent_base_dir.join("http_client_secret_key.ed25519"),
+    }
+}
+
 pub fn default_aquavm_pool_size() -> usize {
     num_cpus::get() * 2
 }
 
+pub fn default_aquavm_preinit() -> bool {
+    true
+}
+
 pub fn default_particle_queue_buffer_size() -> usize {
     128
 }
@@ -251,6 +299,10 @@ pub fn default_registry_replicate_spell_period_sec() -> u32 {
     3600
 }
 
+pub fn default_registry_replicate() -> bool {
+    true
+}
+
 pub fn default_decider_network_api_endpoint() -> String {
     "https://endpoints.omniatech.io/v1/matic/mumbai/public".to_string()
 }
@@ -268,6 +320,10 @@ pub fn default_decider_worker_gas() -> u64 {
     210_000
 }
 
+pub fn default_deal_install_timeout() -> Duration {
+    Duration::from_secs(600)
+}
+
 pub fn default_ipfs_binary_path() -> String {
     "/usr/bin/ipfs".to_string()
 }
@@ -299,3 +355,7 @@ pub fn default_binaries_mapping() -> HashMap<String, String> {
 pub fn default_proof_poll_period() -> Duration {
     Duration::from_secs(60)
 }
+
+pub fn default_deal_core_hold() -> Duration {
+    Duration::from_secs(300)
+}
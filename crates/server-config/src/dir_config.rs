@@ -61,6 +61,36 @@ pub struct UnresolvedDirConfig {
 
     /// Path to stored core_state
     pub core_state_path: Option<PathBuf>,
+
+    /// Path to the WebRTC certificate
+    pub webrtc_certificate_path: Option<PathBuf>,
+
+    /// Directory where ACME account and issued certificates are stored
+    pub acme_dir: Option<PathBuf>,
+
+    /// Path to allowlist and denylist entries added at runtime
+    pub peer_filter_path: Option<PathBuf>,
+
+    /// Path to the cache of particles received from the network, persisted across restarts
+    pub seen_particles_path: Option<PathBuf>,
+
+    /// Path to the append-only log of significant node events
+    pub audit_log_path: Option<PathBuf>,
+
+    /// Path to the unix socket of the local control interface
+    pub control_socket_path: Option<PathBuf>,
+
+    /// Path to the API tokens issued by the management peer
+    pub api_tokens_path: Option<PathBuf>,
+
+    /// Path to the builtin policy rules added at runtime
+    pub builtin_policy_path: Option<PathBuf>,
+
+    /// Path to the management keys added at runtime
+    pub management_keys_path: Option<PathBuf>,
+
+    /// Path to the versions of deployed system service packages
+    pub system_services_versions_path: Option<PathBuf>,
 }
 
 impl UnresolvedDirConfig {
@@ -102,6 +132,34 @@ impl UnresolvedDirConfig {
             .core_state_path
             .clone()
             .unwrap_or(persistent_base_dir.join("cores_state.toml"));
+        let webrtc_certificate_path = self
+            .webrtc_certificate_path
+            .unwrap_or(persistent_base_dir.join("webrtc_certificate.pem"));
+        let acme_dir = self.acme_dir.unwrap_or(persistent_base_dir.join("acme"));
+        let peer_filter_path = self
+            .peer_filter_path
+            .unwrap_or(persistent_base_dir.join("peer_filter.toml"));
+        let seen_particles_path = self
+            .seen_particles_path
+            .unwrap_or(persistent_base_dir.join("seen_particles"));
+        let audit_log_path = self
+            .audit_log_path
+            .unwrap_or(persistent_base_dir.join("audit.log"));
+        let control_socket_path = self
+            .control_socket_path
+            .unwrap_or(persistent_base_dir.join("nox.sock"));
+        let api_tokens_path = self
+            .api_tokens_path
+            .unwrap_or(persistent_base_dir.join("api_tokens.toml"));
+        let builtin_policy_path = self
+            .builtin_policy_path
+            .unwrap_or(persistent_base_dir.join("builtin_policy.toml"));
+        let management_keys_path = self
+            .management_keys_path
+            .unwrap_or(persistent_base_dir.join("management_keys.toml"));
+        let system_services_versions_path = self
+            .system_services_versions_path
+            .unwrap_or(persistent_base_dir.join("system_services_versions.toml"));
 
         create_dirs(&[
             &base,
@@ -115,6 +173,7 @@ impl UnresolvedDirConfig {
             &spell_base_dir,
             &keypairs_base_dir,
             &workers_base_dir,
+            &acme_dir,
             // other
             &cc_events_dir,
         ])
@@ -130,6 +189,7 @@ impl UnresolvedDirConfig {
         let spell_base_dir = canonicalize(spell_base_dir)?;
         let keypairs_base_dir = canonicalize(keypairs_base_dir)?;
         let workers_base_dir = canonicalize(workers_base_dir)?;
+        let acme_dir = canonicalize(acme_dir)?;
 
         let cc_events_dir = canonicalize(cc_events_dir)?;
 
@@ -146,6 +206,16 @@ impl UnresolvedDirConfig {
             workers_base_dir,
             cc_events_dir,
             core_state_path,
+            webrtc_certificate_path,
+            acme_dir,
+            peer_filter_path,
+            seen_particles_path,
+            audit_log_path,
+            control_socket_path,
+            api_tokens_path,
+            builtin_policy_path,
+            management_keys_path,
+            system_services_versions_path,
         })
     }
 }
@@ -166,4 +236,14 @@ pub struct ResolvedDirConfig {
     pub workers_base_dir: PathBuf,
     pub cc_events_dir: PathBuf,
     pub core_state_path: PathBuf,
+    pub webrtc_certificate_path: PathBuf,
+    pub acme_dir: PathBuf,
+    pub peer_filter_path: PathBuf,
+    pub seen_particles_path: PathBuf,
+    pub audit_log_path: PathBuf,
+    pub control_socket_path: PathBuf,
+    pub api_tokens_path: PathBuf,
+    pub builtin_policy_path: PathBuf,
+    pub management_keys_path: PathBuf,
+    pub system_services_versions_path: PathBuf,
 }
@@ -0,0 +1,57 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Resolution of `/dns`, `/dns4`, `/dns6` and `/dnsaddr` bootstrap addresses
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DnsConfig {
+    /// Resolved addresses are cached for the TTL of the DNS records, but no less than `min_ttl`
+    #[serde(with = "humantime_serde")]
+    pub min_ttl: Duration,
+    /// and no more than `max_ttl`
+    #[serde(with = "humantime_serde")]
+    pub max_ttl: Duration,
+    /// How often bootstrap addresses are re-resolved, connected bootstrap nodes are redialed
+    /// when their addresses change. Cached addresses are reused until their TTL expires.
+    #[serde(with = "humantime_serde")]
+    pub reresolve_interval: Duration,
+}
+
+impl Default for DnsConfig {
+    fn default() -> Self {
+        Self {
+            min_ttl: Duration::from_secs(30),
+            max_ttl: Duration::from_secs(3600),
+            reresolve_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+impl DnsConfig {
+    pub fn validate(&self) -> eyre::Result<()> {
+        if self.min_ttl > self.max_ttl {
+            eyre::bail!("dns.min_ttl can't be greater than dns.max_ttl");
+        }
+        if self.reresolve_interval.is_zero() {
+            eyre::bail!("dns.reresolve_interval must be positive");
+        }
+        Ok(())
+    }
+}
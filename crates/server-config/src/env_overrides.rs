@@ -0,0 +1,99 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use config::{Config, Environment};
+use libp2p::PeerId;
+use serde_json::Value;
+
+use crate::UnresolvedConfig;
+
+/// Prefixes of env variables overriding config fields, later ones take precedence.
+/// `FLUENCE_` is kept for compatibility.
+pub const ENV_PREFIXES: [&str; 2] = ["FLUENCE", "NOX"];
+
+/// Env variables listing config files to load, comma-separated
+pub const CONFIG_PATH_ENVS: [&str; 2] = ["FLUENCE_CONFIG", "NOX_CONFIG"];
+
+/// List fields that are `None` by default, so they can't be found in the default config
+const OPTIONAL_LIST_FIELDS: [&str; 5] = [
+    "allowed_binaries",
+    "external_multiaddresses",
+    "bootstrap_nodes",
+    "listen_config.listen_multiaddrs",
+    "system_services.enable",
+];
+
+/// Source overriding any config field with env variables named after the field path,
+/// e.g. `NOX_SYSTEM_SERVICES__DECIDER__WORKER_PERIOD_SEC` for `system_services.decider.worker_period_sec`.
+/// Values of list fields are comma-separated.
+pub fn env_source(prefix: &str) -> Environment {
+    list_fields().into_iter().fold(
+        Environment::with_prefix(prefix)
+            .try_parsing(true)
+            .prefix_separator("_")
+            .separator("__")
+            .list_separator(","),
+        |env, field| env.with_list_parse_key(&field),
+    )
+}
+
+/// Paths of all list fields, taken from the config structs via the default config
+fn list_fields() -> Vec<String> {
+    // default management peer id is generated and logged, so it's set here to skip that
+    let defaults = Config::builder()
+        .set_default("management_peer_id", PeerId::random().to_base58())
+        .and_then(|b| b.build())
+        .and_then(|c| c.try_deserialize::<UnresolvedConfig>())
+        .ok()
+        .and_then(|c| serde_json::to_value(c).ok())
+        .unwrap_or_default();
+
+    let mut fields = vec![];
+    collect_list_fields(&defaults, None, &mut fields);
+    fields.extend(OPTIONAL_LIST_FIELDS.map(String::from));
+    fields.sort();
+    fields.dedup();
+    fields
+}
+
+fn collect_list_fields(value: &Value, path: Option<&str>, fields: &mut Vec<String>) {
+    match value {
+        Value::Array(_) => fields.extend(path.map(String::from)),
+        Value::Object(object) => {
+            for (key, value) in object {
+                let path = match path {
+                    Some(path) => format!("{path}.{key}"),
+                    None => key.clone(),
+                };
+                collect_list_fields(value, Some(&path), fields);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_fields_from_defaults() {
+        let fields = list_fields();
+        assert!(fields.contains(&"nat.relays".to_string()));
+        assert!(fields.contains(&"system_services.enable".to_string()));
+        assert!(!fields.contains(&"system_services".to_string()));
+    }
+}
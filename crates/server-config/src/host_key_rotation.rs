@@ -0,0 +1,383 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use eyre::{eyre, WrapErr};
+use fluence_keypair::{key_pair::KeyFormat, KeyPair};
+use key_encryption::KeyEncryption;
+use serde::{Deserialize, Serialize};
+
+use crate::keys::{create_new_key_pair, load_key};
+
+/// How long the previous host identity is kept after the node restarts with the new key
+pub const DEFAULT_HANDOVER_GRACE_PERIOD: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Handover from the previous host peer id to the next one, persisted until the previous key is retired
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct HostKeyHandover {
+    pub previous_peer_id: String,
+    pub peer_id: String,
+    /// The previous peer id is still served for that long after the node restarts with the new key
+    #[serde(with = "humantime_serde")]
+    pub grace_period: Duration,
+    /// UNIX timestamp in seconds
+    pub requested_at: u64,
+    /// UNIX timestamp in seconds of the restart with the new key, `None` while the rotation is pending
+    pub activated_at: Option<u64>,
+    /// New peer id signed by the previous key in base58, proves that both ids belong to the same host
+    pub signature: String,
+}
+
+impl HostKeyHandover {
+    /// UNIX timestamp in seconds when the previous key is retired
+    pub fn retires_at(&self) -> Option<u64> {
+        self.activated_at
+            .map(|at| at.saturating_add(self.grace_period.as_secs()))
+    }
+}
+
+/// Rotation of the root key pair stored in a file.
+///
+/// `rotate` generates the next key beside the current one as `<key>.next`. On the next start the
+/// current key is moved to `<key>.previous` and the next key takes its place. The previous key is
+/// loaded until the grace period ends, then `retire` removes it.
+///
+/// Activation takes two renames and an update of the handover. If the node stops in between,
+/// the next start finishes the activation from the files found on disk.
+#[derive(Debug, Clone)]
+pub struct HostKeyRotation {
+    key_path: PathBuf,
+    key_format: String,
+    state_path: PathBuf,
+    encryption: Option<KeyEncryption>,
+}
+
+impl HostKeyRotation {
+    pub fn new(
+        key_path: PathBuf,
+        key_format: String,
+        state_path: PathBuf,
+        encryption: Option<KeyEncryption>,
+    ) -> Self {
+        Self {
+            key_path,
+            key_format,
+            state_path,
+            encryption,
+        }
+    }
+
+    fn key_path_with(&self, suffix: &str) -> PathBuf {
+        let mut path = OsString::from(self.key_path.as_os_str());
+        path.push(suffix);
+        path.into()
+    }
+
+    fn next_key_path(&self) -> PathBuf {
+        self.key_path_with(".next")
+    }
+
+    fn previous_key_path(&self) -> PathBuf {
+        self.key_path_with(".previous")
+    }
+
+    /// Handover in progress, either pending restart or in the grace period
+    pub fn handover(&self) -> eyre::Result<Option<HostKeyHandover>> {
+        match std::fs::read_to_string(&self.state_path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map(Some)
+                .wrap_err(format!("failed to parse {}", self.state_path.display())),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err).wrap_err(format!("failed to read {}", self.state_path.display())),
+        }
+    }
+
+    fn persist(&self, handover: &HostKeyHandover) -> eyre::Result<()> {
+        let contents = toml::to_string(handover)?;
+        fs_utils::write_atomically(&self.state_path, contents.as_bytes())
+            .wrap_err(format!("failed to write {}", self.state_path.display()))
+    }
+
+    /// Generates the next host key, it replaces `current` on the next start
+    pub fn rotate(
+        &self,
+        current: &KeyPair,
+        grace_period: Duration,
+    ) -> eyre::Result<HostKeyHandover> {
+        if let Some(handover) = self.handover()? {
+            eyre::bail!(
+                "rotation from {} to {} is already in progress",
+                handover.previous_peer_id,
+                handover.peer_id
+            );
+        }
+
+        let next_key_path = self.next_key_path();
+        let next = create_new_key_pair(
+            &next_key_path,
+            KeyFormat::from_str(&self.key_format)?,
+            self.encryption.as_ref(),
+        )
+        .wrap_err(format!(
+            "failed to create the next key at {}",
+            next_key_path.display()
+        ))?;
+        let signature = current
+            .sign(&next.get_peer_id().to_bytes())
+            .map_err(|err| eyre!("failed to sign the handover: {err}"))?;
+
+        let handover = HostKeyHandover {
+            previous_peer_id: current.get_peer_id().to_string(),
+            peer_id: next.get_peer_id().to_string(),
+            grace_period,
+            requested_at: now_secs(),
+            activated_at: None,
+            signature: bs58::encode(signature.to_vec()).into_string(),
+        };
+        if let Err(err) = self.persist(&handover) {
+            std::fs::remove_file(&next_key_path).ok();
+            return Err(err);
+        }
+        Ok(handover)
+    }
+
+    /// Puts the next key in place of the current one if a rotation is pending. Called before the root key is loaded.
+    pub fn activate(&self) -> eyre::Result<Option<HostKeyHandover>> {
+        let mut handover = match self.handover()? {
+            Some(handover) if handover.activated_at.is_none() => handover,
+            handover => return Ok(handover),
+        };
+
+        let next_key_path = self.next_key_path();
+        let previous_key_path = self.previous_key_path();
+        match (self.key_path.exists(), next_key_path.exists()) {
+            (true, true) => {
+                rename(&self.key_path, &previous_key_path)?;
+                rename(&next_key_path, &self.key_path)?;
+            }
+            // stopped between the renames
+            (false, true) if previous_key_path.exists() => {
+                log::info!("Resuming host key rotation to {}", handover.peer_id);
+                rename(&next_key_path, &self.key_path)?;
+            }
+            // stopped after the renames, before the handover was updated
+            (true, false) if self.is_previous_key_in_place(&handover) => {
+                log::info!("Resuming host key rotation to {}", handover.peer_id);
+            }
+            _ => {
+                log::warn!(
+                    "Host key rotation to {} is abandoned: {} doesn't exist",
+                    handover.peer_id,
+                    next_key_path.display()
+                );
+                std::fs::remove_file(&self.state_path)?;
+                return Ok(None);
+            }
+        }
+        handover.activated_at = Some(now_secs());
+        self.persist(&handover)?;
+        log::info!(
+            "Host key rotated from {} to {}, the previous key is kept for {:?}",
+            handover.previous_peer_id,
+            handover.peer_id,
+            handover.grace_period
+        );
+        Ok(Some(handover))
+    }
+
+    /// Whether `<key>.previous` is the key `handover` rotates from, and not a leftover of an earlier rotation
+    fn is_previous_key_in_place(&self, handover: &HostKeyHandover) -> bool {
+        self.load_previous_key()
+            .is_ok_and(|previous| previous.get_peer_id().to_string() == handover.previous_peer_id)
+    }
+
+    fn load_previous_key(&self) -> eyre::Result<KeyPair> {
+        let previous_key_path = self.previous_key_path();
+        load_key(
+            previous_key_path.clone(),
+            self.key_format.clone(),
+            false,
+            self.encryption.as_ref(),
+        )
+        .wrap_err(format!(
+            "failed to load the previous host key from {}",
+            previous_key_path.display()
+        ))
+    }
+
+    /// The previous key if the handover is in the grace period. Retires the previous key if the grace period is over.
+    pub fn load_previous(&self) -> eyre::Result<Option<(KeyPair, HostKeyHandover)>> {
+        let handover = match self.handover()? {
+            Some(handover) => handover,
+            None => return Ok(None),
+        };
+        match handover.retires_at() {
+            None => return Ok(None),
+            Some(retires_at) if retires_at <= now_secs() => {
+                self.retire()?;
+                return Ok(None);
+            }
+            Some(_) => {}
+        }
+
+        let previous = self.load_previous_key()?;
+        if previous.get_peer_id().to_string() != handover.previous_peer_id {
+            eyre::bail!(
+                "{} doesn't belong to {}",
+                self.previous_key_path().display(),
+                handover.previous_peer_id
+            );
+        }
+        Ok(Some((previous, handover)))
+    }
+
+    /// Finishes the handover and removes the previous key.
+    /// The handover goes first, so a handover is never left without its previous key.
+    pub fn retire(&self) -> eyre::Result<()> {
+        std::fs::remove_file(&self.state_path)
+            .wrap_err(format!("failed to remove {}", self.state_path.display()))?;
+        match std::fs::remove_file(self.previous_key_path()) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err).wrap_err("failed to remove the previous host key"),
+        }
+    }
+}
+
+fn rename(from: &Path, to: &Path) -> eyre::Result<()> {
+    std::fs::rename(from, to).wrap_err(format!(
+        "failed to move {} to {}",
+        from.display(),
+        to.display()
+    ))
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_keypair::Signature;
+
+    use super::*;
+
+    fn rotation(dir: &Path) -> HostKeyRotation {
+        HostKeyRotation::new(
+            dir.join("secret_key.ed25519"),
+            "ed25519".to_string(),
+            dir.join("host_key_rotation.toml"),
+            None,
+        )
+    }
+
+    fn load(path: &Path) -> KeyPair {
+        load_key(path.to_path_buf(), "ed25519".to_string(), true, None).unwrap()
+    }
+
+    #[test]
+    fn rotate_and_retire() {
+        let dir = tempfile::tempdir().unwrap();
+        let key_path = dir.path().join("secret_key.ed25519");
+        let rotation = rotation(dir.path());
+        let load = || load(&key_path);
+        let current = load();
+
+        let handover = rotation.rotate(&current, Duration::from_secs(60)).unwrap();
+        assert_eq!(handover.previous_peer_id, current.get_peer_id().to_string());
+        assert!(rotation.rotate(&current, Duration::from_secs(60)).is_err());
+        // the current key stays in place until the restart
+        assert_eq!(load().get_peer_id(), current.get_peer_id());
+        assert!(rotation.load_previous().unwrap().is_none());
+
+        let signature = bs58::decode(&handover.signature).into_vec().unwrap();
+        let signature = Signature::from_bytes(current.public().get_key_format(), signature);
+        let next = rotation.activate().unwrap().unwrap();
+        assert_eq!(load().get_peer_id().to_string(), handover.peer_id);
+        assert!(current
+            .public()
+            .verify(&load().get_peer_id().to_bytes(), &signature)
+            .is_ok());
+
+        let (previous, _) = rotation.load_previous().unwrap().unwrap();
+        assert_eq!(previous.get_peer_id(), current.get_peer_id());
+        // activation happens once
+        assert_eq!(rotation.activate().unwrap(), Some(next));
+
+        rotation.retire().unwrap();
+        assert!(rotation.handover().unwrap().is_none());
+        assert!(rotation.load_previous().unwrap().is_none());
+        assert_eq!(load().get_peer_id().to_string(), handover.peer_id);
+    }
+
+    #[test]
+    fn resume_after_first_rename() {
+        let dir = tempfile::tempdir().unwrap();
+        let rotation = rotation(dir.path());
+        let current = load(&rotation.key_path);
+        let handover = rotation.rotate(&current, Duration::from_secs(60)).unwrap();
+
+        rename(&rotation.key_path, &rotation.previous_key_path()).unwrap();
+        let activated = rotation.activate().unwrap().unwrap();
+        assert!(activated.activated_at.is_some());
+        assert_eq!(
+            load(&rotation.key_path).get_peer_id().to_string(),
+            handover.peer_id
+        );
+        let (previous, _) = rotation.load_previous().unwrap().unwrap();
+        assert_eq!(previous.get_peer_id(), current.get_peer_id());
+    }
+
+    #[test]
+    fn resume_after_second_rename() {
+        let dir = tempfile::tempdir().unwrap();
+        let rotation = rotation(dir.path());
+        let current = load(&rotation.key_path);
+        let handover = rotation.rotate(&current, Duration::from_secs(60)).unwrap();
+
+        rename(&rotation.key_path, &rotation.previous_key_path()).unwrap();
+        rename(&rotation.next_key_path(), &rotation.key_path).unwrap();
+        let activated = rotation.activate().unwrap().unwrap();
+        assert!(activated.activated_at.is_some());
+        assert_eq!(
+            load(&rotation.key_path).get_peer_id().to_string(),
+            handover.peer_id
+        );
+    }
+
+    #[test]
+    fn abandon_without_next_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let rotation = rotation(dir.path());
+        let current = load(&rotation.key_path);
+        rotation.rotate(&current, Duration::from_secs(60)).unwrap();
+
+        std::fs::remove_file(rotation.next_key_path()).unwrap();
+        assert!(rotation.activate().unwrap().is_none());
+        assert!(rotation.handover().unwrap().is_none());
+        assert_eq!(
+            load(&rotation.key_path).get_peer_id(),
+            current.get_peer_id()
+        );
+    }
+}
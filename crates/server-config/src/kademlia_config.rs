@@ -31,6 +31,31 @@ pub struct KademliaConfig {
     /// Period after which peer ban is lifted
     #[serde(with = "humantime_serde")]
     pub ban_cooldown: Duration,
+    /// How long stored records live, `0s` for records that never expire
+    #[serde(with = "humantime_serde", default = "default_record_ttl")]
+    pub record_ttl: Duration,
+    /// How often stored records are replicated to the closest peers, `0s` to disable
+    #[serde(with = "humantime_serde", default = "default_replication_interval")]
+    pub replication_interval: Duration,
+    /// How often records published by this node are republished, `0s` to disable.
+    /// Republication can also be triggered manually with `kad.republish` builtin.
+    #[serde(with = "humantime_serde", default = "default_publication_interval")]
+    pub publication_interval: Duration,
+}
+
+fn default_record_ttl() -> Duration {
+    Duration::from_secs(36 * 60 * 60)
+}
+fn default_replication_interval() -> Duration {
+    Duration::from_secs(60 * 60)
+}
+fn default_publication_interval() -> Duration {
+    Duration::from_secs(24 * 60 * 60)
+}
+
+/// Zero durations disable the corresponding Kademlia timers
+fn enabled(duration: Duration) -> Option<Duration> {
+    (!duration.is_zero()).then_some(duration)
 }
 
 impl Default for KademliaConfig {
@@ -41,15 +66,42 @@ impl Default for KademliaConfig {
             replication_factor: None,
             peer_fail_threshold: 3,
             ban_cooldown: Duration::from_secs(60),
+            record_ttl: default_record_ttl(),
+            replication_interval: default_replication_interval(),
+            publication_interval: default_publication_interval(),
         }
     }
 }
 
 impl KademliaConfig {
+    pub fn validate(&self) -> eyre::Result<()> {
+        let publication = enabled(self.publication_interval);
+        if let (Some(replication), Some(publication)) =
+            (enabled(self.replication_interval), publication)
+        {
+            if replication > publication {
+                eyre::bail!(
+                    "kademlia.replication_interval can't be greater than kademlia.publication_interval"
+                );
+            }
+        }
+        if let (Some(ttl), Some(publication)) = (enabled(self.record_ttl), publication) {
+            if ttl < publication {
+                eyre::bail!(
+                    "kademlia.record_ttl can't be less than kademlia.publication_interval, records would expire before they are republished"
+                );
+            }
+        }
+        Ok(())
+    }
+
     pub fn as_libp2p(&self) -> LibP2PKadConfig {
         let mut cfg = LibP2PKadConfig::default();
 
         cfg.set_query_timeout(self.query_timeout);
+        cfg.set_record_ttl(enabled(self.record_ttl));
+        cfg.set_replication_interval(enabled(self.replication_interval));
+        cfg.set_publication_interval(enabled(self.publication_interval));
 
         if let Some(max_packet_size) = self.max_packet_size {
             cfg.set_max_packet_size(max_packet_size);
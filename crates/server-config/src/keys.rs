@@ -22,13 +22,18 @@ use std::{
 };
 
 use base64::{engine::general_purpose::STANDARD as base64, Engine};
-use eyre::eyre;
+use eyre::{eyre, WrapErr};
 use fluence_keypair::{key_pair::KeyFormat, KeyPair};
+use key_encryption::{is_encrypted, KeyEncryption};
 
 use fs_utils::create_dirs;
 
-/// Creates new key pair and store its secret key in a `key_path` file.
-fn create_new_key_pair(key_path: &Path, key_format: KeyFormat) -> Result<KeyPair, Error> {
+/// Creates new key pair and store its secret key in a `key_path` file, encrypted if `encryption` is set.
+pub(crate) fn create_new_key_pair(
+    key_path: &Path,
+    key_format: KeyFormat,
+    encryption: Option<&KeyEncryption>,
+) -> eyre::Result<KeyPair> {
     let parents = key_path.parent();
     if let Some(parent_path) = parents {
         create_dirs(&[&parent_path])?
@@ -38,7 +43,10 @@ fn create_new_key_pair(key_path: &Path, key_format: KeyFormat) -> Result<KeyPair
     let secret_key = key_pair
         .secret()
         .expect("error getting secret key from keypair");
-    let encoded = base64.encode(secret_key);
+    let mut encoded = base64.encode(secret_key);
+    if let Some(encryption) = encryption {
+        encoded = encryption.encrypt(encoded.as_bytes())?;
+    }
 
     let mut key_file = File::create(key_path).map_err(|err| {
         std::io::Error::new(
@@ -102,9 +110,14 @@ pub fn decode_key(key_string: String, key_format: String) -> eyre::Result<KeyPai
     }
 }
 
-/// read base64 secret key from file and generate key pair from it
-fn read_secret_key_from_file(key_path: &Path, key_format: String) -> eyre::Result<KeyPair> {
-    let key_string = fs::read_to_string(key_path).map_err(|e| {
+/// read base64 secret key from file and generate key pair from it.
+/// If `encryption` is set, a plaintext key is encrypted in place.
+fn read_secret_key_from_file(
+    key_path: &Path,
+    key_format: String,
+    encryption: Option<&KeyEncryption>,
+) -> eyre::Result<KeyPair> {
+    let mut key_string = fs::read_to_string(key_path).map_err(|e| {
         eyre!(
             "Error reading secret key from {}: {}",
             key_path.display(),
@@ -112,13 +125,36 @@ fn read_secret_key_from_file(key_path: &Path, key_format: String) -> eyre::Resul
         )
     })?;
 
-    decode_key(key_string, key_format).map_err(|err| {
+    let encrypted = is_encrypted(&key_string);
+    if encrypted {
+        let encryption = encryption.ok_or_else(|| {
+            eyre!(
+                "key at {} is encrypted, but keystore isn't configured",
+                key_path.display()
+            )
+        })?;
+        let decrypted = encryption
+            .decrypt(&key_string)
+            .wrap_err(format!("failed to decrypt key at {}", key_path.display()))?;
+        key_string = String::from_utf8(decrypted)?;
+    }
+
+    let key_pair = decode_key(key_string.clone(), key_format).map_err(|err| {
         eyre!(
             "failed to decode key at path {}: {}",
             key_path.display(),
             err
         )
-    })
+    })?;
+
+    if let Some(encryption) = encryption.filter(|_| !encrypted) {
+        log::info!("Encrypting plaintext key at {}", key_path.display());
+        let encrypted = encryption.encrypt(key_string.trim().as_bytes())?;
+        fs_utils::write_atomically(key_path, encrypted.as_bytes())
+            .wrap_err(format!("failed to encrypt key at {}", key_path.display()))?;
+    }
+
+    Ok(key_pair)
 }
 
 pub fn decode_key_pair(key_pair: Vec<u8>, key_format: String) -> eyre::Result<KeyPair> {
@@ -137,18 +173,17 @@ pub fn decode_secret_key(secret_key: Vec<u8>, key_format: String) -> eyre::Resul
 }
 
 /// Read the file with a secret key if it exists, generate a new key pair and write it to file if not.
+/// Keys are encrypted with `encryption` if it's set, see [KeystoreConfig](crate::KeystoreConfig).
 pub fn load_key(
     key_path: PathBuf,
     key_format: String,
     generate_on_absence: bool,
+    encryption: Option<&KeyEncryption>,
 ) -> eyre::Result<KeyPair> {
     if !key_path.exists() {
         return if generate_on_absence {
             log::info!("Generating a new key to {key_path:?}");
-            Ok(create_new_key_pair(
-                &key_path,
-                KeyFormat::from_str(&key_format)?,
-            )?)
+            create_new_key_pair(&key_path, KeyFormat::from_str(&key_format)?, encryption)
         } else {
             Err(eyre!(
                 "Path to secret key does not exist {}",
@@ -158,7 +193,7 @@ pub fn load_key(
     }
 
     if !key_path.is_dir() {
-        read_secret_key_from_file(&key_path, key_format)
+        read_secret_key_from_file(&key_path, key_format, encryption)
     } else {
         Err(Error::new(
             ErrorKind::InvalidInput,
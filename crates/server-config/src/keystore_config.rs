@@ -0,0 +1,110 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::path::PathBuf;
+
+use eyre::WrapErr;
+use key_encryption::KeyEncryption;
+use serde::{Deserialize, Serialize};
+
+/// Encryption of the key pairs stored on disk: the root, builtins and http client key pairs
+/// loaded from files, and worker key pairs. Plaintext keys found on startup are encrypted in place.
+/// Exactly one of the fields must be set; values can be secret references, e.g. `${env:NOX_KEYSTORE_PASSPHRASE}`.
+#[derive(Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct KeystoreConfig {
+    pub passphrase: Option<String>,
+    pub passphrase_path: Option<PathBuf>,
+    /// 32 bytes in base64, i.e. a data key provided by a KMS
+    pub key: Option<String>,
+    pub key_path: Option<PathBuf>,
+}
+
+impl KeystoreConfig {
+    pub fn load(&self) -> eyre::Result<KeyEncryption> {
+        let read = |path: &PathBuf| {
+            std::fs::read_to_string(path)
+                .wrap_err(format!("keystore: failed to read {}", path.display()))
+        };
+        match (
+            &self.passphrase,
+            &self.passphrase_path,
+            &self.key,
+            &self.key_path,
+        ) {
+            (Some(passphrase), None, None, None) => Ok(KeyEncryption::from_passphrase(passphrase)),
+            (None, Some(path), None, None) => {
+                Ok(KeyEncryption::from_passphrase(read(path)?.trim_end()))
+            }
+            (None, None, Some(key), None) => {
+                KeyEncryption::from_key(key).wrap_err("keystore.key is invalid")
+            }
+            (None, None, None, Some(path)) => {
+                KeyEncryption::from_key(&read(path)?).wrap_err("keystore.key_path is invalid")
+            }
+            _ => eyre::bail!(
+                "keystore: exactly one of passphrase, passphrase_path, key or key_path must be specified"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use key_encryption::is_encrypted;
+
+    use super::*;
+    use crate::keys::load_key;
+
+    #[test]
+    fn exactly_one_source() {
+        let config = KeystoreConfig {
+            passphrase: Some("passphrase".to_string()),
+            ..<_>::default()
+        };
+        assert!(config.load().is_ok());
+
+        let config = KeystoreConfig {
+            passphrase: Some("passphrase".to_string()),
+            key: Some("AAAA".to_string()),
+            ..<_>::default()
+        };
+        assert!(config.load().is_err());
+        assert!(KeystoreConfig::default().load().is_err());
+    }
+
+    #[test]
+    fn encrypt_plaintext_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secret_key.ed25519");
+        let format = "ed25519".to_string();
+        let key_pair = load_key(path.clone(), format.clone(), true, None).unwrap();
+
+        let encryption = KeystoreConfig {
+            passphrase: Some("passphrase".to_string()),
+            ..<_>::default()
+        }
+        .load()
+        .unwrap();
+        let migrated = load_key(path.clone(), format.clone(), false, Some(&encryption)).unwrap();
+        assert_eq!(migrated.to_vec(), key_pair.to_vec());
+        assert!(is_encrypted(&std::fs::read_to_string(&path).unwrap()));
+
+        assert!(load_key(path.clone(), format.clone(), false, None).is_err());
+        let loaded = load_key(path, format, false, Some(&encryption)).unwrap();
+        assert_eq!(loaded.to_vec(), key_pair.to_vec());
+    }
+}
@@ -28,29 +28,83 @@
     unreachable_patterns
 )]
 
+mod admin_tls_config;
 pub mod args;
 mod avm_config;
 mod bootstrap_config;
+mod builtin_policy_config;
+mod chain_signer_config;
+mod config_inspect;
+mod config_migration;
+mod config_reload;
+mod data_retention_config;
 mod defaults;
 mod dir_config;
+mod dns_config;
+mod env_overrides;
+mod host_key_rotation;
 mod kademlia_config;
 mod keys;
+mod keystore_config;
+mod listen_endpoint_config;
+mod log_config;
+mod metrics_endpoint_config;
+mod nat_config;
 mod network_config;
 mod node_config;
+mod particle_dedup_config;
+mod particle_limits_config;
+mod particle_priority_config;
+mod private_network_config;
+mod pubsub_config;
 mod resolved_config;
+mod resources_config;
+mod secret_refs;
 mod services_config;
 pub mod system_services_config;
+mod vm_pool_scaling_config;
+mod websocket_tls_config;
 
 pub use defaults::*;
 pub use resolved_config::load_config;
 pub use resolved_config::load_config_with_args;
 pub use resolved_config::ConfigData;
 
+pub use admin_tls_config::AdminTlsConfig;
+pub use args::ConfigCommand;
 pub use bootstrap_config::BootstrapConfig;
+pub use builtin_policy_config::{BuiltinPolicyConfig, PolicyRule, PolicyScope, Principal};
+pub use chain_signer_config::RemoteSignerConfig;
+pub use config_inspect::{
+    explain_config, explain_config_with_args, redact_config, validate_config, ConfigProvenance,
+    ExplainedField,
+};
+pub use config_migration::{migrate, MigrationReport, CONFIG_VERSION};
+pub use config_reload::{ReloadReport, RELOADABLE_FIELDS};
+pub use data_retention_config::{DataRetentionConfig, RetentionPolicyConfig};
+pub use dns_config::DnsConfig;
+pub use env_overrides::{CONFIG_PATH_ENVS, ENV_PREFIXES};
+pub use host_key_rotation::{HostKeyHandover, HostKeyRotation, DEFAULT_HANDOVER_GRACE_PERIOD};
 pub use kademlia_config::KademliaConfig;
+pub use keystore_config::KeystoreConfig;
+pub use listen_endpoint_config::{EndpointProtocol, ListenEndpoint};
+pub use log_config::{LogConfig, LogFileConfig, LogRotation};
+pub use metrics_endpoint_config::MetricsEndpointConfig;
+pub use nat_config::NatConfig;
 pub use network_config::NetworkConfig;
 pub use node_config::{ChainConfig, ChainListenerConfig, NodeConfig, TransportConfig};
-pub use resolved_config::TracingConfig;
+pub use particle_dedup_config::ParticleDedupConfig;
+pub use particle_limits_config::ParticleLimitsConfig;
+pub use particle_priority_config::ParticlePriorityConfig;
+pub use private_network_config::PrivateNetworkConfig;
+pub use pubsub_config::PubSubConfig;
+pub use resolved_config::{OtlpProtocol, TracingConfig};
 pub use resolved_config::{ResolvedConfig, UnresolvedConfig};
+pub use resources_config::{GpuDevice, ResourcesConfig};
 pub use services_config::ServicesConfig;
-pub use system_services_config::{AquaIpfsConfig, DeciderConfig, SystemServicesConfig};
+pub use system_services_config::{
+    AquaIpfsConfig, DealPrefetchConfig, DeciderConfig, IpfsPinningConfig, SystemServicesConfig,
+    TrustGraphConfig,
+};
+pub use vm_pool_scaling_config::VmPoolScalingConfig;
+pub use websocket_tls_config::{AcmeChallenge, AcmeConfig, WebsocketTlsConfig};
@@ -0,0 +1,141 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::net::IpAddr;
+
+use libp2p::core::{multiaddr::Protocol, Multiaddr};
+use serde::{Deserialize, Serialize};
+
+use connection_pool::AllowedPeers;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EndpointProtocol {
+    Tcp,
+    /// Websocket, over TLS if `tls` is set
+    Ws,
+    /// QUIC over UDP, can't be used with a private network
+    Quic,
+}
+
+/// Listen endpoint configured with `[[listen_endpoints]]`
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ListenEndpoint {
+    pub protocol: EndpointProtocol,
+    /// Listens on `listen_ip` and `listen_ipv6` if not set.
+    /// Endpoints with an explicit ip aren't advertised with `external_address`.
+    #[serde(default)]
+    pub ip: Option<IpAddr>,
+    pub port: u16,
+    /// Serve websocket over TLS with the certificate configured in `websocket_tls`
+    #[serde(default)]
+    pub tls: bool,
+    /// Peers that can connect to the endpoint
+    #[serde(default)]
+    pub allow: AllowedPeers,
+}
+
+impl ListenEndpoint {
+    pub fn tcp(port: u16) -> Self {
+        Self::new(EndpointProtocol::Tcp, port)
+    }
+
+    pub fn ws(port: u16) -> Self {
+        Self::new(EndpointProtocol::Ws, port)
+    }
+
+    fn new(protocol: EndpointProtocol, port: u16) -> Self {
+        Self {
+            protocol,
+            ip: None,
+            port,
+            tls: false,
+            allow: AllowedPeers::All,
+        }
+    }
+
+    pub fn validate(&self) -> eyre::Result<()> {
+        if self.tls && self.protocol != EndpointProtocol::Ws {
+            eyre::bail!(
+                "listen_endpoints: tls is supported only by ws endpoints, {:?} endpoint on port {} has it set",
+                self.protocol,
+                self.port
+            );
+        }
+        Ok(())
+    }
+
+    pub fn is_udp(&self) -> bool {
+        self.protocol == EndpointProtocol::Quic
+    }
+
+    /// Endpoint address on `ip`
+    pub fn multiaddr(&self, ip: IpAddr) -> Multiaddr {
+        let mut maddr = Multiaddr::from(ip);
+        match self.protocol {
+            EndpointProtocol::Tcp => maddr.push(Protocol::Tcp(self.port)),
+            EndpointProtocol::Ws => {
+                maddr.push(Protocol::Tcp(self.port));
+                if self.tls {
+                    maddr.push(Protocol::Tls);
+                }
+                maddr.push(Protocol::Ws("/".into()));
+            }
+            EndpointProtocol::Quic => {
+                maddr.push(Protocol::Udp(self.port));
+                maddr.push(Protocol::QuicV1);
+            }
+        }
+        maddr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn endpoint_multiaddr() {
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let endpoint: ListenEndpoint = toml::from_str(
+            r#"
+            protocol = "ws"
+            port = 9443
+            tls = true
+            allow = "clients"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(endpoint.allow, AllowedPeers::Clients);
+        assert_eq!(
+            endpoint.multiaddr(ip),
+            "/ip4/10.0.0.1/tcp/9443/tls/ws".parse().unwrap()
+        );
+
+        let quic = ListenEndpoint::new(EndpointProtocol::Quic, 7777);
+        assert_eq!(
+            quic.multiaddr(ip),
+            "/ip4/10.0.0.1/udp/7777/quic-v1".parse().unwrap()
+        );
+        assert!(quic.validate().is_ok());
+
+        let tls_tcp = ListenEndpoint {
+            tls: true,
+            ..ListenEndpoint::tcp(7777)
+        };
+        assert!(tls_tcp.validate().is_err());
+    }
+}
@@ -0,0 +1,74 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::path::PathBuf;
+
+use bytesize::ByteSize;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct LogConfig {
+    /// Logs are written to this file in addition to stdout
+    pub file: Option<LogFileConfig>,
+}
+
+/// Log file is rotated when it grows beyond `max_size` or when the `rotation` period ends,
+/// whichever comes first. Rotated files are named `<path>.<unix millis>`, `.gz` is appended if compressed.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct LogFileConfig {
+    pub path: PathBuf,
+    pub max_size: Option<ByteSize>,
+    #[serde(default)]
+    pub rotation: LogRotation,
+    /// How many rotated files are kept, all of them if not set
+    pub max_files: Option<usize>,
+    /// Whether rotated files are compressed with gzip
+    #[serde(default)]
+    pub compress: bool,
+}
+
+impl LogFileConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_size.is_some_and(|size| size.as_u64() == 0) {
+            return Err("log.file.max_size must be positive".to_string());
+        }
+        if self.max_files == Some(0) {
+            return Err("log.file.max_files must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    #[default]
+    Never,
+    Hourly,
+    Daily,
+}
+
+impl LogRotation {
+    /// Length of the rotation period in seconds, periods start at multiples of it since the unix epoch
+    pub fn period_secs(&self) -> Option<u64> {
+        match self {
+            LogRotation::Never => None,
+            LogRotation::Hourly => Some(60 * 60),
+            LogRotation::Daily => Some(24 * 60 * 60),
+        }
+    }
+}
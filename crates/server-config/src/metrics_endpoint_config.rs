@@ -0,0 +1,59 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::defaults::default_metrics_port;
+
+/// Serve `/metrics` on a separate port, so it can be protected without exposing the main http listener.
+/// The main listener doesn't serve metrics then.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct MetricsEndpointConfig {
+    #[serde(default = "default_metrics_port")]
+    pub port: u16,
+    /// PEM-encoded certificate chain, metrics are served over TLS if set
+    pub certificate_path: Option<PathBuf>,
+    /// PEM-encoded private key of the certificate
+    pub private_key_path: Option<PathBuf>,
+    /// PEM-encoded CA certificates, clients must present a certificate signed by one of them
+    pub client_ca_path: Option<PathBuf>,
+    /// Whether requests must have a bearer token with at least the read-only role
+    #[serde(default)]
+    pub require_token: bool,
+}
+
+impl MetricsEndpointConfig {
+    pub fn validate(&self) -> eyre::Result<()> {
+        match (&self.certificate_path, &self.private_key_path) {
+            (Some(_), Some(_)) | (None, None) => {}
+            _ => eyre::bail!(
+                "metrics_endpoint: certificate_path and private_key_path must be specified together"
+            ),
+        }
+        if self.client_ca_path.is_some() && self.certificate_path.is_none() {
+            eyre::bail!(
+                "metrics_endpoint.client_ca_path requires certificate_path and private_key_path"
+            );
+        }
+        Ok(())
+    }
+
+    pub fn is_tls(&self) -> bool {
+        self.certificate_path.is_some()
+    }
+}
@@ -0,0 +1,87 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::time::Duration;
+
+use libp2p::multiaddr::Protocol;
+use libp2p::Multiaddr;
+use serde::{Deserialize, Serialize};
+
+/// NAT traversal settings, used only with network transport
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct NatConfig {
+    /// Detect whether the node is publicly reachable by asking connected peers to dial it back
+    pub autonat: bool,
+    /// How often reachability is re-checked once it's confidently known
+    #[serde(with = "humantime_serde")]
+    pub autonat_refresh_interval: Duration,
+    /// Dial peers and accept connections via `/p2p-circuit` relay addresses
+    pub relay_client: bool,
+    /// Relays to listen on while the node isn't publicly reachable
+    pub relays: Vec<Multiaddr>,
+    /// Upgrade relayed connections to direct ones via hole punching (DCUtR),
+    /// enabled along with `relay_client` unless set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hole_punching: Option<bool>,
+    /// Relay connections for peers behind NAT. Makes sense only on publicly reachable nodes
+    pub relay_server: bool,
+    /// Map listen ports on the gateway via UPnP IGD and advertise the mapped external addresses
+    pub upnp: bool,
+}
+
+impl Default for NatConfig {
+    fn default() -> Self {
+        Self {
+            autonat: true,
+            autonat_refresh_interval: Duration::from_secs(15 * 60),
+            relay_client: true,
+            relays: vec![],
+            hole_punching: None,
+            relay_server: false,
+            upnp: false,
+        }
+    }
+}
+
+impl NatConfig {
+    pub fn validate(&self) -> eyre::Result<()> {
+        if !self.relay_client && !self.relays.is_empty() {
+            eyre::bail!("nat.relays can't be used with nat.relay_client disabled");
+        }
+        if !self.relay_client && self.hole_punching == Some(true) {
+            eyre::bail!("nat.hole_punching requires nat.relay_client");
+        }
+        for relay in &self.relays {
+            if !matches!(relay.iter().last(), Some(Protocol::P2p(_))) {
+                eyre::bail!("nat.relays: {relay} must end with /p2p/<relay peer id>");
+            }
+        }
+        Ok(())
+    }
+
+    pub fn hole_punching(&self) -> bool {
+        self.hole_punching.unwrap_or(self.relay_client)
+    }
+
+    pub fn autonat_config(&self, allow_local_addresses: bool) -> libp2p::autonat::Config {
+        libp2p::autonat::Config {
+            refresh_interval: self.autonat_refresh_interval,
+            only_global_ips: !allow_local_addresses,
+            ..Default::default()
+        }
+    }
+}
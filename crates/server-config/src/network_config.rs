@@ -15,21 +15,28 @@
  */
 
 use libp2p::{core::Multiaddr, identity::Keypair, PeerId};
-use libp2p_connection_limits::ConnectionLimits;
 use libp2p_metrics::Metrics;
 use std::sync::Arc;
 use std::time::Duration;
 
 use config_utils::to_peer_id;
+use connection_pool::{
+    BandwidthConfig, ConnectionLimits, DialBackoffConfig, EndpointPeers, HandoverAnnouncement,
+    KeepAliveConfig, ParticleSignatureConfig, PeerFilter, RateLimitConfig,
+};
 use particle_protocol::ProtocolConfig;
+use peer_exchange::PeerExchangeConfig;
 use peer_metrics::{ConnectionPoolMetrics, ConnectivityMetrics};
+use peer_reputation::ReputationConfig;
 
-use crate::{BootstrapConfig, KademliaConfig, ResolvedConfig};
+use crate::{BootstrapConfig, DnsConfig, KademliaConfig, NatConfig, PubSubConfig, ResolvedConfig};
 
 pub struct NetworkConfig {
     pub key_pair: Keypair,
     pub local_peer_id: PeerId,
     pub node_version: &'static str,
+    /// Advertised via Identify, see [particle_protocol::protocol_version]
+    pub protocol_version: String,
     pub bootstrap_nodes: Vec<Multiaddr>,
     pub bootstrap: BootstrapConfig,
     pub libp2p_metrics: Option<Arc<Metrics>>,
@@ -41,9 +48,30 @@ pub struct NetworkConfig {
     pub connection_pool_metrics: Option<ConnectionPoolMetrics>,
     pub connection_limits: ConnectionLimits,
     pub connection_idle_timeout: Duration,
+    /// `None` when NAT traversal is not applicable, i.e. for memory transport
+    pub nat: Option<NatConfig>,
+    pub allow_local_addresses: bool,
+    pub reputation: ReputationConfig,
+    pub bandwidth: BandwidthConfig,
+    pub rate_limit: RateLimitConfig,
+    /// Particles received from the network larger than that are refused
+    pub max_particle_size: Option<usize>,
+    pub particle_signatures: ParticleSignatureConfig,
+    pub pubsub: Option<PubSubConfig>,
+    pub peer_filter: PeerFilter,
+    pub dial_backoff: DialBackoffConfig,
+    pub prefer_ipv6: bool,
+    pub dns: DnsConfig,
+    pub keep_alive: KeepAliveConfig,
+    pub peer_exchange: Option<PeerExchangeConfig>,
+    /// Peer classes allowed on listen endpoints
+    pub endpoint_peers: EndpointPeers,
+    /// Announced to connected peers during the grace period of a host key handover
+    pub host_handover: Option<HandoverAnnouncement>,
 }
 
 impl NetworkConfig {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         libp2p_metrics: Option<Arc<Metrics>>,
         connectivity_metrics: Option<ConnectivityMetrics>,
@@ -52,9 +80,11 @@ impl NetworkConfig {
         config: &ResolvedConfig,
         node_version: &'static str,
         connection_limits: ConnectionLimits,
+        peer_filter: PeerFilter,
     ) -> Self {
         Self {
             node_version,
+            protocol_version: particle_protocol::protocol_version(config.network_name.as_deref()),
             libp2p_metrics,
             local_peer_id: to_peer_id(&key_pair),
             key_pair,
@@ -68,6 +98,38 @@ impl NetworkConfig {
             connection_pool_metrics,
             connection_limits,
             connection_idle_timeout: config.node_config.transport_config.connection_idle_timeout,
+            nat: config
+                .transport_config
+                .transport
+                .is_network()
+                .then(|| config.nat.clone()),
+            allow_local_addresses: config.allow_local_addresses,
+            reputation: config.reputation.clone(),
+            bandwidth: config.bandwidth.clone(),
+            rate_limit: config.rate_limit.clone(),
+            max_particle_size: config
+                .particle_limits
+                .max_particle_size
+                .map(|s| s.as_u64() as usize),
+            particle_signatures: config.particle_signatures.clone(),
+            pubsub: config.pubsub.enabled.then(|| config.pubsub.clone()),
+            peer_filter,
+            dial_backoff: config.dial_backoff.clone(),
+            prefer_ipv6: config.transport_config.prefer_ipv6,
+            dns: config.dns.clone(),
+            keep_alive: config.keep_alive.clone(),
+            peer_exchange: config
+                .peer_exchange
+                .enabled
+                .then(|| config.peer_exchange.clone()),
+            endpoint_peers: EndpointPeers::new(config.endpoint_multiaddrs()),
+            host_handover: config.host_key_handover.as_ref().and_then(|handover| {
+                Some(HandoverAnnouncement {
+                    previous_peer_id: handover.previous_peer_id.clone(),
+                    signature: handover.signature.clone(),
+                    retires_at: handover.retires_at()?,
+                })
+            }),
         }
     }
 }
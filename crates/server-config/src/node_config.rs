@@ -1,5 +1,5 @@
 use std::collections::HashMap;
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv6Addr};
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
@@ -16,16 +16,33 @@ use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 use serde_with::DisplayFromStr;
 
+use connection_pool::{
+    BandwidthConfig, DialBackoffConfig, DrainConfig, KeepAliveConfig, ParticleSignatureConfig,
+    PeerFilterConfig, RateLimitConfig,
+};
+use fluence_libp2p::FaultInjector;
 use fluence_libp2p::PeerId;
+use fluence_libp2p::PrivateNetwork;
 use fluence_libp2p::Transport;
 use fs_utils::to_abs_path;
-use particle_protocol::ProtocolConfig;
+use key_encryption::KeyEncryption;
+use particle_protocol::{Compression, ProtocolConfig};
+use peer_exchange::PeerExchangeConfig;
+use peer_reputation::ReputationConfig;
+use types::management::ManagementKey;
 use types::peer_id;
 
 use crate::avm_config::AVMConfig;
 use crate::keys::{decode_key, decode_secret_key, load_key};
+use crate::listen_endpoint_config::{EndpointProtocol, ListenEndpoint};
 use crate::system_services_config::{ServiceKey, SystemServicesConfig};
-use crate::{BootstrapConfig, KademliaConfig};
+use crate::{
+    AdminTlsConfig, BootstrapConfig, BuiltinPolicyConfig, DataRetentionConfig, DnsConfig,
+    GpuDevice, HostKeyHandover, HostKeyRotation, KademliaConfig, KeystoreConfig,
+    MetricsEndpointConfig, NatConfig, ParticleDedupConfig, ParticleLimitsConfig,
+    ParticlePriorityConfig, PrivateNetworkConfig, PubSubConfig, RemoteSignerConfig,
+    ResourcesConfig, VmPoolScalingConfig, WebsocketTlsConfig,
+};
 
 use super::defaults::*;
 
@@ -46,6 +63,11 @@ pub struct UnresolvedNodeConfig {
     #[serde(default)]
     pub builtins_key_pair: Option<KeypairConfig>,
 
+    /// Particles submitted to the http endpoint are signed with this key, the endpoint is disabled if not set
+    #[derivative(Debug = "ignore")]
+    #[serde(default)]
+    pub http_client_key_pair: Option<KeypairConfig>,
+
     #[serde(flatten)]
     pub transport_config: TransportConfig,
 
@@ -58,9 +80,17 @@ pub struct UnresolvedNodeConfig {
     #[serde(default = "default_bootstrap_nodes")]
     pub bootstrap_nodes: Vec<Multiaddr>,
 
+    /// Name of the network, e.g. "testnet" or "mainnet". Advertised via Identify,
+    /// nodes of other networks are disconnected
+    #[serde(default)]
+    pub network_name: Option<String>,
+
     /// External address to advertise via identify protocol
     pub external_address: Option<IpAddr>,
 
+    /// External IPv6 address to advertise along with `external_address` on dual-stack hosts
+    pub external_ipv6_address: Option<Ipv6Addr>,
+
     /// External multiaddresses to advertise; more flexible that IpAddr
     #[serde(default)]
     pub external_multiaddresses: Vec<Multiaddr>,
@@ -74,6 +104,20 @@ pub struct UnresolvedNodeConfig {
     #[serde(flatten)]
     pub http_config: Option<HttpConfig>,
 
+    #[serde(default)]
+    pub websocket_tls: Option<WebsocketTlsConfig>,
+
+    #[serde(default)]
+    pub metrics_endpoint: Option<MetricsEndpointConfig>,
+
+    #[derivative(Debug = "ignore")]
+    #[serde(default)]
+    pub private_network: Option<PrivateNetworkConfig>,
+
+    #[derivative(Debug = "ignore")]
+    #[serde(default)]
+    pub keystore: Option<KeystoreConfig>,
+
     #[serde(default)]
     pub bootstrap_config: BootstrapConfig,
 
@@ -86,6 +130,16 @@ pub struct UnresolvedNodeConfig {
     #[serde(default)]
     pub protocol_config: ProtocolConfig,
 
+    #[serde(default)]
+    pub particle_data_compression: Compression,
+
+    #[serde(default)]
+    pub data_retention: DataRetentionConfig,
+
+    /// Resource limits, they take precedence over the legacy fields below
+    #[serde(default)]
+    pub resources: ResourcesConfig,
+
     /// These are the AquaVM limits that are used by the AquaVM limit check.
     #[derivative(Debug = "ignore")]
     pub avm_config: Option<AVMConfig>,
@@ -94,6 +148,24 @@ pub struct UnresolvedNodeConfig {
     #[serde(default = "default_aquavm_pool_size")]
     pub aquavm_pool_size: usize,
 
+    #[serde(default = "default_aquavm_preinit")]
+    pub aquavm_preinit: bool,
+
+    #[serde(default)]
+    pub aquavm_pool_scaling: VmPoolScalingConfig,
+
+    #[serde(default)]
+    pub particle_priority: ParticlePriorityConfig,
+
+    #[serde(default)]
+    pub particle_dedup: ParticleDedupConfig,
+
+    #[serde(default)]
+    pub particle_limits: ParticleLimitsConfig,
+
+    #[serde(default)]
+    pub particle_signatures: ParticleSignatureConfig,
+
     /// Default heap size in bytes available for a WASM service unless otherwise specified.
     #[serde_as(as = "Option<DisplayFromStr>")]
     #[serde(default)]
@@ -102,6 +174,45 @@ pub struct UnresolvedNodeConfig {
     #[serde(default)]
     pub kademlia: KademliaConfig,
 
+    #[serde(default)]
+    pub nat: NatConfig,
+
+    #[serde(default)]
+    pub reputation: ReputationConfig,
+
+    #[serde(default)]
+    pub bandwidth: BandwidthConfig,
+
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+
+    #[serde(default)]
+    pub pubsub: PubSubConfig,
+
+    #[serde(default)]
+    pub peer_filter: PeerFilterConfig,
+
+    #[serde(default)]
+    pub builtin_policy: BuiltinPolicyConfig,
+
+    #[serde(default)]
+    pub dial_backoff: DialBackoffConfig,
+
+    #[serde(default)]
+    pub dns: DnsConfig,
+
+    #[serde(default)]
+    pub keep_alive: KeepAliveConfig,
+
+    #[serde(default)]
+    pub peer_exchange: PeerExchangeConfig,
+
+    #[serde(default)]
+    pub drain: DrainConfig,
+
+    #[serde(default = "default_control_socket_enabled")]
+    pub control_socket_enabled: bool,
+
     #[serde(default = "default_particle_queue_buffer_size")]
     pub particle_queue_buffer: usize,
 
@@ -135,6 +246,11 @@ pub struct UnresolvedNodeConfig {
     #[serde(default = "default_management_peer_id")]
     pub management_peer_id: PeerId,
 
+    /// Peer ids with a subset of the management peer capabilities.
+    /// The management peer adds more at runtime with `management.set`.
+    #[serde(default)]
+    pub management_keys: Vec<ManagementKey>,
+
     // TODO: leave for now to migrate
     #[serde(default = "default_allowed_binaries")]
     pub allowed_binaries: Vec<String>,
@@ -156,21 +272,61 @@ pub struct UnresolvedNodeConfig {
 impl UnresolvedNodeConfig {
     pub fn resolve(mut self, persistent_base_dir: &Path) -> eyre::Result<NodeConfig> {
         self.load_system_services_envs();
+        self.resources.validate()?;
+        self.apply_resources();
+        if let Some(chain_config) = &self.chain_config {
+            chain_config.validate()?;
+        }
+        self.builtin_policy.validate()?;
 
         let bootstrap_nodes = match self.local {
             Some(true) => vec![],
             _ => self.bootstrap_nodes,
         };
 
-        let root_key_pair = self
-            .root_key_pair
-            .unwrap_or_default()
-            .get_keypair(default_keypair_path(persistent_base_dir))?;
-
-        let builtins_key_pair = self
-            .builtins_key_pair
-            .unwrap_or_default()
-            .get_keypair(default_builtins_keypair_path(persistent_base_dir))?;
+        let keystore = self
+            .keystore
+            .as_ref()
+            .map(KeystoreConfig::load)
+            .transpose()?;
+
+        let root_key_config = self.root_key_pair.unwrap_or_default();
+        let host_key_rotation = root_key_config
+            .key_path(default_keypair_path(persistent_base_dir))
+            .map(|key_path| {
+                HostKeyRotation::new(
+                    key_path,
+                    root_key_config.format.clone(),
+                    persistent_base_dir.join("host_key_rotation.toml"),
+                    keystore.clone(),
+                )
+            });
+        if let Some(rotation) = &host_key_rotation {
+            rotation.activate()?;
+        }
+        let root_key_pair = root_key_config
+            .get_keypair(default_keypair_path(persistent_base_dir), keystore.as_ref())?;
+        let (previous_root_key_pair, host_key_handover) = host_key_rotation
+            .as_ref()
+            .map(HostKeyRotation::load_previous)
+            .transpose()?
+            .flatten()
+            .unzip();
+
+        let builtins_key_pair = self.builtins_key_pair.unwrap_or_default().get_keypair(
+            default_builtins_keypair_path(persistent_base_dir),
+            keystore.as_ref(),
+        )?;
+
+        let http_client_key_pair = self
+            .http_client_key_pair
+            .map(|c| {
+                c.get_keypair(
+                    default_http_client_keypair_path(persistent_base_dir),
+                    keystore.as_ref(),
+                )
+            })
+            .transpose()?;
 
         let allowed_effectors = self
             .effectors
@@ -185,13 +341,68 @@ impl UnresolvedNodeConfig {
 
         let cpus_range = self.cpus_range.unwrap_or_default();
 
+        if let Some(websocket_tls) = &self.websocket_tls {
+            websocket_tls.validate()?;
+        }
+        if let Some(metrics_endpoint) = &self.metrics_endpoint {
+            metrics_endpoint.validate()?;
+        }
+        self.nat.validate()?;
+        self.pubsub.validate()?;
+        self.dial_backoff.validate().map_err(|err| eyre!(err))?;
+        self.rate_limit.validate().map_err(|err| eyre!(err))?;
+        self.listen_config.validate()?;
+        self.dns.validate()?;
+        self.kademlia.validate()?;
+        self.aquavm_pool_scaling.validate(self.aquavm_pool_size)?;
+        self.particle_priority.validate()?;
+        self.particle_dedup.validate()?;
+        self.particle_limits.validate()?;
+        self.data_retention.validate()?;
+        self.system_services.aqua_ipfs.pinning.validate()?;
+        self.system_services.supervisor.validate()?;
+        self.system_services.decider.validate()?;
+        if let Some(name) = &self.network_name {
+            let valid = name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if name.is_empty() || !valid {
+                eyre::bail!(
+                    "network_name must be non-empty and consist of ASCII letters, digits, '-' and '_', got {name:?}"
+                );
+            }
+        }
+
+        let private_network = self
+            .private_network
+            .as_ref()
+            .map(PrivateNetworkConfig::load)
+            .transpose()?;
+        if private_network.is_some() && self.listen_config.webrtc_port.is_some() {
+            eyre::bail!(
+                "private_network can't be used with WebRTC, unset listen_config.webrtc_port"
+            );
+        }
+        if private_network.is_some() && self.listen_config.has_quic() {
+            eyre::bail!("private_network can't be used with QUIC, remove quic listen_endpoints");
+        }
+        if self.listen_config.has_tls() && self.websocket_tls.is_none() {
+            eyre::bail!("listen_endpoints with tls require websocket_tls to be configured");
+        }
+
         let result = NodeConfig {
             system_cpu_count: self.system_cpu_count,
             cpus_range,
             bootstrap_nodes,
+            network_name: self.network_name,
             root_key_pair,
+            previous_root_key_pair,
+            host_key_handover,
+            host_key_rotation,
             builtins_key_pair,
+            http_client_key_pair,
             external_address: self.external_address,
+            external_ipv6_address: self.external_ipv6_address,
             external_multiaddresses: self.external_multiaddresses,
             metrics_config: self.metrics_config,
             health_config: self.health_config,
@@ -199,10 +410,34 @@ impl UnresolvedNodeConfig {
             root_weights: self.root_weights,
             services_envs: self.services_envs,
             protocol_config: self.protocol_config,
+            particle_data_compression: self.particle_data_compression,
+            data_retention: self.data_retention,
             aquavm_pool_size: self.aquavm_pool_size,
+            aquavm_preinit: self.aquavm_preinit,
+            aquavm_pool_scaling: self.aquavm_pool_scaling,
+            particle_priority: self.particle_priority,
+            particle_dedup: self.particle_dedup,
+            particle_limits: self.particle_limits,
+            particle_signatures: self.particle_signatures,
             default_service_memory_limit: self.default_service_memory_limit,
             avm_config: self.avm_config.unwrap_or_default(),
+            max_workers: self.resources.max_workers,
+            max_vault_size: self.resources.max_vault_size,
+            gpus: self.resources.gpus,
             kademlia: self.kademlia,
+            nat: self.nat,
+            reputation: self.reputation,
+            bandwidth: self.bandwidth,
+            rate_limit: self.rate_limit,
+            pubsub: self.pubsub,
+            peer_filter: self.peer_filter,
+            builtin_policy: self.builtin_policy,
+            dial_backoff: self.dial_backoff,
+            dns: self.dns,
+            keep_alive: self.keep_alive,
+            peer_exchange: self.peer_exchange,
+            drain: self.drain,
+            control_socket_enabled: self.control_socket_enabled,
             particle_queue_buffer: self.particle_queue_buffer,
             effects_queue_buffer: self.effects_queue_buffer,
             workers_queue_buffer: self.workers_queue_buffer,
@@ -212,12 +447,18 @@ impl UnresolvedNodeConfig {
             allow_local_addresses: self.allow_local_addresses,
             particle_execution_timeout: self.particle_execution_timeout,
             management_peer_id: self.management_peer_id,
+            management_keys: self.management_keys,
             transport_config: self.transport_config,
+            fault_injector: None,
             listen_config: self.listen_config,
             allowed_effectors,
             dev_mode_config: self.dev_mode,
             system_services: self.system_services,
             http_config: self.http_config,
+            websocket_tls: self.websocket_tls,
+            metrics_endpoint: self.metrics_endpoint,
+            private_network,
+            keystore,
             chain_config: self.chain_config,
             chain_listener_config: self.chain_listener_config,
         };
@@ -225,6 +466,28 @@ impl UnresolvedNodeConfig {
         Ok(result)
     }
 
+    /// Overrides legacy limits with the ones set in `[resources]`
+    fn apply_resources(&mut self) {
+        let resources = &self.resources;
+        if let Some(pool_size) = resources.aquavm_pool_size {
+            self.aquavm_pool_size = pool_size;
+        }
+        if let Some(heap_size) = resources.aquavm_heap_size {
+            self.avm_config
+                .get_or_insert_with(<_>::default)
+                .aquavm_heap_size_limit = Some(heap_size);
+        }
+        if let Some(memory) = resources.service_memory {
+            self.default_service_memory_limit = Some(memory);
+        }
+        if let Some(size) = resources.max_particle_size {
+            self.particle_limits.max_particle_size = Some(size);
+        }
+        if let Some(size) = resources.max_particle_data_size {
+            self.particle_limits.max_data_size = Some(size);
+        }
+    }
+
     // This is a temporary solution to save backward compatibility for some time
     // Couldn't figure out how to use layered configs for this
     // Print warning not to forget to fix it in the future
@@ -329,19 +592,42 @@ pub struct NodeConfig {
     #[derivative(Debug = "ignore")]
     pub root_key_pair: KeyPair,
 
+    /// Root key pair replaced by a host key rotation, kept until the grace period of the handover ends
+    #[derivative(Debug = "ignore")]
+    pub previous_root_key_pair: Option<KeyPair>,
+
+    /// Handover from `previous_root_key_pair`, announced to peers until its grace period ends
+    pub host_key_handover: Option<HostKeyHandover>,
+
+    /// `None` if the root key pair isn't loaded from a file
+    pub host_key_rotation: Option<HostKeyRotation>,
+
     #[derivative(Debug = "ignore")]
     pub builtins_key_pair: KeyPair,
 
+    #[derivative(Debug = "ignore")]
+    pub http_client_key_pair: Option<KeyPair>,
+
     pub transport_config: TransportConfig,
 
+    /// Network faults injected into every connection, set only by tests
+    #[derivative(Debug = "ignore")]
+    pub fault_injector: Option<FaultInjector>,
+
     pub listen_config: ListenConfig,
 
     /// Bootstrap nodes to join to the Fluence network
     pub bootstrap_nodes: Vec<Multiaddr>,
 
+    /// Nodes advertising a different network name are disconnected
+    pub network_name: Option<String>,
+
     /// External address to advertise via identify protocol
     pub external_address: Option<IpAddr>,
 
+    /// External IPv6 address to advertise along with `external_address` on dual-stack hosts
+    pub external_ipv6_address: Option<Ipv6Addr>,
+
     /// External multiaddresses to advertise; more flexible that IpAddr
     pub external_multiaddresses: Vec<Multiaddr>,
 
@@ -357,17 +643,80 @@ pub struct NodeConfig {
 
     pub protocol_config: ProtocolConfig,
 
+    /// Compression of particle data stored on disk
+    pub particle_data_compression: Compression,
+
+    /// Limits of particle data and anomaly dumps kept on disk
+    pub data_retention: DataRetentionConfig,
+
     /// Number of AVMs to create. By default, `num_cpus::get() * 2` is used
     pub aquavm_pool_size: usize,
 
+    /// Create AVMs while the node is starting, so the first particles don't wait for them
+    pub aquavm_preinit: bool,
+
+    /// Pool grows and shrinks on load between these bounds if enabled
+    pub aquavm_pool_scaling: VmPoolScalingConfig,
+
+    /// Order in which particle classes get AquaVMs when there are not enough of them
+    pub particle_priority: ParticlePriorityConfig,
+
+    /// Rejects particles received from the network more than once, even across restarts
+    pub particle_dedup: ParticleDedupConfig,
+
+    /// Size limits of received particles and of particle data after execution
+    pub particle_limits: ParticleLimitsConfig,
+
+    /// Signature checks of particles received from the network
+    pub particle_signatures: ParticleSignatureConfig,
+
     /// Default heap size in bytes available for a WASM service unless otherwise specified.
     pub default_service_memory_limit: Option<bytesize::ByteSize>,
 
     /// These are the AquaVM limits that are used by the AquaVM limit check.
     pub avm_config: AVMConfig,
 
+    /// Workers for new deals aren't created past that number
+    pub max_workers: Option<usize>,
+
+    /// Files put to a particle vault by `vault.put` can't take more than that
+    pub max_vault_size: Option<bytesize::ByteSize>,
+
+    /// GPU devices workers may request
+    pub gpus: Vec<GpuDevice>,
+
     pub kademlia: KademliaConfig,
 
+    pub nat: NatConfig,
+
+    pub reputation: ReputationConfig,
+
+    pub bandwidth: BandwidthConfig,
+
+    /// Ingress rate limits per peer, particles above them are refused with an error particle
+    pub rate_limit: RateLimitConfig,
+
+    pub pubsub: PubSubConfig,
+
+    pub peer_filter: PeerFilterConfig,
+
+    /// Rules from the config, rules added at runtime are persisted separately
+    pub builtin_policy: BuiltinPolicyConfig,
+
+    pub dial_backoff: DialBackoffConfig,
+
+    pub dns: DnsConfig,
+
+    pub keep_alive: KeepAliveConfig,
+
+    pub peer_exchange: PeerExchangeConfig,
+
+    /// Graceful shutdown of network connections
+    pub drain: DrainConfig,
+
+    /// Whether operators can manage the node via the unix socket at `dir_config.control_socket_path`
+    pub control_socket_enabled: bool,
+
     pub particle_queue_buffer: usize,
 
     pub effects_queue_buffer: usize,
@@ -386,6 +735,8 @@ pub struct NodeConfig {
 
     pub management_peer_id: PeerId,
 
+    pub management_keys: Vec<ManagementKey>,
+
     pub allowed_effectors: HashMap<Hash, HashMap<String, String>>,
 
     pub dev_mode_config: DevModeConfig,
@@ -394,6 +745,18 @@ pub struct NodeConfig {
 
     pub http_config: Option<HttpConfig>,
 
+    pub websocket_tls: Option<WebsocketTlsConfig>,
+
+    pub metrics_endpoint: Option<MetricsEndpointConfig>,
+
+    /// Pre-shared keys of the private network, if the node is a part of one
+    #[derivative(Debug = "ignore")]
+    pub private_network: Option<PrivateNetwork>,
+
+    /// Encrypts worker key pairs on disk, see [KeystoreConfig]
+    #[derivative(Debug = "ignore")]
+    pub keystore: Option<KeyEncryption>,
+
     pub chain_config: Option<ChainConfig>,
 
     pub chain_listener_config: Option<ChainListenerConfig>,
@@ -421,18 +784,53 @@ pub struct TransportConfig {
     #[serde(default = "default_max_established_per_peer_limit")]
     pub max_established_per_peer: Option<u32>,
 
+    /// Max number of inbound connections from a single IP address
+    pub max_established_per_ip: Option<u32>,
+
     pub max_established: Option<u32>,
 
     #[serde(with = "humantime_serde")]
     #[serde(default = "default_connection_idle_timeout")]
     pub connection_idle_timeout: Duration,
+
+    /// Dial IPv6 addresses of a dual-stack peer first, alternating with IPv4 ones
+    #[serde(default = "default_prefer_ipv6")]
+    pub prefer_ipv6: bool,
 }
 
-#[derive(Clone, Deserialize, Serialize, Derivative, Copy)]
+#[derive(Clone, Deserialize, Serialize, Derivative)]
 #[derivative(Debug)]
 pub struct HttpConfig {
     #[serde(default = "default_http_port")]
     pub http_port: u16,
+
+    /// Bearer token required by the admin endpoints, i.e. `/debug/pprof/*` and `/particle`.
+    /// It has the admin role, tokens with narrower roles are issued by the management peer.
+    /// Admin endpoints are disabled if there's no token.
+    #[serde(default)]
+    #[derivative(Debug = "ignore")]
+    pub admin_token: Option<String>,
+
+    /// Port of the gRPC management API, it requires `admin_token` or `admin_tls` as well. Disabled if not set.
+    #[serde(default)]
+    pub grpc_port: Option<u16>,
+
+    /// TLS of the http and gRPC listeners with client certificates authorizing admins
+    #[serde(default)]
+    pub admin_tls: Option<AdminTlsConfig>,
+
+    /// Port of a plain http listener serving only `/health`, `/ready`, `/live` and `/metrics`,
+    /// so probes and scrapers don't need a client certificate of `admin_tls`. Disabled if not set.
+    #[serde(default)]
+    pub probe_port: Option<u16>,
+
+    /// Whether `/metrics` requires a token with at least the read-only role, `/config` always requires it
+    #[serde(default)]
+    pub metrics_require_token: bool,
+
+    /// Whether `/graphql` serves the read-only schema of the node state, it requires a read-only token
+    #[serde(default)]
+    pub graphql_enabled: bool,
 }
 
 #[derive(Clone, Deserialize, Serialize, Derivative)]
@@ -460,6 +858,12 @@ pub struct MetricsConfig {
 pub struct HealthConfig {
     #[serde(default = "default_health_check_enabled")]
     pub health_check_enabled: bool,
+    /// Node isn't ready while the disk of the persistent dir has less free space
+    #[serde(default = "default_min_free_disk_space")]
+    pub min_free_disk_space: bytesize::ByteSize,
+    /// Node isn't live if chain listener hasn't received new blocks for that long
+    #[serde(default = "default_chain_block_timeout", with = "humantime_serde")]
+    pub chain_block_timeout: Duration,
 }
 
 #[derive(Clone, Deserialize, Serialize, Derivative)]
@@ -473,9 +877,68 @@ pub struct ListenConfig {
     #[serde(default = "default_listen_ip")]
     pub listen_ip: IpAddr,
 
+    /// Additional IPv6 address to listen on for dual-stack, i.e. `::` along with `0.0.0.0` as `listen_ip`
+    #[serde(default)]
+    pub listen_ipv6: Option<Ipv6Addr>,
+
     /// For ws connections
     #[serde(default = "default_websocket_port")]
     pub websocket_port: u16,
+
+    /// For WebRTC connections from browsers, UDP. WebRTC is disabled if not set
+    #[serde(default)]
+    pub webrtc_port: Option<u16>,
+
+    /// Endpoints to listen on instead of the `tcp_port` and `websocket_port` pair
+    #[serde(default)]
+    pub listen_endpoints: Vec<ListenEndpoint>,
+}
+
+impl ListenConfig {
+    pub fn validate(&self) -> eyre::Result<()> {
+        if self.listen_ipv6.is_some() && self.listen_ip.is_ipv6() {
+            eyre::bail!("listen_config.listen_ipv6 requires listen_ip to be an IPv4 address");
+        }
+        for endpoint in &self.listen_endpoints {
+            endpoint.validate()?;
+        }
+        Ok(())
+    }
+
+    /// Configured endpoints, or tcp and websocket ones on `tcp_port` and `websocket_port` if there are none
+    pub fn endpoints(&self) -> Vec<ListenEndpoint> {
+        if self.listen_endpoints.is_empty() {
+            vec![
+                ListenEndpoint::tcp(self.tcp_port),
+                ListenEndpoint::ws(self.websocket_port),
+            ]
+        } else {
+            self.listen_endpoints.clone()
+        }
+    }
+
+    pub fn has_quic(&self) -> bool {
+        self.listen_endpoints
+            .iter()
+            .any(|e| e.protocol == EndpointProtocol::Quic)
+    }
+
+    pub fn has_tls(&self) -> bool {
+        self.listen_endpoints.iter().any(|e| e.tls)
+    }
+
+    /// Local ip addresses to listen on for `endpoint`
+    pub fn endpoint_ips(&self, endpoint: &ListenEndpoint) -> Vec<IpAddr> {
+        match endpoint.ip {
+            Some(ip) => vec![ip],
+            None => self.listen_ips().collect(),
+        }
+    }
+
+    /// All local ip addresses to listen on
+    pub fn listen_ips(&self) -> impl Iterator<Item = IpAddr> {
+        std::iter::once(self.listen_ip).chain(self.listen_ipv6.map(IpAddr::V6))
+    }
 }
 
 #[derive(Clone, Deserialize, Serialize, Debug, Copy, Eq, Hash, Ord, PartialEq, PartialOrd)]
@@ -528,7 +991,23 @@ impl Default for KeypairConfig {
 }
 
 impl KeypairConfig {
-    pub fn get_keypair(self, default: PathOrValue) -> Result<KeyPair, eyre::Report> {
+    /// Path of the key file, `None` if the key is set by value
+    pub fn key_path(&self, default: PathOrValue) -> Option<PathBuf> {
+        if self.secret_key.is_some() {
+            return None;
+        }
+        match self.keypair.clone().unwrap_or(default) {
+            PathOrValue::Path { path } => Some(to_abs_path(path)),
+            PathOrValue::Value { .. } => None,
+        }
+    }
+
+    /// Keys loaded from files are encrypted at rest with `encryption` if it's set
+    pub fn get_keypair(
+        self,
+        default: PathOrValue,
+        encryption: Option<&KeyEncryption>,
+    ) -> Result<KeyPair, eyre::Report> {
         use crate::node_config::PathOrValue::{Path, Value};
 
         debug_assert!(
@@ -549,8 +1028,13 @@ impl KeypairConfig {
         match self.keypair.unwrap_or(default) {
             Path { path } => {
                 let path = to_abs_path(path);
-                load_key(path.clone(), self.format.clone(), self.generate_on_absence)
-                    .map_err(|e| eyre!("Failed to load secret key from {:?}: {}", path, e))
+                load_key(
+                    path.clone(),
+                    self.format.clone(),
+                    self.generate_on_absence,
+                    encryption,
+                )
+                .map_err(|e| eyre!("Failed to load secret key from {:?}: {}", path, e))
             }
             Value { value } => decode_key(value, self.format),
         }
@@ -566,7 +1050,26 @@ pub struct ChainConfig {
     pub cc_contract_address: String,
     pub market_contract_address: String,
     pub network_id: u64,
-    pub wallet_key: PrivateKey,
+    /// Signs transactions in memory, required unless `remote_signer` is set
+    #[serde(default)]
+    pub wallet_key: Option<PrivateKey>,
+    /// Signs transactions with a key kept in an HSM or a KMS instead of `wallet_key`
+    #[serde(default)]
+    pub remote_signer: Option<RemoteSignerConfig>,
+}
+
+impl ChainConfig {
+    pub fn validate(&self) -> eyre::Result<()> {
+        match (&self.wallet_key, &self.remote_signer) {
+            (Some(_), Some(_)) => {
+                eyre::bail!("chain_config: wallet_key and remote_signer are mutually exclusive")
+            }
+            (None, None) => {
+                eyre::bail!("chain_config: either wallet_key or remote_signer must be set")
+            }
+            _ => Ok(()),
+        }
+    }
 }
 
 #[derive(Clone, Deserialize, Serialize, Derivative)]
@@ -578,6 +1081,11 @@ pub struct ChainListenerConfig {
     #[serde(default = "default_proof_poll_period")]
     #[serde(with = "humantime_serde")]
     pub proof_poll_period: Duration,
+    /// How long a core freed by an ended deal is kept for deals before capacity commitment
+    /// proves on it again, so a deal matched shortly after doesn't reallocate CCP cores twice
+    #[serde(default = "default_deal_core_hold")]
+    #[serde(with = "humantime_serde")]
+    pub deal_core_hold: Duration,
 }
 
 /// Name of the effector module
@@ -0,0 +1,54 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Deduplication of particles received from the network. Particles are remembered until
+/// their TTL passes, and the cache is persisted so replays are rejected after a restart too.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ParticleDedupConfig {
+    pub enabled: bool,
+    /// Maximum number of remembered particles, the ones closest to expiry are forgotten first
+    pub max_entries: usize,
+    /// How often the cache is written to disk. It's also written on shutdown.
+    #[serde(with = "humantime_serde")]
+    pub persist_interval: Duration,
+}
+
+impl Default for ParticleDedupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_entries: 100_000,
+            persist_interval: Duration::from_secs(10),
+        }
+    }
+}
+
+impl ParticleDedupConfig {
+    pub fn validate(&self) -> eyre::Result<()> {
+        if self.enabled && self.max_entries == 0 {
+            eyre::bail!("particle_dedup.max_entries must be positive");
+        }
+        if self.persist_interval.is_zero() {
+            eyre::bail!("particle_dedup.persist_interval must be positive");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,42 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use bytesize::ByteSize;
+use serde::{Deserialize, Serialize};
+
+/// Size limits of particles. Particles violating them are dropped, and their origin
+/// gets an error particle telling why. Unset limits are not enforced.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ParticleLimitsConfig {
+    /// Particles received from the network with larger data and script together are refused
+    pub max_particle_size: Option<ByteSize>,
+    /// Execution of particles stops once their data grows larger than that,
+    /// the data isn't stored and calls and next peers it requested are dropped
+    pub max_data_size: Option<ByteSize>,
+}
+
+impl ParticleLimitsConfig {
+    pub fn validate(&self) -> eyre::Result<()> {
+        if self.max_particle_size.is_some_and(|s| s.as_u64() == 0) {
+            eyre::bail!("particle_limits.max_particle_size must be positive");
+        }
+        if self.max_data_size.is_some_and(|s| s.as_u64() == 0) {
+            eyre::bail!("particle_limits.max_data_size must be positive");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use types::ParticleClass;
+
+/// Priority of particle classes in the execution queue. When there are not enough AquaVMs,
+/// particles of higher priority classes are executed first.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ParticlePriorityConfig {
+    pub enabled: bool,
+    /// Classes from the highest priority to the lowest, missing classes are executed last
+    pub order: Vec<ParticleClass>,
+}
+
+impl Default for ParticlePriorityConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            order: ParticleClass::DEFAULT_ORDER.to_vec(),
+        }
+    }
+}
+
+impl ParticlePriorityConfig {
+    pub fn validate(&self) -> eyre::Result<()> {
+        let mut seen = HashSet::new();
+        for class in &self.order {
+            if !seen.insert(class) {
+                eyre::bail!("particle_priority.order contains {class:?} more than once");
+            }
+        }
+        Ok(())
+    }
+}
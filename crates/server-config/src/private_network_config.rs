@@ -0,0 +1,63 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use eyre::WrapErr;
+use fluence_libp2p::{PreSharedKey, PrivateNetwork};
+use serde::{Deserialize, Serialize};
+
+/// Isolated network: only nodes configured with the same pre-shared key can connect to each other.
+/// Keys are either 64 hex characters or go-libp2p `swarm.key` file contents.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PrivateNetworkConfig {
+    pub key: Option<String>,
+    pub key_path: Option<PathBuf>,
+    /// Key being rotated out, still accepted for inbound connections until `previous_key_valid_until`
+    pub previous_key: Option<String>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    pub previous_key_valid_until: Option<SystemTime>,
+}
+
+impl PrivateNetworkConfig {
+    pub fn load(&self) -> eyre::Result<PrivateNetwork> {
+        let key = match (&self.key, &self.key_path) {
+            (Some(key), None) => key.clone(),
+            (None, Some(path)) => std::fs::read_to_string(path).wrap_err(format!(
+                "private_network: failed to read key from {}",
+                path.display()
+            ))?,
+            _ => eyre::bail!("private_network: exactly one of key or key_path must be specified"),
+        };
+        let key: PreSharedKey = key.parse().wrap_err("private_network.key is invalid")?;
+        let network = PrivateNetwork::new(key);
+
+        match (&self.previous_key, self.previous_key_valid_until) {
+            (Some(previous_key), Some(valid_until)) => {
+                let previous_key: PreSharedKey = previous_key
+                    .parse()
+                    .wrap_err("private_network.previous_key is invalid")?;
+                Ok(network.with_previous_key(previous_key, valid_until))
+            }
+            (None, None) => Ok(network),
+            _ => eyre::bail!(
+                "private_network: previous_key and previous_key_valid_until must be specified together"
+            ),
+        }
+    }
+}
@@ -0,0 +1,61 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::time::Duration;
+
+use bytesize::ByteSize;
+use libp2p::gossipsub::{Config as GossipsubConfig, ConfigBuilder, ValidationMode};
+use serde::{Deserialize, Serialize};
+
+/// Gossipsub settings used by `pubsub.*` builtins, see `libp2p::gossipsub::Config`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PubSubConfig {
+    pub enabled: bool,
+    /// How often the mesh of each topic is maintained and gossip is emitted
+    #[serde(with = "humantime_serde")]
+    pub heartbeat_interval: Duration,
+    /// Max size of a published message
+    pub max_transmit_size: ByteSize,
+    /// Messages of a topic beyond that number per second don't trigger subscribed spells
+    pub topic_triggers_per_sec: u32,
+}
+
+impl Default for PubSubConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            heartbeat_interval: Duration::from_secs(1),
+            max_transmit_size: ByteSize::kib(64),
+            topic_triggers_per_sec: 10,
+        }
+    }
+}
+
+impl PubSubConfig {
+    pub fn validate(&self) -> eyre::Result<()> {
+        self.as_libp2p().map(|_| ())
+    }
+
+    pub fn as_libp2p(&self) -> eyre::Result<GossipsubConfig> {
+        ConfigBuilder::default()
+            .heartbeat_interval(self.heartbeat_interval)
+            .max_transmit_size(self.max_transmit_size.as_u64() as usize)
+            .validation_mode(ValidationMode::Strict)
+            .build()
+            .map_err(|err| eyre::eyre!("invalid pubsub config: {err}"))
+    }
+}
@@ -14,21 +14,29 @@
  * limitations under the License.
  */
 
+use std::collections::{BTreeMap, HashMap};
 use std::ffi::OsString;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::ops::{Deref, DerefMut};
 use std::path::PathBuf;
 
 use clap::{Args, Command, FromArgMatches};
-use config::{Config, Environment, File, FileFormat, FileSourceFile};
+use config::builder::DefaultState;
+use config::{Config, ConfigBuilder, Environment, File, FileFormat, FileSourceString};
+use connection_pool::AllowedPeers;
 use libp2p::core::{multiaddr::Protocol, Multiaddr};
 use serde::{Deserialize, Serialize};
 use url::Url;
 
 use crate::args;
-use crate::args::DerivedArgs;
+use crate::args::{ConfigCommand, DerivedArgs};
+use crate::config_inspect::ConfigProvenance;
+use crate::config_migration::{migrate_file, migrated_file};
 use crate::dir_config::{ResolvedDirConfig, UnresolvedDirConfig};
+use crate::env_overrides::{env_source, CONFIG_PATH_ENVS, ENV_PREFIXES};
+use crate::log_config::LogConfig;
 use crate::node_config::{NodeConfig, UnresolvedNodeConfig};
+use crate::secret_refs::resolve_secret_refs;
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct UnresolvedConfig {
@@ -40,13 +48,42 @@ pub struct UnresolvedConfig {
 
     pub tracing: Option<TracingConfig>,
 
+    pub log: Option<LogConfig>,
+
+    /// Log directives in `RUST_LOG` format applied on top of `RUST_LOG`, reloadable at runtime
+    pub log_filter: Option<String>,
+
     pub no_banner: Option<bool>,
 
     pub print_config: Option<bool>,
+
+    /// Set when the node is run as `nox config validate` or `nox config explain`
+    #[serde(skip)]
+    pub command: Option<ConfigCommand>,
+
+    /// Secret references resolved on loading by field path, see [crate::redact_config]
+    #[serde(skip)]
+    pub secret_refs: HashMap<String, String>,
 }
 
 impl UnresolvedConfig {
     pub fn resolve(self) -> eyre::Result<ResolvedConfig> {
+        if let Some(TracingConfig::Otlp {
+            sample_ratio: Some(ratio),
+            ..
+        }) = self.tracing
+        {
+            if !(0.0..=1.0).contains(&ratio) {
+                return Err(eyre::eyre!(
+                    "tracing.sample_ratio must be between 0 and 1, got {ratio}"
+                ));
+            }
+        }
+
+        if let Some(file) = self.log.as_ref().and_then(|log| log.file.as_ref()) {
+            file.validate().map_err(|err| eyre::eyre!(err))?;
+        }
+
         let dir_config = self.dir_config.resolve()?;
         let node_config = self.node_config.resolve(&dir_config.persistent_base_dir)?;
 
@@ -67,10 +104,25 @@ pub enum TracingConfig {
     #[serde(rename = "otlp")]
     Otlp {
         endpoint: Url,
+        /// Fraction of root traces to export, spans of sampled remote parents are always exported
         sample_ratio: Option<f64>,
+        #[serde(default)]
+        protocol: OtlpProtocol,
+        /// Resource attributes added to the `service.name`, `service.version` and `peer_id` ones
+        #[serde(default)]
+        resource_attributes: BTreeMap<String, String>,
     },
 }
 
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OtlpProtocol {
+    #[default]
+    Grpc,
+    /// Protobuf over HTTP, the endpoint is a base url, `/v1/traces` is appended to it
+    Http,
+}
+
 #[derive(Clone, Debug)]
 pub struct ResolvedConfig {
     pub dir_config: ResolvedDirConfig,
@@ -92,25 +144,39 @@ impl DerefMut for ResolvedConfig {
 }
 
 impl ResolvedConfig {
+    /// Configured external ip addresses, IPv4 and IPv6 ones on dual-stack hosts
+    fn external_ips(&self) -> impl Iterator<Item = IpAddr> {
+        self.external_address
+            .into_iter()
+            .chain(self.external_ipv6_address.map(IpAddr::V6))
+    }
+
     pub fn external_addresses(&self) -> Vec<Multiaddr> {
-        let mut addrs = if let Some(external_address) = self.external_address {
-            let external_tcp = {
-                let mut maddr = Multiaddr::from(external_address);
-                maddr.push(Protocol::Tcp(self.listen_config.tcp_port));
-                maddr
-            };
+        let mut addrs = vec![];
+        // endpoints bound to an explicit ip are reachable only there
+        let endpoints: Vec<_> = self
+            .listen_config
+            .endpoints()
+            .into_iter()
+            .filter(|e| e.ip.is_none())
+            .collect();
+        for external_address in self.external_ips() {
+            for endpoint in &endpoints {
+                addrs.push(endpoint.multiaddr(external_address));
+            }
+        }
 
-            let external_ws = {
-                let mut maddr = Multiaddr::from(external_address);
-                maddr.push(Protocol::Tcp(self.listen_config.websocket_port));
+        if let Some(websocket_tls) = &self.websocket_tls {
+            let domain = websocket_tls.acme.as_ref().and_then(|a| a.domains.first());
+            if let Some(domain) = domain {
+                let mut maddr = Multiaddr::empty();
+                maddr.push(Protocol::Dns(domain.into()));
+                maddr.push(Protocol::Tcp(websocket_tls.port));
+                maddr.push(Protocol::Tls);
                 maddr.push(Protocol::Ws("/".into()));
-                maddr
-            };
-
-            vec![external_tcp, external_ws]
-        } else {
-            vec![]
-        };
+                addrs.push(maddr);
+            }
+        }
 
         addrs.extend(self.external_multiaddresses.iter().cloned());
 
@@ -119,23 +185,86 @@ impl ResolvedConfig {
 
     pub fn http_listen_addr(&self) -> Option<SocketAddr> {
         self.http_config
+            .as_ref()
             .map(|config| SocketAddr::new(self.listen_config.listen_ip, config.http_port))
     }
 
+    pub fn probe_listen_addr(&self) -> Option<SocketAddr> {
+        let port = self.http_config.as_ref()?.probe_port?;
+        Some(SocketAddr::new(self.listen_config.listen_ip, port))
+    }
+
+    pub fn grpc_listen_addr(&self) -> Option<SocketAddr> {
+        let port = self.http_config.as_ref()?.grpc_port?;
+        Some(SocketAddr::new(self.listen_config.listen_ip, port))
+    }
+
+    pub fn metrics_listen_addr(&self) -> Option<SocketAddr> {
+        let port = self.metrics_endpoint.as_ref()?.port;
+        Some(SocketAddr::new(self.listen_config.listen_ip, port))
+    }
+
+    /// Listen addresses of endpoints along with peers allowed to connect to them
+    pub fn endpoint_multiaddrs(&self) -> Vec<(Multiaddr, AllowedPeers)> {
+        let config = &self.listen_config;
+        config
+            .endpoints()
+            .iter()
+            .flat_map(|endpoint| {
+                config
+                    .endpoint_ips(endpoint)
+                    .into_iter()
+                    .map(move |ip| (endpoint.multiaddr(ip), endpoint.allow))
+            })
+            .collect()
+    }
+
     pub fn listen_multiaddrs(&self) -> Vec<Multiaddr> {
         let config = &self.listen_config;
 
-        let mut tcp = Multiaddr::from(config.listen_ip);
-        tcp.push(Protocol::Tcp(config.tcp_port));
+        let mut addrs: Vec<_> = self
+            .endpoint_multiaddrs()
+            .into_iter()
+            .map(|(addr, _)| addr)
+            .collect();
+        for listen_ip in config.listen_ips() {
+            if let Some(websocket_tls) = &self.websocket_tls {
+                let mut wss = Multiaddr::from(listen_ip);
+                wss.push(Protocol::Tcp(websocket_tls.port));
+                wss.push(Protocol::Tls);
+                wss.push(Protocol::Ws("/".into()));
+                addrs.push(wss);
+            }
+            if let Some(webrtc_port) = config.webrtc_port {
+                let mut webrtc = Multiaddr::from(listen_ip);
+                webrtc.push(Protocol::Udp(webrtc_port));
+                webrtc.push(Protocol::WebRTCDirect);
+                addrs.push(webrtc);
+            }
+        }
+
+        addrs
+    }
 
-        let mut ws = Multiaddr::from(config.listen_ip);
-        ws.push(Protocol::Tcp(config.websocket_port));
-        ws.push(Protocol::Ws("/".into()));
+    /// External `/webrtc-direct` addresses, `certhash` must be the hash of the node's WebRTC certificate
+    pub fn webrtc_external_addresses(&self, certhash: Protocol<'static>) -> Vec<Multiaddr> {
+        let Some(webrtc_port) = self.listen_config.webrtc_port else {
+            return vec![];
+        };
 
-        vec![tcp, ws]
+        self.external_ips()
+            .map(|external_address| {
+                let mut maddr = Multiaddr::from(external_address);
+                maddr.push(Protocol::Udp(webrtc_port));
+                maddr.push(Protocol::WebRTCDirect);
+                maddr.push(certhash.clone());
+                maddr
+            })
+            .collect()
     }
 }
 
+#[derive(Clone)]
 pub struct ConfigData {
     pub binary_name: String,
     pub version: String,
@@ -146,11 +275,16 @@ pub struct ConfigData {
 /// Hierarchically loads the configuration using args and envs.
 /// The source order is:
 ///  - Load and parse Config.toml from cwd, if exists
-///  - Load and parse files provided by FLUENCE_CONFIG env var
+///  - Load and parse files provided by FLUENCE_CONFIG and NOX_CONFIG env vars
 ///  - Load and parse files provided by --config arg
-///  - Load config values from env vars
+///    Files can be in TOML, YAML (.yaml, .yml) or JSON (.json). Files of older config versions
+///    are migrated in memory, or on disk if --migrate-config is passed
+///  - Load config values from FLUENCE_ and NOX_ prefixed env vars, any field can be set
+///    this way, e.g. NOX_SYSTEM_SERVICES__DECIDER__WORKER_PERIOD_SEC
 ///  - Load config values from args (throw error on conflicts with env vars)
 /// On each stage the values override the previous ones.
+/// Then string values like `${file:path}`, `${env:VAR}` or `${exec:command}` are replaced with the secrets they refer to,
+/// `${exec:command}` is only allowed in files.
 ///
 /// # Arguments
 ///
@@ -169,61 +303,80 @@ pub fn load_config_with_args(
     raw_args: Vec<OsString>,
     data: Option<ConfigData>,
 ) -> eyre::Result<UnresolvedConfig> {
-    let arg_source = process_args(raw_args, data)?;
+    let sources = config_sources(raw_args, data)?;
+    let command = sources.args.command();
+    let config = sources.builder(&ConfigProvenance::OVERRIDES).build()?;
+    let exec_denied = sources.builder(&ConfigProvenance::EXEC_DENIED).build()?;
+    let (config, secret_refs) = resolve_secret_refs(config, &exec_denied, true)?;
 
-    let arg_config_sources: Vec<File<FileSourceFile, FileFormat>> = arg_source
-        .configs
-        .iter()
-        .flat_map(|paths| {
-            paths
-                .iter()
-                .map(|path| File::from(path.clone()).format(FileFormat::Toml))
-                .collect::<Vec<File<FileSourceFile, FileFormat>>>()
-        })
-        .collect();
+    let mut config: UnresolvedConfig = config.try_deserialize()?;
+    config.command = command;
+    config.secret_refs = secret_refs;
 
-    let env_source = Environment::with_prefix("FLUENCE")
-        .try_parsing(true)
-        .prefix_separator("_")
-        .separator("__")
-        .list_separator(",")
-        .with_list_parse_key("allowed_binaries")
-        .with_list_parse_key("external_multiaddresses")
-        .with_list_parse_key("bootstrap_nodes")
-        .with_list_parse_key("listen_config.listen_multiaddrs")
-        .with_list_parse_key("system_services.enable");
-
-    let env_config_sources: Vec<File<FileSourceFile, FileFormat>> =
-        std::env::var_os("FLUENCE_CONFIG")
-            .and_then(|str| str.into_string().ok())
-            .map(|str| {
-                str.trim()
-                    .split(',')
-                    .map(PathBuf::from)
-                    .map(|path| File::from(path.clone()).format(FileFormat::Toml))
-                    .collect()
-            })
-            .unwrap_or_default();
+    Ok(config)
+}
 
-    let mut config_builder = Config::builder().add_source(
-        File::with_name("Config.toml")
-            .required(false)
-            .format(FileFormat::Toml),
-    );
+/// Config sources grouped by provenance
+pub(crate) struct ConfigSources {
+    files: Vec<File<FileSourceString, FileFormat>>,
+    envs: Vec<Environment>,
+    args: DerivedArgs,
+}
 
-    for source in env_config_sources {
-        config_builder = config_builder.add_source(source)
+impl ConfigSources {
+    /// Builder with sources of the given provenances, in the order they override each other
+    pub(crate) fn builder(&self, provenances: &[ConfigProvenance]) -> ConfigBuilder<DefaultState> {
+        let mut builder = Config::builder();
+        for provenance in provenances {
+            match provenance {
+                ConfigProvenance::Default => {}
+                ConfigProvenance::File => builder = builder.add_source(self.files.clone()),
+                ConfigProvenance::Env => builder = builder.add_source(self.envs.clone()),
+                ConfigProvenance::Args => builder = builder.add_source(self.args.clone()),
+            }
+        }
+        builder
     }
+}
+
+pub(crate) fn config_sources(
+    raw_args: Vec<OsString>,
+    data: Option<ConfigData>,
+) -> eyre::Result<ConfigSources> {
+    let arg_source = process_args(raw_args, data)?;
+
+    let arg_config_paths = arg_source.configs.iter().flatten().cloned();
+
+    let env_config_paths = CONFIG_PATH_ENVS
+        .iter()
+        .filter_map(|env| std::env::var_os(env))
+        .filter_map(|str| str.into_string().ok())
+        .flat_map(|str| str.trim().split(',').map(PathBuf::from).collect::<Vec<_>>());
+
+    // (path, required)
+    let paths: Vec<(PathBuf, bool)> = std::iter::once((PathBuf::from("Config.toml"), false))
+        .chain(env_config_paths.map(|path| (path, true)))
+        .chain(arg_config_paths.map(|path| (path, true)))
+        .collect();
 
-    for source in arg_config_sources {
-        config_builder = config_builder.add_source(source)
+    if arg_source.migrate_config {
+        for (path, required) in &paths {
+            if *required || path.exists() {
+                migrate_file(path)?;
+            }
+        }
     }
-    config_builder = config_builder.add_source(env_source).add_source(arg_source);
-    let config = config_builder.build()?;
 
-    let config: UnresolvedConfig = config.try_deserialize()?;
+    let mut files = vec![];
+    for (path, required) in paths {
+        files.extend(migrated_file(&path, required)?);
+    }
 
-    Ok(config)
+    Ok(ConfigSources {
+        files,
+        envs: ENV_PREFIXES.into_iter().map(env_source).collect(),
+        args: arg_source,
+    })
 }
 
 fn process_args(raw_args: Vec<OsString>, data: Option<ConfigData>) -> eyre::Result<DerivedArgs> {
@@ -251,7 +404,9 @@ mod tests {
 
     use base64::{engine::general_purpose::STANDARD as base64, Engine};
     use fluence_keypair::KeyPair;
+    use particle_protocol::CompressionAlgorithm;
     use tempfile::{tempdir, NamedTempFile};
+    use types::ParticleClass;
 
     use super::*;
 
@@ -535,6 +690,65 @@ mod tests {
         });
     }
 
+    #[test]
+    fn load_yaml_and_json_files() {
+        let mut yaml = tempfile::Builder::new()
+            .suffix(".yaml")
+            .tempfile()
+            .expect("Could not create temp file");
+        write!(
+            yaml,
+            r#"
+tcp_port: 7778
+allowed_binaries:
+  - /bin/sh
+"#
+        )
+        .expect("Could not write in file");
+
+        let mut json = tempfile::Builder::new()
+            .suffix(".json")
+            .tempfile()
+            .expect("Could not create temp file");
+        write!(json, r#"{{"websocket_port": 9998}}"#).expect("Could not write in file");
+
+        let paths = format!("{},{}", yaml.path().display(), json.path().display());
+        temp_env::with_var("FLUENCE_CONFIG", Some(paths), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            assert_eq!(config.node_config.allowed_binaries, vec!["/bin/sh"]);
+            let config = config.resolve().expect("Could not resolve config");
+            assert_eq!(config.listen_config.tcp_port, 7778);
+            assert_eq!(config.listen_config.websocket_port, 9998);
+        });
+    }
+
+    #[test]
+    fn load_old_config_version() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [[decider]]
+            worker_period_sec = 600
+
+            [[aqua_ipfs]]
+            ipfs_binary_path = "/usr/local/bin/ipfs"
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let system_services = config.node_config.system_services;
+            assert_eq!(system_services.decider.worker_period_sec, 600);
+            assert_eq!(
+                system_services.aqua_ipfs.ipfs_binary_path,
+                "/usr/local/bin/ipfs"
+            );
+        });
+    }
+
     #[test]
     fn load_allowed_binaries_with_file() {
         let mut file = NamedTempFile::new().expect("Could not create temp file");
@@ -599,7 +813,9 @@ mod tests {
                 config.tracing,
                 Some(TracingConfig::Otlp {
                     endpoint: Url::parse("grpc://10.10.10.10:122").unwrap(),
-                    sample_ratio: Some(0.1)
+                    sample_ratio: Some(0.1),
+                    protocol: OtlpProtocol::Grpc,
+                    resource_attributes: BTreeMap::new(),
                 })
             );
         });
@@ -626,7 +842,9 @@ mod tests {
                     config.tracing,
                     Some(TracingConfig::Otlp {
                         endpoint: Url::parse("grpc://10.10.10.10:122").unwrap(),
-                        sample_ratio: None
+                        sample_ratio: None,
+                        protocol: OtlpProtocol::Grpc,
+                        resource_attributes: BTreeMap::new(),
                     })
                 );
             },
@@ -667,9 +885,104 @@ mod tests {
                 config.tracing,
                 Some(TracingConfig::Otlp {
                     endpoint: Url::parse("grpc://10.10.10.10:122").unwrap(),
-                    sample_ratio: None
+                    sample_ratio: None,
+                    protocol: OtlpProtocol::Grpc,
+                    resource_attributes: BTreeMap::new(),
+                })
+            );
+        });
+    }
+
+    #[test]
+    fn load_log_file() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [log]
+            format = "logfmt"
+
+            [log.file]
+            path = "/var/log/nox/nox.log"
+            max_size = "100 MiB"
+            rotation = "daily"
+            max_files = 7
+            compress = true
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let log_file = config.log.clone().and_then(|log| log.file).unwrap();
+            assert_eq!(log_file.path, PathBuf::from("/var/log/nox/nox.log"));
+            assert_eq!(log_file.max_size, Some(bytesize::ByteSize::mib(100)));
+            assert_eq!(log_file.rotation, crate::LogRotation::Daily);
+            assert_eq!(log_file.max_files, Some(7));
+            assert!(log_file.compress);
+            config.resolve().expect("Could not resolve config");
+        });
+
+        temp_env::with_vars(
+            [
+                ("FLUENCE_LOG__FILE__PATH", Some("nox.log")),
+                ("FLUENCE_LOG__FILE__MAX_FILES", Some("0")),
+            ],
+            || {
+                let config = load_config_with_args(vec![], None).expect("Could not load config");
+                assert!(config.resolve().is_err());
+            },
+        );
+    }
+
+    #[test]
+    fn load_tracing_otlp_http() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [tracing]
+            type = "otlp"
+            endpoint = "http://10.10.10.10:4318"
+            protocol = "http"
+
+            [tracing.resource_attributes]
+            "deployment.environment" = "testnet"
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let args = vec![
+                OsString::from("nox"),
+                OsString::from("--tracing-sample-ratio"),
+                OsString::from("0.5"),
+            ];
+            let config = load_config_with_args(args, None).expect("Could not load config");
+            assert_eq!(
+                config.tracing,
+                Some(TracingConfig::Otlp {
+                    endpoint: Url::parse("http://10.10.10.10:4318").unwrap(),
+                    sample_ratio: Some(0.5),
+                    protocol: OtlpProtocol::Http,
+                    resource_attributes: BTreeMap::from([(
+                        "deployment.environment".to_string(),
+                        "testnet".to_string()
+                    )]),
                 })
             );
+
+            let args = vec![
+                OsString::from("nox"),
+                OsString::from("--tracing-sample-ratio"),
+                OsString::from("2"),
+            ];
+            let config = load_config_with_args(args, None).expect("Could not load config");
+            assert!(config.resolve().is_err());
         });
     }
 
@@ -678,12 +991,42 @@ mod tests {
         temp_env::with_vars([("FLUENCE_HTTP_PORT", Some("1234"))], || {
             let config = load_config_with_args(vec![], None).expect("Could not load config");
             assert_eq!(
-                config.node_config.http_config.map(|x| x.http_port),
+                config.node_config.http_config.as_ref().map(|x| x.http_port),
                 Some(1234)
             );
         });
     }
 
+    #[test]
+    fn load_nested_fields_with_nox_env() {
+        temp_env::with_vars(
+            [
+                ("FLUENCE_HTTP_PORT", Some("1234")),
+                ("NOX_HTTP_PORT", Some("4321")),
+                (
+                    "NOX_SYSTEM_SERVICES__DECIDER__WORKER_PERIOD_SEC",
+                    Some("42"),
+                ),
+                (
+                    "NOX_NAT__RELAYS",
+                    Some("/ip4/1.1.1.1/tcp/7777,/ip4/2.2.2.2/tcp/7777"),
+                ),
+            ],
+            || {
+                let config = load_config_with_args(vec![], None).expect("Could not load config");
+                assert_eq!(
+                    config.node_config.http_config.as_ref().map(|x| x.http_port),
+                    Some(4321)
+                );
+                assert_eq!(
+                    config.node_config.system_services.decider.worker_period_sec,
+                    42
+                );
+                assert_eq!(config.node_config.nat.relays.len(), 2);
+            },
+        );
+    }
+
     #[test]
     fn load_http_port_with_args() {
         temp_env::with_vars([("FLUENCE_HTTP_PORT", Some("1234"))], || {
@@ -694,35 +1037,43 @@ mod tests {
             ];
             let config = load_config_with_args(args, None).expect("Could not load config");
             assert_eq!(
-                config.node_config.http_config.map(|x| x.http_port),
+                config.node_config.http_config.as_ref().map(|x| x.http_port),
                 Some(1001)
             );
         });
     }
 
     #[test]
-    fn load_env_upgrade_timeout() {
-        temp_env::with_vars(
-            [("FLUENCE_PROTOCOL_CONFIG__UPGRADE_TIMEOUT", Some("60s"))],
-            || {
-                let args = vec![];
-                let config = load_config_with_args(args, None).expect("Could not load config");
-                assert_eq!(
-                    config.node_config.protocol_config.upgrade_timeout,
-                    Duration::from_secs(60)
-                );
-            },
-        );
+    fn load_webrtc_port_with_args() {
+        let args = vec![
+            OsString::from("nox"),
+            OsString::from("--webrtc-port"),
+            OsString::from("9998"),
+        ];
+        let config = load_config_with_args(args, None).expect("Could not load config");
+        let config = config.resolve().unwrap();
+        assert_eq!(config.listen_config.webrtc_port, Some(9998));
+
+        let webrtc: Multiaddr = format!(
+            "/ip4/{}/udp/9998/webrtc-direct",
+            config.listen_config.listen_ip
+        )
+        .parse()
+        .unwrap();
+        assert!(config.listen_multiaddrs().contains(&webrtc));
     }
 
     #[test]
-    fn load_file_upgrade_timeout() {
+    fn load_websocket_tls_acme() {
         let mut file = NamedTempFile::new().expect("Could not create temp file");
         write!(
             file,
             r#"
-            [protocol_config]
-            upgrade_timeout = "60s"
+            [websocket_tls]
+            port = 443
+            [websocket_tls.acme]
+            domains = ["nox.example.com"]
+            challenge = "tls-alpn-01"
             "#
         )
         .expect("Could not write in file");
@@ -731,10 +1082,1134 @@ mod tests {
 
         temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
             let config = load_config_with_args(vec![], None).expect("Could not load config");
-            assert_eq!(
-                config.node_config.protocol_config.upgrade_timeout,
-                Duration::from_secs(60)
-            );
+            let config = config.resolve().unwrap();
+            let websocket_tls = config.websocket_tls.clone().expect("websocket_tls is set");
+            let acme = websocket_tls.acme.expect("acme is set");
+            assert_eq!(acme.challenge, crate::AcmeChallenge::TlsAlpn01);
+            assert_eq!(acme.renew_before, crate::default_acme_renew_before());
+
+            let wss: Multiaddr = "/dns/nox.example.com/tcp/443/tls/ws".parse().unwrap();
+            assert!(config.external_addresses().contains(&wss));
+        });
+    }
+
+    #[test]
+    fn websocket_tls_requires_certificate() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [websocket_tls]
+            certificate_path = "/tmp/cert.pem"
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            assert!(config.resolve().is_err());
+        });
+    }
+
+    #[test]
+    fn load_metrics_endpoint() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [metrics_endpoint]
+            port = 9100
+            certificate_path = "/tmp/cert.pem"
+            private_key_path = "/tmp/key.pem"
+            client_ca_path = "/tmp/ca.pem"
+            require_token = true
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+            let metrics_endpoint = config
+                .metrics_endpoint
+                .clone()
+                .expect("metrics_endpoint is set");
+            assert!(metrics_endpoint.is_tls());
+            assert!(metrics_endpoint.require_token);
+            assert_eq!(config.metrics_listen_addr().map(|a| a.port()), Some(9100));
+        });
+    }
+
+    #[test]
+    fn load_admin_tls() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [http_config]
+            grpc_port = 18090
+            [http_config.admin_tls]
+            certificate_path = "/tmp/cert.pem"
+            private_key_path = "/tmp/key.pem"
+            client_ca_path = "/tmp/ca.pem"
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+            let http_config = config.http_config.clone().expect("http_config is set");
+            let admin_tls = http_config.admin_tls.expect("admin_tls is set");
+            assert_eq!(admin_tls.client_ca_path, PathBuf::from("/tmp/ca.pem"));
+            assert!(!admin_tls.require_client_certificate);
+        });
+    }
+
+    #[test]
+    fn metrics_endpoint_client_ca_requires_tls() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [metrics_endpoint]
+            client_ca_path = "/tmp/ca.pem"
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            assert!(config.resolve().is_err());
+        });
+    }
+
+    #[test]
+    fn load_nat_relays() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [nat]
+            autonat = false
+            relays = ["/ip4/1.2.3.4/tcp/7777/p2p/12D3KooWB9P1xmV3c7ZPpBemovbwCiRRTKd3Kq2jsVPQN4ZukDfy"]
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+            assert!(!config.nat.autonat);
+            assert!(config.nat.relay_client);
+            assert!(config.nat.hole_punching());
+            assert_eq!(config.nat.relays.len(), 1);
+        });
+    }
+
+    #[test]
+    fn load_nat_without_relay_client() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [nat]
+            relay_client = false
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+            assert!(!config.nat.relay_client);
+            assert!(!config.nat.hole_punching());
+        });
+    }
+
+    #[test]
+    fn load_nat_upnp() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [nat]
+            upnp = true
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+            assert!(config.nat.upnp);
+            assert!(config.nat.autonat);
+        });
+    }
+
+    #[test]
+    fn nat_relays_require_peer_id() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [nat]
+            relays = ["/ip4/1.2.3.4/tcp/7777"]
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            assert!(config.resolve().is_err());
+        });
+    }
+
+    #[test]
+    fn load_reputation_with_env() {
+        temp_env::with_vars(
+            [
+                ("FLUENCE_REPUTATION__BAN_THRESHOLD", Some("-50")),
+                ("FLUENCE_REPUTATION__DECAY_HALF_LIFE", Some("1h")),
+            ],
+            || {
+                let config = load_config_with_args(vec![], None).expect("Could not load config");
+                let config = config.resolve().unwrap();
+                assert_eq!(config.reputation.ban_threshold, -50.0);
+                assert_eq!(
+                    config.reputation.decay_half_life,
+                    Duration::from_secs(60 * 60)
+                );
+                assert_eq!(config.reputation.max_particles_per_window, 1000);
+            },
+        );
+    }
+
+    #[test]
+    fn load_env_upgrade_timeout() {
+        temp_env::with_vars(
+            [("FLUENCE_PROTOCOL_CONFIG__UPGRADE_TIMEOUT", Some("60s"))],
+            || {
+                let args = vec![];
+                let config = load_config_with_args(args, None).expect("Could not load config");
+                assert_eq!(
+                    config.node_config.protocol_config.upgrade_timeout,
+                    Duration::from_secs(60)
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn load_file_upgrade_timeout() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [protocol_config]
+            upgrade_timeout = "60s"
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            assert_eq!(
+                config.node_config.protocol_config.upgrade_timeout,
+                Duration::from_secs(60)
+            );
+        });
+    }
+
+    #[test]
+    fn load_connection_limits() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            max_established_per_ip = 10
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let transport_config = config.node_config.transport_config;
+            assert_eq!(transport_config.max_established_per_ip, Some(10));
+            assert_eq!(transport_config.max_established_per_peer, Some(5));
+            assert_eq!(transport_config.max_established, None);
+        });
+    }
+
+    #[test]
+    fn load_bandwidth() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [bandwidth]
+            per_connection = "1 MiB"
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+            assert_eq!(
+                config.bandwidth.per_connection,
+                Some(bytesize::ByteSize::mib(1))
+            );
+            assert_eq!(config.bandwidth.global, None);
+        });
+    }
+
+    #[test]
+    fn load_rate_limit() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [rate_limit]
+            particles_per_sec = 100
+            bytes_per_sec = "1 MiB"
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+            assert_eq!(config.rate_limit.particles_per_sec, Some(100));
+            assert_eq!(
+                config.rate_limit.bytes_per_sec,
+                Some(bytesize::ByteSize::mib(1))
+            );
+        });
+    }
+
+    #[test]
+    fn load_pubsub() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [pubsub]
+            heartbeat_interval = "500ms"
+            max_transmit_size = "1 MiB"
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+            assert!(config.pubsub.enabled);
+            assert_eq!(config.pubsub.heartbeat_interval, Duration::from_millis(500));
+            assert_eq!(config.pubsub.max_transmit_size, bytesize::ByteSize::mib(1));
+        });
+    }
+
+    #[test]
+    fn load_peer_filter() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [peer_filter]
+            denylist = ["12D3KooWB9P1xmV3c7ZPpBemovbwCiRRTKd3Kq2jsVPQN4ZukDfy"]
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+            assert!(config.peer_filter.allowlist.is_empty());
+            assert_eq!(config.peer_filter.denylist.len(), 1);
+            assert!(config
+                .dir_config
+                .peer_filter_path
+                .ends_with("peer_filter.toml"));
+        });
+    }
+
+    #[test]
+    fn load_dial_backoff() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [dial_backoff]
+            initial_delay = "500ms"
+            multiplier = 3.0
+            max_attempts = 10
+            scope = "per_peer"
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+            let backoff = &config.dial_backoff;
+            assert_eq!(backoff.initial_delay, Duration::from_millis(500));
+            assert_eq!(backoff.max_delay, Duration::from_secs(60));
+            assert_eq!(backoff.max_attempts, Some(10));
+            assert_eq!(backoff.scope, connection_pool::BackoffScope::PerPeer);
+            assert_eq!(backoff.delay(3), Duration::from_millis(4500));
+        });
+    }
+
+    #[test]
+    fn load_dual_stack() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            external_address = "1.2.3.4"
+            external_ipv6_address = "2001:db8::1"
+            listen_ip = "0.0.0.0"
+            listen_ipv6 = "::"
+            tcp_port = 7777
+            websocket_port = 9999
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+            assert!(config.transport_config.prefer_ipv6);
+
+            let listen = config.listen_multiaddrs();
+            let tcp4: Multiaddr = "/ip4/0.0.0.0/tcp/7777".parse().unwrap();
+            let ws6: Multiaddr = "/ip6/::/tcp/9999/ws".parse().unwrap();
+            assert!(listen.contains(&tcp4));
+            assert!(listen.contains(&ws6));
+
+            let external = config.external_addresses();
+            let tcp4: Multiaddr = "/ip4/1.2.3.4/tcp/7777".parse().unwrap();
+            let tcp6: Multiaddr = "/ip6/2001:db8::1/tcp/7777".parse().unwrap();
+            assert!(external.contains(&tcp4));
+            assert!(external.contains(&tcp6));
+        });
+    }
+
+    #[test]
+    fn load_listen_endpoints() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            external_address = "1.2.3.4"
+            [[listen_endpoints]]
+            protocol = "tcp"
+            port = 7777
+            allow = "nodes"
+            [[listen_endpoints]]
+            protocol = "quic"
+            port = 7777
+            [[listen_endpoints]]
+            protocol = "ws"
+            ip = "10.0.0.1"
+            port = 9999
+            allow = "clients"
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+
+            let endpoints = config.endpoint_multiaddrs();
+            let tcp: Multiaddr = "/ip4/0.0.0.0/tcp/7777".parse().unwrap();
+            let quic: Multiaddr = "/ip4/0.0.0.0/udp/7777/quic-v1".parse().unwrap();
+            let ws: Multiaddr = "/ip4/10.0.0.1/tcp/9999/ws".parse().unwrap();
+            assert_eq!(
+                endpoints,
+                vec![
+                    (tcp, AllowedPeers::Nodes),
+                    (quic, AllowedPeers::All),
+                    (ws, AllowedPeers::Clients)
+                ]
+            );
+
+            // endpoint bound to an explicit ip isn't advertised
+            let external = config.external_addresses();
+            let tcp: Multiaddr = "/ip4/1.2.3.4/tcp/7777".parse().unwrap();
+            let quic: Multiaddr = "/ip4/1.2.3.4/udp/7777/quic-v1".parse().unwrap();
+            assert_eq!(external, vec![tcp, quic]);
+        });
+    }
+
+    #[test]
+    fn listen_ipv6_requires_ipv4_listen_ip() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            listen_ip = "::"
+            listen_ipv6 = "::"
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            assert!(config.resolve().is_err());
+        });
+    }
+
+    #[test]
+    fn load_kademlia_intervals() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [kademlia]
+            query_timeout = "3s"
+            peer_fail_threshold = 3
+            ban_cooldown = "60s"
+            record_ttl = "0s"
+            replication_interval = "10m"
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+            assert_eq!(config.kademlia.record_ttl, Duration::ZERO);
+            assert_eq!(
+                config.kademlia.replication_interval,
+                Duration::from_secs(600)
+            );
+            assert_eq!(
+                config.kademlia.publication_interval,
+                Duration::from_secs(24 * 60 * 60)
+            );
+        });
+    }
+
+    #[test]
+    fn kademlia_record_ttl_outlives_publication() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [kademlia]
+            query_timeout = "3s"
+            peer_fail_threshold = 3
+            ban_cooldown = "60s"
+            record_ttl = "1h"
+            publication_interval = "2h"
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            assert!(config.resolve().is_err());
+        });
+    }
+
+    #[test]
+    fn load_dns() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [dns]
+            min_ttl = "10s"
+            reresolve_interval = "5m"
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+            assert_eq!(config.dns.min_ttl, Duration::from_secs(10));
+            assert_eq!(config.dns.max_ttl, Duration::from_secs(3600));
+            assert_eq!(config.dns.reresolve_interval, Duration::from_secs(300));
+        });
+    }
+
+    #[test]
+    fn load_keep_alive() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [keep_alive]
+            ping_interval = "30s"
+            [keep_alive.nodes]
+            keep_alive = true
+            idle_timeout = "10m"
+            [keep_alive.clients]
+            keep_alive = false
+            idle_timeout = "20s"
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+            let keep_alive = &config.keep_alive;
+            assert_eq!(keep_alive.ping_interval, Duration::from_secs(30));
+            assert!(keep_alive.bootstrap.keep_alive);
+            assert!(keep_alive.nodes.keep_alive);
+            assert_eq!(keep_alive.clients.idle_timeout, Duration::from_secs(20));
+        });
+    }
+
+    #[test]
+    fn load_peer_exchange() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [peer_exchange]
+            sample_size = 8
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+            assert!(config.peer_exchange.enabled);
+            assert_eq!(config.peer_exchange.sample_size, 8);
+            assert_eq!(config.peer_exchange.max_dials, 4);
+        });
+    }
+
+    #[test]
+    fn load_network_name() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            network_name = "custom-x"
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+            assert_eq!(config.network_name.as_deref(), Some("custom-x"));
+        });
+    }
+
+    #[test]
+    fn invalid_network_name() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            network_name = "test net"
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            assert!(config.resolve().is_err());
+        });
+    }
+
+    #[test]
+    fn load_aquavm_pool_scaling() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            aquavm_pool_size = 4
+            [aquavm_pool_scaling]
+            max_size = 16
+            scale_up_latency = "500ms"
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+            let scaling = &config.aquavm_pool_scaling;
+            assert_eq!(scaling.bounds(config.aquavm_pool_size), Some((4, 16)));
+            assert_eq!(scaling.scale_up_latency, Duration::from_millis(500));
+            assert_eq!(scaling.scale_down_after, Duration::from_secs(60));
+            assert_eq!(scaling.warm_vms, 1);
+            assert!(config.aquavm_preinit);
+        });
+    }
+
+    #[test]
+    fn aquavm_pool_scaling_warm_above_max() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [aquavm_pool_scaling]
+            max_size = 4
+            warm_vms = 8
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            assert!(config.resolve().is_err());
+        });
+    }
+
+    #[test]
+    fn aquavm_pool_scaling_min_above_max() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [aquavm_pool_scaling]
+            min_size = 8
+            max_size = 4
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            assert!(config.resolve().is_err());
+        });
+    }
+
+    #[test]
+    fn load_resources() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            default_service_memory_limit = "1 GiB"
+            [resources]
+            aquavm_pool_size = 4
+            aquavm_heap_size = "100 MiB"
+            max_workers = 16
+            max_particle_data_size = "1 MiB"
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let args = vec![
+                OsString::from("nox"),
+                OsString::from("--aqua-pool-size"),
+                OsString::from("3"),
+            ];
+            let config = load_config_with_args(args, None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+            assert_eq!(config.aquavm_pool_size, 3);
+            assert_eq!(
+                config.avm_config.aquavm_heap_size_limit,
+                Some(bytesize::ByteSize::mib(100))
+            );
+            assert_eq!(
+                config.default_service_memory_limit,
+                Some(bytesize::ByteSize::gib(1))
+            );
+            assert_eq!(
+                config.particle_limits.max_data_size,
+                Some(bytesize::ByteSize::mib(1))
+            );
+            assert_eq!(config.max_workers, Some(16));
+            assert_eq!(config.max_vault_size, None);
+        });
+    }
+
+    #[test]
+    fn load_particle_limits() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [particle_limits]
+            max_particle_size = "16 MiB"
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+            assert_eq!(
+                config.particle_limits.max_particle_size,
+                Some(bytesize::ByteSize::mib(16))
+            );
+            assert_eq!(config.particle_limits.max_data_size, None);
+        });
+    }
+
+    #[test]
+    fn load_particle_signatures() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [particle_signatures]
+            enforcement = "strict"
+            exempt_peers = ["12D3KooWB9P1xmV3c7ZPpBemovbwCiRRTKd3Kq2jsVPQN4ZukDfy"]
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+            let signatures = &config.particle_signatures;
+            assert_eq!(
+                signatures.enforcement,
+                connection_pool::SignatureEnforcement::Strict
+            );
+            assert_eq!(signatures.exempt_peers.len(), 1);
+        });
+    }
+
+    #[test]
+    fn load_management_keys() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [[management_keys]]
+            peer_id = "12D3KooWB9P1xmV3c7ZPpBemovbwCiRRTKd3Kq2jsVPQN4ZukDfy"
+            capabilities = ["ban_peers", "read_audit_log"]
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+            assert_eq!(config.management_keys.len(), 1);
+            let capabilities: Vec<_> = config.management_keys[0]
+                .capabilities
+                .iter()
+                .copied()
+                .collect();
+            assert_eq!(
+                capabilities,
+                vec![
+                    types::management::Capability::BanPeers,
+                    types::management::Capability::ReadAuditLog
+                ]
+            );
+            assert!(config
+                .dir_config
+                .management_keys_path
+                .ends_with("management_keys.toml"));
+        });
+    }
+
+    #[test]
+    fn load_data_retention() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [data_retention]
+            interval = "1m"
+            particles.max_age = "1d"
+            anomalies.max_total_size = "1 GiB"
+            anomalies.max_particle_size = "100 MiB"
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+            let retention = config.data_retention;
+            assert!(retention.is_enabled());
+            assert_eq!(retention.interval, Duration::from_secs(60));
+            assert_eq!(
+                retention.particles.max_age,
+                Some(Duration::from_secs(24 * 60 * 60))
+            );
+            assert_eq!(retention.particles.max_total_size, None);
+            assert_eq!(
+                retention.anomalies.max_total_size,
+                Some(bytesize::ByteSize::gib(1))
+            );
+        });
+    }
+
+    #[test]
+    fn load_particle_priority() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [particle_priority]
+            order = ["deal_spell", "system_spell"]
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+            assert!(config.particle_priority.enabled);
+            assert_eq!(
+                config.particle_priority.order,
+                vec![ParticleClass::DealSpell, ParticleClass::SystemSpell]
+            );
+        });
+    }
+
+    #[test]
+    fn particle_priority_duplicates() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [particle_priority]
+            order = ["client", "system_spell", "client"]
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            assert!(config.resolve().is_err());
+        });
+    }
+
+    #[test]
+    fn load_compression() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [protocol_config.compression]
+            algorithm = "lz4"
+            threshold = "1 KiB"
+            [particle_data_compression]
+            enabled = false
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+            let compression = &config.protocol_config.compression;
+            assert!(compression.enabled);
+            assert_eq!(compression.algorithm, CompressionAlgorithm::Lz4);
+            assert_eq!(compression.threshold, bytesize::ByteSize::kib(1));
+            assert_eq!(
+                config.protocol_config.upgrade_timeout,
+                Duration::from_secs(10)
+            );
+            assert!(!config.particle_data_compression.enabled);
+        });
+    }
+
+    #[test]
+    fn load_particle_dedup() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [particle_dedup]
+            max_entries = 1000
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+            assert!(config.particle_dedup.enabled);
+            assert_eq!(config.particle_dedup.max_entries, 1000);
+            assert_eq!(
+                config.particle_dedup.persist_interval,
+                Duration::from_secs(10)
+            );
+            assert!(config
+                .dir_config
+                .seen_particles_path
+                .ends_with("seen_particles"));
+        });
+    }
+
+    #[test]
+    fn load_drain() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [drain]
+            timeout = "30s"
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+            assert_eq!(config.drain.timeout, Duration::from_secs(30));
+            assert_eq!(config.drain.relays, 3);
+        });
+    }
+
+    #[test]
+    fn load_control_socket() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            control_socket_enabled = false
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+            assert!(!config.control_socket_enabled);
+            assert!(config
+                .dir_config
+                .control_socket_path
+                .ends_with("persistent/nox.sock"));
+        });
+    }
+
+    #[test]
+    fn load_private_network() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [private_network]
+            key = "{}"
+            previous_key = "{}"
+            previous_key_valid_until = "2030-01-01T00:00:00Z"
+            "#,
+            "ab".repeat(32),
+            "cd".repeat(32),
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+            let private_network = config.private_network.expect("private_network is set");
+            assert_eq!(private_network.key().fingerprint(), "abababab");
+        });
+    }
+
+    #[test]
+    fn private_network_requires_previous_key_expiration() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [private_network]
+            key = "{}"
+            previous_key = "{}"
+            "#,
+            "ab".repeat(32),
+            "cd".repeat(32),
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            assert!(config.resolve().is_err());
         });
     }
 
@@ -818,7 +2293,7 @@ mod tests {
                     ]
                 );
                 assert_eq!(config.node_config.listen_config.websocket_port, 666);
-                assert_eq!(config.node_config.aquavm_pool_size, 160);
+                assert_eq!(config.node_config.resources.aquavm_pool_size, Some(160));
             },
         );
     }
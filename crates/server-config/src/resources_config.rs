@@ -0,0 +1,163 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashSet;
+
+use bytesize::ByteSize;
+use serde::{Deserialize, Serialize};
+
+use crate::defaults::default_service_memory_limit;
+
+/// Resource limits of the node in one place. Sizes are given as `"512 MiB"`, `"4 GB"` or a number of bytes.
+/// Unset limits fall back to the legacy fields: `aquavm_pool_size`, `avm_config.aquavm_heap_size_limit`,
+/// `default_service_memory_limit` and `particle_limits`, which old config files are migrated from.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ResourcesConfig {
+    /// Number of AquaVMs, i.e. particle script execution parallelism
+    pub aquavm_pool_size: Option<usize>,
+    /// Heap available to an AquaVM instance
+    pub aquavm_heap_size: Option<ByteSize>,
+    /// Heap available to a WASM service module, unless set in the module config
+    pub service_memory: Option<ByteSize>,
+    /// Workers hosted by the node, workers for new deals aren't created past that number
+    pub max_workers: Option<usize>,
+    /// Files put to a particle vault by `vault.put` can't take more than that
+    pub max_vault_size: Option<ByteSize>,
+    /// Particles received from the network with larger data and script together are refused
+    pub max_particle_size: Option<ByteSize>,
+    /// Particles with data larger than that after execution aren't sent further
+    pub max_particle_data_size: Option<ByteSize>,
+    /// GPU devices workers may request, each is given to one worker at a time
+    pub gpus: Vec<GpuDevice>,
+}
+
+/// A GPU device offered to workers
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct GpuDevice {
+    /// Device index or UUID as the driver knows it, e.g. `"0"` or `"GPU-5d3a..."`
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<ByteSize>,
+}
+
+impl ResourcesConfig {
+    pub fn validate(&self) -> eyre::Result<()> {
+        if self.aquavm_pool_size == Some(0) {
+            eyre::bail!("resources.aquavm_pool_size must be positive");
+        }
+        if self.max_workers == Some(0) {
+            eyre::bail!("resources.max_workers must be positive");
+        }
+        let mut gpu_ids = HashSet::new();
+        for gpu in &self.gpus {
+            if gpu.id.trim().is_empty() {
+                eyre::bail!("resources.gpus must have non-empty ids");
+            }
+            if !gpu_ids.insert(gpu.id.as_str()) {
+                eyre::bail!("resources.gpus has a duplicate device {}", gpu.id);
+            }
+        }
+
+        let sizes = [
+            ("aquavm_heap_size", self.aquavm_heap_size),
+            ("service_memory", self.service_memory),
+            ("max_vault_size", self.max_vault_size),
+            ("max_particle_size", self.max_particle_size),
+            ("max_particle_data_size", self.max_particle_data_size),
+        ];
+        for (name, size) in sizes {
+            if size.is_some_and(|s| s.as_u64() == 0) {
+                eyre::bail!("resources.{name} must be positive");
+            }
+        }
+
+        // AquaVM and services are 32-bit WASM modules
+        let wasm_memory = [
+            ("aquavm_heap_size", self.aquavm_heap_size),
+            ("service_memory", self.service_memory),
+        ];
+        let max_memory = default_service_memory_limit();
+        for (name, size) in wasm_memory {
+            if let Some(size) = size.filter(|s| *s > max_memory) {
+                eyre::bail!(
+                    "resources.{name} can't exceed {} bytes of WASM memory, got {}",
+                    max_memory.as_u64(),
+                    size.as_u64()
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_resources() {
+        let config: ResourcesConfig = toml::from_str(
+            r#"
+            aquavm_pool_size = 8
+            service_memory = "1 GiB"
+            max_vault_size = 1048576
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.service_memory, Some(ByteSize::gib(1)));
+        assert_eq!(config.max_vault_size, Some(ByteSize::mib(1)));
+        assert!(config.validate().is_ok());
+
+        let config = ResourcesConfig {
+            service_memory: Some(ByteSize::gib(8)),
+            ..<_>::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = ResourcesConfig {
+            max_workers: Some(0),
+            ..<_>::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn validate_gpus() {
+        let config: ResourcesConfig = toml::from_str(
+            r#"
+            [[gpus]]
+            id = "0"
+            model = "A100"
+            memory = "80 GiB"
+
+            [[gpus]]
+            id = "1"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.gpus.len(), 2);
+        assert_eq!(config.gpus[0].memory, Some(ByteSize::gib(80)));
+        assert_eq!(config.gpus[1].model, None);
+        assert!(config.validate().is_ok());
+
+        let mut config = config;
+        config.gpus[1].id = "0".to_string();
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,218 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::process::Command;
+use std::sync::Mutex;
+
+use config::{Config, Source, Value, ValueKind};
+use eyre::{eyre, WrapErr};
+
+/// Value of fields set by `${exec:...}` when commands aren't run
+pub(crate) const REDACTED_EXEC: &str = "<exec>";
+
+/// Outputs of `${exec:...}` commands by command
+static EXEC_OUTPUTS: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+/// Resolves string values that are references to secrets, so secrets don't have to be in the config:
+///  - `${file:/run/secrets/wallet_key}` is replaced with the file contents
+///  - `${env:WALLET_KEY}` is replaced with the env variable value
+///  - `${exec:vault kv get -field=wallet_key secret/nox}` is replaced with the output of the command,
+///    which is how external secret stores are queried. Commands can only be set in config files,
+///    `exec_denied` is the config of other sources, i.e. env variables and args. A command is run
+///    once per process, its output is reused on reloads. If `run_exec` is false, commands
+///    aren't run at all and their fields are set to [REDACTED_EXEC], which is enough to explain the config
+///
+/// Surrounding whitespace is trimmed. Returns the resolved config and references by field path.
+pub(crate) fn resolve_secret_refs(
+    config: Config,
+    exec_denied: &Config,
+    run_exec: bool,
+) -> eyre::Result<(Config, HashMap<String, String>)> {
+    let mut refs = vec![];
+    for (key, value) in config.collect()? {
+        collect_refs(&value, key, &mut refs);
+    }
+    if refs.is_empty() {
+        return Ok((config, <_>::default()));
+    }
+
+    let mut denied = vec![];
+    for (key, value) in exec_denied.collect()? {
+        collect_refs(&value, key, &mut denied);
+    }
+    let denied: HashSet<_> = denied
+        .into_iter()
+        .filter(|(_, reference)| is_exec(reference))
+        .map(|(path, _)| path)
+        .collect();
+
+    let mut builder = Config::builder().add_source(config);
+    let mut references = HashMap::new();
+    for (path, reference) in refs {
+        if is_exec(&reference) && denied.contains(&path) {
+            eyre::bail!("{path}: exec references are only allowed in config files");
+        }
+        let secret = if is_exec(&reference) && !run_exec {
+            REDACTED_EXEC.to_string()
+        } else {
+            resolve_ref(&reference)
+                .wrap_err_with(|| format!("failed to resolve {reference} for {path}"))?
+        };
+        builder = builder.set_override(&path, secret)?;
+        references.insert(path, reference);
+    }
+    Ok((builder.build()?, references))
+}
+
+fn collect_refs(value: &Value, path: String, refs: &mut Vec<(String, String)>) {
+    match &value.kind {
+        ValueKind::String(s) if parse_ref(s).is_some() => refs.push((path, s.clone())),
+        ValueKind::Table(table) => {
+            for (key, value) in table {
+                collect_refs(value, format!("{path}.{key}"), refs);
+            }
+        }
+        ValueKind::Array(array) => {
+            for (i, value) in array.iter().enumerate() {
+                collect_refs(value, format!("{path}[{i}]"), refs);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Splits `${kind:reference}` into kind and reference
+fn parse_ref(value: &str) -> Option<(&str, &str)> {
+    let inner = value.strip_prefix("${")?.strip_suffix('}')?;
+    let (kind, reference) = inner.split_once(':')?;
+    matches!(kind, "file" | "env" | "exec").then_some((kind, reference))
+}
+
+fn is_exec(value: &str) -> bool {
+    matches!(parse_ref(value), Some(("exec", _)))
+}
+
+fn resolve_ref(value: &str) -> eyre::Result<String> {
+    let (kind, reference) = parse_ref(value).ok_or_else(|| eyre!("not a secret reference"))?;
+    let secret = match kind {
+        "file" => std::fs::read_to_string(reference)?,
+        "env" => std::env::var(reference)?,
+        "exec" => return run_exec(reference),
+        _ => unreachable!("checked in parse_ref"),
+    };
+    Ok(secret.trim().to_string())
+}
+
+/// Runs `command` unless it has already been run by this process
+fn run_exec(command: &str) -> eyre::Result<String> {
+    let mut outputs = EXEC_OUTPUTS.lock().expect("exec outputs lock is poisoned");
+    if let Some(output) = outputs.get(command) {
+        return Ok(output.clone());
+    }
+
+    let output = Command::new("sh").arg("-c").arg(command).output()?;
+    if !output.status.success() {
+        eyre::bail!(
+            "command exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let output = String::from_utf8(output.stdout)?.trim().to_string();
+    outputs.insert(command.to_string(), output.clone());
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    #[test]
+    fn resolve_refs() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        writeln!(file, "file_secret").expect("Could not write in file");
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("NOX_TEST_SECRET", Some("env_secret"), || {
+            let config = Config::builder()
+                .set_override("a.file", format!("${{file:{path}}}"))
+                .unwrap()
+                .set_override("a.env", "${env:NOX_TEST_SECRET}")
+                .unwrap()
+                .set_override("exec", "${exec:echo exec_secret}")
+                .unwrap()
+                .set_override("plain", "${unknown:value}")
+                .unwrap()
+                .build()
+                .unwrap();
+
+            let (config, refs) = resolve_secret_refs(config, &Config::default(), true).unwrap();
+            assert_eq!(config.get_string("a.file").unwrap(), "file_secret");
+            assert_eq!(config.get_string("a.env").unwrap(), "env_secret");
+            assert_eq!(config.get_string("exec").unwrap(), "exec_secret");
+            assert_eq!(config.get_string("plain").unwrap(), "${unknown:value}");
+            assert_eq!(refs.len(), 3);
+        });
+    }
+
+    #[test]
+    fn missing_secret() {
+        let config = Config::builder()
+            .set_override("key", "${env:NOX_TEST_MISSING_SECRET}")
+            .unwrap()
+            .build()
+            .unwrap();
+        assert!(resolve_secret_refs(config, &Config::default(), true).is_err());
+    }
+
+    #[test]
+    fn exec_only_from_files() {
+        let config = Config::builder()
+            .set_override("key", "${exec:echo secret}")
+            .unwrap()
+            .build()
+            .unwrap();
+        let env = config.clone();
+        assert!(resolve_secret_refs(config, &env, true).is_err());
+    }
+
+    #[test]
+    fn exec_runs_once() {
+        let dir = tempfile::tempdir().expect("create tempdir");
+        let runs = dir.path().join("runs");
+        let command = format!("${{exec:echo run >> {0}; wc -l < {0}}}", runs.display());
+        let config = Config::builder()
+            .set_override("key", command)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        for _ in 0..2 {
+            let (resolved, _) =
+                resolve_secret_refs(config.clone(), &Config::default(), true).unwrap();
+            assert_eq!(resolved.get_string("key").unwrap(), "1");
+        }
+
+        let (explained, refs) = resolve_secret_refs(config, &Config::default(), false).unwrap();
+        assert_eq!(explained.get_string("key").unwrap(), REDACTED_EXEC);
+        assert_eq!(refs.len(), 1);
+    }
+}
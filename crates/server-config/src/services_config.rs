@@ -48,6 +48,8 @@ pub struct ServicesConfig {
     pub builtins_management_peer_id: PeerId,
     /// Default heap size in bytes available for the module unless otherwise specified.
     pub default_service_memory_limit: Option<ByteSize>,
+    /// Files put to a particle vault by `vault.put` can't take more than that
+    pub max_vault_size: Option<ByteSize>,
     /// List of allowed effector modules by CID
     pub allowed_effectors: HashMap<Hash, HashMap<String, PathBuf>>,
     /// Mapping of binary names to their paths for mounted binaries used in developer mode
@@ -67,6 +69,7 @@ impl ServicesConfig {
         management_peer_id: PeerId,
         builtins_management_peer_id: PeerId,
         default_service_memory_limit: Option<ByteSize>,
+        max_vault_size: Option<ByteSize>,
         allowed_effectors: HashMap<Hash, HashMap<String, String>>,
         mounted_binaries_mapping: HashMap<String, String>,
         is_dev_mode: bool,
@@ -126,6 +129,7 @@ impl ServicesConfig {
             management_peer_id,
             builtins_management_peer_id,
             default_service_memory_limit,
+            max_vault_size,
             allowed_effectors,
             mounted_binaries_mapping,
             is_dev_mode,
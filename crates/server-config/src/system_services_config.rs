@@ -15,8 +15,12 @@
  */
 
 use super::defaults::*;
+use bytesize::ByteSize;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fmt::Formatter;
+use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
 #[serde(rename_all = "kebab-case")]
@@ -63,6 +67,10 @@ impl std::fmt::Display for ServiceKey {
 pub struct SystemServicesConfig {
     #[serde(default = "default_system_services")]
     pub enable: Vec<ServiceKey>,
+    /// Enabled services deployed in background after the node has started.
+    /// Nothing should depend on them during startup, e.g. aqua-ipfs
+    #[serde(default)]
+    pub lazy: Vec<ServiceKey>,
     #[serde(default)]
     pub aqua_ipfs: AquaIpfsConfig,
     #[serde(default)]
@@ -71,28 +79,53 @@ pub struct SystemServicesConfig {
     pub registry: RegistryConfig,
     #[serde(default)]
     pub connector: ConnectorConfig,
+    #[serde(default)]
+    pub trust_graph: TrustGraphConfig,
+    /// Versions the system services are pinned to. A pinned service isn't updated when the node
+    /// binary bundles another version, only by the `system.update` builtin
+    #[serde(default)]
+    pub pinned_versions: HashMap<ServiceKey, String>,
+    /// Directory with replacements of the bundled system spells, e.g. a customized decider:
+    /// `<spell name>.air` for the script and `<spell name>.toml` for the trigger config
+    #[serde(default)]
+    pub spell_overrides_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub supervisor: SupervisorConfig,
 }
 
 impl Default for SystemServicesConfig {
     fn default() -> Self {
         Self {
             enable: default_system_services(),
+            lazy: vec![],
             aqua_ipfs: Default::default(),
             decider: Default::default(),
             registry: Default::default(),
             connector: Default::default(),
+            trust_graph: Default::default(),
+            pinned_versions: Default::default(),
+            spell_overrides_dir: None,
+            supervisor: Default::default(),
         }
     }
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct AquaIpfsConfig {
+    /// IPFS API returned to clients of aqua-ipfs, so they can fetch what the node uploaded
     #[serde(default = "default_ipfs_multiaddr")]
     pub external_api_multiaddr: String,
+    /// IPFS API aqua-ipfs calls on behalf of users
     #[serde(default = "default_ipfs_multiaddr")]
     pub local_api_multiaddr: String,
+    /// IPFS API the decider fetches deal definitions from, `decider.worker_ipfs_multiaddr` if not set
+    #[serde(default)]
+    pub deal_api_multiaddr: Option<String>,
     #[serde(default = "default_ipfs_binary_path")]
     pub ipfs_binary_path: String,
+    /// Limits on content pinned at `local_api_multiaddr`
+    #[serde(default)]
+    pub pinning: IpfsPinningConfig,
 }
 
 impl Default for AquaIpfsConfig {
@@ -100,7 +133,111 @@ impl Default for AquaIpfsConfig {
         Self {
             external_api_multiaddr: default_ipfs_multiaddr(),
             local_api_multiaddr: default_ipfs_multiaddr(),
+            deal_api_multiaddr: None,
             ipfs_binary_path: default_ipfs_binary_path(),
+            pinning: <_>::default(),
+        }
+    }
+}
+
+/// Pins above these limits are removed, least recently pinned first. IPFS doesn't track
+/// when content was pinned, so pins found on startup count as pinned at startup.
+/// Nothing is unpinned unless some limit is set.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct IpfsPinningConfig {
+    /// How often pins are checked against the limits
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+    /// Pins older than that are removed
+    #[serde(with = "humantime_serde")]
+    pub pin_lifetime: Option<Duration>,
+    /// Total size of pinned content
+    pub max_pinned_size: Option<ByteSize>,
+}
+
+impl Default for IpfsPinningConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(600),
+            pin_lifetime: None,
+            max_pinned_size: None,
+        }
+    }
+}
+
+impl IpfsPinningConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.pin_lifetime.is_some() || self.max_pinned_size.is_some()
+    }
+
+    pub fn validate(&self) -> eyre::Result<()> {
+        let prefix = "system_services.aqua_ipfs.pinning";
+        if self.interval.is_zero() {
+            eyre::bail!("{prefix}.interval must be positive");
+        }
+        if self.pin_lifetime.is_some_and(|lifetime| lifetime.is_zero()) {
+            eyre::bail!("{prefix}.pin_lifetime must be positive");
+        }
+        if self.max_pinned_size.is_some_and(|s| s.as_u64() == 0) {
+            eyre::bail!("{prefix}.max_pinned_size must be positive");
+        }
+        Ok(())
+    }
+}
+
+/// Periodic health checks of the enabled system services: their services must be deployed
+/// and respond, their periodic spells must keep running
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct SupervisorConfig {
+    #[serde(with = "humantime_serde")]
+    pub check_interval: Duration,
+    /// A spell that hasn't run for that many of its trigger periods is considered stalled
+    pub stalled_spell_periods: u32,
+    /// Whether failed system services are redeployed, otherwise they are only reported
+    pub restart: bool,
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        Self {
+            check_interval: Duration::from_secs(60),
+            stalled_spell_periods: 3,
+            restart: true,
+        }
+    }
+}
+
+impl SupervisorConfig {
+    pub fn validate(&self) -> eyre::Result<()> {
+        let prefix = "system_services.supervisor";
+        if self.check_interval.is_zero() {
+            eyre::bail!("{prefix}.check_interval must be positive");
+        }
+        if self.stalled_spell_periods == 0 {
+            eyre::bail!("{prefix}.stalled_spell_periods must be positive");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct TrustGraphConfig {
+    /// If set, peers outside of `peer_filter.allowlist` are allowed when their
+    /// trust graph weight is at least that
+    pub allowlist_min_weight: Option<u32>,
+    /// How long trust graph weights are cached for the allowlist
+    #[serde(with = "humantime_serde")]
+    pub weight_cache_ttl: Duration,
+}
+
+impl Default for TrustGraphConfig {
+    fn default() -> Self {
+        Self {
+            allowlist_min_weight: None,
+            weight_cache_ttl: Duration::from_secs(300),
         }
     }
 }
@@ -139,6 +276,15 @@ pub struct DeciderConfig {
     pub worker_gas: u64,
     #[serde(default)]
     pub wallet_key: Option<String>,
+    /// How many deals may be installed concurrently, the rest wait in a queue. Not limited if not set
+    #[serde(default)]
+    pub max_concurrent_installs: Option<usize>,
+    /// A deal installation taking longer frees its slot for the next deal in the queue
+    #[serde(default = "default_deal_install_timeout")]
+    #[serde(with = "humantime_serde")]
+    pub install_timeout: Duration,
+    #[serde(default)]
+    pub prefetch: DealPrefetchConfig,
 }
 
 impl Default for DeciderConfig {
@@ -153,10 +299,67 @@ impl Default for DeciderConfig {
             start_block: default_decider_start_block_hex(),
             worker_gas: default_decider_worker_gas(),
             wallet_key: None,
+            max_concurrent_installs: None,
+            install_timeout: default_deal_install_timeout(),
+            prefetch: DealPrefetchConfig::default(),
         }
     }
 }
 
+impl DeciderConfig {
+    pub fn validate(&self) -> eyre::Result<()> {
+        let prefix = "system_services.decider";
+        if self.max_concurrent_installs == Some(0) {
+            eyre::bail!("{prefix}.max_concurrent_installs must be positive");
+        }
+        if self.install_timeout.is_zero() {
+            eyre::bail!("{prefix}.install_timeout must be positive");
+        }
+        self.prefetch.validate()
+    }
+}
+
+/// Fetching app CIDs of matched deals from IPFS before their workers are created
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct DealPrefetchConfig {
+    pub enabled: bool,
+    /// How many prefetched deals are remembered, the oldest are forgotten first
+    pub max_deals: usize,
+    /// How long a prefetched deal is remembered while waiting for its worker
+    #[serde(with = "humantime_serde")]
+    pub ttl: Duration,
+    #[serde(with = "humantime_serde")]
+    pub fetch_timeout: Duration,
+}
+
+impl Default for DealPrefetchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_deals: 16,
+            ttl: Duration::from_secs(3600),
+            fetch_timeout: Duration::from_secs(300),
+        }
+    }
+}
+
+impl DealPrefetchConfig {
+    pub fn validate(&self) -> eyre::Result<()> {
+        let prefix = "system_services.decider.prefetch";
+        if self.max_deals == 0 {
+            eyre::bail!("{prefix}.max_deals must be positive");
+        }
+        if self.ttl.is_zero() {
+            eyre::bail!("{prefix}.ttl must be positive");
+        }
+        if self.fetch_timeout.is_zero() {
+            eyre::bail!("{prefix}.fetch_timeout must be positive");
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct RegistryConfig {
     #[serde(default = "default_registry_spell_period_sec")]
@@ -167,6 +370,14 @@ pub struct RegistryConfig {
     pub renew_period_sec: u32,
     #[serde(default = "default_registry_replicate_spell_period_sec")]
     pub replicate_period_sec: u32,
+    /// How long a record lives unless its provider renews it, the registry default if not set.
+    /// Shorter TTLs make discovery fresher at the cost of more renewals
+    #[serde(default)]
+    pub record_ttl_sec: Option<u64>,
+    /// Whether records are replicated to neighbour peers, small networks may disable it
+    /// to reduce churn
+    #[serde(default = "default_registry_replicate")]
+    pub replicate: bool,
 }
 
 impl Default for RegistryConfig {
@@ -176,6 +387,8 @@ impl Default for RegistryConfig {
             expired_period_sec: default_registry_expired_spell_period_sec(),
             renew_period_sec: default_registry_renew_spell_period_sec(),
             replicate_period_sec: default_registry_replicate_spell_period_sec(),
+            record_ttl_sec: None,
+            replicate: default_registry_replicate(),
         }
     }
 }
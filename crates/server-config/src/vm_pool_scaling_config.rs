@@ -0,0 +1,83 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Autoscaling of the AquaVM pool. Pool has fixed `aquavm_pool_size` unless `max_size` is set.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct VmPoolScalingConfig {
+    /// Pool starts with and never shrinks below that many VMs, `aquavm_pool_size` if not set
+    pub min_size: Option<usize>,
+    /// Pool grows up to that many VMs
+    pub max_size: Option<usize>,
+    /// Pool grows when at least that many particles wait for a free VM
+    pub scale_up_queue_depth: usize,
+    /// Pool grows when all VMs are busy and average interpretation time exceeds that
+    #[serde(with = "humantime_serde")]
+    pub scale_up_latency: Duration,
+    /// Pool shrinks by one VM each time it has free VMs for that long
+    #[serde(with = "humantime_serde")]
+    pub scale_down_after: Duration,
+    /// Pool grows ahead of load to keep that many VMs free, up to `max_size`
+    pub warm_vms: usize,
+}
+
+impl Default for VmPoolScalingConfig {
+    fn default() -> Self {
+        Self {
+            min_size: None,
+            max_size: None,
+            scale_up_queue_depth: 1,
+            scale_up_latency: Duration::from_secs(1),
+            scale_down_after: Duration::from_secs(60),
+            warm_vms: 1,
+        }
+    }
+}
+
+impl VmPoolScalingConfig {
+    /// Bounds of the pool, `None` if autoscaling is disabled
+    pub fn bounds(&self, pool_size: usize) -> Option<(usize, usize)> {
+        let max_size = self.max_size?;
+        Some((self.min_size.unwrap_or(pool_size), max_size))
+    }
+
+    pub fn validate(&self, pool_size: usize) -> eyre::Result<()> {
+        if let Some((min_size, max_size)) = self.bounds(pool_size) {
+            if min_size == 0 {
+                eyre::bail!("aquavm_pool_scaling.min_size must be positive");
+            }
+            if min_size > max_size {
+                eyre::bail!(
+                    "aquavm_pool_scaling.min_size ({min_size}) can't be greater than max_size ({max_size})"
+                );
+            }
+            if self.warm_vms > max_size {
+                eyre::bail!(
+                    "aquavm_pool_scaling.warm_vms ({}) can't be greater than max_size ({max_size})",
+                    self.warm_vms
+                );
+            }
+        }
+        if self.scale_up_queue_depth == 0 {
+            eyre::bail!("aquavm_pool_scaling.scale_up_queue_depth must be positive");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::defaults::{
+    default_acme_check_interval, default_acme_directory_url, default_acme_renew_before,
+    default_websocket_tls_port,
+};
+
+/// Serve websocket over TLS (wss) on a separate port
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WebsocketTlsConfig {
+    #[serde(default = "default_websocket_tls_port")]
+    pub port: u16,
+    /// PEM-encoded certificate chain, used when ACME is not configured
+    pub certificate_path: Option<PathBuf>,
+    /// PEM-encoded private key, used when ACME is not configured
+    pub private_key_path: Option<PathBuf>,
+    /// Obtain and renew certificate automatically via ACME
+    pub acme: Option<AcmeConfig>,
+}
+
+impl WebsocketTlsConfig {
+    pub fn validate(&self) -> eyre::Result<()> {
+        match (&self.acme, &self.certificate_path, &self.private_key_path) {
+            (Some(acme), None, None) => {
+                if acme.domains.is_empty() {
+                    eyre::bail!("websocket_tls.acme.domains must not be empty");
+                }
+                Ok(())
+            }
+            (None, Some(_), Some(_)) => Ok(()),
+            (Some(_), _, _) => eyre::bail!(
+                "websocket_tls: certificate_path and private_key_path can't be used together with acme"
+            ),
+            (None, _, _) => eyre::bail!(
+                "websocket_tls: either acme or both certificate_path and private_key_path must be specified"
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AcmeConfig {
+    /// Domains to issue the certificate for, the first one is advertised in external addresses
+    pub domains: Vec<String>,
+    pub contact_email: Option<String>,
+    #[serde(default = "default_acme_directory_url")]
+    pub directory_url: String,
+    #[serde(default)]
+    pub challenge: AcmeChallenge,
+    /// Certificate is renewed when it expires sooner than that
+    #[serde(default = "default_acme_renew_before")]
+    #[serde(with = "humantime_serde")]
+    pub renew_before: Duration,
+    /// How often certificate expiration is checked
+    #[serde(default = "default_acme_check_interval")]
+    #[serde(with = "humantime_serde")]
+    pub check_interval: Duration,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub enum AcmeChallenge {
+    /// Served by the http endpoint, port 80 of the domains must be forwarded to `http_port`
+    #[default]
+    #[serde(rename = "http-01")]
+    Http01,
+    /// Served by the wss listener, port 443 of the domains must be forwarded to `websocket_tls.port`
+    #[serde(rename = "tls-alpn-01")]
+    TlsAlpn01,
+}
@@ -1,9 +1,12 @@
 use connection_pool::LifecycleEvent;
 use fluence_libp2p::PeerId;
+use peer_metrics::SpellTriggerType;
+use pubsub::TopicMessage;
 use serde::{Deserialize, Serialize};
 use std::pin::Pin;
 use thiserror::Error;
 use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
 use types::peer_id;
 
 pub use crate::config::*;
@@ -14,6 +17,8 @@ pub type SpellId = String;
 pub struct TriggerEvent {
     pub spell_id: SpellId,
     pub info: TriggerInfo,
+    /// When the bus sent the event to the spell
+    pub triggered_at: Instant,
 }
 
 #[derive(Clone, Debug)]
@@ -22,6 +27,18 @@ pub enum TriggerInfo {
     Timer(TimerEvent),
     /// Event is triggered by a peer event.
     Peer(PeerEvent),
+    /// Event is triggered by a message published to a topic.
+    Topic(TopicEvent),
+}
+
+impl TriggerInfo {
+    pub fn trigger_type(&self) -> SpellTriggerType {
+        match self {
+            TriggerInfo::Timer(_) => SpellTriggerType::Timer,
+            TriggerInfo::Peer(_) => SpellTriggerType::PeerEvent,
+            TriggerInfo::Topic(_) => SpellTriggerType::Topic,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -65,6 +82,29 @@ impl PeerEvent {
     }
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Event is triggered by a message published to a pubsub topic
+pub struct TopicEvent {
+    pub topic: String,
+    /// Author of the message
+    #[serde(
+        serialize_with = "peer_id::serde::serialize",
+        deserialize_with = "peer_id::serde::deserialize"
+    )]
+    pub peer_id: PeerId,
+    pub data: String,
+}
+
+impl From<TopicMessage> for TopicEvent {
+    fn from(m: TopicMessage) -> Self {
+        Self {
+            topic: m.topic,
+            peer_id: m.source,
+            data: String::from_utf8_lossy(&m.data).into_owned(),
+        }
+    }
+}
+
 /// Types of events that are available for subscription.
 #[derive(PartialEq, Eq, Hash, Debug, Clone)]
 pub enum PeerEventType {
@@ -78,6 +118,9 @@ pub struct TriggerInfoAqua {
     timer: Vec<TimerEvent>,
     // Vec is a representation for Aqua optional values. This Vec always holds at most 1 element.
     peer: Vec<PeerEvent>,
+    // Vec is a representation for Aqua optional values. This Vec always holds at most 1 element.
+    #[serde(default)]
+    topic: Vec<TopicEvent>,
 }
 
 impl From<TriggerInfo> for TriggerInfoAqua {
@@ -86,10 +129,17 @@ impl From<TriggerInfo> for TriggerInfoAqua {
             TriggerInfo::Timer(t) => Self {
                 timer: vec![t],
                 peer: vec![], // Empty Vec corresponds to Aqua nil
+                topic: vec![],
             },
             TriggerInfo::Peer(p) => Self {
                 timer: vec![], // Empty Vec corresponds to Aqua nil
                 peer: vec![p],
+                topic: vec![],
+            },
+            TriggerInfo::Topic(t) => Self {
+                timer: vec![], // Empty Vec corresponds to Aqua nil
+                peer: vec![],
+                topic: vec![t],
             },
         }
     }
@@ -97,10 +147,13 @@ impl From<TriggerInfo> for TriggerInfoAqua {
 
 impl From<TriggerInfoAqua> for TriggerInfo {
     fn from(i: TriggerInfoAqua) -> Self {
-        match (i.timer.first(), i.peer.first()) {
-            (Some(t), None) => Self::Timer(t.clone()),
-            (None, Some(p)) => Self::Peer(p.clone()),
-            _ => unreachable!("TriggerInfoAqua should always have either timer or peer event"),
+        match (i.timer.first(), i.peer.first(), i.topic.first()) {
+            (Some(t), None, None) => Self::Timer(t.clone()),
+            (None, Some(p), None) => Self::Peer(p.clone()),
+            (None, None, Some(t)) => Self::Topic(t.clone()),
+            _ => unreachable!(
+                "TriggerInfoAqua should always have exactly one of timer, peer or topic events"
+            ),
         }
     }
 }
@@ -115,8 +168,14 @@ pub(crate) struct Command {
 pub enum Action {
     /// Subscribe a spell to a list of triggers
     Subscribe(SpellId, SpellTriggerConfigs),
-    /// Remove all subscriptions of a spell
+    /// Remove all subscriptions of a spell, except for topics
     Unsubscribe(SpellId),
+    /// Subscribe a spell to messages of a pubsub topic
+    SubscribeTopic(SpellId, String),
+    /// Unsubscribe a spell from messages of a pubsub topic
+    UnsubscribeTopic(SpellId, String),
+    /// Unsubscribe a spell from all pubsub topics
+    UnsubscribeTopics(SpellId),
     /// Actually start the scheduling
     Start,
 }
@@ -173,10 +232,33 @@ impl SpellEventBusApi {
     }
 
     /// Unsubscribe a spell from all events.
+    /// Topic subscriptions are kept, since they aren't part of the spell trigger config.
     pub async fn unsubscribe(&self, spell_id: SpellId) -> Result<(), EventBusError> {
         self.send(Action::Unsubscribe(spell_id)).await
     }
 
+    /// Subscribe a spell to messages of a pubsub topic.
+    /// The node subscribes to the topic while at least one spell is subscribed to it.
+    pub async fn subscribe_topic(
+        &self,
+        spell_id: SpellId,
+        topic: String,
+    ) -> Result<(), EventBusError> {
+        self.send(Action::SubscribeTopic(spell_id, topic)).await
+    }
+
+    pub async fn unsubscribe_topic(
+        &self,
+        spell_id: SpellId,
+        topic: String,
+    ) -> Result<(), EventBusError> {
+        self.send(Action::UnsubscribeTopic(spell_id, topic)).await
+    }
+
+    pub async fn unsubscribe_topics(&self, spell_id: SpellId) -> Result<(), EventBusError> {
+        self.send(Action::UnsubscribeTopics(spell_id)).await
+    }
+
     pub async fn start_scheduling(&self) -> Result<(), EventBusError> {
         self.send(Action::Start).await
     }
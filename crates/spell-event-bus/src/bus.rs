@@ -3,16 +3,18 @@ use crate::config::{SpellTriggerConfigs, TriggerConfig};
 use futures::stream::BoxStream;
 use futures::StreamExt;
 use futures::{future, FutureExt};
-use peer_metrics::SpellMetrics;
+use peer_metrics::{SpellMetrics, SpellTriggerType};
+use pubsub::PubSubApi;
 use std::cmp::Ordering;
 use std::collections::{BinaryHeap, HashMap, HashSet};
 use std::pin::Pin;
 use std::sync::Arc;
-use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::time::Duration;
 use thiserror::Error;
 use tokio::select;
 use tokio::sync::mpsc;
 use tokio::task;
+use tokio::time::Instant;
 use tracing::Instrument;
 
 struct PeerEventSubscribers {
@@ -99,6 +101,9 @@ struct SubscribersState {
     subscribers: PeerEventSubscribers,
     scheduled: BinaryHeap<Scheduled>,
     active: HashSet<Arc<SpellId>>,
+    topics: HashMap<String, HashSet<Arc<SpellId>>>,
+    /// Start of the current one-second window and the number of messages in it, by topic
+    topic_windows: HashMap<String, (Instant, u32)>,
 }
 
 impl SubscribersState {
@@ -107,6 +112,8 @@ impl SubscribersState {
             subscribers: PeerEventSubscribers::new(),
             scheduled: BinaryHeap::new(),
             active: HashSet::new(),
+            topics: HashMap::new(),
+            topic_windows: HashMap::new(),
         }
     }
 
@@ -144,6 +151,75 @@ impl SubscribersState {
         self.subscribers.get(event_type)
     }
 
+    /// Returns true if the topic had no subscribers before
+    fn subscribe_topic(&mut self, spell_id: SpellId, topic: String) -> bool {
+        let subscribers = self.topics.entry(topic).or_default();
+        let is_new = subscribers.is_empty();
+        subscribers.insert(Arc::new(spell_id));
+        is_new
+    }
+
+    /// Returns true if the topic has no subscribers left
+    fn unsubscribe_topic(&mut self, spell_id: &SpellId, topic: &str) -> bool {
+        let Some(subscribers) = self.topics.get_mut(topic) else {
+            return false;
+        };
+        subscribers.remove(spell_id);
+        if subscribers.is_empty() {
+            self.topics.remove(topic);
+            self.topic_windows.remove(topic);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns topics that have no subscribers left
+    fn unsubscribe_topics(&mut self, spell_id: &SpellId) -> Vec<String> {
+        let mut abandoned = vec![];
+        self.topics.retain(|topic, subscribers| {
+            subscribers.remove(spell_id);
+            if subscribers.is_empty() {
+                abandoned.push(topic.clone());
+            }
+            !subscribers.is_empty()
+        });
+        for topic in &abandoned {
+            self.topic_windows.remove(topic);
+        }
+        abandoned
+    }
+
+    fn topic_subscribers(&self, topic: &str) -> impl Iterator<Item = &Arc<SpellId>> {
+        self.topics.get(topic).into_iter().flatten()
+    }
+
+    /// Whether a message of the topic may trigger spells, at most `per_sec` messages
+    /// of each topic do so every second
+    fn allow_topic_trigger(&mut self, topic: &str, per_sec: u32, now: Instant) -> bool {
+        if !self.topics.contains_key(topic) {
+            return false;
+        }
+        let window = self
+            .topic_windows
+            .entry(topic.to_string())
+            .or_insert((now, 0));
+        if now.saturating_duration_since(window.0) >= Duration::from_secs(1) {
+            *window = (now, 0);
+        }
+        window.1 = window.1.saturating_add(1);
+        window.1 <= per_sec
+    }
+
+    fn report_subscriptions(&self, metrics: &SpellMetrics) {
+        let peer_event_spells: HashSet<_> =
+            self.subscribers.subscribers.values().flatten().collect();
+        let topic_subscriptions = self.topics.values().map(HashSet::len).sum();
+        metrics.bus_subscriptions(SpellTriggerType::Timer, self.scheduled.len());
+        metrics.bus_subscriptions(SpellTriggerType::PeerEvent, peer_event_spells.len());
+        metrics.bus_subscriptions(SpellTriggerType::Topic, topic_subscriptions);
+    }
+
     fn next_scheduled_in(&self, now: Instant) -> Option<Duration> {
         self.scheduled
             .peek()
@@ -171,12 +247,17 @@ pub struct SpellEventBus {
     send_events: mpsc::UnboundedSender<TriggerEvent>,
     /// Spell metrics
     spell_metrics: Option<SpellMetrics>,
+    /// Source of topic messages, node is subscribed to topics that have subscribed spells
+    pubsub: PubSubApi,
+    /// Messages of a topic beyond that number per second don't trigger spells
+    topic_triggers_per_sec: u32,
 }
 
 impl SpellEventBus {
     pub fn new(
         spell_metrics: Option<SpellMetrics>,
         sources: Vec<BoxStream<'static, PeerEvent>>,
+        pubsub: PubSubApi,
     ) -> (
         Self,
         SpellEventBusApi,
@@ -192,10 +273,19 @@ impl SpellEventBus {
             recv_cmd_channel,
             send_events,
             spell_metrics,
+            pubsub,
+            topic_triggers_per_sec: u32::MAX,
         };
         (this, api, recv_events)
     }
 
+    pub fn with_topic_triggers_per_sec(self, topic_triggers_per_sec: u32) -> Self {
+        Self {
+            topic_triggers_per_sec,
+            ..self
+        }
+    }
+
     pub fn start(self) -> task::JoinHandle<()> {
         task::Builder::new()
             .name("spell-bus")
@@ -212,6 +302,7 @@ impl SpellEventBus {
             .map(|source| source.fuse())
             .collect::<Vec<_>>();
         let mut sources_channel = futures::stream::select_all(sources);
+        let mut topic_messages = self.pubsub.messages().fuse();
 
         let mut state = SubscribersState::new();
         let mut is_started = false;
@@ -258,36 +349,82 @@ impl SpellEventBus {
                                 log::trace!("Unsubscribe {spell_id}");
                                 state.unsubscribe(spell_id);
                             },
+                            Action::SubscribeTopic(spell_id, topic) => {
+                                log::trace!("Subscribe {spell_id} to topic {topic}");
+                                if state.subscribe_topic(spell_id.clone(), topic.clone()) {
+                                    Self::pubsub_subscribe(&self.pubsub, topic);
+                                }
+                            },
+                            Action::UnsubscribeTopic(spell_id, topic) => {
+                                log::trace!("Unsubscribe {spell_id} from topic {topic}");
+                                if state.unsubscribe_topic(spell_id, topic) {
+                                    Self::pubsub_unsubscribe(&self.pubsub, topic);
+                                }
+                            },
+                            Action::UnsubscribeTopics(spell_id) => {
+                                log::trace!("Unsubscribe {spell_id} from all topics");
+                                for topic in state.unsubscribe_topics(spell_id) {
+                                    Self::pubsub_unsubscribe(&self.pubsub, &topic);
+                                }
+                            },
                             Action::Start => {
                                 log::trace!("Start the bus");
                                 is_started = true;
                             }
                         };
+                        if let Some(m) = &self.spell_metrics {
+                            state.report_subscriptions(m);
+                        }
                         reply.send(()).map_err(|_| {
                             BusInternalError::Reply(action)
                         })?;
                     },
                     Some(event) = sources_channel.next(), if is_started => {
+                        let metrics = self.spell_metrics.as_ref();
+                        if let Some(m) = metrics {
+                            m.bus_event_produced(SpellTriggerType::PeerEvent);
+                        }
                         for spell_id in state.subscribers(&event.get_type()) {
                             let event = TriggerInfo::Peer(event.clone());
-                            Self::trigger_spell(&send_events, spell_id, event)?;
+                            Self::trigger_spell(&send_events, metrics, spell_id, event)?;
+                        }
+                    },
+                    Some(message) = topic_messages.next(), if is_started => {
+                        let metrics = self.spell_metrics.as_ref();
+                        if let Some(m) = metrics {
+                            m.bus_event_produced(SpellTriggerType::Topic);
+                        }
+                        let event = TopicEvent::from(message);
+                        if !state.allow_topic_trigger(&event.topic, self.topic_triggers_per_sec, Instant::now()) {
+                            log::debug!("Message of topic {} doesn't trigger spells: too many messages", event.topic);
+                        } else {
+                            for spell_id in state.topic_subscribers(&event.topic) {
+                                let event = TriggerInfo::Topic(event.clone());
+                                Self::trigger_spell(&send_events, metrics, spell_id, event)?;
+                            }
                         }
                     },
                     _ = timer_task, if is_started => {
                         // The timer is triggered only if there are some spells to be awaken.
                         if let Some(scheduled_spell) = state.scheduled.pop() {
                             log::trace!("Execute: {:?}", scheduled_spell);
-                            let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs();
+                            let metrics = self.spell_metrics.as_ref();
+                            if let Some(m) = metrics {
+                                m.bus_event_produced(SpellTriggerType::Timer);
+                                m.bus_scheduling_lag(Instant::now().saturating_duration_since(scheduled_spell.run_at));
+                            }
+                            let timestamp = now_millis::now_sec();
                             let spell_id = scheduled_spell.data.id.clone();
-                            Self::trigger_spell(&send_events, &scheduled_spell.data.id, TriggerInfo::Timer(TimerEvent{ timestamp }))?;
+                            Self::trigger_spell(&send_events, metrics, &scheduled_spell.data.id, TriggerInfo::Timer(TimerEvent{ timestamp }))?;
                             // Do not reschedule the spell otherwise.
                             if let Some(rescheduled) = Scheduled::at(scheduled_spell.data, Instant::now()) {
                                 log::trace!("Reschedule: {:?}", rescheduled);
                                 state.scheduled.push(rescheduled);
                             } else {
                                 state.active.remove(&spell_id);
-                                if let Some(m) = &self.spell_metrics {
+                                if let Some(m) = metrics {
                                     m.observe_finished_spell();
+                                    state.report_subscriptions(m);
                                 }
                             }
                         }
@@ -300,18 +437,38 @@ impl SpellEventBus {
         }
     }
 
+    fn pubsub_subscribe(pubsub: &PubSubApi, topic: &str) {
+        if let Err(err) = pubsub.subscribe(topic.to_string()) {
+            log::warn!("Failed to subscribe to topic {topic}: {err}");
+        }
+    }
+
+    fn pubsub_unsubscribe(pubsub: &PubSubApi, topic: &str) {
+        if let Err(err) = pubsub.unsubscribe(topic.to_string()) {
+            log::warn!("Failed to unsubscribe from topic {topic}: {err}");
+        }
+    }
+
     #[allow(clippy::result_large_err)]
     fn trigger_spell(
         send_events: &mpsc::UnboundedSender<TriggerEvent>,
+        metrics: Option<&SpellMetrics>,
         id: &Arc<SpellId>,
         event: TriggerInfo,
     ) -> Result<(), BusInternalError> {
-        send_events
-            .send(TriggerEvent {
-                spell_id: (**id).clone(),
-                info: event.clone(),
-            })
-            .map_err(|e| BusInternalError::SendEvent((**id).clone(), event, Box::pin(e)))?;
+        let trigger_type = event.trigger_type();
+        let result = send_events.send(TriggerEvent {
+            spell_id: (**id).clone(),
+            info: event.clone(),
+            triggered_at: Instant::now(),
+        });
+        if let Some(m) = metrics {
+            match &result {
+                Ok(_) => m.bus_event_delivered(trigger_type),
+                Err(_) => m.bus_event_dropped(trigger_type),
+            }
+        }
+        result.map_err(|e| BusInternalError::SendEvent((**id).clone(), event, Box::pin(e)))?;
         Ok(())
     }
 }
@@ -416,7 +573,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_subscribe_one() {
-        let (bus, api, event_receiver) = SpellEventBus::new(None, vec![]);
+        let (bus, api, event_receiver) = SpellEventBus::new(None, vec![], PubSubApi::disabled());
         let bus = bus.start();
         let _ = api.start_scheduling().await;
         let event_stream = UnboundedReceiverStream::new(event_receiver);
@@ -439,9 +596,42 @@ mod tests {
         );
     }
 
+    #[tokio::test(start_paused = true)]
+    async fn test_periodic_virtual_time() {
+        let (bus, api, event_receiver) = SpellEventBus::new(None, vec![], PubSubApi::disabled());
+        let bus = bus.start();
+        let _ = api.start_scheduling().await;
+        let event_stream = UnboundedReceiverStream::new(event_receiver);
+
+        let period = Duration::from_secs(3600);
+        let start = Instant::now();
+        let real_start = std::time::Instant::now();
+        subscribe_periodic_endless(&api, "spell1".to_string(), period).await;
+
+        // paused time is advanced to the next timer as soon as the runtime is idle
+        let events = event_stream.take(3).collect::<Vec<TriggerEvent>>().await;
+        try_catch(
+            || {
+                assert_eq!(events.len(), 3);
+                assert!(start.elapsed() >= period * 2);
+                assert!(real_start.elapsed() < period);
+                let TriggerInfo::Timer(first) = &events[0].info else {
+                    panic!("expected timer event, got {:?}", events[0].info);
+                };
+                let TriggerInfo::Timer(last) = &events[2].info else {
+                    panic!("expected timer event, got {:?}", events[2].info);
+                };
+                assert!(last.timestamp >= first.timestamp + 2 * period.as_secs() - 10);
+            },
+            || {
+                bus.abort();
+            },
+        );
+    }
+
     #[tokio::test]
     async fn test_subscribe_many() {
-        let (bus, api, event_receiver) = SpellEventBus::new(None, vec![]);
+        let (bus, api, event_receiver) = SpellEventBus::new(None, vec![], PubSubApi::disabled());
         let bus = bus.start();
         let _ = api.start_scheduling().await;
         let event_stream = UnboundedReceiverStream::new(event_receiver);
@@ -475,7 +665,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_subscribe_oneshot() {
-        let (bus, api, event_receiver) = SpellEventBus::new(None, vec![]);
+        let (bus, api, event_receiver) = SpellEventBus::new(None, vec![], PubSubApi::disabled());
         let bus = bus.start();
         let _ = api.start_scheduling().await;
         let event_stream = UnboundedReceiverStream::new(event_receiver);
@@ -507,7 +697,8 @@ mod tests {
     async fn test_subscribe_connect() {
         let (send, recv) = mpsc::unbounded_channel();
         let recv = UnboundedReceiverStream::new(recv).boxed();
-        let (bus, api, event_receiver) = SpellEventBus::new(None, vec![recv]);
+        let (bus, api, event_receiver) =
+            SpellEventBus::new(None, vec![recv], PubSubApi::disabled());
         let mut event_stream = UnboundedReceiverStream::new(event_receiver);
         let bus = bus.start();
         let _ = api.start_scheduling().await;
@@ -538,7 +729,8 @@ mod tests {
     async fn test_unsubscribe() {
         let (send, recv) = mpsc::unbounded_channel();
         let recv = UnboundedReceiverStream::new(recv).boxed();
-        let (bus, api, mut event_receiver) = SpellEventBus::new(None, vec![recv]);
+        let (bus, api, mut event_receiver) =
+            SpellEventBus::new(None, vec![recv], PubSubApi::disabled());
         let bus = bus.start();
         let _ = api.start_scheduling().await;
 
@@ -568,7 +760,8 @@ mod tests {
     async fn test_subscribe_many_spells_with_diff_event_types() {
         let (recv, hdl) = emulate_connect(Duration::from_millis(10));
         let recv = UnboundedReceiverStream::new(recv).boxed();
-        let (bus, api, event_receiver) = SpellEventBus::new(None, vec![recv]);
+        let (bus, api, event_receiver) =
+            SpellEventBus::new(None, vec![recv], PubSubApi::disabled());
         let event_stream = UnboundedReceiverStream::new(event_receiver);
         let bus = bus.start();
         let _ = api.start_scheduling().await;
@@ -604,7 +797,7 @@ mod tests {
     #[tokio::test]
     async fn test_double_subscribe_before_run() {
         //log_utils::enable_logs();
-        let (bus, api, event_receiver) = SpellEventBus::new(None, vec![]);
+        let (bus, api, event_receiver) = SpellEventBus::new(None, vec![], PubSubApi::disabled());
         let bus = bus.start();
         let mut event_stream = UnboundedReceiverStream::new(event_receiver).fuse();
         let spell1_id = "spell1".to_string();
@@ -642,7 +835,8 @@ mod tests {
 
     #[tokio::test]
     async fn test_resubscribing_same_spell() {
-        let (bus, api, mut event_receiver) = SpellEventBus::new(None, vec![]);
+        let (bus, api, mut event_receiver) =
+            SpellEventBus::new(None, vec![], PubSubApi::disabled());
         let bus = bus.start();
         let _ = api.start_scheduling().await;
         let spell1_id = "spell1".to_string();
@@ -674,4 +868,101 @@ mod tests {
             },
         );
     }
+
+    #[tokio::test]
+    async fn test_subscribe_topic() {
+        let (pubsub_outlet, mut pubsub_commands) = mpsc::unbounded_channel();
+        let pubsub = PubSubApi::new(pubsub_outlet);
+        let (bus, api, mut event_receiver) = SpellEventBus::new(None, vec![], pubsub);
+        let bus = bus.start();
+        let _ = api.start_scheduling().await;
+
+        let Some(pubsub::Command::Messages { out: messages }) = pubsub_commands.recv().await else {
+            panic!("bus must listen to topic messages");
+        };
+
+        let spell1_id = "spell1".to_string();
+        let spell2_id = "spell2".to_string();
+        api.subscribe_topic(spell1_id.clone(), "topic".to_string())
+            .await
+            .unwrap();
+        api.subscribe_topic(spell2_id.clone(), "topic".to_string())
+            .await
+            .unwrap();
+        assert_matches!(
+            pubsub_commands.try_recv(),
+            Ok(pubsub::Command::Subscribe { topic }) if topic == "topic"
+        );
+        assert!(
+            pubsub_commands.try_recv().is_err(),
+            "node must subscribe to the topic only once"
+        );
+
+        let author = PeerId::random();
+        messages
+            .send(pubsub::TopicMessage {
+                topic: "topic".to_string(),
+                source: author,
+                data: b"hello".to_vec(),
+            })
+            .unwrap();
+        let mut triggered = vec![];
+        for _ in 0..2 {
+            let event = event_receiver.recv().await.unwrap();
+            assert_matches!(
+                event.info,
+                TriggerInfo::Topic(t) if t.peer_id == author && t.data == "hello"
+            );
+            triggered.push(event.spell_id);
+        }
+        assert!(triggered.contains(&spell1_id), "spell_1 must be triggered");
+        assert!(triggered.contains(&spell2_id), "spell_2 must be triggered");
+
+        api.unsubscribe_topics(spell1_id).await.unwrap();
+        assert!(pubsub_commands.try_recv().is_err());
+        api.unsubscribe_topic(spell2_id, "topic".to_string())
+            .await
+            .unwrap();
+        assert_matches!(
+            pubsub_commands.try_recv(),
+            Ok(pubsub::Command::Unsubscribe { topic }) if topic == "topic"
+        );
+        bus.abort();
+    }
+
+    #[tokio::test]
+    async fn test_topic_trigger_limit() {
+        let (pubsub_outlet, mut pubsub_commands) = mpsc::unbounded_channel();
+        let pubsub = PubSubApi::new(pubsub_outlet);
+        let (bus, api, mut event_receiver) = SpellEventBus::new(None, vec![], pubsub);
+        let bus = bus.with_topic_triggers_per_sec(1).start();
+        let _ = api.start_scheduling().await;
+
+        let Some(pubsub::Command::Messages { out: messages }) = pubsub_commands.recv().await else {
+            panic!("bus must listen to topic messages");
+        };
+
+        let spell_id = "spell1".to_string();
+        api.subscribe_topic(spell_id.clone(), "topic".to_string())
+            .await
+            .unwrap();
+
+        for data in [b"first", b"flood"] {
+            messages
+                .send(pubsub::TopicMessage {
+                    topic: "topic".to_string(),
+                    source: PeerId::random(),
+                    data: data.to_vec(),
+                })
+                .unwrap();
+        }
+        let event = event_receiver.recv().await.unwrap();
+        assert_matches!(event.info, TriggerInfo::Topic(t) if t.data == "first");
+        let flooded = tokio::time::timeout(Duration::from_millis(100), event_receiver.recv()).await;
+        assert!(
+            flooded.is_err(),
+            "the second message must not trigger the spell"
+        );
+        bus.abort();
+    }
 }
@@ -2,8 +2,9 @@ use crate::api::PeerEventType;
 use fluence_spell_dtos::trigger_config::{
     ClockConfig, ConnectionPoolConfig, TriggerConfig as UserTriggerConfig,
 };
-use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::time::Duration;
 use thiserror::Error;
+use tokio::time::Instant;
 
 const MAX_PERIOD_YEAR: u32 = 100;
 
@@ -22,11 +23,10 @@ pub enum ConfigError {
     InvalidEndSec,
 }
 
-/// Convert timestamp to std::time::Instant.
+/// Convert timestamp to tokio::time::Instant, so schedules follow the paused time in tests.
 /// Fails if the timestamp is in the past or overflow occurred which actually shouldn't happen.
 fn to_instant(timestamp: u64) -> Option<Instant> {
-    let target_time = UNIX_EPOCH.checked_add(Duration::from_secs(timestamp))?;
-    let duration = target_time.duration_since(SystemTime::now()).ok()?;
+    let duration = Duration::from_secs(timestamp).checked_sub(now_millis::now())?;
     Instant::now().checked_add(duration)
 }
 
@@ -175,7 +175,7 @@ impl TimerConfig {
     }
 
     pub fn into_rescheduled(self) -> Option<TimerConfig> {
-        let now = std::time::Instant::now();
+        let now = Instant::now();
         // Check that the spell is ended
         if self.end_at.map(|end_at| end_at <= now).unwrap_or(false) {
             return None;
@@ -198,7 +198,8 @@ mod trigger_config_tests {
     use crate::api::PeerEventType;
     use crate::config::{PeerEventConfig, SpellTriggerConfigs, TimerConfig, TriggerConfig};
     use std::assert_matches::assert_matches;
-    use std::time::{Duration, Instant};
+    use std::time::Duration;
+    use tokio::time::Instant;
 
     #[test]
     fn test_reschedule_ok_periodic() {
@@ -343,6 +343,7 @@ mod tests {
             management_pid,
             root_key_pair.get_peer_id(),
             Some(service_memory_limit),
+            None,
             Default::default(),
             Default::default(),
             true,
@@ -0,0 +1,318 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use audit_log::{AuditEvent, AuditEventKind, AuditLog};
+use eyre::{eyre, WrapErr};
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
+use peer_metrics::{DealPrefetchMetrics, PrefetchResult, PrefetchUsage};
+use server_config::DealPrefetchConfig;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, MissedTickBehavior};
+use types::DealId;
+
+use crate::ipfs_pinning::http_api_url;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FetchState {
+    Fetching,
+    Fetched,
+    Failed,
+}
+
+#[derive(Debug)]
+struct Entry {
+    app_cid: String,
+    state: FetchState,
+    added_at: Instant,
+}
+
+/// Prefetched deals waiting for their workers, bounded by `max_deals` and `ttl`
+#[derive(Debug)]
+struct PrefetchCache {
+    max_deals: usize,
+    ttl: Duration,
+    entries: HashMap<DealId, Entry>,
+}
+
+impl PrefetchCache {
+    fn new(max_deals: usize, ttl: Duration) -> Self {
+        Self {
+            max_deals,
+            ttl,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Returns false if the deal is already known, so it isn't fetched twice
+    fn insert(&mut self, deal_id: DealId, app_cid: String, now: Instant) -> bool {
+        if self.entries.contains_key(&deal_id) {
+            return false;
+        }
+        if self.entries.len() >= self.max_deals {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.added_at)
+                .map(|(deal_id, _)| deal_id.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        let entry = Entry {
+            app_cid,
+            state: FetchState::Fetching,
+            added_at: now,
+        };
+        self.entries.insert(deal_id, entry);
+        true
+    }
+
+    fn finish(&mut self, deal_id: &DealId, fetched: bool) {
+        if let Some(entry) = self.entries.get_mut(deal_id) {
+            entry.state = if fetched {
+                FetchState::Fetched
+            } else {
+                FetchState::Failed
+            };
+        }
+    }
+
+    /// Forgets the deal since its worker has been created
+    fn activate(&mut self, deal_id: &DealId) -> PrefetchUsage {
+        match self.entries.remove(deal_id).map(|entry| entry.state) {
+            Some(FetchState::Fetched) => PrefetchUsage::Hit,
+            Some(FetchState::Fetching) => PrefetchUsage::Pending,
+            Some(FetchState::Failed) | None => PrefetchUsage::Miss,
+        }
+    }
+
+    fn expire(&mut self, now: Instant) {
+        let ttl = self.ttl;
+        self.entries
+            .retain(|_, entry| now.duration_since(entry.added_at) < ttl);
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+/// Fetches app CIDs of matched deals into the IPFS node used by workers, so the content is
+/// available locally by the time the decider installs the deal
+pub struct DealPrefetch {
+    api_url: String,
+    config: DealPrefetchConfig,
+    client: reqwest::Client,
+    metrics: Option<DealPrefetchMetrics>,
+    audit_log: AuditLog,
+    deal_matched: mpsc::UnboundedReceiver<(DealId, String)>,
+}
+
+impl DealPrefetch {
+    /// `api_multiaddr` is the IPFS API, e.g. `/ip4/127.0.0.1/tcp/5001`
+    pub fn new(
+        api_multiaddr: &str,
+        config: DealPrefetchConfig,
+        metrics: Option<DealPrefetchMetrics>,
+        audit_log: AuditLog,
+        deal_matched: mpsc::UnboundedReceiver<(DealId, String)>,
+    ) -> eyre::Result<Self> {
+        let api_url = http_api_url(api_multiaddr)?;
+        let client = reqwest::Client::builder()
+            .timeout(config.fetch_timeout)
+            .build()
+            .wrap_err("failed to create IPFS API client")?;
+        Ok(Self {
+            api_url,
+            config,
+            client,
+            metrics,
+            audit_log,
+            deal_matched,
+        })
+    }
+
+    /// Prefetches deals as they are matched and accounts created workers as prefetch hits or misses
+    pub fn start(mut self) -> JoinHandle<()> {
+        tokio::task::Builder::new()
+            .name("deal-prefetch")
+            .spawn(async move {
+                let mut cache = PrefetchCache::new(self.config.max_deals, self.config.ttl);
+                let mut fetches = FuturesUnordered::new();
+                let mut audit_events = self.audit_log.subscribe();
+                let mut expiry = tokio::time::interval(self.config.ttl);
+                expiry.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                loop {
+                    tokio::select! {
+                        Some((deal_id, app_cid)) = self.deal_matched.recv() => {
+                            if cache.insert(deal_id.clone(), app_cid.clone(), Instant::now()) {
+                                tracing::debug!(%deal_id, app_cid, "Prefetching deal content");
+                                let client = self.client.clone();
+                                let api_url = self.api_url.clone();
+                                fetches.push(async move {
+                                    let result = fetch(&client, &api_url, &app_cid).await;
+                                    (deal_id, app_cid, result)
+                                });
+                            }
+                        },
+                        Some((deal_id, app_cid, result)) = fetches.next() => {
+                            self.on_fetched(&mut cache, deal_id, app_cid, result);
+                        },
+                        event = audit_events.recv() => match event {
+                            Ok(event) => self.on_audit_event(&mut cache, &event),
+                            Err(RecvError::Lagged(skipped)) => {
+                                tracing::warn!("Deal prefetch skipped {skipped} audit events");
+                            }
+                            Err(RecvError::Closed) => break,
+                        },
+                        _ = expiry.tick() => cache.expire(Instant::now()),
+                    }
+                    if let Some(metrics) = &self.metrics {
+                        metrics.cached(cache.len());
+                    }
+                }
+            })
+            .expect("Could not spawn task")
+    }
+
+    fn on_fetched(
+        &self,
+        cache: &mut PrefetchCache,
+        deal_id: DealId,
+        app_cid: String,
+        result: eyre::Result<()>,
+    ) {
+        let result = match result {
+            Ok(()) => {
+                tracing::info!(%deal_id, app_cid, "Prefetched deal content");
+                PrefetchResult::Fetched
+            }
+            Err(err) => {
+                tracing::warn!(%deal_id, app_cid, "Could not prefetch deal content: {err:#}");
+                PrefetchResult::Failed
+            }
+        };
+        cache.finish(&deal_id, result == PrefetchResult::Fetched);
+        if let Some(metrics) = &self.metrics {
+            metrics.prefetched(result);
+        }
+    }
+
+    fn on_audit_event(&self, cache: &mut PrefetchCache, event: &AuditEvent) {
+        if let Some(deal_id) = created_worker_deal(event) {
+            let usage = cache.activate(&deal_id);
+            tracing::debug!(%deal_id, "Deal activated, prefetch {usage:?}");
+            if let Some(metrics) = &self.metrics {
+                metrics.activated(usage);
+            }
+        }
+    }
+}
+
+/// Reads all blocks of `cid` recursively, so the IPFS node stores them without pinning.
+/// The deal pins its content on installation, and the rest is garbage collected by IPFS
+async fn fetch(client: &reqwest::Client, api_url: &str, cid: &str) -> eyre::Result<()> {
+    let url = format!("{api_url}/api/v0/refs");
+    let url = reqwest::Url::parse_with_params(&url, &[("arg", cid), ("recursive", "true")])?;
+    let response = client.post(url.clone()).send().await?;
+    let status = response.status();
+    let body = response.bytes().await?;
+    if !status.is_success() {
+        return Err(eyre!(
+            "{url} returned {status}: {}",
+            String::from_utf8_lossy(&body)
+        ));
+    }
+    // refs are streamed as json lines, errors are reported per ref
+    for line in body.split(|b| *b == b'\n').filter(|l| !l.is_empty()) {
+        let reference: serde_json::Value = serde_json::from_slice(line)?;
+        if let Some(err) = reference
+            .get("Err")
+            .and_then(|e| e.as_str())
+            .filter(|e| !e.is_empty())
+        {
+            return Err(eyre!("failed to fetch {cid}: {err}"));
+        }
+    }
+    Ok(())
+}
+
+/// Deal of a worker created by the decider, see `deal_id=` details of `WorkerCreated` events
+fn created_worker_deal(event: &AuditEvent) -> Option<DealId> {
+    if event.kind != AuditEventKind::WorkerCreated {
+        return None;
+    }
+    let details = event.details.as_deref()?;
+    let deal_id = details.strip_prefix("deal_id=")?;
+    Some(DealId::from(deal_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deal(id: &str) -> DealId {
+        DealId::from(id)
+    }
+
+    #[test]
+    fn cache_usage() {
+        let now = Instant::now();
+        let mut cache = PrefetchCache::new(4, Duration::from_secs(60));
+        assert!(cache.insert(deal("0x01"), "cid1".into(), now));
+        assert!(!cache.insert(deal("0x01"), "cid1".into(), now));
+        assert!(cache.insert(deal("0x02"), "cid2".into(), now));
+        assert!(cache.insert(deal("0x03"), "cid3".into(), now));
+        cache.finish(&deal("0x01"), true);
+        cache.finish(&deal("0x03"), false);
+
+        assert_eq!(cache.activate(&deal("0x01")), PrefetchUsage::Hit);
+        assert_eq!(cache.activate(&deal("0x02")), PrefetchUsage::Pending);
+        assert_eq!(cache.activate(&deal("0x03")), PrefetchUsage::Miss);
+        assert_eq!(cache.activate(&deal("0x04")), PrefetchUsage::Miss);
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[test]
+    fn cache_limits() {
+        let now = Instant::now();
+        let mut cache = PrefetchCache::new(2, Duration::from_secs(60));
+        cache.insert(deal("0x01"), "cid1".into(), now);
+        cache.insert(deal("0x02"), "cid2".into(), now + Duration::from_secs(10));
+        cache.insert(deal("0x03"), "cid3".into(), now + Duration::from_secs(20));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.activate(&deal("0x01")), PrefetchUsage::Miss);
+
+        cache.expire(now + Duration::from_secs(65));
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.activate(&deal("0x03")), PrefetchUsage::Pending);
+    }
+
+    #[test]
+    fn worker_deal() {
+        let event =
+            AuditEvent::new(AuditEventKind::WorkerCreated, "worker").with_details("deal_id=0xAB");
+        assert_eq!(created_worker_deal(&event), Some(deal("ab")));
+        let event = AuditEvent::new(AuditEventKind::WorkerCreated, "worker");
+        assert_eq!(created_worker_deal(&event), None);
+    }
+}
@@ -0,0 +1,110 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[derive(Default, Deserialize, Serialize)]
+struct VersionsFile {
+    #[serde(default)]
+    packages: HashMap<String, String>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// Deployed version by package name
+    packages: HashMap<String, String>,
+    /// Where versions are persisted, `None` to keep them in memory only
+    path: Option<PathBuf>,
+}
+
+/// Versions of system service packages deployed on the host. They are persisted, so pinned
+/// packages deployed by an older node binary are kept after it's upgraded
+#[derive(Debug, Clone, Default)]
+pub struct DeployedVersions {
+    inner: Arc<RwLock<Inner>>,
+}
+
+impl DeployedVersions {
+    /// Creates versions persisted in `path`, loading them from there if it exists
+    pub fn load(path: PathBuf) -> io::Result<Self> {
+        let file: VersionsFile = match std::fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => VersionsFile::default(),
+            Err(err) => return Err(err),
+        };
+        Ok(Self {
+            inner: Arc::new(RwLock::new(Inner {
+                packages: file.packages,
+                path: Some(path),
+            })),
+        })
+    }
+
+    /// Deployed version of `package`, `None` if it has never been deployed
+    pub fn get(&self, package: &str) -> Option<String> {
+        self.inner.read().packages.get(package).cloned()
+    }
+
+    pub(crate) fn set(&self, package: &str, version: &str) -> io::Result<()> {
+        let mut inner = self.inner.write();
+        inner
+            .packages
+            .insert(package.to_string(), version.to_string());
+        inner.persist()
+    }
+}
+
+impl Inner {
+    fn persist(&self) -> io::Result<()> {
+        if let Some(path) = &self.path {
+            let file = VersionsFile {
+                packages: self.packages.clone(),
+            };
+            let contents = toml::to_string(&file)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            std::fs::write(path, contents)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn persist_across_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("system_services_versions.toml");
+
+        let versions = DeployedVersions::load(path.clone()).unwrap();
+        assert_eq!(versions.get("registry"), None);
+        versions.set("registry", "0.9.4").unwrap();
+        versions.set("decider", "0.6.11").unwrap();
+        versions.set("registry", "0.9.5").unwrap();
+
+        let versions = DeployedVersions::load(path).unwrap();
+        assert_eq!(versions.get("registry").as_deref(), Some("0.9.5"));
+        assert_eq!(versions.get("decider").as_deref(), Some("0.6.11"));
+        assert_eq!(versions.get("aqua-ipfs"), None);
+    }
+}
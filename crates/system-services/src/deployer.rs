@@ -1,12 +1,16 @@
 use crate::distro::*;
 use crate::CallService;
+use crate::DeployedVersions;
+use crate::SpellProgress;
 use crate::{DeploymentStatus, PackageDistro, ServiceDistro, ServiceStatus, SpellDistro};
-use eyre::eyre;
+use eyre::{eyre, WrapErr};
+use fluence_spell_dtos::trigger_config::TriggerConfig;
 use futures::{FutureExt, StreamExt, TryStreamExt};
 use libp2p::PeerId;
 use particle_execution::FunctionOutcome;
 use particle_modules::{AddBlueprint, ModuleRepository};
 use particle_services::{ParticleAppServices, PeerScope, ServiceError, ServiceType};
+use serde::Serialize;
 use serde_json::{json, Value as JValue};
 use sorcerer::{install_spell, remove_spell};
 use spell_event_bus::api::{SpellEventBusApi, SpellId};
@@ -14,7 +18,7 @@ use spell_service_api::{CallParams, SpellServiceApi};
 use spell_storage::SpellStorage;
 use std::collections::HashMap;
 use std::thread::available_parallelism;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use uuid_utils::uuid;
 
 // default bound on the number of computations it can perform simultaneously
@@ -39,6 +43,30 @@ enum ServiceUpdateStatus {
     NotFound,
 }
 
+/// Result of updating a package with `Deployer::update_system_services`
+#[derive(Clone, Debug, Serialize)]
+pub struct PackageUpdate {
+    pub package: String,
+    /// Version deployed before the update, if known
+    pub from: Option<String>,
+    pub to: String,
+    /// Why the update failed and the package was rolled back, `None` if it's updated
+    pub error: Option<String>,
+}
+
+// Services and spells of a package as they were before an update
+struct PackageSnapshot {
+    // Blueprint ids of services by their aliases
+    services: Vec<(String, String)>,
+    spells: Vec<SpellSnapshot>,
+}
+
+struct SpellSnapshot {
+    name: String,
+    air: String,
+    trigger_config: TriggerConfig,
+}
+
 #[derive(Clone, Debug)]
 pub struct Deployer {
     // These fields are used for deploying system services
@@ -53,6 +81,9 @@ pub struct Deployer {
     management_id: PeerId,
 
     system_service_distros: SystemServiceDistros,
+    // Versions packages are pinned to by package names
+    pinned_versions: HashMap<String, String>,
+    deployed_versions: DeployedVersions,
 }
 
 impl Deployer {
@@ -77,27 +108,54 @@ impl Deployer {
             management_id,
 
             system_service_distros,
+            pinned_versions: HashMap::new(),
+            deployed_versions: DeployedVersions::default(),
         }
     }
+
+    /// Versions the packages are pinned to by package names
+    pub fn with_pinned_versions(self, pinned_versions: HashMap<String, String>) -> Self {
+        Self {
+            pinned_versions,
+            ..self
+        }
+    }
+
+    /// Where deployed versions are tracked, they are kept in memory only by default
+    pub fn with_deployed_versions(self, deployed_versions: DeployedVersions) -> Self {
+        Self {
+            deployed_versions,
+            ..self
+        }
+    }
+
+    /// Deployer of other distros, e.g. after the system services config has been reloaded
+    pub fn with_distros(self, system_service_distros: SystemServiceDistros) -> Self {
+        Self {
+            system_service_distros,
+            ..self
+        }
+    }
+
+    /// Moves distros with the `names` to a separate deployer, e.g. to deploy them in background
+    pub fn split_off(&mut self, names: &[String]) -> Self {
+        let distros = self.system_service_distros.split_off(names);
+        self.clone().with_distros(distros)
+    }
+
     pub fn versions(&self) -> Versions {
         self.system_service_distros.versions.clone()
     }
 
     pub async fn deploy_system_services(self) -> eyre::Result<()> {
-        // TODO: Can we do this without cloning?
-
-        let services = self.services.clone();
-        let root_worker_id = self.host_peer_id;
-        let call: CallService = Box::new(move |srv, fnc, args| {
-            call_service(&services, PeerScope::Host, root_worker_id, &srv, &fnc, args)
-        });
+        let call = self.host_call_service();
 
         let parallelism = available_parallelism()
             .map(|x| x.get())
             .unwrap_or(DEFAULT_PARALLELISM);
 
         futures::stream::iter(self.system_service_distros.distros.values())
-            .map(|distro| async { self.deploy_package(&call, distro.clone()).await }.boxed())
+            .map(|distro| async { self.deploy_pinned_package(&call, distro).await }.boxed())
             .boxed()
             .buffer_unordered(parallelism)
             .try_collect::<Vec<_>>()
@@ -106,6 +164,210 @@ impl Deployer {
         Ok(())
     }
 
+    /// Deploys the bundled versions of the packages, even if they are pinned to other versions.
+    /// A package that fails to update is rolled back to the services and spells it had before,
+    /// other packages are updated nevertheless
+    pub async fn update_system_services(self) -> eyre::Result<Vec<PackageUpdate>> {
+        let call = self.host_call_service();
+
+        let mut updates = vec![];
+        for package in self.system_service_distros.distros.values() {
+            let from = self.deployed_versions.get(&package.name);
+            if from.as_deref() == Some(package.version) {
+                continue;
+            }
+
+            let snapshot = self.snapshot(package);
+            let error = match self.deploy_package(&call, package.clone()).await {
+                Ok(()) => {
+                    self.deployed_versions.set(&package.name, package.version)?;
+                    tracing::info!(
+                        package = package.name,
+                        from = ?from,
+                        to = package.version,
+                        "updated a system service"
+                    );
+                    None
+                }
+                Err(err) => {
+                    tracing::error!(
+                        package = package.name,
+                        to = package.version,
+                        "couldn't update a system service (will roll it back): {err}"
+                    );
+                    self.rollback(snapshot).await.wrap_err_with(|| {
+                        format!("failed to roll back {} after a failed update", package.name)
+                    })?;
+                    Some(err.to_string())
+                }
+            };
+            updates.push(PackageUpdate {
+                package: package.name.clone(),
+                from,
+                to: package.version.to_string(),
+                error,
+            });
+        }
+        Ok(updates)
+    }
+
+    /// Checks that services of the packages respond and their periodic spells keep running.
+    /// Returns why packages have failed by package names.
+    pub fn check_system_services(
+        &self,
+        progress: &mut SpellProgress,
+        stalled_spell_periods: u32,
+    ) -> HashMap<String, String> {
+        let now = Instant::now();
+        self.system_service_distros
+            .distros
+            .values()
+            .filter_map(|package| {
+                self.check_package(package, progress, stalled_spell_periods, now)
+                    .err()
+                    .map(|err| (package.name.clone(), err.to_string()))
+            })
+            .collect()
+    }
+
+    fn check_package(
+        &self,
+        package: &PackageDistro,
+        progress: &mut SpellProgress,
+        stalled_spell_periods: u32,
+        now: Instant,
+    ) -> eyre::Result<()> {
+        for service_distro in &package.services {
+            let service_name = &service_distro.name;
+            // the service responds if it's resolved and its interface is loaded
+            self.services
+                .get_interface(PeerScope::Host, service_name.clone(), "")
+                .map_err(|err| eyre!("service {service_name} doesn't respond: {err}"))?;
+        }
+
+        for spell_distro in &package.spells {
+            let spell_name = &spell_distro.name;
+            let spell_id = self
+                .find_same_spell(spell_name)
+                .ok_or_else(|| eyre!("spell {spell_name} isn't deployed"))?;
+            let params = self.spell_params(&spell_id);
+            let trigger_config = self
+                .spells_api
+                .get_trigger_config(params.clone())
+                .map_err(|err| eyre!("spell {spell_name} doesn't respond: {err}"))?;
+            let counter = self
+                .spells_api
+                .get_counter(params)
+                .map_err(|err| eyre!("spell {spell_name} doesn't respond: {err}"))?;
+
+            let stalled_for = progress.observe(&spell_id, counter, now);
+            let period = Duration::from_secs(u64::from(trigger_config.clock.period_sec));
+            // spells without a periodic trigger may not run for any time
+            if !period.is_zero() && stalled_for > period * stalled_spell_periods {
+                return Err(eyre!(
+                    "spell {spell_name} hasn't run for {}s",
+                    stalled_for.as_secs()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes services and spells of the distros from the host, e.g. when a system service
+    /// is disabled at runtime. Packages that aren't deployed are skipped.
+    pub async fn remove_system_services(self) -> eyre::Result<()> {
+        for package in self.system_service_distros.distros.values() {
+            self.remove_package(package).await?;
+        }
+        Ok(())
+    }
+
+    async fn remove_package(&self, package: &PackageDistro) -> eyre::Result<()> {
+        // spells go first, so they don't call services that are already removed
+        for spell_distro in &package.spells {
+            let spell_name = spell_distro.name.clone();
+            if let Some(spell_id) = self.find_same_spell(&spell_name) {
+                self.remove_old_spell(&spell_name, &spell_id).await?;
+                tracing::info!(spell_name, spell_id, "removed a system spell");
+            }
+        }
+
+        for service_distro in &package.services {
+            let service_name = service_distro.name.clone();
+            let existing_service =
+                self.services
+                    .get_service_info(PeerScope::Host, service_name.clone(), "");
+            match existing_service {
+                Ok(service) if service.service_type != ServiceType::Spell => {
+                    self.services
+                        .remove_service(
+                            PeerScope::Host,
+                            &get_deployer_particle_id(),
+                            &service.id,
+                            self.host_peer_id,
+                            false,
+                        )
+                        .await?;
+                    tracing::info!(
+                        service_name,
+                        service_id = service.id,
+                        "removed a system service"
+                    );
+                }
+                _ => {
+                    tracing::debug!(service_name, "no system service found, nothing to remove");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn host_call_service(&self) -> CallService {
+        let services = self.services.clone();
+        let root_worker_id = self.host_peer_id;
+        Box::new(move |srv, fnc, args| {
+            call_service(&services, PeerScope::Host, root_worker_id, &srv, &fnc, args)
+        })
+    }
+
+    // Deploys the bundled version of the package unless it's pinned to another one.
+    // Then the deployed version is kept, it's an error if there's none.
+    async fn deploy_pinned_package(
+        &self,
+        call: &CallService,
+        package: &PackageDistro,
+    ) -> eyre::Result<()> {
+        let name = &package.name;
+        let bundled = package.version;
+        if let Some(pinned) = self.pinned_versions.get(name) {
+            if pinned != bundled {
+                match self.deployed_versions.get(name) {
+                    // already updated by system.update, so redeploying changes only the settings
+                    Some(deployed) if deployed == bundled => {}
+                    Some(deployed) => {
+                        tracing::warn!(
+                            package = name,
+                            pinned,
+                            bundled,
+                            deployed,
+                            "system service is pinned to another version than bundled; will keep the deployed one until system.update"
+                        );
+                        return Ok(());
+                    }
+                    None => {
+                        return Err(eyre!(
+                            "system service {name} is pinned to {pinned}, but {bundled} is bundled and nothing is deployed"
+                        ))
+                    }
+                }
+            }
+        }
+
+        self.deploy_package(call, package.clone()).await?;
+        self.deployed_versions.set(name, bundled)?;
+        Ok(())
+    }
+
     async fn deploy_package(&self, call: &CallService, package: PackageDistro) -> eyre::Result<()> {
         let mut services = HashMap::new();
         for service_distro in package.services {
@@ -131,7 +393,7 @@ impl Deployer {
 
     async fn deploy_system_spell(&self, spell_distro: SpellDistro) -> eyre::Result<ServiceStatus> {
         let spell_name = spell_distro.name.clone();
-        match self.find_same_spell(&spell_distro) {
+        match self.find_same_spell(&spell_name) {
             Some(spell_id) => {
                 tracing::debug!(
                     spell_name,
@@ -166,11 +428,27 @@ impl Deployer {
         }
     }
 
+    async fn update_spell(&self, spell_distro: &SpellDistro, spell_id: &str) -> eyre::Result<()> {
+        self.replace_spell(
+            spell_id,
+            spell_distro.trigger_config.clone(),
+            spell_distro.air.to_string(),
+            Some(json!(spell_distro.kv)),
+        )
+        .await
+    }
+
     // Updating spell is:
     // - updating script
     // - updating trigger config
-    // - updating kv
-    async fn update_spell(&self, spell_distro: &SpellDistro, spell_id: &str) -> eyre::Result<()> {
+    // - updating kv, if given
+    async fn replace_spell(
+        &self,
+        spell_id: &str,
+        config: TriggerConfig,
+        air: String,
+        kv: Option<JValue>,
+    ) -> eyre::Result<()> {
         // stop spell
         let result = self
             .spell_event_bus_api
@@ -183,22 +461,16 @@ impl Deployer {
             );
         }
 
-        let trigger_config = spell_event_bus::api::from_user_config(&spell_distro.trigger_config)?;
-        let params = CallParams::new(
-            self.host_peer_id,
-            PeerScope::Host,
-            spell_id.to_string(),
-            Some(format!("spell_{spell_id}_0")),
-            DEPLOYER_TTL,
-        );
+        let trigger_config = spell_event_bus::api::from_user_config(&config)?;
+        let params = self.spell_params(spell_id);
         // update trigger config
-        let config = spell_distro.trigger_config.clone();
         self.spells_api.set_trigger_config(params.clone(), config)?;
         // update spell script
-        let air = spell_distro.air.to_string();
         self.spells_api.set_script(params.clone(), air)?;
         // update init_data without affecting other keys
-        self.spells_api.update_kv(params, json!(spell_distro.kv))?;
+        if let Some(kv) = kv {
+            self.spells_api.update_kv(params, kv)?;
+        }
 
         // resubscribe spell
         if let Some(trigger_config) = trigger_config {
@@ -213,6 +485,87 @@ impl Deployer {
         Ok(())
     }
 
+    fn spell_params(&self, spell_id: &str) -> CallParams {
+        CallParams::new(
+            self.host_peer_id,
+            PeerScope::Host,
+            spell_id.to_string(),
+            Some(format!("spell_{spell_id}_0")),
+            DEPLOYER_TTL,
+        )
+    }
+
+    // Services and spells of the package deployed on the host, to roll back a failed update
+    fn snapshot(&self, package: &PackageDistro) -> PackageSnapshot {
+        let services = package
+            .services
+            .iter()
+            .filter_map(|service_distro| {
+                let service = self
+                    .services
+                    .get_service_info(PeerScope::Host, service_distro.name.clone(), "")
+                    .ok()?;
+                (service.service_type != ServiceType::Spell)
+                    .then(|| (service_distro.name.clone(), service.blueprint_id))
+            })
+            .collect();
+        let spells = package
+            .spells
+            .iter()
+            .filter_map(|spell_distro| {
+                let spell_id = self.find_same_spell(&spell_distro.name)?;
+                let params = self.spell_params(&spell_id);
+                let air = self.spells_api.get_script(params.clone()).ok()?;
+                let trigger_config = self.spells_api.get_trigger_config(params).ok()?;
+                Some(SpellSnapshot {
+                    name: spell_distro.name.clone(),
+                    air,
+                    trigger_config,
+                })
+            })
+            .collect();
+        PackageSnapshot { services, spells }
+    }
+
+    // Restores services and spells of a package from the snapshot.
+    // KV of the spells isn't restored and the package init isn't rerun.
+    async fn rollback(&self, snapshot: PackageSnapshot) -> eyre::Result<()> {
+        for (service_name, blueprint_id) in snapshot.services {
+            match self.find_same_service(service_name.clone(), &blueprint_id) {
+                ServiceUpdateStatus::NoUpdate(_) => continue,
+                ServiceUpdateStatus::NeedUpdate(service_id) => {
+                    self.services
+                        .remove_service(
+                            PeerScope::Host,
+                            &get_deployer_particle_id(),
+                            &service_id,
+                            self.host_peer_id,
+                            false,
+                        )
+                        .await?;
+                }
+                ServiceUpdateStatus::NotFound => {}
+            }
+            let service_id = self.create_service(&service_name, blueprint_id).await?;
+            tracing::info!(service_name, service_id, "rolled back a system service");
+        }
+
+        for spell in snapshot.spells {
+            let spell_name = spell.name;
+            match self.find_same_spell(&spell_name) {
+                Some(spell_id) => {
+                    self.replace_spell(&spell_id, spell.trigger_config, spell.air, None)
+                        .await?;
+                    tracing::info!(spell_name, spell_id, "rolled back a system spell");
+                }
+                None => {
+                    tracing::warn!(spell_name, "system spell is gone, can't roll it back");
+                }
+            }
+        }
+        Ok(())
+    }
+
     async fn remove_old_spell(&self, spell_name: &str, spell_id: &str) -> eyre::Result<()> {
         remove_spell(
             &get_deployer_particle_id(),
@@ -258,26 +611,26 @@ impl Deployer {
     }
 
     // Two spells are the same if they have the same alias
-    fn find_same_spell(&self, new_spell: &SpellDistro) -> Option<SpellId> {
+    fn find_same_spell(&self, spell_name: &str) -> Option<SpellId> {
         let existing_spell =
             self.services
-                .get_service_info(PeerScope::Host, new_spell.name.to_string(), "");
+                .get_service_info(PeerScope::Host, spell_name.to_string(), "");
         match existing_spell {
             Err(ServiceError::NoSuchService(_, _)) => {
-                log::debug!("no existing spell found for {}", new_spell.name);
+                log::debug!("no existing spell found for {}", spell_name);
                 None
             }
             Err(err) => {
                 log::error!(
                     "can't obtain details on a spell `{}` (will create a new one): {err}",
-                    new_spell.name
+                    spell_name
                 );
                 None
             }
             Ok(spell) if spell.service_type != ServiceType::Spell => {
                 log::warn!(
                 "alias `{}` already used for a service [{}]; it will be used for a spell, the service won't be removed",
-                new_spell.name,
+                spell_name,
                 spell.id
             );
                 None
@@ -324,6 +677,16 @@ impl Deployer {
             ServiceUpdateStatus::NotFound => {}
         }
 
+        let service_id = self.create_service(&service_name, blueprint_id).await?;
+        tracing::info!(service_name, service_id, "deployed a new service");
+        Ok(ServiceStatus::Created(service_id))
+    }
+
+    async fn create_service(
+        &self,
+        service_name: &str,
+        blueprint_id: String,
+    ) -> eyre::Result<String> {
         let service_id = self
             .services
             .create_service(
@@ -341,8 +704,7 @@ impl Deployer {
                 self.management_id,
             )
             .await?;
-        tracing::info!(service_name, service_id, "deployed a new service");
-        Ok(ServiceStatus::Created(service_id))
+        Ok(service_id)
     }
 
     fn find_same_service(&self, service_name: String, blueprint_id: &str) -> ServiceUpdateStatus {
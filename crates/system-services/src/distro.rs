@@ -8,6 +8,7 @@ use server_config::system_services_config::{
 use std::collections::HashMap;
 use std::sync::Arc;
 
+use crate::spell_overrides::apply_spell_overrides;
 use crate::{
     apply_binary_path_override, CallService, DeploymentStatus, InitService, PackageDistro,
     ServiceDistro, ServiceStatus, SpellDistro,
@@ -36,9 +37,21 @@ impl SystemServiceDistros {
         self
     }
 
+    /// Removes packages with the `names` and returns them as separate distros
+    pub fn split_off(&mut self, names: &[String]) -> Self {
+        let distros = names
+            .iter()
+            .filter_map(|name| self.distros.remove_entry(name))
+            .collect();
+        Self {
+            distros,
+            versions: self.versions.clone(),
+        }
+    }
+
     pub fn default_from(config: SystemServicesConfig) -> eyre::Result<Self> {
-        log::warn!("{:?}", config);
-        let distros: HashMap<String, PackageDistro> = config
+        log::debug!("{:?}", config);
+        let mut distros: HashMap<String, PackageDistro> = config
             .enable
             .iter()
             .map(|key| {
@@ -46,11 +59,20 @@ impl SystemServiceDistros {
                     AquaIpfs => default_aqua_ipfs_distro(&config.aqua_ipfs),
                     TrustGraph => default_trust_graph_distro(),
                     Registry => default_registry_distro(&config.registry),
-                    Decider => default_decider_distro(&config.decider, &config.connector),
+                    Decider => {
+                        let mut decider = config.decider.clone();
+                        if let Some(multiaddr) = &config.aqua_ipfs.deal_api_multiaddr {
+                            decider.worker_ipfs_multiaddr = multiaddr.clone();
+                        }
+                        default_decider_distro(&decider, &config.connector)
+                    }
                 };
                 distro.map(|d| (d.name.clone(), d))
             })
             .collect::<eyre::Result<_>>()?;
+        if let Some(dir) = &config.spell_overrides_dir {
+            apply_spell_overrides(&mut distros, dir)?;
+        }
 
         let versions = Self::versions_from(&distros);
 
@@ -195,6 +217,32 @@ pub fn default_aqua_ipfs_distro(config: &AquaIpfsConfig) -> eyre::Result<Package
 }
 
 pub fn default_registry_distro(config: &RegistryConfig) -> eyre::Result<PackageDistro> {
+    fn mk_init(name: String, record_ttl_sec: u64) -> InitService {
+        let init = move |call: &CallService, deployment: DeploymentStatus| {
+            if let Some(ServiceStatus::Created(id) | ServiceStatus::Existing(id)) =
+                deployment.services.get(&name)
+            {
+                let result = call(
+                    name.clone(),
+                    "set_expired_timeout".to_string(),
+                    vec![json!(record_ttl_sec)],
+                );
+                // records keep the default TTL, that's no reason to fail the deployment
+                if let Err(err) = result {
+                    tracing::warn!(
+                        service_id = id,
+                        service_alias = name,
+                        "couldn't set record TTL: {err}"
+                    );
+                } else {
+                    tracing::info!(service_id = id, service_alias = name, "initialized service");
+                }
+            }
+            Ok(())
+        };
+        Box::new(init)
+    }
+
     let marine_config: TomlMarineConfig = toml::from_slice(registry_distro::CONFIG)?;
     let service_distro = ServiceDistro {
         modules: registry_distro::modules(),
@@ -205,7 +253,12 @@ pub fn default_registry_distro(config: &RegistryConfig) -> eyre::Result<PackageD
     let registry_config = registry_distro::RegistryConfig {
         expired_interval: config.expired_period_sec,
         renew_interval: config.renew_period_sec,
-        replicate_interval: config.replicate_period_sec,
+        // the spell replicates once the interval has passed since the last replication
+        replicate_interval: if config.replicate {
+            config.replicate_period_sec
+        } else {
+            u32::MAX
+        },
     };
     let spell_distro = registry_distro::registry_spell(registry_config);
     let mut trigger_config = TriggerConfig::default();
@@ -213,7 +266,7 @@ pub fn default_registry_distro(config: &RegistryConfig) -> eyre::Result<PackageD
     trigger_config.clock.period_sec = config.registry_period_sec;
     let spell_distro = SpellDistro {
         name: "registry-spell".to_string(),
-        air: spell_distro.air,
+        air: spell_distro.air.to_string(),
         kv: spell_distro.init_data,
         trigger_config,
     };
@@ -222,7 +275,9 @@ pub fn default_registry_distro(config: &RegistryConfig) -> eyre::Result<PackageD
         version: registry_distro::VERSION,
         services: vec![service_distro],
         spells: vec![spell_distro],
-        init: None,
+        init: config
+            .record_ttl_sec
+            .map(|ttl| Arc::new(mk_init(Registry.to_string(), ttl))),
     };
     Ok(package)
 }
@@ -271,7 +326,7 @@ pub fn default_decider_distro(
     decider_trigger_config.clock.period_sec = decider_config.decider_period_sec;
     let spell_distro = SpellDistro {
         name: Decider.to_string(),
-        air: decider_spell_distro.air,
+        air: decider_spell_distro.air.to_string(),
         kv: decider_spell_distro.kv,
         trigger_config: decider_trigger_config,
     };
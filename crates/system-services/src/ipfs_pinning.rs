@@ -0,0 +1,281 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+use eyre::{eyre, WrapErr};
+use libp2p::multiaddr::Protocol;
+use libp2p::Multiaddr;
+use serde_json::Value as JValue;
+use server_config::IpfsPinningConfig;
+use tokio::task::JoinHandle;
+
+const IPFS_API_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug)]
+struct Pin {
+    cid: String,
+    size: u64,
+    /// When the pin has been seen for the first time
+    pinned_at: SystemTime,
+}
+
+#[derive(Debug, Default)]
+struct UnpinStats {
+    unpinned: usize,
+    reclaimed: u64,
+    pinned_size: u64,
+}
+
+/// Unpins content from the IPFS node used by aqua-ipfs according to [IpfsPinningConfig]
+pub struct IpfsPinGc {
+    api_url: String,
+    config: IpfsPinningConfig,
+    client: reqwest::Client,
+    pins: HashMap<String, Pin>,
+}
+
+impl IpfsPinGc {
+    /// `api_multiaddr` is the IPFS API, e.g. `/ip4/127.0.0.1/tcp/5001`
+    pub fn new(api_multiaddr: &str, config: IpfsPinningConfig) -> eyre::Result<Self> {
+        let api_url = http_api_url(api_multiaddr)?;
+        let client = reqwest::Client::builder()
+            .timeout(IPFS_API_TIMEOUT)
+            .build()
+            .wrap_err("failed to create IPFS API client")?;
+        Ok(Self {
+            api_url,
+            config,
+            client,
+            pins: HashMap::new(),
+        })
+    }
+
+    /// Checks pins every `config.interval`
+    pub fn start(mut self) -> JoinHandle<()> {
+        tokio::task::Builder::new()
+            .name("ipfs-pin-gc")
+            .spawn(async move {
+                let mut interval = tokio::time::interval(self.config.interval);
+                interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                loop {
+                    interval.tick().await;
+                    match self.unpin_excess().await {
+                        Ok(stats) => tracing::debug!(
+                            "Checked IPFS pins: unpinned {}, reclaimed {} bytes, {} bytes pinned",
+                            stats.unpinned,
+                            stats.reclaimed,
+                            stats.pinned_size
+                        ),
+                        Err(err) => tracing::warn!("Could not check IPFS pins: {err:#}"),
+                    }
+                }
+            })
+            .expect("Could not spawn task")
+    }
+
+    async fn unpin_excess(&mut self) -> eyre::Result<UnpinStats> {
+        let now = SystemTime::now();
+        let pinned = self.list_pins().await?;
+        self.pins.retain(|cid, _| pinned.contains(cid));
+        for cid in pinned {
+            if !self.pins.contains_key(&cid) {
+                // content is immutable, so the size is fetched once
+                let size = self.pin_size(&cid).await?;
+                let pin = Pin {
+                    cid: cid.clone(),
+                    size,
+                    pinned_at: now,
+                };
+                self.pins.insert(cid, pin);
+            }
+        }
+
+        let mut stats = UnpinStats::default();
+        let pins = self.pins.values().cloned().collect();
+        let (kept, removed) = split_excess(pins, &self.config, now);
+        for pin in removed {
+            self.call("pin/rm", &pin.cid)
+                .await
+                .wrap_err_with(|| format!("failed to unpin {}", pin.cid))?;
+            tracing::info!(cid = pin.cid, size = pin.size, "unpinned IPFS content");
+            self.pins.remove(&pin.cid);
+            stats.unpinned += 1;
+            stats.reclaimed += pin.size;
+        }
+        stats.pinned_size = kept.iter().map(|p| p.size).sum();
+
+        Ok(stats)
+    }
+
+    /// CIDs of recursive pins, i.e. pinned by users rather than as parts of other pins
+    async fn list_pins(&self) -> eyre::Result<Vec<String>> {
+        let url = format!("{}/api/v0/pin/ls?type=recursive", self.api_url);
+        let result = self.post(url).await?;
+        let keys = result
+            .get("Keys")
+            .and_then(JValue::as_object)
+            .ok_or_else(|| eyre!("unexpected pin/ls response: {result}"))?;
+        Ok(keys.keys().cloned().collect())
+    }
+
+    async fn pin_size(&self, cid: &str) -> eyre::Result<u64> {
+        let result = self.call("files/stat", &format!("/ipfs/{cid}")).await?;
+        result
+            .get("CumulativeSize")
+            .and_then(JValue::as_u64)
+            .ok_or_else(|| eyre!("unexpected files/stat response for {cid}: {result}"))
+    }
+
+    async fn call(&self, method: &str, arg: &str) -> eyre::Result<JValue> {
+        let url = format!("{}/api/v0/{method}", self.api_url);
+        let url = reqwest::Url::parse_with_params(&url, &[("arg", arg)])?;
+        self.post(url.to_string()).await
+    }
+
+    async fn post(&self, url: String) -> eyre::Result<JValue> {
+        let response = self.client.post(&url).send().await?;
+        let status = response.status();
+        let body = response.bytes().await?;
+        if !status.is_success() {
+            return Err(eyre!(
+                "{url} returned {status}: {}",
+                String::from_utf8_lossy(&body)
+            ));
+        }
+        Ok(serde_json::from_slice(&body)?)
+    }
+}
+
+/// Splits pins into kept and removed ones, so that kept ones satisfy `pin_lifetime` and
+/// `max_pinned_size` of `config`. The least recently pinned ones are removed first.
+fn split_excess(
+    mut pins: Vec<Pin>,
+    config: &IpfsPinningConfig,
+    now: SystemTime,
+) -> (Vec<Pin>, Vec<Pin>) {
+    // most recent first, ties are broken by cid to unpin in a stable order
+    pins.sort_by(|a, b| {
+        b.pinned_at
+            .cmp(&a.pinned_at)
+            .then_with(|| a.cid.cmp(&b.cid))
+    });
+
+    let mut total = 0u64;
+    pins.into_iter().partition(|pin| {
+        let age = now.duration_since(pin.pinned_at).unwrap_or(Duration::ZERO);
+        if config.pin_lifetime.is_some_and(|max| age > max) {
+            return false;
+        }
+        total = total.saturating_add(pin.size);
+        config
+            .max_pinned_size
+            .map_or(true, |max| total <= max.as_u64())
+    })
+}
+
+/// Converts an IPFS API multiaddr, e.g. `/dns4/ipfs.fluence.dev/tcp/5001`, to an http url
+pub(crate) fn http_api_url(multiaddr: &str) -> eyre::Result<String> {
+    let maddr: Multiaddr = multiaddr
+        .parse()
+        .wrap_err_with(|| format!("invalid IPFS API multiaddr {multiaddr}"))?;
+    let mut host = None;
+    let mut port = None;
+    for protocol in maddr.iter() {
+        match protocol {
+            Protocol::Ip4(ip) => host = Some(ip.to_string()),
+            Protocol::Ip6(ip) => host = Some(format!("[{ip}]")),
+            Protocol::Dns(name) | Protocol::Dns4(name) | Protocol::Dns6(name) => {
+                host = Some(name.to_string())
+            }
+            Protocol::Tcp(p) => port = Some(p),
+            _ => {}
+        }
+    }
+    match (host, port) {
+        (Some(host), Some(port)) => Ok(format!("http://{host}:{port}")),
+        _ => Err(eyre!(
+            "IPFS API multiaddr {multiaddr} must contain a host and a tcp port"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytesize::ByteSize;
+
+    use super::*;
+
+    fn pin(cid: &str, size: u64, age_secs: u64, now: SystemTime) -> Pin {
+        Pin {
+            cid: cid.to_string(),
+            size,
+            pinned_at: now - Duration::from_secs(age_secs),
+        }
+    }
+
+    fn cids(pins: &[Pin]) -> Vec<&str> {
+        pins.iter().map(|p| p.cid.as_str()).collect()
+    }
+
+    #[test]
+    fn split_excess_least_recent_first() {
+        let now = SystemTime::now();
+        let pins = vec![
+            pin("old", 10, 300, now),
+            pin("new", 10, 10, now),
+            pin("mid", 10, 100, now),
+            pin("ancient", 1, 10_000, now),
+        ];
+        let config = IpfsPinningConfig {
+            pin_lifetime: Some(Duration::from_secs(1000)),
+            max_pinned_size: Some(ByteSize::b(25)),
+            ..<_>::default()
+        };
+
+        let (kept, removed) = split_excess(pins, &config, now);
+        assert_eq!(cids(&kept), vec!["new", "mid"]);
+        assert_eq!(cids(&removed), vec!["old", "ancient"]);
+    }
+
+    #[test]
+    fn split_excess_without_limits() {
+        let now = SystemTime::now();
+        let pins = vec![pin("a", 10, 300, now), pin("b", 10, 10, now)];
+
+        let (kept, removed) = split_excess(pins, &IpfsPinningConfig::default(), now);
+        assert_eq!(cids(&kept), vec!["b", "a"]);
+        assert!(removed.is_empty());
+    }
+
+    #[test]
+    fn api_url() {
+        assert_eq!(
+            http_api_url("/dns4/ipfs.fluence.dev/tcp/5001").unwrap(),
+            "http://ipfs.fluence.dev:5001"
+        );
+        assert_eq!(
+            http_api_url("/ip4/127.0.0.1/tcp/5001").unwrap(),
+            "http://127.0.0.1:5001"
+        );
+        assert_eq!(
+            http_api_url("/ip6/::1/tcp/5001").unwrap(),
+            "http://[::1]:5001"
+        );
+        assert!(http_api_url("/ip4/127.0.0.1/udp/5001").is_err());
+    }
+}
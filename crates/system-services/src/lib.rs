@@ -1,12 +1,21 @@
 #![feature(try_blocks)]
 #![feature(result_option_inspect)]
 
+mod deal_prefetch;
+mod deployed_versions;
 mod deployer;
 mod distro;
+mod ipfs_pinning;
+mod spell_overrides;
+mod supervision;
 
-pub use deployer::Deployer;
+pub use deal_prefetch::DealPrefetch;
+pub use deployed_versions::DeployedVersions;
+pub use deployer::{Deployer, PackageUpdate};
 pub use distro::SystemServiceDistros;
 pub use distro::Versions;
+pub use ipfs_pinning::IpfsPinGc;
+pub use supervision::SpellProgress;
 
 use fluence_app_service::TomlMarineConfig;
 use fluence_spell_dtos::trigger_config::TriggerConfig;
@@ -94,7 +103,7 @@ pub struct SpellDistro {
     /// The name of the spell which is also used as an alias for the spell
     pub name: String,
     /// The AIR script of the spell
-    pub air: &'static str,
+    pub air: String,
     /// Initial values for the KV storage of the spell
     /// Note that these values are saved as JSON strings
     pub kv: HashMap<&'static str, Value>,
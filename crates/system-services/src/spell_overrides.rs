@@ -0,0 +1,158 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use eyre::{eyre, WrapErr};
+use fluence_spell_dtos::trigger_config::TriggerConfig;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::PackageDistro;
+
+const SCRIPT_EXTENSION: &str = "air";
+const TRIGGER_CONFIG_EXTENSION: &str = "toml";
+
+/// Replaces scripts and trigger configs of system spells with the ones found in `dir`:
+/// `<spell name>.air` for the script, `<spell name>.toml` for the trigger config.
+/// Other files are ignored. It's an error if an override doesn't match any spell of the
+/// `packages` or is invalid, so a typo doesn't silently leave the bundled spell in place.
+pub(crate) fn apply_spell_overrides(
+    packages: &mut HashMap<String, PackageDistro>,
+    dir: &Path,
+) -> eyre::Result<()> {
+    let entries = std::fs::read_dir(dir)
+        .wrap_err_with(|| format!("failed to read spell overrides dir {}", dir.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        let (Some(spell_name), Some(extension)) = (
+            path.file_stem().and_then(|s| s.to_str()),
+            path.extension().and_then(|e| e.to_str()),
+        ) else {
+            continue;
+        };
+        if extension != SCRIPT_EXTENSION && extension != TRIGGER_CONFIG_EXTENSION {
+            continue;
+        }
+
+        let spell = packages
+            .values_mut()
+            .flat_map(|package| package.spells.iter_mut())
+            .find(|spell| spell.name == spell_name)
+            .ok_or_else(|| {
+                eyre!(
+                    "spell override {} doesn't match any enabled system spell",
+                    path.display()
+                )
+            })?;
+        let contents = std::fs::read_to_string(&path)
+            .wrap_err_with(|| format!("failed to read spell override {}", path.display()))?;
+        if extension == SCRIPT_EXTENSION {
+            validate_script(&contents)
+                .wrap_err_with(|| format!("invalid spell script {}", path.display()))?;
+            spell.air = contents;
+        } else {
+            spell.trigger_config = parse_trigger_config(&contents)
+                .wrap_err_with(|| format!("invalid spell trigger config {}", path.display()))?;
+        }
+        tracing::info!(
+            spell_name,
+            path = %path.display(),
+            "system spell is overridden"
+        );
+    }
+    Ok(())
+}
+
+// There's no AIR parser at hand, so the script is only checked to be a non-empty
+// s-expression with balanced parentheses; AquaVM reports the rest on the first run
+fn validate_script(air: &str) -> eyre::Result<()> {
+    let air = air.trim();
+    if !air.starts_with('(') {
+        return Err(eyre!("script must be a non-empty AIR instruction"));
+    }
+
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in air.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '(' if !in_string => depth += 1,
+            ')' if !in_string => {
+                depth = depth
+                    .checked_sub(1)
+                    .ok_or_else(|| eyre!("unbalanced parentheses"))?;
+            }
+            _ => {}
+        }
+    }
+    if in_string {
+        return Err(eyre!("unterminated string literal"));
+    }
+    if depth != 0 {
+        return Err(eyre!("unbalanced parentheses"));
+    }
+    Ok(())
+}
+
+fn parse_trigger_config(contents: &str) -> eyre::Result<TriggerConfig> {
+    let config: TriggerConfig = toml::from_str(contents)?;
+    spell_event_bus::api::from_user_config(&config)?;
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scripts() {
+        assert!(validate_script("(null)").is_ok());
+        assert!(
+            validate_script("(seq (call %init_peer_id% (\"op\" \"noop\") [\")\"]) (null))").is_ok()
+        );
+        assert!(validate_script("").is_err());
+        assert!(validate_script("null").is_err());
+        assert!(validate_script("(seq (null)").is_err());
+        assert!(validate_script("(null))").is_err());
+        assert!(validate_script("(call %init_peer_id% (\"op\" \"noop) [])").is_err());
+    }
+
+    #[test]
+    fn trigger_configs() {
+        let config = parse_trigger_config(
+            r#"
+            [clock]
+            start_sec = 1
+            end_sec = 0
+            period_sec = 60
+
+            [connections]
+            connect = false
+            disconnect = false
+
+            [blockchain]
+            start_block = 0
+            end_block = 0
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.clock.period_sec, 60);
+
+        assert!(parse_trigger_config("[clock]\nstart_sec = \"soon\"").is_err());
+    }
+}
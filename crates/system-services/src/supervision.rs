@@ -0,0 +1,77 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Execution counters of system spells seen by the checks, to find spells that stopped running
+#[derive(Debug, Default)]
+pub struct SpellProgress {
+    // the last seen counter and since when it's seen by spell ids
+    counters: HashMap<String, (Option<u32>, Instant)>,
+}
+
+impl SpellProgress {
+    /// Records `counter` of the spell, returns for how long it hasn't changed
+    pub(crate) fn observe(
+        &mut self,
+        spell_id: &str,
+        counter: Option<u32>,
+        now: Instant,
+    ) -> Duration {
+        match self.counters.get(spell_id) {
+            Some((seen, since)) if *seen == counter => now.saturating_duration_since(*since),
+            _ => {
+                self.counters.insert(spell_id.to_string(), (counter, now));
+                Duration::ZERO
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stalled_counter() {
+        let mut progress = SpellProgress::default();
+        let start = Instant::now();
+        let later = |secs| start + Duration::from_secs(secs);
+
+        assert_eq!(progress.observe("spell", None, start), Duration::ZERO);
+        assert_eq!(
+            progress.observe("spell", None, later(10)),
+            Duration::from_secs(10)
+        );
+        assert_eq!(
+            progress.observe("spell", Some(1), later(20)),
+            Duration::ZERO
+        );
+        assert_eq!(
+            progress.observe("spell", Some(1), later(50)),
+            Duration::from_secs(30)
+        );
+        assert_eq!(
+            progress.observe("other", Some(1), later(50)),
+            Duration::ZERO
+        );
+        assert_eq!(
+            progress.observe("spell", Some(2), later(60)),
+            Duration::ZERO
+        );
+    }
+}
@@ -30,10 +30,12 @@
 extern crate fstrings;
 
 pub use service::*;
+pub use spell::*;
 pub use utils::*;
 
 pub use crate::misc::*;
 
 mod misc;
 mod service;
+mod spell;
 mod utils;
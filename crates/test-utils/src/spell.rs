@@ -0,0 +1,229 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::time::Duration;
+
+use ccp_shared::types::CUID;
+use eyre::{eyre, WrapErr};
+use fluence_spell_dtos::trigger_config::{ClockConfig, TriggerConfig};
+use hex::FromHex;
+use maplit::hashmap;
+use serde_json::{json, Value as JValue};
+
+use connected_client::ConnectedClient;
+
+pub type SpellId = String;
+pub type WorkerPeerId = String;
+
+pub const DEFAULT_DEAL_ID: &str = "default_deal";
+
+/// Compute unit every test node has
+pub fn default_cu_ids() -> Vec<CUID> {
+    vec![
+        CUID::from_hex("54ae1b506c260367a054f80800a545f23e32c6bc4a8908c9a794cb8dad23e5ea")
+            .expect("valid CUID"),
+    ]
+}
+
+pub fn clock_config(period_sec: u32, start_sec: u32, end_sec: u32) -> TriggerConfig {
+    TriggerConfig {
+        clock: ClockConfig {
+            start_sec,
+            end_sec,
+            period_sec,
+        },
+        ..Default::default()
+    }
+}
+
+/// Creates a worker for `deal_id` on the client's relay, or returns the existing one
+pub async fn create_worker_with(
+    client: &mut ConnectedClient,
+    deal_id: &str,
+    cu_ids: Vec<CUID>,
+) -> WorkerPeerId {
+    let data = hashmap! {
+        "deal_id" => json!(deal_id),
+        "relay" => json!(client.node.to_string()),
+        "client" => json!(client.peer_id.to_string()),
+        "cu_ids" => json!(cu_ids)
+    };
+
+    let response = client
+        .execute_particle(
+            r#"
+            (seq
+                (xor
+                    (call relay ("worker" "create") [deal_id cu_ids] worker_peer_id)
+                    (seq
+                        (call relay ("worker" "get_worker_id") [deal_id] get_worker_peer_id)
+                        (ap get_worker_peer_id.$.[0] worker_peer_id)
+                    )
+                )
+                (call client ("return" "") [worker_peer_id])
+            )"#,
+            data,
+        )
+        .await
+        .unwrap();
+
+    let worker_id = response[0]
+        .as_str()
+        .expect("worker_id is in response")
+        .to_string();
+    assert_ne!(worker_id.len(), 0);
+
+    worker_id
+}
+
+/// Same as [create_worker_with] with the default compute unit, [DEFAULT_DEAL_ID] if `deal_id` isn't set
+pub async fn create_worker(client: &mut ConnectedClient, deal_id: Option<String>) -> WorkerPeerId {
+    let deal_id = deal_id.unwrap_or_else(|| DEFAULT_DEAL_ID.to_string());
+    create_worker_with(client, &deal_id, default_cu_ids()).await
+}
+
+/// Installs spell on `worker_id`, `alias` is optional
+pub async fn install_spell(
+    client: &mut ConnectedClient,
+    worker_id: &str,
+    script: &str,
+    config: TriggerConfig,
+    init_data: JValue,
+    alias: Option<&str>,
+) -> SpellId {
+    let install = if alias.is_some() {
+        r#"(call worker_id ("spell" "install") [script data config alias] spell_id)"#
+    } else {
+        r#"(call worker_id ("spell" "install") [script data config] spell_id)"#
+    };
+    let script_air = f!(r#"
+    (seq
+        (call relay ("op" "noop") [])
+        (seq
+            {install}
+            (call client ("return" "") [spell_id])
+        )
+    )"#);
+
+    let data = hashmap! {
+        "script" => json!(script),
+        "config" => json!(config),
+        "client" => json!(client.peer_id.to_string()),
+        "relay" => json!(client.node.to_string()),
+        "worker_id" => json!(worker_id),
+        "alias" => json!(alias),
+        "data" => init_data,
+    };
+
+    let response = client.execute_particle(script_air, data).await.unwrap();
+
+    let spell_id = response[0]
+        .as_str()
+        .expect("spell_id is in response")
+        .to_string();
+    assert_ne!(spell_id.len(), 0);
+
+    spell_id
+}
+
+/// Creates a worker for `deal_id` and installs spell on it
+pub async fn create_spell(
+    client: &mut ConnectedClient,
+    script: &str,
+    config: TriggerConfig,
+    init_data: JValue,
+    deal_id: Option<String>,
+) -> (SpellId, WorkerPeerId) {
+    let worker_id = create_worker(client, deal_id).await;
+    let spell_id = install_spell(client, &worker_id, script, config, init_data, None).await;
+    (spell_id, worker_id)
+}
+
+/// Same as [create_spell], but the spell is installed under `alias`
+pub async fn create_spell_with_alias(
+    client: &mut ConnectedClient,
+    script: &str,
+    config: TriggerConfig,
+    init_data: JValue,
+    deal_id: Option<String>,
+    alias: &str,
+) -> (SpellId, WorkerPeerId) {
+    let worker_id = create_worker(client, deal_id).await;
+    let spell_id = install_spell(client, &worker_id, script, config, init_data, Some(alias)).await;
+    (spell_id, worker_id)
+}
+
+/// How many times the spell was executed, `None` if the counter isn't set yet
+pub async fn spell_counter(
+    client: &mut ConnectedClient,
+    worker_id: &str,
+    spell_id: &str,
+) -> eyre::Result<Option<u64>> {
+    let data = hashmap! {
+        "spell_id" => json!(spell_id),
+        "worker" => json!(worker_id),
+        "client" => json!(client.peer_id.to_string()),
+        "relay" => json!(client.node.to_string()),
+    };
+    let response = client
+        .execute_particle(
+            r#"
+        (seq
+            (seq
+                (call relay ("op" "noop") [])
+                (call worker (spell_id "get_u32") ["hw_counter"] counter)
+            )
+            (call client ("return" "") [counter])
+        )"#,
+            data,
+        )
+        .await
+        .wrap_err("get spell counter")?;
+
+    let counter = &response[0];
+    if !counter["success"].as_bool().unwrap_or(false) {
+        return Ok(None);
+    }
+    counter["value"]
+        .as_u64()
+        .map(Some)
+        .ok_or_else(|| eyre!("unexpected counter response: {:?}", response))
+}
+
+/// Polls spell counter until the spell is executed at least `times` times, returns the counter
+pub async fn wait_spell_executed(
+    client: &mut ConnectedClient,
+    worker_id: &str,
+    spell_id: &str,
+    times: u64,
+    timeout: Duration,
+) -> eyre::Result<u64> {
+    let poll = async {
+        loop {
+            if let Some(counter) = spell_counter(client, worker_id, spell_id).await? {
+                if counter >= times {
+                    return eyre::Ok(counter);
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    };
+    tokio::time::timeout(timeout, poll)
+        .await
+        .wrap_err_with(|| {
+            format!("spell {spell_id} wasn't executed {times} times in {timeout:?}")
+        })?
+}
@@ -14,6 +14,7 @@
  * limitations under the License.
  */
 
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::{convert::Infallible, task::Waker, time::Duration};
 
@@ -38,6 +39,10 @@ impl AquaRuntime for EasyVM {
         Ok(EasyVM { delay })
     }
 
+    fn with_interpreter(delay: Option<Duration>, _: PathBuf) -> Option<Duration> {
+        delay
+    }
+
     fn into_effects(
         outcome: Result<RawAVMOutcome, Self::Error>,
         _particle_id: String,
@@ -1,5 +1,8 @@
 mod deal_id;
+pub mod management;
+pub mod particle_class;
 pub mod peer_id;
 pub mod peer_scope;
 
 pub use deal_id::DealId;
+pub use particle_class::ParticleClass;
@@ -0,0 +1,59 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use libp2p_identity::PeerId;
+use serde::{Deserialize, Serialize};
+
+/// What a management key is allowed to do besides calls open to everyone
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    /// Install, update and remove spells and services on the host, i.e. system services
+    DeploySystemServices,
+    /// Add peers to the denylist and allowlist or remove them from there
+    BanPeers,
+    /// Drain connections of the node
+    DrainNode,
+    /// Query and verify the audit log
+    ReadAuditLog,
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Capability::DeploySystemServices => "deploy_system_services",
+            Capability::BanPeers => "ban_peers",
+            Capability::DrainNode => "drain_node",
+            Capability::ReadAuditLog => "read_audit_log",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Peer id with a subset of the management peer permissions
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ManagementKey {
+    #[serde(
+        serialize_with = "crate::peer_id::serde::serialize",
+        deserialize_with = "crate::peer_id::serde::deserialize"
+    )]
+    pub peer_id: PeerId,
+    #[serde(default)]
+    pub capabilities: BTreeSet<Capability>,
+}
@@ -0,0 +1,38 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use serde::{Deserialize, Serialize};
+
+/// Class of a particle that defines its priority in the execution queue
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParticleClass {
+    /// Particles of spells installed on the host, e.g. decider
+    SystemSpell,
+    /// Particles of spells installed on workers by deals
+    DealSpell,
+    /// Everything else, i.e. particles sent by clients
+    Client,
+}
+
+impl ParticleClass {
+    /// Default priority order, highest first
+    pub const DEFAULT_ORDER: [ParticleClass; 3] = [
+        ParticleClass::SystemSpell,
+        ParticleClass::DealSpell,
+        ParticleClass::Client,
+    ];
+}
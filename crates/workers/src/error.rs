@@ -15,6 +15,7 @@
  */
 
 use core_manager::errors::AcquireError;
+use key_encryption::KeyEncryptionError;
 use libp2p::PeerId;
 use std::path::PathBuf;
 use thiserror::Error;
@@ -82,6 +83,19 @@ pub enum KeyStorageError {
 
     #[error("Keypair for peer_id {0} not found")]
     KeypairNotFound(PeerId),
+    #[error("Keypair {path:?} is encrypted, but keystore isn't configured")]
+    EncryptedKeypair { path: PathBuf },
+    #[error("Error encrypting keypair: {err}")]
+    EncryptKeypair {
+        #[source]
+        err: KeyEncryptionError,
+    },
+    #[error("Error decrypting keypair {path:?}: {err}")]
+    DecryptKeypair {
+        path: PathBuf,
+        #[source]
+        err: KeyEncryptionError,
+    },
 }
 
 #[derive(Debug, Error)]
@@ -116,6 +130,28 @@ pub enum WorkersError {
     },
     #[error("Worker for {deal_id} already exists")]
     WorkerAlreadyExists { deal_id: DealId },
+    #[error("Can't create worker for {deal_id}: the node already hosts {limit} workers")]
+    WorkersLimitReached { deal_id: DealId, limit: usize },
+    #[error("Can't create worker for {deal_id}: the node is in maintenance")]
+    Maintenance { deal_id: DealId },
+    #[error("Can't create worker for {deal_id}: the deal isn't in the deal allowlist")]
+    DealNotAllowed { deal_id: DealId },
+    #[error(
+        "Can't create worker for {deal_id}: {requested} GPUs requested, {available} available"
+    )]
+    GpusUnavailable {
+        deal_id: DealId,
+        requested: usize,
+        available: usize,
+    },
+    #[error("Can't activate worker {worker_id}: GPU {device} isn't declared by the node")]
+    GpuNotDeclared { worker_id: WorkerId, device: String },
+    #[error("Can't activate worker {worker_id}: GPU {device} is assigned to worker {other}")]
+    GpuTaken {
+        worker_id: WorkerId,
+        device: String,
+        other: WorkerId,
+    },
     #[error("Worker for deal_id {0} not found")]
     WorkerNotFoundByDeal(DealId),
     #[error("Worker {0} not found")]
@@ -138,6 +174,17 @@ pub enum WorkersError {
         #[source]
         err: std::io::Error,
     },
+    #[error("Error serializing deal allowlist: {err}")]
+    SerializeDealAllowlist {
+        #[source]
+        err: toml::ser::Error,
+    },
+    #[error("Error writing deal allowlist to {path:?}: {err}")]
+    WriteErrorDealAllowlist {
+        path: PathBuf,
+        #[source]
+        err: std::io::Error,
+    },
     #[error("Keypair for peer_id {0} not found")]
     KeypairNotFound(PeerId),
     #[error("Failed to create runtime for worker {worker_id}: {err}")]
@@ -20,9 +20,12 @@ use std::str::FromStr;
 
 use parking_lot::RwLock;
 
-use crate::persistence::{load_persisted_key_pairs, persist_keypair, remove_keypair};
+use crate::persistence::{
+    load_persisted_key_pairs, persist_keypair, remove_keypair, PersistedKeypair,
+};
 use crate::KeyStorageError;
 use fluence_keypair::{KeyFormat, KeyPair};
+use key_encryption::KeyEncryption;
 use types::peer_scope::{PeerScope, WorkerId};
 
 pub struct KeyStorage {
@@ -30,25 +33,49 @@ pub struct KeyStorage {
     worker_key_pairs: RwLock<HashMap<WorkerId, KeyPair>>,
     key_pairs_dir: PathBuf,
     pub root_key_pair: KeyPair,
+    /// Worker key pairs are encrypted on disk if set
+    encryption: Option<KeyEncryption>,
 }
 
 impl KeyStorage {
     pub async fn from_path(key_pairs_dir: PathBuf, root_key_pair: KeyPair) -> eyre::Result<Self> {
+        Self::load(key_pairs_dir, root_key_pair, None).await
+    }
+
+    /// Loads persisted worker key pairs, decrypting them with `encryption`.
+    /// If `encryption` is set, plaintext key pairs are encrypted in place.
+    pub async fn load(
+        key_pairs_dir: PathBuf,
+        root_key_pair: KeyPair,
+        encryption: Option<KeyEncryption>,
+    ) -> eyre::Result<Self> {
         let key_pairs = load_persisted_key_pairs(key_pairs_dir.as_path()).await?;
 
         let mut worker_key_pairs = HashMap::with_capacity(key_pairs.len());
-        for (keypair, path) in key_pairs {
-            let format = KeyFormat::from_str(&keypair.key_format)
-                .map_err(|err| KeyStorageError::PersistedKeypairInvalidKeyFormat { err, path })?;
-            let keypair: KeyPair = KeyPair::from_secret_key(keypair.private_key_bytes, format)?;
+        for (persisted, path) in key_pairs {
+            let was_encrypted = persisted.is_encrypted();
+            let persisted = persisted.decrypt(encryption.as_ref(), &path)?;
+            let format = KeyFormat::from_str(&persisted.key_format).map_err(|err| {
+                KeyStorageError::PersistedKeypairInvalidKeyFormat {
+                    err,
+                    path: path.clone(),
+                }
+            })?;
+            let keypair: KeyPair =
+                KeyPair::from_secret_key(persisted.private_key_bytes.clone(), format)?;
 
             let worker_id: WorkerId = keypair.get_peer_id().into();
+            if let Some(encryption) = encryption.as_ref().filter(|_| !was_encrypted) {
+                log::info!("Encrypting plaintext keypair of worker {worker_id} at {path:?}");
+                persist_keypair(&key_pairs_dir, worker_id, persisted.encrypt(encryption)?).await?;
+            }
             worker_key_pairs.insert(worker_id, keypair);
         }
         Ok(Self {
             worker_key_pairs: RwLock::new(worker_key_pairs),
             key_pairs_dir,
             root_key_pair,
+            encryption,
         })
     }
 
@@ -65,7 +92,11 @@ impl KeyStorage {
     pub async fn create_key_pair(&self) -> Result<KeyPair, KeyStorageError> {
         let keypair = KeyPair::generate_ed25519();
         let worker_id: WorkerId = keypair.get_peer_id().into();
-        persist_keypair(&self.key_pairs_dir, worker_id, (&keypair).try_into()?).await?;
+        let mut persisted: PersistedKeypair = (&keypair).try_into()?;
+        if let Some(encryption) = &self.encryption {
+            persisted = persisted.encrypt(encryption)?;
+        }
+        persist_keypair(&self.key_pairs_dir, worker_id, persisted).await?;
         let mut guard = self.worker_key_pairs.write();
         guard.insert(worker_id, keypair.clone());
         Ok(keypair)
@@ -81,7 +112,10 @@ impl KeyStorage {
 
 #[cfg(test)]
 mod tests {
+    use crate::persistence::keypair_file_name;
     use crate::KeyStorage;
+    use key_encryption::KeyEncryption;
+    use std::os::unix::fs::PermissionsExt;
     use tempfile::tempdir;
 
     #[tokio::test]
@@ -232,4 +266,64 @@ mod tests {
             None
         );
     }
+
+    #[tokio::test]
+    async fn test_encryption() {
+        let temp_dir = tempdir().expect("Failed to create temporary directory");
+        let key_pairs_dir = temp_dir.path().to_path_buf();
+        let root_key_pair = fluence_keypair::KeyPair::generate_ed25519();
+
+        // a plaintext key pair persisted before encryption is enabled
+        let plaintext_storage = KeyStorage::from_path(key_pairs_dir.clone(), root_key_pair.clone())
+            .await
+            .expect("Failed to create KeyStorage from path");
+        let key_pair_1 = plaintext_storage
+            .create_key_pair()
+            .await
+            .expect("Failed to create key pair 1");
+        drop(plaintext_storage);
+
+        let encryption = KeyEncryption::from_passphrase("passphrase");
+        let key_storage = KeyStorage::load(
+            key_pairs_dir.clone(),
+            root_key_pair.clone(),
+            Some(encryption.clone()),
+        )
+        .await
+        .expect("Failed to migrate KeyStorage");
+        let key_pair_2 = key_storage
+            .create_key_pair()
+            .await
+            .expect("Failed to create key pair 2");
+        drop(key_storage);
+
+        for key_pair in [&key_pair_1, &key_pair_2] {
+            let worker_id = key_pair.get_peer_id().into();
+            let path = key_pairs_dir.join(keypair_file_name(worker_id));
+            let contents = std::fs::read_to_string(&path).unwrap();
+            assert!(contents.contains("encrypted_key"));
+            assert!(!contents.contains("private_key_bytes"));
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        assert!(
+            KeyStorage::from_path(key_pairs_dir.clone(), root_key_pair.clone())
+                .await
+                .is_err(),
+            "encrypted key pairs can't be loaded without the passphrase"
+        );
+
+        let key_storage = KeyStorage::load(key_pairs_dir, root_key_pair, Some(encryption))
+            .await
+            .expect("Failed to load encrypted KeyStorage");
+        for key_pair in [key_pair_1, key_pair_2] {
+            assert_eq!(
+                key_storage
+                    .get_worker_key_pair(key_pair.get_peer_id().into())
+                    .map(|k| k.to_vec()),
+                Some(key_pair.to_vec())
+            );
+        }
+    }
 }
@@ -2,6 +2,7 @@
 
 mod error;
 mod key_storage;
+mod management_keys;
 mod persistence;
 mod scope;
 mod workers;
@@ -13,6 +14,8 @@ pub use core_manager::CUID;
 pub use error::KeyStorageError;
 pub use error::WorkersError;
 pub use key_storage::KeyStorage;
+pub use management_keys::{ManagementKeyError, ManagementKeys};
+pub use persistence::DealSettings;
 pub use scope::PeerScopes;
 pub use tokio::sync::mpsc::Receiver;
 pub use types::peer_scope::WorkerId;
@@ -0,0 +1,213 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::BTreeSet;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use fluence_libp2p::PeerId;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use types::management::{Capability, ManagementKey};
+
+#[derive(Debug, Error)]
+pub enum ManagementKeyError {
+    #[error("management key {0} is in the node config, it can't be changed at runtime")]
+    Configured(PeerId),
+    #[error("failed to persist management keys: {0}")]
+    Persist(#[from] io::Error),
+}
+
+#[derive(Default, Deserialize, Serialize)]
+struct KeysFile {
+    #[serde(default)]
+    keys: Vec<ManagementKey>,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// Keys from the node config, immutable at runtime
+    config: Vec<ManagementKey>,
+    /// Keys managed at runtime
+    runtime: Vec<ManagementKey>,
+    /// Where runtime keys are persisted, `None` to keep them in memory only
+    path: Option<PathBuf>,
+}
+
+/// Peer ids with a subset of the management peer capabilities, managed by the management peer
+#[derive(Clone, Default)]
+pub struct ManagementKeys {
+    inner: Arc<RwLock<Inner>>,
+}
+
+impl ManagementKeys {
+    pub fn new(config: Vec<ManagementKey>) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(Inner {
+                config,
+                ..<_>::default()
+            })),
+        }
+    }
+
+    /// Creates keys with runtime ones persisted in `path`, loading them from there if it exists
+    pub fn load(config: Vec<ManagementKey>, path: PathBuf) -> io::Result<Self> {
+        let file: KeysFile = match std::fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => KeysFile::default(),
+            Err(err) => return Err(err),
+        };
+        Ok(Self {
+            inner: Arc::new(RwLock::new(Inner {
+                config,
+                runtime: file.keys,
+                path: Some(path),
+            })),
+        })
+    }
+
+    /// Capabilities of `peer_id`, empty if it isn't a management key
+    pub fn capabilities(&self, peer_id: &PeerId) -> BTreeSet<Capability> {
+        let inner = self.inner.read();
+        inner
+            .config
+            .iter()
+            .chain(&inner.runtime)
+            .filter(|key| key.peer_id == *peer_id)
+            .flat_map(|key| key.capabilities.iter().copied())
+            .collect()
+    }
+
+    pub fn has_capability(&self, peer_id: &PeerId, capability: Capability) -> bool {
+        let inner = self.inner.read();
+        inner
+            .config
+            .iter()
+            .chain(&inner.runtime)
+            .any(|key| key.peer_id == *peer_id && key.capabilities.contains(&capability))
+    }
+
+    pub fn contains(&self, peer_id: &PeerId) -> bool {
+        let inner = self.inner.read();
+        inner
+            .config
+            .iter()
+            .chain(&inner.runtime)
+            .any(|key| key.peer_id == *peer_id)
+    }
+
+    /// Both config and runtime keys
+    pub fn list(&self) -> Vec<ManagementKey> {
+        let inner = self.inner.read();
+        inner.config.iter().chain(&inner.runtime).cloned().collect()
+    }
+
+    /// Adds the key or replaces capabilities of an existing one, and persists runtime keys
+    pub fn set(&self, key: ManagementKey) -> Result<(), ManagementKeyError> {
+        let mut inner = self.inner.write();
+        if inner.config.iter().any(|k| k.peer_id == key.peer_id) {
+            return Err(ManagementKeyError::Configured(key.peer_id));
+        }
+        inner.runtime.retain(|k| k.peer_id != key.peer_id);
+        inner.runtime.push(key);
+        Self::persist(&inner)?;
+        Ok(())
+    }
+
+    /// Removes the key and persists runtime keys. Returns whether the key was there.
+    pub fn revoke(&self, peer_id: &PeerId) -> Result<bool, ManagementKeyError> {
+        let mut inner = self.inner.write();
+        if inner.config.iter().any(|k| k.peer_id == *peer_id) {
+            return Err(ManagementKeyError::Configured(*peer_id));
+        }
+        let count = inner.runtime.len();
+        inner.runtime.retain(|k| k.peer_id != *peer_id);
+        let revoked = inner.runtime.len() != count;
+        if revoked {
+            Self::persist(&inner)?;
+        }
+        Ok(revoked)
+    }
+
+    fn persist(inner: &Inner) -> io::Result<()> {
+        if let Some(path) = &inner.path {
+            let file = KeysFile {
+                keys: inner.runtime.clone(),
+            };
+            let contents = toml::to_string(&file)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            std::fs::write(path, contents)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(peer_id: PeerId, capabilities: &[Capability]) -> ManagementKey {
+        ManagementKey {
+            peer_id,
+            capabilities: capabilities.iter().copied().collect(),
+        }
+    }
+
+    #[test]
+    fn capabilities() {
+        let auditor = PeerId::random();
+        let configured = PeerId::random();
+        let keys = ManagementKeys::new(vec![key(configured, &[Capability::BanPeers])]);
+        keys.set(key(auditor, &[Capability::ReadAuditLog])).unwrap();
+
+        assert!(keys.has_capability(&auditor, Capability::ReadAuditLog));
+        assert!(!keys.has_capability(&auditor, Capability::BanPeers));
+        assert!(keys.has_capability(&configured, Capability::BanPeers));
+        assert!(!keys.contains(&PeerId::random()));
+
+        assert!(matches!(
+            keys.revoke(&configured),
+            Err(ManagementKeyError::Configured(_))
+        ));
+        assert!(keys.revoke(&auditor).unwrap());
+        assert!(!keys.contains(&auditor));
+    }
+
+    #[test]
+    fn persist_runtime_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("management_keys.toml");
+        let deployer = PeerId::random();
+
+        let keys = ManagementKeys::load(vec![], path.clone()).unwrap();
+        keys.set(key(deployer, &[Capability::DeploySystemServices]))
+            .unwrap();
+        keys.set(key(deployer, &[Capability::DrainNode])).unwrap();
+
+        let keys = ManagementKeys::load(vec![], path.clone()).unwrap();
+        assert_eq!(
+            keys.capabilities(&deployer),
+            BTreeSet::from([Capability::DrainNode])
+        );
+        keys.revoke(&deployer).unwrap();
+
+        let keys = ManagementKeys::load(vec![], path).unwrap();
+        assert!(keys.list().is_empty());
+    }
+}
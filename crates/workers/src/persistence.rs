@@ -15,19 +15,23 @@
  */
 
 use crate::error::KeyStorageError::{
-    CannotExtractRSASecretKey, SerializePersistedKeypair, WriteErrorPersistedKeypair,
+    CannotExtractRSASecretKey, DecryptKeypair, EncryptKeypair, EncryptedKeypair,
+    SerializePersistedKeypair, WriteErrorPersistedKeypair,
 };
 use crate::error::{KeyStorageError, WorkersError};
 use crate::workers::WorkerInfo;
 use crate::KeyStorageError::RemoveErrorPersistedKeypair;
 use core_manager::CUID;
 use fluence_keypair::KeyPair;
+use fs_utils::BatchWriter;
+use key_encryption::KeyEncryption;
 use libp2p::PeerId;
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 use types::peer_id;
 use types::peer_scope::WorkerId;
+use types::DealId;
 
 pub const fn default_bool<const V: bool>() -> bool {
     V
@@ -35,8 +39,56 @@ pub const fn default_bool<const V: bool>() -> bool {
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PersistedKeypair {
+    /// Empty if the key is encrypted
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub private_key_bytes: Vec<u8>,
     pub key_format: String,
+    /// `private_key_bytes` encrypted with the keystore key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encrypted_key: Option<String>,
+}
+
+impl PersistedKeypair {
+    pub fn is_encrypted(&self) -> bool {
+        self.encrypted_key.is_some()
+    }
+
+    pub fn encrypt(self, encryption: &KeyEncryption) -> Result<Self, KeyStorageError> {
+        let encrypted_key = encryption
+            .encrypt(&self.private_key_bytes)
+            .map_err(|err| EncryptKeypair { err })?;
+        Ok(Self {
+            private_key_bytes: vec![],
+            key_format: self.key_format,
+            encrypted_key: Some(encrypted_key),
+        })
+    }
+
+    /// Returns the key with `private_key_bytes` in plaintext. `path` is used in errors only.
+    pub fn decrypt(
+        self,
+        encryption: Option<&KeyEncryption>,
+        path: &Path,
+    ) -> Result<Self, KeyStorageError> {
+        let Some(encrypted_key) = self.encrypted_key else {
+            return Ok(self);
+        };
+        let encryption = encryption.ok_or_else(|| EncryptedKeypair {
+            path: path.to_path_buf(),
+        })?;
+        let private_key_bytes =
+            encryption
+                .decrypt(&encrypted_key)
+                .map_err(|err| DecryptKeypair {
+                    path: path.to_path_buf(),
+                    err,
+                })?;
+        Ok(Self {
+            private_key_bytes,
+            key_format: self.key_format,
+            encrypted_key: None,
+        })
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -52,6 +104,25 @@ pub struct PersistedWorker {
     #[serde(default = "default_bool::<true>")]
     pub active: bool,
     pub cu_ids: Vec<CUID>,
+    #[serde(default)]
+    pub settings: DealSettings,
+    /// GPU devices assigned to the worker
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub gpus: Vec<String>,
+}
+
+/// Deal settings adjustable at runtime, override node-wide decider settings
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq, Eq)]
+pub struct DealSettings {
+    /// Period of the worker spell
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub worker_period_sec: Option<u32>,
+}
+
+/// Workers are created only for these deals if the list is persisted
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct PersistedDealAllowlist {
+    pub deals: Vec<DealId>,
 }
 
 impl From<PersistedWorker> for WorkerInfo {
@@ -61,6 +132,8 @@ impl From<PersistedWorker> for WorkerInfo {
             creator: val.creator,
             active: RwLock::new(val.active),
             cu_ids: val.cu_ids,
+            settings: RwLock::new(val.settings),
+            gpus: val.gpus,
         }
     }
 }
@@ -72,6 +145,7 @@ impl TryFrom<&KeyPair> for PersistedKeypair {
         Ok(Self {
             private_key_bytes: keypair.secret().map_err(|_| CannotExtractRSASecretKey)?,
             key_format: keypair.public().get_key_format().into(),
+            encrypted_key: None,
         })
     }
 }
@@ -84,6 +158,8 @@ pub(crate) fn worker_file_name(worker_id: WorkerId) -> String {
     format!("{}_info.toml", worker_id)
 }
 
+const DEAL_ALLOWLIST_FILE_NAME: &str = "deal_allowlist.toml";
+
 fn is_keypair(path: &Path) -> bool {
     path.file_name()
         .and_then(|n| n.to_str())
@@ -96,7 +172,8 @@ pub(crate) fn is_worker(path: &Path) -> bool {
         .map_or(false, |n| n.ends_with("_info.toml"))
 }
 
-/// Persist keypair info to disk, so it is recreated after restart
+/// Persist keypair info to disk, so it is recreated after restart.
+/// The file is replaced atomically, so an existing key isn't lost if writing fails.
 pub(crate) async fn persist_keypair(
     keypairs_dir: &Path,
     worker_id: WorkerId,
@@ -105,8 +182,7 @@ pub(crate) async fn persist_keypair(
     let path = keypairs_dir.join(keypair_file_name(worker_id));
     let bytes =
         toml::ser::to_vec(&persisted_keypair).map_err(|err| SerializePersistedKeypair { err })?;
-    tokio::fs::write(&path, bytes)
-        .await
+    fs_utils::write_atomically(&path, &bytes)
         .map_err(|err| WriteErrorPersistedKeypair { path, err })
 }
 
@@ -125,7 +201,9 @@ pub(crate) async fn remove_keypair(
     Ok(())
 }
 
+/// Persist worker info to disk, returns once the batch with the write is committed
 pub(crate) async fn persist_worker(
+    writer: &BatchWriter,
     workers_dir: &Path,
     worker_id: WorkerId,
     worker: PersistedWorker,
@@ -133,23 +211,56 @@ pub(crate) async fn persist_worker(
     let path = workers_dir.join(worker_file_name(worker_id));
     let bytes =
         toml::to_vec(&worker).map_err(|err| WorkersError::SerializePersistedWorker { err })?;
-    tokio::fs::write(&path, bytes)
+    writer
+        .write(path.clone(), bytes)
         .await
         .map_err(|err| WorkersError::WriteErrorPersistedWorker { path, err })
 }
 
 pub(crate) async fn remove_worker(
+    writer: &BatchWriter,
     workers_dir: &Path,
     worker_id: WorkerId,
 ) -> Result<(), WorkersError> {
     let path = workers_dir.join(worker_file_name(worker_id));
-    tokio::fs::remove_file(path.as_path()).await.map_err(|err| {
-        WorkersError::RemoveErrorPersistedWorker {
+    writer
+        .remove(path.clone())
+        .await
+        .map_err(|err| WorkersError::RemoveErrorPersistedWorker {
             path,
             worker_id,
             err,
+        })
+}
+
+/// Persist the deal allowlist to disk, `None` removes it
+pub(crate) async fn persist_deal_allowlist(
+    writer: &BatchWriter,
+    workers_dir: &Path,
+    deals: Option<Vec<DealId>>,
+) -> Result<(), WorkersError> {
+    let path = workers_dir.join(DEAL_ALLOWLIST_FILE_NAME);
+    let result = match deals {
+        Some(deals) => {
+            let bytes = toml::to_vec(&PersistedDealAllowlist { deals })
+                .map_err(|err| WorkersError::SerializeDealAllowlist { err })?;
+            writer.write(path.clone(), bytes).await
         }
-    })
+        None => writer.remove(path.clone()).await,
+    };
+    result.map_err(|err| WorkersError::WriteErrorDealAllowlist { path, err })
+}
+
+pub(crate) async fn load_deal_allowlist(workers_dir: &Path) -> eyre::Result<Option<Vec<DealId>>> {
+    let path = workers_dir.join(DEAL_ALLOWLIST_FILE_NAME);
+    match tokio::fs::read(&path).await {
+        Ok(bytes) => {
+            let allowlist: PersistedDealAllowlist = toml::from_slice(&bytes)?;
+            Ok(Some(allowlist.deals))
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
 }
 
 /// Load info about persisted workers from disk in parallel
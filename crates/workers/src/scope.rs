@@ -1,8 +1,10 @@
-use crate::KeyStorage;
+use crate::{KeyStorage, ManagementKeys};
 use derivative::Derivative;
 use fluence_libp2p::PeerId;
+use parking_lot::RwLock;
 use std::sync::Arc;
 use thiserror::Error;
+use types::management::Capability;
 use types::peer_scope::{PeerScope, WorkerId};
 
 /// Represents information about various peer IDs.
@@ -12,6 +14,11 @@ pub struct PeerScopes {
     host_peer_id: PeerId,
     management_peer_id: PeerId,
     builtins_management_peer_id: PeerId,
+    /// Host peer id before a host key rotation, treated as the host until it's retired
+    previous_host_peer_id: Arc<RwLock<Option<PeerId>>>,
+    /// Peer ids with a subset of the management peer capabilities
+    #[derivative(Debug = "ignore")]
+    management_keys: ManagementKeys,
     #[derivative(Debug = "ignore")]
     key_storage: Arc<KeyStorage>,
 }
@@ -33,12 +40,39 @@ impl PeerScopes {
             host_peer_id,
             management_peer_id,
             builtins_management_peer_id,
+            previous_host_peer_id: <_>::default(),
+            management_keys: <_>::default(),
             key_storage,
         }
     }
 
+    pub fn with_previous_host(self, previous_host_peer_id: Option<PeerId>) -> Self {
+        *self.previous_host_peer_id.write() = previous_host_peer_id;
+        self
+    }
+
+    pub fn with_management_keys(self, management_keys: ManagementKeys) -> Self {
+        Self {
+            management_keys,
+            ..self
+        }
+    }
+
+    pub fn management_keys(&self) -> &ManagementKeys {
+        &self.management_keys
+    }
+
+    /// Stops treating the previous host peer id as the host
+    pub fn retire_previous_host(&self) -> Option<PeerId> {
+        self.previous_host_peer_id.write().take()
+    }
+
+    pub fn get_previous_host_peer_id(&self) -> Option<PeerId> {
+        *self.previous_host_peer_id.read()
+    }
+
     pub fn scope(&self, peer_id: PeerId) -> Result<PeerScope, ScopeNotFound> {
-        if self.host_peer_id == peer_id {
+        if self.is_host(peer_id) {
             Ok(PeerScope::Host)
         } else {
             let worker_id: WorkerId = peer_id.into();
@@ -55,13 +89,19 @@ impl PeerScopes {
     }
 
     pub fn is_host(&self, peer_id: PeerId) -> bool {
-        self.host_peer_id == peer_id
+        self.host_peer_id == peer_id || self.get_previous_host_peer_id() == Some(peer_id)
     }
 
+    /// Whether `peer_id` is the management peer, which has every capability
     pub fn is_management(&self, peer_id: PeerId) -> bool {
         self.management_peer_id == peer_id || self.builtins_management_peer_id == peer_id
     }
 
+    /// Whether `peer_id` is the management peer or a management key with `capability`
+    pub fn has_capability(&self, peer_id: PeerId, capability: Capability) -> bool {
+        self.is_management(peer_id) || self.management_keys.has_capability(&peer_id, capability)
+    }
+
     pub fn get_host_peer_id(&self) -> PeerId {
         self.host_peer_id
     }
@@ -1,25 +1,34 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ops::Deref;
 use std::path::PathBuf;
-use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 use parking_lot::lock_api::RwLockUpgradableReadGuard;
 use parking_lot::RwLock;
 use tokio::runtime::{Handle, Runtime, UnhandledPanic};
 use tokio::sync::mpsc::{Receiver, Sender};
 
+use audit_log::{AuditEvent, AuditEventKind, AuditLog};
 use core_manager::manager::{CoreManager, CoreManagerFunctions};
 use core_manager::types::{AcquireRequest, WorkType};
 use core_manager::CUID;
 use fluence_libp2p::PeerId;
+use fs_utils::BatchWriter;
 use types::peer_scope::WorkerId;
 use types::DealId;
 
 use crate::error::WorkersError;
-use crate::persistence::{load_persisted_workers, persist_worker, remove_worker, PersistedWorker};
+use crate::persistence::{
+    load_deal_allowlist, load_persisted_workers, persist_deal_allowlist, persist_worker,
+    remove_worker, DealSettings, PersistedWorker,
+};
 use crate::KeyStorage;
 
+/// How long a worker data write waits for other writes to be committed along
+const WRITE_BATCH_DELAY: Duration = Duration::from_millis(5);
+
 /// Information about a worker.
 pub struct WorkerInfo {
     /// The unique identifier for the deal associated with the worker.
@@ -30,12 +39,17 @@ pub struct WorkerInfo {
     pub active: RwLock<bool>,
     /// A count of compute units available for this worker.
     pub cu_ids: Vec<CUID>,
+    /// Deal settings overriding node-wide ones.
+    pub settings: RwLock<DealSettings>,
+    /// GPU devices assigned to the worker.
+    pub gpus: Vec<String>,
 }
 
 pub struct WorkerParams {
     deal_id: DealId,
     creator: PeerId,
     cu_ids: Vec<CUID>,
+    gpu_count: usize,
 }
 
 impl WorkerParams {
@@ -44,8 +58,14 @@ impl WorkerParams {
             deal_id,
             creator,
             cu_ids,
+            gpu_count: 0,
         }
     }
+
+    /// Requests GPU devices for the worker, its creation fails if there aren't that many free ones
+    pub fn with_gpu_count(self, gpu_count: usize) -> Self {
+        Self { gpu_count, ..self }
+    }
 }
 
 /// Manages a collection of workers.
@@ -56,6 +76,8 @@ pub struct Workers {
     worker_infos: RwLock<HashMap<WorkerId, WorkerInfo>>,
     /// Directory path where worker data is persisted.
     workers_dir: PathBuf,
+    /// Groups writes of worker data, so deal activation bursts are committed in batches.
+    writer: BatchWriter,
     /// Key storage for managing worker key pairs.
     key_storage: Arc<KeyStorage>,
     /// Mapping of worker IDs to worker runtime.
@@ -64,6 +86,16 @@ pub struct Workers {
     core_manager: Arc<CoreManager>,
     /// Number of created tokio runtimes
     runtime_counter: Arc<AtomicU32>,
+    /// Workers for new deals aren't created past that number
+    max_workers: Option<usize>,
+    /// Workers for new deals aren't created while the node is in maintenance
+    maintenance: AtomicBool,
+    /// Workers are created only for these deals, if set
+    deal_allowlist: RwLock<Option<HashSet<DealId>>>,
+    /// GPU devices of the node, each is assigned to one worker at a time
+    gpus: Vec<String>,
+    /// Worker lifecycle changes are recorded there
+    audit_log: AuditLog,
 
     sender: Sender<Event>,
 }
@@ -101,6 +133,7 @@ impl Workers {
         channel_size: usize,
     ) -> eyre::Result<(Self, Receiver<Event>)> {
         let workers = load_persisted_workers(workers_dir.as_path()).await?;
+        let deal_allowlist = load_deal_allowlist(workers_dir.as_path()).await?;
         let mut worker_ids = HashMap::with_capacity(workers.len());
         let mut worker_infos = HashMap::with_capacity(workers.len());
         let mut runtimes = HashMap::with_capacity(workers.len());
@@ -135,16 +168,133 @@ impl Workers {
                 worker_ids: RwLock::new(worker_ids),
                 worker_infos: RwLock::new(worker_infos),
                 workers_dir,
+                writer: BatchWriter::spawn(WRITE_BATCH_DELAY),
                 key_storage,
                 runtimes: RwLock::new(runtimes),
                 runtime_counter: worker_counter,
                 core_manager,
+                max_workers: None,
+                maintenance: AtomicBool::new(false),
+                deal_allowlist: RwLock::new(deal_allowlist.map(HashSet::from_iter)),
+                gpus: vec![],
+                audit_log: <_>::default(),
                 sender,
             },
             receiver,
         ))
     }
 
+    /// Limits the number of hosted workers, workers loaded from disk are kept even if there are more.
+    pub fn with_max_workers(self, max_workers: Option<usize>) -> Self {
+        Self {
+            max_workers,
+            ..self
+        }
+    }
+
+    /// Declares GPU devices that workers may request
+    pub fn with_gpus(self, gpus: Vec<String>) -> Self {
+        Self { gpus, ..self }
+    }
+
+    /// GPU devices assigned to the worker, empty for unknown workers
+    pub fn get_gpus(&self, worker_id: WorkerId) -> Vec<String> {
+        self.worker_infos
+            .read()
+            .get(&worker_id)
+            .map(|info| info.gpus.clone())
+            .unwrap_or_default()
+    }
+
+    /// Devices assigned to workers other than `except`
+    fn assigned_gpus(&self, except: Option<WorkerId>) -> HashMap<String, WorkerId> {
+        self.worker_infos
+            .read()
+            .iter()
+            .filter(|(worker_id, _)| Some(**worker_id) != except)
+            .flat_map(|(worker_id, info)| info.gpus.iter().map(|gpu| (gpu.clone(), *worker_id)))
+            .collect()
+    }
+
+    fn pick_gpus(&self, deal_id: &DealId, count: usize) -> Result<Vec<String>, WorkersError> {
+        if count == 0 {
+            return Ok(vec![]);
+        }
+        let assigned = self.assigned_gpus(None);
+        let free: Vec<String> = self
+            .gpus
+            .iter()
+            .filter(|gpu| !assigned.contains_key(*gpu))
+            .cloned()
+            .collect();
+        if free.len() < count {
+            return Err(WorkersError::GpusUnavailable {
+                deal_id: deal_id.clone(),
+                requested: count,
+                available: free.len(),
+            });
+        }
+        Ok(free.into_iter().take(count).collect())
+    }
+
+    /// Checks the devices are still declared by the node and not assigned to other workers,
+    /// which may happen after `resources.gpus` is changed
+    fn check_gpus(&self, worker_id: WorkerId, gpus: &[String]) -> Result<(), WorkersError> {
+        let assigned = self.assigned_gpus(Some(worker_id));
+        for device in gpus {
+            if !self.gpus.contains(device) {
+                return Err(WorkersError::GpuNotDeclared {
+                    worker_id,
+                    device: device.clone(),
+                });
+            }
+            if let Some(other) = assigned.get(device) {
+                return Err(WorkersError::GpuTaken {
+                    worker_id,
+                    device: device.clone(),
+                    other: *other,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Stops or resumes creating workers for new deals, existing workers keep running
+    pub fn set_maintenance(&self, enabled: bool) {
+        self.maintenance.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn is_in_maintenance(&self) -> bool {
+        self.maintenance.load(Ordering::Relaxed)
+    }
+
+    /// Restricts creating workers to the `deals`, `None` allows all deals.
+    /// The allowlist is persisted, existing workers are kept.
+    pub async fn set_deal_allowlist(&self, deals: Option<Vec<DealId>>) -> Result<(), WorkersError> {
+        persist_deal_allowlist(&self.writer, &self.workers_dir, deals.clone()).await?;
+        *self.deal_allowlist.write() = deals.map(HashSet::from_iter);
+        Ok(())
+    }
+
+    pub fn deal_allowlist(&self) -> Option<Vec<DealId>> {
+        self.deal_allowlist
+            .read()
+            .as_ref()
+            .map(|deals| deals.iter().cloned().collect())
+    }
+
+    fn is_deal_allowed(&self, deal_id: &DealId) -> bool {
+        self.deal_allowlist
+            .read()
+            .as_ref()
+            .map_or(true, |deals| deals.contains(deal_id))
+    }
+
+    /// Records created, removed, activated and deactivated workers into `audit_log`
+    pub fn with_audit_log(self, audit_log: AuditLog) -> Self {
+        Self { audit_log, ..self }
+    }
+
     /// Retrieves the deal ID associated with the specified worker ID.
     ///
     /// # Arguments
@@ -182,14 +332,24 @@ impl Workers {
         let deal_id = params.deal_id;
         let init_peer_id = params.creator;
         let cu_ids = params.cu_ids;
+        let gpu_count = params.gpu_count;
 
         let worker_id = {
             let guard = self.worker_ids.read();
             guard.get(&deal_id).cloned()
         };
-        match worker_id {
-            Some(_) => Err(WorkersError::WorkerAlreadyExists { deal_id }),
-            _ => {
+        let max_workers = self
+            .max_workers
+            .filter(|max| self.worker_ids.read().len() >= *max);
+        match (worker_id, max_workers) {
+            (Some(_), _) => Err(WorkersError::WorkerAlreadyExists { deal_id }),
+            (None, _) if self.is_in_maintenance() => Err(WorkersError::Maintenance { deal_id }),
+            (None, _) if !self.is_deal_allowed(&deal_id) => {
+                Err(WorkersError::DealNotAllowed { deal_id })
+            }
+            (None, Some(limit)) => Err(WorkersError::WorkersLimitReached { deal_id, limit }),
+            (None, None) => {
+                let gpus = self.pick_gpus(&deal_id, gpu_count)?;
                 let key_pair = self
                     .key_storage
                     .create_key_pair()
@@ -199,7 +359,13 @@ impl Workers {
                 let worker_id: WorkerId = key_pair.get_peer_id().into();
 
                 let worker_info = self
-                    .store_worker(worker_id, deal_id.clone(), init_peer_id, cu_ids.clone())
+                    .store_worker(
+                        worker_id,
+                        deal_id.clone(),
+                        init_peer_id,
+                        cu_ids.clone(),
+                        gpus.clone(),
+                    )
                     .await;
 
                 match worker_info {
@@ -210,6 +376,8 @@ impl Workers {
                             if worker_ids.contains_key(&deal_id) {
                                 return Err(WorkersError::WorkerAlreadyExists { deal_id });
                             }
+                            // another worker could take the devices while this one was stored
+                            self.check_gpus(worker_id, &gpus)?;
 
                             let (runtime, thread_count) = Self::build_runtime(
                                 self.core_manager.clone(),
@@ -268,6 +436,11 @@ impl Workers {
                     }
                 }
 
+                self.audit_log.record(
+                    AuditEvent::new(AuditEventKind::WorkerCreated, worker_id)
+                        .with_actor(init_peer_id)
+                        .with_details(format!("deal_id={deal_id}")),
+                );
                 Ok(worker_id)
             }
         }
@@ -291,7 +464,7 @@ impl Workers {
             .send(Event::WorkerRemoved { worker_id })
             .await
             .map_err(|_err| WorkersError::FailedToNotifySubsystem { worker_id })?;
-        remove_worker(&self.workers_dir, worker_id).await?;
+        remove_worker(&self.writer, &self.workers_dir, worker_id).await?;
         self.key_storage
             .remove_key_pair(worker_id)
             .await
@@ -320,6 +493,10 @@ impl Workers {
                 .expect("Could not spawn task");
         }
 
+        self.audit_log.record(
+            AuditEvent::new(AuditEventKind::WorkerRemoved, worker_id)
+                .with_details(format!("deal_id={deal_id}")),
+        );
         Ok(())
     }
 
@@ -327,6 +504,8 @@ impl Workers {
     ///
     /// The activation process sets the worker's status to `true`, indicating that the worker
     /// is active. The updated status is persisted, and internal data structures are updated.
+    /// Activation is rejected if the worker's GPUs are no longer declared by the node or are
+    /// assigned to another worker.
     ///
     /// # Arguments
     ///
@@ -339,7 +518,10 @@ impl Workers {
     /// - `Err(WorkersError)` if an error occurs during the activation process.
     ///
     pub async fn activate_worker(&self, worker_id: WorkerId) -> Result<(), WorkersError> {
+        self.check_gpus(worker_id, &self.get_gpus(worker_id))?;
         self.set_worker_status(worker_id, true).await?;
+        self.audit_log
+            .record(AuditEvent::new(AuditEventKind::WorkerActivated, worker_id));
         Ok(())
     }
 
@@ -360,9 +542,40 @@ impl Workers {
     ///
     pub async fn deactivate_worker(&self, worker_id: WorkerId) -> Result<(), WorkersError> {
         self.set_worker_status(worker_id, false).await?;
+        self.audit_log.record(AuditEvent::new(
+            AuditEventKind::WorkerDeactivated,
+            worker_id,
+        ));
         Ok(())
     }
 
+    /// Retrieves the deal settings of the worker with the specified `worker_id`.
+    pub fn get_deal_settings(&self, worker_id: WorkerId) -> Result<DealSettings, WorkersError> {
+        self.worker_infos
+            .read()
+            .get(&worker_id)
+            .map(|info| info.settings.read().clone())
+            .ok_or(WorkersError::WorkerNotFound(worker_id))
+    }
+
+    /// Replaces the deal settings of the worker with the specified `worker_id` and persists them.
+    pub async fn set_deal_settings(
+        &self,
+        worker_id: WorkerId,
+        settings: DealSettings,
+    ) -> Result<(), WorkersError> {
+        let worker = {
+            let guard = self.worker_infos.read();
+            let worker_info = guard
+                .get(&worker_id)
+                .ok_or(WorkersError::WorkerNotFound(worker_id))?;
+            *worker_info.settings.write() = settings;
+            Self::to_persisted(worker_id, worker_info)
+        };
+
+        persist_worker(&self.writer, &self.workers_dir, worker_id, worker).await
+    }
+
     pub fn get_runtime_handle(&self, worker_id: WorkerId) -> Option<Handle> {
         self.runtimes
             .read()
@@ -501,8 +714,10 @@ impl Workers {
         deal_id: DealId,
         creator: PeerId,
         cu_ids: Vec<CUID>,
+        gpus: Vec<String>,
     ) -> Result<WorkerInfo, WorkersError> {
         persist_worker(
+            &self.writer,
             &self.workers_dir,
             worker_id,
             PersistedWorker {
@@ -511,6 +726,8 @@ impl Workers {
                 deal_id: deal_id.clone().into(),
                 active: true,
                 cu_ids: cu_ids.clone(),
+                settings: DealSettings::default(),
+                gpus: gpus.clone(),
             },
         )
         .await?;
@@ -519,6 +736,8 @@ impl Workers {
             creator,
             active: RwLock::new(true),
             cu_ids,
+            settings: RwLock::new(DealSettings::default()),
+            gpus,
         };
         Ok(worker_info)
     }
@@ -528,36 +747,31 @@ impl Workers {
         worker_id: WorkerId,
         status: bool,
     ) -> Result<(), WorkersError> {
-        let (creator, deal_id, cu_ids) = {
+        let worker = {
             let guard = self.worker_infos.read();
             let worker_info = guard
                 .get(&worker_id)
                 .ok_or(WorkersError::WorkerNotFound(worker_id))?;
-            let mut active = worker_info.active.write();
-            *active = status;
-
-            (
-                worker_info.creator,
-                worker_info.deal_id.clone(),
-                worker_info.cu_ids.clone(),
-            )
+            *worker_info.active.write() = status;
+            Self::to_persisted(worker_id, worker_info)
         };
 
-        persist_worker(
-            &self.workers_dir,
-            worker_id,
-            PersistedWorker {
-                worker_id,
-                creator,
-                deal_id: deal_id.into(),
-                active: status,
-                cu_ids,
-            },
-        )
-        .await?;
+        persist_worker(&self.writer, &self.workers_dir, worker_id, worker).await?;
         Ok(())
     }
 
+    fn to_persisted(worker_id: WorkerId, worker_info: &WorkerInfo) -> PersistedWorker {
+        PersistedWorker {
+            worker_id,
+            creator: worker_info.creator,
+            deal_id: worker_info.deal_id.clone().into(),
+            active: *worker_info.active.read(),
+            cu_ids: worker_info.cu_ids.clone(),
+            settings: worker_info.settings.read().clone(),
+            gpus: worker_info.gpus.clone(),
+        }
+    }
+
     fn build_runtime(
         core_manager: Arc<CoreManager>,
         worker_counter: Arc<AtomicU32>,
@@ -596,7 +810,8 @@ impl Workers {
 
 #[cfg(test)]
 mod tests {
-    use crate::{KeyStorage, WorkerParams, Workers, CUID};
+    use crate::error::WorkersError;
+    use crate::{DealSettings, KeyStorage, WorkerParams, Workers, CUID};
     use core_manager::manager::{CoreManager, DummyCoreManager};
     use hex::FromHex;
     use libp2p::PeerId;
@@ -698,6 +913,76 @@ mod tests {
         tokio::task::spawn_blocking(|| drop(workers)).await.unwrap();
     }
 
+    #[tokio::test]
+    async fn test_max_workers() {
+        let temp_dir = tempdir().expect("Failed to create temporary directory");
+        let key_pairs_dir = temp_dir.path().join("key_pairs").to_path_buf();
+        let workers_dir = temp_dir.path().join("workers").to_path_buf();
+        let root_key_pair = fluence_keypair::KeyPair::generate_ed25519();
+        let core_manager = Arc::new(DummyCoreManager::default().into());
+        let key_storage = Arc::new(
+            KeyStorage::from_path(key_pairs_dir.clone(), root_key_pair.clone())
+                .await
+                .expect("Failed to create KeyStorage from path"),
+        );
+
+        let (workers, _receiver) =
+            Workers::from_path(workers_dir.clone(), key_storage.clone(), core_manager, 128)
+                .await
+                .expect("Failed to create Workers from path");
+        let workers = workers.with_max_workers(Some(1));
+
+        let unit_ids = vec![<CUID>::from_hex(
+            "54ae1b506c260367a054f80800a545f23e32c6bc4a8908c9a794cb8dad23e5ea",
+        )
+        .unwrap()];
+        workers
+            .create_worker(WorkerParams::new(
+                "deal_id_1".into(),
+                PeerId::random(),
+                unit_ids.clone(),
+            ))
+            .await
+            .expect("Failed to create worker");
+
+        let result = workers
+            .create_worker(WorkerParams::new(
+                "deal_id_2".into(),
+                PeerId::random(),
+                unit_ids.clone(),
+            ))
+            .await;
+        assert!(matches!(
+            result,
+            Err(WorkersError::WorkersLimitReached { limit: 1, .. })
+        ));
+        assert_eq!(workers.list_workers().len(), 1);
+
+        let workers = workers.with_max_workers(None);
+        workers.set_maintenance(true);
+        let result = workers
+            .create_worker(WorkerParams::new(
+                "deal_id_2".into(),
+                PeerId::random(),
+                unit_ids.clone(),
+            ))
+            .await;
+        assert!(matches!(result, Err(WorkersError::Maintenance { .. })));
+
+        workers.set_maintenance(false);
+        workers
+            .create_worker(WorkerParams::new(
+                "deal_id_2".into(),
+                PeerId::random(),
+                unit_ids,
+            ))
+            .await
+            .expect("Failed to create worker after maintenance");
+        assert_eq!(workers.list_workers().len(), 2);
+
+        tokio::task::spawn_blocking(|| drop(workers)).await.unwrap();
+    }
+
     #[tokio::test]
     async fn test_worker_creation_dupes() {
         // Create a temporary directory for worker storage
@@ -928,4 +1213,166 @@ mod tests {
         // tokio doesn't allow to drop runtimes in async context, so shifting workers drop to the blocking thread
         tokio::task::spawn_blocking(|| drop(workers)).await.unwrap();
     }
+
+    #[tokio::test]
+    async fn test_deal_settings() {
+        let temp_dir = tempdir().expect("Failed to create temporary directory");
+        let key_pairs_dir = temp_dir.path().join("key_pairs").to_path_buf();
+        let workers_dir = temp_dir.path().join("workers").to_path_buf();
+        let root_key_pair = fluence_keypair::KeyPair::generate_ed25519();
+        let core_manager: Arc<CoreManager> = Arc::new(DummyCoreManager::default().into());
+        let key_storage = Arc::new(
+            KeyStorage::from_path(key_pairs_dir.clone(), root_key_pair.clone())
+                .await
+                .expect("Failed to create KeyStorage from path"),
+        );
+
+        let (workers, _receiver) = Workers::from_path(
+            workers_dir.clone(),
+            key_storage.clone(),
+            core_manager.clone(),
+            128,
+        )
+        .await
+        .expect("Failed to create Workers from path");
+
+        let unit_ids = vec![<CUID>::from_hex(
+            "54ae1b506c260367a054f80800a545f23e32c6bc4a8908c9a794cb8dad23e5ea",
+        )
+        .unwrap()];
+        let worker_id = workers
+            .create_worker(WorkerParams::new(
+                "deal_id_1".into(),
+                PeerId::random(),
+                unit_ids.clone(),
+            ))
+            .await
+            .expect("Failed to create worker");
+        assert_eq!(
+            workers.get_deal_settings(worker_id).unwrap(),
+            DealSettings::default()
+        );
+
+        let settings = DealSettings {
+            worker_period_sec: Some(60),
+        };
+        workers
+            .set_deal_settings(worker_id, settings.clone())
+            .await
+            .expect("Failed to set deal settings");
+        workers
+            .set_deal_allowlist(Some(vec!["deal_id_1".into()]))
+            .await
+            .expect("Failed to set deal allowlist");
+
+        let result = workers
+            .create_worker(WorkerParams::new(
+                "deal_id_2".into(),
+                PeerId::random(),
+                unit_ids.clone(),
+            ))
+            .await;
+        assert!(matches!(result, Err(WorkersError::DealNotAllowed { .. })));
+        tokio::task::spawn_blocking(|| drop(workers)).await.unwrap();
+
+        let (workers, _receiver) =
+            Workers::from_path(workers_dir.clone(), key_storage.clone(), core_manager, 128)
+                .await
+                .expect("Failed to create Workers from path");
+        assert_eq!(workers.get_deal_settings(worker_id).unwrap(), settings);
+        assert_eq!(workers.deal_allowlist(), Some(vec!["deal_id_1".into()]));
+
+        workers
+            .set_deal_allowlist(None)
+            .await
+            .expect("Failed to remove deal allowlist");
+        workers
+            .create_worker(WorkerParams::new(
+                "deal_id_2".into(),
+                PeerId::random(),
+                unit_ids,
+            ))
+            .await
+            .expect("Failed to create worker without allowlist");
+        tokio::task::spawn_blocking(|| drop(workers)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_gpus() {
+        let temp_dir = tempdir().expect("Failed to create temporary directory");
+        let key_pairs_dir = temp_dir.path().join("key_pairs").to_path_buf();
+        let workers_dir = temp_dir.path().join("workers").to_path_buf();
+        let root_key_pair = fluence_keypair::KeyPair::generate_ed25519();
+        let core_manager: Arc<CoreManager> = Arc::new(DummyCoreManager::default().into());
+        let key_storage = Arc::new(
+            KeyStorage::from_path(key_pairs_dir.clone(), root_key_pair.clone())
+                .await
+                .expect("Failed to create KeyStorage from path"),
+        );
+
+        let (workers, _receiver) = Workers::from_path(
+            workers_dir.clone(),
+            key_storage.clone(),
+            core_manager.clone(),
+            128,
+        )
+        .await
+        .expect("Failed to create Workers from path");
+        let workers = workers.with_gpus(vec!["gpu0".to_string(), "gpu1".to_string()]);
+
+        let unit_ids = vec![<CUID>::from_hex(
+            "54ae1b506c260367a054f80800a545f23e32c6bc4a8908c9a794cb8dad23e5ea",
+        )
+        .unwrap()];
+        let worker_id = workers
+            .create_worker(
+                WorkerParams::new("deal_id_1".into(), PeerId::random(), unit_ids.clone())
+                    .with_gpu_count(2),
+            )
+            .await
+            .expect("Failed to create worker");
+        assert_eq!(workers.get_gpus(worker_id), vec!["gpu0", "gpu1"]);
+
+        let result = workers
+            .create_worker(
+                WorkerParams::new("deal_id_2".into(), PeerId::random(), unit_ids.clone())
+                    .with_gpu_count(1),
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(WorkersError::GpusUnavailable {
+                requested: 1,
+                available: 0,
+                ..
+            })
+        ));
+        let other_id = workers
+            .create_worker(WorkerParams::new(
+                "deal_id_2".into(),
+                PeerId::random(),
+                unit_ids,
+            ))
+            .await
+            .expect("Failed to create worker without GPUs");
+        assert!(workers.get_gpus(other_id).is_empty());
+        tokio::task::spawn_blocking(|| drop(workers)).await.unwrap();
+
+        let (workers, _receiver) =
+            Workers::from_path(workers_dir.clone(), key_storage.clone(), core_manager, 128)
+                .await
+                .expect("Failed to create Workers from path");
+        let workers = workers.with_gpus(vec!["gpu0".to_string()]);
+        assert_eq!(workers.get_gpus(worker_id), vec!["gpu0", "gpu1"]);
+        let result = workers.activate_worker(worker_id).await;
+        assert!(matches!(
+            result,
+            Err(WorkersError::GpuNotDeclared { device, .. }) if device == "gpu1"
+        ));
+        workers
+            .activate_worker(other_id)
+            .await
+            .expect("Failed to activate worker without GPUs");
+        tokio::task::spawn_blocking(|| drop(workers)).await.unwrap();
+    }
 }
@@ -0,0 +1,28 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+fn main() {
+    println!("cargo:rerun-if-changed=proto/management.proto");
+
+    // protoc is vendored, so the build doesn't depend on the one installed on the host
+    let protoc = protoc_bin_vendored::protoc_bin_path().expect("vendored protoc is available");
+    std::env::set_var("PROTOC", protoc);
+
+    tonic_build::configure()
+        .build_client(false)
+        .compile(&["proto/management.proto"], &["proto"])
+        .expect("compile management.proto");
+}
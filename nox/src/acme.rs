@@ -0,0 +1,394 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use eyre::{eyre, WrapErr};
+use instant_acme::{
+    Account, AccountCredentials, AuthorizationStatus, ChallengeType, Identifier, NewAccount,
+    NewOrder, Order, OrderStatus,
+};
+use parking_lot::RwLock;
+use tokio::task::JoinHandle;
+
+use fluence_libp2p::TlsCertificateResolver;
+use server_config::{AcmeChallenge, AcmeConfig, WebsocketTlsConfig};
+
+const ACCOUNT_FILE: &str = "account.json";
+const CERTIFICATE_FILE: &str = "certificate.pem";
+const PRIVATE_KEY_FILE: &str = "private_key.pem";
+
+/// How long to wait before retrying a failed certificate issuance
+const RETRY_INTERVAL: Duration = Duration::from_secs(10 * 60);
+const ORDER_POLL_ATTEMPTS: usize = 10;
+
+/// Pending ACME HTTP-01 challenges, served by the http endpoint.
+/// Maps challenge token to key authorization.
+#[derive(Clone, Default)]
+pub struct AcmeHttpChallenges(Arc<RwLock<HashMap<String, String>>>);
+
+impl AcmeHttpChallenges {
+    pub fn get(&self, token: &str) -> Option<String> {
+        self.0.read().get(token).cloned()
+    }
+
+    pub(crate) fn insert(&self, token: String, key_authorization: String) {
+        self.0.write().insert(token, key_authorization);
+    }
+
+    pub(crate) fn remove(&self, token: &str) {
+        self.0.write().remove(token);
+    }
+}
+
+/// Provides certificates for the wss listener: either loads them from configured files,
+/// or obtains and renews them via ACME in the background.
+pub struct CertificateManager {
+    config: WebsocketTlsConfig,
+    acme_dir: PathBuf,
+    resolver: Arc<TlsCertificateResolver>,
+    http_challenges: AcmeHttpChallenges,
+}
+
+impl CertificateManager {
+    pub fn new(
+        config: WebsocketTlsConfig,
+        acme_dir: PathBuf,
+        resolver: Arc<TlsCertificateResolver>,
+    ) -> Self {
+        Self {
+            config,
+            acme_dir,
+            resolver,
+            http_challenges: AcmeHttpChallenges::default(),
+        }
+    }
+
+    pub fn http_challenges(&self) -> AcmeHttpChallenges {
+        self.http_challenges.clone()
+    }
+
+    /// Loads the existing certificate, if any. ACME certificate is issued after `start`.
+    pub fn load(&self) -> eyre::Result<()> {
+        let (certificate_path, private_key_path) = match &self.config.acme {
+            None => (
+                self.config
+                    .certificate_path
+                    .clone()
+                    .ok_or_else(|| eyre!("websocket_tls.certificate_path is not set"))?,
+                self.config
+                    .private_key_path
+                    .clone()
+                    .ok_or_else(|| eyre!("websocket_tls.private_key_path is not set"))?,
+            ),
+            Some(_) => {
+                let certificate_path = self.acme_dir.join(CERTIFICATE_FILE);
+                if !certificate_path.exists() {
+                    tracing::info!("No ACME certificate found, it will be issued after start");
+                    return Ok(());
+                }
+                (certificate_path, self.acme_dir.join(PRIVATE_KEY_FILE))
+            }
+        };
+
+        let certificate = std::fs::read(&certificate_path).wrap_err_with(|| {
+            format!("error reading certificate {}", certificate_path.display())
+        })?;
+        let private_key = std::fs::read(&private_key_path).wrap_err_with(|| {
+            format!("error reading private key {}", private_key_path.display())
+        })?;
+        self.resolver
+            .set_certificate(&certificate, &private_key)
+            .wrap_err("invalid websocket TLS certificate")?;
+
+        Ok(())
+    }
+
+    /// Starts background certificate renewal if ACME is configured
+    pub fn start(self) -> Option<JoinHandle<()>> {
+        let acme = self.config.acme.clone()?;
+
+        let handle = tokio::task::Builder::new()
+            .name("acme")
+            .spawn(async move {
+                loop {
+                    let delay = match self.renew_if_needed(&acme).await {
+                        Ok(()) => acme.check_interval,
+                        Err(err) => {
+                            tracing::error!("Failed to issue ACME certificate: {:?}", err);
+                            RETRY_INTERVAL.min(acme.check_interval)
+                        }
+                    };
+                    tokio::time::sleep(delay).await;
+                }
+            })
+            .expect("Could not spawn task");
+
+        Some(handle)
+    }
+
+    async fn renew_if_needed(&self, acme: &AcmeConfig) -> eyre::Result<()> {
+        let certificate_path = self.acme_dir.join(CERTIFICATE_FILE);
+        if self.resolver.has_certificate() && certificate_path.exists() {
+            let certificate = std::fs::read(&certificate_path)?;
+            if !expires_within(&certificate, acme.renew_before)? {
+                return Ok(());
+            }
+            tracing::info!("ACME certificate expires soon, renewing");
+        }
+
+        self.issue(acme).await?;
+        tracing::info!("ACME certificate issued for {:?}", acme.domains);
+
+        Ok(())
+    }
+
+    async fn issue(&self, acme: &AcmeConfig) -> eyre::Result<()> {
+        let account = self.account(acme).await?;
+
+        let identifiers: Vec<_> = acme.domains.iter().cloned().map(Identifier::Dns).collect();
+        let mut order = account
+            .new_order(&NewOrder {
+                identifiers: &identifiers,
+            })
+            .await?;
+
+        // (domain, token, url)
+        let mut challenges = vec![];
+        let authorizations = order.authorizations().await?;
+        for authorization in &authorizations {
+            match authorization.status {
+                AuthorizationStatus::Pending => {}
+                AuthorizationStatus::Valid => continue,
+                status => eyre::bail!("unexpected ACME authorization status {:?}", status),
+            }
+
+            let challenge_type = match acme.challenge {
+                AcmeChallenge::Http01 => ChallengeType::Http01,
+                AcmeChallenge::TlsAlpn01 => ChallengeType::TlsAlpn01,
+            };
+            let challenge = authorization
+                .challenges
+                .iter()
+                .find(|c| c.r#type == challenge_type)
+                .ok_or_else(|| eyre!("ACME server didn't offer {:?} challenge", challenge_type))?;
+            let Identifier::Dns(domain) = &authorization.identifier;
+
+            let key_authorization = order.key_authorization(challenge);
+            match acme.challenge {
+                AcmeChallenge::Http01 => self.http_challenges.insert(
+                    challenge.token.clone(),
+                    key_authorization.as_str().to_string(),
+                ),
+                AcmeChallenge::TlsAlpn01 => {
+                    let (certificate, private_key) = tls_alpn_challenge_certificate(
+                        domain,
+                        key_authorization.digest().as_ref(),
+                    )?;
+                    self.resolver.set_challenge_certificate(
+                        domain.clone(),
+                        certificate.as_bytes(),
+                        private_key.as_bytes(),
+                    )?;
+                }
+            }
+            challenges.push((
+                domain.clone(),
+                challenge.token.clone(),
+                challenge.url.clone(),
+            ));
+        }
+
+        let validation: eyre::Result<()> = try {
+            for (_, _, url) in &challenges {
+                order.set_challenge_ready(url).await?;
+            }
+            wait_order_ready(&mut order).await?;
+        };
+
+        for (domain, token, _) in challenges {
+            self.http_challenges.remove(&token);
+            self.resolver.remove_challenge_certificate(&domain);
+        }
+        validation?;
+
+        let mut params = rcgen::CertificateParams::new(acme.domains.clone());
+        params.distinguished_name = rcgen::DistinguishedName::new();
+        let key = rcgen::Certificate::from_params(params)?;
+        order.finalize(&key.serialize_request_der()?).await?;
+
+        let mut certificate = None;
+        for _ in 0..ORDER_POLL_ATTEMPTS {
+            certificate = order.certificate().await?;
+            if certificate.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        let certificate = certificate.ok_or_else(|| eyre!("ACME certificate wasn't issued"))?;
+        let private_key = key.serialize_private_key_pem();
+
+        self.resolver
+            .set_certificate(certificate.as_bytes(), private_key.as_bytes())?;
+        write_file(&self.acme_dir.join(PRIVATE_KEY_FILE), &private_key)?;
+        write_file(&self.acme_dir.join(CERTIFICATE_FILE), &certificate)?;
+
+        Ok(())
+    }
+
+    async fn account(&self, acme: &AcmeConfig) -> eyre::Result<Account> {
+        let path = self.acme_dir.join(ACCOUNT_FILE);
+        if path.exists() {
+            let credentials = std::fs::read_to_string(&path)?;
+            let credentials: AccountCredentials = serde_json::from_str(&credentials)
+                .wrap_err_with(|| format!("invalid ACME account at {}", path.display()))?;
+            return Ok(Account::from_credentials(credentials).await?);
+        }
+
+        let contact = acme.contact_email.as_ref().map(|e| format!("mailto:{e}"));
+        let contact: Vec<&str> = contact.iter().map(String::as_str).collect();
+        let (account, credentials) = Account::create(
+            &NewAccount {
+                contact: &contact,
+                terms_of_service_agreed: true,
+                only_return_existing: false,
+            },
+            &acme.directory_url,
+            None,
+        )
+        .await?;
+        write_file(&path, &serde_json::to_string(&credentials)?)?;
+
+        Ok(account)
+    }
+}
+
+async fn wait_order_ready(order: &mut Order) -> eyre::Result<()> {
+    let mut delay = Duration::from_millis(250);
+    for _ in 0..ORDER_POLL_ATTEMPTS {
+        tokio::time::sleep(delay).await;
+        let state = order.refresh().await?;
+        match state.status {
+            OrderStatus::Ready | OrderStatus::Valid => return Ok(()),
+            OrderStatus::Invalid => {
+                eyre::bail!("ACME order is invalid: {:?}", state.error)
+            }
+            OrderStatus::Pending | OrderStatus::Processing => delay *= 2,
+        }
+    }
+
+    Err(eyre!("ACME order wasn't validated in time"))
+}
+
+/// Self-signed certificate with `acmeIdentifier` extension, see RFC 8737
+fn tls_alpn_challenge_certificate(domain: &str, digest: &[u8]) -> eyre::Result<(String, String)> {
+    let mut params = rcgen::CertificateParams::new(vec![domain.to_string()]);
+    params.custom_extensions = vec![rcgen::CustomExtension::new_acme_identifier(digest)];
+    let certificate = rcgen::Certificate::from_params(params)?;
+
+    Ok((
+        certificate.serialize_pem()?,
+        certificate.serialize_private_key_pem(),
+    ))
+}
+
+/// Whether the first certificate in the PEM chain expires sooner than `period`
+fn expires_within(certificate: &[u8], period: Duration) -> eyre::Result<bool> {
+    let (_, pem) = x509_parser::pem::parse_x509_pem(certificate)
+        .map_err(|e| eyre!("invalid certificate PEM: {e}"))?;
+    let certificate = pem
+        .parse_x509()
+        .map_err(|e| eyre!("invalid certificate: {e}"))?;
+    let not_after = certificate.validity().not_after.timestamp();
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+    Ok(not_after < (now + period).as_secs() as i64)
+}
+
+fn write_file(path: &Path, contents: &str) -> eyre::Result<()> {
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, contents)
+        .and_then(|_| std::fs::rename(&tmp, path))
+        .wrap_err_with(|| format!("error writing {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn self_signed(not_after_year: i32) -> (String, String) {
+        let mut params = rcgen::CertificateParams::new(vec!["localhost".to_string()]);
+        params.not_before = rcgen::date_time_ymd(2000, 1, 1);
+        params.not_after = rcgen::date_time_ymd(not_after_year, 1, 1);
+        let certificate = rcgen::Certificate::from_params(params).unwrap();
+        (
+            certificate.serialize_pem().unwrap(),
+            certificate.serialize_private_key_pem(),
+        )
+    }
+
+    #[test]
+    fn certificate_expiration() {
+        let day = Duration::from_secs(24 * 60 * 60);
+
+        let (expired, _) = self_signed(2001);
+        assert!(expires_within(expired.as_bytes(), day).unwrap());
+
+        let (valid, _) = self_signed(2100);
+        assert!(!expires_within(valid.as_bytes(), 30 * day).unwrap());
+    }
+
+    #[test]
+    fn load_certificate_from_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let (certificate, private_key) = self_signed(2100);
+        let certificate_path = dir.path().join("cert.pem");
+        let private_key_path = dir.path().join("key.pem");
+        std::fs::write(&certificate_path, certificate).unwrap();
+        std::fs::write(&private_key_path, private_key).unwrap();
+
+        let resolver = Arc::new(TlsCertificateResolver::default());
+        let config = WebsocketTlsConfig {
+            port: 0,
+            certificate_path: Some(certificate_path),
+            private_key_path: Some(private_key_path),
+            acme: None,
+        };
+        let manager = CertificateManager::new(config, dir.path().to_path_buf(), resolver.clone());
+        manager.load().unwrap();
+
+        assert!(resolver.has_certificate());
+        assert!(manager.start().is_none());
+    }
+
+    #[test]
+    fn tls_alpn_certificate_is_valid() {
+        let (certificate, private_key) =
+            tls_alpn_challenge_certificate("example.com", &[0u8; 32]).unwrap();
+
+        let resolver = TlsCertificateResolver::default();
+        resolver
+            .set_challenge_certificate(
+                "example.com".to_string(),
+                certificate.as_bytes(),
+                private_key.as_bytes(),
+            )
+            .unwrap();
+    }
+}
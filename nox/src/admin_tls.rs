@@ -0,0 +1,165 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use axum::middleware::AddExtension;
+use axum::Extension;
+use axum_server::accept::Accept;
+use axum_server::tls_rustls::{RustlsAcceptor, RustlsConfig};
+use eyre::WrapErr;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use server_config::AdminTlsConfig;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::server::TlsStream;
+use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+use tower_layer::Layer;
+
+/// Certificate of an admin client verified against `admin_tls.client_ca_path`.
+/// Any such certificate has the admin role, subjects aren't mapped to roles.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClientCertificate {
+    /// Common name of the subject, or the certificate fingerprint if there's none
+    pub subject: String,
+}
+
+impl ClientCertificate {
+    pub fn from_der(der: &[u8]) -> Self {
+        let common_name =
+            x509_parser::parse_x509_certificate(der)
+                .ok()
+                .and_then(|(_, certificate)| {
+                    let common_name = certificate.subject().iter_common_name().next()?;
+                    common_name.as_str().ok().map(str::to_string)
+                });
+        let subject = common_name.unwrap_or_else(|| blake3::hash(der).to_hex().to_string());
+        Self { subject }
+    }
+
+    /// Caller as recorded in the audit log
+    pub fn caller(&self) -> String {
+        format!("cert:{}", self.subject)
+    }
+}
+
+/// TLS of the http and gRPC listeners, see [AdminTlsConfig]
+#[derive(Clone)]
+pub struct AdminTls {
+    http: RustlsConfig,
+    grpc: ServerTlsConfig,
+}
+
+impl AdminTls {
+    /// Reads certificates configured in `config`
+    pub fn load(config: &AdminTlsConfig) -> eyre::Result<Self> {
+        let read = |path: &PathBuf| {
+            std::fs::read(path).wrap_err(format!("failed to read {}", path.display()))
+        };
+        let certificate = read(&config.certificate_path)?;
+        let private_key = read(&config.private_key_path)?;
+        let client_ca = read(&config.client_ca_path)?;
+        let client_auth_optional = !config.require_client_certificate;
+
+        let http = fluence_libp2p::static_tls_server_config(
+            &certificate,
+            &private_key,
+            Some(&client_ca),
+            client_auth_optional,
+        )
+        .wrap_err("invalid http_config.admin_tls certificates")?;
+        let grpc = ServerTlsConfig::new()
+            .identity(Identity::from_pem(&certificate, &private_key))
+            .client_ca_root(Certificate::from_pem(&client_ca))
+            .client_auth_optional(client_auth_optional);
+        Ok(Self {
+            http: RustlsConfig::from_config(Arc::new(http)),
+            grpc,
+        })
+    }
+
+    pub fn grpc_config(&self) -> ServerTlsConfig {
+        self.grpc.clone()
+    }
+
+    pub fn http_acceptor(&self) -> ClientCertificateAcceptor {
+        ClientCertificateAcceptor {
+            inner: RustlsAcceptor::new(self.http.clone()),
+        }
+    }
+}
+
+/// Accepts TLS connections of the http listener, putting the [ClientCertificate] of the connection,
+/// if any, into extensions of its requests
+#[derive(Clone)]
+pub struct ClientCertificateAcceptor {
+    inner: RustlsAcceptor,
+}
+
+impl<I, S> Accept<I, S> for ClientCertificateAcceptor
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: Send + 'static,
+{
+    type Stream = TlsStream<I>;
+    type Service = AddExtension<S, Option<ClientCertificate>>;
+    type Future = BoxFuture<'static, io::Result<(Self::Stream, Self::Service)>>;
+
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        let accept = self.inner.accept(stream, service);
+        async move {
+            let (stream, service) = accept.await?;
+            let certificate = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|certificates| certificates.first())
+                .map(|certificate| ClientCertificate::from_der(&certificate.0));
+            Ok((stream, Extension(certificate).layer(service)))
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_certificate_subject() {
+        let mut params = rcgen::CertificateParams::new(vec![]);
+        params
+            .distinguished_name
+            .push(rcgen::DnType::CommonName, "deployer");
+        let der = rcgen::Certificate::from_params(params)
+            .unwrap()
+            .serialize_der()
+            .unwrap();
+        let certificate = ClientCertificate::from_der(&der);
+        assert_eq!(certificate.caller(), "cert:deployer");
+
+        let mut params = rcgen::CertificateParams::new(vec![]);
+        params.distinguished_name = rcgen::DistinguishedName::new();
+        let der = rcgen::Certificate::from_params(params)
+            .unwrap()
+            .serialize_der()
+            .unwrap();
+        let certificate = ClientCertificate::from_der(&der);
+        assert_eq!(certificate.subject, blake3::hash(&der).to_hex().to_string());
+    }
+}
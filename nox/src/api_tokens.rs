@@ -0,0 +1,314 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+
+/// Access level of an API token, every role includes the lower ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Metrics, config and node events
+    ReadOnly,
+    /// Profiling on top of read-only access
+    Operator,
+    /// Everything, including particle submission
+    Admin,
+}
+
+/// Issued token without its secret
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TokenInfo {
+    pub id: String,
+    pub name: String,
+    pub role: Role,
+    /// UNIX timestamp in milliseconds
+    pub created_at: u64,
+}
+
+/// Token secret, shown only once on issuance or rotation
+#[derive(Debug, Clone, Serialize)]
+pub struct IssuedToken {
+    pub id: String,
+    pub token: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct StoredToken {
+    id: String,
+    name: String,
+    role: Role,
+    created_at: u64,
+    /// Hash of the token, so the file doesn't grant access by itself
+    hash: String,
+}
+
+impl StoredToken {
+    fn info(&self) -> TokenInfo {
+        TokenInfo {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            role: self.role,
+            created_at: self.created_at,
+        }
+    }
+}
+
+#[derive(Default, Deserialize, Serialize)]
+struct TokensFile {
+    #[serde(default)]
+    tokens: Vec<StoredToken>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ApiTokenError {
+    #[error("token {0} not found")]
+    NotFound(String),
+    #[error("failed to persist api tokens: {0}")]
+    Persist(#[from] io::Error),
+}
+
+/// Id of the admin token from the node config
+pub const CONFIG_TOKEN_ID: &str = "config";
+
+#[derive(Default)]
+struct Inner {
+    tokens: Vec<StoredToken>,
+    /// Where issued tokens are persisted, `None` to keep them in memory only
+    path: Option<PathBuf>,
+    /// Token from the node config, it has the admin role and can't be revoked at runtime
+    admin_token: Option<String>,
+}
+
+/// Tokens accepted by the http endpoint, issued and rotated by the management peer
+#[derive(Clone, Default)]
+pub struct ApiTokens {
+    inner: Arc<RwLock<Inner>>,
+}
+
+impl ApiTokens {
+    pub fn new(admin_token: Option<String>) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(Inner {
+                admin_token,
+                ..<_>::default()
+            })),
+        }
+    }
+
+    /// Creates tokens persisted in `path`, loading them from there if it exists
+    pub fn load(path: PathBuf, admin_token: Option<String>) -> io::Result<Self> {
+        let file: TokensFile = match std::fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => TokensFile::default(),
+            Err(err) => return Err(err),
+        };
+        Ok(Self {
+            inner: Arc::new(RwLock::new(Inner {
+                tokens: file.tokens,
+                path: Some(path),
+                admin_token,
+            })),
+        })
+    }
+
+    /// Whether there's any token to authorize with, endpoints requiring a token are hidden otherwise
+    pub fn is_enabled(&self) -> bool {
+        let inner = self.inner.read();
+        inner.admin_token.is_some() || !inner.tokens.is_empty()
+    }
+
+    /// Role of `token`, `None` if it's unknown
+    pub fn role(&self, token: &str) -> Option<Role> {
+        self.identify(token).map(|(_, role)| role)
+    }
+
+    /// Id and role of `token`, the token from the node config is identified as `config`.
+    /// Tokens are compared in constant time, so timings don't reveal how much of a token matches.
+    pub fn identify(&self, token: &str) -> Option<(String, Role)> {
+        let inner = self.inner.read();
+        let is_admin = inner
+            .admin_token
+            .as_ref()
+            .is_some_and(|admin| bool::from(admin.as_bytes().ct_eq(token.as_bytes())));
+        if is_admin {
+            return Some((CONFIG_TOKEN_ID.to_string(), Role::Admin));
+        }
+        let hash = hash(token);
+        inner
+            .tokens
+            .iter()
+            .find(|t| bool::from(t.hash.as_bytes().ct_eq(hash.as_bytes())))
+            .map(|t| (t.id.clone(), t.role))
+    }
+
+    pub fn list(&self) -> Vec<TokenInfo> {
+        self.inner.read().tokens.iter().map(|t| t.info()).collect()
+    }
+
+    pub fn issue(&self, name: String, role: Role) -> Result<IssuedToken, ApiTokenError> {
+        let token = generate_secret();
+        let id = hex::encode(rand::random::<[u8; 8]>());
+        let mut inner = self.inner.write();
+        let mut tokens = inner.tokens.clone();
+        tokens.push(StoredToken {
+            id: id.clone(),
+            name,
+            role,
+            created_at: now_millis::now_ms() as u64,
+            hash: hash(&token),
+        });
+        inner.replace(tokens)?;
+        Ok(IssuedToken { id, token })
+    }
+
+    /// Replaces the secret of the token, the old one stops working right away
+    pub fn rotate(&self, id: &str) -> Result<IssuedToken, ApiTokenError> {
+        let token = generate_secret();
+        let mut inner = self.inner.write();
+        let mut tokens = inner.tokens.clone();
+        let stored = tokens
+            .iter_mut()
+            .find(|t| t.id == id)
+            .ok_or_else(|| ApiTokenError::NotFound(id.to_string()))?;
+        stored.hash = hash(&token);
+        inner.replace(tokens)?;
+        Ok(IssuedToken {
+            id: id.to_string(),
+            token,
+        })
+    }
+
+    pub fn revoke(&self, id: &str) -> Result<TokenInfo, ApiTokenError> {
+        let mut inner = self.inner.write();
+        let mut tokens = inner.tokens.clone();
+        let position = tokens
+            .iter()
+            .position(|t| t.id == id)
+            .ok_or_else(|| ApiTokenError::NotFound(id.to_string()))?;
+        let removed = tokens.remove(position);
+        inner.replace(tokens)?;
+        Ok(removed.info())
+    }
+}
+
+impl Inner {
+    /// Persists `tokens` and only then puts them in use, so a failed write changes nothing
+    fn replace(&mut self, tokens: Vec<StoredToken>) -> io::Result<()> {
+        if let Some(path) = &self.path {
+            let file = TokensFile { tokens };
+            let contents = toml::to_string(&file)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            fs_utils::write_atomically(path, contents.as_bytes())?;
+            self.tokens = file.tokens;
+        } else {
+            self.tokens = tokens;
+        }
+        Ok(())
+    }
+}
+
+fn generate_secret() -> String {
+    format!("nox_{}", hex::encode(rand::random::<[u8; 32]>()))
+}
+
+fn hash(token: &str) -> String {
+    blake3::hash(token.as_bytes()).to_hex().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    #[test]
+    fn roles() {
+        let tokens = ApiTokens::new(Some("secret".to_string()));
+        assert!(tokens.is_enabled());
+        assert_eq!(tokens.role("secret"), Some(Role::Admin));
+        assert_eq!(tokens.role("wrong"), None);
+
+        let issued = tokens.issue("grafana".to_string(), Role::ReadOnly).unwrap();
+        assert_eq!(tokens.role(&issued.token), Some(Role::ReadOnly));
+        assert_eq!(
+            tokens.identify("secret"),
+            Some((CONFIG_TOKEN_ID.to_string(), Role::Admin))
+        );
+        assert_eq!(
+            tokens.identify(&issued.token),
+            Some((issued.id, Role::ReadOnly))
+        );
+        assert!(Role::ReadOnly < Role::Operator && Role::Operator < Role::Admin);
+
+        assert!(!ApiTokens::default().is_enabled());
+    }
+
+    #[test]
+    fn rotate_and_revoke() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("api_tokens.toml");
+
+        let tokens = ApiTokens::load(path.clone(), None).unwrap();
+        let issued = tokens.issue("ci".to_string(), Role::Operator).unwrap();
+        let rotated = tokens.rotate(&issued.id).unwrap();
+        assert_eq!(tokens.role(&issued.token), None);
+        assert_eq!(tokens.role(&rotated.token), Some(Role::Operator));
+
+        // only hashes are persisted
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(!contents.contains(&rotated.token));
+
+        let tokens = ApiTokens::load(path.clone(), None).unwrap();
+        assert_eq!(tokens.role(&rotated.token), Some(Role::Operator));
+        let revoked = tokens.revoke(&issued.id).unwrap();
+        assert_eq!(revoked.name, "ci");
+        assert!(matches!(
+            tokens.revoke(&issued.id),
+            Err(ApiTokenError::NotFound(_))
+        ));
+
+        let tokens = ApiTokens::load(path, None).unwrap();
+        assert!(tokens.list().is_empty());
+        assert!(!tokens.is_enabled());
+    }
+
+    #[test]
+    fn failed_persist_changes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("api_tokens.toml");
+
+        let tokens = ApiTokens::load(path.clone(), None).unwrap();
+        let issued = tokens.issue("ci".to_string(), Role::Operator).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o077, 0);
+
+        // the file can't be replaced while a directory is in its place
+        std::fs::remove_file(&path).unwrap();
+        std::fs::create_dir(&path).unwrap();
+        assert!(tokens.issue("grafana".to_string(), Role::ReadOnly).is_err());
+        assert!(tokens.rotate(&issued.id).is_err());
+        assert!(tokens.revoke(&issued.id).is_err());
+        assert_eq!(tokens.list().len(), 1);
+        assert_eq!(tokens.role(&issued.token), Some(Role::Operator));
+    }
+}
@@ -14,12 +14,14 @@
  * limitations under the License.
  */
 
+use connection_pool::PeerClass;
 use itertools::Itertools;
 use libp2p::{
     core::{multiaddr::Protocol, Multiaddr},
     identify::Event as IdentifyEvent,
 };
 use particle_protocol::PROTOCOL_NAME;
+use peer_exchange::PROTOCOL_NAME as PEER_EXCHANGE_PROTOCOL;
 use tokio::sync::oneshot;
 
 use super::FluenceNetworkBehaviour;
@@ -27,7 +29,13 @@ use super::FluenceNetworkBehaviour;
 /// Network address information is exchanged via Identify protocol.
 /// That information is passed to relay, so nodes know each other's addresses
 impl FluenceNetworkBehaviour {
-    pub fn inject_identify_event(&mut self, event: IdentifyEvent, allow_local_addresses: bool) {
+    /// Nodes with a `protocol_version` other than ours are from another network and are disconnected
+    pub fn inject_identify_event(
+        &mut self,
+        event: IdentifyEvent,
+        allow_local_addresses: bool,
+        protocol_version: &str,
+    ) {
         match event {
             IdentifyEvent::Received { peer_id, info, .. } => {
                 log::trace!(
@@ -55,7 +63,20 @@ impl FluenceNetworkBehaviour {
                     }
                 }
 
-                if supports_fluence {
+                if supports_fluence
+                    && supports_kademlia
+                    && info.protocol_version != protocol_version
+                {
+                    log::warn!(
+                        target: "network",
+                        "Disconnecting node {}: it's from another network, protocol version {} doesn't match {}",
+                        peer_id, info.protocol_version, protocol_version
+                    );
+                    // kademlia adds connected nodes on its own
+                    self.kademlia.remove_kad_node(&peer_id);
+                    let (out, _inlet) = oneshot::channel();
+                    self.connection_pool.disconnect(peer_id, out);
+                } else if supports_fluence {
                     let protocols: Vec<_> = info.protocols.iter().map(|p| p.to_string()).collect();
                     log::debug!(
                         target: "network",
@@ -66,7 +87,21 @@ impl FluenceNetworkBehaviour {
                     // we want to have full info on non-kademlia peers as well
                     self.connection_pool
                         .add_discovered_addresses(peer_id, addresses.clone());
-                    if supports_kademlia {
+                    self.connection_pool.set_protocols(peer_id, protocols);
+                    let class = if supports_kademlia {
+                        PeerClass::Node
+                    } else {
+                        PeerClass::Client
+                    };
+                    self.connection_pool.set_peer_class(peer_id, class);
+                    // peers with bad reputation are kept out of the routing table
+                    let banned = self.connection_pool.reputation().is_banned(&peer_id);
+                    if supports_kademlia && !banned {
+                        if let Some(peer_exchange) = self.peer_exchange.as_mut() {
+                            let supports_exchange =
+                                info.protocols.iter().any(|p| p.eq(&PEER_EXCHANGE_PROTOCOL));
+                            peer_exchange.add_node(peer_id, addresses.clone(), supports_exchange);
+                        }
                         self.kademlia.add_kad_node(peer_id, addresses);
                     }
                 } else {
@@ -93,7 +128,7 @@ impl FluenceNetworkBehaviour {
     }
 }
 
-fn filter_addresses(addresses: Vec<Multiaddr>, allow_local: bool) -> Vec<Multiaddr> {
+pub(super) fn filter_addresses(addresses: Vec<Multiaddr>, allow_local: bool) -> Vec<Multiaddr> {
     // Deduplicate addresses
     let addresses = addresses.iter().unique();
 
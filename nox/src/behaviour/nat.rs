@@ -0,0 +1,172 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::Arc;
+
+use libp2p::autonat::{Event as AutonatEvent, NatStatus};
+use libp2p::core::transport::ListenerId;
+use libp2p::dcutr::Event as DcutrEvent;
+use libp2p::multiaddr::Protocol;
+use libp2p::relay::client::Event as RelayClientEvent;
+use libp2p::upnp::Event as UpnpEvent;
+use libp2p::{Multiaddr, Swarm};
+use parking_lot::RwLock;
+
+use super::FluenceNetworkBehaviour;
+
+/// Keeps `/p2p-circuit` listeners on configured relays while the node isn't publicly reachable,
+/// so peers can reach it through a relay and then upgrade to a direct connection via DCUtR
+pub struct RelayListeners {
+    relays: Vec<Multiaddr>,
+    listeners: Vec<ListenerId>,
+}
+
+impl RelayListeners {
+    pub fn new(relays: Vec<Multiaddr>) -> Self {
+        Self {
+            relays,
+            listeners: vec![],
+        }
+    }
+
+    /// Without AutoNAT reachability is unknown, so relays are used unconditionally
+    pub fn start(&mut self, swarm: &mut Swarm<FluenceNetworkBehaviour>) {
+        if !swarm.behaviour().autonat.is_enabled() {
+            self.listen(swarm);
+        }
+    }
+
+    pub fn inject_autonat_event(
+        &mut self,
+        swarm: &mut Swarm<FluenceNetworkBehaviour>,
+        event: AutonatEvent,
+    ) {
+        if let AutonatEvent::StatusChanged { old, new } = event {
+            log::info!(target: "network", "NAT status changed from {old:?} to {new:?}");
+            match new {
+                NatStatus::Private => self.listen(swarm),
+                NatStatus::Public(_) => self.stop(swarm),
+                NatStatus::Unknown => {}
+            }
+        }
+    }
+
+    fn listen(&mut self, swarm: &mut Swarm<FluenceNetworkBehaviour>) {
+        if !self.listeners.is_empty() {
+            return;
+        }
+
+        for relay in &self.relays {
+            let circuit = relay.clone().with(Protocol::P2pCircuit);
+            match swarm.listen_on(circuit) {
+                Ok(listener) => self.listeners.push(listener),
+                Err(err) => {
+                    log::warn!(target: "network", "Failed to listen via relay {relay}: {err}")
+                }
+            }
+        }
+    }
+
+    fn stop(&mut self, swarm: &mut Swarm<FluenceNetworkBehaviour>) {
+        for listener in self.listeners.drain(..) {
+            swarm.remove_listener(listener);
+        }
+    }
+}
+
+pub fn log_relay_client_event(event: RelayClientEvent) {
+    match event {
+        RelayClientEvent::ReservationReqAccepted {
+            relay_peer_id,
+            renewal,
+            ..
+        } => {
+            if !renewal {
+                log::info!(target: "network", "Reserved a slot on relay {relay_peer_id}");
+            }
+        }
+        RelayClientEvent::OutboundCircuitEstablished { relay_peer_id, .. } => {
+            log::debug!(target: "network", "Established outbound circuit via relay {relay_peer_id}");
+        }
+        RelayClientEvent::InboundCircuitEstablished { src_peer_id, .. } => {
+            log::debug!(target: "network", "Established inbound circuit from {src_peer_id}");
+        }
+    }
+}
+
+pub fn log_dcutr_event(event: DcutrEvent) {
+    match event.result {
+        Ok(connection_id) => log::debug!(
+            target: "network",
+            "Upgraded relayed connection with {} to direct connection {connection_id:?}",
+            event.remote_peer_id
+        ),
+        Err(err) => log::debug!(
+            target: "network",
+            "Failed to upgrade relayed connection with {}: {err}",
+            event.remote_peer_id
+        ),
+    }
+}
+
+/// IPv6 hosts are usually reachable without NAT, so an observed address that is one of
+/// our own global IPv6 listen addresses is confirmed right away. Other candidates, i.e.
+/// observed IPv4 addresses behind NAT, are left to AutoNAT.
+pub fn confirm_ipv6_candidate(swarm: &mut Swarm<FluenceNetworkBehaviour>, candidate: Multiaddr) {
+    let global_ipv6 = matches!(candidate.iter().next(), Some(Protocol::Ip6(ip)) if ip.is_global());
+    let listened = swarm.listeners().any(|addr| addr == &candidate);
+    let confirmed = swarm.external_addresses().any(|addr| addr == &candidate);
+    if global_ipv6 && listened && !confirmed {
+        log::info!(target: "network", "Confirmed IPv6 external address {candidate}");
+        swarm.add_external_address(candidate);
+    }
+}
+
+/// External addresses mapped on the gateway via UPnP. Swarm confirms them as external addresses
+/// by itself, these are kept to be reported by `peer identify` along with configured ones.
+#[derive(Clone, Default)]
+pub struct PortMappings {
+    addresses: Arc<RwLock<Vec<Multiaddr>>>,
+}
+
+impl PortMappings {
+    pub fn addresses(&self) -> Vec<Multiaddr> {
+        self.addresses.read().clone()
+    }
+
+    pub fn inject_upnp_event(&self, event: UpnpEvent) {
+        match event {
+            UpnpEvent::NewExternalAddr(addr) => {
+                log::info!(target: "network", "Mapped external address {addr} via UPnP");
+                let mut addresses = self.addresses.write();
+                if !addresses.contains(&addr) {
+                    addresses.push(addr);
+                }
+            }
+            UpnpEvent::ExpiredExternalAddr(addr) => {
+                log::info!(target: "network", "UPnP mapping of {addr} has expired");
+                self.addresses.write().retain(|a| a != &addr);
+            }
+            UpnpEvent::GatewayNotFound => {
+                log::warn!(target: "network", "UPnP gateway not found, ports aren't mapped")
+            }
+            UpnpEvent::NonRoutableGateway => log::warn!(
+                target: "network",
+                "UPnP gateway isn't exposed to the public network, ports aren't mapped"
+            ),
+        }
+    }
+}
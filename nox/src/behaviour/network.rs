@@ -15,20 +15,30 @@
  */
 use libp2p::identify::Config as IdentifyConfig;
 use libp2p::{
-    connection_limits::Behaviour as ConnectionLimits,
+    autonat::Behaviour as Autonat,
+    dcutr::Behaviour as Dcutr,
     identify::Behaviour as Identify,
     ping::{Behaviour as Ping, Config as PingConfig},
-    swarm::NetworkBehaviour,
+    relay::{client::Behaviour as RelayClient, Behaviour as RelayServer},
+    swarm::{behaviour::toggle::Toggle, NetworkBehaviour},
+    upnp::tokio::Behaviour as Upnp,
 };
 use tokio::sync::mpsc;
 
-use connection_pool::ConnectionPoolBehaviour;
+use connection_pool::{
+    BandwidthLimiter, ConnectionLimitsBehaviour, ConnectionPoolBehaviour, DialBackoff,
+    HostHandover, KeepAlive, MaintenanceAnnouncement, RateLimiter,
+};
 use health::HealthCheckRegistry;
 use kademlia::{Kademlia, KademliaConfig};
-use particle_protocol::{ExtendedParticle, PROTOCOL_NAME};
+use particle_protocol::ExtendedParticle;
+use peer_exchange::PeerExchange;
+use peer_reputation::PeerReputation;
+use pubsub::{PubSub, PubSubApi};
 use server_config::NetworkConfig;
 
 use crate::connectivity::Connectivity;
+use crate::dns::DnsResolver;
 use crate::health::{BootstrapNodesHealth, ConnectivityHealth, KademliaBootstrapHealth};
 
 /// Coordinates protocols, so they can cooperate
@@ -36,22 +46,37 @@ use crate::health::{BootstrapNodesHealth, ConnectivityHealth, KademliaBootstrapH
 pub struct FluenceNetworkBehaviour {
     identify: Identify,
     ping: Ping,
-    connection_limits: ConnectionLimits,
+    connection_limits: ConnectionLimitsBehaviour,
     pub(crate) connection_pool: ConnectionPoolBehaviour,
+    maintenance: MaintenanceAnnouncement,
+    host_handover: HostHandover,
     pub(crate) kademlia: Kademlia,
+    pub(crate) autonat: Toggle<Autonat>,
+    relay_client: Toggle<RelayClient>,
+    relay_server: Toggle<RelayServer>,
+    dcutr: Toggle<Dcutr>,
+    pubsub: Toggle<PubSub>,
+    upnp: Toggle<Upnp>,
+    pub(crate) peer_exchange: Toggle<PeerExchange>,
 }
 
 impl FluenceNetworkBehaviour {
+    /// `relay_client` must be created together with the relay transport passed to the swarm
     pub fn new(
         cfg: NetworkConfig,
+        relay_client: Option<RelayClient>,
         health_registry: Option<&mut HealthCheckRegistry>,
     ) -> (Self, Connectivity, mpsc::Receiver<ExtendedParticle>) {
         let local_public_key = cfg.key_pair.public();
         let identify = Identify::new(
-            IdentifyConfig::new(PROTOCOL_NAME.into(), local_public_key)
+            IdentifyConfig::new(cfg.protocol_version.clone(), local_public_key)
                 .with_agent_version(cfg.node_version.into()),
         );
-        let ping = Ping::new(PingConfig::new());
+        let ping = Ping::new(
+            PingConfig::new()
+                .with_interval(cfg.keep_alive.ping_interval)
+                .with_timeout(cfg.keep_alive.ping_timeout),
+        );
 
         let kad_config = KademliaConfig {
             peer_id: cfg.local_peer_id,
@@ -59,21 +84,72 @@ impl FluenceNetworkBehaviour {
         };
 
         let (kademlia, kademlia_api) = Kademlia::new(kad_config, cfg.libp2p_metrics);
+        let bandwidth = BandwidthLimiter::new(cfg.bandwidth);
         let (connection_pool, particle_stream, connection_pool_api) = ConnectionPoolBehaviour::new(
             cfg.particle_queue_buffer,
             cfg.protocol_config,
-            cfg.local_peer_id,
-            cfg.connection_pool_metrics,
+            cfg.key_pair.clone().into(),
+            cfg.connection_pool_metrics.clone(),
+            PeerReputation::new(cfg.reputation),
+            bandwidth.clone(),
+            RateLimiter::new(cfg.rate_limit),
+            cfg.max_particle_size,
+            cfg.particle_signatures,
+            cfg.peer_filter,
+            DialBackoff::new(cfg.dial_backoff.clone()),
+            cfg.prefer_ipv6,
+            KeepAlive::new(cfg.keep_alive.clone(), &cfg.bootstrap_nodes),
+            cfg.endpoint_peers,
         );
+        let maintenance = connection_pool.maintenance_announcement();
+        let host_handover = connection_pool.host_handover(cfg.host_handover);
+
+        let connection_limits =
+            ConnectionLimitsBehaviour::new(cfg.connection_limits, cfg.connection_pool_metrics);
+
+        let nat = cfg.nat.as_ref();
+        let autonat = nat.filter(|nat| nat.autonat).map(|nat| {
+            Autonat::new(
+                cfg.local_peer_id,
+                nat.autonat_config(cfg.allow_local_addresses),
+            )
+        });
+        let relay_server = nat
+            .filter(|nat| nat.relay_server)
+            .map(|_| RelayServer::new(cfg.local_peer_id, Default::default()));
+        let dcutr = nat
+            .filter(|nat| nat.hole_punching() && relay_client.is_some())
+            .map(|_| Dcutr::new(cfg.local_peer_id));
+        let upnp = nat.filter(|nat| nat.upnp).map(|_| Upnp::default());
+
+        let (pubsub, pubsub_api) = match &cfg.pubsub {
+            Some(pubsub) => {
+                let config = pubsub.as_libp2p().expect("pubsub config is validated");
+                let (pubsub, api) = PubSub::new(cfg.key_pair.clone(), config);
+                (Some(pubsub), api)
+            }
+            None => (None, PubSubApi::disabled()),
+        };
 
-        let connection_limits = ConnectionLimits::new(cfg.connection_limits);
+        let peer_exchange = cfg
+            .peer_exchange
+            .map(|config| PeerExchange::new(config, cfg.local_peer_id));
 
         let this = Self {
             kademlia,
             connection_pool,
+            maintenance,
+            host_handover,
             connection_limits,
             identify,
             ping,
+            autonat: autonat.into(),
+            relay_client: relay_client.into(),
+            relay_server: relay_server.into(),
+            dcutr: dcutr.into(),
+            pubsub: pubsub.into(),
+            upnp: upnp.into(),
+            peer_exchange: peer_exchange.into(),
         };
 
         let bootstrap_nodes = cfg.bootstrap_nodes.clone();
@@ -98,6 +174,10 @@ impl FluenceNetworkBehaviour {
             bootstrap_frequency: cfg.bootstrap_frequency,
             metrics: cfg.connectivity_metrics,
             health,
+            bandwidth,
+            pubsub: pubsub_api,
+            dial_backoff: cfg.dial_backoff,
+            dns: DnsResolver::new(cfg.dns),
         };
 
         (this, connectivity, particle_stream)
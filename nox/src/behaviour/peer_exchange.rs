@@ -0,0 +1,58 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use peer_exchange::PeerExchangeEvent;
+
+use super::identify::filter_addresses;
+use super::FluenceNetworkBehaviour;
+
+/// Peers shared by other nodes are added to Kademlia and a few of them are dialed,
+/// so the node connects to the network faster and doesn't rely on bootstrap nodes alone
+impl FluenceNetworkBehaviour {
+    pub fn inject_peer_exchange_event(
+        &mut self,
+        event: PeerExchangeEvent,
+        allow_local_addresses: bool,
+    ) {
+        let PeerExchangeEvent::Discovered { from, peers } = event;
+        let Some(peer_exchange) = self.peer_exchange.as_mut() else {
+            return;
+        };
+
+        let mut dials = 0;
+        for contact in peers {
+            let addresses = filter_addresses(contact.addresses, allow_local_addresses);
+            let banned = self
+                .connection_pool
+                .reputation()
+                .is_banned(&contact.peer_id);
+            if addresses.is_empty() || banned {
+                continue;
+            }
+
+            log::debug!(
+                target: "network",
+                "Peer {} shared by {}, addresses {:?}",
+                contact.peer_id, from, addresses
+            );
+            if dials < peer_exchange.max_dials() && !peer_exchange.is_connected(&contact.peer_id) {
+                peer_exchange.dial(contact.peer_id, addresses.clone());
+                dials += 1;
+            }
+            self.kademlia.add_kad_node(contact.peer_id, addresses);
+        }
+    }
+}
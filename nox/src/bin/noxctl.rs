@@ -0,0 +1,127 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Sends commands to the control socket of a nox running on the same host
+
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use serde_json::{json, Value};
+use server_config::{default_base_dir, persistent_dir};
+
+const USAGE: &str = "\
+Usage: noxctl [--socket PATH] COMMAND [ARGUMENT]
+
+Commands:
+    status          Peer id, versions, uptime and counts of connections, services and workers
+    services        Services deployed on the host and workers
+    spells          Spells deployed on the host and workers
+    workers         Workers with their deals and activity
+    drain           Closes network connections as on shutdown, the node keeps running
+    maintenance     Stops accepting new deals and clients, prints drain progress
+    resume          Leaves maintenance
+    reload-config   Rereads the config and applies fields that can be changed at runtime
+    rotate-logs     Rotates the log file
+    rotate-host-key [GRACE_PERIOD]
+                    Generates a new host key, the node switches to it on restart and keeps serving
+                    the current peer id for GRACE_PERIOD, e.g. `12h` (24h by default)
+
+By default the socket is looked up at the default location of the node's persistent directory.";
+
+fn main() -> ExitCode {
+    let mut socket = persistent_dir(&default_base_dir()).join("nox.sock");
+    let mut command = None;
+    let mut argument = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--socket" | "-s" => match args.next() {
+                Some(path) => socket = PathBuf::from(path),
+                None => return usage_error("--socket requires a path"),
+            },
+            "--help" | "-h" => {
+                println!("{USAGE}");
+                return ExitCode::SUCCESS;
+            }
+            _ if command.is_none() => command = Some(arg),
+            _ if argument.is_none() => argument = Some(arg),
+            _ => return usage_error(&format!("unexpected argument {arg:?}")),
+        }
+    }
+
+    let command = match command.as_deref() {
+        Some("status") => "status",
+        Some("services") => "list_services",
+        Some("spells") => "list_spells",
+        Some("workers") => "list_workers",
+        Some("drain") => "drain",
+        Some("maintenance") => "enter_maintenance",
+        Some("resume") => "exit_maintenance",
+        Some("reload-config") => "reload_config",
+        Some("rotate-logs") => "rotate_logs",
+        Some("rotate-host-key") => "rotate_host_key",
+        Some(other) => return usage_error(&format!("unknown command {other:?}")),
+        None => return usage_error("command is required"),
+    };
+
+    let mut request = json!({ "command": command });
+    match argument {
+        Some(grace_period) if command == "rotate_host_key" => {
+            request["grace_period"] = grace_period.into()
+        }
+        Some(arg) => return usage_error(&format!("unexpected argument {arg:?}")),
+        None => {}
+    }
+
+    match send(&socket, &request) {
+        Ok(response) => print_response(response),
+        Err(err) => {
+            eprintln!("Failed to talk to nox at {}: {err}", socket.display());
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn send(socket: &Path, request: &Value) -> std::io::Result<Value> {
+    let mut stream = UnixStream::connect(socket)?;
+    let mut request = serde_json::to_vec(request)?;
+    request.push(b'\n');
+    stream.write_all(&request)?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    Ok(serde_json::from_str(&line)?)
+}
+
+fn print_response(response: Value) -> ExitCode {
+    if let Some(error) = response.get("error") {
+        eprintln!("Error: {}", error.as_str().unwrap_or_default());
+        return ExitCode::FAILURE;
+    }
+    match response.get("result") {
+        Some(Value::Null) | None => println!("ok"),
+        Some(result) => println!("{result:#}"),
+    }
+    ExitCode::SUCCESS
+}
+
+fn usage_error(message: &str) -> ExitCode {
+    eprintln!("{message}\n\n{USAGE}");
+    ExitCode::from(2)
+}
@@ -14,23 +14,716 @@
  * limitations under the License.
  */
 
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use aquamarine::AquamarineApi;
+use audit_log::{AuditEvent, AuditEventKind, AuditLog, AuditQuery};
+use connection_pool::{ConnectionPoolT, DrainConfig};
+use core_manager::manager::{CoreManager, CoreManagerFunctions};
 use futures::FutureExt;
-use particle_builtins::{ok, CustomService, NodeInfo};
-use particle_execution::ServiceFunction;
-use serde_json::json;
+use libp2p::PeerId;
+use particle_args::{Args, JError};
+use particle_builtins::{ok, wrap, wrap_unit, CustomService, NodeInfo};
+use particle_execution::{ParticleParams, ServiceFunction};
+use serde_json::{json, Value as JValue};
+use types::management::{Capability, ManagementKey};
+use types::peer_scope::PeerScope;
+use workers::{ManagementKeyError, PeerScopes};
+
+use crate::api_tokens::{ApiTokenError, ApiTokens, Role};
+use crate::behaviour::PortMappings;
+use crate::config_reload::ConfigReloader;
+use crate::log_control::LogControl;
+use crate::trust_graph::TrustGraph;
+use crate::Connectivity;
 
-pub fn make_peer_builtin(node_info: NodeInfo) -> (String, CustomService) {
+pub fn make_peer_builtin(
+    node_info: NodeInfo,
+    port_mappings: PortMappings,
+) -> (String, CustomService) {
     (
         "peer".to_string(),
         CustomService::new(
-            vec![("identify", make_peer_identify_closure(node_info))],
+            vec![(
+                "identify",
+                make_peer_identify_closure(node_info, port_mappings),
+            )],
             None,
         ),
     )
 }
-fn make_peer_identify_closure(node_info: NodeInfo) -> ServiceFunction {
+fn make_peer_identify_closure(node_info: NodeInfo, port_mappings: PortMappings) -> ServiceFunction {
     ServiceFunction::Immut(Box::new(move |_args, _params| {
-        let node_info = node_info.clone();
+        let mut node_info = node_info.clone();
+        for addr in port_mappings.addresses() {
+            if !node_info.external_addresses.contains(&addr) {
+                node_info.external_addresses.push(addr);
+            }
+        }
         async move { ok(json!(node_info)) }.boxed()
     }))
 }
+
+pub fn make_aquavm_builtin(
+    aquamarine_api: AquamarineApi,
+    scopes: PeerScopes,
+) -> (String, CustomService) {
+    (
+        "aquavm".to_string(),
+        CustomService::new(
+            vec![(
+                "update_interpreter",
+                make_update_interpreter_closure(aquamarine_api, scopes),
+            )],
+            None,
+        ),
+    )
+}
+
+/// Switches AquaVMs to the interpreter at the given path, management peer only.
+/// Executions in flight finish on the old interpreter.
+fn make_update_interpreter_closure(
+    aquamarine_api: AquamarineApi,
+    scopes: PeerScopes,
+) -> ServiceFunction {
+    ServiceFunction::Immut(Box::new(move |args, params| {
+        let aquamarine_api = aquamarine_api.clone();
+        let scopes = scopes.clone();
+        async move { wrap_unit(update_interpreter(aquamarine_api, scopes, args, params).await) }
+            .boxed()
+    }))
+}
+
+async fn update_interpreter(
+    aquamarine_api: AquamarineApi,
+    scopes: PeerScopes,
+    args: Args,
+    params: ParticleParams,
+) -> Result<(), JError> {
+    if !scopes.is_management(params.init_peer_id) {
+        return Err(JError::new(format!(
+            "aquavm.update_interpreter can be called only by management peer id; init_peer_id={}",
+            params.init_peer_id
+        )));
+    }
+    let path: String = Args::next("path", &mut args.function_args.into_iter())?;
+    aquamarine_api
+        .update_interpreter(PathBuf::from(path))
+        .await
+        .map_err(|err| JError::new(err.to_string()))
+}
+
+pub fn make_config_builtin(
+    reloader: ConfigReloader,
+    scopes: PeerScopes,
+) -> (String, CustomService) {
+    (
+        "config".to_string(),
+        CustomService::new(
+            vec![
+                (
+                    "reload",
+                    make_reload_closure(reloader.clone(), scopes.clone()),
+                ),
+                (
+                    "effective",
+                    make_effective_closure(reloader.clone(), scopes.clone()),
+                ),
+                (
+                    "registry",
+                    make_registry_closure(reloader.clone(), scopes.clone()),
+                ),
+                ("set_registry", make_set_registry_closure(reloader, scopes)),
+            ],
+            None,
+        ),
+    )
+}
+
+/// Rereads the node config and applies reloadable fields, management peer only.
+/// Returns which changed fields were applied and which require restart.
+fn make_reload_closure(reloader: ConfigReloader, scopes: PeerScopes) -> ServiceFunction {
+    ServiceFunction::Immut(Box::new(move |_args, params| {
+        let reloader = reloader.clone();
+        let scopes = scopes.clone();
+        async move { wrap(reload_config(reloader, scopes, params).await) }.boxed()
+    }))
+}
+
+async fn reload_config(
+    reloader: ConfigReloader,
+    scopes: PeerScopes,
+    params: ParticleParams,
+) -> Result<JValue, JError> {
+    if !scopes.is_management(params.init_peer_id) {
+        return Err(JError::new(format!(
+            "config.reload can be called only by management peer id; init_peer_id={}",
+            params.init_peer_id
+        )));
+    }
+    let report = reloader
+        .reload()
+        .await
+        .map_err(|err| JError::new(format!("config reload failed: {err:?}")))?;
+    Ok(json!(report))
+}
+
+/// Effective config with secrets masked, management or host peer on the host only
+fn make_effective_closure(reloader: ConfigReloader, scopes: PeerScopes) -> ServiceFunction {
+    ServiceFunction::Immut(Box::new(move |_args, params| {
+        let reloader = reloader.clone();
+        let scopes = scopes.clone();
+        async move { wrap(effective_config(reloader, scopes, params).await) }.boxed()
+    }))
+}
+
+async fn effective_config(
+    reloader: ConfigReloader,
+    scopes: PeerScopes,
+    params: ParticleParams,
+) -> Result<JValue, JError> {
+    let is_allowed =
+        scopes.is_management(params.init_peer_id) || scopes.is_host(params.init_peer_id);
+    if !matches!(params.peer_scope, PeerScope::Host) || !is_allowed {
+        return Err(JError::new(format!(
+            "config.effective can be called only on the host by management or host peer id; init_peer_id={}",
+            params.init_peer_id
+        )));
+    }
+    reloader
+        .effective_config()
+        .await
+        .ok_or_else(|| JError::new("effective config isn't available on this node"))
+}
+
+/// Registry settings the node runs with, management or host peer on the host only
+fn make_registry_closure(reloader: ConfigReloader, scopes: PeerScopes) -> ServiceFunction {
+    ServiceFunction::Immut(Box::new(move |_args, params| {
+        let reloader = reloader.clone();
+        let scopes = scopes.clone();
+        async move {
+            let is_allowed =
+                scopes.is_management(params.init_peer_id) || scopes.is_host(params.init_peer_id);
+            if !matches!(params.peer_scope, PeerScope::Host) || !is_allowed {
+                return wrap(Err(JError::new(format!(
+                    "config.registry can be called only on the host by management or host peer id; init_peer_id={}",
+                    params.init_peer_id
+                ))));
+            }
+            ok(json!(reloader.registry_config().await))
+        }
+        .boxed()
+    }))
+}
+
+/// Redeploys the registry with the given settings on top of the current ones,
+/// e.g. `{"record_ttl_sec": 3600, "replicate": false}`, management peer only.
+/// The settings apply until restart or config reload.
+fn make_set_registry_closure(reloader: ConfigReloader, scopes: PeerScopes) -> ServiceFunction {
+    ServiceFunction::Immut(Box::new(move |args, params| {
+        let reloader = reloader.clone();
+        let scopes = scopes.clone();
+        async move { wrap_unit(set_registry(reloader, scopes, args, params).await) }.boxed()
+    }))
+}
+
+async fn set_registry(
+    reloader: ConfigReloader,
+    scopes: PeerScopes,
+    args: Args,
+    params: ParticleParams,
+) -> Result<(), JError> {
+    check_management("config.set_registry", &scopes, &params)?;
+    let mut args = args.function_args.into_iter();
+    let settings: serde_json::Map<String, JValue> = Args::next("settings", &mut args)?;
+
+    let mut registry = json!(reloader.registry_config().await);
+    if let Some(registry) = registry.as_object_mut() {
+        registry.extend(settings);
+    }
+    let registry = serde_json::from_value(registry)
+        .map_err(|err| JError::new(format!("invalid registry settings: {err}")))?;
+    reloader
+        .set_registry_config(registry)
+        .await
+        .map_err(|err| JError::new(format!("{err:?}")))
+}
+
+pub fn make_system_builtin(
+    reloader: ConfigReloader,
+    scopes: PeerScopes,
+) -> (String, CustomService) {
+    (
+        "system".to_string(),
+        CustomService::new(
+            vec![("update", make_system_update_closure(reloader, scopes))],
+            None,
+        ),
+    )
+}
+
+/// Updates the enabled system services to the versions bundled with the node, management peer only.
+/// Returns the updated packages, failed ones are rolled back and come with an error.
+fn make_system_update_closure(reloader: ConfigReloader, scopes: PeerScopes) -> ServiceFunction {
+    ServiceFunction::Immut(Box::new(move |_args, params| {
+        let reloader = reloader.clone();
+        let scopes = scopes.clone();
+        async move {
+            let result: Result<JValue, JError> = try {
+                check_management("system.update", &scopes, &params)?;
+                let updates = reloader
+                    .update_system_services()
+                    .await
+                    .map_err(|err| JError::new(format!("{err:?}")))?;
+                json!(updates)
+            };
+            wrap(result)
+        }
+        .boxed()
+    }))
+}
+
+pub fn make_cores_builtin(
+    core_manager: Arc<CoreManager>,
+    scopes: PeerScopes,
+) -> (String, CustomService) {
+    (
+        "cores".to_string(),
+        CustomService::new(
+            vec![("map", make_cores_map_closure(core_manager, scopes))],
+            None,
+        ),
+    )
+}
+
+/// Physical cores with their hyperthreads, NUMA nodes and assigned compute units,
+/// management peer only
+fn make_cores_map_closure(core_manager: Arc<CoreManager>, scopes: PeerScopes) -> ServiceFunction {
+    ServiceFunction::Immut(Box::new(move |_args, params| {
+        let result: Result<JValue, JError> = try {
+            check_management("cores.map", &scopes, &params)?;
+            json!(core_manager.get_core_map())
+        };
+        async move { wrap(result) }.boxed()
+    }))
+}
+
+pub fn make_trust_graph_builtin(
+    trust_graph: TrustGraph,
+    scopes: PeerScopes,
+) -> (String, CustomService) {
+    let closure = |function: &'static str| {
+        let trust_graph = trust_graph.clone();
+        let scopes = scopes.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let result = call_trust_graph(function, &trust_graph, &scopes, args, params);
+            async move { wrap(result) }.boxed()
+        }))
+    };
+    (
+        "trust".to_string(),
+        CustomService::new(
+            vec![
+                ("issue", closure("issue")),
+                ("revoke", closure("revoke")),
+                ("weight", closure("weight")),
+            ],
+            None,
+        ),
+    )
+}
+
+/// Trust graph operations on behalf of the host key: `issue(peer_id, expires_at_sec)` returns
+/// the new weight of the peer, `revoke(peer_id)`, both management peer only.
+/// `weight(peer_id)` is available to everyone.
+fn call_trust_graph(
+    function: &str,
+    trust_graph: &TrustGraph,
+    scopes: &PeerScopes,
+    args: Args,
+    params: ParticleParams,
+) -> Result<JValue, JError> {
+    let mut args = args.function_args.into_iter();
+    let peer_id: String = Args::next("peer_id", &mut args)?;
+    let peer_id = PeerId::from_str(&peer_id)
+        .map_err(|err| JError::new(format!("invalid peer id {peer_id}: {err}")))?;
+    let to_error = |err: eyre::Report| JError::new(format!("trust.{function} failed: {err}"));
+
+    match function {
+        "issue" => {
+            check_management("trust.issue", scopes, &params)?;
+            let expires_at_sec: u64 = Args::next("expires_at_sec", &mut args)?;
+            let weight = trust_graph
+                .issue_trust(peer_id, expires_at_sec)
+                .map_err(to_error)?;
+            Ok(json!(weight))
+        }
+        "revoke" => {
+            check_management("trust.revoke", scopes, &params)?;
+            trust_graph.revoke(peer_id).map_err(to_error)?;
+            Ok(JValue::Null)
+        }
+        "weight" => Ok(json!(trust_graph.weight(peer_id).map_err(to_error)?)),
+        _ => Err(JError::new(format!("unknown function trust.{function}"))),
+    }
+}
+
+pub fn make_log_builtin(log_control: LogControl, scopes: PeerScopes) -> (String, CustomService) {
+    (
+        "log".to_string(),
+        CustomService::new(
+            vec![
+                (
+                    "set_filter",
+                    make_set_log_filter_closure(log_control.clone(), scopes.clone()),
+                ),
+                (
+                    "reset_filter",
+                    make_reset_log_filter_closure(log_control, scopes),
+                ),
+            ],
+            None,
+        ),
+    )
+}
+
+/// Applies log directives, e.g. `chain_listener=trace`, on top of the configured ones
+/// for the given number of seconds, management peer only
+fn make_set_log_filter_closure(log_control: LogControl, scopes: PeerScopes) -> ServiceFunction {
+    ServiceFunction::Immut(Box::new(move |args, params| {
+        let log_control = log_control.clone();
+        let scopes = scopes.clone();
+        async move { wrap_unit(set_log_filter(log_control, scopes, args, params)) }.boxed()
+    }))
+}
+
+fn set_log_filter(
+    log_control: LogControl,
+    scopes: PeerScopes,
+    args: Args,
+    params: ParticleParams,
+) -> Result<(), JError> {
+    check_management("log.set_filter", &scopes, &params)?;
+    let mut args = args.function_args.into_iter();
+    let directives: String = Args::next("directives", &mut args)?;
+    let duration_sec: u64 = Args::next("duration_sec", &mut args)?;
+    log_control
+        .set_temporary(directives, Duration::from_secs(duration_sec))
+        .map_err(|err| JError::new(format!("{err:?}")))
+}
+
+/// Reverts log directives set by `log.set_filter`, management peer only
+fn make_reset_log_filter_closure(log_control: LogControl, scopes: PeerScopes) -> ServiceFunction {
+    ServiceFunction::Immut(Box::new(move |_args, params| {
+        let result = check_management("log.reset_filter", &scopes, &params).and_then(|_| {
+            log_control
+                .reset()
+                .map_err(|err| JError::new(format!("{err:?}")))
+        });
+        async move { wrap_unit(result) }.boxed()
+    }))
+}
+
+pub fn make_network_builtin(
+    connectivity: Connectivity,
+    drain: DrainConfig,
+    scopes: PeerScopes,
+) -> (String, CustomService) {
+    (
+        "network".to_string(),
+        CustomService::new(
+            vec![
+                (
+                    "topology",
+                    make_topology_closure(connectivity.clone(), scopes.clone()),
+                ),
+                ("drain", make_drain_closure(connectivity, drain, scopes)),
+            ],
+            None,
+        ),
+    )
+}
+
+/// Closes network connections the same way as on shutdown, but the node keeps running.
+/// Management peer or management keys with the `drain_node` capability only.
+fn make_drain_closure(
+    connectivity: Connectivity,
+    drain: DrainConfig,
+    scopes: PeerScopes,
+) -> ServiceFunction {
+    ServiceFunction::Immut(Box::new(move |_args, params| {
+        let connection_pool = connectivity.connection_pool.clone();
+        let drain = drain.clone();
+        let result = check_capability("network.drain", Capability::DrainNode, &scopes, &params);
+        async move {
+            let result: Result<(), JError> = try {
+                result?;
+                tokio::time::timeout(drain.timeout, connection_pool.drain(drain.relays))
+                    .await
+                    .map_err(|_| {
+                        JError::new(format!("drain didn't finish in {:?}", drain.timeout))
+                    })?;
+            };
+            wrap_unit(result)
+        }
+        .boxed()
+    }))
+}
+
+/// Connected peers, Kademlia routing table and bootstrap status, management peer only
+fn make_topology_closure(connectivity: Connectivity, scopes: PeerScopes) -> ServiceFunction {
+    ServiceFunction::Immut(Box::new(move |_args, params| {
+        let connectivity = connectivity.clone();
+        let result = check_management("network.topology", &scopes, &params);
+        async move {
+            let result: Result<JValue, JError> = try {
+                result?;
+                let topology = connectivity
+                    .topology()
+                    .await
+                    .map_err(|err| JError::new(format!("failed to get topology: {err}")))?;
+                json!(topology)
+            };
+            wrap(result)
+        }
+        .boxed()
+    }))
+}
+
+pub fn make_audit_builtin(audit_log: AuditLog, scopes: PeerScopes) -> (String, CustomService) {
+    (
+        "audit".to_string(),
+        CustomService::new(
+            vec![
+                (
+                    "query",
+                    make_audit_query_closure(audit_log.clone(), scopes.clone()),
+                ),
+                ("verify", make_audit_verify_closure(audit_log, scopes)),
+            ],
+            None,
+        ),
+    )
+}
+
+/// Audit events matching the optional `{since, kinds, limit}` query, oldest first, management peer only
+fn make_audit_query_closure(audit_log: AuditLog, scopes: PeerScopes) -> ServiceFunction {
+    ServiceFunction::Immut(Box::new(move |args, params| {
+        let result = query_audit_log(&audit_log, &scopes, args, params);
+        async move { wrap(result) }.boxed()
+    }))
+}
+
+fn query_audit_log(
+    audit_log: &AuditLog,
+    scopes: &PeerScopes,
+    args: Args,
+    params: ParticleParams,
+) -> Result<JValue, JError> {
+    check_capability("audit.query", Capability::ReadAuditLog, scopes, &params)?;
+    let mut args = args.function_args.into_iter();
+    let query: Option<AuditQuery> = Args::next_opt("query", &mut args)?;
+    let events = audit_log
+        .query(&query.unwrap_or_default())
+        .map_err(|err| JError::new(format!("failed to read audit log: {err}")))?;
+    Ok(json!(events))
+}
+
+/// Checks the hash chain of the audit log, returns `{events, broken_at}`, management peer only
+fn make_audit_verify_closure(audit_log: AuditLog, scopes: PeerScopes) -> ServiceFunction {
+    ServiceFunction::Immut(Box::new(move |_, params| {
+        let result = check_capability("audit.verify", Capability::ReadAuditLog, &scopes, &params)
+            .and_then(|_| {
+                let status = audit_log
+                    .verify()
+                    .map_err(|err| JError::new(format!("failed to read audit log: {err}")))?;
+                Ok(json!(status))
+            });
+        async move { wrap(result) }.boxed()
+    }))
+}
+
+pub fn make_api_token_builtin(
+    tokens: ApiTokens,
+    audit_log: AuditLog,
+    scopes: PeerScopes,
+) -> (String, CustomService) {
+    let closure = |function: &'static str| {
+        let tokens = tokens.clone();
+        let audit_log = audit_log.clone();
+        let scopes = scopes.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let result = manage_api_tokens(function, &tokens, &audit_log, &scopes, args, params);
+            async move { wrap(result) }.boxed()
+        }))
+    };
+    (
+        "api_token".to_string(),
+        CustomService::new(
+            vec![
+                ("issue", closure("issue")),
+                ("rotate", closure("rotate")),
+                ("revoke", closure("revoke")),
+                ("list", closure("list")),
+            ],
+            None,
+        ),
+    )
+}
+
+/// Manages tokens of the http endpoint, management peer only:
+/// `issue(name, role)` and `rotate(id)` return the token secret, it isn't stored on the node
+fn manage_api_tokens(
+    function: &str,
+    tokens: &ApiTokens,
+    audit_log: &AuditLog,
+    scopes: &PeerScopes,
+    args: Args,
+    params: ParticleParams,
+) -> Result<JValue, JError> {
+    check_management(&format!("api_token.{function}"), scopes, &params)?;
+    let mut args = args.function_args.into_iter();
+    let to_jerror = |err: ApiTokenError| JError::new(format!("api_token.{function} failed: {err}"));
+    let result = match function {
+        "issue" => {
+            let name: String = Args::next("name", &mut args)?;
+            let role: Role = Args::next("role", &mut args)?;
+            let issued = tokens.issue(name, role).map_err(to_jerror)?;
+            audit_log.record(
+                AuditEvent::new(AuditEventKind::ApiTokenIssued, &issued.id)
+                    .with_actor(params.init_peer_id),
+            );
+            json!(issued)
+        }
+        "rotate" => {
+            let id: String = Args::next("id", &mut args)?;
+            let issued = tokens.rotate(&id).map_err(to_jerror)?;
+            audit_log.record(
+                AuditEvent::new(AuditEventKind::ApiTokenIssued, &issued.id)
+                    .with_actor(params.init_peer_id)
+                    .with_details("rotated"),
+            );
+            json!(issued)
+        }
+        "revoke" => {
+            let id: String = Args::next("id", &mut args)?;
+            let revoked = tokens.revoke(&id).map_err(to_jerror)?;
+            audit_log.record(
+                AuditEvent::new(AuditEventKind::ApiTokenRevoked, &revoked.id)
+                    .with_actor(params.init_peer_id),
+            );
+            json!(revoked)
+        }
+        _ => json!(tokens.list()),
+    };
+    Ok(result)
+}
+
+pub fn make_management_builtin(audit_log: AuditLog, scopes: PeerScopes) -> (String, CustomService) {
+    let closure = |function: &'static str| {
+        let audit_log = audit_log.clone();
+        let scopes = scopes.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let result = manage_management_keys(function, &audit_log, &scopes, args, params);
+            async move { wrap(result) }.boxed()
+        }))
+    };
+    (
+        "management".to_string(),
+        CustomService::new(
+            vec![
+                ("set", closure("set")),
+                ("revoke", closure("revoke")),
+                ("list", closure("list")),
+            ],
+            None,
+        ),
+    )
+}
+
+/// Manages peer ids with a subset of the management peer capabilities, management peer only:
+/// `set(peer_id, capabilities)` adds a key or replaces its capabilities, `revoke(peer_id)` removes it
+fn manage_management_keys(
+    function: &str,
+    audit_log: &AuditLog,
+    scopes: &PeerScopes,
+    args: Args,
+    params: ParticleParams,
+) -> Result<JValue, JError> {
+    check_management(&format!("management.{function}"), scopes, &params)?;
+    let keys = scopes.management_keys();
+    let mut args = args.function_args.into_iter();
+    let to_jerror =
+        |err: ManagementKeyError| JError::new(format!("management.{function} failed: {err}"));
+    let result = match function {
+        "set" => {
+            let peer_id: String = Args::next("peer_id", &mut args)?;
+            let peer_id = PeerId::from_str(&peer_id)?;
+            let capabilities: BTreeSet<Capability> = Args::next("capabilities", &mut args)?;
+            let details = capabilities
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(",");
+            keys.set(ManagementKey {
+                peer_id,
+                capabilities,
+            })
+            .map_err(to_jerror)?;
+            audit_log.record(
+                AuditEvent::new(AuditEventKind::ManagementKeySet, peer_id)
+                    .with_actor(params.init_peer_id)
+                    .with_details(details),
+            );
+            json!(true)
+        }
+        "revoke" => {
+            let peer_id: String = Args::next("peer_id", &mut args)?;
+            let peer_id = PeerId::from_str(&peer_id)?;
+            let revoked = keys.revoke(&peer_id).map_err(to_jerror)?;
+            if revoked {
+                audit_log.record(
+                    AuditEvent::new(AuditEventKind::ManagementKeyRevoked, peer_id)
+                        .with_actor(params.init_peer_id),
+                );
+            }
+            json!(revoked)
+        }
+        _ => json!(keys.list()),
+    };
+    Ok(result)
+}
+
+/// Checks the caller is the management peer or a management key with `capability`
+fn check_capability(
+    function: &str,
+    capability: Capability,
+    scopes: &PeerScopes,
+    params: &ParticleParams,
+) -> Result<(), JError> {
+    if !scopes.has_capability(params.init_peer_id, capability) {
+        return Err(JError::new(format!(
+            "{function} can be called only by management peer id or a management key with the {capability} capability; init_peer_id={}",
+            params.init_peer_id
+        )));
+    }
+    Ok(())
+}
+
+fn check_management(
+    function: &str,
+    scopes: &PeerScopes,
+    params: &ParticleParams,
+) -> Result<(), JError> {
+    if !scopes.is_management(params.init_peer_id) {
+        return Err(JError::new(format!(
+            "{function} can be called only by management peer id; init_peer_id={}",
+            params.init_peer_id
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,346 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use audit_log::{AuditEvent, AuditEventKind, AuditLog};
+use connection_pool::{ConnectionPoolApi, ConnectionPoolT};
+use eyre::{eyre, WrapErr};
+use particle_builtins::BuiltinPolicy;
+use particle_modules::{EffectorsMode, ModuleRepository};
+use serde_json::Value;
+use server_config::system_services_config::{RegistryConfig, ServiceKey};
+use server_config::{
+    load_config, redact_config, ReloadReport, SystemServicesConfig, UnresolvedConfig,
+};
+use system_services::{Deployer, PackageUpdate, SpellProgress, SystemServiceDistros};
+use tokio::sync::Mutex;
+
+use crate::log_control::{LogControl, LogFilterReload};
+use crate::log_file::RotationTrigger;
+
+struct ReloadState {
+    /// Config the node runs with, i.e. the startup config with reloaded fields applied
+    effective: Value,
+    /// Fields set by secret references on startup or reload, they are masked in the effective config
+    secret_refs: HashMap<String, String>,
+}
+
+/// Rereads the node config and applies fields that can be changed at runtime,
+/// see [server_config::RELOADABLE_FIELDS]
+#[derive(Clone)]
+pub struct ConfigReloader {
+    state: Arc<Mutex<Option<ReloadState>>>,
+    log_control: LogControl,
+    connection_pool: ConnectionPoolApi,
+    modules: ModuleRepository,
+    deployer: Deployer,
+    /// System services config the node runs with, including services enabled or disabled at runtime
+    system_services: Arc<Mutex<SystemServicesConfig>>,
+    /// `None` if the metrics registry wasn't created on startup
+    metrics_enabled: Option<Arc<AtomicBool>>,
+    is_dev_mode: bool,
+    audit_log: AuditLog,
+    builtin_policy: Arc<BuiltinPolicy>,
+}
+
+impl ConfigReloader {
+    pub fn new(
+        log_control: LogControl,
+        connection_pool: ConnectionPoolApi,
+        modules: ModuleRepository,
+        deployer: Deployer,
+        system_services: SystemServicesConfig,
+        metrics_enabled: Option<Arc<AtomicBool>>,
+        is_dev_mode: bool,
+    ) -> Self {
+        Self {
+            state: <_>::default(),
+            log_control,
+            connection_pool,
+            modules,
+            deployer,
+            system_services: Arc::new(Mutex::new(system_services)),
+            metrics_enabled,
+            is_dev_mode,
+            audit_log: <_>::default(),
+            builtin_policy: <_>::default(),
+        }
+    }
+
+    /// Records applied reloads into `audit_log`
+    pub fn with_audit_log(self, audit_log: AuditLog) -> Self {
+        Self { audit_log, ..self }
+    }
+
+    /// Replaces configured rules of `builtin_policy` on reload
+    pub fn with_builtin_policy(self, builtin_policy: Arc<BuiltinPolicy>) -> Self {
+        Self {
+            builtin_policy,
+            ..self
+        }
+    }
+
+    /// Allows reloading. `config` is the config the node has been started with.
+    pub async fn enable(
+        &self,
+        config: &UnresolvedConfig,
+        log_filter: LogFilterReload,
+        log_rotation: Option<RotationTrigger>,
+    ) -> eyre::Result<()> {
+        let effective = serde_json::to_value(config).wrap_err("failed to serialize config")?;
+        self.log_control
+            .enable(log_filter, config.log_filter.clone(), log_rotation);
+        *self.state.lock().await = Some(ReloadState {
+            effective,
+            secret_refs: config.secret_refs.clone(),
+        });
+        Ok(())
+    }
+
+    /// Config the node runs with, including defaults and reloaded fields, with secrets masked.
+    /// `None` if reloading isn't enabled, since the startup config is passed on enabling.
+    pub async fn effective_config(&self) -> Option<Value> {
+        let state = self.state.lock().await;
+        let state = state.as_ref()?;
+        let mut config = state.effective.clone();
+        redact_config(&mut config, &state.secret_refs);
+        Some(config)
+    }
+
+    /// Loads config from the same sources as on startup and applies reloadable fields.
+    /// Fails without applying anything if the new config is invalid or system services
+    /// fail to deploy. If a later field fails, the fields applied before it are kept
+    /// and reported by [ConfigReloader::effective_config].
+    pub async fn reload(&self) -> eyre::Result<ReloadReport> {
+        let mut state = self.state.lock().await;
+        let state = state
+            .as_mut()
+            .ok_or_else(|| eyre!("config reload isn't enabled on this node"))?;
+
+        let config = load_config(None)?;
+        let new = serde_json::to_value(&config).wrap_err("failed to serialize config")?;
+        let resolved = config.clone().resolve()?;
+
+        let mut report = ReloadReport::new(&state.effective, &new);
+        if self.metrics_enabled.is_none() {
+            report.require_restart("metrics_config.metrics_enabled");
+        }
+
+        // deployment is the only step that can fail on a valid config, so it goes first
+        if report.is_applied("system_services") {
+            // services removed from the config aren't undeployed
+            let mut system_services = self.system_services.lock().await;
+            let distros = SystemServiceDistros::default_from(resolved.system_services.clone())
+                .wrap_err("failed to get system service distros")?;
+            self.deployer
+                .clone()
+                .with_distros(distros)
+                .with_pinned_versions(pinned_versions(&resolved.system_services))
+                .deploy_system_services()
+                .await
+                .wrap_err("failed to deploy system services")?;
+            *system_services = resolved.system_services.clone();
+            report.update_field(&mut state.effective, &new, "system_services");
+        }
+        if report.is_applied("log_filter") {
+            self.log_control.set_configured(config.log_filter.clone())?;
+        }
+        if report.is_applied("builtin_policy") {
+            self.builtin_policy
+                .set_configured(resolved.builtin_policy.clone());
+        }
+        if report.is_applied("rate_limit") {
+            self.connection_pool
+                .set_rate_limit(resolved.rate_limit.clone())
+                .await;
+        }
+        // all effectors are allowed in dev mode, so there's nothing to change
+        if report.is_applied("effectors") && !self.is_dev_mode {
+            let effectors = resolved
+                .allowed_effectors
+                .iter()
+                .map(|(cid, binaries)| {
+                    let binaries = binaries
+                        .iter()
+                        .map(|(name, path)| (name.clone(), PathBuf::from(path)))
+                        .collect();
+                    (cid.clone(), binaries)
+                })
+                .collect();
+            self.modules
+                .set_effectors(EffectorsMode::RestrictedEffectors { effectors });
+        }
+        if let Some(metrics_enabled) = &self.metrics_enabled {
+            metrics_enabled.store(resolved.metrics_config.metrics_enabled, Ordering::Relaxed);
+        }
+
+        report.update(&mut state.effective, &new);
+        state.secret_refs.extend(config.secret_refs);
+        if !report.applied.is_empty() {
+            self.audit_log.record(
+                AuditEvent::new(AuditEventKind::ConfigReloaded, "config")
+                    .with_details(report.applied.join(",")),
+            );
+        }
+        Ok(report)
+    }
+
+    /// System services enabled on the node
+    pub async fn system_services(&self) -> Vec<ServiceKey> {
+        self.system_services.lock().await.enable.clone()
+    }
+
+    /// Deploys or removes services and spells of the system service `key` on the running node.
+    /// The config isn't changed, so the `enable` list applies again on restart.
+    /// Returns the system services enabled after the change.
+    pub async fn set_system_service(
+        &self,
+        key: ServiceKey,
+        enabled: bool,
+    ) -> eyre::Result<Vec<ServiceKey>> {
+        let mut system_services = self.system_services.lock().await;
+        let deployer = self.deployer_of(&key, system_services.clone())?;
+        if enabled {
+            deployer
+                .deploy_system_services()
+                .await
+                .wrap_err_with(|| format!("failed to deploy {key}"))?;
+            if !system_services.enable.contains(&key) {
+                system_services.enable.push(key);
+            }
+        } else {
+            deployer
+                .remove_system_services()
+                .await
+                .wrap_err_with(|| format!("failed to remove {key}"))?;
+            system_services.enable.retain(|k| *k != key);
+        }
+        Ok(system_services.enable.clone())
+    }
+
+    /// Updates the enabled system services to the versions bundled with the node, even if
+    /// they are pinned to other versions. Packages that fail to update are rolled back.
+    pub async fn update_system_services(&self) -> eyre::Result<Vec<PackageUpdate>> {
+        let system_services = self.system_services.lock().await;
+        let distros = SystemServiceDistros::default_from(system_services.clone())
+            .wrap_err("failed to get system service distros")?;
+        let updates = self
+            .deployer
+            .clone()
+            .with_distros(distros)
+            .update_system_services()
+            .await
+            .wrap_err("failed to update system services")?;
+        for update in &updates {
+            let from = update.from.as_deref().unwrap_or("unknown");
+            let details = match &update.error {
+                None => format!("{from} -> {}", update.to),
+                Some(err) => format!("{from} -> {} rolled back: {err}", update.to),
+            };
+            self.audit_log.record(
+                AuditEvent::new(AuditEventKind::SystemServiceUpdated, &update.package)
+                    .with_details(details),
+            );
+        }
+        Ok(updates)
+    }
+
+    /// Checks the enabled system services, see [Deployer::check_system_services]
+    pub async fn check_system_services(
+        &self,
+        progress: &mut SpellProgress,
+        stalled_spell_periods: u32,
+    ) -> eyre::Result<HashMap<String, String>> {
+        let system_services = self.system_services.lock().await;
+        let distros = SystemServiceDistros::default_from(system_services.clone())
+            .wrap_err("failed to get system service distros")?;
+        Ok(self
+            .deployer
+            .clone()
+            .with_distros(distros)
+            .check_system_services(progress, stalled_spell_periods))
+    }
+
+    /// Redeploys the system service `name` unless it has been disabled in the meantime
+    pub async fn redeploy_system_service(&self, name: &str) -> eyre::Result<()> {
+        let key =
+            ServiceKey::from_string(name).ok_or_else(|| eyre!("unknown system service {name}"))?;
+        let system_services = self.system_services.lock().await;
+        if !system_services.enable.contains(&key) {
+            return Ok(());
+        }
+        self.deployer_of(&key, system_services.clone())?
+            .deploy_system_services()
+            .await
+            .wrap_err_with(|| format!("failed to redeploy {key}"))
+    }
+
+    /// Registry settings the node runs with
+    pub async fn registry_config(&self) -> RegistryConfig {
+        self.system_services.lock().await.registry.clone()
+    }
+
+    /// Redeploys the registry with `registry` settings, until restart or config reload
+    pub async fn set_registry_config(&self, registry: RegistryConfig) -> eyre::Result<()> {
+        let mut system_services = self.system_services.lock().await;
+        if !system_services.enable.contains(&ServiceKey::Registry) {
+            return Err(eyre!("registry isn't enabled on this node"));
+        }
+        let config = SystemServicesConfig {
+            registry,
+            ..system_services.clone()
+        };
+        self.deployer_of(&ServiceKey::Registry, config.clone())?
+            .deploy_system_services()
+            .await
+            .wrap_err("failed to redeploy registry")?;
+        *system_services = config;
+        Ok(())
+    }
+
+    /// Deployer of the system service `key` configured by `config`
+    fn deployer_of(
+        &self,
+        key: &ServiceKey,
+        config: SystemServicesConfig,
+    ) -> eyre::Result<Deployer> {
+        let config = SystemServicesConfig {
+            enable: vec![key.clone()],
+            ..config
+        };
+        let pinned_versions = pinned_versions(&config);
+        let distros = SystemServiceDistros::default_from(config)
+            .wrap_err_with(|| format!("failed to get {key} distro"))?;
+        Ok(self
+            .deployer
+            .clone()
+            .with_distros(distros)
+            .with_pinned_versions(pinned_versions))
+    }
+}
+
+/// Pinned versions of the system services by their package names
+pub fn pinned_versions(config: &SystemServicesConfig) -> HashMap<String, String> {
+    config
+        .pinned_versions
+        .iter()
+        .map(|(key, version)| (key.to_string(), version.clone()))
+        .collect()
+}
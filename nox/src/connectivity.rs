@@ -14,20 +14,28 @@
  * limitations under the License.
  */
 
-use std::cmp::min;
-use std::collections::HashSet;
-use std::time::Duration;
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
 
+use crate::dns::DnsResolver;
 use crate::health::ConnectivityHealth;
-use connection_pool::{ConnectionPoolApi, ConnectionPoolT, LifecycleEvent};
+use connection_pool::{
+    BandwidthLimiter, ConnectedPeer, ConnectionPoolApi, ConnectionPoolT, DialBackoffConfig,
+    LifecycleEvent,
+};
 use fluence_libp2p::PeerId;
 use futures::{stream::iter, StreamExt};
 use humantime_serde::re::humantime::format_duration as pretty;
-use kademlia::{KademliaApi, KademliaApiT, KademliaError};
+use itertools::Itertools;
+use kademlia::{KBucket, KademliaApi, KademliaApiT, KademliaError};
 use libp2p::Multiaddr;
+use parking_lot::Mutex;
 use particle_protocol::{Contact, ExtendedParticle, SendStatus};
-use peer_metrics::{ConnectivityMetrics, Resolution};
-use tokio::time::sleep;
+use peer_metrics::{ConnectivityMetrics, ParticleStage, Resolution};
+use pubsub::PubSubApi;
+use serde::Serialize;
+use tokio::time::{sleep, MissedTickBehavior};
+use tokio_stream::wrappers::IntervalStream;
 use tracing::{instrument, Instrument, Span};
 
 use crate::tasks::Tasks;
@@ -46,6 +54,33 @@ pub struct Connectivity {
     pub bootstrap_frequency: usize,
     pub metrics: Option<ConnectivityMetrics>,
     pub health: Option<ConnectivityHealth>,
+    pub bandwidth: BandwidthLimiter,
+    pub pubsub: PubSubApi,
+    /// Retry policy for disconnected bootstrap nodes
+    pub dial_backoff: DialBackoffConfig,
+    pub dns: DnsResolver,
+}
+
+/// Node's current view of the network
+#[derive(Debug, Serialize)]
+pub struct Topology {
+    pub peer_id: String,
+    pub connected_peers: Vec<ConnectedPeer>,
+    pub routing_table: Vec<KBucket>,
+    pub bootstrap: BootstrapStatus,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BootstrapStatus {
+    pub nodes: Vec<BootstrapNodeStatus>,
+    /// `None` if health checks are disabled
+    pub kademlia_bootstrap_finished: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BootstrapNodeStatus {
+    pub address: Multiaddr,
+    pub connected: bool,
 }
 
 impl Connectivity {
@@ -62,6 +97,41 @@ impl Connectivity {
         Tasks::new("Connectivity", vec![run_bootstrap, reconnect_bootstraps])
     }
 
+    pub async fn topology(&self) -> Result<Topology, KademliaError> {
+        let connected_peers = self.connection_pool.connected_peers().await;
+        let routing_table = self.kademlia.routing_table().await?;
+
+        let nodes = match &self.health {
+            Some(health) => health.bootstrap_nodes.statuses(),
+            // without health checks, resolved addresses of DNS bootstraps can't be matched
+            None => self
+                .bootstrap_nodes
+                .iter()
+                .map(|addr| {
+                    let connected = connected_peers.iter().any(|p| p.addresses.contains(addr));
+                    (addr.clone(), connected)
+                })
+                .collect(),
+        };
+        let bootstrap = BootstrapStatus {
+            nodes: nodes
+                .into_iter()
+                .map(|(address, connected)| BootstrapNodeStatus { address, connected })
+                .collect(),
+            kademlia_bootstrap_finished: self
+                .health
+                .as_ref()
+                .map(|h| h.kademlia_bootstrap.is_finished()),
+        };
+
+        Ok(Topology {
+            peer_id: self.peer_id.to_base58(),
+            connected_peers,
+            routing_table,
+            bootstrap,
+        })
+    }
+
     #[instrument(level = tracing::Level::INFO, skip_all)]
     pub async fn resolve_contact(&self, target: PeerId, particle_id: &str) -> Option<Contact> {
         let metrics = self.metrics.as_ref();
@@ -134,7 +204,11 @@ impl Connectivity {
         );
         let metrics = self.metrics.as_ref();
         let id = particle.particle.id.clone();
+        let started = Instant::now();
         let sent = self.connection_pool.send(contact.clone(), particle).await;
+        if let Some(m) = metrics {
+            m.particle_stage_time(&id, ParticleStage::Send, started.elapsed());
+        }
         match &sent {
             SendStatus::Ok => {
                 if let Some(m) = metrics {
@@ -224,12 +298,21 @@ impl Connectivity {
     }
 
     /// Dial bootstraps, and then re-dial on each disconnection
+    /// and whenever resolved addresses of DNS bootstraps change
     pub async fn reconnect_bootstraps(self) {
         let pool = self.connection_pool;
         let kademlia = self.kademlia;
         let bootstrap_nodes = self.bootstrap_nodes;
         let metrics = self.metrics.as_ref();
         let health = self.health.as_ref();
+        let dns = &self.dns;
+
+        // resolved address => bootstrap address it was resolved from
+        let resolved_bootstraps = Mutex::new(HashMap::<Multiaddr, Multiaddr>::new());
+        let resolved_bootstraps = &resolved_bootstraps;
+        // bootstraps that are being dialed, to avoid dialing the same bootstrap concurrently
+        let reconnecting = Mutex::new(HashSet::<Multiaddr>::new());
+        let reconnecting = &reconnecting;
 
         let disconnections = {
             use tokio_stream::StreamExt as stream;
@@ -239,8 +322,14 @@ impl Connectivity {
             stream::filter_map(events, move |e| {
                 if let LifecycleEvent::Disconnected(Contact { addresses, .. }) = e {
                     let addresses = addresses.into_iter();
-                    let addresses = addresses.filter(|addr| bootstrap_nodes.contains(addr));
-                    let addresses = addresses.collect::<Vec<_>>();
+                    let addresses = addresses.filter_map(|addr| {
+                        if bootstrap_nodes.contains(&addr) {
+                            Some(addr)
+                        } else {
+                            resolved_bootstraps.lock().get(&addr).cloned()
+                        }
+                    });
+                    let addresses = addresses.unique().collect::<Vec<_>>();
                     if !addresses.is_empty() {
                         metrics.map(|m| m.bootstrap_disconnected.inc());
                         if let Some(h) = health {
@@ -255,34 +344,110 @@ impl Connectivity {
         }
         .flatten();
 
-        // TODO: take from config
-        let max = Duration::from_secs(60);
-        // TODO: exponential backoff + random?
-        let delta = Duration::from_secs(5);
+        let dns_bootstraps: Vec<_> = bootstrap_nodes
+            .iter()
+            .filter(|addr| DnsResolver::is_dns(addr))
+            .cloned()
+            .collect();
+        // bootstrap address => addresses it was resolved to last time
+        let last_resolved = Mutex::new(HashMap::<Multiaddr, Vec<Multiaddr>>::new());
+        let last_resolved = &last_resolved;
+        let address_changes = if dns_bootstraps.is_empty() {
+            futures::stream::empty().boxed()
+        } else {
+            let mut interval = tokio::time::interval(dns.reresolve_interval());
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            IntervalStream::new(interval)
+                .then(move |_| {
+                    let dns_bootstraps = dns_bootstraps.clone();
+                    async move {
+                        let mut changed = vec![];
+                        for addr in dns_bootstraps {
+                            let Ok(mut resolved) = dns.resolve(&addr).await else {
+                                continue;
+                            };
+                            resolved.sort();
+                            let previous =
+                                last_resolved.lock().insert(addr.clone(), resolved.clone());
+                            if previous.is_some_and(|previous| previous != resolved) {
+                                log::info!(
+                                    "Addresses of bootstrap {} changed to {:?}",
+                                    addr,
+                                    resolved
+                                );
+                                changed.push(addr);
+                            }
+                        }
+                        iter(changed)
+                    }
+                })
+                .flatten()
+                .boxed()
+        };
+
+        let backoff = &self.dial_backoff;
 
         let reconnect = move |kademlia: KademliaApi,
                               pool: ConnectionPoolApi,
                               addr: Multiaddr,
                               parent_span: Span| {
             (async move {
-                let mut delay = Duration::from_secs(0);
+                if !reconnecting.lock().insert(addr.clone()) {
+                    return;
+                }
+
+                let mut attempts = 0;
                 loop {
                     tracing::info!("Will reconnect bootstrap {}", addr);
-                    if let Some(contact) = pool.dial(addr.clone()).await {
+                    let contact = match dns.resolve(&addr).await {
+                        Ok(resolved) => {
+                            let mut contact = None;
+                            for resolved_addr in resolved {
+                                if resolved_addr != addr {
+                                    resolved_bootstraps
+                                        .lock()
+                                        .insert(resolved_addr.clone(), addr.clone());
+                                }
+                                contact = pool.dial(resolved_addr).await;
+                                if contact.is_some() {
+                                    break;
+                                }
+                            }
+                            contact
+                        }
+                        Err(err) => {
+                            log::warn!("can't resolve bootstrap {}: {}", addr, err);
+                            None
+                        }
+                    };
+
+                    if let Some(contact) = contact {
                         tracing::info!("Connected bootstrap {}", contact);
                         let ok = kademlia.add_contact(contact);
                         debug_assert!(ok, "kademlia.add_contact");
                         metrics.map(|m| m.bootstrap_connected.inc());
                         if let Some(h) = health {
-                            h.bootstrap_nodes.on_bootstrap_connected(addr)
+                            h.bootstrap_nodes.on_bootstrap_connected(addr.clone())
                         }
                         break;
                     }
 
-                    delay = min(delay + delta, max);
+                    attempts += 1;
+                    if backoff.max_attempts.is_some_and(|max| attempts >= max) {
+                        log::warn!(
+                            "can't connect bootstrap {}, giving up after {} attempts",
+                            addr,
+                            attempts
+                        );
+                        break;
+                    }
+                    // pool backs off the address with the same policy, so wait at least that long
+                    let delay = backoff.delay_with_jitter(attempts);
                     log::warn!("can't connect bootstrap {} (pause {})", addr, pretty(delay));
                     sleep(delay).await;
                 }
+
+                reconnecting.lock().remove(&addr);
             })
             .instrument(parent_span)
         };
@@ -290,7 +455,7 @@ impl Connectivity {
         let parent_span = tracing::Span::current();
         let bootstraps = iter(bootstrap_nodes.clone().into_iter().collect::<Vec<_>>());
         bootstraps
-            .chain(disconnections)
+            .chain(futures::stream::select(disconnections, address_changes))
             .for_each_concurrent(None, |addr| {
                 reconnect(kademlia.clone(), pool.clone(), addr, parent_span.clone())
             })
@@ -309,3 +474,9 @@ impl AsRef<ConnectionPoolApi> for Connectivity {
         &self.connection_pool
     }
 }
+
+impl AsRef<PubSubApi> for Connectivity {
+    fn as_ref(&self) -> &PubSubApi {
+        &self.pubsub
+    }
+}
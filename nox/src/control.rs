@@ -0,0 +1,527 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::fs::{self, Permissions};
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use audit_log::{AuditEvent, AuditEventKind, AuditLog};
+use connection_pool::{
+    ConnectedPeer, ConnectionPoolApi, ConnectionPoolT, DrainConfig, MaintenanceStatus,
+};
+use eyre::eyre;
+use libp2p::PeerId;
+use particle_services::{ParticleAppServices, PeerScope, ServiceInfo};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use server_config::system_services_config::ServiceKey;
+use server_config::{HostKeyHandover, ReloadReport};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::task::JoinHandle;
+use workers::Workers;
+
+use crate::config_reload::ConfigReloader;
+use crate::host_key::HostKey;
+use crate::log_control::LogControl;
+use crate::Versions;
+
+/// Command sent to the control socket as a single line of JSON, e.g. `{"command":"status"}`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum ControlCommand {
+    Status,
+    ListServices,
+    ListSpells,
+    ListWorkers,
+    /// Closes network connections the same way as on shutdown, but the node keeps running
+    Drain,
+    /// Stops accepting new deals and clients, existing workers keep running
+    EnterMaintenance,
+    ExitMaintenance,
+    ReloadConfig,
+    RotateLogs,
+    /// Generates a new host key, the node switches to it on restart
+    RotateHostKey {
+        /// How long the current peer id is served after the restart, 24h if not set
+        #[serde(default, with = "humantime_serde")]
+        grace_period: Option<Duration>,
+    },
+    ListSystemServices,
+    /// Deploys services and spells of a system service, e.g. `aqua-ipfs`, until restart
+    EnableSystemService {
+        service: ServiceKey,
+    },
+    /// Removes services and spells of a system service until restart
+    DisableSystemService {
+        service: ServiceKey,
+    },
+}
+
+/// Reply to a [ControlCommand], written as a single line of JSON
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlResponse {
+    Result(Value),
+    Error(String),
+}
+
+impl From<eyre::Result<Value>> for ControlResponse {
+    fn from(result: eyre::Result<Value>) -> Self {
+        match result {
+            Ok(value) => ControlResponse::Result(value),
+            Err(err) => ControlResponse::Error(format!("{err:#}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeStatus {
+    pub peer_id: String,
+    pub node_version: String,
+    pub air_version: String,
+    pub spell_version: String,
+    pub uptime_secs: u64,
+    pub connections: usize,
+    pub services: usize,
+    pub spells: usize,
+    pub workers: usize,
+    pub maintenance: MaintenanceStatus,
+    /// Host key rotation pending restart or in the grace period
+    pub host_key_handover: Option<HostKeyHandover>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ServiceEntry {
+    pub id: String,
+    pub blueprint_id: String,
+    pub owner_id: String,
+    pub aliases: Vec<String>,
+    pub worker_id: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkerEntry {
+    pub worker_id: String,
+    pub deal_id: Option<String>,
+    pub active: bool,
+}
+
+/// Node management operations available to operators, i.e. via the control socket.
+/// Access to the socket is limited by its file permissions, so no credentials are needed.
+#[derive(Clone)]
+pub struct Control {
+    peer_id: PeerId,
+    versions: Versions,
+    started_at: Instant,
+    connection_pool: ConnectionPoolApi,
+    services: ParticleAppServices,
+    workers: Arc<Workers>,
+    config_reloader: ConfigReloader,
+    log_control: LogControl,
+    drain: DrainConfig,
+    audit_log: AuditLog,
+    host_key: HostKey,
+}
+
+impl Control {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        peer_id: PeerId,
+        versions: Versions,
+        connection_pool: ConnectionPoolApi,
+        services: ParticleAppServices,
+        workers: Arc<Workers>,
+        config_reloader: ConfigReloader,
+        log_control: LogControl,
+        drain: DrainConfig,
+        audit_log: AuditLog,
+        host_key: HostKey,
+    ) -> Self {
+        Self {
+            peer_id,
+            versions,
+            started_at: Instant::now(),
+            connection_pool,
+            services,
+            workers,
+            config_reloader,
+            log_control,
+            drain,
+            audit_log,
+            host_key,
+        }
+    }
+
+    /// Listens on the control socket at `socket_path`. Returns `None` if the socket can't be created,
+    /// the node runs without the control socket then.
+    pub fn listen(self, socket_path: PathBuf) -> Option<JoinHandle<()>> {
+        let listener = match bind(&socket_path) {
+            Ok(listener) => listener,
+            Err(err) => {
+                log::warn!(
+                    "Failed to create control socket at {}: {err}",
+                    socket_path.display()
+                );
+                return None;
+            }
+        };
+        log::info!("Control socket listening at {}", socket_path.display());
+
+        let task = async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        let this = self.clone();
+                        tokio::spawn(async move {
+                            if let Err(err) = this.serve(stream).await {
+                                log::debug!("Control connection failed: {err}");
+                            }
+                        });
+                    }
+                    Err(err) => log::warn!("Failed to accept control connection: {err}"),
+                }
+            }
+        };
+        let handle = tokio::task::Builder::new()
+            .name("control-socket")
+            .spawn(task)
+            .expect("Could not spawn task");
+        Some(handle)
+    }
+
+    /// Executes commands until the client closes the connection
+    async fn serve(&self, stream: UnixStream) -> io::Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let response = match serde_json::from_str(&line) {
+                Ok(command) => self.execute(command).await.into(),
+                Err(err) => ControlResponse::Error(format!("invalid command: {err}")),
+            };
+            let mut response = serde_json::to_vec(&response)?;
+            response.push(b'\n');
+            writer.write_all(&response).await?;
+        }
+        Ok(())
+    }
+
+    async fn execute(&self, command: ControlCommand) -> eyre::Result<Value> {
+        log::info!("Control command: {command:?}");
+        let result = match command {
+            ControlCommand::Status => serde_json::to_value(self.status().await)?,
+            ControlCommand::ListServices => serde_json::to_value(self.list_services(false))?,
+            ControlCommand::ListSpells => serde_json::to_value(self.list_services(true))?,
+            ControlCommand::ListWorkers => serde_json::to_value(self.list_workers())?,
+            ControlCommand::Drain => {
+                self.drain().await?;
+                Value::Null
+            }
+            ControlCommand::EnterMaintenance => {
+                serde_json::to_value(self.set_maintenance(true).await)?
+            }
+            ControlCommand::ExitMaintenance => {
+                serde_json::to_value(self.set_maintenance(false).await)?
+            }
+            ControlCommand::ReloadConfig => serde_json::to_value(self.reload_config().await?)?,
+            ControlCommand::RotateLogs => {
+                self.rotate_logs()?;
+                Value::Null
+            }
+            ControlCommand::RotateHostKey { grace_period } => {
+                serde_json::to_value(self.rotate_host_key(grace_period)?)?
+            }
+            ControlCommand::ListSystemServices => {
+                serde_json::to_value(self.system_services().await)?
+            }
+            ControlCommand::EnableSystemService { service } => {
+                serde_json::to_value(self.set_system_service(service, true).await?)?
+            }
+            ControlCommand::DisableSystemService { service } => {
+                serde_json::to_value(self.set_system_service(service, false).await?)?
+            }
+        };
+        Ok(result)
+    }
+
+    pub async fn status(&self) -> NodeStatus {
+        let (spells, services): (Vec<_>, Vec<_>) = self
+            .services
+            .list_services_all()
+            .into_iter()
+            .partition(|s| s.service_type.is_spell());
+        NodeStatus {
+            peer_id: self.peer_id.to_string(),
+            node_version: self.versions.node_version.clone(),
+            air_version: self.versions.avm_version.clone(),
+            spell_version: self.versions.spell_version.clone(),
+            uptime_secs: self.started_at.elapsed().as_secs(),
+            connections: self.connection_pool.count_connections().await,
+            services: services.len(),
+            spells: spells.len(),
+            workers: self.workers.list_workers().len(),
+            maintenance: self.connection_pool.maintenance_status().await,
+            host_key_handover: self.host_key.handover().unwrap_or_else(|err| {
+                log::warn!("Failed to read host key handover: {err:#}");
+                None
+            }),
+        }
+    }
+
+    /// Services or spells deployed on the host and workers
+    pub fn list_services(&self, spells: bool) -> Vec<ServiceEntry> {
+        self.services
+            .list_services_all()
+            .iter()
+            .filter(|s| s.service_type.is_spell() == spells)
+            .map(|s| self.service_entry(s))
+            .collect()
+    }
+
+    fn service_entry(&self, info: &ServiceInfo) -> ServiceEntry {
+        let worker_id = match info.peer_scope {
+            PeerScope::WorkerId(worker_id) => worker_id.to_string(),
+            PeerScope::Host => self.peer_id.to_string(),
+        };
+        ServiceEntry {
+            id: info.id.clone(),
+            blueprint_id: info.blueprint_id.clone(),
+            owner_id: info.owner_id.to_string(),
+            aliases: info.aliases.clone(),
+            worker_id,
+        }
+    }
+
+    pub fn list_workers(&self) -> Vec<WorkerEntry> {
+        self.workers
+            .list_workers()
+            .into_iter()
+            .map(|worker_id| WorkerEntry {
+                worker_id: worker_id.to_string(),
+                deal_id: self
+                    .workers
+                    .get_deal_id(worker_id)
+                    .ok()
+                    .map(|d| d.to_string()),
+                active: self.workers.is_worker_active(worker_id),
+            })
+            .collect()
+    }
+
+    pub async fn list_peers(&self) -> Vec<ConnectedPeer> {
+        self.connection_pool.connected_peers().await
+    }
+
+    /// Closes network connections the same way as on shutdown, but the node keeps running
+    pub async fn drain(&self) -> eyre::Result<()> {
+        let drained = self.connection_pool.drain(self.drain.relays);
+        tokio::time::timeout(self.drain.timeout, drained)
+            .await
+            .map_err(|_| eyre!("drain didn't finish in {:?}", self.drain.timeout))
+    }
+
+    /// In maintenance the node refuses new deals, asks connected clients to move to other relays
+    /// and disconnects new ones, while existing workers keep running. Returns drain progress.
+    pub async fn set_maintenance(&self, enabled: bool) -> MaintenanceStatus {
+        let changed = self.workers.is_in_maintenance() != enabled;
+        self.workers.set_maintenance(enabled);
+        let status = self
+            .connection_pool
+            .set_maintenance(enabled, self.drain.relays)
+            .await;
+        if changed {
+            let kind = if enabled {
+                AuditEventKind::MaintenanceStarted
+            } else {
+                AuditEventKind::MaintenanceFinished
+            };
+            self.audit_log.record(AuditEvent::new(kind, self.peer_id));
+        }
+        status
+    }
+
+    /// Config the node runs with, with secrets masked
+    pub async fn effective_config(&self) -> Option<Value> {
+        self.config_reloader.effective_config().await
+    }
+
+    pub async fn reload_config(&self) -> eyre::Result<ReloadReport> {
+        self.config_reloader.reload().await
+    }
+
+    /// System services enabled on the node, including changes made at runtime
+    pub async fn system_services(&self) -> Vec<ServiceKey> {
+        self.config_reloader.system_services().await
+    }
+
+    /// Deploys or removes the system `service`, returns enabled system services
+    pub async fn set_system_service(
+        &self,
+        service: ServiceKey,
+        enabled: bool,
+    ) -> eyre::Result<Vec<ServiceKey>> {
+        let enabled_services = self
+            .config_reloader
+            .set_system_service(service.clone(), enabled)
+            .await?;
+        let kind = if enabled {
+            AuditEventKind::SystemServiceEnabled
+        } else {
+            AuditEventKind::SystemServiceDisabled
+        };
+        self.audit_log.record(AuditEvent::new(kind, service));
+        Ok(enabled_services)
+    }
+
+    pub fn rotate_logs(&self) -> eyre::Result<()> {
+        self.log_control.rotate_file()
+    }
+
+    /// Generates a new host key, the node switches to it on restart and serves the current peer id
+    /// until `grace_period` ends
+    pub fn rotate_host_key(&self, grace_period: Option<Duration>) -> eyre::Result<HostKeyHandover> {
+        self.host_key.rotate(grace_period)
+    }
+}
+
+/// Binds the socket accessible only by the node's user, replacing a socket left by a previous run.
+/// Anything else at `path` is kept and binding fails.
+///
+/// The socket is bound in a directory only the node's user can enter and moved to `path`
+/// once its permissions are set, so others can't connect in between.
+fn bind(path: &Path) -> io::Result<UnixListener> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if !metadata.file_type().is_socket() => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and isn't a socket", path.display()),
+            ));
+        }
+        Ok(_) => {}
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+
+    let file_name = path.file_name().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "socket path has no file name")
+    })?;
+    let private_dir = path.with_file_name(format!(".{}.bind", file_name.to_string_lossy()));
+    // left if the node was killed while binding
+    match fs::remove_dir_all(&private_dir) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+    fs::DirBuilder::new().mode(0o700).create(&private_dir)?;
+
+    let bound = private_dir.join(file_name);
+    let listener = UnixListener::bind(&bound).and_then(|listener| {
+        fs::set_permissions(&bound, Permissions::from_mode(0o600))?;
+        // replaces a stale socket atomically
+        fs::rename(&bound, path)?;
+        Ok(listener)
+    });
+    let removed = fs::remove_dir_all(&private_dir);
+    let listener = listener?;
+    removed?;
+    Ok(listener)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn protocol() {
+        let command: ControlCommand = serde_json::from_str(r#"{"command":"list_spells"}"#).unwrap();
+        assert_eq!(command, ControlCommand::ListSpells);
+        let command: ControlCommand =
+            serde_json::from_str(r#"{"command":"enter_maintenance"}"#).unwrap();
+        assert_eq!(command, ControlCommand::EnterMaintenance);
+        let command: ControlCommand =
+            serde_json::from_str(r#"{"command":"rotate_host_key","grace_period":"12h"}"#).unwrap();
+        assert_eq!(
+            command,
+            ControlCommand::RotateHostKey {
+                grace_period: Some(Duration::from_secs(12 * 60 * 60))
+            }
+        );
+        let command: ControlCommand =
+            serde_json::from_str(r#"{"command":"rotate_host_key"}"#).unwrap();
+        assert_eq!(
+            command,
+            ControlCommand::RotateHostKey { grace_period: None }
+        );
+        let command: ControlCommand =
+            serde_json::from_str(r#"{"command":"disable_system_service","service":"aqua-ipfs"}"#)
+                .unwrap();
+        assert_eq!(
+            command,
+            ControlCommand::DisableSystemService {
+                service: ServiceKey::AquaIpfs
+            }
+        );
+        assert!(serde_json::from_str::<ControlCommand>(
+            r#"{"command":"enable_system_service","service":"connector"}"#
+        )
+        .is_err());
+        assert!(serde_json::from_str::<ControlCommand>(r#"{"command":"shutdown"}"#).is_err());
+
+        let response = ControlResponse::from(Ok(json!({"workers": 1})));
+        assert_eq!(
+            serde_json::to_string(&response).unwrap(),
+            r#"{"result":{"workers":1}}"#
+        );
+        let response = ControlResponse::from(Err(eyre!("not enabled")));
+        assert_eq!(
+            serde_json::to_string(&response).unwrap(),
+            r#"{"error":"not enabled"}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn bind_replaces_stale_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nox.sock");
+
+        let stale = bind(&path).unwrap();
+        drop(stale);
+        let _listener = bind(&path).unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        UnixStream::connect(&path).await.unwrap();
+        assert!(!dir.path().join(".nox.sock.bind").exists());
+    }
+
+    #[tokio::test]
+    async fn bind_keeps_other_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nox.sock");
+        fs::write(&path, "data").unwrap();
+
+        assert!(bind(&path).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "data");
+    }
+}
@@ -14,6 +14,8 @@
  * limitations under the License.
  */
 
+use std::time::Duration;
+
 use futures::{FutureExt, StreamExt};
 use prometheus_client::registry::Registry;
 use tokio::sync::mpsc;
@@ -22,10 +24,12 @@ use tracing::{instrument, Instrument};
 
 use aquamarine::{AquamarineApi, AquamarineApiError, RemoteRoutingEffects};
 use fluence_libp2p::PeerId;
+use now_millis::now_ms;
 use particle_protocol::{ExtendedParticle, Particle};
 use peer_metrics::DispatcherMetrics;
 
 use crate::effectors::Effectors;
+use crate::seen_particles::SeenParticles;
 use crate::tasks::Tasks;
 
 type Effects = Result<RemoteRoutingEffects, AquamarineApiError>;
@@ -39,6 +43,10 @@ pub struct Dispatcher {
     aquamarine: AquamarineApi,
     effectors: Effectors,
     metrics: Option<DispatcherMetrics>,
+    /// If set, particles received more than once are dropped
+    seen_particles: Option<SeenParticles>,
+    /// How often `seen_particles` are persisted
+    persist_interval: Duration,
 }
 
 impl Dispatcher {
@@ -55,8 +63,26 @@ impl Dispatcher {
             aquamarine,
             particle_parallelism,
             metrics: registry.map(|r| DispatcherMetrics::new(r, particle_parallelism)),
+            seen_particles: None,
+            persist_interval: Duration::from_secs(10),
         }
     }
+
+    pub fn with_seen_particles(
+        self,
+        seen_particles: Option<SeenParticles>,
+        persist_interval: Duration,
+    ) -> Self {
+        Self {
+            seen_particles,
+            persist_interval,
+            ..self
+        }
+    }
+
+    pub fn seen_particles(&self) -> Option<SeenParticles> {
+        self.seen_particles.clone()
+    }
 }
 
 impl Dispatcher {
@@ -76,12 +102,52 @@ impl Dispatcher {
                     .in_current_span(),
             )
             .expect("Could not spawn task");
+        let mut tasks = vec![particles];
+        if let Some(seen_particles) = self.seen_particles.clone() {
+            let persist = tokio::task::Builder::new()
+                .name("seen particles")
+                .spawn(
+                    Self::persist_seen_particles(
+                        seen_particles,
+                        self.persist_interval,
+                        self.metrics.clone(),
+                    )
+                    .in_current_span(),
+                )
+                .expect("Could not spawn task");
+            tasks.push(persist);
+        }
         let effects = tokio::task::Builder::new()
             .name("effects")
             .spawn(self.process_effects(effects_stream).in_current_span())
             .expect("Could not spawn task");
+        tasks.push(effects);
 
-        Tasks::new("Dispatcher", vec![particles, effects])
+        Tasks::new("Dispatcher", tasks)
+    }
+
+    async fn persist_seen_particles(
+        seen_particles: SeenParticles,
+        interval: Duration,
+        metrics: Option<DispatcherMetrics>,
+    ) {
+        let mut interval = tokio::time::interval(interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            if let Some(m) = &metrics {
+                let stats = seen_particles.take_stats();
+                m.seen_particles(stats.entries, stats.evicted);
+            }
+            let seen_particles = seen_particles.clone();
+            let result =
+                tokio::task::spawn_blocking(move || seen_particles.persist(now_ms() as u64)).await;
+            match result {
+                Ok(Err(err)) => log::warn!("Failed to persist seen particles: {err}"),
+                Err(err) => log::warn!("Failed to persist seen particles: {err}"),
+                Ok(Ok(())) => {}
+            }
+        }
     }
 
     pub async fn process_particles<Src>(self, particle_stream: Src)
@@ -91,6 +157,7 @@ impl Dispatcher {
         let parallelism = self.particle_parallelism;
         let aquamarine = self.aquamarine;
         let metrics = self.metrics;
+        let seen_particles = self.seen_particles;
         particle_stream
             .for_each_concurrent(parallelism, move |ext_particle| {
                 let current_span = tracing::info_span!(parent: ext_particle.span.as_ref(), "Dispatcher::process_particles::for_each");
@@ -109,6 +176,17 @@ impl Dispatcher {
                     return async {}.boxed();
                 }
 
+                if let Some(seen_particles) = &seen_particles {
+                    if !seen_particles.insert(particle, now_ms() as u64) {
+                        let particle_id = &particle.id.as_str();
+                        if let Some(m) = metrics {
+                            m.particle_duplicate(particle_id);
+                        }
+                        tracing::info!(target: "duplicate", particle_id = particle_id, "Particle has been received already");
+                        return async {}.boxed();
+                    }
+                }
+
                 async move {
                     aquamarine
                         .execute(ext_particle, None)
@@ -0,0 +1,213 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use hickory_resolver::config::{ResolverConfig, ResolverOpts};
+use hickory_resolver::error::ResolveError;
+use hickory_resolver::TokioAsyncResolver;
+use libp2p::multiaddr::Protocol;
+use libp2p::Multiaddr;
+use parking_lot::Mutex;
+use server_config::DnsConfig;
+
+/// Max depth of `/dnsaddr` records pointing to other `/dnsaddr` records
+const MAX_DNSADDR_DEPTH: usize = 4;
+
+#[derive(Debug, Clone)]
+struct Resolved {
+    addresses: Vec<Multiaddr>,
+    valid_until: Instant,
+}
+
+/// Resolves DNS multiaddrs and caches results for the TTL of DNS records, so dialing
+/// the same bootstrap node doesn't query DNS every time, yet picks up changed records
+#[derive(Clone)]
+pub struct DnsResolver {
+    resolver: TokioAsyncResolver,
+    config: DnsConfig,
+    cache: Arc<Mutex<HashMap<Multiaddr, Resolved>>>,
+}
+
+impl DnsResolver {
+    pub fn new(config: DnsConfig) -> Self {
+        let resolver = TokioAsyncResolver::tokio_from_system_conf().unwrap_or_else(|err| {
+            log::warn!("Failed to read system DNS config, using default resolvers: {err}");
+            TokioAsyncResolver::tokio(ResolverConfig::default(), ResolverOpts::default())
+        });
+
+        Self {
+            resolver,
+            config,
+            cache: <_>::default(),
+        }
+    }
+
+    pub fn is_dns(addr: &Multiaddr) -> bool {
+        matches!(
+            addr.iter().next(),
+            Some(Protocol::Dns(_) | Protocol::Dns4(_) | Protocol::Dns6(_) | Protocol::Dnsaddr(_))
+        )
+    }
+
+    /// Resolves `addr` to ip multiaddrs, or returns it as is if it isn't a DNS multiaddr
+    pub async fn resolve(&self, addr: &Multiaddr) -> Result<Vec<Multiaddr>, ResolveError> {
+        if !Self::is_dns(addr) {
+            return Ok(vec![addr.clone()]);
+        }
+
+        let now = Instant::now();
+        if let Some(resolved) = self.cache.lock().get(addr) {
+            if resolved.valid_until > now {
+                return Ok(resolved.addresses.clone());
+            }
+        }
+
+        let (addresses, valid_until) = self.lookup(addr.clone(), 0).await?;
+        let valid_until = valid_until.clamp(now + self.config.min_ttl, now + self.config.max_ttl);
+        log::debug!(
+            "Resolved {addr} to {addresses:?} for {}s",
+            valid_until.duration_since(now).as_secs()
+        );
+        self.cache.lock().insert(
+            addr.clone(),
+            Resolved {
+                addresses: addresses.clone(),
+                valid_until,
+            },
+        );
+
+        Ok(addresses)
+    }
+
+    fn lookup(
+        &self,
+        addr: Multiaddr,
+        depth: usize,
+    ) -> BoxFuture<'_, Result<(Vec<Multiaddr>, Instant), ResolveError>> {
+        async move {
+            let mut protocols = addr.iter();
+            let first = protocols.next();
+            let rest: Multiaddr = protocols.collect();
+            let with_ip = |ip: IpAddr| {
+                let mut maddr = Multiaddr::from(ip);
+                rest.iter().for_each(|p| maddr.push(p));
+                maddr
+            };
+
+            match first {
+                Some(Protocol::Dns(name)) => {
+                    let lookup = self.resolver.lookup_ip(name.as_ref()).await?;
+                    let addresses = lookup.iter().map(with_ip).collect();
+                    Ok((addresses, lookup.valid_until()))
+                }
+                Some(Protocol::Dns4(name)) => {
+                    let lookup = self.resolver.ipv4_lookup(name.as_ref()).await?;
+                    let addresses = lookup.iter().map(|a| with_ip(a.0.into())).collect();
+                    Ok((addresses, lookup.valid_until()))
+                }
+                Some(Protocol::Dns6(name)) => {
+                    let lookup = self.resolver.ipv6_lookup(name.as_ref()).await?;
+                    let addresses = lookup.iter().map(|a| with_ip(a.0.into())).collect();
+                    Ok((addresses, lookup.valid_until()))
+                }
+                Some(Protocol::Dnsaddr(name)) => self.lookup_dnsaddr(&name, &rest, depth).await,
+                _ => Ok((vec![addr], Instant::now() + self.config.max_ttl)),
+            }
+        }
+        .boxed()
+    }
+
+    /// Resolves `/dnsaddr/<name>` via `dnsaddr=<multiaddr>` TXT records of `_dnsaddr.<name>`,
+    /// keeping only records that end with `suffix`, i.e. with the same `/p2p/<peer id>`
+    async fn lookup_dnsaddr(
+        &self,
+        name: &str,
+        suffix: &Multiaddr,
+        depth: usize,
+    ) -> Result<(Vec<Multiaddr>, Instant), ResolveError> {
+        let lookup = self.resolver.txt_lookup(format!("_dnsaddr.{name}")).await?;
+        let mut valid_until = lookup.valid_until();
+
+        let records: Vec<Multiaddr> = lookup
+            .iter()
+            .flat_map(|txt| txt.txt_data().iter())
+            .filter_map(|data| std::str::from_utf8(data).ok()?.strip_prefix("dnsaddr="))
+            .filter_map(|maddr| maddr.parse().ok())
+            .filter(|maddr: &Multiaddr| maddr.ends_with(suffix))
+            .collect();
+
+        let mut addresses = vec![];
+        for record in records {
+            if !Self::is_dns(&record) {
+                addresses.push(record);
+            } else if depth < MAX_DNSADDR_DEPTH {
+                match self.lookup(record.clone(), depth + 1).await {
+                    Ok((resolved, until)) => {
+                        addresses.extend(resolved);
+                        valid_until = valid_until.min(until);
+                    }
+                    Err(err) => log::warn!("Failed to resolve {record} from {name}: {err}"),
+                }
+            } else {
+                log::warn!("Skipping {record} from {name}: too many nested dnsaddr records");
+            }
+        }
+
+        Ok((addresses, valid_until))
+    }
+
+    pub fn reresolve_interval(&self) -> Duration {
+        self.config.reresolve_interval
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn ip_addresses_are_not_resolved() {
+        let dns = DnsResolver::new(DnsConfig::default());
+        let addr: Multiaddr = "/ip4/1.2.3.4/tcp/7777".parse().unwrap();
+        assert!(!DnsResolver::is_dns(&addr));
+        assert_eq!(dns.resolve(&addr).await.unwrap(), vec![addr]);
+
+        let addr: Multiaddr = "/dnsaddr/fluence.dev".parse().unwrap();
+        assert!(DnsResolver::is_dns(&addr));
+    }
+
+    #[tokio::test]
+    async fn cached_addresses_are_reused() {
+        let dns = DnsResolver::new(DnsConfig::default());
+        let addr: Multiaddr = "/dns4/nox.invalid/tcp/7777".parse().unwrap();
+        let resolved: Multiaddr = "/ip4/1.2.3.4/tcp/7777".parse().unwrap();
+        dns.cache.lock().insert(
+            addr.clone(),
+            Resolved {
+                addresses: vec![resolved.clone()],
+                valid_until: Instant::now() + Duration::from_secs(60),
+            },
+        );
+
+        assert_eq!(dns.resolve(&addr).await.unwrap(), vec![resolved]);
+    }
+}
@@ -15,10 +15,13 @@
  */
 
 use futures::{stream::iter, StreamExt};
+use tokio::time::sleep;
 use tracing::instrument;
 
 use aquamarine::RemoteRoutingEffects;
-use particle_protocol::Particle;
+use particle_protocol::{ExtendedParticle, Particle};
+use peer_metrics::ParticleStage;
+use types::peer_scope::PeerScope;
 
 use crate::connectivity::Connectivity;
 
@@ -41,6 +44,11 @@ impl Effectors {
             return;
         }
 
+        let worker_id = match effects.peer_scope {
+            PeerScope::WorkerId(worker_id) => Some(worker_id.into()),
+            PeerScope::Host => None,
+        };
+
         // take every next peers, and try to send particle there concurrently
         let nps = iter(effects.next_peers);
         let particle = &effects.particle;
@@ -54,6 +62,20 @@ impl Effectors {
                     .resolve_contact(target, particle.as_ref())
                     .await
                 {
+                    let delay = connectivity.bandwidth.outbound(
+                        contact.peer_id,
+                        worker_id,
+                        particle.particle.data.len(),
+                    );
+                    if !delay.is_zero() {
+                        tracing::debug!(
+                            particle_id = particle.particle.id,
+                            "Sending particle to {} is throttled for {delay:?}",
+                            contact.peer_id
+                        );
+                        sleep(delay).await;
+                    }
+
                     // forward particle
                     let sent = connectivity.send(contact, particle).await;
                     if sent {
@@ -63,5 +85,37 @@ impl Effectors {
             }
         })
         .await;
+
+        self.record_timings(&effects.particle);
+    }
+
+    /// Exports stage timings of the particle as metrics and as an event of its trace
+    fn record_timings(&self, particle: &ExtendedParticle) {
+        let timings = &particle.timings;
+        let total = timings.received_at.elapsed();
+        let id = particle.particle.id.as_str();
+        tracing::info!(
+            parent: particle.span.as_ref(),
+            particle_id = id,
+            queue_ms = timings.queue.map(|t| t.as_millis() as u64),
+            interpretation_ms = timings.interpretation.map(|t| t.as_millis() as u64),
+            service_calls_ms = timings.service_calls.map(|t| t.as_millis() as u64),
+            total_ms = total.as_millis() as u64,
+            "Particle timings"
+        );
+
+        if let Some(m) = self.connectivity.metrics.as_ref() {
+            let stages = [
+                (ParticleStage::Queue, timings.queue),
+                (ParticleStage::Interpretation, timings.interpretation),
+                (ParticleStage::ServiceCalls, timings.service_calls),
+                (ParticleStage::Total, Some(total)),
+            ];
+            for (stage, time) in stages {
+                if let Some(time) = time {
+                    m.particle_stage_time(id, stage, time);
+                }
+            }
+        }
     }
 }
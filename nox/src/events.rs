@@ -0,0 +1,117 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use audit_log::{AuditEvent, AuditEventKind, AuditLog, AuditQuery};
+use axum::extract::ws::{Message, WebSocket};
+use connection_pool::LifecycleEvent;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use itertools::Itertools;
+use serde_json::Value;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+
+/// Parses event kinds in their snake_case form, e.g. `peer_connected`
+pub fn parse_event_kinds<'a>(
+    kinds: impl IntoIterator<Item = &'a str>,
+) -> Result<Vec<AuditEventKind>, serde_json::Error> {
+    kinds
+        .into_iter()
+        .map(|kind| serde_json::from_value(Value::from(kind.trim())))
+        .collect()
+}
+
+/// Snake_case name of the event kind, the same as in the JSON format of events
+pub fn event_kind_name(kind: AuditEventKind) -> String {
+    match serde_json::to_value(kind) {
+        Ok(Value::String(name)) => name,
+        _ => format!("{kind:?}"),
+    }
+}
+
+/// Events matching `filter` until the audit log is dropped
+pub fn subscribe_events(
+    audit_log: &AuditLog,
+    filter: AuditQuery,
+) -> BoxStream<'static, AuditEvent> {
+    let events = audit_log.subscribe();
+    futures::stream::unfold((events, filter), |(mut events, filter)| async move {
+        loop {
+            match events.recv().await {
+                Ok(event) if filter.matches(&event) => return Some((event, (events, filter))),
+                Ok(_) => {}
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Events subscriber is too slow, skipped {skipped} events");
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+    .boxed()
+}
+
+/// Publishes connections and disconnections of peers to the audit log subscribers
+pub fn publish_peer_events(
+    events: BoxStream<'static, LifecycleEvent>,
+    audit_log: AuditLog,
+) -> JoinHandle<()> {
+    let task = events.for_each(move |event| {
+        let event = match event {
+            LifecycleEvent::Connected(contact) => {
+                AuditEvent::new(AuditEventKind::PeerConnected, contact.peer_id)
+                    .with_details(contact.addresses.iter().join(","))
+            }
+            LifecycleEvent::Disconnected(contact) => {
+                AuditEvent::new(AuditEventKind::PeerDisconnected, contact.peer_id)
+            }
+        };
+        audit_log.publish(event);
+        futures::future::ready(())
+    });
+    tokio::task::Builder::new()
+        .name("peer-events")
+        .spawn(task)
+        .expect("Could not spawn task")
+}
+
+/// Sends events matching `filter` as JSON text messages until the client disconnects
+pub async fn stream_events(mut socket: WebSocket, audit_log: AuditLog, filter: AuditQuery) {
+    let mut events = subscribe_events(&audit_log, filter);
+    loop {
+        tokio::select! {
+            event = events.next() => match event {
+                Some(event) => {
+                    let message = match serde_json::to_string(&event) {
+                        Ok(message) => message,
+                        Err(err) => {
+                            tracing::warn!("Failed to serialize node event: {err}");
+                            continue;
+                        }
+                    };
+                    if socket.send(Message::Text(message)).await.is_err() {
+                        break;
+                    }
+                }
+                None => break,
+            },
+            message = socket.recv() => match message {
+                None | Some(Err(_)) | Some(Ok(Message::Close(_))) => break,
+                // pings are answered by axum, other messages are ignored
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
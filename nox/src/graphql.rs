@@ -0,0 +1,321 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use async_graphql::{
+    ComplexObject, Context, EmptyMutation, EmptySubscription, Object, Schema, SimpleObject,
+};
+use particle_modules::ModuleRepository;
+
+use crate::control::{Control, ServiceEntry, WorkerEntry};
+
+/// Nested queries deeper than that are rejected
+const MAX_DEPTH: usize = 8;
+const MAX_COMPLEXITY: usize = 1000;
+
+pub type NodeSchema = Schema<Query, EmptyMutation, EmptySubscription>;
+
+/// Read-only schema over the node state, relations between objects are resolved lazily
+pub fn node_schema(control: Control, modules: ModuleRepository) -> NodeSchema {
+    Schema::build(Query, EmptyMutation, EmptySubscription)
+        .data(NodeState { control, modules })
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish()
+}
+
+struct NodeState {
+    control: Control,
+    modules: ModuleRepository,
+}
+
+impl NodeState {
+    fn services(&self) -> Vec<Service> {
+        let services = self.control.list_services(false).into_iter();
+        let spells = self.control.list_services(true).into_iter();
+        services
+            .map(|s| Service::new(s, false))
+            .chain(spells.map(|s| Service::new(s, true)))
+            .collect()
+    }
+
+    fn workers(&self) -> Vec<Worker> {
+        self.control
+            .list_workers()
+            .into_iter()
+            .map(Worker::from)
+            .collect()
+    }
+
+    fn blueprints(&self) -> Vec<Blueprint> {
+        self.modules
+            .get_blueprints()
+            .into_iter()
+            .map(|b| Blueprint {
+                id: b.id,
+                name: b.name,
+                module_hashes: b.dependencies.iter().map(|h| h.to_string()).collect(),
+            })
+            .collect()
+    }
+
+    /// Modules with a valid config, broken files are skipped
+    fn modules(&self) -> Vec<Module> {
+        let modules = match self.modules.list_modules() {
+            Ok(serde_json::Value::Array(modules)) => modules,
+            _ => return vec![],
+        };
+        modules
+            .into_iter()
+            .filter_map(|m| {
+                Some(Module {
+                    hash: m.get("hash")?.as_str()?.to_string(),
+                    name: m.get("name")?.as_str()?.to_string(),
+                })
+            })
+            .collect()
+    }
+}
+
+fn state<'a>(ctx: &Context<'a>) -> &'a NodeState {
+    ctx.data_unchecked::<NodeState>()
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    async fn peer_id(&self, ctx: &Context<'_>) -> String {
+        state(ctx).control.status().await.peer_id
+    }
+
+    /// Services and spells on the host and workers
+    async fn services(&self, ctx: &Context<'_>, spells: Option<bool>) -> Vec<Service> {
+        let services = state(ctx).services().into_iter();
+        services
+            .filter(|s| spells.is_none() || spells == Some(s.spell))
+            .collect()
+    }
+
+    async fn service(&self, ctx: &Context<'_>, id: String) -> Option<Service> {
+        state(ctx).services().into_iter().find(|s| s.id == id)
+    }
+
+    async fn blueprints(&self, ctx: &Context<'_>) -> Vec<Blueprint> {
+        state(ctx).blueprints()
+    }
+
+    async fn blueprint(&self, ctx: &Context<'_>, id: String) -> Option<Blueprint> {
+        state(ctx).blueprints().into_iter().find(|b| b.id == id)
+    }
+
+    async fn modules(&self, ctx: &Context<'_>) -> Vec<Module> {
+        state(ctx).modules()
+    }
+
+    async fn workers(&self, ctx: &Context<'_>) -> Vec<Worker> {
+        state(ctx).workers()
+    }
+
+    async fn worker(&self, ctx: &Context<'_>, id: String) -> Option<Worker> {
+        state(ctx).workers().into_iter().find(|w| w.id == id)
+    }
+
+    /// Deals the workers were created for
+    async fn deals(&self, ctx: &Context<'_>) -> Vec<Deal> {
+        state(ctx)
+            .workers()
+            .into_iter()
+            .filter_map(|w| w.to_deal())
+            .collect()
+    }
+
+    /// Peers with established connections
+    async fn peers(&self, ctx: &Context<'_>) -> Vec<Peer> {
+        let peers = state(ctx).control.list_peers().await;
+        peers
+            .into_iter()
+            .map(|p| Peer {
+                peer_id: p.peer_id.to_base58(),
+                addresses: p.addresses.iter().map(|a| a.to_string()).collect(),
+                class: p.class.map(|c| format!("{c:?}").to_lowercase()),
+                protocols: p.protocols,
+            })
+            .collect()
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct Service {
+    id: String,
+    blueprint_id: String,
+    owner_id: String,
+    aliases: Vec<String>,
+    /// Worker id, or the host peer id for services on the host
+    worker_id: String,
+    spell: bool,
+}
+
+impl Service {
+    fn new(entry: ServiceEntry, spell: bool) -> Self {
+        Self {
+            id: entry.id,
+            blueprint_id: entry.blueprint_id,
+            owner_id: entry.owner_id,
+            aliases: entry.aliases,
+            worker_id: entry.worker_id,
+            spell,
+        }
+    }
+}
+
+#[ComplexObject]
+impl Service {
+    async fn blueprint(&self, ctx: &Context<'_>) -> Option<Blueprint> {
+        let blueprints = state(ctx).blueprints().into_iter();
+        blueprints.find(|b| b.id == self.blueprint_id)
+    }
+
+    /// `null` for services on the host
+    async fn worker(&self, ctx: &Context<'_>) -> Option<Worker> {
+        let workers = state(ctx).workers().into_iter();
+        workers.find(|w| w.id == self.worker_id)
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct Blueprint {
+    id: String,
+    name: String,
+    module_hashes: Vec<String>,
+}
+
+#[ComplexObject]
+impl Blueprint {
+    async fn modules(&self, ctx: &Context<'_>) -> Vec<Module> {
+        let modules = state(ctx).modules().into_iter();
+        modules
+            .filter(|m| self.module_hashes.contains(&m.hash))
+            .collect()
+    }
+
+    /// Services created from the blueprint
+    async fn services(&self, ctx: &Context<'_>) -> Vec<Service> {
+        let services = state(ctx).services().into_iter();
+        services.filter(|s| s.blueprint_id == self.id).collect()
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct Module {
+    hash: String,
+    name: String,
+}
+
+#[ComplexObject]
+impl Module {
+    /// Blueprints depending on the module
+    async fn blueprints(&self, ctx: &Context<'_>) -> Vec<Blueprint> {
+        let blueprints = state(ctx).blueprints().into_iter();
+        blueprints
+            .filter(|b| b.module_hashes.contains(&self.hash))
+            .collect()
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct Worker {
+    id: String,
+    deal_id: Option<String>,
+    active: bool,
+}
+
+impl From<WorkerEntry> for Worker {
+    fn from(entry: WorkerEntry) -> Self {
+        Self {
+            id: entry.worker_id,
+            deal_id: entry.deal_id,
+            active: entry.active,
+        }
+    }
+}
+
+#[ComplexObject]
+impl Worker {
+    async fn services(&self, ctx: &Context<'_>, spells: Option<bool>) -> Vec<Service> {
+        let services = state(ctx).services().into_iter();
+        services
+            .filter(|s| s.worker_id == self.id)
+            .filter(|s| spells.is_none() || spells == Some(s.spell))
+            .collect()
+    }
+
+    async fn deal(&self) -> Option<Deal> {
+        self.to_deal()
+    }
+}
+
+impl Worker {
+    fn to_deal(&self) -> Option<Deal> {
+        Some(Deal {
+            id: self.deal_id.clone()?,
+            worker_id: self.id.clone(),
+        })
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct Deal {
+    id: String,
+    worker_id: String,
+}
+
+#[ComplexObject]
+impl Deal {
+    async fn worker(&self, ctx: &Context<'_>) -> Option<Worker> {
+        let workers = state(ctx).workers().into_iter();
+        workers.find(|w| w.id == self.worker_id)
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct Peer {
+    peer_id: String,
+    addresses: Vec<String>,
+    /// `bootstrap`, `node` or `client`, `null` until the peer is identified
+    class: Option<String>,
+    protocols: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schema() {
+        let sdl = Schema::build(Query, EmptyMutation, EmptySubscription)
+            .finish()
+            .sdl();
+        assert!(sdl.contains("services(spells: Boolean): [Service!]!"));
+        assert!(sdl.contains("blueprint: Blueprint"));
+        assert!(sdl.contains("deal: Deal"));
+        assert!(!sdl.contains("type Mutation"));
+    }
+}
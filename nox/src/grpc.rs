@@ -0,0 +1,545 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use audit_log::{arguments_hash, AuditEvent, AuditEventKind, AuditLog, AuditQuery};
+use connection_pool::MaintenanceStatus;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use libp2p::PeerId;
+use server_config::HostKeyHandover;
+use tonic::service::Interceptor;
+use tonic::transport::Server;
+use tonic::{Extensions, Request, Response, Status};
+
+use crate::admin_tls::{AdminTls, ClientCertificate};
+use crate::api_tokens::{ApiTokens, Role};
+use crate::control::{self, Control};
+use crate::events::{event_kind_name, parse_event_kinds, subscribe_events};
+use crate::http_particle::{HttpParticles, ParticleRequest, SubmitError, DEFAULT_TTL};
+use crate::Versions;
+
+pub mod proto {
+    tonic::include_proto!("nox.management.v1");
+}
+
+use proto::management_server::{Management, ManagementServer};
+use proto::telemetry_server::{Telemetry, TelemetryServer};
+
+const DEFAULT_STATUS_INTERVAL: Duration = Duration::from_secs(10);
+const MIN_STATUS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Management and telemetry RPCs, see `proto/management.proto`
+#[derive(Clone)]
+pub struct GrpcApi {
+    peer_id: PeerId,
+    versions: Versions,
+    control: Control,
+    particles: Option<HttpParticles>,
+    audit_log: AuditLog,
+}
+
+impl GrpcApi {
+    pub fn new(
+        peer_id: PeerId,
+        versions: Versions,
+        control: Control,
+        particles: Option<HttpParticles>,
+        audit_log: AuditLog,
+    ) -> Self {
+        Self {
+            peer_id,
+            versions,
+            control,
+            particles,
+            audit_log,
+        }
+    }
+
+    /// Runs a mutating call and records it into the audit log along with the caller
+    async fn audited<T>(
+        &self,
+        function: &str,
+        extensions: &Extensions,
+        args_hash: String,
+        call: impl Future<Output = Result<T, Status>>,
+    ) -> Result<T, Status> {
+        let caller = extensions
+            .get::<Caller>()
+            .map_or("unknown", |c| c.0.as_str());
+        let event = AuditEvent::new(
+            AuditEventKind::ManagementOperation,
+            format!("grpc.{function}"),
+        )
+        .with_actor(caller);
+        let result = call.await;
+        let outcome = if result.is_ok() { "ok" } else { "error" };
+        self.audit_log
+            .record(event.with_details(format!("args_hash={args_hash} outcome={outcome}")));
+        result
+    }
+}
+
+/// Serves the API to clients with an admin token or client certificate until the server fails
+pub async fn start_grpc_endpoint(
+    listen_addr: SocketAddr,
+    api: GrpcApi,
+    tokens: ApiTokens,
+    tls: Option<AdminTls>,
+) -> eyre::Result<()> {
+    let auth = AdminAuth { tokens };
+    let mut server = Server::builder();
+    if let Some(tls) = tls {
+        server = server.tls_config(tls.grpc_config())?;
+    }
+    server
+        .add_service(ManagementServer::with_interceptor(
+            api.clone(),
+            auth.clone(),
+        ))
+        .add_service(TelemetryServer::with_interceptor(api, auth))
+        .serve(listen_addr)
+        .await?;
+    Ok(())
+}
+
+/// Who the call is authorized for: `token:<token id>` or `cert:<certificate subject>`.
+/// Put into request extensions by [AdminAuth].
+#[derive(Clone)]
+struct Caller(String);
+
+/// Accepts calls with the `authorization: Bearer <token>` metadata of a token with the admin role,
+/// or over TLS with a client certificate signed by the admin CA
+#[derive(Clone)]
+struct AdminAuth {
+    tokens: ApiTokens,
+}
+
+impl Interceptor for AdminAuth {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let certificate = request
+            .peer_certs()
+            .and_then(|certificates| certificates.first().cloned());
+        if let Some(certificate) = certificate {
+            let caller = ClientCertificate::from_der(certificate.get_ref()).caller();
+            request.extensions_mut().insert(Caller(caller));
+            return Ok(request);
+        }
+
+        let (id, role) = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .and_then(|provided| self.tokens.identify(provided))
+            .ok_or_else(|| Status::unauthenticated("Invalid token"))?;
+        if role == Role::Admin {
+            request
+                .extensions_mut()
+                .insert(Caller(format!("token:{id}")));
+            Ok(request)
+        } else {
+            Err(Status::permission_denied("Admin role required"))
+        }
+    }
+}
+
+fn internal(err: eyre::Report) -> Status {
+    Status::internal(format!("{err:#}"))
+}
+
+#[tonic::async_trait]
+impl Management for GrpcApi {
+    async fn get_peer_id(
+        &self,
+        _: Request<proto::Empty>,
+    ) -> Result<Response<proto::PeerIdResponse>, Status> {
+        Ok(Response::new(proto::PeerIdResponse {
+            peer_id: self.peer_id.to_string(),
+        }))
+    }
+
+    async fn get_versions(
+        &self,
+        _: Request<proto::Empty>,
+    ) -> Result<Response<proto::VersionsResponse>, Status> {
+        let versions = &self.versions;
+        Ok(Response::new(proto::VersionsResponse {
+            node: versions.node_version.clone(),
+            avm: versions.avm_version.clone(),
+            spell: versions.spell_version.clone(),
+            aqua_ipfs: versions.system_service.aqua_ipfs_version.clone(),
+            trust_graph: versions.system_service.trust_graph_version.clone(),
+            registry: versions.system_service.registry_version.clone(),
+            decider: versions.system_service.decider_version.clone(),
+        }))
+    }
+
+    async fn get_config(
+        &self,
+        _: Request<proto::Empty>,
+    ) -> Result<Response<proto::ConfigResponse>, Status> {
+        let config = self
+            .control
+            .effective_config()
+            .await
+            .ok_or_else(|| Status::unavailable("Config isn't available"))?;
+        Ok(Response::new(proto::ConfigResponse {
+            config_json: config.to_string(),
+        }))
+    }
+
+    async fn get_status(
+        &self,
+        _: Request<proto::Empty>,
+    ) -> Result<Response<proto::NodeStatus>, Status> {
+        Ok(Response::new(self.control.status().await.into()))
+    }
+
+    async fn list_services(
+        &self,
+        _: Request<proto::Empty>,
+    ) -> Result<Response<proto::ServiceList>, Status> {
+        Ok(Response::new(service_list(
+            self.control.list_services(false),
+        )))
+    }
+
+    async fn list_spells(
+        &self,
+        _: Request<proto::Empty>,
+    ) -> Result<Response<proto::ServiceList>, Status> {
+        Ok(Response::new(service_list(
+            self.control.list_services(true),
+        )))
+    }
+
+    async fn list_workers(
+        &self,
+        _: Request<proto::Empty>,
+    ) -> Result<Response<proto::WorkerList>, Status> {
+        let workers = self
+            .control
+            .list_workers()
+            .into_iter()
+            .map(|w| proto::Worker {
+                worker_id: w.worker_id,
+                deal_id: w.deal_id,
+                active: w.active,
+            })
+            .collect();
+        Ok(Response::new(proto::WorkerList { workers }))
+    }
+
+    async fn drain(
+        &self,
+        request: Request<proto::Empty>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        self.audited("drain", request.extensions(), arguments_hash(&()), async {
+            self.control.drain().await.map_err(internal)
+        })
+        .await?;
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn enter_maintenance(
+        &self,
+        request: Request<proto::Empty>,
+    ) -> Result<Response<proto::MaintenanceStatus>, Status> {
+        let status = self.audited(
+            "enter_maintenance",
+            request.extensions(),
+            arguments_hash(&()),
+            async { Ok(self.control.set_maintenance(true).await) },
+        );
+        Ok(Response::new(status.await?.into()))
+    }
+
+    async fn exit_maintenance(
+        &self,
+        request: Request<proto::Empty>,
+    ) -> Result<Response<proto::MaintenanceStatus>, Status> {
+        let status = self.audited(
+            "exit_maintenance",
+            request.extensions(),
+            arguments_hash(&()),
+            async { Ok(self.control.set_maintenance(false).await) },
+        );
+        Ok(Response::new(status.await?.into()))
+    }
+
+    async fn reload_config(
+        &self,
+        request: Request<proto::Empty>,
+    ) -> Result<Response<proto::ReloadReport>, Status> {
+        let report = self.audited(
+            "reload_config",
+            request.extensions(),
+            arguments_hash(&()),
+            async { self.control.reload_config().await.map_err(internal) },
+        );
+        let report = report.await?;
+        Ok(Response::new(proto::ReloadReport {
+            applied: report.applied,
+            requires_restart: report.requires_restart,
+        }))
+    }
+
+    async fn rotate_logs(
+        &self,
+        request: Request<proto::Empty>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        self.audited(
+            "rotate_logs",
+            request.extensions(),
+            arguments_hash(&()),
+            async {
+                self.control
+                    .rotate_logs()
+                    .map_err(|err| Status::failed_precondition(format!("{err:#}")))
+            },
+        )
+        .await?;
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn rotate_host_key(
+        &self,
+        request: Request<proto::RotateHostKeyRequest>,
+    ) -> Result<Response<proto::HostKeyHandover>, Status> {
+        let grace_period_secs = request.get_ref().grace_period_secs;
+        let handover = self.audited(
+            "rotate_host_key",
+            request.extensions(),
+            arguments_hash(&grace_period_secs),
+            async {
+                self.control
+                    .rotate_host_key(grace_period_secs.map(Duration::from_secs))
+                    .map_err(|err| Status::failed_precondition(format!("{err:#}")))
+            },
+        );
+        Ok(Response::new(handover.await?.into()))
+    }
+
+    async fn submit_particle(
+        &self,
+        request: Request<proto::ParticleRequest>,
+    ) -> Result<Response<proto::ParticleResponse>, Status> {
+        let particles = self
+            .particles
+            .as_ref()
+            .ok_or_else(|| Status::unimplemented("http_client_key_pair isn't configured"))?;
+        let (_, extensions, request) = request.into_parts();
+        let data = if request.data_json.is_empty() {
+            HashMap::new()
+        } else {
+            serde_json::from_str(&request.data_json)
+                .map_err(|e| Status::invalid_argument(format!("Invalid data_json: {e}")))?
+        };
+        let request = ParticleRequest {
+            script: request.script,
+            data,
+            ttl: request.ttl_ms.unwrap_or(DEFAULT_TTL.as_millis() as u32),
+            wait: request.wait,
+        };
+
+        let args_hash = arguments_hash(&request);
+        let result = self.audited("submit_particle", &extensions, args_hash, async {
+            particles.submit(request).await.map_err(|err| match err {
+                SubmitError::InvalidTtl(_) => Status::invalid_argument(err.to_string()),
+                SubmitError::Expired(_) => Status::deadline_exceeded(err.to_string()),
+                _ => Status::internal(err.to_string()),
+            })
+        });
+        let response = result.await?;
+        Ok(Response::new(proto::ParticleResponse {
+            particle_id: response.particle_id,
+            result_json: response
+                .result
+                .map(|result| serde_json::Value::from(result).to_string()),
+        }))
+    }
+}
+
+#[tonic::async_trait]
+impl Telemetry for GrpcApi {
+    type StreamEventsStream = BoxStream<'static, Result<proto::NodeEvent, Status>>;
+    type StreamStatusStream = BoxStream<'static, Result<proto::NodeStatus, Status>>;
+
+    async fn stream_events(
+        &self,
+        request: Request<proto::EventsRequest>,
+    ) -> Result<Response<Self::StreamEventsStream>, Status> {
+        let request = request.into_inner();
+        let kinds = parse_event_kinds(request.kinds.iter().map(String::as_str))
+            .map_err(|e| Status::invalid_argument(format!("Invalid event kind: {e}")))?;
+        let filter = AuditQuery {
+            kinds,
+            subject: request.subject,
+            ..<_>::default()
+        };
+        let events = subscribe_events(&self.audit_log, filter).map(|event| Ok(event.into()));
+        Ok(Response::new(events.boxed()))
+    }
+
+    async fn stream_status(
+        &self,
+        request: Request<proto::StatusRequest>,
+    ) -> Result<Response<Self::StreamStatusStream>, Status> {
+        let interval = match request.into_inner().interval_ms {
+            0 => DEFAULT_STATUS_INTERVAL,
+            millis => Duration::from_millis(millis),
+        };
+        if interval < MIN_STATUS_INTERVAL {
+            return Err(Status::invalid_argument(format!(
+                "interval_ms must be at least {}",
+                MIN_STATUS_INTERVAL.as_millis()
+            )));
+        }
+
+        let control = self.control.clone();
+        let statuses =
+            futures::stream::unfold(tokio::time::interval(interval), move |mut interval| {
+                let control = control.clone();
+                async move {
+                    interval.tick().await;
+                    Some((Ok(control.status().await.into()), interval))
+                }
+            });
+        Ok(Response::new(statuses.boxed()))
+    }
+}
+
+fn service_list(services: Vec<control::ServiceEntry>) -> proto::ServiceList {
+    let services = services
+        .into_iter()
+        .map(|s| proto::Service {
+            id: s.id,
+            blueprint_id: s.blueprint_id,
+            owner_id: s.owner_id,
+            aliases: s.aliases,
+            worker_id: s.worker_id,
+        })
+        .collect();
+    proto::ServiceList { services }
+}
+
+impl From<control::NodeStatus> for proto::NodeStatus {
+    fn from(status: control::NodeStatus) -> Self {
+        Self {
+            peer_id: status.peer_id,
+            node_version: status.node_version,
+            air_version: status.air_version,
+            spell_version: status.spell_version,
+            uptime_secs: status.uptime_secs,
+            connections: status.connections as u64,
+            services: status.services as u64,
+            spells: status.spells as u64,
+            workers: status.workers as u64,
+            maintenance: Some(status.maintenance.into()),
+            host_key_handover: status.host_key_handover.map(Into::into),
+        }
+    }
+}
+
+impl From<HostKeyHandover> for proto::HostKeyHandover {
+    fn from(handover: HostKeyHandover) -> Self {
+        Self {
+            previous_peer_id: handover.previous_peer_id,
+            peer_id: handover.peer_id,
+            grace_period_secs: handover.grace_period.as_secs(),
+            requested_at: handover.requested_at,
+            activated_at: handover.activated_at,
+            signature: handover.signature,
+        }
+    }
+}
+
+impl From<MaintenanceStatus> for proto::MaintenanceStatus {
+    fn from(status: MaintenanceStatus) -> Self {
+        Self {
+            enabled: status.enabled,
+            remaining_clients: status.remaining_clients as u64,
+            rejected_clients: status.rejected_clients as u64,
+            elapsed_secs: status.elapsed_secs,
+        }
+    }
+}
+
+impl From<AuditEvent> for proto::NodeEvent {
+    fn from(event: AuditEvent) -> Self {
+        Self {
+            timestamp: event.timestamp,
+            kind: event_kind_name(event.kind),
+            actor: event.actor,
+            subject: event.subject,
+            details: event.details,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api_tokens::CONFIG_TOKEN_ID;
+
+    use super::*;
+
+    #[test]
+    fn admin_auth() {
+        let tokens = ApiTokens::new(Some("secret".to_string()));
+        let operator = tokens.issue("ci".to_string(), Role::Operator).unwrap();
+        let mut auth = AdminAuth { tokens };
+
+        let mut request = Request::new(());
+        request
+            .metadata_mut()
+            .insert("authorization", "Bearer secret".parse().unwrap());
+        let request = auth.call(request).unwrap();
+        let caller = request.extensions().get::<Caller>().unwrap();
+        assert_eq!(caller.0, format!("token:{CONFIG_TOKEN_ID}"));
+
+        let mut request = Request::new(());
+        request
+            .metadata_mut()
+            .insert("authorization", "Bearer wrong".parse().unwrap());
+        let status = auth.call(request).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+        let status = auth.call(Request::new(())).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+        let mut request = Request::new(());
+        let header = format!("Bearer {}", operator.token);
+        request
+            .metadata_mut()
+            .insert("authorization", header.parse().unwrap());
+        let status = auth.call(request).unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+    }
+
+    #[test]
+    fn node_event() {
+        let event = AuditEvent::new(AuditEventKind::PeerConnected, "12D3KooW");
+        let event = proto::NodeEvent::from(event);
+        assert_eq!(event.kind, "peer_connected");
+        assert_eq!(event.subject, "12D3KooW");
+        assert_eq!(event.actor, None);
+    }
+}
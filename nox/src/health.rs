@@ -1,7 +1,10 @@
+use bytesize::ByteSize;
 use health::HealthCheck;
 use libp2p::Multiaddr;
+use nix::sys::statvfs::statvfs;
 use parking_lot::RwLock;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
@@ -38,6 +41,15 @@ impl BootstrapNodesHealth {
         let mut guard = self.bootstrap_nodes_statuses.write();
         guard.insert(addr, true);
     }
+
+    /// Bootstrap node addresses and whether they're connected
+    pub fn statuses(&self) -> Vec<(Multiaddr, bool)> {
+        let guard = self.bootstrap_nodes_statuses.read();
+        guard
+            .iter()
+            .map(|(addr, connected)| (addr.clone(), *connected))
+            .collect()
+    }
 }
 
 impl HealthCheck for BootstrapNodesHealth {
@@ -65,6 +77,10 @@ impl KademliaBootstrapHealth {
     pub fn on_boostrap_failed(&self) {
         self.status.store(false, Ordering::Release)
     }
+
+    pub fn is_finished(&self) -> bool {
+        self.status.load(Ordering::Acquire)
+    }
 }
 
 impl Default for KademliaBootstrapHealth {
@@ -86,6 +102,73 @@ impl HealthCheck for KademliaBootstrapHealth {
     }
 }
 
+/// Fails until system services are deployed and while the supervisor finds some of them failed
+#[derive(Clone, Default)]
+pub struct SystemServicesHealth {
+    deployed: Arc<AtomicBool>,
+    /// Why system services have failed the last check by their names
+    failures: Arc<RwLock<BTreeMap<String, String>>>,
+}
+
+impl SystemServicesHealth {
+    pub fn on_deployed(&self) {
+        self.deployed.store(true, Ordering::Release)
+    }
+
+    pub fn on_checked(&self, failures: impl IntoIterator<Item = (String, String)>) {
+        *self.failures.write() = failures.into_iter().collect();
+    }
+}
+
+impl HealthCheck for SystemServicesHealth {
+    fn status(&self) -> eyre::Result<()> {
+        if !self.deployed.load(Ordering::Acquire) {
+            return Err(eyre::eyre!("System services aren't deployed yet"));
+        }
+        let failures = self.failures.read();
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            let failures: Vec<_> = failures
+                .iter()
+                .map(|(name, reason)| format!("{name}: {reason}"))
+                .collect();
+            Err(eyre::eyre!(
+                "System services have failed: {}",
+                failures.join("; ")
+            ))
+        }
+    }
+}
+
+/// Fails when the disk of `path` has less than `min_free` space available
+pub struct DiskSpaceHealth {
+    path: PathBuf,
+    min_free: ByteSize,
+}
+
+impl DiskSpaceHealth {
+    pub fn new(path: PathBuf, min_free: ByteSize) -> Self {
+        Self { path, min_free }
+    }
+}
+
+impl HealthCheck for DiskSpaceHealth {
+    fn status(&self) -> eyre::Result<()> {
+        let stat = statvfs(&self.path)?;
+        let available = ByteSize::b(stat.blocks_available() as u64 * stat.fragment_size() as u64);
+        if available < self.min_free {
+            return Err(eyre::eyre!(
+                "Only {} available on the disk of {}, expected at least {}",
+                available,
+                self.path.display(),
+                self.min_free
+            ));
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -183,6 +266,36 @@ mod tests {
         assert!(health.status().is_err());
     }
 
+    #[test]
+    fn system_services_health() {
+        let health = SystemServicesHealth::default();
+        assert!(health.status().is_err());
+        health.on_deployed();
+        assert!(health.status().is_ok());
+
+        health.on_checked([(
+            "decider".to_string(),
+            "spell decider hasn't run for 400s".to_string(),
+        )]);
+        let err = health.status().unwrap_err().to_string();
+        assert!(err.contains("decider: spell decider hasn't run for 400s"));
+        health.on_checked([]);
+        assert!(health.status().is_ok());
+    }
+
+    #[test]
+    fn disk_space_health() {
+        let dir = tempfile::tempdir().unwrap();
+        let health = DiskSpaceHealth::new(dir.path().to_path_buf(), ByteSize::b(0));
+        assert!(health.status().is_ok());
+
+        let health = DiskSpaceHealth::new(dir.path().to_path_buf(), ByteSize::pib(1024));
+        assert!(health.status().is_err());
+
+        let health = DiskSpaceHealth::new(dir.path().join("missing"), ByteSize::b(0));
+        assert!(health.status().is_err());
+    }
+
     #[test]
     fn status_error_should_contain_expected_message() {
         let health = KademliaBootstrapHealth::default();
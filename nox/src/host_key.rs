@@ -0,0 +1,124 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use audit_log::{AuditEvent, AuditEventKind, AuditLog};
+use eyre::eyre;
+use fluence_keypair::KeyPair;
+use server_config::{HostKeyHandover, HostKeyRotation, DEFAULT_HANDOVER_GRACE_PERIOD};
+use tokio::task::JoinHandle;
+use workers::PeerScopes;
+
+/// Rotation of the host peer id with a handover from the previous one.
+/// The new key is generated on request and activated on restart. Until the grace period ends,
+/// the previous peer id is served as the host, the handover is announced to connected peers
+/// so they route the previous peer id to the new one, and the chain listener and connector keep
+/// using the previous peer id, so the operator can move on-chain registrations to the new one.
+#[derive(Clone)]
+pub struct HostKey {
+    key_pair: KeyPair,
+    /// `None` if the root key pair isn't loaded from a file
+    rotation: Option<HostKeyRotation>,
+    scopes: PeerScopes,
+    audit_log: AuditLog,
+}
+
+impl HostKey {
+    pub fn new(
+        key_pair: KeyPair,
+        rotation: Option<HostKeyRotation>,
+        scopes: PeerScopes,
+        audit_log: AuditLog,
+    ) -> Self {
+        Self {
+            key_pair,
+            rotation,
+            scopes,
+            audit_log,
+        }
+    }
+
+    fn rotation(&self) -> eyre::Result<&HostKeyRotation> {
+        self.rotation
+            .as_ref()
+            .ok_or_else(|| eyre!("root key pair isn't loaded from a file, it can't be rotated"))
+    }
+
+    /// Handover pending restart or in the grace period
+    pub fn handover(&self) -> eyre::Result<Option<HostKeyHandover>> {
+        match &self.rotation {
+            Some(rotation) => rotation.handover(),
+            None => Ok(None),
+        }
+    }
+
+    /// Generates the next host key, the node switches to it on restart
+    pub fn rotate(&self, grace_period: Option<Duration>) -> eyre::Result<HostKeyHandover> {
+        let grace_period = grace_period.unwrap_or(DEFAULT_HANDOVER_GRACE_PERIOD);
+        let handover = self.rotation()?.rotate(&self.key_pair, grace_period)?;
+        log::info!(
+            "Host key rotation to {} requested, restart the node to activate it",
+            handover.peer_id
+        );
+        self.audit_log.record(
+            AuditEvent::new(
+                AuditEventKind::HostKeyRotationStarted,
+                &handover.previous_peer_id,
+            )
+            .with_details(format!("next peer id {}", handover.peer_id)),
+        );
+        Ok(handover)
+    }
+
+    /// Retires the previous host peer id when the grace period of the handover ends
+    pub fn start(self) -> Option<JoinHandle<()>> {
+        let previous = self.scopes.get_previous_host_peer_id()?;
+        let rotation = self.rotation.clone()?;
+        let retires_at = match rotation.handover() {
+            Ok(handover) => handover.and_then(|h| h.retires_at())?,
+            Err(err) => {
+                log::warn!("Failed to read host key handover: {err:#}");
+                return None;
+            }
+        };
+        let delay = Duration::from_secs(retires_at.saturating_sub(now_secs()));
+        log::info!("Previous host peer id {previous} is retired in {delay:?}");
+
+        let task = async move {
+            tokio::time::sleep(delay).await;
+            self.scopes.retire_previous_host();
+            if let Err(err) = rotation.retire() {
+                log::warn!("Failed to remove the previous host key: {err:#}");
+            }
+            log::info!("Previous host peer id {previous} is retired");
+            self.audit_log
+                .record(AuditEvent::new(AuditEventKind::HostKeyRetired, previous));
+        };
+        let handle = tokio::task::Builder::new()
+            .name("host-key-retirement")
+            .spawn(task)
+            .expect("Could not spawn task");
+        Some(handle)
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
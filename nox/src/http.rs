@@ -1,37 +1,65 @@
+use crate::acme::AcmeHttpChallenges;
+use crate::admin_tls::{AdminTls, ClientCertificate};
+use crate::api_tokens::{ApiTokens, Role};
+use crate::config_reload::ConfigReloader;
+use crate::events::{parse_event_kinds, stream_events};
+use crate::graphql::NodeSchema;
+use crate::http_error::{negotiate, ApiError, ApiResult, ErrorCode, API_VERSION};
+use crate::http_particle::{HttpParticles, ParticleRequest, SubmitError};
+use crate::profiling;
 use crate::Versions;
+use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
+use audit_log::{arguments_hash, AuditEvent, AuditEventKind, AuditLog, AuditQuery};
 use axum::body::Body;
-use axum::http::header::CONTENT_TYPE;
-use axum::response::ErrorResponse;
+use axum::extract::rejection::{JsonRejection, QueryRejection};
+use axum::extract::ws::WebSocketUpgrade;
+use axum::extract::FromRequestParts;
+use axum::extract::Query;
+use axum::http::header::{AUTHORIZATION, CONTENT_DISPOSITION, CONTENT_TYPE};
+use axum::http::request::Parts;
+use axum::http::HeaderMap;
 use axum::{
-    extract::State,
+    extract::{Path, State},
     http::StatusCode,
     response::{IntoResponse, Response},
-    routing::get,
+    routing::{get, post},
     Json, Router,
 };
-use health::{HealthCheckRegistry, HealthStatus};
+use health::{CheckReport, HealthCheckRegistry, HealthReport, HealthStatus};
 use libp2p::PeerId;
 use prometheus_client::encoding::text::encode;
 use prometheus_client::registry::Registry;
+use serde::Deserialize;
 use serde_json::{json, Value};
+use std::convert::Infallible;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tokio::sync::oneshot;
+use std::time::Duration;
+use tokio::sync::{oneshot, Mutex};
 
-async fn handler_404() -> impl IntoResponse {
-    (StatusCode::NOT_FOUND, "No such endpoint")
+async fn handler_404() -> ApiError {
+    ApiError::not_found()
 }
 
-async fn handle_metrics(State(state): State<RouteState>) -> axum::response::Result<Response<Body>> {
+async fn handle_metrics(
+    State(state): State<RouteState>,
+    credentials: Credentials,
+) -> ApiResult<Response<Body>> {
+    check_metrics_access(&state, &credentials)?;
+    let metrics = state.0.metrics.as_ref().ok_or_else(ApiError::not_found)?;
+    metrics_response(metrics)
+}
+
+/// Metrics in the OpenMetrics text format, 404 while they're disabled
+pub fn metrics_response(metrics: &MetricsEndpoint) -> ApiResult<Response<Body>> {
+    if !metrics.enabled.load(Ordering::Relaxed) {
+        return Err(ApiError::not_found());
+    }
     let mut buf = String::new();
-    let registry = state
-        .0
-        .metric_registry
-        .as_ref()
-        .ok_or((StatusCode::NOT_FOUND, "No such endpoint"))?;
-    encode(&mut buf, registry).map_err(|e| {
+    encode(&mut buf, &metrics.registry).map_err(|e| {
         tracing::warn!("Metrics encode error: {}", e);
-        ErrorResponse::from(StatusCode::INTERNAL_SERVER_ERROR)
+        ApiError::internal("Could not encode metrics")
     })?;
 
     let body = Body::from(buf);
@@ -43,7 +71,7 @@ async fn handle_metrics(State(state): State<RouteState>) -> axum::response::Resu
         .body(body)
         .map_err(|e| {
             tracing::warn!("Could not create metric response: {}", e);
-            ErrorResponse::from(StatusCode::INTERNAL_SERVER_ERROR)
+            ApiError::internal("Could not create metric response")
         })
 }
 
@@ -69,82 +97,501 @@ async fn handle_versions(State(state): State<RouteState>) -> Response {
     .into_response()
 }
 
-/// Health check endpoint follows consul contract https://developer.hashicorp.com/consul/docs/services/usage/checks#http-checks
-async fn handle_health(State(state): State<RouteState>) -> axum::response::Result<Response> {
-    fn make_json(keys: Vec<&'static str>, status: &str) -> Vec<Value> {
-        keys.into_iter().map(|k| json!({k: status})).collect()
-    }
-
+fn health_report(state: &RouteState) -> ApiResult<HealthReport> {
     let registry = state
         .0
         .health_registry
         .as_ref()
-        .ok_or((StatusCode::NOT_FOUND, "No such endpoint"))?;
-    let result = match registry.status() {
-        HealthStatus::Ok(keys) => (StatusCode::OK, Json(make_json(keys, "Ok"))).into_response(),
-        HealthStatus::Warning(ok, fail) => {
-            let mut result = make_json(ok, "Ok");
-            let mut fail = make_json(fail, "Fail");
-            result.append(&mut fail);
-            (StatusCode::TOO_MANY_REQUESTS, Json(result)).into_response()
-        }
-        HealthStatus::Fail(keys) => (
-            StatusCode::SERVICE_UNAVAILABLE,
-            Json(make_json(keys, "Fail")),
+        .ok_or_else(ApiError::not_found)?;
+    Ok(registry.report())
+}
+
+fn checks_json<'a>(checks: impl Iterator<Item = &'a CheckReport>) -> Vec<Value> {
+    checks
+        .map(|check| match &check.reason {
+            None => json!({"name": check.name, "status": "Ok"}),
+            Some(reason) => json!({"name": check.name, "status": "Fail", "reason": reason}),
+        })
+        .collect()
+}
+
+/// Health check endpoint follows consul contract https://developer.hashicorp.com/consul/docs/services/usage/checks#http-checks
+async fn handle_health(State(state): State<RouteState>) -> ApiResult<Response> {
+    let report = health_report(&state)?;
+    let (code, status) = match report.status() {
+        HealthStatus::Ok(_) => (StatusCode::OK, "Ok"),
+        HealthStatus::Warning(..) => (StatusCode::TOO_MANY_REQUESTS, "Warning"),
+        HealthStatus::Fail(_) => (StatusCode::SERVICE_UNAVAILABLE, "Fail"),
+    };
+    let body = json!({
+        "status": status,
+        "checks": checks_json(report.checks.iter()),
+    });
+    Ok((code, Json(body)).into_response())
+}
+
+/// Readiness probe, fails while any check fails
+async fn handle_ready(State(state): State<RouteState>) -> ApiResult<Response> {
+    let report = health_report(&state)?;
+    let ready = report.is_ready();
+    let code = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = json!({
+        "ready": ready,
+        "checks": checks_json(report.checks.iter()),
+    });
+    Ok((code, Json(body)).into_response())
+}
+
+/// Liveness probe, fails only if a liveness check fails, i.e. the node has to be restarted
+async fn handle_live(State(state): State<RouteState>) -> ApiResult<Response> {
+    let report = health_report(&state)?;
+    let live = report.is_live();
+    let code = if live {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = json!({
+        "live": live,
+        "checks": checks_json(report.liveness()),
+    });
+    Ok((code, Json(body)).into_response())
+}
+
+/// Effective config with secrets masked, it always requires a read-only token
+async fn handle_config(
+    State(state): State<RouteState>,
+    credentials: Credentials,
+) -> ApiResult<Response> {
+    check_role(&state, &credentials, Role::ReadOnly)?;
+    let reloader = state
+        .0
+        .config_reloader
+        .as_ref()
+        .ok_or_else(ApiError::not_found)?;
+    let config = reloader
+        .effective_config()
+        .await
+        .ok_or_else(|| ApiError::new(ErrorCode::Unavailable, "Config isn't available"))?;
+    Ok(Json(config).into_response())
+}
+
+/// Serves ACME HTTP-01 challenges for the websocket TLS certificate
+async fn handle_acme_challenge(
+    State(state): State<RouteState>,
+    Path(token): Path<String>,
+) -> Response {
+    let key_authorization = state
+        .0
+        .acme_challenges
+        .as_ref()
+        .and_then(|challenges| challenges.get(&token));
+    match key_authorization {
+        Some(key_authorization) => key_authorization.into_response(),
+        None => ApiError::new(ErrorCode::NotFound, "No such challenge").into_response(),
+    }
+}
+
+/// Bearer token and verified client certificate of a request
+struct Credentials {
+    headers: HeaderMap,
+    certificate: Option<ClientCertificate>,
+}
+
+#[axum::async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Credentials {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self {
+            headers: parts.headers.clone(),
+            certificate: parts
+                .extensions
+                .get::<Option<ClientCertificate>>()
+                .cloned()
+                .flatten(),
+        })
+    }
+}
+
+/// Checks the bearer token has at least `role`. Clients with a certificate signed by the admin CA are admins,
+/// so they have any role regardless of the certificate subject.
+/// Endpoints requiring a token are hidden unless there's any token or certificate to authorize with.
+/// Returns the caller: `token:<token id>` or `cert:<certificate subject>`.
+fn check_role(state: &RouteState, credentials: &Credentials, role: Role) -> ApiResult<String> {
+    if let Some(certificate) = &credentials.certificate {
+        return Ok(certificate.caller());
+    }
+    let tokens = &state.0.auth.tokens;
+    if !tokens.is_enabled() && state.0.auth.tls.is_none() {
+        return Err(ApiError::not_found());
+    }
+    authorize(tokens, &credentials.headers, role).map(|id| format!("token:{id}"))
+}
+
+/// Checks the bearer token is one of `tokens` and has at least `role`. Returns id of the token.
+pub fn authorize(tokens: &ApiTokens, headers: &HeaderMap, role: Role) -> ApiResult<String> {
+    let (id, granted) = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .and_then(|provided| tokens.identify(provided))
+        .ok_or_else(|| ApiError::new(ErrorCode::Unauthorized, "Invalid token"))?;
+    if granted >= role {
+        Ok(id)
+    } else {
+        Err(ApiError::new(
+            ErrorCode::Forbidden,
+            "Insufficient token role",
+        ))
+    }
+}
+
+fn check_metrics_access(state: &RouteState, credentials: &Credentials) -> ApiResult<()> {
+    if state.0.auth.protect_metrics {
+        check_role(state, credentials, Role::ReadOnly).map(drop)
+    } else {
+        Ok(())
+    }
+}
+
+fn bad_request(rejection: impl ToString) -> ApiError {
+    ApiError::new(ErrorCode::BadRequest, rejection)
+}
+
+#[derive(Deserialize)]
+struct ProfileParams {
+    seconds: Option<u64>,
+}
+
+fn pprof_response(profile: eyre::Result<Vec<u8>>, name: &str) -> ApiResult<Response> {
+    let profile = profile.map_err(|e| {
+        tracing::warn!("Could not collect {} profile: {:?}", name, e);
+        ApiError::internal(e)
+    })?;
+    Response::builder()
+        .header(CONTENT_TYPE, "application/octet-stream")
+        .header(
+            CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{name}.pb\""),
         )
-            .into_response(),
+        .body(Body::from(profile))
+        .map_err(|e| {
+            tracing::warn!("Could not create profile response: {}", e);
+            ApiError::internal("Could not create profile response")
+        })
+}
+
+/// CPU profile for `seconds`, compatible with `go tool pprof`
+async fn handle_cpu_profile(
+    State(state): State<RouteState>,
+    credentials: Credentials,
+    params: Result<Query<ProfileParams>, QueryRejection>,
+) -> ApiResult<Response> {
+    check_role(&state, &credentials, Role::Operator)?;
+    let duration = profile_duration(params)?;
+    // sampling profiler is process-wide, so only one profile is collected at a time
+    let _guard = state
+        .0
+        .profiling
+        .try_lock()
+        .map_err(|_| ApiError::new(ErrorCode::Conflict, "Profiling is already in progress"))?;
+
+    tracing::info!("Collecting cpu profile for {:?}", duration);
+    pprof_response(profiling::cpu_profile(duration).await, "cpu")
+}
+
+/// Heap allocations sampled for `seconds` and still live, compatible with `go tool pprof`
+async fn handle_heap_profile(
+    State(state): State<RouteState>,
+    credentials: Credentials,
+    params: Result<Query<ProfileParams>, QueryRejection>,
+) -> ApiResult<Response> {
+    check_role(&state, &credentials, Role::Operator)?;
+    let duration = profile_duration(params)?;
+    let _guard = state
+        .0
+        .profiling
+        .try_lock()
+        .map_err(|_| ApiError::new(ErrorCode::Conflict, "Profiling is already in progress"))?;
+
+    tracing::info!("Collecting heap profile for {:?}", duration);
+    pprof_response(profiling::heap_profile(duration).await, "heap")
+}
+
+fn profile_duration(params: Result<Query<ProfileParams>, QueryRejection>) -> ApiResult<Duration> {
+    let Query(params) = params.map_err(bad_request)?;
+    let duration = params
+        .seconds
+        .map(Duration::from_secs)
+        .unwrap_or(profiling::DEFAULT_PROFILE_DURATION);
+    if duration.is_zero() || duration > profiling::MAX_PROFILE_DURATION {
+        return Err(ApiError::new(
+            ErrorCode::BadRequest,
+            format!(
+                "seconds must be between 1 and {}",
+                profiling::MAX_PROFILE_DURATION.as_secs()
+            ),
+        ));
+    }
+    Ok(duration)
+}
+
+/// Injects the particle as if it was sent by the configured http client key
+async fn handle_particle(
+    State(state): State<RouteState>,
+    credentials: Credentials,
+    request: Result<Json<ParticleRequest>, JsonRejection>,
+) -> ApiResult<Response> {
+    let caller = check_role(&state, &credentials, Role::Admin)?;
+    let Json(request) = request.map_err(bad_request)?;
+    let particles = state.0.particles.as_ref().ok_or_else(ApiError::not_found)?;
+    let event =
+        AuditEvent::new(AuditEventKind::ManagementOperation, "http.particle").with_actor(caller);
+    let args_hash = arguments_hash(&request);
+    let result = particles.submit(request).await;
+    if let Some(audit_log) = &state.0.audit_log {
+        let outcome = if result.is_ok() { "ok" } else { "error" };
+        audit_log.record(event.with_details(format!("args_hash={args_hash} outcome={outcome}")));
+    }
+    match result {
+        Ok(response) => Ok(Json(response).into_response()),
+        Err(err) => {
+            let code = match err {
+                SubmitError::InvalidTtl(_) => ErrorCode::BadRequest,
+                SubmitError::Expired(_) => ErrorCode::Timeout,
+                SubmitError::Signing(_) | SubmitError::Execution(_) => ErrorCode::Internal,
+            };
+            Err(ApiError::new(code, err))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct EventsParams {
+    /// Comma-separated event kinds, all if not set
+    kinds: Option<String>,
+    subject: Option<String>,
+}
+
+/// Streams node events as JSON messages over a websocket
+async fn handle_events(
+    State(state): State<RouteState>,
+    credentials: Credentials,
+    params: Result<Query<EventsParams>, QueryRejection>,
+    ws: Option<WebSocketUpgrade>,
+) -> ApiResult<Response> {
+    check_role(&state, &credentials, Role::ReadOnly)?;
+    let Query(params) = params.map_err(bad_request)?;
+    let audit_log = state.0.audit_log.clone().ok_or_else(ApiError::not_found)?;
+    let ws =
+        ws.ok_or_else(|| ApiError::new(ErrorCode::UpgradeRequired, "Expected websocket upgrade"))?;
+
+    let kinds = parse_event_kinds(params.kinds.iter().flat_map(|kinds| kinds.split(',')))
+        .map_err(|e| ApiError::new(ErrorCode::BadRequest, format!("Invalid event kind: {e}")))?;
+    let filter = AuditQuery {
+        kinds,
+        subject: params.subject,
+        ..<_>::default()
     };
-    Ok(result)
+    Ok(ws.on_upgrade(move |socket| stream_events(socket, audit_log, filter)))
+}
+
+/// Executes read-only queries over services, blueprints, modules, workers, deals and peers
+async fn handle_graphql(
+    State(state): State<RouteState>,
+    credentials: Credentials,
+    request: GraphQLRequest,
+) -> ApiResult<GraphQLResponse> {
+    check_role(&state, &credentials, Role::ReadOnly)?;
+    let schema = state.0.graphql.as_ref().ok_or_else(ApiError::not_found)?;
+    Ok(schema.execute(request.into_inner()).await.into())
 }
 
 #[derive(Clone)]
 struct RouteState(Arc<Inner>);
 
+/// Metrics served at `/metrics` while `enabled` is set, it's toggled on config reload
+pub struct MetricsEndpoint {
+    pub registry: Registry,
+    pub enabled: Arc<AtomicBool>,
+}
+
+/// Tokens checked by the endpoints that require authorization
+#[derive(Clone, Default)]
+pub struct HttpAuth {
+    pub tokens: ApiTokens,
+    /// Whether `/metrics` requires a read-only token
+    pub protect_metrics: bool,
+    /// Serve over TLS, authorizing admins by client certificates as well
+    pub tls: Option<AdminTls>,
+}
+
 struct Inner {
-    metric_registry: Option<Registry>,
+    metrics: Option<MetricsEndpoint>,
     health_registry: Option<HealthCheckRegistry>,
     peer_id: PeerId,
     versions: Versions,
+    acme_challenges: Option<AcmeHttpChallenges>,
+    config_reloader: Option<ConfigReloader>,
+    auth: HttpAuth,
+    profiling: Mutex<()>,
+    particles: Option<HttpParticles>,
+    audit_log: Option<AuditLog>,
+    graphql: Option<NodeSchema>,
 }
 #[derive(Debug)]
 pub struct StartedHttp {
     pub listen_addr: SocketAddr,
+    pub probe_listen_addr: Option<SocketAddr>,
+}
+
+/// Routes of the http endpoint are served only if their handlers are set
+pub struct HttpEndpointConfig {
+    pub listen_addr: SocketAddr,
+    pub peer_id: PeerId,
+    pub versions: Versions,
+    pub auth: HttpAuth,
+    pub metrics: Option<MetricsEndpoint>,
+    pub health_registry: Option<HealthCheckRegistry>,
+    pub acme_challenges: Option<AcmeHttpChallenges>,
+    pub config_reloader: Option<ConfigReloader>,
+    pub particles: Option<HttpParticles>,
+    pub audit_log: Option<AuditLog>,
+    pub graphql: Option<NodeSchema>,
+    /// Plain http listener of probes and metrics, so they're reachable without a client certificate
+    pub probe_listen_addr: Option<SocketAddr>,
+}
+
+impl HttpEndpointConfig {
+    /// Endpoint without authentication that serves only routes which don't need handlers
+    pub fn new(listen_addr: SocketAddr, peer_id: PeerId, versions: Versions) -> Self {
+        Self {
+            listen_addr,
+            peer_id,
+            versions,
+            auth: <_>::default(),
+            metrics: None,
+            health_registry: None,
+            acme_challenges: None,
+            config_reloader: None,
+            particles: None,
+            audit_log: None,
+            graphql: None,
+            probe_listen_addr: None,
+        }
+    }
 }
 
 pub async fn start_http_endpoint(
-    listen_addr: SocketAddr,
-    metric_registry: Option<Registry>,
-    health_registry: Option<HealthCheckRegistry>,
-    peer_id: PeerId,
-    versions: Versions,
+    config: HttpEndpointConfig,
     notify: oneshot::Sender<StartedHttp>,
 ) -> eyre::Result<()> {
+    let HttpEndpointConfig {
+        listen_addr,
+        peer_id,
+        versions,
+        auth,
+        metrics,
+        health_registry,
+        acme_challenges,
+        config_reloader,
+        particles,
+        audit_log,
+        graphql,
+        probe_listen_addr,
+    } = config;
+    let tls = auth.tls.clone();
     let state = RouteState(Arc::new(Inner {
-        metric_registry,
+        metrics,
         health_registry,
         peer_id,
         versions,
+        acme_challenges,
+        config_reloader,
+        auth,
+        profiling: Mutex::new(()),
+        particles,
+        audit_log,
+        graphql,
     }));
-    let app: Router = Router::new()
+    let probes: Router<RouteState> = Router::new()
         .route("/metrics", get(handle_metrics))
+        .route("/health", get(handle_health))
+        .route("/ready", get(handle_ready))
+        .route("/live", get(handle_live));
+    let api = probes
+        .clone()
         .route("/peer_id", get(handle_peer_id))
         .route("/versions", get(handle_versions))
-        .route("/health", get(handle_health))
+        .route("/config", get(handle_config))
+        .route("/debug/pprof/profile", get(handle_cpu_profile))
+        .route("/debug/pprof/heap", get(handle_heap_profile))
+        .route("/particle", post(handle_particle))
+        .route("/events", get(handle_events))
+        .route("/graphql", post(handle_graphql));
+    let app: Router = versioned(api)
+        .route(
+            "/.well-known/acme-challenge/:token",
+            get(handle_acme_challenge),
+        )
+        .fallback(handler_404)
+        .layer(axum::middleware::from_fn(negotiate))
+        .with_state(state.clone());
+    // probe connections have no client certificate, so protected metrics require a token there
+    let probe_app: Router = versioned(probes)
         .fallback(handler_404)
+        .layer(axum::middleware::from_fn(negotiate))
         .with_state(state);
 
     let listener = tokio::net::TcpListener::bind(listen_addr).await?;
     let local_addr = listener.local_addr()?;
-    let server = axum::serve(listener, app.into_make_service());
+    let probe_listener = match probe_listen_addr {
+        Some(addr) => Some(tokio::net::TcpListener::bind(addr).await?),
+        None => None,
+    };
     notify
         .send(StartedHttp {
             listen_addr: local_addr,
+            probe_listen_addr: probe_listener
+                .as_ref()
+                .map(|listener| listener.local_addr())
+                .transpose()?,
         })
         .expect("Could not send http info");
-    server.await?;
+    let server = async move {
+        match tls {
+            Some(tls) => {
+                axum_server::from_tcp(listener.into_std()?)
+                    .acceptor(tls.http_acceptor())
+                    .serve(app.into_make_service())
+                    .await
+            }
+            None => axum::serve(listener, app.into_make_service()).await,
+        }
+    };
+    let probe_server = async move {
+        match probe_listener {
+            Some(listener) => axum::serve(listener, probe_app.into_make_service()).await,
+            None => futures::future::pending().await,
+        }
+    };
+    tokio::try_join!(server, probe_server)?;
     Ok(())
 }
 
+/// Serves `api` under `/v{API_VERSION}` and unversioned paths, which are kept for existing
+/// scrapers and probes, they serve the latest version
+fn versioned(api: Router<RouteState>) -> Router<RouteState> {
+    Router::new()
+        .nest(&format!("/v{API_VERSION}"), api.clone())
+        .merge(api)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -152,6 +599,13 @@ mod tests {
     use reqwest::StatusCode;
     use std::net::SocketAddr;
 
+    fn admin_auth(token: &str) -> HttpAuth {
+        HttpAuth {
+            tokens: ApiTokens::new(Some(token.to_string())),
+            ..<_>::default()
+        }
+    }
+
     fn test_versions() -> Versions {
         Versions {
             node_version: "node_test_version".to_string(),
@@ -174,11 +628,7 @@ mod tests {
         let (notify_sender, notify_receiver) = oneshot::channel();
         tokio::spawn(async move {
             start_http_endpoint(
-                addr,
-                None,
-                None,
-                PeerId::random(),
-                test_versions(),
+                HttpEndpointConfig::new(addr, PeerId::random(), test_versions()),
                 notify_sender,
             )
             .await
@@ -200,6 +650,61 @@ mod tests {
         assert_eq!(&body[..], br#"{"node":"node_test_version","avm":"avm_test_version","spell":"spell_test_version","aqua_ipfs":"aqua_ipfs_test_version","trust_graph":"trust_graph_test_version","registry":"registry_test_version","decider":"decider_test_version"}"#);
     }
 
+    #[tokio::test]
+    async fn test_versioned_routes() {
+        let addr = "127.0.0.1:0".parse::<SocketAddr>().unwrap();
+
+        let (notify_sender, notify_receiver) = oneshot::channel();
+        tokio::spawn(async move {
+            start_http_endpoint(
+                HttpEndpointConfig::new(addr, PeerId::random(), test_versions()),
+                notify_sender,
+            )
+            .await
+            .unwrap();
+        });
+
+        let http_info = notify_receiver.await.unwrap();
+
+        let client = reqwest::Client::new();
+        let base = format!("http://{}", http_info.listen_addr);
+
+        let response = client
+            .get(format!("{base}/v1/versions"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = client
+            .get(format!("{base}/v1/unknown"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let error: ApiError = serde_json::from_slice(&response.bytes().await.unwrap()).unwrap();
+        assert_eq!(error, ApiError::not_found());
+
+        let response = client
+            .get(format!("{base}/v1/unknown"))
+            .header("accept", "text/plain")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.text().await.unwrap(), "No such endpoint");
+
+        let response = client
+            .get(format!("{base}/v1/versions"))
+            .header("accept", "application/vnd.nox.v2+json")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+        let error: ApiError = serde_json::from_slice(&response.bytes().await.unwrap()).unwrap();
+        assert_eq!(error.code, ErrorCode::NotAcceptable);
+    }
+
     #[tokio::test]
     async fn test_peer_id_route() {
         // Create a test server
@@ -208,9 +713,12 @@ mod tests {
 
         let (notify_sender, notify_receiver) = oneshot::channel();
         tokio::spawn(async move {
-            start_http_endpoint(addr, None, None, peer_id, test_versions(), notify_sender)
-                .await
-                .unwrap();
+            start_http_endpoint(
+                HttpEndpointConfig::new(addr, peer_id, test_versions()),
+                notify_sender,
+            )
+            .await
+            .unwrap();
         });
 
         let http_info = notify_receiver.await.unwrap();
@@ -240,16 +748,11 @@ mod tests {
         let (notify_sender, notify_receiver) = oneshot::channel();
         let health_registry = HealthCheckRegistry::new();
         tokio::spawn(async move {
-            start_http_endpoint(
-                addr,
-                None,
-                Some(health_registry),
-                peer_id,
-                test_versions(),
-                notify_sender,
-            )
-            .await
-            .unwrap();
+            let config = HttpEndpointConfig {
+                health_registry: Some(health_registry),
+                ..HttpEndpointConfig::new(addr, peer_id, test_versions())
+            };
+            start_http_endpoint(config, notify_sender).await.unwrap();
         });
 
         let http_info = notify_receiver.await.unwrap();
@@ -264,7 +767,8 @@ mod tests {
         let status = response.status();
         let body = response.bytes().await.unwrap();
         assert_eq!(status, StatusCode::OK);
-        assert_eq!(&body[..], (r#"[]"#).as_bytes());
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body, json!({"status": "Ok", "checks": []}));
     }
 
     #[tokio::test]
@@ -284,16 +788,11 @@ mod tests {
         let success_check = SuccessHealthCheck {};
         health_registry.register("test_check", success_check);
         tokio::spawn(async move {
-            start_http_endpoint(
-                addr,
-                None,
-                Some(health_registry),
-                peer_id,
-                test_versions(),
-                notify_sender,
-            )
-            .await
-            .unwrap();
+            let config = HttpEndpointConfig {
+                health_registry: Some(health_registry),
+                ..HttpEndpointConfig::new(addr, peer_id, test_versions())
+            };
+            start_http_endpoint(config, notify_sender).await.unwrap();
         });
 
         let http_info = notify_receiver.await.unwrap();
@@ -308,7 +807,11 @@ mod tests {
         let status = response.status();
         let body = response.bytes().await.unwrap();
         assert_eq!(status, StatusCode::OK);
-        assert_eq!(&body[..], (r#"[{"test_check":"Ok"}]"#).as_bytes());
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            json!({"status": "Ok", "checks": [{"name": "test_check", "status": "Ok"}]})
+        );
     }
 
     #[tokio::test]
@@ -336,16 +839,11 @@ mod tests {
         health_registry.register("test_check", success_check);
         health_registry.register("test_check_2", fail_check);
         tokio::spawn(async move {
-            start_http_endpoint(
-                addr,
-                None,
-                Some(health_registry),
-                peer_id,
-                test_versions(),
-                notify_sender,
-            )
-            .await
-            .unwrap();
+            let config = HttpEndpointConfig {
+                health_registry: Some(health_registry),
+                ..HttpEndpointConfig::new(addr, peer_id, test_versions())
+            };
+            start_http_endpoint(config, notify_sender).await.unwrap();
         });
 
         let http_info = notify_receiver.await.unwrap();
@@ -360,9 +858,13 @@ mod tests {
         let status = response.status();
         let body = response.bytes().await.unwrap();
         assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        let body: Value = serde_json::from_slice(&body).unwrap();
         assert_eq!(
-            &body[..],
-            (r#"[{"test_check":"Ok"},{"test_check_2":"Fail"}]"#).as_bytes()
+            body,
+            json!({"status": "Warning", "checks": [
+                {"name": "test_check", "status": "Ok"},
+                {"name": "test_check_2", "status": "Fail", "reason": "Failed"}
+            ]})
         );
     }
 
@@ -382,13 +884,275 @@ mod tests {
         }
         let fail_check = FailHealthCheck {};
         health_registry.register("test_check", fail_check);
+        tokio::spawn(async move {
+            let config = HttpEndpointConfig {
+                health_registry: Some(health_registry),
+                ..HttpEndpointConfig::new(addr, peer_id, test_versions())
+            };
+            start_http_endpoint(config, notify_sender).await.unwrap();
+        });
+
+        let http_info = notify_receiver.await.unwrap();
+
+        let client = reqwest::Client::new();
+
+        let response = client
+            .get(format!("http://{}/health", http_info.listen_addr))
+            .send()
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = response.bytes().await.unwrap();
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            json!({"status": "Fail", "checks": [
+                {"name": "test_check", "status": "Fail", "reason": "Failed"}
+            ]})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ready_and_live_routes() {
+        // Create a test server
+        let addr = "127.0.0.1:0".parse::<SocketAddr>().unwrap();
+        let peer_id = PeerId::random();
+
+        let (notify_sender, notify_receiver) = oneshot::channel();
+        let mut health_registry = HealthCheckRegistry::new();
+        struct SuccessHealthCheck {}
+        impl HealthCheck for SuccessHealthCheck {
+            fn status(&self) -> eyre::Result<()> {
+                Ok(())
+            }
+        }
+        struct FailHealthCheck {}
+        impl HealthCheck for FailHealthCheck {
+            fn status(&self) -> eyre::Result<()> {
+                Err(eyre::eyre!("Failed"))
+            }
+        }
+        health_registry.register("readiness_check", FailHealthCheck {});
+        health_registry.register_liveness("liveness_check", SuccessHealthCheck {});
+        tokio::spawn(async move {
+            let config = HttpEndpointConfig {
+                health_registry: Some(health_registry),
+                ..HttpEndpointConfig::new(addr, peer_id, test_versions())
+            };
+            start_http_endpoint(config, notify_sender).await.unwrap();
+        });
+
+        let http_info = notify_receiver.await.unwrap();
+
+        let client = reqwest::Client::new();
+
+        let response = client
+            .get(format!("http://{}/ready", http_info.listen_addr))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body: Value = serde_json::from_slice(&response.bytes().await.unwrap()).unwrap();
+        assert_eq!(body["ready"], json!(false));
+        assert_eq!(body["checks"].as_array().unwrap().len(), 2);
+
+        let response = client
+            .get(format!("http://{}/live", http_info.listen_addr))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: Value = serde_json::from_slice(&response.bytes().await.unwrap()).unwrap();
+        assert_eq!(
+            body,
+            json!({"live": true, "checks": [{"name": "liveness_check", "status": "Ok"}]})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_acme_challenge_route() {
+        // Create a test server
+        let addr = "127.0.0.1:0".parse::<SocketAddr>().unwrap();
+        let peer_id = PeerId::random();
+
+        let (notify_sender, notify_receiver) = oneshot::channel();
+        let challenges = AcmeHttpChallenges::default();
+        challenges.insert("token".to_string(), "token.thumbprint".to_string());
+        tokio::spawn(async move {
+            let config = HttpEndpointConfig {
+                acme_challenges: Some(challenges),
+                ..HttpEndpointConfig::new(addr, peer_id, test_versions())
+            };
+            start_http_endpoint(config, notify_sender).await.unwrap();
+        });
+
+        let http_info = notify_receiver.await.unwrap();
+
+        let client = reqwest::Client::new();
+
+        let response = client
+            .get(format!(
+                "http://{}/.well-known/acme-challenge/token",
+                http_info.listen_addr
+            ))
+            .send()
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = response.bytes().await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(&body[..], b"token.thumbprint");
+
+        let response = client
+            .get(format!(
+                "http://{}/.well-known/acme-challenge/unknown",
+                http_info.listen_addr
+            ))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_toggle() {
+        // Create a test server
+        let addr = "127.0.0.1:0".parse::<SocketAddr>().unwrap();
+        let peer_id = PeerId::random();
+
+        let (notify_sender, notify_receiver) = oneshot::channel();
+        let enabled = Arc::new(AtomicBool::new(true));
+        let metrics = MetricsEndpoint {
+            registry: Registry::default(),
+            enabled: enabled.clone(),
+        };
+        tokio::spawn(async move {
+            let config = HttpEndpointConfig {
+                metrics: Some(metrics),
+                ..HttpEndpointConfig::new(addr, peer_id, test_versions())
+            };
+            start_http_endpoint(config, notify_sender).await.unwrap();
+        });
+
+        let http_info = notify_receiver.await.unwrap();
+
+        let client = reqwest::Client::new();
+        let url = format!("http://{}/metrics", http_info.listen_addr);
+
+        let response = client.get(&url).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        enabled.store(false, Ordering::Relaxed);
+        let response = client.get(&url).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_probe_listener() {
+        let addr = "127.0.0.1:0".parse::<SocketAddr>().unwrap();
+        let peer_id = PeerId::random();
+
+        let (notify_sender, notify_receiver) = oneshot::channel();
+        let metrics = MetricsEndpoint {
+            registry: Registry::default(),
+            enabled: Arc::new(AtomicBool::new(true)),
+        };
+        tokio::spawn(async move {
+            let config = HttpEndpointConfig {
+                metrics: Some(metrics),
+                health_registry: Some(HealthCheckRegistry::new()),
+                auth: admin_auth("secret"),
+                probe_listen_addr: Some(addr),
+                ..HttpEndpointConfig::new(addr, peer_id, test_versions())
+            };
+            start_http_endpoint(config, notify_sender).await.unwrap();
+        });
+
+        let http_info = notify_receiver.await.unwrap();
+        let probe_addr = http_info.probe_listen_addr.unwrap();
+        assert_ne!(probe_addr, http_info.listen_addr);
+
+        let client = reqwest::Client::new();
+        for path in ["/health", "/ready", "/live", "/metrics", "/v1/health"] {
+            let response = client
+                .get(format!("http://{probe_addr}{path}"))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{path}");
+        }
+        for path in ["/versions", "/config", "/debug/pprof/heap"] {
+            let response = client
+                .get(format!("http://{probe_addr}{path}"))
+                .bearer_auth("secret")
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{path}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pprof_requires_admin_token() {
+        let addr = "127.0.0.1:0".parse::<SocketAddr>().unwrap();
+        let peer_id = PeerId::random();
+
+        let (notify_sender, notify_receiver) = oneshot::channel();
+        tokio::spawn(async move {
+            let config = HttpEndpointConfig {
+                auth: admin_auth("secret"),
+                ..HttpEndpointConfig::new(addr, peer_id, test_versions())
+            };
+            start_http_endpoint(config, notify_sender).await.unwrap();
+        });
+
+        let http_info = notify_receiver.await.unwrap();
+
+        let client = reqwest::Client::new();
+        let url = format!("http://{}/debug/pprof/profile", http_info.listen_addr);
+
+        let response = client.get(&url).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = client.get(&url).bearer_auth("wrong").send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = client
+            .get(format!("{url}?seconds=3600"))
+            .bearer_auth("secret")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = client
+            .get(format!("{url}?seconds=1"))
+            .bearer_auth("secret")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!response.bytes().await.unwrap().is_empty());
+
+        // config requires a token even if metrics don't
+        let response = client
+            .get(format!("http://{}/config", http_info.listen_addr))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_pprof_disabled_without_admin_token() {
+        let addr = "127.0.0.1:0".parse::<SocketAddr>().unwrap();
+        let peer_id = PeerId::random();
+
+        let (notify_sender, notify_receiver) = oneshot::channel();
         tokio::spawn(async move {
             start_http_endpoint(
-                addr,
-                None,
-                Some(health_registry),
-                peer_id,
-                test_versions(),
+                HttpEndpointConfig::new(addr, peer_id, test_versions()),
                 notify_sender,
             )
             .await
@@ -397,16 +1161,147 @@ mod tests {
 
         let http_info = notify_receiver.await.unwrap();
 
+        let response = reqwest::Client::new()
+            .get(format!("http://{}/debug/pprof/heap", http_info.listen_addr))
+            .bearer_auth("secret")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_particle_route_disabled_without_client_key() {
+        let addr = "127.0.0.1:0".parse::<SocketAddr>().unwrap();
+        let peer_id = PeerId::random();
+
+        let (notify_sender, notify_receiver) = oneshot::channel();
+        tokio::spawn(async move {
+            let config = HttpEndpointConfig {
+                auth: admin_auth("secret"),
+                ..HttpEndpointConfig::new(addr, peer_id, test_versions())
+            };
+            start_http_endpoint(config, notify_sender).await.unwrap();
+        });
+
+        let http_info = notify_receiver.await.unwrap();
+
         let client = reqwest::Client::new();
+        let url = format!("http://{}/particle", http_info.listen_addr);
+        let request = json!({"script": "(null)"}).to_string();
 
         let response = client
-            .get(format!("http://{}/health", http_info.listen_addr))
+            .post(&url)
+            .header("content-type", "application/json")
+            .body(request.clone())
             .send()
             .await
             .unwrap();
-        let status = response.status();
-        let body = response.bytes().await.unwrap();
-        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
-        assert_eq!(&body[..], (r#"[{"test_check":"Fail"}]"#).as_bytes());
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = client
+            .post(&url)
+            .bearer_auth("secret")
+            .header("content-type", "application/json")
+            .body(request)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_events_route_requires_websocket() {
+        let addr = "127.0.0.1:0".parse::<SocketAddr>().unwrap();
+        let peer_id = PeerId::random();
+
+        let (notify_sender, notify_receiver) = oneshot::channel();
+        tokio::spawn(async move {
+            let config = HttpEndpointConfig {
+                audit_log: Some(AuditLog::default()),
+                auth: admin_auth("secret"),
+                ..HttpEndpointConfig::new(addr, peer_id, test_versions())
+            };
+            start_http_endpoint(config, notify_sender).await.unwrap();
+        });
+
+        let http_info = notify_receiver.await.unwrap();
+
+        let client = reqwest::Client::new();
+        let url = format!("http://{}/events", http_info.listen_addr);
+
+        let response = client.get(&url).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = client
+            .get(format!("{url}?kinds=unknown"))
+            .bearer_auth("secret")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UPGRADE_REQUIRED);
+    }
+
+    #[tokio::test]
+    async fn test_token_roles() {
+        let addr = "127.0.0.1:0".parse::<SocketAddr>().unwrap();
+        let peer_id = PeerId::random();
+
+        let tokens = ApiTokens::new(None);
+        let read_only = tokens.issue("grafana".to_string(), Role::ReadOnly).unwrap();
+        let metrics = MetricsEndpoint {
+            registry: Registry::default(),
+            enabled: Arc::new(AtomicBool::new(true)),
+        };
+        let auth = HttpAuth {
+            tokens,
+            protect_metrics: true,
+            tls: None,
+        };
+
+        let (notify_sender, notify_receiver) = oneshot::channel();
+        tokio::spawn(async move {
+            let config = HttpEndpointConfig {
+                metrics: Some(metrics),
+                auth,
+                ..HttpEndpointConfig::new(addr, peer_id, test_versions())
+            };
+            start_http_endpoint(config, notify_sender).await.unwrap();
+        });
+
+        let http_info = notify_receiver.await.unwrap();
+
+        let client = reqwest::Client::new();
+        let metrics_url = format!("http://{}/metrics", http_info.listen_addr);
+
+        let response = client.get(&metrics_url).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = client
+            .get(&metrics_url)
+            .bearer_auth(&read_only.token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = client
+            .get(format!("http://{}/debug/pprof/heap", http_info.listen_addr))
+            .bearer_auth(&read_only.token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        // graphql schema isn't configured
+        let response = client
+            .post(format!("http://{}/graphql", http_info.listen_addr))
+            .bearer_auth(&read_only.token)
+            .header("content-type", "application/json")
+            .body(r#"{"query":"{ peerId }"}"#)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
 }
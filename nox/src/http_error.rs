@@ -0,0 +1,206 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use axum::extract::Request;
+use axum::http::header::{ACCEPT, CONTENT_TYPE};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+
+/// Version of the http API served under `/v1`
+pub const API_VERSION: u32 = 1;
+
+/// Stable error codes, new ones may be added but existing ones don't change between node versions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    NotFound,
+    Unauthorized,
+    Forbidden,
+    BadRequest,
+    NotAcceptable,
+    Conflict,
+    UpgradeRequired,
+    Timeout,
+    Unavailable,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::Forbidden => StatusCode::FORBIDDEN,
+            ErrorCode::BadRequest => StatusCode::BAD_REQUEST,
+            ErrorCode::NotAcceptable => StatusCode::NOT_ACCEPTABLE,
+            ErrorCode::Conflict => StatusCode::CONFLICT,
+            ErrorCode::UpgradeRequired => StatusCode::UPGRADE_REQUIRED,
+            ErrorCode::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            ErrorCode::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Whether the same request may succeed later
+    pub fn retryable(self) -> bool {
+        matches!(
+            self,
+            ErrorCode::Conflict | ErrorCode::Timeout | ErrorCode::Unavailable
+        )
+    }
+}
+
+/// Error response of the http API: `{"code": "not_found", "message": "...", "retryable": false}`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ApiError {
+    pub code: ErrorCode,
+    pub message: String,
+    pub retryable: bool,
+}
+
+pub type ApiResult<T> = Result<T, ApiError>;
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: impl ToString) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+            retryable: code.retryable(),
+        }
+    }
+
+    pub fn not_found() -> Self {
+        Self::new(ErrorCode::NotFound, "No such endpoint")
+    }
+
+    pub fn internal(message: impl ToString) -> Self {
+        Self::new(ErrorCode::Internal, message)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let mut response = (self.code.status(), Json(&self)).into_response();
+        // kept for content negotiation, see [negotiate]
+        response.extensions_mut().insert(self);
+        response
+    }
+}
+
+/// Formats requested via the `Accept` header
+#[derive(Debug, PartialEq, Eq)]
+enum Accepted {
+    Json,
+    Text,
+    /// `application/vnd.nox.v<N>+json` of an unsupported version
+    Version(u32),
+}
+
+fn accepted(headers: &HeaderMap) -> Accepted {
+    let Some(accept) = headers.get(ACCEPT).and_then(|v| v.to_str().ok()) else {
+        return Accepted::Json;
+    };
+    let media_types: Vec<&str> = accept
+        .split(',')
+        .filter_map(|t| t.split(';').next())
+        .map(str::trim)
+        .collect();
+
+    for media_type in &media_types {
+        let version = media_type
+            .strip_prefix("application/vnd.nox.v")
+            .and_then(|t| t.strip_suffix("+json"))
+            .and_then(|v| v.parse().ok());
+        match version {
+            Some(API_VERSION) => return Accepted::Json,
+            Some(version) => return Accepted::Version(version),
+            None => {}
+        }
+    }
+
+    let json = media_types
+        .iter()
+        .any(|t| matches!(*t, "application/json" | "application/*" | "*/*"));
+    let text = media_types
+        .iter()
+        .any(|t| matches!(*t, "text/plain" | "text/*"));
+    if text && !json {
+        Accepted::Text
+    } else {
+        Accepted::Json
+    }
+}
+
+/// Rejects requests for unsupported API versions and renders errors as plain text
+/// for clients that don't accept JSON
+pub async fn negotiate(request: Request, next: Next) -> Response {
+    let accepted = accepted(request.headers());
+    if let Accepted::Version(version) = accepted {
+        return ApiError::new(
+            ErrorCode::NotAcceptable,
+            format!("API version {version} isn't supported, the latest one is {API_VERSION}"),
+        )
+        .into_response();
+    }
+
+    let response = next.run(request).await;
+    match response.extensions().get::<ApiError>() {
+        Some(error) if accepted == Accepted::Text => {
+            let message = error.message.clone();
+            let (mut parts, _) = response.into_parts();
+            parts.headers.insert(
+                CONTENT_TYPE,
+                HeaderValue::from_static("text/plain; charset=utf-8"),
+            );
+            parts.headers.remove(axum::http::header::CONTENT_LENGTH);
+            Response::from_parts(parts, message.into())
+        }
+        _ => response,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accept(value: &str) -> Accepted {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, value.parse().unwrap());
+        accepted(&headers)
+    }
+
+    #[test]
+    fn negotiation() {
+        assert_eq!(accepted(&HeaderMap::new()), Accepted::Json);
+        assert_eq!(accept("text/plain"), Accepted::Text);
+        assert_eq!(accept("text/plain, */*;q=0.1"), Accepted::Json);
+        assert_eq!(accept("application/vnd.nox.v1+json"), Accepted::Json);
+        assert_eq!(accept("application/vnd.nox.v2+json"), Accepted::Version(2));
+    }
+
+    #[test]
+    fn envelope() {
+        let error = ApiError::new(ErrorCode::Timeout, "Particle expired");
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            serde_json::json!({"code": "timeout", "message": "Particle expired", "retryable": true})
+        );
+        assert!(!ApiError::not_found().retryable);
+    }
+}
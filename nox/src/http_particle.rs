@@ -0,0 +1,177 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use aquamarine::AquamarineApi;
+use fluence_keypair::KeyPair;
+use futures::FutureExt;
+use libp2p::PeerId;
+use now_millis::now_ms;
+use parking_lot::Mutex;
+use particle_args::JError;
+use particle_execution::{FunctionOutcome, ServiceFunction};
+use particle_protocol::{ExtendedParticle, Particle};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JValue};
+use tokio::sync::oneshot;
+use tracing::Span;
+use uuid_utils::uuid;
+
+pub const DEFAULT_TTL: Duration = Duration::from_secs(60);
+/// Longer TTLs are rejected, so a waiting request doesn't hang for too long
+pub const MAX_TTL: Duration = Duration::from_secs(600);
+
+fn default_ttl() -> u32 {
+    DEFAULT_TTL.as_millis() as u32
+}
+
+/// Particle submitted to the http endpoint
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ParticleRequest {
+    pub script: String,
+    /// Values returned by `getDataSrv` calls on this node, by function name
+    #[serde(default)]
+    pub data: HashMap<String, JValue>,
+    /// TTL in milliseconds
+    #[serde(default = "default_ttl")]
+    pub ttl: u32,
+    /// Whether to wait until the script calls `callbackSrv.response` on this node
+    #[serde(default)]
+    pub wait: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ParticleResponse {
+    pub particle_id: String,
+    /// Arguments of the `callbackSrv.response` call if the request waited for it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Vec<JValue>>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SubmitError {
+    #[error("ttl must be between 1 and {} ms, got {0}", MAX_TTL.as_millis())]
+    InvalidTtl(u32),
+    #[error("failed to sign particle: {0}")]
+    Signing(String),
+    #[error("failed to execute particle: {0}")]
+    Execution(String),
+    #[error("particle {0} expired before calling callbackSrv.response")]
+    Expired(String),
+}
+
+/// Injects particles into the local aquamarine as if they were sent by the client with `key_pair`
+#[derive(Clone)]
+pub struct HttpParticles {
+    aquamarine_api: AquamarineApi,
+    key_pair: KeyPair,
+    host_peer_id: PeerId,
+}
+
+impl HttpParticles {
+    pub fn new(aquamarine_api: AquamarineApi, key_pair: KeyPair, host_peer_id: PeerId) -> Self {
+        Self {
+            aquamarine_api,
+            key_pair,
+            host_peer_id,
+        }
+    }
+
+    pub async fn submit(&self, request: ParticleRequest) -> Result<ParticleResponse, SubmitError> {
+        let ttl = Duration::from_millis(request.ttl as u64);
+        if ttl.is_zero() || ttl > MAX_TTL {
+            return Err(SubmitError::InvalidTtl(request.ttl));
+        }
+
+        let mut particle = Particle {
+            id: uuid(),
+            init_peer_id: self.key_pair.get_peer_id(),
+            timestamp: now_ms() as u64,
+            ttl: request.ttl,
+            script: request.script,
+            signature: vec![],
+            data: vec![],
+        };
+        particle
+            .sign(&self.key_pair)
+            .map_err(|err| SubmitError::Signing(err.to_string()))?;
+        let particle_id = particle.id.clone();
+        tracing::info!(particle_id, "Submitting particle received via http");
+
+        let (outlet, inlet) = oneshot::channel();
+        let function = self.particle_function(request.data, outlet);
+        self.aquamarine_api
+            .clone()
+            .execute(
+                ExtendedParticle::new(particle, Span::current()),
+                Some(function),
+            )
+            .await
+            .map_err(|err| SubmitError::Execution(err.to_string()))?;
+
+        if !request.wait {
+            return Ok(ParticleResponse {
+                particle_id,
+                result: None,
+            });
+        }
+        match tokio::time::timeout(ttl, inlet).await {
+            Ok(Ok(result)) => Ok(ParticleResponse {
+                particle_id,
+                result: Some(result),
+            }),
+            _ => Err(SubmitError::Expired(particle_id)),
+        }
+    }
+
+    /// Serves calls on this node that builtins don't handle:
+    /// `getDataSrv` returns request data and `-relay-` as the host peer id,
+    /// `callbackSrv.response` passes its arguments to `outlet`
+    fn particle_function(
+        &self,
+        data: HashMap<String, JValue>,
+        outlet: oneshot::Sender<Vec<JValue>>,
+    ) -> ServiceFunction {
+        let host_peer_id = self.host_peer_id;
+        let outlet = Arc::new(Mutex::new(Some(outlet)));
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let service = (args.service_id.as_str(), args.function_name.as_str());
+            let outcome = match service {
+                ("getDataSrv", "-relay-") => {
+                    Some(FunctionOutcome::Ok(json!(host_peer_id.to_string())))
+                }
+                ("getDataSrv", name) => Some(match data.get(name) {
+                    Some(value) => FunctionOutcome::Ok(value.clone()),
+                    None => FunctionOutcome::Err(JError::new(format!(
+                        "request data has no '{name}' field"
+                    ))),
+                }),
+                ("callbackSrv", "response") => {
+                    if let Some(outlet) = outlet.lock().take() {
+                        outlet.send(args.function_args.clone()).ok();
+                    }
+                    Some(FunctionOutcome::Empty)
+                }
+                _ => None,
+            };
+            let outcome = outcome.unwrap_or(FunctionOutcome::NotDefined { args, params });
+            async move { outcome }.boxed()
+        }))
+    }
+}
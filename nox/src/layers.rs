@@ -1,26 +1,37 @@
 use libp2p::PeerId;
 use opentelemetry::trace::TracerProvider;
 use opentelemetry::{global, KeyValue};
-use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_otlp::{SpanExporterBuilder, WithExportConfig};
 use opentelemetry_sdk::propagation::TraceContextPropagator;
 use opentelemetry_sdk::trace::Sampler;
 use opentelemetry_sdk::Resource;
-use server_config::TracingConfig;
+use server_config::{LogFileConfig, OtlpProtocol, TracingConfig};
 use std::str::FromStr;
+use std::sync::Mutex;
 use tracing::level_filters::LevelFilter;
 use tracing::Subscriber;
 use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::EnvFilter;
 use tracing_subscriber::Layer;
 
+use crate::log_file::{RotatingFile, RotationTrigger};
+
 pub fn env_filter<S>() -> impl Layer<S>
 where
     S: Subscriber + for<'span> LookupSpan<'span>,
 {
-    let rust_log = std::env::var("RUST_LOG")
-        .unwrap_or_default()
-        .replace(char::is_whitespace, "");
+    log_filter(None)
+}
+
+/// Same as [env_filter], with `directives` in `RUST_LOG` format applied on top of `RUST_LOG`
+pub fn log_filter(directives: Option<&str>) -> EnvFilter {
+    let mut rust_log = std::env::var("RUST_LOG").unwrap_or_default();
+    if let Some(directives) = directives {
+        rust_log = format!("{rust_log},{directives}");
+    }
+    let rust_log = rust_log.replace(char::is_whitespace, "");
 
-    tracing_subscriber::EnvFilter::builder()
+    EnvFilter::builder()
         .with_default_directive(LevelFilter::INFO.into())
         .parse_lossy(rust_log)
         .add_directive("cranelift_codegen=off".parse().unwrap())
@@ -39,15 +50,17 @@ where
         .add_directive("avm_server::runner=error".parse().unwrap())
 }
 
+fn log_format() -> LogFormat {
+    let log_format = std::env::var("FLUENCE_LOG_FORMAT").unwrap_or_default();
+
+    LogFormat::from_str(log_format.as_str()).unwrap_or(LogFormat::Default)
+}
+
 pub fn log_layer<S>() -> impl Layer<S>
 where
     S: Subscriber + for<'span> LookupSpan<'span>,
 {
-    let log_format = std::env::var("FLUENCE_LOG_FORMAT").unwrap_or_default();
-
-    let log_format = LogFormat::from_str(log_format.as_str()).unwrap_or(LogFormat::Default);
-
-    match log_format {
+    match log_format() {
         LogFormat::Logfmt => tracing_logfmt::builder()
             .with_target(true)
             .with_span_path(false)
@@ -61,6 +74,32 @@ where
     }
 }
 
+/// Writes logs in the same format as [log_layer] to a rotating file.
+/// The returned trigger rotates the file on demand.
+pub fn log_file_layer<S>(config: LogFileConfig) -> eyre::Result<(impl Layer<S>, RotationTrigger)>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let trigger = RotationTrigger::default();
+    let writer = Mutex::new(RotatingFile::open_with_trigger(config, trigger.clone())?);
+
+    let layer = match log_format() {
+        LogFormat::Logfmt => tracing_logfmt::builder()
+            .with_target(true)
+            .with_span_path(false)
+            .with_span_name(false)
+            .layer_with_writer(writer)
+            .boxed(),
+        LogFormat::Default => tracing_subscriber::fmt::layer()
+            .with_ansi(false)
+            .with_thread_ids(true)
+            .with_thread_names(true)
+            .with_writer(writer)
+            .boxed(),
+    };
+    Ok((layer, trigger))
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum LogFormat {
     Logfmt,
@@ -104,12 +143,21 @@ where
         TracingConfig::Otlp {
             endpoint,
             sample_ratio,
+            protocol,
+            resource_attributes,
         } => {
-            let resource = Resource::new(vec![
-                KeyValue::new("service.name", "rust-peer"),
-                KeyValue::new("service.version", version.to_string()),
-                KeyValue::new("peer_id", peer_id.to_base58()),
-            ]);
+            let attributes = resource_attributes
+                .iter()
+                .map(|(key, value)| KeyValue::new(key.clone(), value.clone()));
+            let resource = Resource::new(
+                [
+                    KeyValue::new("service.name", "rust-peer"),
+                    KeyValue::new("service.version", version.to_string()),
+                    KeyValue::new("peer_id", peer_id.to_base58()),
+                ]
+                .into_iter()
+                .chain(attributes),
+            );
 
             let mut config = opentelemetry_sdk::trace::config().with_resource(resource);
 
@@ -119,13 +167,20 @@ where
                 )));
             }
 
+            let exporter: SpanExporterBuilder = match protocol {
+                OtlpProtocol::Grpc => opentelemetry_otlp::new_exporter()
+                    .tonic()
+                    .with_endpoint(endpoint.as_str())
+                    .into(),
+                OtlpProtocol::Http => opentelemetry_otlp::new_exporter()
+                    .http()
+                    .with_endpoint(endpoint.as_str())
+                    .into(),
+            };
+
             let tracer = opentelemetry_otlp::new_pipeline()
                 .tracing()
-                .with_exporter(
-                    opentelemetry_otlp::new_exporter()
-                        .tonic()
-                        .with_endpoint(endpoint.as_str()),
-                )
+                .with_exporter(exporter)
                 .with_trace_config(config)
                 .install_batch(opentelemetry_sdk::runtime::Tokio)?;
 
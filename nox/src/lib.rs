@@ -29,26 +29,55 @@
     unreachable_patterns
 )]
 
+mod acme;
+mod admin_tls;
+mod api_tokens;
 mod builtins;
+mod config_reload;
 mod connectivity;
+mod control;
 mod dispatcher;
+mod dns;
 mod effectors;
+mod events;
+mod graphql;
+mod grpc;
 mod health;
+mod host_key;
 mod http;
+mod http_error;
+mod http_particle;
 mod layers;
+mod lifecycle;
+mod log_control;
+mod log_file;
 mod metrics;
+mod metrics_endpoint;
 mod node;
+mod profiling;
+mod seen_particles;
+mod supervisor;
 mod tasks;
+mod trust_graph;
 
 mod behaviour {
     mod identify;
+    mod nat;
     mod network;
+    mod peer_exchange;
 
+    pub use nat::{
+        confirm_ipv6_candidate, log_dcutr_event, log_relay_client_event, PortMappings,
+        RelayListeners,
+    };
     pub use network::{FluenceNetworkBehaviour, FluenceNetworkBehaviourEvent};
 }
 
 pub use behaviour::{FluenceNetworkBehaviour, FluenceNetworkBehaviourEvent};
+pub use config_reload::ConfigReloader;
 pub use http::StartedHttp;
+pub use log_control::{LogControl, LogFilterReload};
+pub use log_file::RotationTrigger;
 pub use node::Node;
 
 // to be available in benchmarks
@@ -56,6 +85,8 @@ pub use connection_pool::Command as ConnectionPoolCommand;
 pub use connectivity::Connectivity;
 pub use kademlia::Command as KademliaCommand;
 pub use layers::env_filter;
+pub use layers::log_file_layer;
+pub use layers::log_filter;
 pub use layers::log_layer;
 pub use layers::tracing_layer;
 
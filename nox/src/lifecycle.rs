@@ -0,0 +1,141 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use itertools::Itertools;
+use parking_lot::Mutex;
+use peer_metrics::{LifecycleMetrics, LifecycleStage};
+
+/// Durations of node startup or shutdown phases. Phases that don't depend on each other run
+/// concurrently, so their durations may add up to more than the total time of the stage.
+#[derive(Clone)]
+pub struct LifecycleTimings {
+    stage: LifecycleStage,
+    started_at: Instant,
+    phases: Arc<Mutex<Vec<(&'static str, Duration)>>>,
+    metrics: Option<LifecycleMetrics>,
+}
+
+impl LifecycleTimings {
+    pub fn startup() -> Self {
+        Self::new(LifecycleStage::Startup)
+    }
+
+    pub fn shutdown() -> Self {
+        Self::new(LifecycleStage::Shutdown)
+    }
+
+    fn new(stage: LifecycleStage) -> Self {
+        Self {
+            stage,
+            started_at: Instant::now(),
+            phases: <_>::default(),
+            metrics: None,
+        }
+    }
+
+    /// Reports phases to `metrics`, including the ones already finished
+    pub fn with_metrics(self, metrics: Option<LifecycleMetrics>) -> Self {
+        if let Some(metrics) = &metrics {
+            for (name, elapsed) in self.phases() {
+                metrics.phase_finished(self.stage, name, elapsed);
+            }
+        }
+        Self { metrics, ..self }
+    }
+
+    /// Runs `phase` and records how long it took
+    pub async fn measure<F: Future>(&self, name: &'static str, phase: F) -> F::Output {
+        let start = Instant::now();
+        let result = phase.await;
+        let elapsed = start.elapsed();
+        log::info!("{:?} phase {name} finished in {elapsed:?}", self.stage);
+        self.phases.lock().push((name, elapsed));
+        if let Some(metrics) = &self.metrics {
+            metrics.phase_finished(self.stage, name, elapsed);
+        }
+        result
+    }
+
+    pub fn phases(&self) -> Vec<(&'static str, Duration)> {
+        self.phases.lock().clone()
+    }
+
+    /// Logs total time of the stage and durations of all phases finished so far
+    pub fn report(&self) {
+        let elapsed = self.started_at.elapsed();
+        let phases = self
+            .phases()
+            .into_iter()
+            .map(|(name, elapsed)| format!("{name} {elapsed:?}"))
+            .join(", ");
+        match self.stage {
+            LifecycleStage::Startup => log::info!("Node started in {elapsed:?}: {phases}"),
+            LifecycleStage::Shutdown => log::info!("Node stopped in {elapsed:?}: {phases}"),
+        }
+        if let Some(metrics) = &self.metrics {
+            metrics.finished(self.stage, elapsed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prometheus_client::encoding::text::encode;
+    use prometheus_client::registry::Registry;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn concurrent_phases() {
+        let timings = LifecycleTimings::startup();
+        let sleep = |ms| tokio::time::sleep(Duration::from_millis(ms));
+        let (a, ()) = tokio::join!(
+            timings.measure("a", async {
+                sleep(50).await;
+                1
+            }),
+            timings.measure("b", sleep(10)),
+        );
+        assert_eq!(a, 1);
+
+        let phases = timings.phases();
+        assert_eq!(
+            phases.iter().map(|(name, _)| *name).collect::<Vec<_>>(),
+            vec!["b", "a"]
+        );
+        assert!(phases[1].1 >= Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn metrics_include_earlier_phases() {
+        let mut registry = Registry::default();
+        let timings = LifecycleTimings::startup();
+        timings.measure("a", async {}).await;
+        let timings = timings.with_metrics(Some(LifecycleMetrics::new(&mut registry)));
+        timings.measure("b", async {}).await;
+        timings.report();
+
+        let mut metrics = String::new();
+        encode(&mut metrics, &registry).unwrap();
+        assert!(metrics.contains(r#"lifecycle_phase_duration_seconds{stage="Startup",phase="a"}"#));
+        assert!(metrics.contains(r#"lifecycle_phase_duration_seconds{stage="Startup",phase="b"}"#));
+        assert!(metrics.contains(r#"lifecycle_duration_seconds{stage="Startup"}"#));
+    }
+}
@@ -0,0 +1,180 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use eyre::WrapErr;
+use parking_lot::Mutex;
+use tracing_subscriber::filter::Directive;
+
+use crate::log_file::RotationTrigger;
+
+/// Replaces the log filter with the given directives on top of `RUST_LOG`
+pub type LogFilterReload = Box<dyn Fn(Option<&str>) -> eyre::Result<()> + Send + Sync>;
+
+struct LogControlState {
+    reload: LogFilterReload,
+    /// Directives from the `log_filter` config field
+    configured: Option<String>,
+    /// Directives set at runtime on top of the configured ones, reverted after a while
+    temporary: Option<String>,
+    /// Incremented on every temporary change, so a stale revert doesn't undo a newer change
+    generation: u64,
+    /// `None` if logs aren't written to a file
+    file_rotation: Option<RotationTrigger>,
+}
+
+/// Changes log filter at runtime, without restarting the node
+#[derive(Clone, Default)]
+pub struct LogControl {
+    state: Arc<Mutex<Option<LogControlState>>>,
+}
+
+impl LogControl {
+    /// Allows changing the log filter. `configured` are the directives applied on startup.
+    pub fn enable(
+        &self,
+        reload: LogFilterReload,
+        configured: Option<String>,
+        file_rotation: Option<RotationTrigger>,
+    ) {
+        *self.state.lock() = Some(LogControlState {
+            reload,
+            configured,
+            temporary: None,
+            generation: 0,
+            file_rotation,
+        });
+    }
+
+    /// Rotates the log file on the next write
+    pub fn rotate_file(&self) -> eyre::Result<()> {
+        let state = self.state.lock();
+        let state = state.as_ref().ok_or_else(not_enabled)?;
+        let trigger = state
+            .file_rotation
+            .as_ref()
+            .ok_or_else(|| eyre::eyre!("logs aren't written to a file on this node"))?;
+        trigger.rotate();
+        Ok(())
+    }
+
+    /// Replaces configured directives, temporary ones stay on top
+    pub fn set_configured(&self, directives: Option<String>) -> eyre::Result<()> {
+        let mut state = self.state.lock();
+        let state = state.as_mut().ok_or_else(not_enabled)?;
+        state.configured = directives;
+        state.apply()
+    }
+
+    /// Applies `directives` on top of the configured ones until `revert_after` passes
+    pub fn set_temporary(&self, directives: String, revert_after: Duration) -> eyre::Result<()> {
+        for directive in directives.split(',').filter(|d| !d.trim().is_empty()) {
+            Directive::from_str(directive.trim())
+                .wrap_err_with(|| format!("invalid log directive {directive:?}"))?;
+        }
+
+        let generation = {
+            let mut state = self.state.lock();
+            let state = state.as_mut().ok_or_else(not_enabled)?;
+            state.temporary = Some(directives);
+            state.generation += 1;
+            state.apply()?;
+            state.generation
+        };
+
+        let this = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(revert_after).await;
+            if let Err(err) = this.revert(Some(generation)) {
+                tracing::warn!("Failed to revert log filter: {err:?}");
+            }
+        });
+        Ok(())
+    }
+
+    /// Reverts temporary directives now
+    pub fn reset(&self) -> eyre::Result<()> {
+        self.revert(None)
+    }
+
+    fn revert(&self, generation: Option<u64>) -> eyre::Result<()> {
+        let mut state = self.state.lock();
+        let state = state.as_mut().ok_or_else(not_enabled)?;
+        if generation.is_some_and(|g| g != state.generation) || state.temporary.is_none() {
+            return Ok(());
+        }
+        state.temporary = None;
+        state.generation += 1;
+        tracing::info!("Log filter reverted to {:?}", state.configured);
+        state.apply()
+    }
+}
+
+impl LogControlState {
+    fn apply(&self) -> eyre::Result<()> {
+        let directives = match (&self.configured, &self.temporary) {
+            (Some(configured), Some(temporary)) => Some(format!("{configured},{temporary}")),
+            (configured, temporary) => configured.clone().or(temporary.clone()),
+        };
+        (self.reload)(directives.as_deref())
+    }
+}
+
+fn not_enabled() -> eyre::Report {
+    eyre::eyre!("log filter can't be changed on this node")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn temporary_filter_is_reverted() {
+        let applied = Arc::new(Mutex::new(vec![]));
+        let control = LogControl::default();
+        let log = applied.clone();
+        control.enable(
+            Box::new(move |d| {
+                log.lock().push(d.map(String::from));
+                Ok(())
+            }),
+            Some("aquamarine=warn".to_string()),
+            None,
+        );
+
+        assert!(control
+            .set_temporary("chain_listener=verbose".to_string(), Duration::ZERO)
+            .is_err());
+        control
+            .set_temporary(
+                "chain_listener=trace".to_string(),
+                Duration::from_millis(10),
+            )
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(
+            *applied.lock(),
+            vec![
+                Some("aquamarine=warn,chain_listener=trace".to_string()),
+                Some("aquamarine=warn".to_string()),
+            ]
+        );
+    }
+}
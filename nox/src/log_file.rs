@@ -0,0 +1,287 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use server_config::LogFileConfig;
+
+/// Requests rotation of a [RotatingFile] regardless of its size and period
+#[derive(Clone, Default)]
+pub struct RotationTrigger {
+    requested: Arc<AtomicBool>,
+}
+
+impl RotationTrigger {
+    /// The file is rotated on the next write
+    pub fn rotate(&self) {
+        self.requested.store(true, Ordering::Relaxed);
+    }
+
+    fn take(&self) -> bool {
+        self.requested.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// Log file that rotates itself according to [LogFileConfig]
+pub struct RotatingFile {
+    config: LogFileConfig,
+    file: File,
+    size: u64,
+    /// Rotation period the file was opened in
+    period: Option<u64>,
+    trigger: RotationTrigger,
+}
+
+impl RotatingFile {
+    pub fn open(config: LogFileConfig) -> io::Result<Self> {
+        Self::open_with_trigger(config, RotationTrigger::default())
+    }
+
+    pub fn open_with_trigger(config: LogFileConfig, trigger: RotationTrigger) -> io::Result<Self> {
+        if let Some(dir) = config.path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&config.path)?;
+        let size = file.metadata()?.len();
+        let period = current_period(&config);
+        Ok(Self {
+            config,
+            file,
+            size,
+            period,
+            trigger,
+        })
+    }
+
+    fn should_rotate(&self, incoming: usize) -> bool {
+        let oversized = self
+            .config
+            .max_size
+            .is_some_and(|max| self.size > 0 && self.size + incoming as u64 > max.as_u64());
+        // the request is taken first, so it doesn't cause another rotation on the next write
+        self.trigger.take() || oversized || current_period(&self.config) != self.period
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let rotated = suffixed(&self.config.path, &millis.to_string());
+        fs::rename(&self.config.path, &rotated)?;
+
+        *self = Self::open_with_trigger(self.config.clone(), self.trigger.clone())?;
+
+        let config = self.config.clone();
+        if config.compress {
+            // compression may take a while, so it doesn't block logging
+            std::thread::spawn(move || {
+                if let Err(err) = compress(&rotated) {
+                    eprintln!("Failed to compress log file {}: {err}", rotated.display());
+                }
+                prune(&config);
+            });
+        } else {
+            prune(&config);
+        }
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.should_rotate(buf.len()) {
+            if let Err(err) = self.rotate() {
+                // keep writing to the current file
+                eprintln!(
+                    "Failed to rotate log file {}: {err}",
+                    self.config.path.display()
+                );
+                self.period = current_period(&self.config);
+            }
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn current_period(config: &LogFileConfig) -> Option<u64> {
+    let period_secs = config.rotation.period_secs()?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    Some(now / period_secs)
+}
+
+fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+fn compress(path: &Path) -> io::Result<()> {
+    let mut input = File::open(path)?;
+    let output = File::create(suffixed(path, "gz"))?;
+    let mut encoder = GzEncoder::new(output, Compression::default());
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?;
+    fs::remove_file(path)
+}
+
+/// Removes the oldest rotated files beyond `max_files`
+fn prune(config: &LogFileConfig) {
+    let Some(max_files) = config.max_files else {
+        return;
+    };
+    let (Some(dir), Some(name)) = (config.path.parent(), config.path.file_name()) else {
+        return;
+    };
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let prefix = format!("{}.", name.to_string_lossy());
+
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    // rotated files are ordered by their timestamp suffix
+    let mut rotated: Vec<(u128, PathBuf)> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let suffix = file_name.strip_prefix(&prefix)?;
+            let millis = suffix.trim_end_matches(".gz").parse().ok()?;
+            Some((millis, entry.path()))
+        })
+        .collect();
+    rotated.sort();
+
+    let excess = rotated.len().saturating_sub(max_files);
+    for (_, path) in rotated.into_iter().take(excess) {
+        if let Err(err) = fs::remove_file(&path) {
+            eprintln!("Failed to remove old log file {}: {err}", path.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytesize::ByteSize;
+    use server_config::LogRotation;
+
+    use super::*;
+
+    fn config(dir: &Path, compress: bool) -> LogFileConfig {
+        LogFileConfig {
+            path: dir.join("nox.log"),
+            max_size: Some(ByteSize::b(10)),
+            rotation: LogRotation::Never,
+            max_files: Some(2),
+            compress,
+        }
+    }
+
+    fn rotated_files(dir: &Path) -> Vec<String> {
+        let mut files: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|name| name != "nox.log")
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn rotate_by_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut file = RotatingFile::open(config(dir.path(), false)).unwrap();
+
+        for _ in 0..4 {
+            file.write_all(b"0123456789").unwrap();
+            // rotated files are named by millis
+            std::thread::sleep(Duration::from_millis(2));
+        }
+
+        // 3 files were rotated, the oldest one is removed
+        assert_eq!(rotated_files(dir.path()).len(), 2);
+        let current = fs::read_to_string(dir.path().join("nox.log")).unwrap();
+        assert_eq!(current, "0123456789");
+    }
+
+    #[test]
+    fn compress_rotated() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut file = RotatingFile::open(config(dir.path(), true)).unwrap();
+
+        file.write_all(b"0123456789").unwrap();
+        file.write_all(b"0123456789").unwrap();
+
+        // compression happens in the background
+        let mut files = vec![];
+        for _ in 0..100 {
+            files = rotated_files(dir.path());
+            if files.iter().all(|f| f.ends_with(".gz")) {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(files.len(), 1);
+        assert!(files[0].ends_with(".gz"));
+    }
+
+    #[test]
+    fn rotate_on_request() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = LogFileConfig {
+            max_size: None,
+            ..config(dir.path(), false)
+        };
+        let trigger = RotationTrigger::default();
+        let mut file = RotatingFile::open_with_trigger(config, trigger.clone()).unwrap();
+
+        file.write_all(b"0123456789").unwrap();
+        file.write_all(b"0123456789").unwrap();
+        assert!(rotated_files(dir.path()).is_empty());
+
+        trigger.rotate();
+        file.write_all(b"abc").unwrap();
+        assert_eq!(rotated_files(dir.path()).len(), 1);
+        let current = fs::read_to_string(dir.path().join("nox.log")).unwrap();
+        assert_eq!(current, "abc");
+    }
+}
@@ -31,18 +31,25 @@ use eyre::WrapErr;
 use libp2p::PeerId;
 use std::sync::Arc;
 use tokio::signal;
+use tokio::signal::unix::{Signal, SignalKind};
 use tokio::sync::oneshot;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
 use air_interpreter_fs::write_default_air_interpreter;
-use aquamarine::{DataStoreConfig, VmConfig};
+use aquamarine::{DataRetention, DataStoreConfig, RetentionPolicy, VmConfig};
 use avm_server::avm_runner::AVMRunner;
 use config_utils::to_peer_id;
 use core_manager::manager::{CoreManager, CoreManagerFunctions, PersistentCoreManager};
 use fs_utils::to_abs_path;
-use nox::{env_filter, log_layer, tracing_layer, Node};
-use server_config::{load_config, ConfigData, ResolvedConfig};
+use nox::{
+    log_file_layer, log_filter, log_layer, tracing_layer, ConfigReloader, LogFilterReload, Node,
+    RotationTrigger,
+};
+use server_config::{
+    explain_config, load_config, validate_config, ConfigCommand, ConfigData, ResolvedConfig,
+    RetentionPolicyConfig, UnresolvedConfig,
+};
 use tracing_panic::panic_hook;
 use tracing_subscriber::reload;
 use tracing_subscriber::Layer;
@@ -60,6 +67,17 @@ trait Stoppable {
 #[global_allocator]
 static ALLOC: dhat::Alloc = dhat::Alloc;
 
+#[cfg(not(feature = "dhat-heap"))]
+#[global_allocator]
+static ALLOC: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+
+/// Heap profiling is compiled in, but samples allocations (every 512KiB on average)
+/// only while a heap profile is requested, see `profiling::heap_profile`
+#[cfg(not(feature = "dhat-heap"))]
+#[allow(non_upper_case_globals)]
+#[export_name = "malloc_conf"]
+pub static malloc_conf: &[u8] = b"prof:true,prof_active:false,lg_prof_sample:19\0";
+
 fn main() -> eyre::Result<()> {
     #[cfg(feature = "dhat-heap")]
     let _profiler = dhat::Profiler::new_heap();
@@ -70,11 +88,15 @@ fn main() -> eyre::Result<()> {
         prev_hook(panic_info);
     }));
 
+    let (reloadable_filter, filter_handle) = reload::Layer::new(log_filter(None));
     let (reloadable_tracing_layer, reload_handle) = reload::Layer::new(None);
+    // log file is set up once the config is loaded
+    let (reloadable_log_file_layer, log_file_handle) = reload::Layer::new(None);
 
     tracing_subscriber::registry()
-        .with(env_filter())
+        .with(reloadable_filter)
         .with(log_layer())
+        .with(reloadable_log_file_layer)
         .with(reloadable_tracing_layer)
         .init();
 
@@ -87,7 +109,33 @@ fn main() -> eyre::Result<()> {
         description: DESCRIPTION.to_string(),
     };
 
-    let config = load_config(Some(config_data))?;
+    let config = load_config(Some(config_data.clone()))?;
+
+    match config.command {
+        Some(ConfigCommand::Validate) => return validate_config_command(config),
+        Some(ConfigCommand::Explain) => {
+            for field in explain_config(Some(config_data))? {
+                println!("{field}");
+            }
+            return Ok(());
+        }
+        None => {}
+    }
+
+    let log_filter_reload: LogFilterReload = Box::new(move |directives| {
+        filter_handle.reload(log_filter(directives))?;
+        Ok(())
+    });
+    if config.log_filter.is_some() {
+        log_filter_reload(config.log_filter.as_deref())?;
+    }
+
+    let mut log_rotation = None;
+    if let Some(file) = config.log.as_ref().and_then(|log| log.file.clone()) {
+        let (layer, trigger) = log_file_layer(file)?;
+        log_file_handle.modify(move |log_file_layer| *log_file_layer = Some(layer.boxed()))?;
+        log_rotation = Some(trigger);
+    }
 
     match config.no_banner {
         Some(true) => {}
@@ -166,7 +214,15 @@ fn main() -> eyre::Result<()> {
             write_default_air_interpreter(&interpreter_path)?;
             log::info!("AIR interpreter: {:?}", interpreter_path);
 
-            let fluence = start_fluence(resolved_config, core_manager, peer_id).await?;
+            let fluence = start_fluence(
+                resolved_config,
+                &config,
+                log_filter_reload,
+                log_rotation,
+                core_manager,
+                peer_id,
+            )
+            .await?;
             log::info!("Fluence has been successfully started.");
 
             signal::ctrl_c().await.expect("Failed to listen for event");
@@ -180,6 +236,9 @@ fn main() -> eyre::Result<()> {
 // NOTE: to stop Fluence just call Stoppable::stop()
 async fn start_fluence(
     config: ResolvedConfig,
+    unresolved_config: &UnresolvedConfig,
+    log_filter_reload: LogFilterReload,
+    log_rotation: Option<RotationTrigger>,
     core_manager: Arc<CoreManager>,
     peer_id: PeerId,
 ) -> eyre::Result<impl Stoppable> {
@@ -187,7 +246,16 @@ async fn start_fluence(
 
     let listen_addrs = config.listen_multiaddrs();
     let vm_config = vm_config(&config);
-    let data_store_config = DataStoreConfig::new(config.dir_config.avm_base_dir.clone());
+    let mut data_store_config = DataStoreConfig::new(config.dir_config.avm_base_dir.clone())
+        .with_compression(config.particle_data_compression.clone());
+    let retention = &config.data_retention;
+    if retention.is_enabled() {
+        data_store_config = data_store_config.with_retention(DataRetention {
+            particles: retention_policy(&retention.particles),
+            anomalies: retention_policy(&retention.anomalies),
+            interval: retention.interval,
+        });
+    }
 
     let system_services_config = config.system_services.clone();
     let system_service_distros =
@@ -207,6 +275,14 @@ async fn start_fluence(
     .wrap_err("error create node instance")?;
     node.listen(listen_addrs).wrap_err("error on listen")?;
 
+    let config_reloader = node.config_reloader.clone();
+    config_reloader
+        .enable(unresolved_config, log_filter_reload, log_rotation)
+        .await?;
+    let hangup =
+        signal::unix::signal(SignalKind::hangup()).wrap_err("failed to listen for SIGHUP")?;
+    tokio::spawn(reload_on_hangup(config_reloader, hangup));
+
     let started_node = node.start(peer_id).await.wrap_err("node failed to start")?;
 
     struct Fluence {
@@ -226,6 +302,43 @@ async fn start_fluence(
     })
 }
 
+/// Resolves config and runs cross-field checks, fails if there are problems
+fn validate_config_command(config: UnresolvedConfig) -> eyre::Result<()> {
+    let config = config.resolve().wrap_err("invalid config")?;
+    let problems = validate_config(&config);
+    if problems.is_empty() {
+        println!("Config is valid");
+        return Ok(());
+    }
+    for problem in &problems {
+        eprintln!("{problem}");
+    }
+    eyre::bail!("config has {} problem(s)", problems.len())
+}
+
+/// Reloads config on every SIGHUP
+async fn reload_on_hangup(config_reloader: ConfigReloader, mut hangup: Signal) {
+    while hangup.recv().await.is_some() {
+        log::info!("Received SIGHUP, reloading config");
+        match config_reloader.reload().await {
+            Ok(report) => log::info!(
+                "Config reloaded; applied: {:?}, requires restart: {:?}",
+                report.applied,
+                report.requires_restart
+            ),
+            Err(err) => log::error!("Config reload failed: {err:?}"),
+        }
+    }
+}
+
+fn retention_policy(config: &RetentionPolicyConfig) -> RetentionPolicy {
+    RetentionPolicy {
+        max_age: config.max_age,
+        max_total_size: config.max_total_size.map(|s| s.as_u64()),
+        max_particle_size: config.max_particle_size.map(|s| s.as_u64()),
+    }
+}
+
 fn vm_config(config: &ResolvedConfig) -> VmConfig {
     VmConfig::new(
         to_peer_id(&config.root_key_pair.clone().into()),
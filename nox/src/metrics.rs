@@ -1,3 +1,10 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use libp2p::multiaddr::Protocol;
+use libp2p::swarm::{ConnectionId, DialError, SwarmEvent};
+use libp2p::Multiaddr;
+use peer_metrics::{DialOutcome, NetworkProtocolMetrics};
 use prometheus_client::collector::Collector;
 use prometheus_client::encoding::{DescriptorEncoder, EncodeMetric, MetricEncoder};
 use prometheus_client::metrics::counter::ConstCounter;
@@ -321,3 +328,75 @@ impl Collector for TokioCollector {
         Ok(())
     }
 }
+
+/// Measures how long outgoing dials take, see [NetworkProtocolMetrics]
+pub struct DialLatency {
+    metrics: NetworkProtocolMetrics,
+    dials: HashMap<ConnectionId, Instant>,
+}
+
+impl DialLatency {
+    pub fn new(metrics: NetworkProtocolMetrics) -> Self {
+        Self {
+            metrics,
+            dials: <_>::default(),
+        }
+    }
+
+    pub fn record<E>(&mut self, event: &SwarmEvent<E>) {
+        match event {
+            SwarmEvent::Dialing { connection_id, .. } => {
+                self.dials.insert(*connection_id, Instant::now());
+            }
+            SwarmEvent::ConnectionEstablished {
+                connection_id,
+                endpoint,
+                established_in,
+                ..
+            } if endpoint.is_dialer() => {
+                self.dials.remove(connection_id);
+                self.metrics.dial_finished(
+                    transport_label(endpoint.get_remote_address()),
+                    DialOutcome::Success,
+                    *established_in,
+                );
+            }
+            SwarmEvent::OutgoingConnectionError {
+                connection_id,
+                error,
+                ..
+            } => {
+                if let Some(started) = self.dials.remove(connection_id) {
+                    let transport = match error {
+                        DialError::Transport(errors) => {
+                            errors.first().map(|(addr, _)| transport_label(addr))
+                        }
+                        _ => None,
+                    };
+                    self.metrics.dial_finished(
+                        transport.unwrap_or("unknown"),
+                        DialOutcome::Failure,
+                        started.elapsed(),
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Outermost transport of the address, so relayed dials are told apart from direct ones
+fn transport_label(addr: &Multiaddr) -> &'static str {
+    let mut label = "other";
+    for protocol in addr.iter() {
+        label = match protocol {
+            Protocol::P2pCircuit => return "relay",
+            Protocol::WebRTCDirect | Protocol::WebRTC => "webrtc",
+            Protocol::Ws(_) | Protocol::Wss(_) => "websocket",
+            Protocol::Tcp(_) if label == "other" => "tcp",
+            Protocol::Memory(_) => "memory",
+            _ => label,
+        };
+    }
+    label
+}
@@ -0,0 +1,169 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::Response;
+use axum::routing::get;
+use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
+use eyre::WrapErr;
+use server_config::MetricsEndpointConfig;
+
+use crate::api_tokens::{ApiTokens, Role};
+use crate::http::{authorize, metrics_response, MetricsEndpoint};
+use crate::http_error::{negotiate, ApiError, ApiResult, API_VERSION};
+
+/// Listener serving only `/metrics`, separate from the main http endpoint
+pub struct MetricsListener {
+    pub listen_addr: SocketAddr,
+    /// Serve over TLS, possibly requiring client certificates
+    pub tls: Option<RustlsConfig>,
+    /// Require a bearer token with at least the read-only role
+    pub tokens: Option<ApiTokens>,
+}
+
+impl MetricsListener {
+    /// Reads certificates configured in `config`
+    pub fn new(
+        listen_addr: SocketAddr,
+        config: &MetricsEndpointConfig,
+        tokens: ApiTokens,
+    ) -> eyre::Result<Self> {
+        let tls = match (&config.certificate_path, &config.private_key_path) {
+            (Some(certificate_path), Some(private_key_path)) => {
+                let read = |path: &std::path::PathBuf| {
+                    std::fs::read(path).wrap_err(format!("failed to read {}", path.display()))
+                };
+                let client_ca = config.client_ca_path.as_ref().map(read).transpose()?;
+                let tls = fluence_libp2p::static_tls_server_config(
+                    &read(certificate_path)?,
+                    &read(private_key_path)?,
+                    client_ca.as_deref(),
+                    false,
+                )
+                .wrap_err("invalid metrics_endpoint certificates")?;
+                Some(RustlsConfig::from_config(Arc::new(tls)))
+            }
+            _ => None,
+        };
+        Ok(Self {
+            listen_addr,
+            tls,
+            tokens: config.require_token.then_some(tokens),
+        })
+    }
+}
+
+#[derive(Clone)]
+struct MetricsState {
+    metrics: Arc<MetricsEndpoint>,
+    tokens: Option<ApiTokens>,
+}
+
+async fn handle_metrics(
+    State(state): State<MetricsState>,
+    headers: HeaderMap,
+) -> ApiResult<Response<Body>> {
+    if let Some(tokens) = &state.tokens {
+        authorize(tokens, &headers, Role::ReadOnly)?;
+    }
+    metrics_response(&state.metrics)
+}
+
+async fn handler_404() -> ApiError {
+    ApiError::not_found()
+}
+
+pub async fn start_metrics_endpoint(
+    listener: MetricsListener,
+    metrics: MetricsEndpoint,
+) -> eyre::Result<()> {
+    let state = MetricsState {
+        metrics: Arc::new(metrics),
+        tokens: listener.tokens,
+    };
+    let app: Router = Router::new()
+        .route("/metrics", get(handle_metrics))
+        .route(&format!("/v{API_VERSION}/metrics"), get(handle_metrics))
+        .fallback(handler_404)
+        .layer(axum::middleware::from_fn(negotiate))
+        .with_state(state);
+
+    match listener.tls {
+        Some(tls) => {
+            axum_server::bind_rustls(listener.listen_addr, tls)
+                .serve(app.into_make_service())
+                .await?
+        }
+        None => {
+            let tcp_listener = tokio::net::TcpListener::bind(listener.listen_addr).await?;
+            axum::serve(tcp_listener, app.into_make_service()).await?
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+
+    use prometheus_client::registry::Registry;
+    use reqwest::StatusCode;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn require_token() {
+        // bind to a free port picked by OS
+        let listen_addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let tokens = ApiTokens::new(Some("secret".to_string()));
+        let listener = MetricsListener {
+            listen_addr,
+            tls: None,
+            tokens: Some(tokens),
+        };
+        let metrics = MetricsEndpoint {
+            registry: Registry::default(),
+            enabled: Arc::new(AtomicBool::new(true)),
+        };
+        tokio::spawn(start_metrics_endpoint(listener, metrics));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let client = reqwest::Client::new();
+        let url = format!("http://{listen_addr}/metrics");
+        let response = client.get(&url).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = client.get(&url).bearer_auth("secret").send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = client
+            .get(format!("http://{listen_addr}/config"))
+            .bearer_auth("secret")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
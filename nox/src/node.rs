@@ -14,7 +14,9 @@
  * limitations under the License.
  */
 
+use std::path::PathBuf;
 use std::process::exit;
+use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 use std::{io, net::SocketAddr};
 
@@ -31,7 +33,6 @@ use libp2p::{
     identity::Keypair,
     PeerId, Swarm, TransportError,
 };
-use libp2p_connection_limits::ConnectionLimits;
 use libp2p_metrics::{Metrics, Recorder};
 use prometheus_client::registry::Registry;
 use tokio::sync::{mpsc, oneshot};
@@ -40,20 +41,27 @@ use tracing::Instrument;
 
 use aquamarine::{
     AquaRuntime, AquamarineApi, AquamarineApiError, AquamarineBackend, DataStoreConfig,
-    RemoteRoutingEffects, VmPoolConfig,
+    ParticlePriority, RemoteRoutingEffects, VmPoolConfig, VmPoolScaling,
 };
+use audit_log::AuditLog;
 use chain_connector::ChainConnector;
-use chain_listener::ChainListener;
+use chain_listener::{ChainListener, ChainListenerHealth};
 use config_utils::to_peer_id;
-use connection_pool::ConnectionPoolT;
+use connection_pool::{ConnectionLimits, ConnectionPoolT, DrainConfig, PeerFilter, TrustCheck};
 use core_manager::manager::CoreManager;
-use fluence_libp2p::build_transport;
+use fluence_libp2p::{
+    build_network_transport_with_tls, build_transport, load_or_create_webrtc_certificate,
+    tls_server_config, webrtc_certhash, with_fault_injection, with_quic_transport,
+    with_relay_client_transport, with_stream_metrics, with_webrtc_transport,
+    TlsCertificateResolver,
+};
 use health::HealthCheckRegistry;
-use particle_builtins::{Builtins, CustomService, NodeInfo};
+use particle_builtins::{BuiltinPolicy, Builtins, CustomService, NodeInfo};
 use particle_execution::ParticleFunctionStatic;
 use particle_protocol::ExtendedParticle;
 use peer_metrics::{
-    ConnectionPoolMetrics, ConnectivityMetrics, ParticleExecutorMetrics, ServicesMetrics,
+    ConnectionPoolMetrics, ConnectivityMetrics, DataStoreMetrics, DealPrefetchMetrics,
+    LifecycleMetrics, NetworkProtocolMetrics, ParticleExecutorMetrics, ServicesMetrics,
     ServicesMetricsBackend, SpellMetrics, VmPoolMetrics,
 };
 use server_config::system_services_config::ServiceKey;
@@ -61,15 +69,39 @@ use server_config::{NetworkConfig, ResolvedConfig, ServicesConfig};
 use sorcerer::Sorcerer;
 use spell_event_bus::api::{PeerEvent, SpellEventBusApi, TriggerEvent};
 use spell_event_bus::bus::SpellEventBus;
-use system_services::{Deployer, SystemServiceDistros};
-use workers::{KeyStorage, PeerScopes, Workers};
-
-use crate::behaviour::FluenceNetworkBehaviourEvent;
-use crate::builtins::make_peer_builtin;
+use system_services::{DealPrefetch, DeployedVersions, Deployer, IpfsPinGc, SystemServiceDistros};
+use workers::{KeyStorage, ManagementKeys, PeerScopes, Workers};
+
+use crate::acme::CertificateManager;
+use crate::admin_tls::AdminTls;
+use crate::api_tokens::ApiTokens;
+use crate::behaviour::{
+    confirm_ipv6_candidate, log_dcutr_event, log_relay_client_event, FluenceNetworkBehaviourEvent,
+    PortMappings, RelayListeners,
+};
+use crate::builtins::{
+    make_api_token_builtin, make_aquavm_builtin, make_audit_builtin, make_config_builtin,
+    make_cores_builtin, make_log_builtin, make_management_builtin, make_network_builtin,
+    make_peer_builtin, make_system_builtin, make_trust_graph_builtin,
+};
+use crate::config_reload::{pinned_versions, ConfigReloader};
+use crate::control::Control;
 use crate::dispatcher::Dispatcher;
 use crate::effectors::Effectors;
-use crate::http::start_http_endpoint;
-use crate::metrics::TokioCollector;
+use crate::events::publish_peer_events;
+use crate::graphql::{node_schema, NodeSchema};
+use crate::grpc::{start_grpc_endpoint, GrpcApi};
+use crate::health::{DiskSpaceHealth, SystemServicesHealth};
+use crate::host_key::HostKey;
+use crate::http::{start_http_endpoint, HttpAuth, HttpEndpointConfig, MetricsEndpoint};
+use crate::http_particle::HttpParticles;
+use crate::lifecycle::LifecycleTimings;
+use crate::log_control::LogControl;
+use crate::metrics::{DialLatency, TokioCollector};
+use crate::metrics_endpoint::{start_metrics_endpoint, MetricsListener};
+use crate::seen_particles::SeenParticles;
+use crate::supervisor::SystemServicesSupervisor;
+use crate::trust_graph::TrustGraph;
 use crate::{Connectivity, Versions};
 
 use super::behaviour::FluenceNetworkBehaviour;
@@ -85,6 +117,16 @@ pub struct Node<RT: AquaRuntime> {
     pub dispatcher: Dispatcher,
     aquamarine_backend: AquamarineBackend<RT, Arc<Builtins<Connectivity>>>,
     system_service_deployer: Deployer,
+    /// Deploys system services that aren't needed for the node to start in background
+    lazy_system_service_deployer: Option<Deployer>,
+    /// Unpins content from the aqua-ipfs IPFS node if pinning limits are set
+    ipfs_pin_gc: Option<IpfsPinGc>,
+    /// Fetches content of matched deals from IPFS before their workers are created
+    deal_prefetch: Option<DealPrefetch>,
+    /// Health-checks system services and redeploys the failed ones
+    system_services_supervisor: SystemServicesSupervisor,
+    /// Consults trust graph weights for the allowlist once system services are deployed
+    trust_check: Option<TrustCheck>,
 
     spell_event_bus_api: SpellEventBusApi,
     spell_event_bus: SpellEventBus,
@@ -92,28 +134,60 @@ pub struct Node<RT: AquaRuntime> {
     sorcerer: Sorcerer,
 
     metrics_registry: Option<Registry>,
+    metrics_enabled: Option<Arc<AtomicBool>>,
     health_registry: Option<HealthCheckRegistry>,
+    system_services_health: Option<SystemServicesHealth>,
     libp2p_metrics: Option<Arc<Metrics>>,
+    dial_latency: Option<DialLatency>,
     services_metrics_backend: ServicesMetricsBackend,
 
     http_listen_addr: Option<SocketAddr>,
+    grpc_listen_addr: Option<SocketAddr>,
+    probe_listen_addr: Option<SocketAddr>,
+    metrics_listener: Option<MetricsListener>,
+    http_auth: HttpAuth,
+    http_particles: Option<HttpParticles>,
+    graphql: Option<NodeSchema>,
 
     pub builtins_management_peer_id: PeerId,
 
     pub scope: PeerScopes,
 
     allow_local_addresses: bool,
+    /// Advertised via Identify, nodes with another one are disconnected
+    protocol_version: String,
     versions: Versions,
 
     pub chain_listener: Option<ChainListener>,
 
     workers: Arc<Workers>,
+
+    certificate_manager: Option<CertificateManager>,
+
+    relay_listeners: RelayListeners,
+
+    port_mappings: PortMappings,
+
+    drain: DrainConfig,
+
+    pub config_reloader: ConfigReloader,
+
+    audit_log: AuditLog,
+
+    control: Control,
+    /// `None` if the control socket is disabled
+    control_socket_path: Option<PathBuf>,
+
+    host_key: HostKey,
+
+    startup_timings: LifecycleTimings,
 }
 
 async fn setup_listener(
     connector: Option<Arc<ChainConnector>>,
     config: &ResolvedConfig,
     core_manager: Arc<CoreManager>,
+    health_registry: Option<&mut HealthCheckRegistry>,
 ) -> eyre::Result<Option<ChainListener>> {
     if let (Some(connector), Some(chain_config), Some(listener_config)) = (
         connector,
@@ -134,7 +208,12 @@ async fn setup_listener(
         };
 
         let cc_events_dir = config.dir_config.cc_events_dir.clone();
-        let host_id = config.root_key_pair.get_peer_id();
+        // on-chain registrations keep the previous peer id until the host key handover ends
+        let host_id = config
+            .previous_root_key_pair
+            .as_ref()
+            .unwrap_or(&config.root_key_pair)
+            .get_peer_id();
         let ws_client = WsClientBuilder::default()
             .build(&listener_config.ws_endpoint)
             .await
@@ -161,7 +240,12 @@ async fn setup_listener(
             ccp_client,
             cc_events_dir,
         );
-        Ok(Some(chain_listener))
+        let health = health_registry.map(|registry| {
+            let health = ChainListenerHealth::new(config.health_config.chain_block_timeout);
+            registry.register_liveness("chain_listener", health.clone());
+            health
+        });
+        Ok(Some(chain_listener.with_health(health)))
     } else {
         Ok(None)
     }
@@ -177,39 +261,152 @@ impl<RT: AquaRuntime> Node<RT> {
         air_version: &'static str,
         system_service_distros: SystemServiceDistros,
     ) -> eyre::Result<Box<Self>> {
+        let startup_timings = LifecycleTimings::startup();
         let key_pair: Keypair = config.node_config.root_key_pair.clone().into();
         let transport = config.transport_config.transport;
-        let transport =
-            build_transport(transport, &key_pair, config.transport_config.socket_timeout);
+        let mut external_addresses = config.external_addresses();
+        let webrtc_enabled = transport.is_network() && config.listen_config.webrtc_port.is_some();
+        let quic_enabled = transport.is_network() && config.listen_config.has_quic();
+        let relay_client_enabled = transport.is_network() && config.nat.relay_client;
+        let pnet = config.private_network.clone();
+        if let Some(pnet) = &pnet {
+            log::info!(
+                "Private network is enabled, pre-shared key {}",
+                pnet.key().fingerprint()
+            );
+        }
+        let (transport, certificate_manager) = match config.websocket_tls.clone() {
+            Some(websocket_tls) if transport.is_network() => {
+                let resolver = Arc::new(TlsCertificateResolver::default());
+                let certificate_manager = CertificateManager::new(
+                    websocket_tls,
+                    config.dir_config.acme_dir.clone(),
+                    resolver.clone(),
+                );
+                certificate_manager.load()?;
+                let transport = build_network_transport_with_tls(
+                    &key_pair,
+                    config.transport_config.socket_timeout,
+                    tls_server_config(resolver),
+                    pnet.clone(),
+                );
+                (transport, Some(certificate_manager))
+            }
+            _ => {
+                let transport = build_transport(
+                    transport,
+                    &key_pair,
+                    config.transport_config.socket_timeout,
+                    pnet.clone(),
+                );
+                (transport, None)
+            }
+        };
+        let transport = if quic_enabled {
+            with_quic_transport(transport, &key_pair)
+        } else {
+            transport
+        };
+        let transport = if webrtc_enabled {
+            let certificate =
+                load_or_create_webrtc_certificate(&config.dir_config.webrtc_certificate_path)
+                    .wrap_err("failed to load WebRTC certificate")?;
+            external_addresses
+                .extend(config.webrtc_external_addresses(webrtc_certhash(&certificate)));
+            with_webrtc_transport(transport, &key_pair, certificate)
+        } else {
+            transport
+        };
+        let (transport, relay_client, relay_listeners) = if relay_client_enabled {
+            let (relay_transport, relay_client) =
+                libp2p::relay::client::new(key_pair.public().to_peer_id());
+            let transport = with_relay_client_transport(
+                transport,
+                relay_transport,
+                &key_pair,
+                config.transport_config.socket_timeout,
+                pnet,
+            );
+            let relay_listeners = RelayListeners::new(config.nat.relays.clone());
+            (transport, Some(relay_client), relay_listeners)
+        } else {
+            (transport, None, RelayListeners::new(vec![]))
+        };
 
         let builtins_peer_id = to_peer_id(&config.builtins_key_pair.clone().into());
 
         let root_key_pair: KeyPair = key_pair.clone().into();
 
-        let key_storage = KeyStorage::from_path(
-            config.dir_config.keypairs_base_dir.clone(),
-            root_key_pair.clone(),
-        )
-        .await?;
+        let key_storage = startup_timings
+            .measure(
+                "key_storage",
+                KeyStorage::load(
+                    config.dir_config.keypairs_base_dir.clone(),
+                    root_key_pair.clone(),
+                    config.keystore.clone(),
+                ),
+            )
+            .await?;
 
         let key_storage = Arc::new(key_storage);
 
+        let management_keys = ManagementKeys::load(
+            config.management_keys.clone(),
+            config.dir_config.management_keys_path.clone(),
+        )
+        .wrap_err("failed to load management keys")?;
         let scopes = PeerScopes::new(
             root_key_pair.get_peer_id(),
             config.management_peer_id,
             builtins_peer_id,
             key_storage.clone(),
-        );
+        )
+        .with_previous_host(
+            config
+                .previous_root_key_pair
+                .as_ref()
+                .map(KeyPair::get_peer_id),
+        )
+        .with_management_keys(management_keys);
 
-        let (workers, worker_events) = Workers::from_path(
-            config.dir_config.workers_base_dir.clone(),
-            key_storage.clone(),
-            core_manager.clone(),
-            config.node_config.workers_queue_buffer,
+        let audit_log = AuditLog::open(&config.dir_config.audit_log_path)
+            .wrap_err("failed to open audit log")?;
+
+        let http_config = config.http_config.as_ref();
+        let api_tokens = ApiTokens::load(
+            config.dir_config.api_tokens_path.clone(),
+            http_config.and_then(|c| c.admin_token.clone()),
         )
-        .await?;
+        .wrap_err("failed to load api tokens")?;
+        let admin_tls = http_config
+            .and_then(|c| c.admin_tls.as_ref())
+            .map(AdminTls::load)
+            .transpose()?;
+        let http_auth = HttpAuth {
+            tokens: api_tokens.clone(),
+            protect_metrics: http_config.is_some_and(|c| c.metrics_require_token),
+            tls: admin_tls,
+        };
 
-        let workers = Arc::new(workers);
+        let (workers, worker_events) = startup_timings
+            .measure(
+                "workers",
+                Workers::from_path(
+                    config.dir_config.workers_base_dir.clone(),
+                    key_storage.clone(),
+                    core_manager.clone(),
+                    config.node_config.workers_queue_buffer,
+                ),
+            )
+            .await?;
+
+        let gpus = config.node_config.gpus.iter().map(|gpu| gpu.id.clone());
+        let workers = Arc::new(
+            workers
+                .with_max_workers(config.node_config.max_workers)
+                .with_gpus(gpus.collect())
+                .with_audit_log(audit_log.clone()),
+        );
 
         let services_config = ServicesConfig::new(
             scopes.get_host_peer_id(),
@@ -220,6 +417,7 @@ impl<RT: AquaRuntime> Node<RT> {
             config.management_peer_id,
             builtins_peer_id,
             config.node_config.default_service_memory_limit,
+            config.node_config.max_vault_size,
             config.node_config.allowed_effectors.clone(),
             config.node_config.dev_mode_config.binaries.clone(),
             config.node_config.dev_mode_config.enable,
@@ -232,18 +430,49 @@ impl<RT: AquaRuntime> Node<RT> {
             None
         };
 
+        // toggled on config reload
+        let metrics_enabled = metrics_registry
+            .as_ref()
+            .map(|_| Arc::new(AtomicBool::new(true)));
+
         let mut health_registry = if config.health_config.health_check_enabled {
             Some(HealthCheckRegistry::default())
         } else {
             None
         };
+        let system_services_health = health_registry.as_mut().map(|registry| {
+            let health = SystemServicesHealth::default();
+            registry.register("system_services", health.clone());
+            registry.register(
+                "disk_space",
+                DiskSpaceHealth::new(
+                    config.dir_config.persistent_base_dir.clone(),
+                    config.health_config.min_free_disk_space,
+                ),
+            );
+            health
+        });
 
         let libp2p_metrics = metrics_registry.as_mut().map(|r| Arc::new(Metrics::new(r)));
         let connectivity_metrics = metrics_registry.as_mut().map(ConnectivityMetrics::new);
         let connection_pool_metrics = metrics_registry.as_mut().map(ConnectionPoolMetrics::new);
         let plumber_metrics = metrics_registry.as_mut().map(ParticleExecutorMetrics::new);
         let vm_pool_metrics = metrics_registry.as_mut().map(VmPoolMetrics::new);
+        let data_store_metrics = metrics_registry.as_mut().map(DataStoreMetrics::new);
         let spell_metrics = metrics_registry.as_mut().map(SpellMetrics::new);
+        let network_protocol_metrics = metrics_registry.as_mut().map(NetworkProtocolMetrics::new);
+        let startup_timings =
+            startup_timings.with_metrics(metrics_registry.as_mut().map(LifecycleMetrics::new));
+
+        let transport = match network_protocol_metrics.clone() {
+            Some(metrics) => with_stream_metrics(transport, Arc::new(metrics)),
+            None => transport,
+        };
+        let transport = match config.fault_injector.clone() {
+            Some(injector) => with_fault_injection(transport, injector),
+            None => transport,
+        };
+        let dial_latency = network_protocol_metrics.map(DialLatency::new);
 
         if config.metrics_config.tokio_metrics_enabled {
             if let Some(r) = metrics_registry.as_mut() {
@@ -252,7 +481,6 @@ impl<RT: AquaRuntime> Node<RT> {
             }
         }
 
-        #[allow(deprecated)]
         let connection_limits = ConnectionLimits::default()
             .with_max_pending_incoming(config.node_config.transport_config.max_pending_incoming)
             .with_max_pending_outgoing(config.node_config.transport_config.max_pending_outgoing)
@@ -265,8 +493,23 @@ impl<RT: AquaRuntime> Node<RT> {
             .with_max_established_per_peer(
                 config.node_config.transport_config.max_established_per_peer,
             )
+            .with_max_established_per_ip(config.node_config.transport_config.max_established_per_ip)
             .with_max_established(config.node_config.transport_config.max_established);
 
+        let peer_filter = PeerFilter::load(
+            config.peer_filter.clone(),
+            config.dir_config.peer_filter_path.clone(),
+            [config.management_peer_id, builtins_peer_id],
+        )
+        .wrap_err("failed to load peer filter")?;
+
+        let builtin_policy = BuiltinPolicy::load(
+            config.builtin_policy.clone(),
+            config.dir_config.builtin_policy_path.clone(),
+        )
+        .wrap_err("failed to load builtin policy")?;
+        let builtin_policy = Arc::new(builtin_policy);
+
         let network_config = NetworkConfig::new(
             libp2p_metrics.clone(),
             connectivity_metrics,
@@ -275,15 +518,18 @@ impl<RT: AquaRuntime> Node<RT> {
             &config,
             node_version,
             connection_limits,
+            peer_filter,
         );
 
         let allow_local_addresses = config.allow_local_addresses;
+        let protocol_version = network_config.protocol_version.clone();
 
         let (swarm, connectivity, particle_stream) = Self::swarm(
             root_key_pair.clone().into(),
             network_config,
             transport,
-            config.external_addresses(),
+            relay_client,
+            external_addresses.clone(),
             health_registry.as_mut(),
             metrics_registry.as_mut(),
         )?;
@@ -310,16 +556,106 @@ impl<RT: AquaRuntime> Node<RT> {
             scopes.clone(),
             health_registry.as_mut(),
             config.system_services.decider.network_api_endpoint.clone(),
-        );
+        )
+        .with_audit_log(audit_log.clone())
+        .with_policy(builtin_policy.clone());
 
-        builtins.services.create_persisted_services().await?;
+        let (connector, chain_builtins) = if let Some(chain_config) = config.chain_config.clone() {
+            let host_id = scopes.get_host_peer_id();
+            // on-chain registrations keep the previous peer id until the host key handover ends
+            let compute_peer_id = scopes.get_previous_host_peer_id().unwrap_or(host_id);
+            let (chain_connector, chain_builtins) =
+                ChainConnector::new(chain_config.clone(), host_id, compute_peer_id).map_err(
+                    |err| {
+                        log::error!(
+                            "Error connecting to http endpoint {}, error: {err}",
+                            chain_config.http_endpoint
+                        );
+                        err
+                    },
+                )?;
+            (Some(chain_connector), Some(chain_builtins))
+        } else {
+            if config.system_services.enable.contains(&ServiceKey::Decider) {
+                log::error!(
+                    "Decider cannot be used without chain connector. Please, specify chain config"
+                );
+                exit(1);
+            }
+
+            (None, None)
+        };
+
+        // persisted services and chain listener don't depend on each other
+        let ((), chain_listener) = tokio::try_join!(
+            startup_timings.measure("services", builtins.services.create_persisted_services()),
+            startup_timings.measure(
+                "chain_listener",
+                setup_listener(
+                    connector,
+                    &config,
+                    core_manager.clone(),
+                    health_registry.as_mut(),
+                ),
+            ),
+        )?;
+
+        let decider = &config.system_services.decider;
+        let (chain_listener, deal_prefetch) = match chain_listener {
+            Some(listener) if decider.prefetch.enabled => {
+                let (deal_matched, deals) = mpsc::unbounded_channel();
+                // the IPFS node the decider fetches deals from
+                let ipfs_multiaddr = config
+                    .system_services
+                    .aqua_ipfs
+                    .deal_api_multiaddr
+                    .as_ref()
+                    .unwrap_or(&decider.worker_ipfs_multiaddr);
+                let prefetch = DealPrefetch::new(
+                    ipfs_multiaddr,
+                    decider.prefetch.clone(),
+                    metrics_registry.as_mut().map(DealPrefetchMetrics::new),
+                    audit_log.clone(),
+                    deals,
+                )?;
+                (
+                    Some(listener.with_deal_matched(deal_matched)),
+                    Some(prefetch),
+                )
+            }
+            listener => (listener, None),
+        };
 
         let builtins = Arc::new(builtins);
 
         let (effects_out, effects_in) = mpsc::channel(config.node_config.effects_queue_buffer);
 
-        let pool_config =
+        let mut pool_config =
             VmPoolConfig::new(config.aquavm_pool_size, config.particle_execution_timeout);
+        let scaling = &config.aquavm_pool_scaling;
+        if let Some((min_size, max_size)) = scaling.bounds(config.aquavm_pool_size) {
+            pool_config = pool_config.with_scaling(VmPoolScaling {
+                min_size,
+                max_size,
+                scale_up_queue_depth: scaling.scale_up_queue_depth,
+                scale_up_latency: scaling.scale_up_latency,
+                scale_down_after: scaling.scale_down_after,
+                warm_vms: scaling.warm_vms,
+            });
+        }
+        pool_config = pool_config
+            .with_preinit(config.aquavm_preinit)
+            .with_max_data_size(
+                config
+                    .particle_limits
+                    .max_data_size
+                    .map(|s| s.as_u64() as usize),
+            );
+        if config.particle_priority.enabled {
+            pool_config = pool_config.with_priority(ParticlePriority::new(
+                config.particle_priority.order.clone(),
+            ));
+        }
         let (aquamarine_backend, aquamarine_api) = AquamarineBackend::new(
             pool_config,
             vm_config,
@@ -328,6 +664,7 @@ impl<RT: AquaRuntime> Node<RT> {
             effects_out,
             plumber_metrics,
             vm_pool_metrics,
+            data_store_metrics,
             health_registry.as_mut(),
             workers.clone(),
             key_storage.clone(),
@@ -335,6 +672,18 @@ impl<RT: AquaRuntime> Node<RT> {
             worker_events,
         )?;
         let effectors = Effectors::new(connectivity.clone());
+        let dedup = &config.particle_dedup;
+        let seen_particles = if dedup.enabled {
+            let seen_particles = SeenParticles::load(
+                config.dir_config.seen_particles_path.clone(),
+                dedup.max_entries,
+                now_millis::now_ms() as u64,
+            )
+            .wrap_err("failed to load seen particles")?;
+            Some(seen_particles)
+        } else {
+            None
+        };
         let dispatcher = {
             let parallelism = config.particle_processor_parallelism;
             Dispatcher::new(
@@ -344,13 +693,16 @@ impl<RT: AquaRuntime> Node<RT> {
                 parallelism,
                 metrics_registry.as_mut(),
             )
+            .with_seen_particles(seen_particles, dedup.persist_interval)
         };
 
         let recv_connection_pool_events = connectivity.connection_pool.lifecycle_events();
         let sources = vec![recv_connection_pool_events.map(PeerEvent::from).boxed()];
 
         let (spell_event_bus, spell_event_bus_api, spell_events_receiver) =
-            SpellEventBus::new(spell_metrics.clone(), sources);
+            SpellEventBus::new(spell_metrics.clone(), sources, connectivity.pubsub.clone());
+        let spell_event_bus = spell_event_bus
+            .with_topic_triggers_per_sec(config.node_config.pubsub.topic_triggers_per_sec);
 
         let spell_service_api = spell_service_api::SpellServiceApi::new(builtins.services.clone());
         let (sorcerer, mut custom_service_functions, spell_version) = Sorcerer::new(
@@ -364,6 +716,8 @@ impl<RT: AquaRuntime> Node<RT> {
             scopes.clone(),
             spell_service_api.clone(),
             spell_metrics,
+            connectivity.pubsub.clone(),
+            audit_log.clone(),
         );
 
         let allowed_binaries = config
@@ -374,7 +728,7 @@ impl<RT: AquaRuntime> Node<RT> {
             .into_iter()
             .collect::<_>();
         let node_info = NodeInfo {
-            external_addresses: config.external_addresses(),
+            external_addresses,
             node_version: env!("CARGO_PKG_VERSION"),
             air_version: air_interpreter_wasm::VERSION,
             spell_version: spell_version.clone(),
@@ -389,33 +743,94 @@ impl<RT: AquaRuntime> Node<RT> {
                 node_info.spell_version.clone(),
             );
         }
-        custom_service_functions.extend_one(make_peer_builtin(node_info));
+        let port_mappings = PortMappings::default();
+        custom_service_functions.extend_one(make_peer_builtin(node_info, port_mappings.clone()));
+        custom_service_functions
+            .extend_one(make_aquavm_builtin(aquamarine_api.clone(), scopes.clone()));
 
         let services = builtins.services.clone();
         let modules = builtins.modules.clone();
 
-        let connector = if let Some(chain_config) = config.chain_config.clone() {
-            let host_id = scopes.get_host_peer_id();
-            let (chain_connector, chain_builtins) =
-                ChainConnector::new(chain_config.clone(), host_id).map_err(|err| {
-                    log::error!(
-                        "Error connecting to http endpoint {}, error: {err}",
-                        chain_config.http_endpoint
-                    );
-                    err
-                })?;
-            custom_service_functions.extend(chain_builtins.into_iter());
-            Some(chain_connector)
-        } else {
-            if config.system_services.enable.contains(&ServiceKey::Decider) {
-                log::error!(
-                    "Decider cannot be used without chain connector. Please, specify chain config"
-                );
-                exit(1);
-            }
+        custom_service_functions.extend(chain_builtins.into_iter().flatten());
 
-            None
-        };
+        let trust_graph = TrustGraph::new(
+            services.clone(),
+            scopes.get_host_peer_id(),
+            root_key_pair.clone(),
+            config.system_services.trust_graph.clone(),
+        );
+        let trust_check = trust_graph.trust_check();
+        custom_service_functions.extend_one(make_trust_graph_builtin(trust_graph, scopes.clone()));
+
+        let mut system_services_deployer = Deployer::new(
+            services.clone(),
+            modules.clone(),
+            sorcerer.spell_storage.clone(),
+            spell_event_bus_api.clone(),
+            spell_service_api,
+            scopes.get_host_peer_id(),
+            builtins_peer_id,
+            system_service_distros,
+        )
+        .with_pinned_versions(pinned_versions(&config.system_services))
+        .with_deployed_versions(
+            DeployedVersions::load(config.dir_config.system_services_versions_path.clone())
+                .wrap_err("failed to load deployed system service versions")?,
+        );
+        let lazy_system_services = config
+            .system_services
+            .lazy
+            .iter()
+            .map(|key| key.to_string())
+            .collect::<Vec<_>>();
+        let lazy_system_services_deployer = (!lazy_system_services.is_empty())
+            .then(|| system_services_deployer.split_off(&lazy_system_services));
+        let aqua_ipfs = &config.system_services.aqua_ipfs;
+        let aqua_ipfs_enabled = config
+            .system_services
+            .enable
+            .contains(&ServiceKey::AquaIpfs);
+        let ipfs_pin_gc = (aqua_ipfs_enabled && aqua_ipfs.pinning.is_enabled())
+            .then(|| IpfsPinGc::new(&aqua_ipfs.local_api_multiaddr, aqua_ipfs.pinning.clone()))
+            .transpose()?;
+
+        let log_control = LogControl::default();
+        custom_service_functions.extend_one(make_log_builtin(log_control.clone(), scopes.clone()));
+        let config_reloader = ConfigReloader::new(
+            log_control.clone(),
+            connectivity.connection_pool.clone(),
+            modules.clone(),
+            system_services_deployer.clone(),
+            config.system_services.clone(),
+            metrics_enabled.clone(),
+            config.dev_mode_config.enable,
+        )
+        .with_audit_log(audit_log.clone())
+        .with_builtin_policy(builtin_policy);
+        custom_service_functions
+            .extend_one(make_config_builtin(config_reloader.clone(), scopes.clone()));
+        custom_service_functions
+            .extend_one(make_system_builtin(config_reloader.clone(), scopes.clone()));
+        custom_service_functions.extend_one(make_cores_builtin(core_manager, scopes.clone()));
+        let system_services_supervisor = SystemServicesSupervisor::new(
+            config_reloader.clone(),
+            config.system_services.supervisor.clone(),
+            system_services_health.clone(),
+            audit_log.clone(),
+        );
+        custom_service_functions.extend_one(make_audit_builtin(audit_log.clone(), scopes.clone()));
+        custom_service_functions.extend_one(make_network_builtin(
+            connectivity.clone(),
+            config.drain.clone(),
+            scopes.clone(),
+        ));
+        custom_service_functions
+            .extend_one(make_management_builtin(audit_log.clone(), scopes.clone()));
+        custom_service_functions.extend_one(make_api_token_builtin(
+            api_tokens.clone(),
+            audit_log.clone(),
+            scopes.clone(),
+        ));
 
         custom_service_functions.into_iter().for_each(
             move |(
@@ -434,17 +849,6 @@ impl<RT: AquaRuntime> Node<RT> {
             },
         );
 
-        let system_services_deployer = Deployer::new(
-            services,
-            modules,
-            sorcerer.spell_storage.clone(),
-            spell_event_bus_api.clone(),
-            spell_service_api,
-            scopes.get_host_peer_id(),
-            builtins_peer_id,
-            system_service_distros,
-        );
-
         let versions = Versions::new(
             node_version.to_string(),
             air_version.to_string(),
@@ -452,7 +856,44 @@ impl<RT: AquaRuntime> Node<RT> {
             system_services_deployer.versions(),
         );
 
-        let chain_listener = setup_listener(connector, &config, core_manager).await?;
+        let host_key = HostKey::new(
+            root_key_pair.clone(),
+            config.host_key_rotation.clone(),
+            scopes.clone(),
+            audit_log.clone(),
+        );
+        let control = Control::new(
+            scopes.get_host_peer_id(),
+            versions.clone(),
+            connectivity.connection_pool.clone(),
+            services,
+            workers.clone(),
+            config_reloader.clone(),
+            log_control,
+            config.drain.clone(),
+            audit_log.clone(),
+            host_key.clone(),
+        );
+        let metrics_listener = config
+            .metrics_endpoint
+            .as_ref()
+            .zip(config.metrics_listen_addr())
+            .map(|(endpoint, listen_addr)| {
+                MetricsListener::new(listen_addr, endpoint, api_tokens.clone())
+            })
+            .transpose()?;
+        let graphql = config
+            .http_config
+            .as_ref()
+            .is_some_and(|c| c.graphql_enabled)
+            .then(|| node_schema(control.clone(), modules));
+        let control_socket_path = config
+            .control_socket_enabled
+            .then(|| config.dir_config.control_socket_path.clone());
+
+        let http_particles = config.http_client_key_pair.clone().map(|key_pair| {
+            HttpParticles::new(aquamarine_api.clone(), key_pair, scopes.get_host_peer_id())
+        });
 
         Ok(Self::with(
             particle_stream,
@@ -463,21 +904,46 @@ impl<RT: AquaRuntime> Node<RT> {
             dispatcher,
             aquamarine_backend,
             system_services_deployer,
+            lazy_system_services_deployer,
+            ipfs_pin_gc,
+            deal_prefetch,
+            system_services_supervisor,
+            trust_check,
             spell_event_bus_api,
             spell_event_bus,
             spell_events_receiver,
             sorcerer,
             metrics_registry,
+            metrics_enabled,
             health_registry,
+            system_services_health,
             libp2p_metrics,
+            dial_latency,
             services_metrics_backend,
             config.http_listen_addr(),
+            config.grpc_listen_addr(),
+            config.probe_listen_addr(),
+            metrics_listener,
+            http_auth,
+            http_particles,
+            graphql,
             builtins_peer_id,
             scopes,
             allow_local_addresses,
+            protocol_version,
             versions,
             chain_listener,
             workers.clone(),
+            certificate_manager,
+            relay_listeners,
+            port_mappings,
+            config.drain.clone(),
+            config_reloader,
+            audit_log,
+            control,
+            control_socket_path,
+            host_key,
+            startup_timings,
         ))
     }
 
@@ -485,6 +951,7 @@ impl<RT: AquaRuntime> Node<RT> {
         key_pair: Keypair,
         network_config: NetworkConfig,
         transport: Boxed<(PeerId, StreamMuxerBox)>,
+        relay_client: Option<libp2p::relay::client::Behaviour>,
         external_addresses: Vec<Multiaddr>,
         health_registry: Option<&mut HealthCheckRegistry>,
         metrics_registry: Option<&mut Registry>,
@@ -496,7 +963,7 @@ impl<RT: AquaRuntime> Node<RT> {
         let connection_idle_timeout = network_config.connection_idle_timeout;
 
         let (behaviour, connectivity, particle_stream) =
-            FluenceNetworkBehaviour::new(network_config, health_registry);
+            FluenceNetworkBehaviour::new(network_config, relay_client, health_registry);
 
         let mut swarm = match metrics_registry {
             None => SwarmBuilder::with_existing_identity(key_pair)
@@ -545,6 +1012,10 @@ impl<RT: AquaRuntime> Node<RT> {
 
 pub struct StartedNode {
     pub exit_outlet: oneshot::Sender<()>,
+    /// Stops the node like a crash would: connections aren't drained and seen particles aren't persisted
+    pub kill_outlet: oneshot::Sender<()>,
+    /// Completes once the node is stopped
+    pub stopped: task::JoinHandle<()>,
     pub http_listen_addr: Option<SocketAddr>,
 }
 
@@ -559,21 +1030,46 @@ impl<RT: AquaRuntime> Node<RT> {
         dispatcher: Dispatcher,
         aquamarine_backend: AquamarineBackend<RT, Arc<Builtins<Connectivity>>>,
         system_service_deployer: Deployer,
+        lazy_system_service_deployer: Option<Deployer>,
+        ipfs_pin_gc: Option<IpfsPinGc>,
+        deal_prefetch: Option<DealPrefetch>,
+        system_services_supervisor: SystemServicesSupervisor,
+        trust_check: Option<TrustCheck>,
         spell_event_bus_api: SpellEventBusApi,
         spell_event_bus: SpellEventBus,
         spell_events_receiver: mpsc::UnboundedReceiver<TriggerEvent>,
         sorcerer: Sorcerer,
         metrics_registry: Option<Registry>,
+        metrics_enabled: Option<Arc<AtomicBool>>,
         health_registry: Option<HealthCheckRegistry>,
+        system_services_health: Option<SystemServicesHealth>,
         libp2p_metrics: Option<Arc<Metrics>>,
+        dial_latency: Option<DialLatency>,
         services_metrics_backend: ServicesMetricsBackend,
         http_listen_addr: Option<SocketAddr>,
+        grpc_listen_addr: Option<SocketAddr>,
+        probe_listen_addr: Option<SocketAddr>,
+        metrics_listener: Option<MetricsListener>,
+        http_auth: HttpAuth,
+        http_particles: Option<HttpParticles>,
+        graphql: Option<NodeSchema>,
         builtins_management_peer_id: PeerId,
         scope: PeerScopes,
         allow_local_addresses: bool,
+        protocol_version: String,
         versions: Versions,
         chain_listener: Option<ChainListener>,
         workers: Arc<Workers>,
+        certificate_manager: Option<CertificateManager>,
+        relay_listeners: RelayListeners,
+        port_mappings: PortMappings,
+        drain: DrainConfig,
+        config_reloader: ConfigReloader,
+        audit_log: AuditLog,
+        control: Control,
+        control_socket_path: Option<PathBuf>,
+        host_key: HostKey,
+        startup_timings: LifecycleTimings,
     ) -> Box<Self> {
         let node_service = Self {
             particle_stream,
@@ -585,22 +1081,47 @@ impl<RT: AquaRuntime> Node<RT> {
             dispatcher,
             aquamarine_backend,
             system_service_deployer,
+            lazy_system_service_deployer,
+            ipfs_pin_gc,
+            deal_prefetch,
+            system_services_supervisor,
+            trust_check,
             spell_event_bus_api,
             spell_event_bus,
             spell_events_receiver,
             sorcerer,
 
             metrics_registry,
+            metrics_enabled,
             health_registry,
+            system_services_health,
             libp2p_metrics,
+            dial_latency,
             services_metrics_backend,
             http_listen_addr,
+            grpc_listen_addr,
+            probe_listen_addr,
+            metrics_listener,
+            http_auth,
+            http_particles,
+            graphql,
             builtins_management_peer_id,
             scope,
             allow_local_addresses,
+            protocol_version,
             versions,
             chain_listener,
             workers,
+            certificate_manager,
+            relay_listeners,
+            port_mappings,
+            drain,
+            config_reloader,
+            audit_log,
+            control,
+            control_socket_path,
+            host_key,
+            startup_timings,
         };
 
         Box::new(node_service)
@@ -610,33 +1131,99 @@ impl<RT: AquaRuntime> Node<RT> {
     #[allow(clippy::boxed_local)] // Mike said it should be boxed
     pub async fn start(self: Box<Self>, peer_id: PeerId) -> eyre::Result<StartedNode> {
         let (exit_outlet, exit_inlet) = oneshot::channel();
+        let (kill_outlet, mut kill_inlet) = oneshot::channel::<()>();
         let (http_bind_outlet, http_bind_inlet) = oneshot::channel();
 
         let particle_stream = self.particle_stream;
         let effects_stream = self.effects_stream;
         let mut swarm = self.swarm;
         let connectivity = self.connectivity;
+        let connection_pool = connectivity.connection_pool.clone();
         let dispatcher = self.dispatcher;
+        let seen_particles = dispatcher.seen_particles();
         let aquamarine_backend = self.aquamarine_backend;
         let spell_event_bus = self.spell_event_bus;
         let spell_events_receiver = self.spell_events_receiver;
         let sorcerer = self.sorcerer;
-        let metrics_registry = self.metrics_registry;
+        let metrics = self
+            .metrics_registry
+            .zip(self.metrics_enabled)
+            .map(|(registry, enabled)| MetricsEndpoint { registry, enabled });
         let health_registry = self.health_registry;
         let services_metrics_backend = self.services_metrics_backend;
         let http_listen_addr = self.http_listen_addr;
+        let grpc_listen_addr = self.grpc_listen_addr;
+        let probe_listen_addr = self.probe_listen_addr;
+        // metrics are served either by the main http endpoint or by the separate listener
+        let (metrics, separate_metrics) = match self.metrics_listener {
+            Some(listener) => (None, metrics.map(|metrics| (listener, metrics))),
+            None => (metrics, None),
+        };
+        let http_auth = self.http_auth;
+        let http_particles = self.http_particles;
+        let graphql = self.graphql;
         let task_name = format!("node-{peer_id}");
         let libp2p_metrics = self.libp2p_metrics;
+        let mut dial_latency = self.dial_latency;
         let allow_local_addresses = self.allow_local_addresses;
+        let protocol_version = self.protocol_version;
         let versions = self.versions;
         let workers = self.workers.clone();
         let chain_listener = self.chain_listener;
+        let certificate_manager = self.certificate_manager;
+        let acme_challenges = certificate_manager
+            .as_ref()
+            .map(CertificateManager::http_challenges);
+        let mut relay_listeners = self.relay_listeners;
+        let port_mappings = self.port_mappings;
+        let drain = self.drain;
+        let config_reloader = self.config_reloader;
+        let audit_log = self.audit_log;
+        let control = self.control;
+        let control_socket_path = self.control_socket_path;
+        let host_key = self.host_key;
+        let ipfs_pin_gc = self.ipfs_pin_gc;
+        let deal_prefetch = self.deal_prefetch;
+        let system_services_supervisor = self.system_services_supervisor;
+
+        let stopped = task::Builder::new().name(&task_name.clone()).spawn(async move {
+            let grpc_auth = http_auth.tokens.is_enabled() || http_auth.tls.is_some();
+            let mut grpc_server = match grpc_listen_addr {
+                Some(grpc_listen_addr) if grpc_auth => {
+                    tracing::info!("Starting grpc endpoint at {}", grpc_listen_addr);
+                    let api = GrpcApi::new(peer_id, versions.clone(), control.clone(), http_particles.clone(), audit_log.clone());
+                    let (tokens, tls) = (http_auth.tokens.clone(), http_auth.tls.clone());
+                    async move {
+                        start_grpc_endpoint(grpc_listen_addr, api, tokens, tls)
+                            .await.expect("Could not start grpc server");
+                    }.boxed()
+                }
+                Some(_) => {
+                    tracing::warn!("Grpc endpoint isn't started, it requires admin_token or admin_tls");
+                    futures::future::pending().boxed()
+                }
+                None => futures::future::pending().boxed(),
+            };
 
-        task::Builder::new().name(&task_name.clone()).spawn(async move {
+            let http_audit_log = audit_log.clone();
             let mut http_server = if let Some(http_listen_addr) = http_listen_addr {
                 tracing::info!("Starting http endpoint at {}", http_listen_addr);
+                let config = HttpEndpointConfig {
+                    listen_addr: http_listen_addr,
+                    peer_id,
+                    versions,
+                    auth: http_auth,
+                    metrics,
+                    health_registry,
+                    acme_challenges,
+                    config_reloader: Some(config_reloader),
+                    particles: http_particles,
+                    audit_log: Some(http_audit_log),
+                    graphql,
+                    probe_listen_addr,
+                };
                 async move {
-                    start_http_endpoint(http_listen_addr, metrics_registry, health_registry, peer_id, versions, http_bind_outlet)
+                    start_http_endpoint(config, http_bind_outlet)
                         .await.expect("Could not start http server");
                 }.boxed()
             } else {
@@ -644,53 +1231,176 @@ impl<RT: AquaRuntime> Node<RT> {
             };
 
 
+            let mut metrics_server = if let Some((listener, metrics)) = separate_metrics {
+                tracing::info!("Starting metrics endpoint at {}", listener.listen_addr);
+                async move {
+                    start_metrics_endpoint(listener, metrics)
+                        .await.expect("Could not start metrics server");
+                }.boxed()
+            } else {
+                futures::future::pending().boxed()
+            };
+
             let services_metrics_backend = services_metrics_backend.start();
             let spell_event_bus = spell_event_bus.start();
             let sorcerer = sorcerer.start(spell_events_receiver);
             let chain_listener = chain_listener.map(|c| c.start());
+            let certificate_manager = certificate_manager.and_then(|c| c.start());
+            let control_socket = control_socket_path.and_then(|path| control.clone().listen(path));
+            let host_key_retirement = host_key.start();
+            let ipfs_pin_gc = ipfs_pin_gc.map(IpfsPinGc::start);
+            let deal_prefetch = deal_prefetch.map(DealPrefetch::start);
+            let system_services_supervisor = system_services_supervisor.start();
             let aquamarine_backend = aquamarine_backend.start();
+            let connection_pool = connectivity.connection_pool.clone();
+            let peer_events = publish_peer_events(connection_pool.lifecycle_events(), audit_log);
             let mut connectivity = connectivity.start();
             let mut dispatcher = dispatcher.start(particle_stream, effects_stream);
+            relay_listeners.start(&mut swarm);
             let mut exit_inlet = Some(exit_inlet);
+            // kill outlet may be dropped, that doesn't stop the node
+            let mut killable = true;
+            let mut killed = false;
             loop {
                 let exit_inlet = exit_inlet.as_mut().expect("Could not get exit inlet");
                 tokio::select! {
                     Some(e) = swarm.next() => {
                         if let Some(m) = libp2p_metrics.as_ref() { m.record(&e) }
-                        if let SwarmEvent::Behaviour(FluenceNetworkBehaviourEvent::Identify(i)) = e {
-                            swarm.behaviour_mut().inject_identify_event(i, allow_local_addresses);
+                        if let Some(d) = dial_latency.as_mut() { d.record(&e) }
+                        match e {
+                            SwarmEvent::Behaviour(FluenceNetworkBehaviourEvent::Identify(i)) => {
+                                if let Some(m) = libp2p_metrics.as_ref() { m.record(&i) }
+                                swarm.behaviour_mut().inject_identify_event(i, allow_local_addresses, &protocol_version);
+                            }
+                            SwarmEvent::Behaviour(FluenceNetworkBehaviourEvent::Autonat(a)) => {
+                                relay_listeners.inject_autonat_event(&mut swarm, a);
+                            }
+                            SwarmEvent::Behaviour(FluenceNetworkBehaviourEvent::RelayClient(r)) => {
+                                log_relay_client_event(r);
+                            }
+                            SwarmEvent::Behaviour(FluenceNetworkBehaviourEvent::Dcutr(d)) => {
+                                if let Some(m) = libp2p_metrics.as_ref() { m.record(&d) }
+                                log_dcutr_event(d);
+                            }
+                            SwarmEvent::Behaviour(FluenceNetworkBehaviourEvent::Ping(p)) => {
+                                if let Some(m) = libp2p_metrics.as_ref() { m.record(&p) }
+                            }
+                            SwarmEvent::Behaviour(FluenceNetworkBehaviourEvent::RelayServer(r)) => {
+                                if let Some(m) = libp2p_metrics.as_ref() { m.record(&r) }
+                            }
+                            SwarmEvent::Behaviour(FluenceNetworkBehaviourEvent::PeerExchange(p)) => {
+                                swarm.behaviour_mut().inject_peer_exchange_event(p, allow_local_addresses);
+                            }
+                            SwarmEvent::Behaviour(FluenceNetworkBehaviourEvent::Upnp(u)) => {
+                                port_mappings.inject_upnp_event(u);
+                            }
+                            SwarmEvent::NewExternalAddrCandidate { address } => {
+                                confirm_ipv6_candidate(&mut swarm, address);
+                            }
+                            _ => {}
                         }
                     },
                     _ = &mut http_server => {},
+                    _ = &mut grpc_server => {},
+                    _ = &mut metrics_server => {},
                     _ = &mut connectivity => {},
                     _ = &mut dispatcher => {},
                     _ = exit_inlet => {
                         log::info!("Exit inlet");
                         break;
                     }
+                    result = &mut kill_inlet, if killable => match result {
+                        Ok(()) => {
+                            log::info!("Kill inlet");
+                            killed = true;
+                            break;
+                        }
+                        Err(_) => killable = false,
+                    },
                 }
             }
 
+            let shutdown_timings = LifecycleTimings::shutdown();
+            if !killed {
+                log::info!("Draining network connections");
+                let timeout = drain.timeout;
+                let drained = tokio::time::timeout(timeout, connection_pool.drain(drain.relays));
+                shutdown_timings.measure("drain", async {
+                    tokio::pin!(drained);
+                    loop {
+                        tokio::select! {
+                            // swarm is polled so particles and goodbyes are sent and connections are closed
+                            Some(_) = swarm.next() => {},
+                            result = &mut drained => {
+                                if result.is_err() {
+                                    log::warn!("Network drain didn't finish in {:?}, dropping connections", timeout);
+                                }
+                                break;
+                            }
+                        }
+                    }
+                }).await;
+            }
+
             log::info!("Stopping node");
             if let Some(c) = chain_listener { c.abort() }
+            if let Some(c) = certificate_manager { c.abort() }
+            if let Some(c) = control_socket { c.abort() }
+            if let Some(h) = host_key_retirement { h.abort() }
+            if let Some(g) = ipfs_pin_gc { g.abort() }
+            if let Some(p) = deal_prefetch { p.abort() }
+            system_services_supervisor.abort();
             services_metrics_backend.abort();
             spell_event_bus.abort();
             sorcerer.abort();
-            dispatcher.cancel().await;
-            connectivity.cancel().await;
+            peer_events.abort();
+            shutdown_timings.measure("dispatcher", dispatcher.cancel()).await;
+            if let Some(seen_particles) = seen_particles.filter(|_| !killed) {
+                shutdown_timings.measure("seen_particles", async {
+                    if let Err(err) = seen_particles.persist(now_millis::now_ms() as u64) {
+                        log::warn!("Failed to persist seen particles: {err}");
+                    }
+                }).await;
+            }
+            shutdown_timings.measure("connectivity", connectivity.cancel()).await;
             aquamarine_backend.abort();
             workers.shutdown();
+            shutdown_timings.report();
         }.in_current_span()).expect("Could not spawn task");
 
         // Note: need to be after the start of the node to be able to subscribe spells
+        let startup_timings = self.startup_timings;
         let deployer = self.system_service_deployer;
-        deployer
-            .deploy_system_services()
+        startup_timings
+            .measure("system_services", deployer.deploy_system_services())
             .await
             .context("deploying system services failed")?;
+        if let Some(health) = &self.system_services_health {
+            health.on_deployed();
+        }
+        if let Some(trust_check) = self.trust_check {
+            connection_pool.set_trust_check(Some(trust_check)).await;
+        }
+        if let Some(deployer) = self.lazy_system_service_deployer {
+            let timings = startup_timings.clone();
+            tokio::task::Builder::new()
+                .name("lazy-system-services")
+                .spawn(async move {
+                    let deployed = timings
+                        .measure("lazy_system_services", deployer.deploy_system_services())
+                        .await;
+                    if let Err(err) = deployed {
+                        log::error!("Deploying lazy system services failed: {err:?}");
+                    }
+                })
+                .expect("Could not spawn task");
+        }
 
-        self.spell_event_bus_api
-            .start_scheduling()
+        startup_timings
+            .measure(
+                "spell_scheduling",
+                self.spell_event_bus_api.start_scheduling(),
+            )
             .await
             .map_err(|e| eyre::eyre!("{e}"))
             .context("running spell event bus failed")?;
@@ -701,8 +1411,11 @@ impl<RT: AquaRuntime> Node<RT> {
         }))
         .await;
 
+        startup_timings.report();
         Ok(StartedNode {
             exit_outlet,
+            kill_outlet,
+            stopped,
             http_listen_addr,
         })
     }
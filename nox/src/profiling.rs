@@ -0,0 +1,82 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::time::Duration;
+
+use eyre::{eyre, WrapErr};
+
+/// Profiling longer than that is rejected, so a forgotten request doesn't slow the node down for long
+pub const MAX_PROFILE_DURATION: Duration = Duration::from_secs(60);
+pub const DEFAULT_PROFILE_DURATION: Duration = Duration::from_secs(10);
+
+/// Samples the CPU of all node threads for `duration`, returns the profile in pprof protobuf format
+pub async fn cpu_profile(duration: Duration) -> eyre::Result<Vec<u8>> {
+    tokio::task::spawn_blocking(move || {
+        use pprof::protos::Message;
+
+        let guard = pprof::ProfilerGuardBuilder::default()
+            .frequency(99)
+            .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+            .build()
+            .wrap_err("failed to start cpu profiler")?;
+        std::thread::sleep(duration);
+        let profile = guard
+            .report()
+            .build()
+            .wrap_err("failed to build cpu profile")?
+            .pprof()
+            .wrap_err("failed to convert cpu profile to pprof")?;
+
+        let mut body = Vec::new();
+        profile
+            .encode(&mut body)
+            .wrap_err("failed to encode cpu profile")?;
+        Ok(body)
+    })
+    .await
+    .wrap_err("cpu profiler task failed")?
+}
+
+/// Samples heap allocations for `duration`, returns the ones still live in pprof protobuf format.
+/// Available only when the node runs with jemalloc profiling, i.e. without the `dhat-heap` feature.
+pub async fn heap_profile(duration: Duration) -> eyre::Result<Vec<u8>> {
+    let prof_ctl = jemalloc_pprof::PROF_CTL
+        .as_ref()
+        .ok_or_else(|| eyre!("heap profiling isn't enabled"))?
+        .clone();
+    let mut prof_ctl = prof_ctl.lock_owned().await;
+    prof_ctl
+        .activate()
+        .map_err(|err| eyre!("failed to activate heap profiling: {err}"))?;
+
+    // sampling is deactivated by a separate task, so it stops even if the request is cancelled
+    tokio::spawn(async move {
+        tokio::time::sleep(duration).await;
+        tokio::task::spawn_blocking(move || {
+            let profile = prof_ctl
+                .dump_pprof()
+                .map_err(|err| eyre!("failed to dump heap profile: {err}"));
+            if let Err(err) = prof_ctl.deactivate() {
+                tracing::warn!("Failed to deactivate heap profiling: {err}");
+            }
+            profile
+        })
+        .await
+        .wrap_err("heap profiler task failed")?
+    })
+    .await
+    .wrap_err("heap profiler task failed")?
+}
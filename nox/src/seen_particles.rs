@@ -0,0 +1,317 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use particle_protocol::Particle;
+
+type ParticleHash = [u8; 32];
+
+/// Persisted entry is a particle hash followed by its deadline as little-endian u64
+const ENTRY_SIZE: usize = 32 + 8;
+
+/// Entries are grouped by their deadline into buckets of that width
+const BUCKET_MS: u64 = 100;
+
+/// Particles received from the network, remembered until their deadline.
+/// Particle is identified by its id, signature and data, so a particle that comes back
+/// with new data after visiting other peers isn't considered a duplicate.
+///
+/// Memory is bounded by `max_entries` regardless of the particle rate:
+/// entries closest to expiry are evicted first, in constant time per entry.
+#[derive(Clone)]
+pub struct SeenParticles {
+    inner: Arc<Mutex<Inner>>,
+    /// Where the cache is persisted, `None` to keep it in memory only
+    path: Option<PathBuf>,
+    max_entries: usize,
+}
+
+#[derive(Default)]
+struct Inner {
+    deadlines: HashMap<ParticleHash, u64>,
+    /// Hashes by the bucket of their deadline, expired buckets are dropped as a whole.
+    /// Hash is stale if its deadline has changed to a later bucket since it was added.
+    buckets: BTreeMap<u64, Vec<ParticleHash>>,
+    /// Entries evicted before their deadline since the last [SeenParticles::take_stats]
+    evicted: u64,
+    /// Whether there are changes that aren't persisted yet
+    dirty: bool,
+}
+
+pub struct SeenParticlesStats {
+    pub entries: usize,
+    /// Entries evicted before their deadline since the previous call, their duplicates aren't rejected
+    pub evicted: u64,
+}
+
+impl SeenParticles {
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            inner: <_>::default(),
+            path: None,
+            max_entries,
+        }
+    }
+
+    /// Creates cache persisted in `path`, loading entries that haven't expired yet from there if it exists
+    pub fn load(path: PathBuf, max_entries: usize, now_ms: u64) -> io::Result<Self> {
+        let contents = match std::fs::read(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => vec![],
+            Err(err) => return Err(err),
+        };
+
+        let mut this = Self::new(max_entries);
+        this.path = Some(path);
+        {
+            let mut inner = this.inner.lock();
+            // incomplete trailing entry is ignored
+            for entry in contents.chunks_exact(ENTRY_SIZE) {
+                let (hash, deadline) = entry.split_at(32);
+                let hash: ParticleHash = hash.try_into().expect("hash is 32 bytes");
+                let deadline =
+                    u64::from_le_bytes(deadline.try_into().expect("deadline is 8 bytes"));
+                if deadline > now_ms {
+                    inner.insert(hash, deadline);
+                }
+            }
+            inner.evict(max_entries, now_ms);
+            inner.evicted = 0;
+        }
+        Ok(this)
+    }
+
+    /// Remembers `particle`, returns `false` if it has been seen already and hasn't expired yet
+    pub fn insert(&self, particle: &Particle, now_ms: u64) -> bool {
+        let Some(deadline) = particle.deadline() else {
+            // particles with overflowing deadline are expired, so they're never executed anyway
+            return true;
+        };
+
+        let hash = hash(particle);
+        let mut inner = self.inner.lock();
+        if inner.deadlines.get(&hash).is_some_and(|d| *d > now_ms) {
+            return false;
+        }
+        inner.evict(self.max_entries.saturating_sub(1), now_ms);
+        inner.insert(hash, deadline);
+        inner.dirty = true;
+        true
+    }
+
+    pub fn take_stats(&self) -> SeenParticlesStats {
+        let mut inner = self.inner.lock();
+        SeenParticlesStats {
+            entries: inner.deadlines.len(),
+            evicted: std::mem::take(&mut inner.evicted),
+        }
+    }
+
+    /// Writes entries that haven't expired yet to disk, does nothing if nothing has changed
+    pub fn persist(&self, now_ms: u64) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let contents = {
+            let mut inner = self.inner.lock();
+            if !inner.dirty {
+                return Ok(());
+            }
+            inner.dirty = false;
+            inner.remove_expired(now_ms);
+            let mut contents = Vec::with_capacity(inner.deadlines.len() * ENTRY_SIZE);
+            let alive = inner.deadlines.iter().filter(|(_, d)| **d > now_ms);
+            for (hash, deadline) in alive {
+                contents.extend_from_slice(hash);
+                contents.extend_from_slice(&deadline.to_le_bytes());
+            }
+            contents
+        };
+
+        // write to a temporary file first, so a crash doesn't leave a half-written cache
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, contents)?;
+        std::fs::rename(tmp_path, path)
+    }
+}
+
+impl Inner {
+    fn insert(&mut self, hash: ParticleHash, deadline: u64) {
+        self.deadlines.insert(hash, deadline);
+        self.buckets
+            .entry(deadline / BUCKET_MS)
+            .or_default()
+            .push(hash);
+    }
+
+    /// Removes expired entries, then the ones closest to expiry until at most `max_entries` are left
+    fn evict(&mut self, max_entries: usize, now_ms: u64) {
+        self.remove_expired(now_ms);
+        while self.deadlines.len() > max_entries {
+            let Some(mut bucket) = self.buckets.first_entry() else {
+                break;
+            };
+            let bucket_id = *bucket.key();
+            let hash = bucket.get_mut().pop();
+            if bucket.get().is_empty() {
+                bucket.remove();
+            }
+            if let Some(hash) = hash {
+                if self.remove(&hash, bucket_id) {
+                    self.evicted += 1;
+                    self.dirty = true;
+                }
+            }
+        }
+    }
+
+    /// Removes buckets with all deadlines passed. Expired entries of a partially expired bucket
+    /// are kept until the whole bucket expires, lookups check the deadline anyway.
+    fn remove_expired(&mut self, now_ms: u64) {
+        while let Some(bucket) = self.buckets.first_entry() {
+            let bucket_id = *bucket.key();
+            if (bucket_id + 1) * BUCKET_MS > now_ms {
+                break;
+            }
+            for hash in bucket.remove() {
+                self.remove(&hash, bucket_id);
+            }
+        }
+    }
+
+    /// Removes `hash` if it hasn't moved to another bucket. Returns whether it was removed.
+    fn remove(&mut self, hash: &ParticleHash, bucket_id: u64) -> bool {
+        let current = self
+            .deadlines
+            .get(hash)
+            .is_some_and(|deadline| *deadline / BUCKET_MS == bucket_id);
+        if current {
+            self.deadlines.remove(hash);
+        }
+        current
+    }
+}
+
+fn hash(particle: &Particle) -> ParticleHash {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(particle.id.as_bytes());
+    hasher.update(&particle.signature);
+    hasher.update(&particle.data);
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn particle(id: &str, data: &[u8]) -> Particle {
+        Particle {
+            id: id.to_string(),
+            timestamp: 1000,
+            ttl: 1000,
+            data: data.to_vec(),
+            ..<_>::default()
+        }
+    }
+
+    #[test]
+    fn reject_duplicates() {
+        let seen = SeenParticles::new(10);
+        let first = particle("1", b"data");
+
+        assert!(seen.insert(&first, 1000));
+        assert!(!seen.insert(&first, 1500));
+        // same particle with different data after a hop
+        assert!(seen.insert(&particle("1", b"new data"), 1500));
+        // expired entries are forgotten
+        assert!(seen.insert(&first, 2500));
+    }
+
+    #[test]
+    fn evict_closest_to_expiry() {
+        let seen = SeenParticles::new(2);
+        let short = Particle {
+            ttl: 100,
+            ..particle("short", b"")
+        };
+        let medium = Particle {
+            ttl: 500,
+            ..particle("medium", b"")
+        };
+        let long = particle("long", b"");
+
+        assert!(seen.insert(&short, 1000));
+        assert!(seen.insert(&long, 1000));
+        // evicts short
+        assert!(seen.insert(&medium, 1000));
+        // evicts medium
+        assert!(seen.insert(&short, 1000));
+        assert!(!seen.insert(&long, 1000));
+    }
+
+    #[test]
+    fn count_evictions() {
+        let seen = SeenParticles::new(2);
+        let short = Particle {
+            ttl: 100,
+            ..particle("short", b"")
+        };
+
+        assert!(seen.insert(&short, 1000));
+        assert!(seen.insert(&particle("1", b""), 1000));
+        assert!(seen.insert(&particle("2", b""), 1000));
+        let stats = seen.take_stats();
+        assert_eq!(stats.entries, 2);
+        assert_eq!(stats.evicted, 1);
+
+        // expired entries aren't counted as evicted
+        let later = Particle {
+            timestamp: 2000,
+            ..particle("3", b"")
+        };
+        assert!(seen.insert(&later, 2500));
+        let stats = seen.take_stats();
+        assert_eq!(stats.entries, 1);
+        assert_eq!(stats.evicted, 0);
+    }
+
+    #[test]
+    fn persist_across_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("seen_particles");
+        let first = particle("1", b"data");
+        let short = Particle {
+            ttl: 100,
+            ..particle("2", b"data")
+        };
+
+        let seen = SeenParticles::load(path.clone(), 10, 1000).unwrap();
+        assert!(seen.insert(&first, 1000));
+        assert!(seen.insert(&short, 1000));
+        seen.persist(1000).unwrap();
+
+        let seen = SeenParticles::load(path, 10, 1500).unwrap();
+        assert!(!seen.insert(&first, 1500));
+        // expired before the restart
+        assert!(seen.insert(&short, 1500));
+    }
+}
@@ -0,0 +1,105 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use audit_log::{AuditEvent, AuditEventKind, AuditLog};
+use server_config::system_services_config::SupervisorConfig;
+use system_services::SpellProgress;
+use tokio::task::JoinHandle;
+use tokio::time::{interval_at, Instant, MissedTickBehavior};
+
+use crate::config_reload::ConfigReloader;
+use crate::health::SystemServicesHealth;
+
+/// Checks the enabled system services periodically, reports the failed ones through the health
+/// endpoint and redeploys them, so e.g. a crashed decider doesn't stop deal processing silently
+pub struct SystemServicesSupervisor {
+    reloader: ConfigReloader,
+    config: SupervisorConfig,
+    health: Option<SystemServicesHealth>,
+    audit_log: AuditLog,
+}
+
+impl SystemServicesSupervisor {
+    pub fn new(
+        reloader: ConfigReloader,
+        config: SupervisorConfig,
+        health: Option<SystemServicesHealth>,
+        audit_log: AuditLog,
+    ) -> Self {
+        Self {
+            reloader,
+            config,
+            health,
+            audit_log,
+        }
+    }
+
+    /// The first check is done after `check_interval`, so system services have time to deploy
+    pub fn start(self) -> JoinHandle<()> {
+        tokio::task::Builder::new()
+            .name("system-services-supervisor")
+            .spawn(async move {
+                let period = self.config.check_interval;
+                let mut interval = interval_at(Instant::now() + period, period);
+                interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                let mut progress = SpellProgress::default();
+                loop {
+                    interval.tick().await;
+                    self.check(&mut progress).await;
+                }
+            })
+            .expect("Could not spawn task")
+    }
+
+    async fn check(&self, progress: &mut SpellProgress) {
+        let failures = match self
+            .reloader
+            .check_system_services(progress, self.config.stalled_spell_periods)
+            .await
+        {
+            Ok(failures) => failures,
+            Err(err) => {
+                tracing::warn!("Could not check system services: {err:#}");
+                return;
+            }
+        };
+        if let Some(health) = &self.health {
+            health.on_checked(failures.clone());
+        }
+        if !self.config.restart {
+            for (name, reason) in failures {
+                tracing::warn!(name, "System service has failed: {reason}");
+            }
+            return;
+        }
+
+        let restarted = !failures.is_empty();
+        for (name, reason) in failures {
+            tracing::warn!(name, "System service has failed, redeploying: {reason}");
+            match self.reloader.redeploy_system_service(&name).await {
+                Ok(()) => self.audit_log.record(
+                    AuditEvent::new(AuditEventKind::SystemServiceRestarted, &name)
+                        .with_details(reason),
+                ),
+                Err(err) => tracing::error!(name, "Could not redeploy system service: {err:#}"),
+            }
+        }
+        if restarted {
+            // redeployed spells get time to run before they are checked again
+            *progress = SpellProgress::default();
+        }
+    }
+}
@@ -0,0 +1,227 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use avm_server::SecurityTetraplet;
+use connection_pool::TrustCheck;
+use eyre::eyre;
+use fluence_keypair::KeyPair;
+use libp2p::PeerId;
+use parking_lot::Mutex;
+use particle_args::Args;
+use particle_execution::{FunctionOutcome, ParticleParams};
+use particle_services::{ParticleAppServices, PeerScope};
+use serde_json::{json, Value as JValue};
+use server_config::TrustGraphConfig;
+use uuid_utils::uuid;
+
+const TRUST_GRAPH_SERVICE: &str = "trust-graph";
+const TRUST_GRAPH_TTL: Duration = Duration::from_secs(60);
+
+/// Host side of the deployed trust-graph service. Certificates are issued and revoked
+/// with the host key, weights are cached for the connection allowlist.
+#[derive(Clone)]
+pub struct TrustGraph {
+    services: ParticleAppServices,
+    host_peer_id: PeerId,
+    key_pair: KeyPair,
+    config: TrustGraphConfig,
+    weights: Arc<Mutex<HashMap<PeerId, (u32, Instant)>>>,
+}
+
+impl TrustGraph {
+    pub fn new(
+        services: ParticleAppServices,
+        host_peer_id: PeerId,
+        key_pair: KeyPair,
+        config: TrustGraphConfig,
+    ) -> Self {
+        Self {
+            services,
+            host_peer_id,
+            key_pair,
+            config,
+            weights: <_>::default(),
+        }
+    }
+
+    /// Weight of the peer in the trust graph, 0 if nobody trusts it
+    pub fn weight(&self, peer_id: PeerId) -> eyre::Result<u32> {
+        let result = self.call(
+            "get_weight",
+            vec![json!(peer_id.to_base58()), json!(now_millis::now_sec())],
+            Some(1),
+        )?;
+        let weight = result["weight"]
+            .as_u64()
+            .ok_or_else(|| eyre!("get_weight returned invalid result: {result}"))?;
+        let weight = u32::try_from(weight)?;
+        self.weights
+            .lock()
+            .insert(peer_id, (weight, Instant::now()));
+        Ok(weight)
+    }
+
+    /// Issues a certificate for `peer_id` signed by the host key until `expires_at_sec`.
+    /// Returns the new weight of the peer.
+    pub fn issue_trust(&self, peer_id: PeerId, expires_at_sec: u64) -> eyre::Result<u32> {
+        let issued_for = json!(peer_id.to_base58());
+        let issued_at = now_millis::now_sec();
+
+        let bytes = self.call(
+            "get_trust_bytes",
+            vec![issued_for.clone(), json!(expires_at_sec), json!(issued_at)],
+            None,
+        )?;
+        let signature = self.sign(&bytes["result"])?;
+        let trust = self.call(
+            "issue_trust",
+            vec![
+                issued_for,
+                json!(expires_at_sec),
+                json!(issued_at),
+                signature,
+            ],
+            None,
+        )?;
+        let result = self.call(
+            "add_trust",
+            vec![
+                trust["trust"].clone(),
+                json!(self.host_peer_id.to_base58()),
+                json!(issued_at),
+            ],
+            Some(2),
+        )?;
+        self.weights.lock().remove(&peer_id);
+
+        let weight = result["weight"]
+            .as_u64()
+            .ok_or_else(|| eyre!("add_trust returned invalid result: {result}"))?;
+        Ok(u32::try_from(weight)?)
+    }
+
+    /// Revokes trust in `peer_id` on behalf of the host key
+    pub fn revoke(&self, peer_id: PeerId) -> eyre::Result<()> {
+        let revoked = json!(peer_id.to_base58());
+        let revoked_at = now_millis::now_sec();
+
+        let bytes = self.call(
+            "get_revocation_bytes",
+            vec![revoked.clone(), json!(revoked_at)],
+            None,
+        )?;
+        let signature = self.sign(&bytes["result"])?;
+        let revocation = self.call(
+            "issue_revocation",
+            vec![
+                json!(self.host_peer_id.to_base58()),
+                revoked,
+                json!(revoked_at),
+                signature,
+            ],
+            None,
+        )?;
+        self.call(
+            "revoke",
+            vec![revocation["revocation"].clone(), json!(revoked_at)],
+            Some(1),
+        )?;
+        self.weights.lock().remove(&peer_id);
+        Ok(())
+    }
+
+    /// Allows peers with at least `allowlist_min_weight`, `None` if it isn't configured.
+    /// Weights are cached for `weight_cache_ttl`, peers whose weight can't be obtained aren't trusted.
+    pub fn trust_check(&self) -> Option<TrustCheck> {
+        let min_weight = self.config.allowlist_min_weight?;
+        let this = self.clone();
+        Some(TrustCheck::new(move |peer_id| {
+            let cached = this.weights.lock().get(peer_id).copied();
+            let weight = match cached {
+                Some((weight, at)) if at.elapsed() < this.config.weight_cache_ttl => weight,
+                _ => this.weight(*peer_id).unwrap_or_else(|err| {
+                    log::debug!("Failed to get trust graph weight of {peer_id}: {err}");
+                    0
+                }),
+            };
+            weight >= min_weight
+        }))
+    }
+
+    fn sign(&self, bytes: &JValue) -> eyre::Result<JValue> {
+        let bytes: Vec<u8> = serde_json::from_value(bytes.clone())
+            .map_err(|err| eyre!("trust-graph returned invalid bytes to sign: {err}"))?;
+        let signature = self
+            .key_pair
+            .sign(&bytes)
+            .map_err(|err| eyre!("failed to sign: {err}"))?;
+        Ok(json!(signature.to_vec()))
+    }
+
+    /// Calls trust-graph on the host. `timestamp_arg` is the position of an argument that
+    /// trust-graph requires to come from the host's `peer.timestamp_sec`.
+    fn call(
+        &self,
+        function_name: &str,
+        function_args: Vec<JValue>,
+        timestamp_arg: Option<usize>,
+    ) -> eyre::Result<JValue> {
+        let mut tetraplets = vec![vec![]; function_args.len()];
+        if let Some(position) = timestamp_arg {
+            tetraplets[position] = vec![SecurityTetraplet {
+                peer_pk: self.host_peer_id.to_base58(),
+                service_id: "peer".to_string(),
+                function_name: "timestamp_sec".to_string(),
+                lens: String::new(),
+            }];
+        }
+        let args = Args {
+            service_id: TRUST_GRAPH_SERVICE.to_string(),
+            function_name: function_name.to_string(),
+            function_args,
+            tetraplets,
+        };
+        let params = ParticleParams {
+            id: uuid(),
+            init_peer_id: self.host_peer_id,
+            peer_scope: PeerScope::Host,
+            timestamp: now_millis::now_ms() as u64,
+            ttl: TRUST_GRAPH_TTL.as_millis() as u32,
+            script: String::new(),
+            signature: vec![],
+            token: "host_call".to_string(),
+        };
+
+        match self.services.call_service(args, params, false) {
+            FunctionOutcome::Ok(result) if result["success"].as_bool() == Some(true) => Ok(result),
+            FunctionOutcome::Ok(result) => Err(eyre!(
+                "{TRUST_GRAPH_SERVICE}.{function_name} failed: {}",
+                result["error"].as_str().unwrap_or("unknown error")
+            )),
+            FunctionOutcome::NotDefined { .. } => {
+                Err(eyre!("{TRUST_GRAPH_SERVICE} isn't deployed on this node"))
+            }
+            FunctionOutcome::Empty => Err(eyre!(
+                "{TRUST_GRAPH_SERVICE}.{function_name} didn't return any result"
+            )),
+            FunctionOutcome::Err(err) => Err(eyre!(err)),
+        }
+    }
+}
@@ -32,7 +32,8 @@ use serde_json::{json, Value as JValue, Value};
 use tokio::sync::RwLock;
 use JValue::Array;
 
-use connection_pool::{ConnectionPoolApi, ConnectionPoolT};
+use audit_log::{arguments_hash, AuditEvent, AuditEventKind, AuditLog};
+use connection_pool::{ConnectionPoolApi, ConnectionPoolT, PeerFilterUpdate, PeerList};
 use health::HealthCheckRegistry;
 use kademlia::{KademliaApi, KademliaApiT};
 use now_millis::{now_ms, now_sec};
@@ -42,9 +43,10 @@ use particle_modules::{
     AddBlueprint, EffectorsMode, ModuleConfig, ModuleRepository, NamedModuleConfig, WASIConfig,
 };
 use particle_protocol::Contact;
-use particle_services::{ParticleAppServices, PeerScope, ServiceInfo, ServiceType};
+use particle_services::{AclEntry, ParticleAppServices, PeerScope, ServiceInfo, ServiceType};
 use peer_metrics::ServicesMetrics;
-use server_config::ServicesConfig;
+use server_config::{PolicyRule, Principal, ServicesConfig};
+use types::management::Capability;
 use types::peer_id;
 use uuid_utils::uuid;
 use workers::{KeyStorage, PeerScopes, Workers};
@@ -54,6 +56,7 @@ use crate::error::HostClosureCallError;
 use crate::error::HostClosureCallError::{DecodeBase58, DecodeUTF8};
 use crate::func::{binary, unary};
 use crate::outcome::{ok, wrap, wrap_unit};
+use crate::policy::BuiltinPolicy;
 use crate::{json, math};
 
 pub struct CustomService {
@@ -90,6 +93,10 @@ pub struct Builtins<C> {
     #[derivative(Debug = "ignore")]
     scopes: PeerScopes,
     connector_api_endpoint: String,
+    #[derivative(Debug = "ignore")]
+    audit_log: AuditLog,
+    #[derivative(Debug = "ignore")]
+    policy: Arc<BuiltinPolicy>,
 }
 
 impl<C> Builtins<C>
@@ -135,10 +142,45 @@ where
             key_storage,
             scopes: scope,
             connector_api_endpoint,
+            audit_log: <_>::default(),
+            policy: <_>::default(),
         }
     }
 
+    /// Denies calls forbidden by `policy`
+    pub fn with_policy(self, policy: Arc<BuiltinPolicy>) -> Self {
+        Self { policy, ..self }
+    }
+
+    /// Records services created and removed, peers banned and keys used into `audit_log`
+    pub fn with_audit_log(self, audit_log: AuditLog) -> Self {
+        Self { audit_log, ..self }
+    }
+
     pub async fn call(&self, args: Args, particle: ParticleParams) -> FunctionOutcome {
+        if let Err(err) = self.check_policy(&args, &particle) {
+            return FunctionOutcome::Err(err);
+        }
+
+        // builtin calls by the management peer and management keys are recorded
+        // to keep privileged actions accountable
+        let init_peer_id = particle.init_peer_id;
+        let is_manager = self.scopes.is_management(init_peer_id)
+            || self.scopes.management_keys().contains(&init_peer_id);
+        let management_call = is_manager.then(|| {
+            let event = AuditEvent::new(
+                AuditEventKind::ManagementOperation,
+                format!("{}.{}", args.service_id, args.function_name),
+            )
+            .with_actor(particle.init_peer_id);
+            let details = format!(
+                "particle_id={} args_hash={}",
+                particle.id,
+                arguments_hash(&args.function_args)
+            );
+            (event, details)
+        });
+
         let mut start = Instant::now();
         let result = self.builtins_call(args, particle).await;
         let result = match result {
@@ -156,11 +198,56 @@ where
                 if let Some(metrics) = self.services.metrics.as_ref() {
                     metrics.observe_builtins(result.not_err(), end as f64);
                 }
+                if let Some((event, details)) = management_call {
+                    let outcome = if result.not_err() { "ok" } else { "error" };
+                    self.audit_log
+                        .record(event.with_details(format!("{details} outcome={outcome}")));
+                }
                 result
             }
         }
     }
 
+    /// Checks the call against the builtin policy. The policy itself is managed only by the management peer,
+    /// so it can't lock the management peer out.
+    fn check_policy(&self, args: &Args, params: &ParticleParams) -> Result<(), JError> {
+        if args.service_id == "policy" {
+            return Ok(());
+        }
+
+        let on_host = matches!(params.peer_scope, PeerScope::Host);
+        let init_peer_id = params.init_peer_id;
+        let allowed = self.policy.allows(
+            &args.service_id,
+            &args.function_name,
+            on_host,
+            |principal| match principal {
+                Principal::Management => self.scopes.is_management(init_peer_id),
+                Principal::Host => self.scopes.is_host(init_peer_id),
+                Principal::Workers => {
+                    matches!(self.scopes.scope(init_peer_id), Ok(PeerScope::WorkerId(_)))
+                }
+                Principal::Peer(peer_id) => *peer_id == init_peer_id,
+            },
+        );
+        if allowed == Some(false) {
+            let builtin = format!("{}.{}", args.service_id, args.function_name);
+            let scope = match params.peer_scope {
+                PeerScope::Host => "host".to_string(),
+                PeerScope::WorkerId(worker_id) => worker_id.to_string(),
+            };
+            self.audit_log.record(
+                AuditEvent::new(AuditEventKind::BuiltinCallDenied, &builtin)
+                    .with_actor(init_peer_id)
+                    .with_details(scope),
+            );
+            return Err(JError::new(format!(
+                "{builtin} is denied by the builtin policy; init_peer_id={init_peer_id}"
+            )));
+        }
+        Ok(())
+    }
+
     pub async fn custom_service_call(
         &self,
         args: Args,
@@ -198,10 +285,19 @@ where
             ("peer", "connect") => wrap(self.connect(args).await),
             ("peer", "get_contact") => self.get_contact(args).await,
             ("peer", "timeout") => self.timeout(args).await,
+            ("peer", "scores") => wrap(self.peer_scores(particle).await),
+
+            ("peer_filter", "list") => wrap(self.peer_filter_list(particle).await),
+            ("peer_filter", "add") => wrap(self.peer_filter_add(args, particle).await),
+            ("peer_filter", "remove") => wrap(self.peer_filter_remove(args, particle).await),
+
+            ("policy", "list") => wrap(self.policy_list(particle)),
+            ("policy", "set") => wrap_unit(self.policy_set(args, particle)),
 
             ("kad", "neighborhood") => wrap(self.neighborhood(args).await),
             ("kad", "neigh_with_addrs") => wrap(self.neighborhood_with_addresses(args).await),
             ("kad", "merge") => wrap(self.kad_merge(args.function_args)),
+            ("kad", "republish") => wrap(self.kad_republish(particle).await),
 
             ("srv", "list") => ok(self.list_services(particle)),
             ("srv", "create") => wrap(self.create_service(args, particle).await),
@@ -211,6 +307,7 @@ where
             ("srv", "add_alias") => wrap_unit(self.add_alias(args, particle).await),
             ("srv", "remove") => wrap_unit(self.remove_service(args, particle).await),
             ("srv", "info") => wrap(self.get_service_info(args, particle)),
+            ("srv", "set_acl") => wrap_unit(self.set_service_acl(args, particle).await),
 
             ("dist", "add_module_from_vault") => wrap(self.add_module_from_vault(args, particle)),
             ("dist", "add_module") => wrap(self.add_module(args)),
@@ -351,6 +448,13 @@ where
         Ok(neighbors)
     }
 
+    /// Republishes Kademlia records published by this node, returns their number
+    async fn kad_republish(&self, params: ParticleParams) -> Result<JValue, JError> {
+        self.check_management(&params, "kad.republish")?;
+        let republished = self.kademlia().republish().await?;
+        Ok(json!(republished))
+    }
+
     async fn is_connected(&self, args: Args) -> Result<JValue, JError> {
         let peer: String = Args::next("peer_id", &mut args.function_args.into_iter())?;
         let peer = PeerId::from_str(peer.as_str())?;
@@ -371,6 +475,109 @@ where
         Ok(json!(ok))
     }
 
+    /// Reputation of peers that misbehaved recently, worst first
+    async fn peer_scores(&self, params: ParticleParams) -> Result<JValue, JError> {
+        if !self.scopes.is_host(params.init_peer_id) {
+            self.check_management(&params, "peer.scores")?;
+        }
+        let scores = self.connection_pool().peer_scores().await;
+        Ok(json!(scores))
+    }
+
+    fn check_management(&self, params: &ParticleParams, function: &str) -> Result<(), JError> {
+        if !self.scopes.is_management(params.init_peer_id) {
+            return Err(JError::new(format!(
+                "{function} can be called only by management peer id; init_peer_id={}",
+                params.init_peer_id
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_capability(
+        &self,
+        params: &ParticleParams,
+        function: &str,
+        capability: Capability,
+    ) -> Result<(), JError> {
+        if !self.scopes.has_capability(params.init_peer_id, capability) {
+            return Err(JError::new(format!(
+                "{function} can be called only by management peer id or a management key with the {capability} capability; init_peer_id={}",
+                params.init_peer_id
+            )));
+        }
+        Ok(())
+    }
+
+    /// Allowlist and denylist entries, both from config and added at runtime
+    async fn peer_filter_list(&self, params: ParticleParams) -> Result<JValue, JError> {
+        self.check_capability(&params, "peer_filter.list", Capability::BanPeers)?;
+        let entries = self.connection_pool().peer_filter().await;
+        Ok(json!(entries))
+    }
+
+    /// Adds peer id to "allowlist" or "denylist", returns false if it was already there
+    async fn peer_filter_add(&self, args: Args, params: ParticleParams) -> Result<JValue, JError> {
+        self.check_capability(&params, "peer_filter.add", Capability::BanPeers)?;
+        let mut args = args.function_args.into_iter();
+        let list: PeerList = Args::next("list", &mut args)?;
+        let peer_id: String = Args::next("peer_id", &mut args)?;
+        let peer_id = PeerId::from_str(peer_id.as_str())?;
+
+        let update = PeerFilterUpdate::Add { list, peer_id };
+        let changed = self.connection_pool().update_peer_filter(update).await?;
+        if changed && list == PeerList::Denylist {
+            self.audit_log.record(
+                AuditEvent::new(AuditEventKind::PeerBanned, peer_id)
+                    .with_actor(params.init_peer_id),
+            );
+        }
+        Ok(json!(changed))
+    }
+
+    /// Removes peer id added at runtime from "allowlist" or "denylist", returns false if it wasn't there
+    async fn peer_filter_remove(
+        &self,
+        args: Args,
+        params: ParticleParams,
+    ) -> Result<JValue, JError> {
+        self.check_capability(&params, "peer_filter.remove", Capability::BanPeers)?;
+        let mut args = args.function_args.into_iter();
+        let list: PeerList = Args::next("list", &mut args)?;
+        let peer_id: String = Args::next("peer_id", &mut args)?;
+        let peer_id = PeerId::from_str(peer_id.as_str())?;
+
+        let update = PeerFilterUpdate::Remove { list, peer_id };
+        let changed = self.connection_pool().update_peer_filter(update).await?;
+        if changed && list == PeerList::Denylist {
+            self.audit_log.record(
+                AuditEvent::new(AuditEventKind::PeerUnbanned, peer_id)
+                    .with_actor(params.init_peer_id),
+            );
+        }
+        Ok(json!(changed))
+    }
+
+    /// Builtin policy rules, both from config and set at runtime
+    fn policy_list(&self, params: ParticleParams) -> Result<JValue, JError> {
+        self.check_management(&params, "policy.list")?;
+        Ok(json!(self.policy.rules().rules))
+    }
+
+    /// Replaces builtin policy rules set at runtime, rules from config stay
+    fn policy_set(&self, args: Args, params: ParticleParams) -> Result<(), JError> {
+        self.check_management(&params, "policy.set")?;
+        let rules: Vec<PolicyRule> = Args::next("rules", &mut args.function_args.into_iter())?;
+        self.policy
+            .set_runtime(rules)
+            .map_err(|err| JError::new(format!("Failed to set builtin policy: {err}")))?;
+        self.audit_log.record(
+            AuditEvent::new(AuditEventKind::BuiltinPolicyChanged, "builtin_policy")
+                .with_actor(params.init_peer_id),
+        );
+        Ok(())
+    }
+
     async fn get_contact(&self, args: Args) -> FunctionOutcome {
         let peer: String = Args::next("peer_id", &mut args.function_args.into_iter())?;
         let peer = PeerId::from_str(peer.as_str())?;
@@ -781,11 +988,16 @@ where
             .create_service(
                 params.peer_scope,
                 ServiceType::Service,
-                blueprint_id,
+                blueprint_id.clone(),
                 params.init_peer_id,
             )
             .await?;
 
+        self.audit_log.record(
+            AuditEvent::new(AuditEventKind::ServiceCreated, &service_id)
+                .with_actor(params.init_peer_id)
+                .with_details(format!("blueprint_id={blueprint_id}")),
+        );
         Ok(JValue::String(service_id))
     }
 
@@ -802,6 +1014,49 @@ where
             )
             .await?;
 
+        self.audit_log.record(
+            AuditEvent::new(AuditEventKind::ServiceRemoved, service_id_or_alias)
+                .with_actor(params.init_peer_id),
+        );
+        Ok(())
+    }
+
+    async fn set_service_acl(&self, args: Args, params: ParticleParams) -> Result<(), JError> {
+        let mut args = args.function_args.into_iter();
+        let service_id_or_alias: String = Args::next("service_id_or_alias", &mut args)?;
+        let acl: Option<Vec<String>> = Args::next_opt("acl", &mut args)?;
+        let acl = acl
+            .map(|entries| {
+                entries
+                    .iter()
+                    .map(|e| e.parse::<AclEntry>())
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .transpose()
+            .map_err(JError::new)?;
+
+        let details = match &acl {
+            Some(acl) => {
+                let entries: Vec<_> = acl.iter().map(ToString::to_string).collect();
+                format!("acl=[{}]", entries.join(", "))
+            }
+            None => "acl=unrestricted".to_string(),
+        };
+        self.services
+            .set_acl(
+                params.peer_scope,
+                service_id_or_alias.clone(),
+                acl,
+                params.init_peer_id,
+                &params.id,
+            )
+            .await?;
+
+        self.audit_log.record(
+            AuditEvent::new(AuditEventKind::ServiceAclChanged, service_id_or_alias)
+                .with_actor(params.init_peer_id)
+                .with_details(details),
+        );
         Ok(())
     }
 
@@ -970,7 +1225,13 @@ where
             }
 
             let keypair = self.key_storage.get_keypair(params.peer_scope).unwrap(); //TODO: fix unwrap
-            json!(keypair.sign(&data)?.to_vec())
+            let signature = keypair.sign(&data)?.to_vec();
+            self.audit_log.record(
+                AuditEvent::new(AuditEventKind::KeyUsed, keypair.get_peer_id())
+                    .with_actor(params.init_peer_id)
+                    .with_details("sig.sign"),
+            );
+            json!(signature)
         };
 
         match result {
@@ -32,6 +32,7 @@
 pub use builtins::{Builtins, CustomService};
 pub use identify::NodeInfo;
 pub use outcome::{ok, wrap, wrap_unit};
+pub use policy::BuiltinPolicy;
 
 mod builtins;
 mod debug;
@@ -42,3 +43,4 @@ mod json;
 mod math;
 mod outcome;
 mod particle_function;
+mod policy;
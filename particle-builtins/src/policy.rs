@@ -0,0 +1,157 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::io;
+use std::path::PathBuf;
+
+use parking_lot::RwLock;
+use server_config::{BuiltinPolicyConfig, PolicyRule, Principal};
+
+#[derive(Default)]
+struct PolicyState {
+    /// Rules from the node config, replaced on config reload
+    configured: BuiltinPolicyConfig,
+    /// Rules set at runtime by the management peer
+    runtime: BuiltinPolicyConfig,
+    /// Configured rules followed by the runtime ones
+    effective: BuiltinPolicyConfig,
+}
+
+/// Who can call privileged builtins, evaluated for every call, see [BuiltinPolicyConfig]
+#[derive(Default)]
+pub struct BuiltinPolicy {
+    state: RwLock<PolicyState>,
+    /// Where runtime rules are persisted, `None` to keep them in memory only
+    path: Option<PathBuf>,
+}
+
+impl BuiltinPolicy {
+    pub fn new(config: BuiltinPolicyConfig) -> Self {
+        let this = Self::default();
+        this.set_configured(config);
+        this
+    }
+
+    /// Creates policy with runtime rules persisted in `path`, loading them from there if it exists
+    pub fn load(config: BuiltinPolicyConfig, path: PathBuf) -> io::Result<Self> {
+        let runtime = match std::fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => BuiltinPolicyConfig::default(),
+            Err(err) => return Err(err),
+        };
+
+        let this = Self {
+            path: Some(path),
+            ..Self::default()
+        };
+        this.state.write().runtime = runtime;
+        this.set_configured(config);
+        Ok(this)
+    }
+
+    pub fn set_configured(&self, config: BuiltinPolicyConfig) {
+        let mut state = self.state.write();
+        state.configured = config;
+        state.update_effective();
+    }
+
+    /// Replaces runtime rules and persists them
+    pub fn set_runtime(&self, rules: Vec<PolicyRule>) -> eyre::Result<()> {
+        let runtime = BuiltinPolicyConfig { rules };
+        runtime.validate()?;
+        if let Some(path) = &self.path {
+            std::fs::write(path, toml::to_string(&runtime)?)?;
+        }
+
+        let mut state = self.state.write();
+        state.runtime = runtime;
+        state.update_effective();
+        Ok(())
+    }
+
+    /// Both configured and runtime rules
+    pub fn rules(&self) -> BuiltinPolicyConfig {
+        self.state.read().effective.clone()
+    }
+
+    /// See [BuiltinPolicyConfig::allows]
+    pub fn allows(
+        &self,
+        service: &str,
+        function: &str,
+        on_host: bool,
+        is_caller: impl Fn(&Principal) -> bool,
+    ) -> Option<bool> {
+        self.state
+            .read()
+            .effective
+            .allows(service, function, on_host, is_caller)
+    }
+}
+
+impl PolicyState {
+    fn update_effective(&mut self) {
+        let rules = self.configured.rules.iter().chain(&self.runtime.rules);
+        self.effective = BuiltinPolicyConfig {
+            rules: rules.cloned().collect(),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use server_config::PolicyScope;
+
+    use super::*;
+
+    fn rule(builtin: &str, allow: Principal) -> PolicyRule {
+        PolicyRule {
+            builtins: vec![builtin.to_string()],
+            scope: Some(PolicyScope::Host),
+            allow: vec![allow],
+        }
+    }
+
+    #[test]
+    fn persist_runtime_rules() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("builtin_policy.toml");
+        let configured = BuiltinPolicyConfig {
+            rules: vec![rule("dist.*", Principal::Management)],
+        };
+        let is_host = |p: &Principal| *p == Principal::Host;
+
+        let policy = BuiltinPolicy::load(configured.clone(), path.clone()).unwrap();
+        policy
+            .set_runtime(vec![rule("spell.install", Principal::Host)])
+            .unwrap();
+        assert!(policy
+            .set_runtime(vec![rule("spell", Principal::Host)])
+            .is_err());
+        assert_eq!(policy.rules().rules.len(), 2);
+
+        let policy = BuiltinPolicy::load(configured, path).unwrap();
+        assert_eq!(policy.allows("spell", "install", true, is_host), Some(true));
+        assert_eq!(
+            policy.allows("dist", "add_module", true, is_host),
+            Some(false)
+        );
+
+        policy.set_configured(<_>::default());
+        assert_eq!(policy.allows("dist", "add_module", true, is_host), None);
+    }
+}
@@ -34,11 +34,20 @@ pub const VIRTUAL_PARTICLE_VAULT_PREFIX: &str = "/tmp/vault";
 #[derive(Debug, Clone)]
 pub struct ParticleVault {
     vault_dir: PathBuf,
+    /// Files put to a particle vault can't take more bytes than that
+    max_size: Option<u64>,
 }
 
 impl ParticleVault {
     pub fn new(vault_dir: PathBuf) -> Self {
-        Self { vault_dir }
+        Self {
+            vault_dir,
+            max_size: None,
+        }
+    }
+
+    pub fn with_max_size(self, max_size: Option<u64>) -> Self {
+        Self { max_size, ..self }
     }
 
     pub fn real_worker_particle_vault(&self, peer_id: PeerId) -> PathBuf {
@@ -107,6 +116,13 @@ impl ParticleVault {
         // but `to_real_path` do path normalization which requires existence of the file to resolve
         // symlinks.
         let real_path = vault_dir.join(&filename);
+        if let Some(max_size) = self.max_size {
+            let size =
+                dir_size(&vault_dir).map_err(|e| VaultError::WriteVault(e, filename.clone()))?;
+            if size + payload.len() as u64 > max_size {
+                return Err(VaultError::SizeLimit(max_size, filename));
+            }
+        }
         if let Some(parent_path) = real_path.parent() {
             create_dir_write_only(parent_path).map_err(CreateVault)?;
         }
@@ -246,4 +262,26 @@ pub enum VaultError {
     ReadVault(#[source] std::io::Error, PathBuf),
     #[error("Write vault failed for filename `{1}`: {0}")]
     WriteVault(#[source] std::io::Error, String),
+    #[error("Write vault failed for filename `{1}`: particle vault can't exceed {0} bytes")]
+    SizeLimit(u64, String),
+}
+
+/// Total size of files in `path`, 0 if it doesn't exist
+fn dir_size(path: &Path) -> std::io::Result<u64> {
+    let entries = match std::fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err),
+    };
+    let mut size = 0;
+    for entry in entries {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(size)
 }
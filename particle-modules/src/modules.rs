@@ -70,7 +70,7 @@ pub struct ModuleRepository {
     blueprints_dir: PathBuf,
     module_interface_cache: Arc<RwLock<HashMap<Hash, JValue>>>,
     blueprints: Arc<RwLock<HashMap<String, Blueprint>>>,
-    effectors: EffectorsMode,
+    effectors: Arc<RwLock<EffectorsMode>>,
 }
 
 impl ModuleRepository {
@@ -83,17 +83,23 @@ impl ModuleRepository {
             blueprints_dir: blueprints_dir.to_path_buf(),
             module_interface_cache: <_>::default(),
             blueprints: blueprints_cache,
-            effectors,
+            effectors: Arc::new(RwLock::new(effectors)),
         }
     }
 
+    /// Replaces allowed effectors. Modules added before keep their mounted binaries.
+    pub fn set_effectors(&self, effectors: EffectorsMode) {
+        *self.effectors.write() = effectors;
+    }
+
     fn make_effectors_config(
         &self,
         module_name: &str,
         module_hash: &Hash,
         mounted_binaries: HashSet<String>,
-    ) -> Result<&HashMap<String, PathBuf>> {
-        let binaries = match &self.effectors {
+    ) -> Result<HashMap<String, PathBuf>> {
+        let effectors = self.effectors.read();
+        let binaries = match &*effectors {
             EffectorsMode::RestrictedEffectors { effectors } => effectors
                 .iter()
                 .find(|(effector_hash, _)| effector_hash == &module_hash)
@@ -117,7 +123,7 @@ impl ModuleRepository {
             }
         }
 
-        Ok(binaries)
+        Ok(binaries.clone())
     }
 
     pub fn add_module(&self, name: String, module: Vec<u8>) -> Result<Hash> {
@@ -128,7 +134,7 @@ impl ModuleRepository {
             .not()
             .then(|| self.make_effectors_config(&name, &hash, mounted))
             .transpose()?;
-        let config = Self::make_config(name, logger_enabled, effector_settings);
+        let config = Self::make_config(name, logger_enabled, effector_settings.as_ref());
         let _config = files::add_module(&self.modules_dir, &hash, &module, config)?;
 
         Ok(hash)
@@ -0,0 +1,209 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::borrow::Cow;
+use std::io;
+
+use bytesize::ByteSize;
+use serde::{Deserialize, Serialize};
+
+/// Tags of the first byte of an encoded frame
+const UNCOMPRESSED: u8 = 0;
+const ZSTD: u8 = 1;
+const LZ4: u8 = 2;
+
+const ZSTD_LEVEL: i32 = 3;
+
+/// Prefix of compressed data at rest, distinguishes it from uncompressed data written before.
+/// AquaVM data, either msgpack or JSON, never starts with a zero byte.
+const STORED_MAGIC: &[u8] = b"\0FLC";
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionAlgorithm {
+    #[default]
+    Zstd,
+    Lz4,
+}
+
+/// Compression of particle data. Receiving side supports all algorithms,
+/// so only the sending side has to be configured.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct Compression {
+    pub enabled: bool,
+    pub algorithm: CompressionAlgorithm,
+    /// Data smaller than that is left uncompressed
+    pub threshold: ByteSize,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            algorithm: CompressionAlgorithm::default(),
+            threshold: ByteSize::kib(64),
+        }
+    }
+}
+
+impl Compression {
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..Self::default()
+        }
+    }
+
+    fn should_compress(&self, data: &[u8]) -> bool {
+        self.enabled && data.len() as u64 >= self.threshold.as_u64()
+    }
+
+    /// Compresses `data`, returns `None` if compressed data isn't smaller
+    fn compress(&self, data: &[u8]) -> io::Result<Option<(u8, Vec<u8>)>> {
+        let (tag, compressed) = match self.algorithm {
+            CompressionAlgorithm::Zstd => (ZSTD, zstd::bulk::compress(data, ZSTD_LEVEL)?),
+            CompressionAlgorithm::Lz4 => (LZ4, lz4_flex::compress_prepend_size(data)),
+        };
+        Ok((compressed.len() < data.len()).then_some((tag, compressed)))
+    }
+
+    /// Tag and payload of the frame for `data`, uncompressed payload borrows `data`
+    pub(crate) fn frame<'a>(&self, data: &'a [u8]) -> io::Result<(u8, Cow<'a, [u8]>)> {
+        let compressed = if self.should_compress(data) {
+            self.compress(data)?
+        } else {
+            None
+        };
+        Ok(match compressed {
+            Some((tag, compressed)) => (tag, Cow::Owned(compressed)),
+            None => (UNCOMPRESSED, Cow::Borrowed(data)),
+        })
+    }
+
+    /// Frame of the compressed protocol: `data` prepended by the tag of the algorithm it's compressed with
+    pub fn encode(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let (tag, payload) = self.frame(data)?;
+        let mut frame = Vec::with_capacity(payload.len() + 1);
+        frame.push(tag);
+        frame.extend_from_slice(&payload);
+        Ok(frame)
+    }
+
+    /// Compresses `data` to be stored on disk, data below the threshold is stored as is
+    pub fn compress_stored<'a>(&self, data: &'a [u8]) -> io::Result<Cow<'a, [u8]>> {
+        if !self.should_compress(data) {
+            return Ok(Cow::Borrowed(data));
+        }
+        match self.compress(data)? {
+            Some((tag, compressed)) => {
+                let mut stored = Vec::with_capacity(STORED_MAGIC.len() + 1 + compressed.len());
+                stored.extend_from_slice(STORED_MAGIC);
+                stored.push(tag);
+                stored.extend_from_slice(&compressed);
+                Ok(Cow::Owned(stored))
+            }
+            None => Ok(Cow::Borrowed(data)),
+        }
+    }
+}
+
+/// Reverses [Compression::encode], fails if decompressed data is larger than `max_size`
+pub fn decode(frame: &[u8], max_size: usize) -> io::Result<Cow<'_, [u8]>> {
+    let (tag, payload) = frame
+        .split_first()
+        .ok_or_else(|| invalid_data("empty compressed frame"))?;
+    match *tag {
+        UNCOMPRESSED => Ok(Cow::Borrowed(payload)),
+        ZSTD => zstd::bulk::decompress(payload, max_size).map(Cow::Owned),
+        LZ4 => {
+            let size = payload
+                .get(..4)
+                .map(|size| u32::from_le_bytes([size[0], size[1], size[2], size[3]]) as usize)
+                .ok_or_else(|| invalid_data("lz4 frame is too short"))?;
+            if size > max_size {
+                return Err(invalid_data(format!(
+                    "decompressed size {size} exceeds the limit of {max_size} bytes"
+                )));
+            }
+            lz4_flex::decompress_size_prepended(payload)
+                .map(Cow::Owned)
+                .map_err(invalid_data)
+        }
+        tag => Err(invalid_data(format!("unknown compression tag {tag}"))),
+    }
+}
+
+/// Reverses [Compression::compress_stored], uncompressed data is returned as is
+pub fn decompress_stored(data: &[u8]) -> io::Result<Cow<'_, [u8]>> {
+    match data.strip_prefix(STORED_MAGIC) {
+        Some(frame) => decode(frame, usize::MAX),
+        None => Ok(Cow::Borrowed(data)),
+    }
+}
+
+fn invalid_data<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compression(algorithm: CompressionAlgorithm) -> Compression {
+        Compression {
+            enabled: true,
+            algorithm,
+            threshold: ByteSize::b(16),
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let data = "canon result ".repeat(100).into_bytes();
+
+        for algorithm in [CompressionAlgorithm::Zstd, CompressionAlgorithm::Lz4] {
+            let compression = compression(algorithm);
+
+            let frame = compression.encode(&data).unwrap();
+            assert_ne!(frame[0], UNCOMPRESSED);
+            assert!(frame.len() < data.len());
+            assert_eq!(decode(&frame, data.len()).unwrap(), data.as_slice());
+            assert!(decode(&frame, data.len() - 1).is_err());
+
+            let stored = compression.compress_stored(&data).unwrap();
+            assert!(stored.starts_with(STORED_MAGIC));
+            assert_eq!(decompress_stored(&stored).unwrap(), data.as_slice());
+        }
+    }
+
+    #[test]
+    fn small_data_is_not_compressed() {
+        let data = b"small".to_vec();
+        let compression = compression(CompressionAlgorithm::Zstd);
+
+        let frame = compression.encode(&data).unwrap();
+        assert_eq!(frame[0], UNCOMPRESSED);
+        assert_eq!(decode(&frame, data.len()).unwrap(), data.as_slice());
+
+        let stored = compression.compress_stored(&data).unwrap();
+        assert_eq!(stored, data.as_slice());
+        assert_eq!(decompress_stored(&stored).unwrap(), data.as_slice());
+    }
+}
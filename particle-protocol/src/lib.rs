@@ -32,17 +32,31 @@ mod libp2p_protocol {
     pub(super) mod upgrade;
 }
 
+mod compression;
 mod contact;
 mod error;
 mod particle;
+mod rejection;
 
+pub use compression::{decompress_stored, Compression, CompressionAlgorithm};
 pub use contact::Contact;
 pub use error::ParticleError;
 pub use libp2p_protocol::message::CompletionChannel;
 pub use libp2p_protocol::message::SendStatus;
 pub use libp2p_protocol::message::{HandlerMessage, ProtocolMessage};
 pub use libp2p_protocol::upgrade::ProtocolConfig;
-pub use particle::ExtendedParticle;
 pub use particle::Particle;
+pub use particle::{ExtendedParticle, ParticleTimings};
+pub use rejection::{Rejection, RejectionNotice, REJECTION_FUNCTION, REJECTION_SERVICE};
 
 pub const PROTOCOL_NAME: &str = "/fluence/particle/2.0.0";
+/// Same as [PROTOCOL_NAME], but frames are tagged with compression of their contents
+pub const COMPRESSED_PROTOCOL_NAME: &str = "/fluence/particle/2.1.0";
+
+/// Protocol version advertised via Identify by nodes of the network named `network_name`
+pub fn protocol_version(network_name: Option<&str>) -> String {
+    match network_name {
+        None => PROTOCOL_NAME.to_string(),
+        Some(name) => format!("{PROTOCOL_NAME}/{name}"),
+    }
+}
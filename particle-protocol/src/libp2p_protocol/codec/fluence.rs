@@ -1,11 +1,14 @@
+use crate::compression::{self, Compression};
 use crate::ProtocolMessage;
 use air_interpreter_sede::{
     define_simple_representation, Format as SedeFormat, FromSerialized as _, MsgPackMultiformat,
     ToSerialized as _,
 };
 use asynchronous_codec::{BytesMut, Decoder, Encoder};
+use std::borrow::Cow;
 use std::io;
 use unsigned_varint::codec::UviBytes;
+use unsigned_varint::encode as varint;
 
 const MAX_BUF_SIZE: usize = 100 * 1024 * 1024;
 
@@ -20,13 +23,26 @@ define_simple_representation!(
 
 pub struct FluenceCodec {
     length: UviBytes<BytesMut>,
+    /// Set for the compressed protocol, where every frame is tagged with its compression
+    compression: Option<Compression>,
 }
 
 impl FluenceCodec {
     pub fn new() -> Self {
         let mut length: UviBytes<BytesMut> = UviBytes::default();
         length.set_max_len(MAX_BUF_SIZE);
-        Self { length }
+        Self {
+            length,
+            compression: None,
+        }
+    }
+
+    /// Codec of the compressed protocol, outgoing messages are compressed according to `compression`
+    pub fn compressed(compression: Compression) -> Self {
+        Self {
+            compression: Some(compression),
+            ..Self::new()
+        }
     }
 }
 
@@ -35,8 +51,14 @@ impl Decoder for FluenceCodec {
     type Error = FluenceCodecError;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        // frame is split off `src` without copying, uncompressed frames are deserialized in place
         let bytes = self.length.decode(src)?;
         if let Some(bytes) = bytes {
+            let bytes = match self.compression {
+                Some(_) => compression::decode(&bytes, MAX_BUF_SIZE)
+                    .map_err(FluenceCodecError::Compression)?,
+                None => Cow::Borrowed(&bytes[..]),
+            };
             return ProtocolMessageRepresentation
                 .deserialize(&bytes)
                 .map(Some)
@@ -54,7 +76,30 @@ impl Encoder for FluenceCodec {
         let msg_buf = ProtocolMessageRepresentation
             .serialize(&item)
             .map_err(FluenceCodecError::Serialize)?;
-        self.length.encode(msg_buf[..].into(), dst)?;
+        let (tag, payload) = match &self.compression {
+            Some(compression) => {
+                let (tag, payload) = compression
+                    .frame(&msg_buf)
+                    .map_err(FluenceCodecError::Compression)?;
+                (Some(tag), payload)
+            }
+            None => (None, Cow::Borrowed(&msg_buf[..])),
+        };
+
+        // frame is written straight to `dst`, so the message is copied only once
+        let len = payload.len() + usize::from(tag.is_some());
+        if len > MAX_BUF_SIZE {
+            return Err(FluenceCodecError::Length(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("message of {len} bytes exceeds the limit of {MAX_BUF_SIZE} bytes"),
+            )));
+        }
+        let mut len_buf = varint::usize_buffer();
+        let len_prefix = varint::usize(len, &mut len_buf);
+        dst.reserve(len_prefix.len() + len);
+        dst.extend_from_slice(len_prefix);
+        dst.extend(tag);
+        dst.extend_from_slice(&payload);
         Ok(())
     }
 }
@@ -65,6 +110,7 @@ pub enum FluenceCodecError {
     Io(std::io::Error),
     /// Length error
     Length(std::io::Error),
+    Compression(std::io::Error),
     Serialize(<ProtocolMessageFormat as SedeFormat<ProtocolMessage>>::SerializationError),
     Deserialize(<ProtocolMessageFormat as SedeFormat<ProtocolMessage>>::DeserializationError),
 }
@@ -80,6 +126,7 @@ impl std::error::Error for FluenceCodecError {
         match self {
             FluenceCodecError::Io(ref e) => Some(e),
             FluenceCodecError::Length(ref e) => Some(e),
+            FluenceCodecError::Compression(ref e) => Some(e),
             FluenceCodecError::Serialize(ref e) => Some(e),
             FluenceCodecError::Deserialize(ref e) => Some(e),
         }
@@ -91,6 +138,7 @@ impl std::fmt::Display for FluenceCodecError {
         match self {
             FluenceCodecError::Io(e) => write!(f, "I/O error: {}", e),
             FluenceCodecError::Length(e) => write!(f, "I/O error: {}", e),
+            FluenceCodecError::Compression(e) => write!(f, "Compression error: {}", e),
             FluenceCodecError::Serialize(e) => write!(f, "Serialization error: {}", e),
             FluenceCodecError::Deserialize(e) => write!(f, "Deserialization error: {}", e),
         }
@@ -102,6 +150,7 @@ impl From<FluenceCodecError> for std::io::Error {
         match value {
             FluenceCodecError::Io(e) => e,
             FluenceCodecError::Length(e) => io::Error::new(io::ErrorKind::InvalidInput, e),
+            FluenceCodecError::Compression(e) => e,
             FluenceCodecError::Serialize(e) => io::Error::new(io::ErrorKind::InvalidInput, e),
             FluenceCodecError::Deserialize(e) => io::Error::new(io::ErrorKind::InvalidInput, e),
         }
@@ -110,12 +159,15 @@ impl From<FluenceCodecError> for std::io::Error {
 
 #[cfg(test)]
 mod tests {
+    use super::ProtocolMessageRepresentation;
     use crate::libp2p_protocol::codec::FluenceCodec;
-    use crate::{Particle, ProtocolMessage};
+    use crate::{Compression, Particle, ProtocolMessage};
+    use air_interpreter_sede::ToSerialized as _;
     use asynchronous_codec::{BytesMut, Decoder, Encoder};
     use base64::{engine::general_purpose::STANDARD as base64, Engine};
     use libp2p::PeerId;
     use std::str::FromStr;
+    use unsigned_varint::codec::UviBytes;
 
     #[test]
     fn isomorphic_codec_test() {
@@ -139,6 +191,57 @@ mod tests {
         assert_eq!(result_message, Some(initial_message))
     }
 
+    #[test]
+    fn compressed_codec_test() {
+        let compression = Compression {
+            threshold: bytesize::ByteSize::b(0),
+            ..<_>::default()
+        };
+        let mut codec = FluenceCodec::compressed(compression);
+        let initial_message = ProtocolMessage::Particle(Particle {
+            id: "id".to_string(),
+            init_peer_id: PeerId::random(),
+            timestamp: 1000,
+            ttl: 1000,
+            script: "script".to_string(),
+            signature: vec![0, 0, 128],
+            data: vec![42; 64 * 1024],
+        });
+        let mut bytes = BytesMut::new();
+        codec
+            .encode(initial_message.clone(), &mut bytes)
+            .expect("Encoding");
+        assert!(bytes.len() < 1024);
+
+        let result_message = codec.decode(&mut bytes).expect("Decoding");
+
+        assert_eq!(result_message, Some(initial_message))
+    }
+
+    #[test]
+    fn frames_are_length_prefixed() {
+        let message = ProtocolMessage::Particle(Particle {
+            data: vec![7; 300],
+            ..<_>::default()
+        });
+        let serialized = ProtocolMessageRepresentation.serialize(&message).unwrap();
+        let mut expected = BytesMut::new();
+        UviBytes::<BytesMut>::default()
+            .encode(serialized[..].into(), &mut expected)
+            .unwrap();
+
+        let mut codec = FluenceCodec::new();
+        let mut bytes = BytesMut::new();
+        codec.encode(message.clone(), &mut bytes).unwrap();
+        assert_eq!(bytes, expected);
+
+        // several frames in one buffer
+        codec.encode(message.clone(), &mut bytes).unwrap();
+        assert_eq!(codec.decode(&mut bytes).unwrap(), Some(message.clone()));
+        assert_eq!(codec.decode(&mut bytes).unwrap(), Some(message));
+        assert_eq!(codec.decode(&mut bytes).unwrap(), None);
+    }
+
     #[test]
     fn deserialization_test() {
         let raw_str = "zwKBBIimYWN0aW9uqFBhcnRpY2xlpGRhdGGQomlk2SRkMjA1ZDE0OC00Y2YxLTRlNzYtOGY2ZS1mY\
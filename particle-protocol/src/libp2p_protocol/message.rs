@@ -19,7 +19,7 @@ use std::time::Duration;
 use serde::{Deserialize, Serialize};
 use tokio::sync::oneshot;
 
-use crate::Particle;
+use crate::{Compression, Contact, Particle};
 
 #[derive(Debug, Default)]
 pub enum SendStatus {
@@ -52,12 +52,16 @@ impl CompletionChannel {
 
 #[derive(Debug)]
 pub enum HandlerMessage {
-    /// Particle being sent to remote peer. Contains a channel to signal write completion.
+    /// Particle being sent to remote peer. Contains a channel to signal write completion
+    /// and compression to use if remote peer supports it.
     /// Send-only, can't be received.
-    OutParticle(Particle, CompletionChannel),
+    OutParticle(Particle, CompletionChannel, Compression),
     /// Particle being received from a remote peer.
     /// Receive-only, can't be sent.
     InParticle(Particle),
+    /// Sent to connected peers before the node shuts down, contains alternative relays
+    /// to reconnect to. Contains a channel to signal write completion.
+    Goodbye(Vec<Contact>, CompletionChannel),
     /// Dummy plug. Generated by the `OneshotHandler` when Inbound or Outbound Upgrade happened.
     Upgrade,
 }
@@ -65,9 +69,12 @@ pub enum HandlerMessage {
 impl HandlerMessage {
     pub fn into_protocol_message(self) -> (ProtocolMessage, Option<oneshot::Sender<SendStatus>>) {
         match self {
-            HandlerMessage::OutParticle(particle, channel) => {
+            HandlerMessage::OutParticle(particle, channel, _) => {
                 (ProtocolMessage::Particle(particle), channel.outlet())
             }
+            HandlerMessage::Goodbye(relays, channel) => {
+                (ProtocolMessage::Goodbye { relays }, channel.outlet())
+            }
             HandlerMessage::Upgrade => (ProtocolMessage::Upgrade, None),
             HandlerMessage::InParticle(_) => {
                 unreachable!("InParticle is never sent, only received")
@@ -88,6 +95,10 @@ impl From<()> for HandlerMessage {
 #[serde(tag = "action")]
 pub enum ProtocolMessage {
     Particle(Particle),
+    /// Remote peer is shutting down, `relays` are the nodes it suggests to reconnect to
+    Goodbye {
+        relays: Vec<Contact>,
+    },
     // TODO: is it needed?
     Upgrade,
 }
@@ -96,6 +107,7 @@ impl std::fmt::Display for ProtocolMessage {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ProtocolMessage::Particle(particle) => particle.fmt(f),
+            ProtocolMessage::Goodbye { relays } => write!(f, "Goodbye ({} relays)", relays.len()),
             ProtocolMessage::Upgrade => write!(f, "Upgrade"),
         }
     }
@@ -105,6 +117,9 @@ impl From<ProtocolMessage> for HandlerMessage {
     fn from(msg: ProtocolMessage) -> HandlerMessage {
         match msg {
             ProtocolMessage::Particle(p) => HandlerMessage::InParticle(p),
+            ProtocolMessage::Goodbye { relays } => {
+                HandlerMessage::Goodbye(relays, CompletionChannel::Ignore)
+            }
             ProtocolMessage::Upgrade => HandlerMessage::Upgrade,
         }
     }
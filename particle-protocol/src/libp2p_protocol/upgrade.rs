@@ -16,7 +16,7 @@
 
 use asynchronous_codec::{FramedRead, FramedWrite};
 use std::fmt::Debug;
-use std::{io, iter, time::Duration};
+use std::{io, time::Duration};
 
 use futures::{
     future::BoxFuture, AsyncRead, AsyncWrite, AsyncWriteExt, FutureExt, SinkExt, StreamExt,
@@ -30,7 +30,7 @@ use log::LevelFilter;
 use serde::{Deserialize, Serialize};
 
 use crate::libp2p_protocol::codec::FluenceCodec;
-use crate::{HandlerMessage, SendStatus, PROTOCOL_NAME};
+use crate::{Compression, HandlerMessage, SendStatus, COMPRESSED_PROTOCOL_NAME, PROTOCOL_NAME};
 
 #[derive(Clone, Deserialize, Serialize, Debug)]
 pub struct ProtocolConfig {
@@ -43,6 +43,10 @@ pub struct ProtocolConfig {
         default = "default_outbound_substream_timeout"
     )]
     pub outbound_substream_timeout: Duration,
+    /// Compression of particles sent to peers that support it.
+    /// If disabled, compressed particles aren't accepted either.
+    #[serde(default)]
+    pub compression: Compression,
 }
 
 impl Default for ProtocolConfig {
@@ -50,6 +54,7 @@ impl Default for ProtocolConfig {
         Self {
             upgrade_timeout: default_upgrade_timeout(),
             outbound_substream_timeout: default_outbound_substream_timeout(),
+            compression: Compression::default(),
         }
     }
 }
@@ -66,6 +71,14 @@ impl ProtocolConfig {
         Self {
             upgrade_timeout,
             outbound_substream_timeout,
+            compression: Compression::default(),
+        }
+    }
+
+    pub fn with_compression(self, compression: Compression) -> Self {
+        Self {
+            compression,
+            ..self
         }
     }
 }
@@ -85,21 +98,42 @@ impl<OutProto: libp2p::swarm::handler::OutboundUpgradeSend, OutEvent> From<Proto
     }
 }
 
-macro_rules! impl_upgrade_info {
-    ($tname:ident) => {
-        impl UpgradeInfo for $tname {
-            type Info = &'static str;
-            type InfoIter = iter::Once<Self::Info>;
+/// Compressed protocol is preferred, peers that don't support it negotiate the plain one
+fn protocols(compression: Option<&Compression>) -> Vec<&'static str> {
+    match compression {
+        Some(compression) if compression.enabled => vec![COMPRESSED_PROTOCOL_NAME, PROTOCOL_NAME],
+        _ => vec![PROTOCOL_NAME],
+    }
+}
+
+fn codec(protocol: &str, compression: Option<Compression>) -> FluenceCodec {
+    if protocol == COMPRESSED_PROTOCOL_NAME {
+        FluenceCodec::compressed(compression.unwrap_or_else(Compression::disabled))
+    } else {
+        FluenceCodec::new()
+    }
+}
 
-            fn protocol_info(&self) -> Self::InfoIter {
-                iter::once(PROTOCOL_NAME)
-            }
-        }
-    };
+impl UpgradeInfo for ProtocolConfig {
+    type Info = &'static str;
+    type InfoIter = Vec<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        protocols(Some(&self.compression))
+    }
 }
 
-impl_upgrade_info!(ProtocolConfig);
-impl_upgrade_info!(HandlerMessage);
+impl UpgradeInfo for HandlerMessage {
+    type Info = &'static str;
+    type InfoIter = Vec<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        match self {
+            HandlerMessage::OutParticle(_, _, compression) => protocols(Some(compression)),
+            _ => protocols(None),
+        }
+    }
+}
 
 impl<Socket> InboundUpgrade<Socket> for ProtocolConfig
 where
@@ -109,9 +143,9 @@ where
     type Error = std::io::Error;
     type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
 
-    fn upgrade_inbound(self, socket: Socket, _: Self::Info) -> Self::Future {
+    fn upgrade_inbound(self, socket: Socket, protocol: Self::Info) -> Self::Future {
         async move {
-            let msg = FramedRead::new(socket, FluenceCodec::new())
+            let msg = FramedRead::new(socket, codec(protocol, Some(self.compression)))
                 .next()
                 .await
                 .ok_or(io::ErrorKind::UnexpectedEof)??;
@@ -145,8 +179,12 @@ where
     type Error = io::Error;
     type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
 
-    fn upgrade_outbound(self, mut socket: Socket, _: Self::Info) -> Self::Future {
+    fn upgrade_outbound(self, mut socket: Socket, protocol: Self::Info) -> Self::Future {
         async move {
+            let compression = match &self {
+                HandlerMessage::OutParticle(_, _, compression) => Some(compression.clone()),
+                _ => None,
+            };
             let (msg, channel) = self.into_protocol_message();
 
             if log::max_level() >= LevelFilter::Debug {
@@ -157,7 +195,7 @@ where
             }
 
             let write = async move || -> Result<_, io::Error> {
-                FramedWrite::new(&mut socket, FluenceCodec::new())
+                FramedWrite::new(&mut socket, codec(protocol, compression))
                     .send(msg)
                     .await?;
 
@@ -197,11 +235,13 @@ mod tests {
         multiaddr::multiaddr,
         transport::{memory::MemoryTransport, Transport},
     };
-    use libp2p::{InboundUpgrade, OutboundUpgrade};
+    use libp2p::{InboundUpgrade, OutboundUpgrade, PeerId};
     use rand::{thread_rng, Rng};
 
     use crate::libp2p_protocol::message::ProtocolMessage;
-    use crate::{HandlerMessage, ProtocolConfig};
+    use crate::{
+        Compression, Contact, HandlerMessage, Particle, ProtocolConfig, COMPRESSED_PROTOCOL_NAME,
+    };
 
     const BYTES: [u8; 175] = [
         123, 34, 97, 99, 116, 105, 111, 110, 34, 58, 34, 80, 97, 114, 116, 105, 99, 108, 101, 34,
@@ -215,8 +255,12 @@ mod tests {
         100, 97, 116, 97, 34, 58, 34, 34, 125,
     ];
 
-    #[tokio::test]
-    async fn oneshot_channel_test() {
+    /// Sends `particle` over `protocol` with outbound upgrade, returns particle received by inbound upgrade
+    async fn transfer(
+        particle: Particle,
+        compression: Compression,
+        protocol: &'static str,
+    ) -> Particle {
         let mem_addr = multiaddr![Memory(thread_rng().gen::<u64>())];
         let mut transport = MemoryTransport::new().boxed();
         let listener_id = ListenerId::next();
@@ -235,27 +279,48 @@ mod tests {
             let conn = listener_upgrade.await.unwrap();
 
             let config = ProtocolConfig::default();
-            config.upgrade_inbound(conn, "/test/1").await.unwrap()
+            config.upgrade_inbound(conn, protocol).await.unwrap()
         });
-        let msg: ProtocolMessage = serde_json::from_slice(&BYTES).unwrap();
-        let sent_particle = match msg {
-            ProtocolMessage::Particle(p) => p,
-            _ => unreachable!("must be particle"),
-        };
-        let msg = HandlerMessage::OutParticle(sent_particle.clone(), <_>::default());
+        let msg = HandlerMessage::OutParticle(particle, <_>::default(), compression);
         let mut transport = MemoryTransport::new();
         let c = transport.dial(listener_addr).unwrap().await.unwrap();
-        msg.upgrade_outbound(c, "/test/1").await.unwrap();
+        msg.upgrade_outbound(c, protocol).await.unwrap();
         let received_particle = inbound.await.unwrap();
 
         match received_particle {
-            HandlerMessage::InParticle(received_particle) => {
-                assert_eq!(sent_particle, received_particle)
-            }
+            HandlerMessage::InParticle(received_particle) => received_particle,
             _ => unreachable!("must be InParticle"),
         }
     }
 
+    #[tokio::test]
+    async fn oneshot_channel_test() {
+        let msg: ProtocolMessage = serde_json::from_slice(&BYTES).unwrap();
+        let sent_particle = match msg {
+            ProtocolMessage::Particle(p) => p,
+            _ => unreachable!("must be particle"),
+        };
+        let received_particle =
+            transfer(sent_particle.clone(), Compression::disabled(), "/test/1").await;
+        assert_eq!(sent_particle, received_particle)
+    }
+
+    #[tokio::test]
+    async fn compressed_channel_test() {
+        let sent_particle = Particle {
+            id: "compressed".to_string(),
+            data: "canon result ".repeat(10_000).into_bytes(),
+            ..<_>::default()
+        };
+        let received_particle = transfer(
+            sent_particle.clone(),
+            Compression::default(),
+            COMPRESSED_PROTOCOL_NAME,
+        )
+        .await;
+        assert_eq!(sent_particle, received_particle)
+    }
+
     #[test]
     fn deserialize() {
         let str = r#"{"action":"Particle","id":"2","init_peer_id":"12D3KooWAcn1f5iZ7wbo9QrYPFgq6o7DGkh7VwC8Zucn6DgWZQDo","timestamp":1617733422130,"ttl":65525,"script":"!","signature":[],"data":"MTJEM0tvb1dDM3dhcjhqcTJzaGFVQ2hSZWttYjNNN0RGRGl4ZkdVTm5ydGY0VlRGQVlVdywxMkQzS29vV0o2bVZLYXpKQzdyd2dtd0JpZm5LZ0JoR2NSTWtaOXdRTjY4dmJ1UGdIUjlO"}"#;
@@ -265,4 +330,23 @@ mod tests {
         let test_msg: Result<ProtocolMessage, _> = serde_json::from_slice(&BYTES);
         test_msg.unwrap();
     }
+
+    #[test]
+    fn goodbye() {
+        let relay = Contact::new(
+            PeerId::random(),
+            vec!["/ip4/1.2.3.4/tcp/7777".parse().unwrap()],
+        );
+        let msg = ProtocolMessage::Goodbye {
+            relays: vec![relay.clone()],
+        };
+        let json = serde_json::to_value(&msg).unwrap();
+        assert_eq!(json["action"], "Goodbye");
+
+        let msg: ProtocolMessage = serde_json::from_value(json).unwrap();
+        match HandlerMessage::from(msg) {
+            HandlerMessage::Goodbye(relays, _) => assert_eq!(relays, vec![relay]),
+            _ => unreachable!("must be Goodbye"),
+        }
+    }
 }
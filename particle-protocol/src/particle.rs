@@ -16,7 +16,7 @@
 
 use std::convert::TryInto;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use derivative::Derivative;
 use libp2p::PeerId;
@@ -36,6 +36,30 @@ use types::peer_id;
 pub struct ExtendedParticle {
     pub particle: Particle,
     pub span: Arc<Span>,
+    pub timings: ParticleTimings,
+}
+
+/// Timings of particle processing stages on the current peer
+#[derive(Clone, Debug)]
+pub struct ParticleTimings {
+    /// When the particle was received or created by the current peer
+    pub received_at: Instant,
+    /// From receiving the particle until AquaVM picks it up
+    pub queue: Option<Duration>,
+    pub interpretation: Option<Duration>,
+    /// Total time of the service calls which results were interpreted along with the particle
+    pub service_calls: Option<Duration>,
+}
+
+impl Default for ParticleTimings {
+    fn default() -> Self {
+        Self {
+            received_at: Instant::now(),
+            queue: None,
+            interpretation: None,
+            service_calls: None,
+        }
+    }
 }
 
 impl AsRef<Particle> for ExtendedParticle {
@@ -55,6 +79,7 @@ impl ExtendedParticle {
         Self {
             particle,
             span: Arc::new(span),
+            timings: <_>::default(),
         }
     }
 
@@ -62,8 +87,13 @@ impl ExtendedParticle {
         Self {
             particle,
             span: span.clone(),
+            timings: <_>::default(),
         }
     }
+
+    pub fn with_timings(self, timings: ParticleTimings) -> Self {
+        Self { timings, ..self }
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize, PartialEq, Derivative)]
@@ -0,0 +1,166 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use fluence_keypair::KeyPair;
+use libp2p::PeerId;
+use now_millis::now_ms;
+use thiserror::Error;
+use uuid_utils::uuid;
+
+use crate::{Particle, ParticleError};
+
+/// Service that error particles call on the origin of a refused particle
+pub const REJECTION_SERVICE: &str = "rejection";
+/// Function of [REJECTION_SERVICE], called with the refused particle id, the reason and a message
+pub const REJECTION_FUNCTION: &str = "rejected";
+/// Error particles only travel to the origin of the refused particle
+const ERROR_PARTICLE_TTL: u32 = 60_000;
+
+/// Why a node refused to process a particle. Reported back to the particle origin
+/// with an error particle, so it can tell refused particles from lost ones.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum Rejection {
+    #[error("particle size {size} exceeds the limit of {limit} bytes")]
+    ParticleTooLarge { size: u64, limit: u64 },
+    #[error("particle data grew to {size} bytes during execution, the limit is {limit} bytes")]
+    DataSizeExceeded { size: u64, limit: u64 },
+    #[error("particle is not signed")]
+    MissingSignature,
+    #[error("particle signature is invalid: {error}")]
+    InvalidSignature { error: String },
+    #[error("rate limit exceeded, retry after {retry_after_ms}ms")]
+    Throttled { retry_after_ms: u64 },
+}
+
+impl Rejection {
+    /// Machine-readable reason passed to [REJECTION_FUNCTION]
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Rejection::ParticleTooLarge { .. } => "particle_too_large",
+            Rejection::DataSizeExceeded { .. } => "data_size_exceeded",
+            Rejection::MissingSignature => "missing_signature",
+            Rejection::InvalidSignature { .. } => "invalid_signature",
+            Rejection::Throttled { .. } => "throttled",
+        }
+    }
+
+    /// Creates a particle from `key_pair` that calls [REJECTION_SERVICE] on `origin` to tell it
+    /// why particle `particle_id` was refused. It's an ordinary particle, so peers unaware
+    /// of rejections still route it to `origin`.
+    pub fn error_particle(
+        &self,
+        particle_id: &str,
+        origin: PeerId,
+        key_pair: &KeyPair,
+    ) -> Result<Particle, ParticleError> {
+        let script = format!(
+            r#"(call "{origin}" ("{REJECTION_SERVICE}" "{REJECTION_FUNCTION}") ["{}" "{}" "{}"])"#,
+            literal(particle_id),
+            self.reason(),
+            literal(&self.to_string())
+        );
+        let mut particle = Particle {
+            id: uuid(),
+            init_peer_id: key_pair.get_peer_id(),
+            timestamp: now_ms() as u64,
+            ttl: ERROR_PARTICLE_TTL,
+            script,
+            signature: vec![],
+            data: vec![],
+        };
+        particle.sign(key_pair)?;
+        Ok(particle)
+    }
+}
+
+/// Rejection reported by an error particle, see [Rejection::error_particle]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RejectionNotice {
+    pub particle_id: String,
+    /// One of [Rejection::reason]
+    pub reason: String,
+    pub message: String,
+}
+
+impl RejectionNotice {
+    /// Returns `None` if `particle` isn't an error particle
+    pub fn from_particle(particle: &Particle) -> Option<Self> {
+        // values are string literals without quotes, see `literal`
+        let parts: Vec<_> = particle.script.split('"').collect();
+        match parts.as_slice() {
+            ["(call ", _origin, " (", REJECTION_SERVICE, " ", REJECTION_FUNCTION, ") [", particle_id, " ", reason, " ", message, "])"] => {
+                Some(Self {
+                    particle_id: particle_id.to_string(),
+                    reason: reason.to_string(),
+                    message: message.to_string(),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the notice is about particle `particle_id`, which may have had quotes dropped
+    pub fn refers_to(&self, particle_id: &str) -> bool {
+        self.particle_id == literal(particle_id)
+    }
+}
+
+/// AIR string literals can't escape quotes, so they're dropped along with backslashes
+fn literal(value: &str) -> String {
+    value.chars().filter(|c| !matches!(c, '"' | '\\')).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_keypair::KeyPair;
+
+    use crate::{Rejection, RejectionNotice};
+
+    #[test]
+    fn error_particle() {
+        let key_pair = KeyPair::generate_ed25519();
+        let origin = KeyPair::generate_ed25519().get_peer_id();
+        let rejection = Rejection::InvalidSignature {
+            error: r#"bad "signature" \ here"#.to_string(),
+        };
+
+        let particle = rejection
+            .error_particle(r#"id"with"quotes"#, origin, &key_pair)
+            .unwrap();
+        particle.verify().unwrap();
+        assert_eq!(particle.init_peer_id, key_pair.get_peer_id());
+        assert!(particle.script.contains(&origin.to_string()));
+
+        let notice = RejectionNotice::from_particle(&particle).unwrap();
+        assert_eq!(
+            notice,
+            RejectionNotice {
+                particle_id: "idwithquotes".to_string(),
+                reason: "invalid_signature".to_string(),
+                message: "particle signature is invalid: bad signature  here".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn not_error_particle() {
+        let particle = crate::Particle {
+            script: r#"(call %init_peer_id% ("op" "noop") [])"#.to_string(),
+            ..<_>::default()
+        };
+        assert_eq!(RejectionNotice::from_particle(&particle), None);
+    }
+}
@@ -0,0 +1,94 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use fluence_libp2p::PeerId;
+
+/// Callers allowed to call functions of a service. Service owner, its worker,
+/// the host and the management peer are allowed regardless of the ACL.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(into = "String", try_from = "String")]
+pub enum AclEntry {
+    /// Any peer
+    Anyone,
+    /// Any worker of this host
+    Workers,
+    Peer(PeerId),
+}
+
+impl Display for AclEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AclEntry::Anyone => write!(f, "anyone"),
+            AclEntry::Workers => write!(f, "workers"),
+            AclEntry::Peer(peer_id) => write!(f, "{peer_id}"),
+        }
+    }
+}
+
+impl FromStr for AclEntry {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "anyone" => Ok(AclEntry::Anyone),
+            "workers" => Ok(AclEntry::Workers),
+            _ => PeerId::from_str(s).map(AclEntry::Peer).map_err(|_| {
+                format!("invalid ACL entry '{s}': expected 'anyone', 'workers' or a peer id")
+            }),
+        }
+    }
+}
+
+impl From<AclEntry> for String {
+    fn from(entry: AclEntry) -> Self {
+        entry.to_string()
+    }
+}
+
+impl TryFrom<String> for AclEntry {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_libp2p::RandomPeerId;
+
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let peer_id = RandomPeerId::random();
+        let entries: Vec<AclEntry> = ["anyone", "workers", &peer_id.to_base58()]
+            .into_iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        assert_eq!(
+            entries,
+            vec![AclEntry::Anyone, AclEntry::Workers, AclEntry::Peer(peer_id)]
+        );
+        assert_eq!(entries[2].to_string(), peer_id.to_base58());
+        assert!("everyone".parse::<AclEntry>().is_err());
+    }
+}
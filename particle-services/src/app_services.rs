@@ -19,10 +19,12 @@ use std::time::{Duration, Instant};
 use std::{collections::HashMap, sync::Arc};
 
 use derivative::Derivative;
+use eyre::WrapErr;
 use fluence_app_service::{
     AppService, AppServiceConfig, AppServiceError, CallParameters, MarineConfig, MarineError,
     MarineWASIConfig, ModuleDescriptor, SecurityTetraplet, ServiceInterface,
 };
+use futures::{stream, StreamExt};
 use humantime_serde::re::humantime::format_duration as pretty;
 use parking_lot::{Mutex, RwLock, RwLockUpgradableReadGuard};
 use serde::{Deserialize, Serialize};
@@ -41,12 +43,16 @@ use peer_metrics::{
     ServicesMetricsBuiltin,
 };
 use server_config::ServicesConfig;
+use types::management::Capability;
 use types::peer_scope::PeerScope;
 use uuid_utils::uuid;
 use workers::{PeerScopes, WorkerId, Workers};
 
+use crate::acl::AclEntry;
 use crate::error::ServiceError;
-use crate::error::ServiceError::{AliasAsServiceId, Forbidden, NoSuchAlias};
+use crate::error::ServiceError::{
+    AliasAsServiceId, Forbidden, ForbiddenByAcl, NoSuchAlias, ParticleExpired,
+};
 use crate::health::PersistedServiceHealth;
 use crate::persistence::{load_persisted_services, remove_persisted_service, PersistedService};
 use crate::ServiceError::{
@@ -90,6 +96,8 @@ pub struct Service {
     pub service_type: ServiceType,
     pub owner_id: PeerId,
     pub aliases: RwLock<Vec<ServiceAlias>>,
+    /// Callers allowed besides the owner, `None` if anyone can call the service
+    pub acl: RwLock<Option<Vec<AclEntry>>>,
     pub peer_scope: PeerScope,
 }
 
@@ -101,6 +109,7 @@ impl Service {
         service_type: ServiceType,
         owner_id: PeerId,
         aliases: Vec<ServiceAlias>,
+        acl: Option<Vec<AclEntry>>,
         peer_scope: PeerScope,
     ) -> Self {
         Self {
@@ -110,6 +119,7 @@ impl Service {
             service_type,
             owner_id,
             aliases: RwLock::new(aliases),
+            acl: RwLock::new(acl),
             peer_scope,
         }
     }
@@ -217,7 +227,8 @@ impl ParticleAppServices {
         workers: Arc<Workers>,
         scope: PeerScopes,
     ) -> Self {
-        let vault = ParticleVault::new(config.particles_vault_dir.clone());
+        let vault = ParticleVault::new(config.particles_vault_dir.clone())
+            .with_max_size(config.max_vault_size.map(|size| size.as_u64()));
         let root_runtime_handle = Handle::current();
 
         let health = health_registry.map(|registry| {
@@ -264,6 +275,7 @@ impl ParticleAppServices {
                 peer_scope,
                 service_id.clone(),
                 vec![],
+                None,
             )
             .await
         };
@@ -353,12 +365,18 @@ impl ParticleAppServices {
             //  service.owner_id has created the service, so can remove. that's OK.
             //  management_peer_id is the node admin, can remove any service. that's OK.
             //  service.worker_id is the worker itself, so can remove. that's OK.
+            //  management keys with deploy_system_services can remove services in the root scope.
 
             let service_worker_id: PeerId = self.scopes.to_peer_id(peer_scope);
+            let can_deploy = matches!(peer_scope, PeerScope::Host)
+                && self
+                    .scopes
+                    .has_capability(init_peer_id, Capability::DeploySystemServices);
 
             if service_worker_id != init_peer_id
                 && service.owner_id != init_peer_id
                 && !self.scopes.is_management(init_peer_id)
+                && !can_deploy
             {
                 return Err(Forbidden {
                     user: init_peer_id,
@@ -405,6 +423,8 @@ impl ParticleAppServices {
     ) -> FunctionOutcome {
         let peer_scope = particle.peer_scope;
         let timestamp = particle.timestamp;
+        let expires_at = timestamp.saturating_add(particle.ttl as u64);
+        let particle_id = particle.id.clone();
 
         let service = self.get_service(peer_scope, function_args.service_id.clone(), &particle.id);
 
@@ -428,6 +448,13 @@ impl ParticleAppServices {
         //         },
         //     ));
         // }
+        if !self.is_call_allowed(&service, particle.init_peer_id) {
+            return FunctionOutcome::Err(JError::from(ForbiddenByAcl {
+                user: particle.init_peer_id,
+                service_id,
+            }));
+        }
+
         // Metrics collection are enables for services with aliases which are installed on root worker or worker spells.
         let service_type = self.get_service_type(service.as_ref(), &peer_scope);
 
@@ -467,6 +494,14 @@ impl ParticleAppServices {
 
         let lock_acquire_start = Instant::now();
         let mut service = service.lock();
+        // A call can't be interrupted once started, so the deadline is checked right before it,
+        // after waiting for other calls of the service to finish
+        if now_ms() as u64 >= expires_at {
+            return FunctionOutcome::Err(JError::from(ParticleExpired {
+                particle_id,
+                service_id,
+            }));
+        }
         let old_memory = service.module_memory_stats();
         let old_mem_usage = ServicesMetricsBuiltin::get_used_memory(&old_memory);
         // TODO: set execution timeout https://github.com/fluencelabs/fluence/issues/1212
@@ -672,7 +707,10 @@ impl ParticleAppServices {
                     }
                 }
                 PeerScope::Host => {
-                    if init_peer_id != self.scopes.get_host_peer_id() {
+                    let can_deploy = self
+                        .scopes
+                        .has_capability(init_peer_id, Capability::DeploySystemServices);
+                    if init_peer_id != self.scopes.get_host_peer_id() && !can_deploy {
                         return Err(ForbiddenAliasRoot(init_peer_id));
                     }
                 }
@@ -704,6 +742,35 @@ impl ParticleAppServices {
         Ok(())
     }
 
+    /// Sets callers allowed to call the service besides its owner, `None` allows anyone
+    pub async fn set_acl(
+        &self,
+        peer_scope: PeerScope,
+        service_id_or_alias: String,
+        acl: Option<Vec<AclEntry>>,
+        init_peer_id: PeerId,
+        particle_id: &str,
+    ) -> Result<(), ServiceError> {
+        let (service, _) = self.get_service(peer_scope, service_id_or_alias, particle_id)?;
+
+        let service_worker_id = self.scopes.to_peer_id(peer_scope);
+        if service_worker_id != init_peer_id
+            && service.owner_id != init_peer_id
+            && !self.scopes.is_management(init_peer_id)
+        {
+            return Err(Forbidden {
+                user: init_peer_id,
+                function: "set_acl",
+                reason: "only creator can change service ACL",
+            });
+        }
+
+        *service.acl.write() = acl;
+        PersistedService::from_service(service.as_ref())
+            .persist(&self.config.services_dir)
+            .await
+    }
+
     pub fn resolve_alias(
         &self,
         peer_scope: PeerScope,
@@ -837,36 +904,24 @@ impl ParticleAppServices {
             h.start_creation()
         }
 
+        let parallelism = std::thread::available_parallelism().map_or(1, |n| n.get());
+        // Services are created concurrently, modules are compiled on the blocking pool.
+        // They are registered in the persisted order though, so aliases are resolved the same way on every start.
+        let mut created = stream::iter(services)
+            .map(|(service, _)| {
+                let this = self.clone();
+                tokio::spawn(async move {
+                    let start = Instant::now();
+                    let result = this.create_persisted_service(&service).await;
+                    (service, result, start.elapsed())
+                })
+            })
+            .buffered(parallelism);
+
         let mut created_service_count = 0;
-        for (service, _) in services {
-            let start = Instant::now();
-            // If the service_type doesn't set in PersistedService, will try to find out if it's a spell by blueprint name
-            // This is mostly done for migration from the old detection method to the new.
-            let service_type = service.service_type.unwrap_or_else(|| {
-                let is_spell: Option<_> = try {
-                    let blueprint_name = self
-                        .modules
-                        .get_blueprint_from_cache(&service.blueprint_id)
-                        .ok()?
-                        .name;
-                    blueprint_name == "spell"
-                };
-                if is_spell.unwrap_or(false) {
-                    ServiceType::Spell
-                } else {
-                    ServiceType::Service
-                }
-            });
-            let result = self
-                .create_service_inner(
-                    service_type,
-                    service.blueprint_id,
-                    service.owner_id,
-                    service.peer_scope,
-                    service.service_id.clone(),
-                    service.aliases.clone(),
-                )
-                .await;
+        while let Some(created) = created.next().await {
+            let (service, result, elapsed) =
+                created.context("persisted service creation failed")?;
             let replaced = match result {
                 Ok(replaced) => replaced,
                 Err(err) => {
@@ -916,7 +971,7 @@ impl ParticleAppServices {
             tracing::info!(
                 "Persisted service {} created in {}, aliases: {:?}",
                 service.service_id,
-                pretty(start.elapsed()),
+                pretty(elapsed),
                 service.aliases
             );
         }
@@ -928,6 +983,39 @@ impl ParticleAppServices {
         Ok(())
     }
 
+    async fn create_persisted_service(
+        &self,
+        service: &PersistedService,
+    ) -> Result<Option<Arc<Service>>, ServiceError> {
+        // If the service_type doesn't set in PersistedService, will try to find out if it's a spell by blueprint name
+        // This is mostly done for migration from the old detection method to the new.
+        let service_type = service.service_type.clone().unwrap_or_else(|| {
+            let is_spell: Option<_> = try {
+                let blueprint_name = self
+                    .modules
+                    .get_blueprint_from_cache(&service.blueprint_id)
+                    .ok()?
+                    .name;
+                blueprint_name == "spell"
+            };
+            if is_spell.unwrap_or(false) {
+                ServiceType::Spell
+            } else {
+                ServiceType::Service
+            }
+        });
+        self.create_service_inner(
+            service_type,
+            service.blueprint_id.clone(),
+            service.owner_id,
+            service.peer_scope,
+            service.service_id.clone(),
+            service.aliases.clone(),
+            service.acl.clone(),
+        )
+        .await
+    }
+
     async fn create_service_inner(
         &self,
         service_type: ServiceType,
@@ -936,14 +1024,11 @@ impl ParticleAppServices {
         peer_scope: PeerScope,
         service_id: String,
         aliases: Vec<String>,
+        acl: Option<Vec<AclEntry>>,
     ) -> Result<Option<Arc<Service>>, ServiceError> {
         let creation_start_time = Instant::now();
         let service = self
-            .create_app_service(
-                self.scopes.to_peer_id(peer_scope),
-                blueprint_id.clone(),
-                service_id.clone(),
-            )
+            .create_app_service(peer_scope, blueprint_id.clone(), service_id.clone())
             .await
             .inspect_err(|_| {
                 if let Some(metrics) = self.metrics.as_ref() {
@@ -960,6 +1045,7 @@ impl ParticleAppServices {
             service_type,
             owner_id,
             aliases,
+            acl,
             peer_scope,
         );
         let service = Arc::new(service);
@@ -1054,12 +1140,27 @@ impl ParticleAppServices {
         Ok(())
     }
 
+    /// Node-wide envs plus the GPU devices assigned to the service's worker
+    fn service_envs(&self, peer_scope: PeerScope) -> HashMap<String, String> {
+        let mut envs = self.config.envs.clone();
+        if let PeerScope::WorkerId(worker_id) = peer_scope {
+            let gpus = self.workers.get_gpus(worker_id);
+            if !gpus.is_empty() {
+                let devices = gpus.join(",");
+                envs.insert("CUDA_VISIBLE_DEVICES".to_string(), devices.clone());
+                envs.insert("NVIDIA_VISIBLE_DEVICES".to_string(), devices);
+            }
+        }
+        envs
+    }
+
     async fn create_app_service(
         &self,
-        current_peer_id: PeerId,
+        peer_scope: PeerScope,
         blueprint_id: String,
         service_id: String,
     ) -> Result<AppService, ServiceError> {
+        let current_peer_id = self.scopes.to_peer_id(peer_scope);
         let persistent_dir = self.config.persistent_work_dir.join(&service_id);
         let ephemeral_dir = self.config.ephemeral_work_dir.join(&service_id);
 
@@ -1106,16 +1207,38 @@ impl ParticleAppServices {
             },
         };
 
-        tracing::debug!(
-            "Creating service {}, envs: {:?}",
-            service_id,
-            self.config.envs
-        );
+        let envs = self.service_envs(peer_scope);
+        tracing::debug!("Creating service {}, envs: {:?}", service_id, envs);
 
-        AppService::new(app_config, service_id, self.config.envs.clone())
+        // Module compilation is blocking, keep it off the async workers
+        tokio::task::spawn_blocking(move || AppService::new(app_config, service_id, envs))
+            .await
+            .map_err(|err| InternalError(format!("service creation task failed: {err}")))?
             .map_err(ServiceError::Engine)
     }
 
+    /// Owner, the service worker, the host and the management peer can always call the service
+    fn is_call_allowed(&self, service: &Service, caller: PeerId) -> bool {
+        let acl = service.acl.read();
+        let Some(acl) = acl.as_ref() else {
+            return true;
+        };
+
+        if caller == service.owner_id
+            || caller == self.scopes.to_peer_id(service.peer_scope)
+            || self.scopes.is_host(caller)
+            || self.scopes.is_management(caller)
+        {
+            return true;
+        }
+
+        acl.iter().any(|entry| match entry {
+            AclEntry::Anyone => true,
+            AclEntry::Workers => matches!(self.scopes.scope(caller), Ok(PeerScope::WorkerId(_))),
+            AclEntry::Peer(peer_id) => *peer_id == caller,
+        })
+    }
+
     fn get_service_type(&self, service: &Service, peer_scope: &PeerScope) -> MetricServiceType {
         let allowed_alias = match peer_scope {
             PeerScope::Host => service.aliases.read().first().cloned(),
@@ -1154,6 +1277,7 @@ mod tests {
     use std::collections::HashMap;
     use std::path::PathBuf;
     use std::sync::Arc;
+    use std::time::Duration;
 
     use base64::{engine::general_purpose::STANDARD as base64, Engine};
     use fluence_app_service::{TomlMarineModuleConfig, TomlMarineNamedModuleConfig};
@@ -1163,6 +1287,7 @@ mod tests {
 
     use config_utils::modules_dir;
     use fluence_libp2p::RandomPeerId;
+    use particle_execution::FunctionOutcome;
     use particle_modules::{AddBlueprint, ModuleRepository};
     use server_config::ServicesConfig;
     use service_modules::load_module;
@@ -1170,6 +1295,7 @@ mod tests {
     use types::peer_scope::PeerScope;
     use workers::{DummyCoreManager, KeyStorage, PeerScopes, Workers};
 
+    use crate::acl::AclEntry;
     use crate::app_services::{ServiceAlias, ServiceType};
     use crate::persistence::load_persisted_services;
     use crate::{ParticleAppServices, ServiceError};
@@ -1224,6 +1350,7 @@ mod tests {
             management_pid,
             root_key_pair.get_peer_id(),
             Some(service_memory_limit),
+            None,
             Default::default(),
             Default::default(),
             true,
@@ -1586,6 +1713,96 @@ mod tests {
         assert_eq!(service_1.owner_id, persisted_service_1.owner_id);
     }
 
+    #[tokio::test]
+    async fn test_set_acl() {
+        let base_dir = TempDir::new("test5").unwrap();
+        let root_keypair = Keypair::generate_ed25519();
+        let management_pid = create_pid();
+        let pas = create_pas(root_keypair, management_pid, base_dir.into_path()).await;
+
+        let module_name = "tetra".to_string();
+        let m_hash = upload_tetra_service(&pas, module_name.clone());
+        let service_id = create_service(&pas, module_name, &m_hash, PeerScope::Host)
+            .await
+            .unwrap();
+
+        let allowed = create_pid();
+        let stranger = create_pid();
+        let result = pas
+            .set_acl(
+                PeerScope::Host,
+                service_id.clone(),
+                Some(vec![AclEntry::Peer(allowed)]),
+                stranger,
+                "",
+            )
+            .await;
+        assert!(matches!(result, Err(ServiceError::Forbidden { .. })));
+
+        pas.set_acl(
+            PeerScope::Host,
+            service_id.clone(),
+            Some(vec![AclEntry::Peer(allowed)]),
+            management_pid,
+            "",
+        )
+        .await
+        .unwrap();
+
+        let call = |caller| {
+            pas.call_function(
+                PeerScope::Host,
+                &service_id,
+                "not_exists",
+                vec![],
+                None,
+                caller,
+                Duration::from_secs(10),
+            )
+        };
+        let is_denied = |outcome: FunctionOutcome| match outcome {
+            FunctionOutcome::Err(err) => err.to_string().contains("ACL"),
+            _ => false,
+        };
+        assert!(is_denied(call(stranger)));
+        assert!(!is_denied(call(allowed)));
+
+        let (persisted, _) = load_persisted_services(&pas.config.services_dir)
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|(s, _)| s.service_id == service_id)
+            .unwrap();
+        assert_eq!(persisted.acl, Some(vec![AclEntry::Peer(allowed)]));
+    }
+
+    #[tokio::test]
+    async fn test_call_expired_particle() {
+        let base_dir = TempDir::new("test6").unwrap();
+        let root_keypair = Keypair::generate_ed25519();
+        let management_pid = create_pid();
+        let pas = create_pas(root_keypair, management_pid, base_dir.into_path()).await;
+
+        let module_name = "tetra".to_string();
+        let m_hash = upload_tetra_service(&pas, module_name.clone());
+        let service_id = create_service(&pas, module_name, &m_hash, PeerScope::Host)
+            .await
+            .unwrap();
+
+        let outcome = pas.call_function(
+            PeerScope::Host,
+            &service_id,
+            "not_exists",
+            vec![],
+            None,
+            management_pid,
+            Duration::ZERO,
+        );
+        assert!(
+            matches!(outcome, FunctionOutcome::Err(err) if err.to_string().contains("expired"))
+        );
+    }
+
     // TODO: add more tests
     //       - add alias success & fail with service collision & test on rewriting alias
     //       - create_service success & fail
@@ -47,6 +47,8 @@ pub enum ServiceError {
         function: &'static str,
         reason: &'static str,
     },
+    #[error("Forbidden. User id '{user}' is not in the ACL of service '{service_id}'")]
+    ForbiddenByAcl { user: PeerId, service_id: String },
     #[error("Forbidden. User id '{0}' cannot call function 'add_alias': only management peer id can add top-level aliases")]
     ForbiddenAliasRoot(PeerId),
     #[error("Forbidden. User id '{0}' cannot call function 'add_alias': only worker, worker creator and management peer id can add worker-level aliases")]
@@ -102,6 +104,11 @@ pub enum ServiceError {
         #[source]
         err: std::io::Error,
     },
+    #[error("Particle {particle_id} expired before service '{service_id}' was called")]
+    ParticleExpired {
+        particle_id: String,
+        service_id: String,
+    },
     #[error("Internal error, smth bad happened: {0}")]
     InternalError(String),
     #[error("Worker {worker_id} not found")]
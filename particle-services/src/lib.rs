@@ -29,11 +29,13 @@
 
 pub use fluence_app_service::{IType, IValue};
 
+pub use acl::AclEntry;
 pub use app_services::ParticleAppServices;
 pub use app_services::ServiceType;
 
 pub use crate::error::ServiceError;
 
+mod acl;
 mod app_services;
 mod error;
 mod health;
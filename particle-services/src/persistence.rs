@@ -18,6 +18,7 @@ use std::path::{Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
 
+use crate::acl::AclEntry;
 use crate::app_services::Service;
 use crate::error::ServiceError;
 use crate::ServiceError::{SerializePersistedService, WritePersistedService};
@@ -36,6 +37,9 @@ pub struct PersistedService {
     #[serde(default)]
     // Old versions of PersistedService may omit `aliases` field, tolerate that
     pub aliases: Vec<String>,
+    /// Callers allowed besides the owner, `None` if anyone can call the service
+    #[serde(default)]
+    pub acl: Option<Vec<AclEntry>>,
     // Old versions of PersistedService may omit `owner` field, tolerate that via RandomPeerId::random
     #[serde(
         serialize_with = "peer_id::serde::serialize",
@@ -52,6 +56,7 @@ impl PersistedService {
             service_type: Some(service.service_type.clone()),
             blueprint_id: service.blueprint_id.clone(),
             aliases: service.aliases.read().clone(),
+            acl: service.acl.read().clone(),
             owner_id: service.owner_id,
             peer_scope: service.peer_scope,
         }
@@ -91,6 +96,7 @@ pub async fn remove_persisted_service(
 
 #[cfg(test)]
 mod tests {
+    use crate::acl::AclEntry;
     use crate::persistence::{load_persisted_services, PersistedService};
     use fluence_libp2p::RandomPeerId;
     use types::peer_scope::PeerScope;
@@ -104,6 +110,7 @@ mod tests {
             service_type: None,
             blueprint_id: "blueprint_id_1".to_string(),
             aliases: vec!["alias_1".to_string()],
+            acl: None,
             owner_id,
             peer_scope: PeerScope::WorkerId(owner_id.into()),
         };
@@ -117,6 +124,7 @@ mod tests {
             service_type: None,
             blueprint_id: "blueprint_id_2".to_string(),
             aliases: vec!["alias_2".to_string()],
+            acl: Some(vec![AclEntry::Workers, AclEntry::Peer(owner_id)]),
             owner_id,
             peer_scope: PeerScope::Host,
         };
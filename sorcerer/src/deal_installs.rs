@@ -0,0 +1,248 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use types::DealId;
+
+/// Bounds how many deals are installed concurrently, so discovering a backlog of deals after
+/// downtime doesn't fetch and create services of all of them at once.
+///
+/// A deal takes a slot when the decider creates its worker. The slot is freed when a spell of the
+/// worker is triggered for the second time, i.e. the first run installing services has had
+/// a whole period to finish, when the worker is removed or after `install_timeout`.
+/// Deals over the limit are queued in the order they come and `worker.create` fails for them,
+/// so the decider retries them on its next runs. Deals not retried for `install_timeout`
+/// leave the queue.
+pub struct DealInstalls {
+    max_concurrent: Option<usize>,
+    install_timeout: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    installing: HashMap<DealId, Slot>,
+    /// Deals waiting for a slot and when they were requested last
+    queue: VecDeque<(DealId, Instant)>,
+}
+
+struct Slot {
+    started_at: Instant,
+    /// UNIX timestamp in seconds, for the progress report
+    started_at_sec: u64,
+    spell_runs: u32,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Admission {
+    Admitted,
+    /// 1-based position among the deals waiting for a slot
+    Queued {
+        position: usize,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InstallingDeal {
+    pub deal_id: DealId,
+    pub started_at_sec: u64,
+}
+
+/// Progress of deal installations, returned by `worker.install_queue`
+#[derive(Debug, Clone, Serialize)]
+pub struct DealInstallProgress {
+    /// `None` if installations aren't limited
+    pub max_concurrent: Option<usize>,
+    pub installing: Vec<InstallingDeal>,
+    pub queued: Vec<DealId>,
+}
+
+impl DealInstalls {
+    pub fn new(max_concurrent: Option<usize>, install_timeout: Duration) -> Self {
+        Self {
+            max_concurrent,
+            install_timeout,
+            state: <_>::default(),
+        }
+    }
+
+    /// Takes a slot for the deal if there's a free one and no deal has been waiting longer
+    pub fn admit(&self, deal_id: &DealId, now: Instant) -> Admission {
+        let Some(max_concurrent) = self.max_concurrent else {
+            return Admission::Admitted;
+        };
+        let mut state = self.state.lock();
+        state.expire(now, self.install_timeout);
+        if state.installing.contains_key(deal_id) {
+            return Admission::Admitted;
+        }
+
+        let position = match state.queue.iter().position(|(d, _)| d == deal_id) {
+            Some(position) => {
+                state.queue[position].1 = now;
+                position
+            }
+            None => {
+                state.queue.push_back((deal_id.clone(), now));
+                state.queue.len() - 1
+            }
+        };
+        let free = max_concurrent.saturating_sub(state.installing.len());
+        if position < free {
+            state.queue.remove(position);
+            let slot = Slot {
+                started_at: now,
+                started_at_sec: now_millis::now_sec(),
+                spell_runs: 0,
+            };
+            state.installing.insert(deal_id.clone(), slot);
+            Admission::Admitted
+        } else {
+            Admission::Queued {
+                position: position + 1 - free,
+            }
+        }
+    }
+
+    /// Frees the slot of the deal on the second run of its worker spells
+    pub fn on_spell_run(&self, deal_id: &DealId) {
+        let mut state = self.state.lock();
+        if let Some(slot) = state.installing.get_mut(deal_id) {
+            slot.spell_runs += 1;
+            if slot.spell_runs >= 2 {
+                state.installing.remove(deal_id);
+            }
+        }
+    }
+
+    /// Frees the slot of the deal or removes it from the queue, e.g. when its worker is removed
+    pub fn finish(&self, deal_id: &DealId) {
+        let mut state = self.state.lock();
+        state.installing.remove(deal_id);
+        state.queue.retain(|(d, _)| d != deal_id);
+    }
+
+    pub fn progress(&self, now: Instant) -> DealInstallProgress {
+        let mut state = self.state.lock();
+        state.expire(now, self.install_timeout);
+        let mut installing: Vec<_> = state
+            .installing
+            .iter()
+            .map(|(deal_id, slot)| InstallingDeal {
+                deal_id: deal_id.clone(),
+                started_at_sec: slot.started_at_sec,
+            })
+            .collect();
+        installing.sort_by_key(|deal| deal.started_at_sec);
+        DealInstallProgress {
+            max_concurrent: self.max_concurrent,
+            installing,
+            queued: state.queue.iter().map(|(d, _)| d.clone()).collect(),
+        }
+    }
+}
+
+impl State {
+    fn expire(&mut self, now: Instant, timeout: Duration) {
+        self.installing.retain(|deal_id, slot| {
+            let expired = now.saturating_duration_since(slot.started_at) >= timeout;
+            if expired {
+                tracing::warn!(%deal_id, "Deal installation didn't finish in {timeout:?}, freeing its slot");
+            }
+            !expired
+        });
+        self.queue
+            .retain(|(_, requested_at)| now.saturating_duration_since(*requested_at) < timeout);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deal(id: &str) -> DealId {
+        DealId::from(id)
+    }
+
+    #[test]
+    fn queue_in_order() {
+        let installs = DealInstalls::new(Some(1), Duration::from_secs(600));
+        let now = Instant::now();
+
+        assert_eq!(installs.admit(&deal("a"), now), Admission::Admitted);
+        assert_eq!(installs.admit(&deal("a"), now), Admission::Admitted);
+        assert_eq!(
+            installs.admit(&deal("b"), now),
+            Admission::Queued { position: 1 }
+        );
+        assert_eq!(
+            installs.admit(&deal("c"), now),
+            Admission::Queued { position: 2 }
+        );
+
+        installs.on_spell_run(&deal("a"));
+        assert_eq!(
+            installs.admit(&deal("c"), now),
+            Admission::Queued { position: 2 }
+        );
+        installs.on_spell_run(&deal("a"));
+        // "b" has been waiting longer
+        assert_eq!(
+            installs.admit(&deal("c"), now),
+            Admission::Queued { position: 1 }
+        );
+        assert_eq!(installs.admit(&deal("b"), now), Admission::Admitted);
+
+        let progress = installs.progress(now);
+        assert_eq!(progress.installing.len(), 1);
+        assert_eq!(progress.installing[0].deal_id, deal("b"));
+        assert_eq!(progress.queued, vec![deal("c")]);
+
+        installs.finish(&deal("b"));
+        assert_eq!(installs.admit(&deal("c"), now), Admission::Admitted);
+    }
+
+    #[test]
+    fn expire_stale() {
+        let timeout = Duration::from_secs(600);
+        let installs = DealInstalls::new(Some(1), timeout);
+        let now = Instant::now();
+
+        assert_eq!(installs.admit(&deal("a"), now), Admission::Admitted);
+        assert_eq!(
+            installs.admit(&deal("b"), now),
+            Admission::Queued { position: 1 }
+        );
+        // "a" took too long and "b" isn't retried anymore
+        let later = now + timeout;
+        assert_eq!(installs.admit(&deal("c"), later), Admission::Admitted);
+        assert!(installs.progress(later).queued.is_empty());
+    }
+
+    #[test]
+    fn unlimited() {
+        let installs = DealInstalls::new(None, Duration::from_secs(600));
+        let now = Instant::now();
+        for id in ["a", "b", "c"] {
+            assert_eq!(installs.admit(&deal(id), now), Admission::Admitted);
+        }
+        assert!(installs.progress(now).installing.is_empty());
+    }
+}
@@ -1,12 +1,14 @@
 #![feature(try_blocks)]
 #![feature(extend_one)]
-pub use sorcerer::Sorcerer;
+pub use sorcerer::{Sorcerer, SorcererContext};
 pub use spell_builtins::{get_spell_info, install_spell, remove_spell, SpellInfo};
 
 #[macro_use]
 extern crate fstrings;
 
+mod deal_installs;
 mod error;
+mod pubsub_builtins;
 mod script_executor;
 mod sorcerer;
 mod spell_builtins;
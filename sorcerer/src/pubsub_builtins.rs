@@ -0,0 +1,129 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use particle_args::{Args, JError};
+use particle_execution::ParticleParams;
+use spell_service_api::{CallParams, SpellServiceApi};
+
+use crate::sorcerer::SorcererContext;
+use crate::utils::parse_spell_id_from;
+
+/// Key in the spell KV with a JSON list of topics the spell is subscribed to.
+/// Used to restore subscriptions on restart
+const TOPICS_KEY: &str = "pubsub_topics";
+
+pub(crate) fn load_topics(
+    spell_service_api: &SpellServiceApi,
+    params: CallParams,
+) -> Result<Vec<String>, JError> {
+    match spell_service_api.get_string(params, TOPICS_KEY.to_string())? {
+        Some(topics) => Ok(serde_json::from_str(&topics)?),
+        None => Ok(vec![]),
+    }
+}
+
+pub(crate) fn store_topics(
+    spell_service_api: &SpellServiceApi,
+    params: CallParams,
+    topics: &[String],
+) -> Result<(), JError> {
+    let topics = serde_json::to_string(topics)?;
+    spell_service_api.set_string(params, TOPICS_KEY.to_string(), topics)?;
+    Ok(())
+}
+
+fn parse_subscriber_spell_id(params: &ParticleParams) -> Result<String, JError> {
+    parse_spell_id_from(params)
+        .map_err(|err| JError::new(format!("only spells can subscribe to topics: {err}")))
+}
+
+pub(crate) async fn pubsub_subscribe(
+    args: Args,
+    params: ParticleParams,
+    ctx: &SorcererContext,
+) -> Result<(), JError> {
+    let SorcererContext {
+        pubsub_api,
+        spell_event_bus_api,
+        spell_service_api,
+        ..
+    } = ctx;
+    let mut args = args.function_args.into_iter();
+    let topic: String = Args::next("topic", &mut args)?;
+
+    if !pubsub_api.is_enabled() {
+        return Err(JError::new("pubsub is disabled on this peer"));
+    }
+
+    let spell_id = parse_subscriber_spell_id(&params)?;
+    let call_params = CallParams::from(spell_id.clone(), params);
+    let mut topics = load_topics(spell_service_api, call_params.clone())?;
+    if !topics.contains(&topic) {
+        topics.push(topic.clone());
+        store_topics(spell_service_api, call_params, &topics)?;
+    }
+
+    spell_event_bus_api.subscribe_topic(spell_id, topic).await?;
+    Ok(())
+}
+
+pub(crate) async fn pubsub_unsubscribe(
+    args: Args,
+    params: ParticleParams,
+    ctx: &SorcererContext,
+) -> Result<(), JError> {
+    let SorcererContext {
+        spell_event_bus_api,
+        spell_service_api,
+        ..
+    } = ctx;
+    let mut args = args.function_args.into_iter();
+    let topic: String = Args::next("topic", &mut args)?;
+
+    let spell_id = parse_subscriber_spell_id(&params)?;
+    let call_params = CallParams::from(spell_id.clone(), params);
+    let mut topics = load_topics(spell_service_api, call_params.clone())?;
+    if topics.contains(&topic) {
+        topics.retain(|t| *t != topic);
+        store_topics(spell_service_api, call_params, &topics)?;
+    }
+
+    spell_event_bus_api
+        .unsubscribe_topic(spell_id, topic)
+        .await?;
+    Ok(())
+}
+
+/// Messages are signed with the host key, so only the host, its workers and management may publish
+pub(crate) async fn pubsub_publish(
+    args: Args,
+    params: ParticleParams,
+    ctx: &SorcererContext,
+) -> Result<(), JError> {
+    let caller = params.init_peer_id;
+    if ctx.scopes.scope(caller).is_err() && !ctx.scopes.is_management(caller) {
+        return Err(JError::new(format!(
+            "pubsub.publish can be called only by the host, its workers or management peer id; init_peer_id={caller}"
+        )));
+    }
+    let pubsub_api = &ctx.pubsub_api;
+    let mut args = args.function_args.into_iter();
+    let topic: String = Args::next("topic", &mut args)?;
+    let data: String = Args::next("data", &mut args)?;
+
+    pubsub_api.publish(topic, data.into_bytes()).await?;
+    Ok(())
+}
@@ -18,6 +18,7 @@ use tracing::{instrument, Span};
 
 use crate::error::SorcererError::{ParticleSigningFailed, ScopeKeypairMissing};
 use crate::Sorcerer;
+use audit_log::{AuditEvent, AuditEventKind};
 use fluence_libp2p::PeerId;
 use now_millis::now_ms;
 use particle_args::JError;
@@ -139,9 +140,19 @@ impl Sorcerer {
             let particle = self.make_spell_particle(peer_scope, event.spell_id.clone())?;
 
             self.store_trigger(event.clone(), peer_scope)?;
+            if let PeerScope::WorkerId(worker_id) = peer_scope {
+                if let Ok(deal_id) = self.workers.get_deal_id(worker_id) {
+                    self.deal_installs.on_spell_run(&deal_id);
+                }
+            }
             if let Some(m) = &self.spell_metrics {
                 m.observe_spell_cast();
+                m.trigger_latency(event.info.trigger_type(), event.triggered_at.elapsed());
             }
+            self.audit_log.publish(
+                AuditEvent::new(AuditEventKind::SpellExecuted, &event.spell_id)
+                    .with_details(format!("trigger={:?}", event.info.trigger_type())),
+            );
 
             self.aquamarine
                 .clone()
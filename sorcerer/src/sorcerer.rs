@@ -15,6 +15,7 @@
  */
 
 use std::collections::HashMap;
+use std::ops::Deref;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -23,21 +24,26 @@ use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
 use tokio_stream::wrappers::UnboundedReceiverStream;
 
+use crate::deal_installs::DealInstalls;
+use crate::pubsub_builtins::{load_topics, pubsub_publish, pubsub_subscribe, pubsub_unsubscribe};
 use crate::spell_builtins::{
-    get_spell_arg, get_spell_id, spell_install, spell_list, spell_remove, spell_update_config,
-    store_error, store_response,
+    get_spell_arg, get_spell_id, spell_get_secret, spell_install, spell_list, spell_remove,
+    spell_set_secret, spell_update_config, store_error, store_response,
 };
 use crate::worker_builins::{
-    activate_deal, create_worker, deactivate_deal, get_worker_peer_id, is_deal_active,
-    remove_worker, worker_list,
+    activate_deal, create_worker, deactivate_deal, get_deal_allowlist, get_deal_settings,
+    get_worker_peer_id, install_queue, is_deal_active, remove_worker, set_deal_allowlist,
+    set_deal_settings, worker_list,
 };
 use aquamarine::AquamarineApi;
+use audit_log::{AuditEvent, AuditEventKind, AuditLog};
 use particle_args::JError;
 use particle_builtins::{wrap, wrap_unit, CustomService};
 use particle_execution::ServiceFunction;
 use particle_modules::ModuleRepository;
 use particle_services::{ParticleAppServices, PeerScope};
 use peer_metrics::SpellMetrics;
+use pubsub::PubSubApi;
 use serde_json::Value;
 use server_config::ResolvedConfig;
 use spell_event_bus::api::{from_user_config, SpellEventBusApi, TriggerEvent};
@@ -46,8 +52,8 @@ use spell_storage::SpellStorage;
 use tracing::Instrument;
 use workers::{KeyStorage, PeerScopes, Workers};
 
-#[derive(Clone)]
-pub struct Sorcerer {
+/// Components shared by the spell scheduler and all sorcerer builtins
+pub struct SorcererContext {
     pub aquamarine: AquamarineApi,
     pub services: ParticleAppServices,
     pub spell_storage: SpellStorage,
@@ -59,6 +65,23 @@ pub struct Sorcerer {
     pub spell_service_api: SpellServiceApi,
     pub spell_metrics: Option<SpellMetrics>,
     pub worker_period_sec: u32,
+    pub pubsub_api: PubSubApi,
+    pub audit_log: AuditLog,
+    pub deal_installs: DealInstalls,
+}
+
+/// Cloning sorcerer, e.g. for every spell execution or builtin call, is a single `Arc` clone
+#[derive(Clone)]
+pub struct Sorcerer {
+    ctx: Arc<SorcererContext>,
+}
+
+impl Deref for Sorcerer {
+    type Target = SorcererContext;
+
+    fn deref(&self) -> &Self::Target {
+        &self.ctx
+    }
 }
 
 impl Sorcerer {
@@ -74,12 +97,14 @@ impl Sorcerer {
         scope: PeerScopes,
         spell_service_api: SpellServiceApi,
         spell_metrics: Option<SpellMetrics>,
+        pubsub_api: PubSubApi,
+        audit_log: AuditLog,
     ) -> (Self, HashMap<String, CustomService>, String) {
         let (spell_storage, spell_version) =
             SpellStorage::create(&config.dir_config.spell_base_dir, &services, &modules)
                 .expect("Spell storage creation");
 
-        let sorcerer = Self {
+        let ctx = SorcererContext {
             aquamarine,
             services,
             spell_storage,
@@ -91,10 +116,18 @@ impl Sorcerer {
             spell_service_api,
             spell_metrics,
             worker_period_sec: config.system_services.decider.worker_period_sec,
+            pubsub_api,
+            audit_log,
+            deal_installs: DealInstalls::new(
+                config.system_services.decider.max_concurrent_installs,
+                config.system_services.decider.install_timeout,
+            ),
         };
+        let sorcerer = Self { ctx: Arc::new(ctx) };
 
         let mut builtin_functions = sorcerer.make_spell_builtins();
         builtin_functions.extend_one(sorcerer.make_worker_builtin());
+        builtin_functions.extend_one(sorcerer.make_pubsub_builtin());
 
         (sorcerer, builtin_functions, spell_version)
     }
@@ -121,7 +154,7 @@ impl Sorcerer {
                     spell_owner,
                     self.spell_script_particle_ttl,
                 );
-                let config = self.spell_service_api.get_trigger_config(params)?;
+                let config = self.spell_service_api.get_trigger_config(params.clone())?;
                 let period = config.clock.period_sec;
                 let config = from_user_config(&config)?;
                 if let Some(config) = config.and_then(|c| c.into_rescheduled()) {
@@ -134,6 +167,12 @@ impl Sorcerer {
                 } else {
                     log::warn!("Spell {spell_id} is not rescheduled since its config is either not found or not reschedulable");
                 }
+
+                for topic in load_topics(&self.spell_service_api, params)? {
+                    self.spell_event_bus_api
+                        .subscribe_topic(spell_id.clone(), topic)
+                        .await?;
+                }
             };
             if let Err(e) = result {
                 // 1. We do not remove the spell we aren't able to reschedule. Users should be able to rerun it manually when updating trigger config.
@@ -192,6 +231,8 @@ impl Sorcerer {
                         "update_trigger_config",
                         self.make_spell_update_config_closure(),
                     ),
+                    ("set_secret", self.make_spell_set_secret_closure()),
+                    ("get_secret", self.make_spell_get_secret_closure()),
                 ],
                 None,
             ),
@@ -236,6 +277,25 @@ impl Sorcerer {
                     ("activate", self.make_activate_deal_closure()),
                     ("deactivate", self.make_deactivate_deal_closure()),
                     ("is_active", self.make_is_deal_active_closure()),
+                    ("set_deal_settings", self.make_set_deal_settings_closure()),
+                    ("get_deal_settings", self.make_get_deal_settings_closure()),
+                    ("set_deal_allowlist", self.make_set_deal_allowlist_closure()),
+                    ("get_deal_allowlist", self.make_get_deal_allowlist_closure()),
+                    ("install_queue", self.make_install_queue_closure()),
+                ],
+                None,
+            ),
+        )
+    }
+
+    fn make_pubsub_builtin(&self) -> (String, CustomService) {
+        (
+            "pubsub".to_string(),
+            CustomService::new(
+                vec![
+                    ("subscribe", self.make_pubsub_subscribe_closure()),
+                    ("unsubscribe", self.make_pubsub_unsubscribe_closure()),
+                    ("publish", self.make_pubsub_publish_closure()),
                 ],
                 None,
             ),
@@ -243,54 +303,41 @@ impl Sorcerer {
     }
 
     fn make_spell_install_closure(&self) -> ServiceFunction {
-        let services = self.services.clone();
-        let storage = self.spell_storage.clone();
-        let spell_event_bus = self.spell_event_bus_api.clone();
-        let workers = self.workers.clone();
-        let spell_service_api = self.spell_service_api.clone();
-        let scope = self.scopes.clone();
+        let ctx = self.ctx.clone();
         ServiceFunction::Immut(Box::new(move |args, params| {
-            let storage = storage.clone();
-            let services = services.clone();
-            let spell_event_bus_api = spell_event_bus.clone();
-            let spell_service_api = spell_service_api.clone();
-            let workers = workers.clone();
-            let scope = scope.clone();
+            let ctx = ctx.clone();
             async move {
-                wrap(
-                    spell_install(
-                        args,
-                        params,
-                        storage,
-                        services,
-                        spell_event_bus_api,
-                        spell_service_api,
-                        workers,
-                        scope,
-                    )
-                    .await,
-                )
+                let init_peer_id = params.init_peer_id;
+                let result = spell_install(args, params, &ctx).await;
+                if let Ok(Value::String(spell_id)) = &result {
+                    ctx.audit_log.record(
+                        AuditEvent::new(AuditEventKind::SpellInstalled, spell_id)
+                            .with_actor(init_peer_id),
+                    );
+                }
+                wrap(result)
             }
             .boxed()
         }))
     }
 
     fn make_spell_remove_closure(&self) -> ServiceFunction {
-        let services = self.services.clone();
-        let storage = self.spell_storage.clone();
-        let spell_event_bus_api = self.spell_event_bus_api.clone();
-        let workers = self.workers.clone();
-        let scopes = self.scopes.clone();
-
+        let ctx = self.ctx.clone();
         ServiceFunction::Immut(Box::new(move |args, params| {
-            let storage = storage.clone();
-            let services = services.clone();
-            let api = spell_event_bus_api.clone();
-            let workers = workers.clone();
-            let scopes = scopes.clone();
+            let ctx = ctx.clone();
             async move {
-                let result =
-                    spell_remove(args, params, storage, services, api, workers, scopes).await;
+                let init_peer_id = params.init_peer_id;
+                let spell_id = args
+                    .function_args
+                    .first()
+                    .and_then(|id| id.as_str().map(str::to_string));
+                let result = spell_remove(args, params, &ctx).await;
+                if let (Ok(()), Some(spell_id)) = (&result, spell_id) {
+                    ctx.audit_log.record(
+                        AuditEvent::new(AuditEventKind::SpellRemoved, spell_id)
+                            .with_actor(init_peer_id),
+                    );
+                }
                 wrap_unit(result)
             }
             .boxed()
@@ -298,40 +345,18 @@ impl Sorcerer {
     }
 
     fn make_spell_list_closure(&self) -> ServiceFunction {
-        let storage = self.spell_storage.clone();
+        let ctx = self.ctx.clone();
         ServiceFunction::Immut(Box::new(move |_, params| {
-            let storage = storage.clone();
-            async move { wrap(spell_list(params, storage)) }.boxed()
+            let result = spell_list(params, &ctx);
+            async move { wrap(result) }.boxed()
         }))
     }
 
     fn make_spell_update_config_closure(&self) -> ServiceFunction {
-        let spell_event_bus_api = self.spell_event_bus_api.clone();
-        let services = self.services.clone();
-        let workers = self.workers.clone();
-        let scope = self.scopes.clone();
-        let spell_service_api = self.spell_service_api.clone();
+        let ctx = self.ctx.clone();
         ServiceFunction::Immut(Box::new(move |args, params| {
-            let spell_event_bus_api = spell_event_bus_api.clone();
-            let services = services.clone();
-            let spell_service_api = spell_service_api.clone();
-            let workers = workers.clone();
-            let scopes = scope.clone();
-            async move {
-                wrap_unit(
-                    spell_update_config(
-                        args,
-                        params,
-                        services,
-                        spell_event_bus_api,
-                        spell_service_api,
-                        workers,
-                        scopes,
-                    )
-                    .await,
-                )
-            }
-            .boxed()
+            let ctx = ctx.clone();
+            async move { wrap_unit(spell_update_config(args, params, &ctx).await) }.boxed()
         }))
     }
 
@@ -350,150 +375,165 @@ impl Sorcerer {
     }
 
     fn make_get_spell_arg_closure(&self) -> ServiceFunction {
-        let spell_service_api = self.spell_service_api.clone();
+        let ctx = self.ctx.clone();
         ServiceFunction::Immut(Box::new(move |args, params| {
-            let spell_service_api = spell_service_api.clone();
-            async move { wrap(get_spell_arg(args, params, spell_service_api)) }.boxed()
+            let result = get_spell_arg(args, params, &ctx);
+            async move { wrap(result) }.boxed()
+        }))
+    }
+
+    fn make_spell_set_secret_closure(&self) -> ServiceFunction {
+        let ctx = self.ctx.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let result = spell_set_secret(args, params, &ctx);
+            async move { wrap_unit(result) }.boxed()
+        }))
+    }
+
+    fn make_spell_get_secret_closure(&self) -> ServiceFunction {
+        let ctx = self.ctx.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let result = spell_get_secret(args, params, &ctx);
+            async move { wrap(result) }.boxed()
         }))
     }
 
     fn make_error_handler_closure(&self) -> ServiceFunction {
-        let spell_service_api = self.spell_service_api.clone();
+        let ctx = self.ctx.clone();
         ServiceFunction::Immut(Box::new(move |args, params| {
-            let spell_service_api = spell_service_api.clone();
-            async move { wrap_unit(store_error(args, params, spell_service_api)) }.boxed()
+            let result = store_error(args, params, &ctx);
+            async move { wrap_unit(result) }.boxed()
         }))
     }
 
     fn make_response_handler_closure(&self) -> ServiceFunction {
-        let spell_service_api = self.spell_service_api.clone();
+        let ctx = self.ctx.clone();
         ServiceFunction::Immut(Box::new(move |args, params| {
-            let spell_service_api = spell_service_api.clone();
-            async move { wrap_unit(store_response(args, params, spell_service_api)) }.boxed()
+            let result = store_response(args, params, &ctx);
+            async move { wrap_unit(result) }.boxed()
         }))
     }
 
     fn make_worker_create_closure(&self) -> ServiceFunction {
-        let workers = self.workers.clone();
+        let ctx = self.ctx.clone();
         ServiceFunction::Immut(Box::new(move |args, params| {
-            let workers = workers.clone();
-            async move {
-                let res: Result<Value, JError> = create_worker(args, params, workers).await;
-                wrap(res)
-            }
-            .boxed()
+            let ctx = ctx.clone();
+            async move { wrap(create_worker(args, params, &ctx).await) }.boxed()
+        }))
+    }
+
+    fn make_install_queue_closure(&self) -> ServiceFunction {
+        let ctx = self.ctx.clone();
+        ServiceFunction::Immut(Box::new(move |_, _| {
+            let result = install_queue(&ctx);
+            async move { wrap(result) }.boxed()
         }))
     }
 
     fn make_worker_get_worker_id_closure(&self) -> ServiceFunction {
-        let workers = self.workers.clone();
+        let ctx = self.ctx.clone();
         ServiceFunction::Immut(Box::new(move |args, _| {
-            let workers = workers.clone();
-            async move { wrap(get_worker_peer_id(args, workers)) }.boxed()
+            let result = get_worker_peer_id(args, &ctx);
+            async move { wrap(result) }.boxed()
         }))
     }
 
     fn make_worker_list_closure(&self) -> ServiceFunction {
-        let workers = self.workers.clone();
+        let ctx = self.ctx.clone();
         ServiceFunction::Immut(Box::new(move |_, _| {
-            let workers = workers.clone();
-            async move { wrap(worker_list(workers)) }.boxed()
+            let result = worker_list(&ctx);
+            async move { wrap(result) }.boxed()
         }))
     }
 
     fn make_worker_remove_closure(&self) -> ServiceFunction {
-        let services = self.services.clone();
-        let storage = self.spell_storage.clone();
-        let spell_event_bus_api = self.spell_event_bus_api.clone();
-        let workers = self.workers.clone();
-        let scopes = self.scopes.clone();
-
+        let ctx = self.ctx.clone();
         ServiceFunction::Immut(Box::new(move |args, params| {
-            let storage = storage.clone();
-            let services = services.clone();
-            let api = spell_event_bus_api.clone();
-            let workers = workers.clone();
-            let scopes = scopes.clone();
-            async move {
-                let res =
-                    remove_worker(args, params, workers, services, storage, api, scopes).await;
-                wrap_unit(res)
-            }
-            .boxed()
+            let ctx = ctx.clone();
+            async move { wrap_unit(remove_worker(args, params, &ctx).await) }.boxed()
         }))
     }
 
     fn make_activate_deal_closure(&self) -> ServiceFunction {
-        let workers = self.workers.clone();
-        let scope = self.scopes.clone();
-        let services = self.services.clone();
-        let spell_event_bus_api = self.spell_event_bus_api.clone();
-        let spells_api = self.spell_service_api.clone();
-        let worker_period_sec = self.worker_period_sec;
+        let ctx = self.ctx.clone();
         ServiceFunction::Immut(Box::new(move |args, params| {
-            let services = services.clone();
-            let spell_event_bus_api = spell_event_bus_api.clone();
-            let spells_api = spells_api.clone();
-            let workers = workers.clone();
-            let scope = scope.clone();
-
-            async move {
-                let res = activate_deal(
-                    args,
-                    params,
-                    workers,
-                    scope,
-                    services,
-                    spell_event_bus_api,
-                    spells_api,
-                    worker_period_sec,
-                )
-                .await;
-                wrap_unit(res)
-            }
-            .boxed()
+            let ctx = ctx.clone();
+            async move { wrap_unit(activate_deal(args, params, &ctx).await) }.boxed()
         }))
     }
 
     fn make_deactivate_deal_closure(&self) -> ServiceFunction {
-        let workers = self.workers.clone();
-        let scope = self.scopes.clone();
-        let spell_storage = self.spell_storage.clone();
-        let spell_event_bus_api = self.spell_event_bus_api.clone();
-        let spells_api = self.spell_service_api.clone();
-
+        let ctx = self.ctx.clone();
         ServiceFunction::Immut(Box::new(move |args, params| {
-            let spells_api = spells_api.clone();
-            let spell_storage = spell_storage.clone();
-            let spell_event_bus_api = spell_event_bus_api.clone();
-            let workers = workers.clone();
-            let scope = scope.clone();
-
-            async move {
-                let res = deactivate_deal(
-                    args,
-                    params,
-                    workers,
-                    scope,
-                    spell_storage,
-                    spell_event_bus_api,
-                    spells_api,
-                )
-                .await;
-                wrap_unit(res)
-            }
-            .boxed()
+            let ctx = ctx.clone();
+            async move { wrap_unit(deactivate_deal(args, params, &ctx).await) }.boxed()
         }))
     }
 
     fn make_is_deal_active_closure(&self) -> ServiceFunction {
-        let workers = self.workers.clone();
+        let ctx = self.ctx.clone();
         ServiceFunction::Immut(Box::new(move |args, _| {
-            let workers = workers.clone();
+            let ctx = ctx.clone();
             async move {
-                tokio::task::spawn_blocking(move || wrap(is_deal_active(args, workers))).await?
+                tokio::task::spawn_blocking(move || wrap(is_deal_active(args, &ctx))).await?
             }
             .boxed()
         }))
     }
+
+    fn make_set_deal_settings_closure(&self) -> ServiceFunction {
+        let ctx = self.ctx.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let ctx = ctx.clone();
+            async move { wrap_unit(set_deal_settings(args, params, &ctx).await) }.boxed()
+        }))
+    }
+
+    fn make_get_deal_settings_closure(&self) -> ServiceFunction {
+        let ctx = self.ctx.clone();
+        ServiceFunction::Immut(Box::new(move |args, _| {
+            let result = get_deal_settings(args, &ctx);
+            async move { wrap(result) }.boxed()
+        }))
+    }
+
+    fn make_set_deal_allowlist_closure(&self) -> ServiceFunction {
+        let ctx = self.ctx.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let ctx = ctx.clone();
+            async move { wrap_unit(set_deal_allowlist(args, params, &ctx).await) }.boxed()
+        }))
+    }
+
+    fn make_get_deal_allowlist_closure(&self) -> ServiceFunction {
+        let ctx = self.ctx.clone();
+        ServiceFunction::Immut(Box::new(move |_, _| {
+            let result = get_deal_allowlist(&ctx);
+            async move { wrap(result) }.boxed()
+        }))
+    }
+
+    fn make_pubsub_subscribe_closure(&self) -> ServiceFunction {
+        let ctx = self.ctx.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let ctx = ctx.clone();
+            async move { wrap_unit(pubsub_subscribe(args, params, &ctx).await) }.boxed()
+        }))
+    }
+
+    fn make_pubsub_unsubscribe_closure(&self) -> ServiceFunction {
+        let ctx = self.ctx.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let ctx = ctx.clone();
+            async move { wrap_unit(pubsub_unsubscribe(args, params, &ctx).await) }.boxed()
+        }))
+    }
+
+    fn make_pubsub_publish_closure(&self) -> ServiceFunction {
+        let ctx = self.ctx.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let ctx = ctx.clone();
+            async move { wrap_unit(pubsub_publish(args, params, &ctx).await) }.boxed()
+        }))
+    }
 }
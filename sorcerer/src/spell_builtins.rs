@@ -14,10 +14,11 @@
  * limitations under the License.
  */
 use serde_json::{json, Value as JValue, Value, Value::Array};
-use std::sync::Arc;
 
+use crate::sorcerer::SorcererContext;
 use crate::utils::parse_spell_id_from;
 use fluence_spell_dtos::trigger_config::TriggerConfig;
+use key_encryption::KeyEncryption;
 use libp2p::PeerId;
 use particle_args::{Args, JError};
 use particle_execution::ParticleParams;
@@ -27,7 +28,8 @@ use spell_event_bus::{api, api::SpellEventBusApi};
 use spell_service_api::{CallParams, SpellServiceApi};
 use spell_storage::SpellStorage;
 use std::time::Duration;
-use workers::{PeerScopes, Workers};
+use types::management::Capability;
+use workers::{KeyStorage, PeerScopes};
 
 pub async fn remove_spell(
     particle_id: &str,
@@ -46,6 +48,17 @@ pub async fn remove_spell(
             "can't remove a spell {spell_id} due to an internal error while unsubscribing from the triggers: {err}"
         )));
     }
+    if let Err(err) = spell_event_bus_api
+        .unsubscribe_topics(spell_id.to_string())
+        .await
+    {
+        log::warn!(
+            "can't unsubscribe a spell {spell_id} from its topics via spell-event-bus-api: {err}"
+        );
+        return Err(JError::new(format!(
+            "can't remove a spell {spell_id} due to an internal error while unsubscribing from the topics: {err}"
+        )));
+    }
 
     spell_storage.unregister_spell(peer_scope, spell_id);
     services
@@ -154,13 +167,17 @@ pub fn get_spell_info(
 pub(crate) async fn spell_install(
     sargs: Args,
     params: ParticleParams,
-    spell_storage: SpellStorage,
-    services: ParticleAppServices,
-    spell_event_bus_api: SpellEventBusApi,
-    spell_service_api: SpellServiceApi,
-    workers: Arc<Workers>,
-    scopes: PeerScopes,
+    ctx: &SorcererContext,
 ) -> Result<JValue, JError> {
+    let SorcererContext {
+        spell_storage,
+        services,
+        spell_event_bus_api,
+        spell_service_api,
+        workers,
+        scopes,
+        ..
+    } = ctx;
     let mut args = sargs.function_args.clone().into_iter();
     let script: String = Args::next("script", &mut args)?;
     let init_data: JValue = Args::next("data", &mut args)?;
@@ -182,18 +199,18 @@ pub(crate) async fn spell_install(
             worker_id.into()
         }
         PeerScope::Host => {
-            if !is_management {
-                return Err(JError::new("Failed to install spell in the root scope, only management peer id can install top-level spells"));
+            if !scopes.has_capability(init_peer_id, Capability::DeploySystemServices) {
+                return Err(JError::new("Failed to install spell in the root scope, only management peer id or a management key with the deploy_system_services capability can install top-level spells"));
             }
             scopes.get_host_peer_id()
         }
     };
 
     let spell_id = install_spell(
-        &services,
-        &spell_storage,
-        &spell_event_bus_api,
-        &spell_service_api,
+        services,
+        spell_storage,
+        spell_event_bus_api,
+        spell_service_api,
         params.peer_scope,
         params.id.clone(),
         Duration::from_millis(params.ttl as u64),
@@ -217,9 +234,9 @@ pub(crate) async fn spell_install(
             // Remove the spell if we failed to add an alias
             remove_spell(
                 &params.id,
-                &spell_storage,
-                &services,
-                &spell_event_bus_api,
+                spell_storage,
+                services,
+                spell_event_bus_api,
                 &spell_id,
                 params.peer_scope,
                 init_peer_id,
@@ -236,10 +253,8 @@ pub(crate) async fn spell_install(
     Ok(JValue::String(spell_id))
 }
 
-pub(crate) fn spell_list(
-    params: ParticleParams,
-    spell_storage: SpellStorage,
-) -> Result<JValue, JError> {
+pub(crate) fn spell_list(params: ParticleParams, ctx: &SorcererContext) -> Result<JValue, JError> {
+    let spell_storage = &ctx.spell_storage;
     Ok(Array(
         spell_storage
             .get_registered_spells_by(params.peer_scope)
@@ -252,12 +267,16 @@ pub(crate) fn spell_list(
 pub(crate) async fn spell_remove(
     args: Args,
     params: ParticleParams,
-    spell_storage: SpellStorage,
-    services: ParticleAppServices,
-    spell_event_bus_api: SpellEventBusApi,
-    workers: Arc<Workers>,
-    scopes: PeerScopes,
+    ctx: &SorcererContext,
 ) -> Result<(), JError> {
+    let SorcererContext {
+        spell_storage,
+        services,
+        spell_event_bus_api,
+        workers,
+        scopes,
+        ..
+    } = ctx;
     let mut args = args.function_args.into_iter();
     let spell_id: String = Args::next("spell_id", &mut args)?;
 
@@ -280,8 +299,8 @@ pub(crate) async fn spell_remove(
         PeerScope::Host => {
             let host_peer_id = scopes.get_host_peer_id();
             let is_host = init_peer_id == host_peer_id;
-            let is_management = scopes.is_management(init_peer_id);
-            if !is_host && !is_management {
+            let can_deploy = scopes.has_capability(init_peer_id, Capability::DeploySystemServices);
+            if !is_host && !can_deploy {
                 return Err(JError::new(format!(
                     "Failed to remove spell {spell_id}, worker itself {host_peer_id} or peer manager"
                 )));
@@ -294,9 +313,9 @@ pub(crate) async fn spell_remove(
 
     remove_spell(
         &params.id,
-        &spell_storage,
-        &services,
-        &spell_event_bus_api,
+        spell_storage,
+        services,
+        spell_event_bus_api,
         &spell_id,
         peer_scope,
         owner_peer_id,
@@ -307,12 +326,16 @@ pub(crate) async fn spell_remove(
 pub(crate) async fn spell_update_config(
     args: Args,
     params: ParticleParams,
-    services: ParticleAppServices,
-    spell_event_bus_api: SpellEventBusApi,
-    spell_service_api: SpellServiceApi,
-    workers: Arc<Workers>,
-    scopes: PeerScopes,
+    ctx: &SorcererContext,
 ) -> Result<(), JError> {
+    let SorcererContext {
+        services,
+        spell_event_bus_api,
+        spell_service_api,
+        workers,
+        scopes,
+        ..
+    } = ctx;
     let mut args = args.function_args.into_iter();
     let spell_id_or_alias: String = Args::next("spell_id", &mut args)?;
 
@@ -334,8 +357,8 @@ pub(crate) async fn spell_update_config(
         PeerScope::Host => {
             let host_peer_id = scopes.get_host_peer_id();
             let is_host = init_peer_id == host_peer_id;
-            let is_management = scopes.is_management(init_peer_id);
-            if !is_host && !is_management {
+            let can_deploy = scopes.has_capability(init_peer_id, Capability::DeploySystemServices);
+            if !is_host && !can_deploy {
                 return Err(JError::new(format!(
                     "Failed to update spell config {spell_id_or_alias}, spell config can be updated by worker itself {host_peer_id} or peer manager; init_peer_id={init_peer_id}"
                 )));
@@ -386,8 +409,9 @@ pub(crate) fn get_spell_id(params: ParticleParams) -> Result<JValue, JError> {
 pub(crate) fn get_spell_arg(
     args: Args,
     params: ParticleParams,
-    spell_service_api: SpellServiceApi,
+    ctx: &SorcererContext,
 ) -> Result<JValue, JError> {
+    let spell_service_api = &ctx.spell_service_api;
     let spell_id = parse_spell_id_from(&params)?;
     let key = args.function_name;
     let call_params = CallParams::from(spell_id.clone(), params);
@@ -404,11 +428,108 @@ pub(crate) fn get_spell_arg(
     })
 }
 
+/// Spell KV keys holding encrypted secrets start with that
+const SECRET_KEY_PREFIX: &str = "secret:";
+const SECRET_KEY_CONTEXT: &str = "nox 2024 spell secrets v1";
+
+/// Secrets are readable only by particles of the spell itself, which are signed by its worker
+fn secret_spell_id(params: &ParticleParams, scopes: &PeerScopes) -> Result<String, JError> {
+    let spell_id = parse_spell_id_from(params)?;
+    if params.init_peer_id != scopes.to_peer_id(params.peer_scope) {
+        return Err(JError::new(format!(
+            "Spell secrets are accessible only to executions of the spell {spell_id}"
+        )));
+    }
+    Ok(spell_id)
+}
+
+/// Secrets are encrypted with a key derived from the worker key pair and the spell id
+fn secret_encryption(
+    key_storage: &KeyStorage,
+    peer_scope: PeerScope,
+    spell_id: &str,
+) -> Result<KeyEncryption, JError> {
+    let key_pair = key_storage
+        .get_keypair(peer_scope)
+        .ok_or_else(|| JError::new(format!("Key pair for {peer_scope:?} not found")))?;
+    let mut material = key_pair
+        .secret()
+        .map_err(|e| JError::new(format!("Failed to get secret key of {peer_scope:?}: {e}")))?;
+    material.extend_from_slice(spell_id.as_bytes());
+    Ok(KeyEncryption::from_bytes(blake3::derive_key(
+        SECRET_KEY_CONTEXT,
+        &material,
+    )))
+}
+
+pub(crate) fn spell_set_secret(
+    args: Args,
+    params: ParticleParams,
+    ctx: &SorcererContext,
+) -> Result<(), JError> {
+    let SorcererContext {
+        spell_service_api,
+        key_storage,
+        scopes,
+        ..
+    } = ctx;
+    let mut args = args.function_args.into_iter();
+    let key: String = Args::next("key", &mut args)?;
+    let value: String = Args::next("value", &mut args)?;
+    let spell_id = secret_spell_id(&params, scopes)?;
+
+    let encrypted = secret_encryption(key_storage, params.peer_scope, &spell_id)?
+        .encrypt(value.as_bytes())
+        .map_err(|e| {
+            JError::new(f!(
+                "Failed to encrypt secret {key} of spell {spell_id}: {e}"
+            ))
+        })?;
+    let call_params = CallParams::from(spell_id.clone(), params);
+    spell_service_api
+        .set_string(call_params, f!("{SECRET_KEY_PREFIX}{key}"), encrypted)
+        .map_err(|e| JError::new(f!("Failed to store secret {key} of spell {spell_id}: {e}")))
+}
+
+pub(crate) fn spell_get_secret(
+    args: Args,
+    params: ParticleParams,
+    ctx: &SorcererContext,
+) -> Result<JValue, JError> {
+    let SorcererContext {
+        spell_service_api,
+        key_storage,
+        scopes,
+        ..
+    } = ctx;
+    let mut args = args.function_args.into_iter();
+    let key: String = Args::next("key", &mut args)?;
+    let spell_id = secret_spell_id(&params, scopes)?;
+
+    let peer_scope = params.peer_scope;
+    let call_params = CallParams::from(spell_id.clone(), params);
+    let encrypted = spell_service_api
+        .get_string(call_params, f!("{SECRET_KEY_PREFIX}{key}"))
+        .map_err(|e| JError::new(f!("Failed to get secret {key} of spell {spell_id}: {e}")))?
+        .ok_or_else(|| JError::new("secret not found"))?;
+    let value = secret_encryption(key_storage, peer_scope, &spell_id)?
+        .decrypt(&encrypted)
+        .map_err(|e| {
+            JError::new(f!(
+                "Failed to decrypt secret {key} of spell {spell_id}: {e}"
+            ))
+        })?;
+    let value = String::from_utf8(value)
+        .map_err(|e| JError::new(f!("Secret {key} of spell {spell_id} isn't UTF-8: {e}")))?;
+    Ok(JValue::String(value))
+}
+
 pub(crate) fn store_error(
     mut args: Args,
     params: ParticleParams,
-    spell_service_api: SpellServiceApi,
+    ctx: &SorcererContext,
 ) -> Result<(), JError> {
+    let spell_service_api = &ctx.spell_service_api;
     let spell_id = parse_spell_id_from(&params)?;
 
     args.function_args.push(json!(params.timestamp));
@@ -426,8 +547,9 @@ pub(crate) fn store_error(
 pub(crate) fn store_response(
     args: Args,
     params: ParticleParams,
-    spell_service_api: SpellServiceApi,
+    ctx: &SorcererContext,
 ) -> Result<(), JError> {
+    let spell_service_api = &ctx.spell_service_api;
     let spell_id = parse_spell_id_from(&params)?;
     let response: Option<JValue> = Args::next_opt("response", &mut args.function_args.into_iter())?;
 
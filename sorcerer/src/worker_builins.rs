@@ -16,41 +16,54 @@
 use fluence_libp2p::PeerId;
 use fluence_spell_dtos::trigger_config::TriggerConfig;
 use futures::TryFutureExt;
-use serde_json::Value as JValue;
+use serde_json::{json, Value as JValue};
 use std::str::FromStr;
-use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use crate::deal_installs::Admission;
+use crate::pubsub_builtins::store_topics;
+use crate::sorcerer::SorcererContext;
 use crate::spell_builtins::remove_spell;
 use particle_args::{Args, JError};
 use particle_execution::ParticleParams;
-use particle_services::{ParticleAppServices, PeerScope};
-use spell_event_bus::api::{from_user_config, SpellEventBusApi};
-use spell_service_api::{CallParams, SpellServiceApi};
-use spell_storage::SpellStorage;
-use workers::{PeerScopes, WorkerParams, Workers, CUID};
+use particle_services::PeerScope;
+use spell_event_bus::api::{from_user_config, EventBusError};
+use spell_service_api::CallParams;
+use types::peer_scope::WorkerId;
+use types::DealId;
+use workers::{DealSettings, WorkerParams, CUID};
 
 pub(crate) async fn create_worker(
     args: Args,
     params: ParticleParams,
-    workers: Arc<Workers>,
+    ctx: &SorcererContext,
 ) -> Result<JValue, JError> {
+    let workers = &ctx.workers;
     let mut args = args.function_args.into_iter();
     let deal_id: String = Args::next("deal_id", &mut args)?;
     let cu_ids: Vec<CUID> = Args::next("cu_ids", &mut args)?;
+    let gpu_count: Option<u32> = Args::next_opt("gpu_count", &mut args)?;
+    let deal: DealId = deal_id.clone().into();
+    if workers.get_worker_id(deal.clone()).is_err() {
+        if let Admission::Queued { position } = ctx.deal_installs.admit(&deal, Instant::now()) {
+            return Err(JError::new(format!(
+                "Installation of deal {deal_id} is queued at position {position}, retry later"
+            )));
+        }
+    }
     Ok(JValue::String(
         workers
-            .create_worker(WorkerParams::new(
-                deal_id.into(),
-                params.init_peer_id,
-                cu_ids,
-            ))
+            .create_worker(
+                WorkerParams::new(deal_id.into(), params.init_peer_id, cu_ids)
+                    .with_gpu_count(gpu_count.unwrap_or_default() as usize),
+            )
             .await?
             .to_string(),
     ))
 }
 
-pub(crate) fn get_worker_peer_id(args: Args, workers: Arc<Workers>) -> Result<JValue, JError> {
+pub(crate) fn get_worker_peer_id(args: Args, ctx: &SorcererContext) -> Result<JValue, JError> {
+    let workers = &ctx.workers;
     let mut args = args.function_args.into_iter();
     let deal_id: String = Args::next("deal_id", &mut args)?;
 
@@ -65,12 +78,16 @@ pub(crate) fn get_worker_peer_id(args: Args, workers: Arc<Workers>) -> Result<JV
 pub(crate) async fn remove_worker(
     args: Args,
     params: ParticleParams,
-    workers: Arc<Workers>,
-    services: ParticleAppServices,
-    spell_storage: SpellStorage,
-    spell_event_bus_api: SpellEventBusApi,
-    scopes: PeerScopes,
+    ctx: &SorcererContext,
 ) -> Result<(), JError> {
+    let SorcererContext {
+        workers,
+        services,
+        spell_storage,
+        spell_event_bus_api,
+        scopes,
+        ..
+    } = ctx;
     let mut args = args.function_args.into_iter();
     let worker_id: String = Args::next("worker_id", &mut args)?;
     let worker_peer_id = PeerId::from_str(&worker_id)?;
@@ -84,14 +101,16 @@ pub(crate) async fn remove_worker(
             if params.init_peer_id != worker_creator && params.init_peer_id != worker_peer_id {
                 return Err(JError::new(format!("Worker {worker_id} can be removed only by worker creator {worker_creator} or worker itself")));
             }
+            let deal_id = workers.get_deal_id(worker_id)?;
             workers.remove_worker(worker_id).await?;
+            ctx.deal_installs.finish(&deal_id);
             let spells: Vec<_> = spell_storage.get_registered_spells_by(peer_scope);
             for s in spells {
                 remove_spell(
                     &params.id,
-                    &spell_storage,
-                    &services,
-                    &spell_event_bus_api,
+                    spell_storage,
+                    services,
+                    spell_event_bus_api,
                     &s,
                     peer_scope,
                     worker_id.into(),
@@ -111,7 +130,13 @@ pub(crate) async fn remove_worker(
     Ok(())
 }
 
-pub(crate) fn worker_list(workers: Arc<Workers>) -> Result<JValue, JError> {
+/// Deals being installed and waiting for installation, see `DealInstalls`
+pub(crate) fn install_queue(ctx: &SorcererContext) -> Result<JValue, JError> {
+    Ok(json!(ctx.deal_installs.progress(Instant::now())))
+}
+
+pub(crate) fn worker_list(ctx: &SorcererContext) -> Result<JValue, JError> {
+    let workers = &ctx.workers;
     Ok(JValue::Array(
         workers
             .list_workers()
@@ -121,57 +146,57 @@ pub(crate) fn worker_list(workers: Arc<Workers>) -> Result<JValue, JError> {
     ))
 }
 
-pub(crate) async fn deactivate_deal(
-    args: Args,
-    params: ParticleParams,
-    workers: Arc<Workers>,
-    scopes: PeerScopes,
-    spell_storage: SpellStorage,
-    spell_event_bus_api: SpellEventBusApi,
-    spell_service_api: SpellServiceApi,
+fn check_deal_admin(
+    ctx: &SorcererContext,
+    params: &ParticleParams,
+    action: &str,
 ) -> Result<(), JError> {
-    let mut args = args.function_args.into_iter();
-    let deal_id: String = Args::next("deal_id", &mut args)?;
-
+    let scopes = &ctx.scopes;
     if !scopes.is_management(params.init_peer_id) && !scopes.is_host(params.init_peer_id) {
-        return Err(JError::new(
-            "Only management or host peer can deactivate deal",
-        ));
-    }
-
-    let worker_id = workers.get_worker_id(deal_id.into())?;
-
-    if !workers.is_worker_active(worker_id) {
-        return Err(JError::new("Deal has already been deactivated"));
+        return Err(JError::new(format!(
+            "Only management or host peer can {action}"
+        )));
     }
+    Ok(())
+}
 
+/// Unschedules all spells of the deal worker and deactivates it
+async fn stop_deal(
+    ctx: &SorcererContext,
+    params: &ParticleParams,
+    worker_id: WorkerId,
+) -> Result<(), JError> {
+    let SorcererContext {
+        workers,
+        spell_storage,
+        spell_event_bus_api,
+        spell_service_api,
+        ..
+    } = ctx;
     let spells = spell_storage.get_registered_spells_by(PeerScope::WorkerId(worker_id));
 
     for spell_id in spells.into_iter() {
-        spell_event_bus_api
-            .unsubscribe(spell_id.clone())
-            .map_err(|e| {
-                JError::new(format!(
-                    "Deal deactivation failed due to failure to stop spell {spell_id} : {e}"
-                ))
-            })
-            .await?;
+        let result: Result<(), JError> = try {
+            spell_event_bus_api.unsubscribe(spell_id.clone()).await?;
+            spell_event_bus_api
+                .unsubscribe_topics(spell_id.clone())
+                .await?;
 
-        spell_service_api
-            .set_trigger_config(
-                CallParams::local(
-                    PeerScope::WorkerId(worker_id),
-                    spell_id.clone(),
-                    worker_id.into(),
-                    Duration::from_millis(params.ttl as u64),
-                ),
-                TriggerConfig::default(),
-            )
-            .map_err(|e| {
-                JError::new(format!(
-                    "Deal deactivation failed due to failure to stop spell {spell_id} : {e}"
-                ))
-            })?;
+            let call_params = CallParams::local(
+                PeerScope::WorkerId(worker_id),
+                spell_id.clone(),
+                worker_id.into(),
+                Duration::from_millis(params.ttl as u64),
+            );
+            spell_service_api.set_trigger_config(call_params.clone(), TriggerConfig::default())?;
+            store_topics(spell_service_api, call_params, &[])?;
+        };
+
+        result.map_err(|e| {
+            JError::new(format!(
+                "Deal deactivation failed due to failure to stop spell {spell_id} : {e}"
+            ))
+        })?;
     }
 
     workers.deactivate_worker(worker_id).await?;
@@ -179,41 +204,34 @@ pub(crate) async fn deactivate_deal(
     Ok(())
 }
 
-pub(crate) async fn activate_deal(
-    args: Args,
-    params: ParticleParams,
-    workers: Arc<Workers>,
-    scopes: PeerScopes,
-    services: ParticleAppServices,
-    spell_event_bus_api: SpellEventBusApi,
-    spell_service_api: SpellServiceApi,
-    worker_period_sec: u32,
+/// (Re)schedules the worker spell of the deal with the deal or node-wide period
+async fn schedule_worker_spell(
+    ctx: &SorcererContext,
+    params: &ParticleParams,
+    worker_id: WorkerId,
 ) -> Result<(), JError> {
-    let mut args = args.function_args.into_iter();
-    let deal_id: String = Args::next("deal_id", &mut args)?;
-
-    if !scopes.is_management(params.init_peer_id) && !scopes.is_host(params.init_peer_id) {
-        return Err(JError::new(
-            "Only management or host peer can activate deal",
-        ));
-    }
-
-    let worker_id = workers.get_worker_id(deal_id.into())?;
-
-    if workers.is_worker_active(worker_id) {
-        return Err(JError::new("Deal has already been activated"));
-    }
-
+    let SorcererContext {
+        workers,
+        services,
+        spell_event_bus_api,
+        spell_service_api,
+        worker_period_sec,
+        ..
+    } = ctx;
     let installation_spell_id = services.resolve_alias(
         PeerScope::WorkerId(worker_id),
         "worker-spell".to_string(),
         &params.id,
     )?;
+    let period_sec = workers
+        .get_deal_settings(worker_id)?
+        .worker_period_sec
+        .unwrap_or(*worker_period_sec);
 
     // same as in decider-distro
     let mut worker_config = TriggerConfig::default();
     worker_config.clock.start_sec = 1;
-    worker_config.clock.period_sec = worker_period_sec;
+    worker_config.clock.period_sec = period_sec;
 
     spell_service_api.set_trigger_config(
         CallParams::local(
@@ -229,20 +247,139 @@ pub(crate) async fn activate_deal(
         "Deal activation failed due to failure to parse trigger config",
     ))?;
 
-    spell_event_bus_api
-        .subscribe(installation_spell_id, trigger_config)
-        .map_err(|e| {
-            JError::new(format!(
-                "Deal activation failed due to failure to start worker spell : {e}"
-            ))
-        })
-        .await?;
+    let result: Result<(), EventBusError> = try {
+        spell_event_bus_api
+            .unsubscribe(installation_spell_id.clone())
+            .await?;
+        spell_event_bus_api
+            .subscribe(installation_spell_id, trigger_config)
+            .await?;
+    };
+    result.map_err(|e| {
+        JError::new(format!(
+            "Deal activation failed due to failure to start worker spell : {e}"
+        ))
+    })
+}
+
+pub(crate) async fn deactivate_deal(
+    args: Args,
+    params: ParticleParams,
+    ctx: &SorcererContext,
+) -> Result<(), JError> {
+    let mut args = args.function_args.into_iter();
+    let deal_id: String = Args::next("deal_id", &mut args)?;
+
+    check_deal_admin(ctx, &params, "deactivate deal")?;
+
+    let worker_id = ctx.workers.get_worker_id(deal_id.into())?;
+
+    if !ctx.workers.is_worker_active(worker_id) {
+        return Err(JError::new("Deal has already been deactivated"));
+    }
+
+    stop_deal(ctx, &params, worker_id).await
+}
+
+pub(crate) async fn activate_deal(
+    args: Args,
+    params: ParticleParams,
+    ctx: &SorcererContext,
+) -> Result<(), JError> {
+    let workers = &ctx.workers;
+    let mut args = args.function_args.into_iter();
+    let deal_id: String = Args::next("deal_id", &mut args)?;
 
+    check_deal_admin(ctx, &params, "activate deal")?;
+
+    let worker_id = workers.get_worker_id(deal_id.into())?;
+
+    if workers.is_worker_active(worker_id) {
+        return Err(JError::new("Deal has already been activated"));
+    }
+
+    schedule_worker_spell(ctx, &params, worker_id).await?;
     workers.activate_worker(worker_id).await?;
     Ok(())
 }
 
-pub(crate) fn is_deal_active(args: Args, workers: Arc<Workers>) -> Result<JValue, JError> {
+/// Replaces the deal settings: the worker spell period, node-wide one if omitted,
+/// and optionally stops or resumes the deal
+pub(crate) async fn set_deal_settings(
+    args: Args,
+    params: ParticleParams,
+    ctx: &SorcererContext,
+) -> Result<(), JError> {
+    let workers = &ctx.workers;
+    let mut args = args.function_args.into_iter();
+    let deal_id: String = Args::next("deal_id", &mut args)?;
+    let worker_period_sec: Option<u32> = Args::next_opt("worker_period_sec", &mut args)?;
+    let stopped: Option<bool> = Args::next_opt("stopped", &mut args)?;
+
+    check_deal_admin(ctx, &params, "change deal settings")?;
+
+    let worker_id = workers.get_worker_id(deal_id.into())?;
+    let settings = DealSettings { worker_period_sec };
+    let changed = workers.get_deal_settings(worker_id)? != settings;
+    workers.set_deal_settings(worker_id, settings).await?;
+
+    let active = workers.is_worker_active(worker_id);
+    match stopped {
+        Some(true) if active => stop_deal(ctx, &params, worker_id).await?,
+        Some(false) if !active => {
+            schedule_worker_spell(ctx, &params, worker_id).await?;
+            workers.activate_worker(worker_id).await?;
+        }
+        _ if active && changed => schedule_worker_spell(ctx, &params, worker_id).await?,
+        _ => {}
+    }
+
+    Ok(())
+}
+
+pub(crate) fn get_deal_settings(args: Args, ctx: &SorcererContext) -> Result<JValue, JError> {
+    let workers = &ctx.workers;
+    let mut args = args.function_args.into_iter();
+    let deal_id: String = Args::next("deal_id", &mut args)?;
+    let worker_id = workers.get_worker_id(deal_id.into())?;
+    let settings = workers.get_deal_settings(worker_id)?;
+
+    Ok(json!({
+        "worker_period_sec": settings.worker_period_sec.into_iter().collect::<Vec<_>>(),
+        "stopped": !workers.is_worker_active(worker_id),
+    }))
+}
+
+/// Workers are created only for the listed deals, all deals are allowed if the list is omitted
+pub(crate) async fn set_deal_allowlist(
+    args: Args,
+    params: ParticleParams,
+    ctx: &SorcererContext,
+) -> Result<(), JError> {
+    let mut args = args.function_args.into_iter();
+    let deals: Option<Vec<String>> = Args::next_opt("deals", &mut args)?;
+
+    check_deal_admin(ctx, &params, "change deal allowlist")?;
+
+    let deals = deals.map(|deals| deals.into_iter().map(DealId::from).collect());
+    ctx.workers.set_deal_allowlist(deals).await?;
+    Ok(())
+}
+
+pub(crate) fn get_deal_allowlist(ctx: &SorcererContext) -> Result<JValue, JError> {
+    let deals = ctx.workers.deal_allowlist().map(|deals| {
+        deals
+            .into_iter()
+            .map(|deal_id| JValue::String(deal_id.to_string()))
+            .collect::<Vec<_>>()
+    });
+    Ok(JValue::Array(
+        deals.map(JValue::Array).into_iter().collect(),
+    ))
+}
+
+pub(crate) fn is_deal_active(args: Args, ctx: &SorcererContext) -> Result<JValue, JError> {
+    let workers = &ctx.workers;
     let mut args = args.function_args.into_iter();
     let deal_id: String = Args::next("deal_id", &mut args)?;
     let worker_id = workers.get_worker_id(deal_id.into())?;